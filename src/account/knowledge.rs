@@ -0,0 +1,290 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{password, password::argon2::Argon2Params, AuthError};
+
+/// Wrong answers allowed before the account's questions stop being accepted
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Fewest characters an answer may have once normalized
+pub const MIN_ANSWER_LEN: usize = 3;
+
+/// A question and the hash of the user's normalized answer
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SecurityAnswer {
+    pub question: String,
+    /// PHC string of the answer after `normalize_answer`
+    pub answer_hash: String,
+}
+
+/// An account's questions and how many wrong attempts have been made since the last success
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SecurityAnswers {
+    pub answers: Vec<SecurityAnswer>,
+    pub failures: u32,
+}
+
+/// Persists each account's security questions
+///
+/// `record_failure` must increment the count in a single atomic operation, so parallel guesses
+/// cannot slip past the attempt limit.
+pub trait SecurityAnswerStore {
+    fn get(
+        &self,
+        account: &str,
+    ) -> impl Future<Output = Result<Option<SecurityAnswers>, AuthError>> + Send;
+
+    /// Replace the account's answers and forget its failures
+    fn set(
+        &self,
+        account: &str,
+        answers: Vec<SecurityAnswer>,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Returns the new failure count
+    fn record_failure(&self, account: &str) -> impl Future<Output = Result<u32, AuthError>> + Send;
+
+    fn clear_failures(&self, account: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Returns `false` if the account had no answers
+    fn delete(&self, account: &str) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Fold case and whitespace so `  Fluffy   McFluff` matches `fluffy mcfluff`
+pub fn normalize_answer(answer: &str) -> String {
+    answer
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Security questions, a knowledge based fallback factor
+///
+/// Answers are normalized with `normalize_answer` and hashed with Argon2id like passwords. They
+/// are often guessable or public, so treat them as low assurance: never as a factor on their own,
+/// only as one step of `AccountRecovery` alongside stronger ones. Every question must be answered
+/// correctly, and after `max_attempts` wrong tries `verify` fails with `AuthError::InvalidState`
+/// until the answers are enrolled again or `unlock` is called.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::account::knowledge::{MemorySecurityAnswerStore, SecurityQuestions};
+/// use lonewolf_auth_toolkit::password::argon2::Argon2Params;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let questions = SecurityQuestions::new(MemorySecurityAnswerStore::default())
+///         .params(Argon2Params::default().memory_kib(1024).iterations(1))
+///         .max_attempts(2);
+///
+///     let enrolled = [
+///         ("Name of your first pet?", "Fluffy McFluff"),
+///         ("City you were born in?", "Pietermaritzburg"),
+///     ];
+///     questions.enroll("SomeUserId", &enrolled).await?;
+///     assert_eq!(questions.questions("SomeUserId").await?.len(), 2);
+///
+///     let answers = [
+///         ("Name of your first pet?", "  fluffy   MCFLUFF "),
+///         ("City you were born in?", "pietermaritzburg"),
+///     ];
+///     assert!(questions.verify("SomeUserId", &answers).await?);
+///
+///     let wrong = [
+///         ("Name of your first pet?", "Rex"),
+///         ("City you were born in?", "Durban"),
+///     ];
+///     assert!(!questions.verify("SomeUserId", &wrong).await?);
+///     assert!(!questions.verify("SomeUserId", &wrong).await?);
+///
+///     // Locked once the attempts are used up, even for the right answers
+///     assert!(questions.verify("SomeUserId", &answers).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct SecurityQuestions<S> {
+    store: S,
+    params: Argon2Params,
+    max_attempts: u32,
+}
+
+impl<S: SecurityAnswerStore> SecurityQuestions<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            params: Argon2Params::default(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    /// Argon2id cost for new answer hashes
+    pub fn params(mut self, params: Argon2Params) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Replace the account's questions with these questions and answers
+    ///
+    /// Fails with `AuthError::InvalidInput` when there are none, a question is repeated or an
+    /// answer is shorter than `MIN_ANSWER_LEN` once normalized.
+    pub async fn enroll(&self, account: &str, answers: &[(&str, &str)]) -> Result<(), AuthError> {
+        if answers.is_empty() {
+            return Err(AuthError::InvalidInput(
+                "At least one security question is needed".to_string(),
+            ));
+        }
+
+        let mut hashed = Vec::with_capacity(answers.len());
+        for (index, (question, answer)) in answers.iter().enumerate() {
+            let question = question.trim();
+            if question.is_empty() || answers[..index].iter().any(|(q, _)| q.trim() == question) {
+                return Err(AuthError::InvalidInput(
+                    "Security questions must be distinct and not empty".to_string(),
+                ));
+            }
+
+            let answer = normalize_answer(answer);
+            if answer.chars().count() < MIN_ANSWER_LEN {
+                return Err(AuthError::InvalidInput(format!(
+                    "Security answers need at least {} characters",
+                    MIN_ANSWER_LEN
+                )));
+            }
+
+            hashed.push(SecurityAnswer {
+                question: question.to_string(),
+                answer_hash: password::hash_with(&answer, &self.params)?,
+            });
+        }
+
+        self.store.set(account, hashed).await
+    }
+
+    /// The account's questions, in the order they were enrolled, to ask the user
+    pub async fn questions(&self, account: &str) -> Result<Vec<String>, AuthError> {
+        Ok(self
+            .store
+            .get(account)
+            .await?
+            .unwrap_or_default()
+            .answers
+            .into_iter()
+            .map(|answer| answer.question)
+            .collect())
+    }
+
+    /// Check an answer to every one of the account's questions
+    ///
+    /// Returns `false` for wrong or missing answers, counting an attempt, and fails with
+    /// `AuthError::NotFound` for accounts without questions.
+    pub async fn verify(&self, account: &str, answers: &[(&str, &str)]) -> Result<bool, AuthError> {
+        let enrolled = self
+            .store
+            .get(account)
+            .await?
+            .filter(|enrolled| !enrolled.answers.is_empty())
+            .ok_or_else(|| AuthError::NotFound("No security questions enrolled".to_string()))?;
+        if enrolled.failures >= self.max_attempts {
+            return Err(AuthError::InvalidState(
+                "Too many wrong security answers".to_string(),
+            ));
+        }
+
+        // Check every answer, so the time taken does not reveal which one was wrong
+        let mut correct = true;
+        for expected in &enrolled.answers {
+            let given = answers
+                .iter()
+                .find(|(question, _)| question.trim() == expected.question)
+                .map(|(_, answer)| normalize_answer(answer))
+                .unwrap_or_default();
+            correct &= password::verify(&given, &expected.answer_hash)?;
+        }
+
+        if correct {
+            if enrolled.failures > 0 {
+                self.store.clear_failures(account).await?;
+            }
+        } else {
+            self.store.record_failure(account).await?;
+        }
+
+        Ok(correct)
+    }
+
+    /// Accept answers again after too many wrong attempts, e.g. once support verified the user
+    pub async fn unlock(&self, account: &str) -> Result<(), AuthError> {
+        self.store.clear_failures(account).await
+    }
+
+    pub async fn delete(&self, account: &str) -> Result<bool, AuthError> {
+        self.store.delete(account).await
+    }
+}
+
+/// Keeps security answers in process memory
+#[derive(Debug, Default)]
+pub struct MemorySecurityAnswerStore {
+    accounts: Mutex<HashMap<String, SecurityAnswers>>,
+}
+
+impl MemorySecurityAnswerStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, SecurityAnswers>>, AuthError> {
+        self.accounts
+            .lock()
+            .map_err(|_| AuthError::backend("Security answer store lock poisoned"))
+    }
+}
+
+impl SecurityAnswerStore for MemorySecurityAnswerStore {
+    async fn get(&self, account: &str) -> Result<Option<SecurityAnswers>, AuthError> {
+        Ok(self.lock()?.get(account).cloned())
+    }
+
+    async fn set(&self, account: &str, answers: Vec<SecurityAnswer>) -> Result<(), AuthError> {
+        self.lock()?.insert(
+            account.to_string(),
+            SecurityAnswers {
+                answers,
+                failures: 0,
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn record_failure(&self, account: &str) -> Result<u32, AuthError> {
+        let mut accounts = self.lock()?;
+        let entry = accounts.entry(account.to_string()).or_default();
+        entry.failures += 1;
+
+        Ok(entry.failures)
+    }
+
+    async fn clear_failures(&self, account: &str) -> Result<(), AuthError> {
+        if let Some(entry) = self.lock()?.get_mut(account) {
+            entry.failures = 0;
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, account: &str) -> Result<bool, AuthError> {
+        Ok(self.lock()?.remove(account).is_some())
+    }
+}
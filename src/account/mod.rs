@@ -0,0 +1,662 @@
+pub mod knowledge;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod recovery;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditSink, NoAudit},
+    mfa::{self, generate_secret, TotpConfig},
+    password,
+    password::argon2::Argon2Params,
+    token::{generate_token, now},
+    trace, AuthError,
+};
+
+/// A registered user
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct User {
+    pub id: String,
+    /// Lowercased
+    pub email: String,
+    pub username: Option<String>,
+    pub email_verified: bool,
+    /// Disabled users cannot sign in
+    pub disabled: bool,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+}
+
+/// Persists users and finds them by the identifier they sign in with
+///
+/// `insert` must fail with `AuthError::InvalidState` when the id, email or username is already
+/// taken, checked atomically, e.g. by unique indexes, so two sign ups cannot claim one address.
+pub trait UserStore {
+    fn insert(&self, user: User) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn get(&self, id: &str) -> impl Future<Output = Result<Option<User>, AuthError>> + Send;
+
+    /// The user whose lowercased email or username is `identifier`
+    fn find(
+        &self,
+        identifier: &str,
+    ) -> impl Future<Output = Result<Option<User>, AuthError>> + Send;
+
+    /// Returns `false` if there is no user with this id
+    fn update(&self, user: User) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Returns `false` if there is no user with this id
+    fn delete(&self, id: &str) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Where a user stands with their authenticator app
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MfaStatus {
+    #[default]
+    Disabled,
+    /// A secret was issued and the first code has not been confirmed yet
+    Pending,
+    Enabled,
+}
+
+/// The secrets a user authenticates with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Credentials {
+    /// PHC string; `None` for passwordless users
+    pub password_hash: Option<String>,
+    /// Base32 TOTP secret; seal it with `crypto::sealed::Sealer` in the store if the database is
+    /// not encrypted at rest
+    pub totp_secret: Option<String>,
+    pub mfa: MfaStatus,
+    /// The last TOTP step accepted, so a code cannot be used twice
+    pub last_totp_step: Option<u64>,
+}
+
+/// Persists each user's credentials
+///
+/// `record_totp_step` must store the step only if it is later than the last recorded one, in a
+/// single atomic operation (e.g. `UPDATE ... WHERE last_totp_step < $1`), so a code intercepted
+/// in flight cannot be replayed.
+pub trait CredentialStore {
+    fn get(
+        &self,
+        user_id: &str,
+    ) -> impl Future<Output = Result<Option<Credentials>, AuthError>> + Send;
+
+    fn set_password_hash(
+        &self,
+        user_id: &str,
+        hash: String,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Replace the TOTP secret and MFA status, forgetting the last recorded step
+    fn set_totp(
+        &self,
+        user_id: &str,
+        secret: Option<String>,
+        status: MfaStatus,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Returns `false` if the step is not later than the last recorded one
+    fn record_totp_step(
+        &self,
+        user_id: &str,
+        step: u64,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    fn delete(&self, user_id: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// The result of a successful password check
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SignIn {
+    pub user: User,
+    /// Ask for a TOTP code before treating the user as signed in
+    pub mfa_required: bool,
+}
+
+/// Registration, password sign in and TOTP management over any user and credential store
+///
+/// Passwords are hashed with Argon2id and transparently rehashed on sign in when the parameters
+/// are raised. Unknown identifiers and wrong passwords fail identically, and take as long, so
+/// sign in cannot be used to discover which addresses are registered.
+///
+/// ### Example
+/// ```rust
+/// use std::{collections::HashMap, sync::Mutex};
+///
+/// use lonewolf_auth_toolkit::account::{
+///     Accounts, CredentialStore, Credentials, MfaStatus, User, UserStore,
+/// };
+/// use lonewolf_auth_toolkit::password::argon2::Argon2Params;
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore {
+///     users: Mutex<HashMap<String, User>>,
+///     credentials: Mutex<HashMap<String, Credentials>>,
+/// }
+///
+/// impl UserStore for MemoryStore {
+///     async fn insert(&self, user: User) -> Result<(), AuthError> {
+///         let mut users = self.users.lock().unwrap();
+///         if users.values().any(|existing| existing.email == user.email) {
+///             return Err(AuthError::InvalidState("Email is taken".to_string()));
+///         }
+///         users.insert(user.id.clone(), user);
+///         Ok(())
+///     }
+///
+///     async fn get(&self, id: &str) -> Result<Option<User>, AuthError> {
+///         Ok(self.users.lock().unwrap().get(id).cloned())
+///     }
+///
+///     async fn find(&self, identifier: &str) -> Result<Option<User>, AuthError> {
+///         let users = self.users.lock().unwrap();
+///         Ok(users.values().find(|user| user.email == identifier).cloned())
+///     }
+///
+///     async fn update(&self, user: User) -> Result<bool, AuthError> {
+///         Ok(self.users.lock().unwrap().insert(user.id.clone(), user).is_some())
+///     }
+///
+///     async fn delete(&self, id: &str) -> Result<bool, AuthError> {
+///         Ok(self.users.lock().unwrap().remove(id).is_some())
+///     }
+/// }
+///
+/// impl CredentialStore for MemoryStore {
+///     async fn get(&self, user_id: &str) -> Result<Option<Credentials>, AuthError> {
+///         Ok(self.credentials.lock().unwrap().get(user_id).cloned())
+///     }
+///
+///     async fn set_password_hash(&self, user_id: &str, hash: String) -> Result<(), AuthError> {
+///         let mut credentials = self.credentials.lock().unwrap();
+///         credentials.entry(user_id.to_string()).or_default().password_hash = Some(hash);
+///         Ok(())
+///     }
+///
+///     async fn set_totp(&self, user_id: &str, secret: Option<String>, status: MfaStatus) -> Result<(), AuthError> {
+///         let mut credentials = self.credentials.lock().unwrap();
+///         let entry = credentials.entry(user_id.to_string()).or_default();
+///         entry.totp_secret = secret;
+///         entry.mfa = status;
+///         entry.last_totp_step = None;
+///         Ok(())
+///     }
+///
+///     async fn record_totp_step(&self, user_id: &str, step: u64) -> Result<bool, AuthError> {
+///         let mut credentials = self.credentials.lock().unwrap();
+///         let entry = credentials.entry(user_id.to_string()).or_default();
+///         if entry.last_totp_step.is_some_and(|last| last >= step) {
+///             return Ok(false);
+///         }
+///         entry.last_totp_step = Some(step);
+///         Ok(true)
+///     }
+///
+///     async fn delete(&self, user_id: &str) -> Result<(), AuthError> {
+///         self.credentials.lock().unwrap().remove(user_id);
+///         Ok(())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     // Cheap parameters keep the example fast; use the defaults in production
+///     let params = Argon2Params::default().memory_kib(1024).iterations(1);
+///     let accounts = Accounts::new(MemoryStore::default(), MemoryStore::default()).params(params);
+///
+///     let user = accounts.register("Someone@Example.com", None, "correct horse battery staple").await?;
+///     assert!(accounts.register("someone@example.com", None, "another password").await.is_err());
+///
+///     let signed_in = accounts.authenticate("someone@example.com", "correct horse battery staple").await?;
+///     assert_eq!(signed_in.user, user);
+///     assert!(!signed_in.mfa_required);
+///
+///     assert!(accounts.authenticate("someone@example.com", "Tr0ub4dor&3").await.is_err());
+///     assert!(accounts.authenticate("nobody@example.com", "Tr0ub4dor&3").await.is_err());
+///
+///     // Show the secret as a QR code; MFA is only enabled once a code from the app is confirmed
+///     let _secret = accounts.start_totp(&user.id).await?;
+///     assert!(!accounts.confirm_totp(&user.id, "not a code".to_string()).await?);
+///
+///     let signed_in = accounts.authenticate("someone@example.com", "correct horse battery staple").await?;
+///     assert!(!signed_in.mfa_required);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Accounts<U, C, A = NoAudit> {
+    users: U,
+    credentials: C,
+    params: Argon2Params,
+    totp: TotpConfig,
+    audit: A,
+}
+
+impl<U: UserStore, C: CredentialStore> Accounts<U, C> {
+    pub fn new(users: U, credentials: C) -> Self {
+        Self {
+            users,
+            credentials,
+            params: Argon2Params::default(),
+            totp: TotpConfig::default(),
+            audit: NoAudit,
+        }
+    }
+}
+
+impl<U: UserStore, C: CredentialStore, A: AuditSink> Accounts<U, C, A> {
+    /// Record sign ins, MFA changes, password changes and account deletions to `audit`
+    pub fn audit<B: AuditSink>(self, audit: B) -> Accounts<U, C, B> {
+        Accounts {
+            users: self.users,
+            credentials: self.credentials,
+            params: self.params,
+            totp: self.totp,
+            audit,
+        }
+    }
+
+    /// Argon2id cost for new hashes; stored hashes with weaker parameters are upgraded on sign in
+    pub fn params(mut self, params: Argon2Params) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn totp_config(mut self, totp: TotpConfig) -> Self {
+        self.totp = totp;
+        self
+    }
+
+    pub fn users(&self) -> &U {
+        &self.users
+    }
+
+    pub fn credentials(&self) -> &C {
+        &self.credentials
+    }
+
+    /// Create a user with a password; check it against a `PasswordPolicy` first
+    pub async fn register(
+        &self,
+        email: &str,
+        username: Option<&str>,
+        password: &str,
+    ) -> Result<User, AuthError> {
+        let email = email.trim().to_lowercase();
+        if email.is_empty() {
+            return Err(AuthError::InvalidInput(
+                "Email address is empty".to_string(),
+            ));
+        }
+
+        let hash = password::hash_with(password, &self.params)?;
+        let user = User {
+            id: generate_token(),
+            email,
+            username: username.map(|username| username.trim().to_lowercase()),
+            email_verified: false,
+            disabled: false,
+            created_at: now()?,
+        };
+
+        self.users.insert(user.clone()).await?;
+        self.credentials.set_password_hash(&user.id, hash).await?;
+        self.record(AuditAction::AccountCreated, &user.id, None)
+            .await?;
+
+        Ok(user)
+    }
+
+    /// Check an email or username and password
+    ///
+    /// Fails with `AuthError::Verification` for unknown users and wrong passwords alike, and with
+    /// `AuthError::InvalidState` for disabled users once the password has been verified.
+    pub async fn authenticate(
+        &self,
+        identifier: &str,
+        password: &str,
+    ) -> Result<SignIn, AuthError> {
+        trace::instrument(
+            "account.authenticate",
+            &[],
+            self.check_password(identifier, password),
+        )
+        .await
+    }
+
+    async fn check_password(&self, identifier: &str, password: &str) -> Result<SignIn, AuthError> {
+        let invalid = || AuthError::Verification("Invalid email or password".to_string());
+
+        let user = self.users.find(&identifier.trim().to_lowercase()).await?;
+        let credentials = match &user {
+            Some(user) => self.credentials.get(&user.id).await?.unwrap_or_default(),
+            None => Credentials::default(),
+        };
+
+        let (user, hash) = match (user, credentials.password_hash) {
+            (Some(user), Some(hash)) => (user, hash),
+            (user, _) => {
+                // Spend as long as a real check would
+                password::hash_with(password, &self.params)?;
+
+                let mut event = AuditEvent::new(AuditAction::LoginFailed)?.detail("unknown user");
+                if let Some(user) = user {
+                    event = event.account(user.id).detail("no password set");
+                }
+                self.audit.record(event).await?;
+
+                return Err(invalid());
+            }
+        };
+
+        if !password::verify(password, &hash)? {
+            self.record(AuditAction::LoginFailed, &user.id, Some("wrong password"))
+                .await?;
+
+            return Err(invalid());
+        }
+
+        if user.disabled {
+            self.record(AuditAction::LoginFailed, &user.id, Some("account disabled"))
+                .await?;
+
+            return Err(AuthError::InvalidState("Account is disabled".to_string()));
+        }
+
+        if password::needs_rehash(&hash, &self.params) {
+            let upgraded = password::hash_with(password, &self.params)?;
+            self.credentials
+                .set_password_hash(&user.id, upgraded)
+                .await?;
+        }
+
+        let mfa_required = credentials.mfa == MfaStatus::Enabled;
+        let detail = mfa_required.then_some("mfa required");
+        self.record(AuditAction::LoginSucceeded, &user.id, detail)
+            .await?;
+
+        Ok(SignIn { user, mfa_required })
+    }
+
+    /// Replace the user's password; revoke their other sessions and tokens afterwards
+    pub async fn set_password(&self, user_id: &str, password: &str) -> Result<(), AuthError> {
+        let hash = password::hash_with(password, &self.params)?;
+        self.credentials.set_password_hash(user_id, hash).await?;
+
+        self.record(AuditAction::PasswordChanged, user_id, None)
+            .await
+    }
+
+    /// Issue a new TOTP secret, pending until `confirm_totp`; any enabled secret is replaced
+    pub async fn start_totp(&self, user_id: &str) -> Result<String, AuthError> {
+        let secret = generate_secret();
+
+        self.credentials
+            .set_totp(user_id, Some(secret.clone()), MfaStatus::Pending)
+            .await?;
+
+        Ok(secret)
+    }
+
+    /// Enable MFA once the user proves their app produces codes for the pending secret
+    pub async fn confirm_totp(&self, user_id: &str, code: String) -> Result<bool, AuthError> {
+        let credentials = self.credentials.get(user_id).await?.unwrap_or_default();
+
+        let secret = match (credentials.mfa, credentials.totp_secret) {
+            (MfaStatus::Pending, Some(secret)) => secret,
+            _ => {
+                return Err(AuthError::InvalidState(
+                    "No TOTP secret is pending confirmation".to_string(),
+                ))
+            }
+        };
+
+        if !self.check_totp(user_id, &code, &secret).await? {
+            self.record(
+                AuditAction::MfaFailed,
+                user_id,
+                Some("enrollment code rejected"),
+            )
+            .await?;
+
+            return Ok(false);
+        }
+
+        self.credentials
+            .set_totp(user_id, Some(secret), MfaStatus::Enabled)
+            .await?;
+        self.record(AuditAction::MfaEnrolled, user_id, Some("totp"))
+            .await?;
+
+        Ok(true)
+    }
+
+    /// Check a code from the user's app; each code is accepted only once
+    pub async fn verify_totp(&self, user_id: &str, code: String) -> Result<bool, AuthError> {
+        let credentials = self.credentials.get(user_id).await?.unwrap_or_default();
+
+        let check = async {
+            match (credentials.mfa, credentials.totp_secret) {
+                (MfaStatus::Enabled, Some(secret)) => {
+                    self.check_totp(user_id, &code, &secret).await
+                }
+                _ => Err(AuthError::InvalidState(
+                    "MFA is not enabled for this user".to_string(),
+                )),
+            }
+        };
+        let verified =
+            trace::instrument("account.verify_totp", &[("account", user_id)], check).await?;
+
+        let action = if verified {
+            AuditAction::MfaVerified
+        } else {
+            AuditAction::MfaFailed
+        };
+        self.record(action, user_id, Some("totp")).await?;
+
+        Ok(verified)
+    }
+
+    pub async fn disable_totp(&self, user_id: &str) -> Result<(), AuthError> {
+        self.credentials
+            .set_totp(user_id, None, MfaStatus::Disabled)
+            .await?;
+
+        self.record(AuditAction::MfaDisabled, user_id, Some("totp"))
+            .await
+    }
+
+    /// Delete the user and their credentials
+    pub async fn delete(&self, user_id: &str) -> Result<bool, AuthError> {
+        self.credentials.delete(user_id).await?;
+
+        let deleted = self.users.delete(user_id).await?;
+        if deleted {
+            self.record(AuditAction::AccountDeleted, user_id, None)
+                .await?;
+        }
+
+        Ok(deleted)
+    }
+
+    async fn record(
+        &self,
+        action: AuditAction,
+        user_id: &str,
+        detail: Option<&str>,
+    ) -> Result<(), AuthError> {
+        let mut event = AuditEvent::new(action)?.account(user_id);
+        if let Some(detail) = detail {
+            event = event.detail(detail);
+        }
+
+        self.audit.record(event).await
+    }
+
+    async fn check_totp(&self, user_id: &str, code: &str, secret: &str) -> Result<bool, AuthError> {
+        match mfa::matching_step(code, secret, &self.totp, now()?)? {
+            Some((_, step)) => self.credentials.record_totp_step(user_id, step).await,
+            None => Ok(false),
+        }
+    }
+}
+
+/// Keeps users in process memory
+///
+/// Ids, emails and usernames are unique, as with the database backed stores: `insert` and
+/// `update` fail with `AuthError::InvalidState` when another user holds one of them.
+#[derive(Debug, Default)]
+pub struct MemoryUserStore {
+    users: Mutex<HashMap<String, User>>,
+}
+
+impl MemoryUserStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, User>>, AuthError> {
+        self.users
+            .lock()
+            .map_err(|_| AuthError::backend("User store lock poisoned"))
+    }
+}
+
+impl UserStore for MemoryUserStore {
+    async fn insert(&self, user: User) -> Result<(), AuthError> {
+        let mut users = self.lock()?;
+        if users.contains_key(&user.id) || users.values().any(|other| conflicts(other, &user)) {
+            return Err(AuthError::InvalidState(
+                "Id, email or username is taken".to_string(),
+            ));
+        }
+
+        users.insert(user.id.clone(), user);
+
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<User>, AuthError> {
+        Ok(self.lock()?.get(id).cloned())
+    }
+
+    async fn find(&self, identifier: &str) -> Result<Option<User>, AuthError> {
+        let users = self.lock()?;
+        let by_email = users.values().find(|user| user.email == identifier);
+
+        Ok(by_email
+            .or_else(|| {
+                users
+                    .values()
+                    .find(|user| user.username.as_deref() == Some(identifier))
+            })
+            .cloned())
+    }
+
+    async fn update(&self, user: User) -> Result<bool, AuthError> {
+        let mut users = self.lock()?;
+        if !users.contains_key(&user.id) {
+            return Ok(false);
+        }
+
+        if users
+            .values()
+            .any(|other| other.id != user.id && conflicts(other, &user))
+        {
+            return Err(AuthError::InvalidState(
+                "Email or username is taken".to_string(),
+            ));
+        }
+
+        users.insert(user.id.clone(), user);
+
+        Ok(true)
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool, AuthError> {
+        Ok(self.lock()?.remove(id).is_some())
+    }
+}
+
+/// Keeps credentials in process memory
+#[derive(Debug, Default)]
+pub struct MemoryCredentialStore {
+    credentials: Mutex<HashMap<String, Credentials>>,
+}
+
+impl MemoryCredentialStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, Credentials>>, AuthError> {
+        self.credentials
+            .lock()
+            .map_err(|_| AuthError::backend("Credential store lock poisoned"))
+    }
+}
+
+impl CredentialStore for MemoryCredentialStore {
+    async fn get(&self, user_id: &str) -> Result<Option<Credentials>, AuthError> {
+        Ok(self.lock()?.get(user_id).cloned())
+    }
+
+    async fn set_password_hash(&self, user_id: &str, hash: String) -> Result<(), AuthError> {
+        self.lock()?
+            .entry(user_id.to_string())
+            .or_default()
+            .password_hash = Some(hash);
+
+        Ok(())
+    }
+
+    async fn set_totp(
+        &self,
+        user_id: &str,
+        secret: Option<String>,
+        status: MfaStatus,
+    ) -> Result<(), AuthError> {
+        let mut credentials = self.lock()?;
+        let credentials = credentials.entry(user_id.to_string()).or_default();
+
+        credentials.totp_secret = secret;
+        credentials.mfa = status;
+        credentials.last_totp_step = None;
+
+        Ok(())
+    }
+
+    async fn record_totp_step(&self, user_id: &str, step: u64) -> Result<bool, AuthError> {
+        let mut credentials = self.lock()?;
+        let credentials = credentials.entry(user_id.to_string()).or_default();
+
+        if credentials.last_totp_step.is_some_and(|last| last >= step) {
+            return Ok(false);
+        }
+        credentials.last_totp_step = Some(step);
+
+        Ok(true)
+    }
+
+    async fn delete(&self, user_id: &str) -> Result<(), AuthError> {
+        self.lock()?.remove(user_id);
+
+        Ok(())
+    }
+}
+
+/// Whether two users share an email or username
+fn conflicts(a: &User, b: &User) -> bool {
+    a.email == b.email || (a.username.is_some() && a.username == b.username)
+}
@@ -0,0 +1,286 @@
+use std::sync::Arc;
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+
+use crate::{
+    postgres::{PostgresClient, Row},
+    AuthError,
+};
+
+use super::{CredentialStore, Credentials, MfaStatus, User, UserStore};
+
+const USER_COLUMNS: &str = "id, email, username, email_verified, disabled, created_at";
+
+/// A `UserStore` keeping users in the `auth_users` table created by `PostgresClient::migrate`
+///
+/// The id, email and username columns have unique indexes, so `insert` claims all three in one
+/// statement and fails with `AuthError::InvalidState` if any is taken.
+///
+/// ### Example
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::account::postgres::{PostgresCredentialStore, PostgresUserStore};
+/// use lonewolf_auth_toolkit::account::Accounts;
+/// use lonewolf_auth_toolkit::postgres::{PostgresClient, PostgresConfig};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = PostgresConfig::new("postgres", "myapp").password("SomePassword");
+///     let postgres = Arc::new(PostgresClient::connect("127.0.0.1:5432", &config).await?);
+///     postgres.migrate().await?;
+///
+///     let accounts = Accounts::new(
+///         PostgresUserStore::new(postgres.clone()),
+///         PostgresCredentialStore::new(postgres),
+///     );
+///
+///     let user = accounts
+///         .register("someone@example.com", None, "correct horse battery staple")
+///         .await?;
+///     let signed_in = accounts
+///         .authenticate("someone@example.com", "correct horse battery staple")
+///         .await?;
+///     assert_eq!(signed_in.user.id, user.id);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct PostgresUserStore<S = TcpStream> {
+    client: Arc<PostgresClient<S>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> PostgresUserStore<S> {
+    pub fn new(client: Arc<PostgresClient<S>>) -> Self {
+        Self { client }
+    }
+
+    pub fn client(&self) -> &PostgresClient<S> {
+        &self.client
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> UserStore for PostgresUserStore<S> {
+    async fn insert(&self, user: User) -> Result<(), AuthError> {
+        let created_at = user.created_at.to_string();
+
+        let inserted = self
+            .client
+            .execute(
+                "INSERT INTO auth_users (id, email, username, email_verified, disabled, created_at) \
+                 VALUES ($1, $2, $3, $4::boolean, $5::boolean, $6::bigint) ON CONFLICT DO NOTHING",
+                &[
+                    Some(&user.id),
+                    Some(&user.email),
+                    user.username.as_deref(),
+                    Some(flag(user.email_verified)),
+                    Some(flag(user.disabled)),
+                    Some(&created_at),
+                ],
+            )
+            .await?;
+
+        if inserted == 0 {
+            return Err(AuthError::InvalidState(
+                "Id, email or username is taken".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<User>, AuthError> {
+        let sql = format!("SELECT {} FROM auth_users WHERE id = $1", USER_COLUMNS);
+
+        self.client
+            .query(&sql, &[Some(id)])
+            .await?
+            .first()
+            .map(user)
+            .transpose()
+    }
+
+    async fn find(&self, identifier: &str) -> Result<Option<User>, AuthError> {
+        let sql = format!(
+            "SELECT {} FROM auth_users WHERE email = $1 OR username = $1 \
+             ORDER BY email = $1 DESC LIMIT 1",
+            USER_COLUMNS
+        );
+
+        self.client
+            .query(&sql, &[Some(identifier)])
+            .await?
+            .first()
+            .map(user)
+            .transpose()
+    }
+
+    async fn update(&self, user: User) -> Result<bool, AuthError> {
+        let updated = self
+            .client
+            .execute(
+                "UPDATE auth_users SET email = $2, username = $3, email_verified = $4::boolean, \
+                 disabled = $5::boolean WHERE id = $1",
+                &[
+                    Some(&user.id),
+                    Some(&user.email),
+                    user.username.as_deref(),
+                    Some(flag(user.email_verified)),
+                    Some(flag(user.disabled)),
+                ],
+            )
+            .await?;
+
+        Ok(updated == 1)
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool, AuthError> {
+        let deleted = self
+            .client
+            .execute("DELETE FROM auth_users WHERE id = $1", &[Some(id)])
+            .await?;
+
+        Ok(deleted == 1)
+    }
+}
+
+/// A `CredentialStore` keeping credentials in the `auth_credentials` table created by
+/// `PostgresClient::migrate`
+///
+/// `record_totp_step` is a single upsert whose update only applies to an earlier step, so two
+/// requests racing with the same code cannot both succeed. TOTP secrets are stored as given; seal
+/// them before they reach the store if the database is not encrypted at rest.
+pub struct PostgresCredentialStore<S = TcpStream> {
+    client: Arc<PostgresClient<S>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> PostgresCredentialStore<S> {
+    pub fn new(client: Arc<PostgresClient<S>>) -> Self {
+        Self { client }
+    }
+
+    pub fn client(&self) -> &PostgresClient<S> {
+        &self.client
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> CredentialStore for PostgresCredentialStore<S> {
+    async fn get(&self, user_id: &str) -> Result<Option<Credentials>, AuthError> {
+        let rows = self
+            .client
+            .query(
+                "SELECT password_hash, totp_secret, mfa, last_totp_step FROM auth_credentials \
+                 WHERE user_id = $1",
+                &[Some(user_id)],
+            )
+            .await?;
+
+        let row = match rows.first() {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let mfa = match row.text(2)? {
+            "disabled" => MfaStatus::Disabled,
+            "pending" => MfaStatus::Pending,
+            "enabled" => MfaStatus::Enabled,
+            other => return Err(AuthError::backend(format!("Unknown MFA status {}", other))),
+        };
+
+        let last_totp_step = match row.get(3) {
+            Some(_) => Some(u64::try_from(row.int(3)?)?),
+            None => None,
+        };
+
+        Ok(Some(Credentials {
+            password_hash: row.get(0).map(str::to_string),
+            totp_secret: row.get(1).map(str::to_string),
+            mfa,
+            last_totp_step,
+        }))
+    }
+
+    async fn set_password_hash(&self, user_id: &str, hash: String) -> Result<(), AuthError> {
+        self.client
+            .execute(
+                "INSERT INTO auth_credentials (user_id, password_hash) VALUES ($1, $2) \
+                 ON CONFLICT (user_id) DO UPDATE SET password_hash = EXCLUDED.password_hash",
+                &[Some(user_id), Some(&hash)],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_totp(
+        &self,
+        user_id: &str,
+        secret: Option<String>,
+        status: MfaStatus,
+    ) -> Result<(), AuthError> {
+        let status = match status {
+            MfaStatus::Disabled => "disabled",
+            MfaStatus::Pending => "pending",
+            MfaStatus::Enabled => "enabled",
+        };
+
+        self.client
+            .execute(
+                "INSERT INTO auth_credentials (user_id, totp_secret, mfa) VALUES ($1, $2, $3) \
+                 ON CONFLICT (user_id) DO UPDATE SET totp_secret = EXCLUDED.totp_secret, \
+                 mfa = EXCLUDED.mfa, last_totp_step = NULL",
+                &[Some(user_id), secret.as_deref(), Some(status)],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn record_totp_step(&self, user_id: &str, step: u64) -> Result<bool, AuthError> {
+        let recorded = self
+            .client
+            .execute(
+                "INSERT INTO auth_credentials (user_id, last_totp_step) VALUES ($1, $2::bigint) \
+                 ON CONFLICT (user_id) DO UPDATE SET last_totp_step = EXCLUDED.last_totp_step \
+                 WHERE auth_credentials.last_totp_step IS NULL \
+                 OR auth_credentials.last_totp_step < EXCLUDED.last_totp_step",
+                &[Some(user_id), Some(&step.to_string())],
+            )
+            .await?;
+
+        Ok(recorded == 1)
+    }
+
+    async fn delete(&self, user_id: &str) -> Result<(), AuthError> {
+        self.client
+            .execute(
+                "DELETE FROM auth_credentials WHERE user_id = $1",
+                &[Some(user_id)],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn user(row: &Row) -> Result<User, AuthError> {
+    Ok(User {
+        id: row.text(0)?.to_string(),
+        email: row.text(1)?.to_string(),
+        username: row.get(2).map(str::to_string),
+        email_verified: row.bool(3)?,
+        disabled: row.bool(4)?,
+        created_at: u64::try_from(row.int(5)?)?,
+    })
+}
+
+fn flag(value: bool) -> &'static str {
+    if value {
+        "true"
+    } else {
+        "false"
+    }
+}
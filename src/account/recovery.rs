@@ -0,0 +1,700 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::knowledge::{SecurityAnswerStore, SecurityQuestions},
+    audit::{AuditAction, AuditEvent, AuditSink, NoAudit},
+    mfa::{
+        self,
+        code::{self, CodeStore},
+        generate_numeric_code,
+        recovery::RecoveryCodeStore,
+    },
+    token::{generate_token, hash_token, now},
+    AuthError,
+};
+
+/// How long a recovery can take, long enough for an administrator to review it
+pub const DEFAULT_TTL: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+/// Wrong codes allowed across all steps before the recovery is denied
+pub const DEFAULT_MAX_FAILURES: u32 = 5;
+
+/// How often a step is retried against a case other requests keep changing before giving up
+const UPDATE_ATTEMPTS: usize = 5;
+
+/// Something the user has to get through to recover their account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RecoveryStep {
+    /// Enter a code sent to the account's email address
+    EmailCode,
+    /// Enter one of the account's recovery codes
+    RecoveryCode,
+    /// Answer the account's security questions; low assurance, so never the only step
+    SecurityQuestions,
+    /// Wait for an administrator to approve the request
+    AdminApproval,
+}
+
+impl RecoveryStep {
+    /// The `snake_case` name, e.g. `email_code`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecoveryStep::EmailCode => "email_code",
+            RecoveryStep::RecoveryCode => "recovery_code",
+            RecoveryStep::SecurityQuestions => "security_questions",
+            RecoveryStep::AdminApproval => "admin_approval",
+        }
+    }
+}
+
+/// Where a recovery is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryStatus {
+    /// Waiting for this step to be completed
+    Awaiting(RecoveryStep),
+    /// Every step is done; `finish` hands over the account
+    Completed,
+    /// An administrator refused it, or too many wrong codes were entered
+    Denied,
+    Expired,
+}
+
+/// A recovery in progress, as persisted between the requests that drive it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecoveryCase {
+    /// Hash of the token given to the user, and the id administrators approve by
+    pub id: String,
+    pub account: String,
+    pub email: String,
+    /// The steps still to complete, in order
+    pub remaining: Vec<RecoveryStep>,
+    pub failures: u32,
+    pub denied: bool,
+    /// The administrator who approved the recovery
+    pub approved_by: Option<String>,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+    /// Incremented on every change, so concurrent changes are detected by `RecoveryStore::update`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub version: u64,
+}
+
+impl RecoveryCase {
+    pub fn status(&self) -> Result<RecoveryStatus, AuthError> {
+        Ok(if self.denied {
+            RecoveryStatus::Denied
+        } else if self.expires_at <= now()? {
+            RecoveryStatus::Expired
+        } else {
+            match self.remaining.first() {
+                Some(step) => RecoveryStatus::Awaiting(*step),
+                None => RecoveryStatus::Completed,
+            }
+        })
+    }
+}
+
+/// Persists recovery cases by id
+///
+/// `update` and `remove` compare the stored `version` and write in one atomic operation (e.g.
+/// `UPDATE ... WHERE id = $1 AND version = $2`), so a user's step racing an administrator's
+/// denial cannot overwrite it and concurrent wrong codes are all counted.
+pub trait RecoveryStore {
+    /// Insert a new case
+    fn save(&self, case: RecoveryCase) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Replace the case stored under its id only if the stored one is still at `version`
+    ///
+    /// Returns `false` and saves nothing if the case is gone or was changed in the meantime.
+    fn update(
+        &self,
+        case: RecoveryCase,
+        version: u64,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    fn load(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<RecoveryCase>, AuthError>> + Send;
+
+    /// Remove the case if it is still at `version`, returning whether it did, so a completed
+    /// recovery is only finished once and never after it was denied
+    fn remove(
+        &self,
+        id: &str,
+        version: u64,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Unexpired cases whose next step is `AdminApproval`, for an approval queue
+    fn awaiting_approval(
+        &self,
+    ) -> impl Future<Output = Result<Vec<RecoveryCase>, AuthError>> + Send;
+}
+
+/// Recovers accounts whose users lost both their password and their MFA device
+///
+/// A recovery runs through the configured steps in order, by default an emailed code, then one of
+/// the account's recovery codes, then an administrator's approval. `start` returns a token to
+/// keep in the user's session; the user facing methods take that token, while administrators
+/// approve or deny by the case id, its hash, as listed by `RecoveryStore::awaiting_approval`.
+/// Taking a step out of order fails with `AuthError::InvalidState` and a wrong code with
+/// `AuthError::Verification`; after `max_failures` wrong codes the recovery is denied. Once
+/// every step is done, `finish` returns the account so the caller can let the user set a new
+/// password and enroll MFA again, e.g. with `Accounts::set_password` and
+/// `Accounts::disable_totp`. Starting, approving, denying and finishing are audited.
+///
+/// Deployments that need it can add `RecoveryStep::SecurityQuestions`, answered with
+/// `verify_security_answers` once `security_questions` has been configured.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::account::recovery::{
+///     AccountRecovery, MemoryRecoveryStore, RecoveryStatus, RecoveryStep,
+/// };
+/// use lonewolf_auth_toolkit::mfa::code::MemoryCodeStore;
+/// use lonewolf_auth_toolkit::mfa::recovery::{self, MemoryRecoveryCodeStore};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let recovery = AccountRecovery::new(
+///         MemoryRecoveryStore::default(),
+///         MemoryCodeStore::default(),
+///         MemoryRecoveryCodeStore::default(),
+///     );
+///     let codes = recovery::generate(recovery.recovery_codes(), "SomeUserId").await?;
+///
+///     let token = recovery.start("SomeUserId", "someone@example.com").await?;
+///     let status = recovery.status(&token).await?;
+///     assert_eq!(status, RecoveryStatus::Awaiting(RecoveryStep::EmailCode));
+///
+///     // Steps can only be taken in order
+///     assert!(recovery.verify_recovery_code(&token, codes[0].clone()).await.is_err());
+///
+///     // Email the code to the address the recovery was started for
+///     let code = recovery.email_code(&token).await?;
+///     recovery.verify_email(&token, code).await?;
+///     let status = recovery.verify_recovery_code(&token, codes[0].clone()).await?;
+///     assert_eq!(status, RecoveryStatus::Awaiting(RecoveryStep::AdminApproval));
+///
+///     // An administrator reviews the queue and approves by case id
+///     let case = recovery.awaiting_approval().await?.remove(0);
+///     assert_eq!(recovery.approve(&case.id, "SomeAdminId").await?, RecoveryStatus::Completed);
+///
+///     assert_eq!(recovery.finish(&token).await?, "SomeUserId");
+///     assert!(recovery.finish(&token).await.is_err());
+///
+///     // A denied recovery stays denied
+///     let other = recovery.start("SomeUserId", "someone@example.com").await?;
+///     recovery.deny(&recovery.case(&other).await?.id, "SomeAdminId").await?;
+///     assert_eq!(recovery.status(&other).await?, RecoveryStatus::Denied);
+///     assert!(recovery.email_code(&other).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct AccountRecovery<S, C, R, A = NoAudit, K = ()> {
+    cases: S,
+    codes: C,
+    recovery_codes: R,
+    steps: Vec<RecoveryStep>,
+    ttl: Duration,
+    max_failures: u32,
+    audit: A,
+    security_questions: K,
+}
+
+impl<S: RecoveryStore, C: CodeStore, R: RecoveryCodeStore> AccountRecovery<S, C, R> {
+    pub fn new(cases: S, codes: C, recovery_codes: R) -> Self {
+        Self {
+            cases,
+            codes,
+            recovery_codes,
+            steps: vec![
+                RecoveryStep::EmailCode,
+                RecoveryStep::RecoveryCode,
+                RecoveryStep::AdminApproval,
+            ],
+            ttl: DEFAULT_TTL,
+            max_failures: DEFAULT_MAX_FAILURES,
+            audit: NoAudit,
+            security_questions: (),
+        }
+    }
+}
+
+impl<S: RecoveryStore, C: CodeStore, R: RecoveryCodeStore, A: AuditSink, K>
+    AccountRecovery<S, C, R, A, K>
+{
+    /// Record recoveries to an audit sink
+    pub fn audit<B: AuditSink>(self, audit: B) -> AccountRecovery<S, C, R, B, K> {
+        AccountRecovery {
+            cases: self.cases,
+            codes: self.codes,
+            recovery_codes: self.recovery_codes,
+            steps: self.steps,
+            ttl: self.ttl,
+            max_failures: self.max_failures,
+            audit,
+            security_questions: self.security_questions,
+        }
+    }
+
+    /// Check `RecoveryStep::SecurityQuestions` against these questions
+    ///
+    /// ### Example
+    /// ```rust
+    /// use lonewolf_auth_toolkit::account::knowledge::{
+    ///     MemorySecurityAnswerStore, SecurityQuestions,
+    /// };
+    /// use lonewolf_auth_toolkit::account::recovery::{
+    ///     AccountRecovery, MemoryRecoveryStore, RecoveryStatus, RecoveryStep,
+    /// };
+    /// use lonewolf_auth_toolkit::mfa::code::MemoryCodeStore;
+    /// use lonewolf_auth_toolkit::mfa::recovery::MemoryRecoveryCodeStore;
+    /// use lonewolf_auth_toolkit::password::argon2::Argon2Params;
+    ///
+    /// #[tokio::main]
+    /// pub async fn main() -> Result<(), anyhow::Error> {
+    ///     let questions = SecurityQuestions::new(MemorySecurityAnswerStore::default())
+    ///         .params(Argon2Params::default().memory_kib(1024).iterations(1));
+    ///     questions.enroll("SomeUserId", &[("Name of your first pet?", "Fluffy")]).await?;
+    ///
+    ///     let recovery = AccountRecovery::new(
+    ///         MemoryRecoveryStore::default(),
+    ///         MemoryCodeStore::default(),
+    ///         MemoryRecoveryCodeStore::default(),
+    ///     )
+    ///     .steps(vec![RecoveryStep::EmailCode, RecoveryStep::SecurityQuestions])?
+    ///     .security_questions(questions);
+    ///
+    ///     let token = recovery.start("SomeUserId", "someone@example.com").await?;
+    ///     let code = recovery.email_code(&token).await?;
+    ///     recovery.verify_email(&token, code).await?;
+    ///
+    ///     let asked = recovery.questions(&token).await?;
+    ///     assert_eq!(asked, ["Name of your first pet?"]);
+    ///     let status = recovery.verify_security_answers(&token, &[(&asked[0], "fluffy")]).await?;
+    ///     assert_eq!(status, RecoveryStatus::Completed);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn security_questions<Q: SecurityAnswerStore>(
+        self,
+        questions: SecurityQuestions<Q>,
+    ) -> AccountRecovery<S, C, R, A, SecurityQuestions<Q>> {
+        AccountRecovery {
+            cases: self.cases,
+            codes: self.codes,
+            recovery_codes: self.recovery_codes,
+            steps: self.steps,
+            ttl: self.ttl,
+            max_failures: self.max_failures,
+            audit: self.audit,
+            security_questions: questions,
+        }
+    }
+
+    /// The steps new recoveries go through, in order; at least one, each at most once
+    ///
+    /// `RecoveryStep::SecurityQuestions` must be combined with another step.
+    pub fn steps(mut self, steps: Vec<RecoveryStep>) -> Result<Self, AuthError> {
+        let repeated = steps
+            .iter()
+            .enumerate()
+            .any(|(index, step)| steps[..index].contains(step));
+        if steps.is_empty() || repeated {
+            return Err(AuthError::InvalidInput(
+                "Recovery needs at least one step, each at most once".to_string(),
+            ));
+        }
+        if steps == [RecoveryStep::SecurityQuestions] {
+            return Err(AuthError::InvalidInput(
+                "Security questions cannot be the only recovery step".to_string(),
+            ));
+        }
+
+        self.steps = steps;
+        Ok(self)
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn max_failures(mut self, max_failures: u32) -> Self {
+        self.max_failures = max_failures;
+        self
+    }
+
+    pub fn recovery_codes(&self) -> &R {
+        &self.recovery_codes
+    }
+
+    /// Start recovering `account`, whose verified address is `email`, returning the user's token
+    pub async fn start(&self, account: &str, email: &str) -> Result<String, AuthError> {
+        let token = generate_token();
+        self.cases
+            .save(RecoveryCase {
+                id: hash_token(&token),
+                account: account.to_string(),
+                email: email.to_string(),
+                remaining: self.steps.clone(),
+                failures: 0,
+                denied: false,
+                approved_by: None,
+                expires_at: now()? + self.ttl.as_secs(),
+                version: 0,
+            })
+            .await?;
+
+        let steps: Vec<_> = self.steps.iter().map(RecoveryStep::as_str).collect();
+        let event = AuditEvent::new(AuditAction::AccountRecoveryStarted)?
+            .account(account)
+            .detail(steps.join(", "));
+        self.audit.record(event).await?;
+
+        Ok(token)
+    }
+
+    /// The recovery behind a token, e.g. to show the address the code was sent to
+    pub async fn case(&self, token: &str) -> Result<RecoveryCase, AuthError> {
+        self.load(&hash_token(token)).await
+    }
+
+    pub async fn status(&self, token: &str) -> Result<RecoveryStatus, AuthError> {
+        self.case(token).await?.status()
+    }
+
+    /// Generate the code for the `EmailCode` step, replacing any earlier one
+    ///
+    /// Delivery is left to the caller, to the case's `email`.
+    pub async fn email_code(&self, token: &str) -> Result<String, AuthError> {
+        let case = self
+            .awaiting(&hash_token(token), RecoveryStep::EmailCode)
+            .await?;
+        let code = generate_numeric_code(6);
+        code::save(
+            &self.codes,
+            &code_key(&case),
+            &code,
+            mfa::email::DEFAULT_TTL,
+        )
+        .await?;
+
+        Ok(code)
+    }
+
+    pub async fn verify_email(
+        &self,
+        token: &str,
+        code: String,
+    ) -> Result<RecoveryStatus, AuthError> {
+        let case = self
+            .awaiting(&hash_token(token), RecoveryStep::EmailCode)
+            .await?;
+        let key = code_key(&case);
+        let passed =
+            code::verify(&self.codes, &key, &code, mfa::email::DEFAULT_MAX_ATTEMPTS).await?;
+
+        self.advance(case, RecoveryStep::EmailCode, passed).await
+    }
+
+    pub async fn verify_recovery_code(
+        &self,
+        token: &str,
+        code: String,
+    ) -> Result<RecoveryStatus, AuthError> {
+        let case = self
+            .awaiting(&hash_token(token), RecoveryStep::RecoveryCode)
+            .await?;
+        let passed = mfa::recovery::verify(&self.recovery_codes, &case.account, code).await?;
+
+        self.advance(case, RecoveryStep::RecoveryCode, passed).await
+    }
+
+    /// Unexpired recoveries waiting for an administrator
+    pub async fn awaiting_approval(&self) -> Result<Vec<RecoveryCase>, AuthError> {
+        self.cases.awaiting_approval().await
+    }
+
+    /// Complete the `AdminApproval` step of the case `id`
+    ///
+    /// Fails with `AuthError::InvalidInput` when administrators try to approve their own account.
+    pub async fn approve(&self, id: &str, admin: &str) -> Result<RecoveryStatus, AuthError> {
+        let case = self
+            .update(id, Some(RecoveryStep::AdminApproval), |case| {
+                if admin.is_empty() || admin == case.account {
+                    return Err(AuthError::InvalidInput(
+                        "Recovery must be approved by another administrator".to_string(),
+                    ));
+                }
+
+                case.remaining.remove(0);
+                case.approved_by = Some(admin.to_string());
+                Ok(())
+            })
+            .await?;
+
+        let event = AuditEvent::new(AuditAction::AccountRecoveryApproved)?
+            .account(&case.account)
+            .detail(admin);
+        self.audit.record(event).await?;
+
+        case.status()
+    }
+
+    /// Refuse the case `id`, at any step
+    pub async fn deny(&self, id: &str, admin: &str) -> Result<(), AuthError> {
+        let case = self
+            .update(id, None, |case| {
+                case.denied = true;
+                Ok(())
+            })
+            .await?;
+
+        self.denied(&case, &format!("denied by {}", admin)).await
+    }
+
+    /// Hand over the account of a completed recovery, once
+    pub async fn finish(&self, token: &str) -> Result<String, AuthError> {
+        let id = hash_token(token);
+        let case = self.load(&id).await?;
+        if case.status()? != RecoveryStatus::Completed {
+            return Err(AuthError::InvalidState(
+                "Recovery is not complete".to_string(),
+            ));
+        }
+        if !self.cases.remove(&id, case.version).await? {
+            return Err(AuthError::InvalidState(
+                "Recovery changed while finishing it".to_string(),
+            ));
+        }
+
+        let event = AuditEvent::new(AuditAction::AccountRecovered)?.account(&case.account);
+        self.audit.record(event).await?;
+
+        Ok(case.account)
+    }
+
+    async fn load(&self, id: &str) -> Result<RecoveryCase, AuthError> {
+        self.cases
+            .load(id)
+            .await?
+            .ok_or_else(|| AuthError::NotFound("Unknown recovery".to_string()))
+    }
+
+    /// The case, if `step` is the one it is waiting for
+    async fn awaiting(&self, id: &str, step: RecoveryStep) -> Result<RecoveryCase, AuthError> {
+        let case = self.load(id).await?;
+
+        match case.status()? {
+            RecoveryStatus::Awaiting(current) if current == step => Ok(case),
+            RecoveryStatus::Awaiting(current) => Err(AuthError::InvalidState(format!(
+                "Recovery is waiting for the {} step",
+                current.as_str()
+            ))),
+            RecoveryStatus::Completed => Err(AuthError::InvalidState(
+                "Recovery is already complete".to_string(),
+            )),
+            RecoveryStatus::Denied => {
+                Err(AuthError::InvalidState("Recovery was denied".to_string()))
+            }
+            RecoveryStatus::Expired => {
+                Err(AuthError::InvalidState("Recovery has expired".to_string()))
+            }
+        }
+    }
+
+    /// Apply `change` to the latest copy of the case and save it, unless another request
+    /// changed the case in the meantime, in which case the latest copy is checked and changed
+    /// again
+    ///
+    /// With a `step`, only a case waiting for that step is changed, so a denial is never undone.
+    async fn update(
+        &self,
+        id: &str,
+        step: Option<RecoveryStep>,
+        change: impl Fn(&mut RecoveryCase) -> Result<(), AuthError>,
+    ) -> Result<RecoveryCase, AuthError> {
+        for _ in 0..UPDATE_ATTEMPTS {
+            let mut case = match step {
+                Some(step) => self.awaiting(id, step).await?,
+                None => self.load(id).await?,
+            };
+            let version = case.version;
+
+            change(&mut case)?;
+            case.version = version + 1;
+            if self.cases.update(case.clone(), version).await? {
+                return Ok(case);
+            }
+        }
+
+        Err(AuthError::InvalidState(
+            "Recovery is being changed by another request".to_string(),
+        ))
+    }
+
+    /// Move past the current step if its check passed, otherwise count the failure
+    async fn advance(
+        &self,
+        case: RecoveryCase,
+        step: RecoveryStep,
+        passed: bool,
+    ) -> Result<RecoveryStatus, AuthError> {
+        if passed {
+            let case = self
+                .update(&case.id, Some(step), |case| {
+                    case.remaining.remove(0);
+                    Ok(())
+                })
+                .await?;
+
+            return case.status();
+        }
+
+        let max_failures = self.max_failures;
+        let case = self
+            .update(&case.id, Some(step), |case| {
+                case.failures += 1;
+                case.denied = case.failures >= max_failures;
+                Ok(())
+            })
+            .await?;
+        if case.denied {
+            self.denied(&case, "too many wrong codes").await?;
+        }
+
+        Err(AuthError::Verification(
+            "Recovery code is wrong".to_string(),
+        ))
+    }
+
+    async fn denied(&self, case: &RecoveryCase, reason: &str) -> Result<(), AuthError> {
+        let event = AuditEvent::new(AuditAction::AccountRecoveryDenied)?
+            .account(&case.account)
+            .detail(reason);
+        self.audit.record(event).await
+    }
+}
+
+impl<S, C, R, A, Q> AccountRecovery<S, C, R, A, SecurityQuestions<Q>>
+where
+    S: RecoveryStore,
+    C: CodeStore,
+    R: RecoveryCodeStore,
+    A: AuditSink,
+    Q: SecurityAnswerStore,
+{
+    /// The questions to ask for the `SecurityQuestions` step
+    pub async fn questions(&self, token: &str) -> Result<Vec<String>, AuthError> {
+        let case = self
+            .awaiting(&hash_token(token), RecoveryStep::SecurityQuestions)
+            .await?;
+
+        self.security_questions.questions(&case.account).await
+    }
+
+    /// Complete the `SecurityQuestions` step with an answer to each question
+    ///
+    /// Wrong answers count towards both the recovery's `max_failures` and the questions' own
+    /// attempt limit.
+    pub async fn verify_security_answers(
+        &self,
+        token: &str,
+        answers: &[(&str, &str)],
+    ) -> Result<RecoveryStatus, AuthError> {
+        let case = self
+            .awaiting(&hash_token(token), RecoveryStep::SecurityQuestions)
+            .await?;
+        let passed = self
+            .security_questions
+            .verify(&case.account, answers)
+            .await?;
+
+        self.advance(case, RecoveryStep::SecurityQuestions, passed)
+            .await
+    }
+}
+
+/// Keeps emailed recovery codes apart from sign in codes for the same address
+fn code_key(case: &RecoveryCase) -> String {
+    format!("recovery:{}", case.id)
+}
+
+/// Keeps recovery cases in process memory, dropping expired ones as new ones are saved
+#[derive(Debug, Default)]
+pub struct MemoryRecoveryStore {
+    cases: Mutex<HashMap<String, RecoveryCase>>,
+}
+
+impl MemoryRecoveryStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, RecoveryCase>>, AuthError> {
+        self.cases
+            .lock()
+            .map_err(|_| AuthError::backend("Recovery store lock poisoned"))
+    }
+}
+
+impl RecoveryStore for MemoryRecoveryStore {
+    async fn save(&self, case: RecoveryCase) -> Result<(), AuthError> {
+        let now = now()?;
+        let mut cases = self.lock()?;
+
+        cases.retain(|_, case| case.expires_at > now);
+        cases.insert(case.id.clone(), case);
+
+        Ok(())
+    }
+
+    async fn load(&self, id: &str) -> Result<Option<RecoveryCase>, AuthError> {
+        Ok(self.lock()?.get(id).cloned())
+    }
+
+    async fn update(&self, case: RecoveryCase, version: u64) -> Result<bool, AuthError> {
+        match self.lock()?.get_mut(&case.id) {
+            Some(current) if current.version == version => {
+                *current = case;
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn remove(&self, id: &str, version: u64) -> Result<bool, AuthError> {
+        let mut cases = self.lock()?;
+        match cases.get(id) {
+            Some(case) if case.version == version => Ok(cases.remove(id).is_some()),
+            _ => Ok(false),
+        }
+    }
+
+    async fn awaiting_approval(&self) -> Result<Vec<RecoveryCase>, AuthError> {
+        let now = now()?;
+
+        Ok(self
+            .lock()?
+            .values()
+            .filter(|case| {
+                !case.denied
+                    && case.expires_at > now
+                    && case.remaining.first() == Some(&RecoveryStep::AdminApproval)
+            })
+            .cloned()
+            .collect())
+    }
+}
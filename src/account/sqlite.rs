@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use crate::{
+    sqlite::{Row, SqliteDatabase},
+    AuthError,
+};
+
+use super::{CredentialStore, Credentials, MfaStatus, User, UserStore};
+
+const USER_COLUMNS: &str = "id, email, username, email_verified, disabled, created_at";
+
+/// A `UserStore` keeping users in the `auth_users` table created by `SqliteDatabase::migrate`
+///
+/// The id, email and username columns have unique indexes, so `insert` claims all three in one
+/// statement and fails with `AuthError::InvalidState` if any is taken.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::account::sqlite::{SqliteCredentialStore, SqliteUserStore};
+/// use lonewolf_auth_toolkit::account::Accounts;
+/// use lonewolf_auth_toolkit::sqlite::SqliteDatabase;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let database = Arc::new(SqliteDatabase::open_in_memory()?);
+///     database.migrate()?;
+///
+///     let accounts = Accounts::new(
+///         SqliteUserStore::new(database.clone()),
+///         SqliteCredentialStore::new(database),
+///     );
+///
+///     let user = accounts
+///         .register("someone@example.com", None, "correct horse battery staple")
+///         .await?;
+///     let signed_in = accounts
+///         .authenticate("someone@example.com", "correct horse battery staple")
+///         .await?;
+///     assert_eq!(signed_in.user.id, user.id);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct SqliteUserStore {
+    database: Arc<SqliteDatabase>,
+}
+
+impl SqliteUserStore {
+    pub fn new(database: Arc<SqliteDatabase>) -> Self {
+        Self { database }
+    }
+
+    pub fn database(&self) -> &SqliteDatabase {
+        &self.database
+    }
+}
+
+impl UserStore for SqliteUserStore {
+    async fn insert(&self, user: User) -> Result<(), AuthError> {
+        let created_at = user.created_at.to_string();
+
+        let inserted = self.database.execute(
+            "INSERT INTO auth_users (id, email, username, email_verified, disabled, created_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT DO NOTHING",
+            &[
+                Some(&user.id),
+                Some(&user.email),
+                user.username.as_deref(),
+                Some(flag(user.email_verified)),
+                Some(flag(user.disabled)),
+                Some(&created_at),
+            ],
+        )?;
+
+        if inserted == 0 {
+            return Err(AuthError::InvalidState(
+                "Id, email or username is taken".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<User>, AuthError> {
+        let sql = format!("SELECT {} FROM auth_users WHERE id = ?1", USER_COLUMNS);
+
+        self.database
+            .query(&sql, &[Some(id)])?
+            .first()
+            .map(user)
+            .transpose()
+    }
+
+    async fn find(&self, identifier: &str) -> Result<Option<User>, AuthError> {
+        let sql = format!(
+            "SELECT {} FROM auth_users WHERE email = ?1 OR username = ?1 \
+             ORDER BY email = ?1 DESC LIMIT 1",
+            USER_COLUMNS
+        );
+
+        self.database
+            .query(&sql, &[Some(identifier)])?
+            .first()
+            .map(user)
+            .transpose()
+    }
+
+    async fn update(&self, user: User) -> Result<bool, AuthError> {
+        let updated = self.database.execute(
+            "UPDATE auth_users SET email = ?2, username = ?3, email_verified = ?4, \
+             disabled = ?5 WHERE id = ?1",
+            &[
+                Some(&user.id),
+                Some(&user.email),
+                user.username.as_deref(),
+                Some(flag(user.email_verified)),
+                Some(flag(user.disabled)),
+            ],
+        )?;
+
+        Ok(updated == 1)
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool, AuthError> {
+        let deleted = self
+            .database
+            .execute("DELETE FROM auth_users WHERE id = ?1", &[Some(id)])?;
+
+        Ok(deleted == 1)
+    }
+}
+
+/// A `CredentialStore` keeping credentials in the `auth_credentials` table created by
+/// `SqliteDatabase::migrate`
+///
+/// `record_totp_step` is a single upsert whose update only applies to an earlier step, so two
+/// requests racing with the same code cannot both succeed. TOTP secrets are stored as given; seal
+/// them before they reach the store if the database is not encrypted at rest.
+pub struct SqliteCredentialStore {
+    database: Arc<SqliteDatabase>,
+}
+
+impl SqliteCredentialStore {
+    pub fn new(database: Arc<SqliteDatabase>) -> Self {
+        Self { database }
+    }
+
+    pub fn database(&self) -> &SqliteDatabase {
+        &self.database
+    }
+}
+
+impl CredentialStore for SqliteCredentialStore {
+    async fn get(&self, user_id: &str) -> Result<Option<Credentials>, AuthError> {
+        let rows = self.database.query(
+            "SELECT password_hash, totp_secret, mfa, last_totp_step FROM auth_credentials \
+             WHERE user_id = ?1",
+            &[Some(user_id)],
+        )?;
+
+        let row = match rows.first() {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let mfa = match row.text(2)? {
+            "disabled" => MfaStatus::Disabled,
+            "pending" => MfaStatus::Pending,
+            "enabled" => MfaStatus::Enabled,
+            other => return Err(AuthError::backend(format!("Unknown MFA status {}", other))),
+        };
+
+        let last_totp_step = match row.get(3) {
+            Some(_) => Some(u64::try_from(row.int(3)?)?),
+            None => None,
+        };
+
+        Ok(Some(Credentials {
+            password_hash: row.get(0).map(str::to_string),
+            totp_secret: row.get(1).map(str::to_string),
+            mfa,
+            last_totp_step,
+        }))
+    }
+
+    async fn set_password_hash(&self, user_id: &str, hash: String) -> Result<(), AuthError> {
+        self.database.execute(
+            "INSERT INTO auth_credentials (user_id, password_hash) VALUES (?1, ?2) \
+             ON CONFLICT (user_id) DO UPDATE SET password_hash = excluded.password_hash",
+            &[Some(user_id), Some(&hash)],
+        )?;
+
+        Ok(())
+    }
+
+    async fn set_totp(
+        &self,
+        user_id: &str,
+        secret: Option<String>,
+        status: MfaStatus,
+    ) -> Result<(), AuthError> {
+        let status = match status {
+            MfaStatus::Disabled => "disabled",
+            MfaStatus::Pending => "pending",
+            MfaStatus::Enabled => "enabled",
+        };
+
+        self.database.execute(
+            "INSERT INTO auth_credentials (user_id, totp_secret, mfa) VALUES (?1, ?2, ?3) \
+             ON CONFLICT (user_id) DO UPDATE SET totp_secret = excluded.totp_secret, \
+             mfa = excluded.mfa, last_totp_step = NULL",
+            &[Some(user_id), secret.as_deref(), Some(status)],
+        )?;
+
+        Ok(())
+    }
+
+    async fn record_totp_step(&self, user_id: &str, step: u64) -> Result<bool, AuthError> {
+        let recorded = self.database.execute(
+            "INSERT INTO auth_credentials (user_id, last_totp_step) VALUES (?1, ?2) \
+             ON CONFLICT (user_id) DO UPDATE SET last_totp_step = excluded.last_totp_step \
+             WHERE auth_credentials.last_totp_step IS NULL \
+             OR auth_credentials.last_totp_step < excluded.last_totp_step",
+            &[Some(user_id), Some(&step.to_string())],
+        )?;
+
+        Ok(recorded == 1)
+    }
+
+    async fn delete(&self, user_id: &str) -> Result<(), AuthError> {
+        self.database.execute(
+            "DELETE FROM auth_credentials WHERE user_id = ?1",
+            &[Some(user_id)],
+        )?;
+
+        Ok(())
+    }
+}
+
+fn user(row: &Row) -> Result<User, AuthError> {
+    Ok(User {
+        id: row.text(0)?.to_string(),
+        email: row.text(1)?.to_string(),
+        username: row.get(2).map(str::to_string),
+        email_verified: row.bool(3)?,
+        disabled: row.bool(4)?,
+        created_at: u64::try_from(row.int(5)?)?,
+    })
+}
+
+fn flag(value: bool) -> &'static str {
+    if value {
+        "1"
+    } else {
+        "0"
+    }
+}
@@ -0,0 +1,537 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    crypto::{blake2b::blake2b_keyed, ct_eq},
+    token::now,
+    trace, AuthError,
+};
+
+/// Prefix used by `KeyFormat::default`
+pub const DEFAULT_PREFIX: &str = "lwk_live";
+
+/// Random base62 characters in a key, about 190 bits
+pub const RANDOM_LEN: usize = 32;
+
+/// Base62 characters holding the CRC32 checksum
+pub const CHECKSUM_LEN: usize = 6;
+
+/// Characters of the random part kept in the clear as the key's id, to find its record
+pub const ID_LEN: usize = 8;
+
+/// Minimum length in bytes of the key used to hash API keys
+pub const MIN_HASH_KEY_LEN: usize = 32;
+
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// The shape of an API key: `<prefix>_<random>_<checksum>`
+///
+/// A distinctive prefix such as `lwk_live` lets secret scanners find leaked keys and tells people
+/// which system and environment a key belongs to. The CRC32 checksum over the rest of the key lets
+/// `check` reject typos and made up keys without a database lookup; it is not a MAC, so keys must
+/// still be verified against storage.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::apikey::KeyFormat;
+///
+/// let live = KeyFormat::new("lwk_live")?;
+/// let key = live.generate();
+/// assert!(key.starts_with("lwk_live_"));
+///
+/// live.check(&key)?;
+///
+/// // Keys for another environment, or with a typo, are rejected before any lookup
+/// assert!(KeyFormat::new("lwk_test")?.check(&key).is_err());
+/// let mut typo = key.into_bytes();
+/// typo[9] = if typo[9] == b'a' { b'b' } else { b'a' };
+/// assert!(live.check(&String::from_utf8(typo)?).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyFormat {
+    prefix: String,
+}
+
+impl Default for KeyFormat {
+    fn default() -> Self {
+        Self {
+            prefix: DEFAULT_PREFIX.to_string(),
+        }
+    }
+}
+
+impl KeyFormat {
+    /// `prefix` is lowercase letters, digits and inner underscores, e.g. `acme_test`
+    pub fn new(prefix: impl Into<String>) -> Result<Self, AuthError> {
+        let prefix = prefix.into();
+
+        let valid = !prefix.is_empty()
+            && !prefix.starts_with('_')
+            && !prefix.ends_with('_')
+            && prefix
+                .bytes()
+                .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'_');
+
+        if !valid {
+            return Err(AuthError::InvalidInput(
+                "API key prefix must be lowercase letters, digits and inner underscores"
+                    .to_string(),
+            ));
+        }
+
+        Ok(Self { prefix })
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// A new random key; show it to its owner once and store only a hash of it
+    pub fn generate(&self) -> String {
+        let random: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(RANDOM_LEN)
+            .map(char::from)
+            .collect();
+
+        let body = format!("{}_{}", self.prefix, random);
+        let checksum = checksum(&body);
+
+        format!("{}_{}", body, checksum)
+    }
+
+    /// Check a key has this format's prefix and a valid checksum
+    pub fn check(&self, key: &str) -> Result<(), AuthError> {
+        self.random_part(key).map(|_| ())
+    }
+
+    /// The random part of a key that passes `check`
+    pub fn random_part<'a>(&self, key: &'a str) -> Result<&'a str, AuthError> {
+        let malformed = || AuthError::Malformed("Not a valid API key".to_string());
+
+        let rest = key
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|rest| rest.strip_prefix('_'))
+            .ok_or_else(malformed)?;
+
+        if rest.len() != RANDOM_LEN + 1 + CHECKSUM_LEN
+            || rest.as_bytes()[RANDOM_LEN] != b'_'
+            || !rest[..RANDOM_LEN]
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric())
+        {
+            return Err(malformed());
+        }
+
+        let (body, found) = key.split_at(key.len() - CHECKSUM_LEN);
+        if checksum(&body[..body.len() - 1]) != found {
+            return Err(AuthError::Malformed(
+                "API key checksum does not match".to_string(),
+            ));
+        }
+
+        Ok(&rest[..RANDOM_LEN])
+    }
+}
+
+/// CRC32 of the key body as fixed width base62
+fn checksum(body: &str) -> String {
+    let mut value = crc32(body.as_bytes());
+    let mut encoded = [b'0'; CHECKSUM_LEN];
+
+    for digit in encoded.iter_mut().rev() {
+        *digit = BASE62[(value % 62) as usize];
+        value /= 62;
+    }
+
+    encoded.iter().map(|&digit| char::from(digit)).collect()
+}
+
+/// CRC-32/ISO-HDLC, the checksum used by zlib and secret scanners
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+/// A stored API key; the key itself is only kept as a keyed hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ApiKeyRecord {
+    /// The first `ID_LEN` characters of the key's random part; safe to show in dashboards and
+    /// should have a unique index
+    pub id: String,
+    /// Keyed BLAKE2b-256 of the whole key, base64url encoded
+    pub hash: String,
+    /// Usually the account or service the key was issued to
+    pub owner: String,
+    /// A human readable name such as "CI deploys"
+    pub label: String,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+    /// Unix timestamp in seconds, or `None` for keys that never expire
+    pub expires_at: Option<u64>,
+    /// Unix timestamp in seconds, set once the key is revoked
+    pub revoked_at: Option<u64>,
+    /// What the key may be used for, e.g. `invoices:read`
+    pub scopes: Vec<String>,
+}
+
+impl ApiKeyRecord {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+
+    /// The required scopes this key was not granted
+    pub fn missing_scopes(&self, required: &[&str]) -> Vec<String> {
+        required
+            .iter()
+            .filter(|scope| !self.has_scope(scope))
+            .map(|scope| scope.to_string())
+            .collect()
+    }
+
+    /// Fail with `AuthError::Verification` naming the missing scopes unless all are granted
+    pub fn require_scope(&self, required: &[&str]) -> Result<(), AuthError> {
+        let missing = self.missing_scopes(required);
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(AuthError::Verification(format!(
+                "API key is missing scopes: {}",
+                missing.join(", ")
+            )))
+        }
+    }
+}
+
+/// A newly created key; `key` is only available now, so show it to its owner once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewApiKey {
+    pub key: String,
+    pub record: ApiKeyRecord,
+}
+
+/// Persists API key records by id
+///
+/// `insert` must fail rather than overwrite when the id already exists. `revoke` must set
+/// `revoked_at` only if the key belongs to the owner and is not yet revoked, and report whether it
+/// did, in a single atomic operation (e.g. `UPDATE ... WHERE id = $1 AND owner = $2 AND revoked_at
+/// IS NULL`).
+pub trait ApiKeyStore {
+    fn insert(&self, record: ApiKeyRecord) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn get(&self, id: &str)
+        -> impl Future<Output = Result<Option<ApiKeyRecord>, AuthError>> + Send;
+
+    /// Returns `false` if the owner has no such key or it was already revoked
+    fn revoke(
+        &self,
+        owner: &str,
+        id: &str,
+        at: u64,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Every key issued to the owner, including revoked and expired ones
+    fn list(
+        &self,
+        owner: &str,
+    ) -> impl Future<Output = Result<Vec<ApiKeyRecord>, AuthError>> + Send;
+}
+
+/// Creates, verifies and revokes API keys
+///
+/// Keys are stored as a keyed BLAKE2b hash, so a leaked database can neither be used to
+/// authenticate nor be brute forced without the hash key, which should live outside it. The key's
+/// id, taken from its random part, finds the record with an indexed lookup and the hash is then
+/// compared in constant time. Keys that fail `KeyFormat::check` are rejected before the store is
+/// touched.
+///
+/// `verify` reports unknown, revoked and expired keys as `AuthError::Verification`.
+///
+/// ### Example
+/// ```rust
+/// use std::{collections::HashMap, sync::Mutex, time::Duration};
+///
+/// use lonewolf_auth_toolkit::apikey::{ApiKeyRecord, ApiKeyStore, ApiKeys};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, ApiKeyRecord>>);
+///
+/// impl ApiKeyStore for MemoryStore {
+///     async fn insert(&self, record: ApiKeyRecord) -> Result<(), AuthError> {
+///         let mut records = self.0.lock().unwrap();
+///         if records.contains_key(&record.id) {
+///             return Err(AuthError::InvalidState("Duplicate API key id".to_string()));
+///         }
+///         records.insert(record.id.clone(), record);
+///         Ok(())
+///     }
+///
+///     async fn get(&self, id: &str) -> Result<Option<ApiKeyRecord>, AuthError> {
+///         Ok(self.0.lock().unwrap().get(id).cloned())
+///     }
+///
+///     async fn revoke(&self, owner: &str, id: &str, at: u64) -> Result<bool, AuthError> {
+///         match self.0.lock().unwrap().get_mut(id) {
+///             Some(record) if record.owner == owner && record.revoked_at.is_none() => {
+///                 record.revoked_at = Some(at);
+///                 Ok(true)
+///             }
+///             _ => Ok(false),
+///         }
+///     }
+///
+///     async fn list(&self, owner: &str) -> Result<Vec<ApiKeyRecord>, AuthError> {
+///         Ok(self.0.lock().unwrap().values().filter(|record| record.owner == owner).cloned().collect())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let keys = ApiKeys::new(MemoryStore::default(), &[7u8; 32])?.ttl(Duration::from_secs(90 * 24 * 60 * 60));
+///
+///     let created = keys.create("SomeAccountName", "CI deploys").await?;
+///     assert!(created.key.starts_with("lwk_live_"));
+///
+///     let record = keys.verify(&created.key).await?;
+///     assert_eq!(record.owner, "SomeAccountName");
+///
+///     // Scoped keys can only do what they were created for
+///     let reader = keys.create_with_scopes("SomeAccountName", "Reporting", &["invoices:read"]).await?;
+///     assert!(keys.verify_scoped(&reader.key, &["invoices:read"]).await.is_ok());
+///
+///     let record = keys.verify(&reader.key).await?;
+///     assert_eq!(record.missing_scopes(&["invoices:read", "invoices:write"]), ["invoices:write"]);
+///     assert!(record.require_scope(&["invoices:write"]).is_err());
+///
+///     // Only the key's owner can revoke it
+///     assert!(!keys.revoke("SomeOtherAccount", &record.id).await?);
+///     assert!(keys.verify(&reader.key).await.is_ok());
+///
+///     assert!(keys.revoke("SomeAccountName", &record.id).await?);
+///     assert!(keys.verify(&reader.key).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct ApiKeys<S> {
+    store: S,
+    format: KeyFormat,
+    hash_key: Zeroizing<Vec<u8>>,
+    ttl: Option<Duration>,
+}
+
+impl<S: ApiKeyStore> ApiKeys<S> {
+    pub fn new(store: S, hash_key: &[u8]) -> Result<Self, AuthError> {
+        if hash_key.len() < MIN_HASH_KEY_LEN {
+            return Err(AuthError::InvalidInput(format!(
+                "API key hash keys must be at least {} bytes",
+                MIN_HASH_KEY_LEN
+            )));
+        }
+
+        Ok(Self {
+            store,
+            format: KeyFormat::default(),
+            hash_key: Zeroizing::new(hash_key.to_vec()),
+            ttl: None,
+        })
+    }
+
+    pub fn format(mut self, format: KeyFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Expire keys this long after they are created; by default they last until revoked
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub async fn create(&self, owner: &str, label: &str) -> Result<NewApiKey, AuthError> {
+        self.create_with_scopes(owner, label, &[]).await
+    }
+
+    /// Create a key that is only good for the given scopes
+    pub async fn create_with_scopes(
+        &self,
+        owner: &str,
+        label: &str,
+        scopes: &[&str],
+    ) -> Result<NewApiKey, AuthError> {
+        if scopes
+            .iter()
+            .any(|scope| scope.is_empty() || scope.contains(char::is_whitespace))
+        {
+            return Err(AuthError::InvalidInput(
+                "API key scopes must be non-empty and contain no whitespace".to_string(),
+            ));
+        }
+
+        let key = self.format.generate();
+        let now = now()?;
+
+        let record = ApiKeyRecord {
+            id: self.format.random_part(&key)?[..ID_LEN].to_string(),
+            hash: self.hash(&key),
+            owner: owner.to_string(),
+            label: label.to_string(),
+            created_at: now,
+            expires_at: self.ttl.map(|ttl| now + ttl.as_secs()),
+            revoked_at: None,
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+        };
+
+        self.store.insert(record.clone()).await?;
+
+        Ok(NewApiKey { key, record })
+    }
+
+    /// The record for a presented key, if the key is known, unrevoked and unexpired
+    pub async fn verify(&self, key: &str) -> Result<ApiKeyRecord, AuthError> {
+        trace::instrument("apikey.verify", &[], self.check(key)).await
+    }
+
+    async fn check(&self, key: &str) -> Result<ApiKeyRecord, AuthError> {
+        let id = &self.format.random_part(key)?[..ID_LEN];
+
+        let record = self
+            .store
+            .get(id)
+            .await?
+            .filter(|record| ct_eq(&record.hash, self.hash(key)))
+            .ok_or_else(|| AuthError::Verification("Unknown API key".to_string()))?;
+
+        if record.is_revoked() {
+            return Err(AuthError::Verification(
+                "API key has been revoked".to_string(),
+            ));
+        }
+
+        if record.is_expired(now()?) {
+            return Err(AuthError::Verification("API key has expired".to_string()));
+        }
+
+        Ok(record)
+    }
+
+    /// Like `verify`, also requiring the key to have every scope in `required`
+    pub async fn verify_scoped(
+        &self,
+        key: &str,
+        required: &[&str],
+    ) -> Result<ApiKeyRecord, AuthError> {
+        let record = self.verify(key).await?;
+        record.require_scope(required)?;
+
+        Ok(record)
+    }
+
+    /// Revoke one of the owner's keys by id; returns `false` if the owner has no such key or it is
+    /// already revoked
+    pub async fn revoke(&self, owner: &str, id: &str) -> Result<bool, AuthError> {
+        self.store.revoke(owner, id, now()?).await
+    }
+
+    /// The owner's keys, newest first
+    pub async fn list(&self, owner: &str) -> Result<Vec<ApiKeyRecord>, AuthError> {
+        let mut records = self.store.list(owner).await?;
+        records.sort_by_key(|record| Reverse(record.created_at));
+
+        Ok(records)
+    }
+
+    fn hash(&self, key: &str) -> String {
+        URL_SAFE_NO_PAD.encode(blake2b_keyed(32, &self.hash_key, key.as_bytes()))
+    }
+}
+
+/// Keeps API key records in process memory
+#[derive(Debug, Default)]
+pub struct MemoryApiKeyStore {
+    records: Mutex<HashMap<String, ApiKeyRecord>>,
+}
+
+impl MemoryApiKeyStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, ApiKeyRecord>>, AuthError> {
+        self.records
+            .lock()
+            .map_err(|_| AuthError::backend("API key store lock poisoned"))
+    }
+}
+
+impl ApiKeyStore for MemoryApiKeyStore {
+    async fn insert(&self, record: ApiKeyRecord) -> Result<(), AuthError> {
+        let mut records = self.lock()?;
+        if records.contains_key(&record.id) {
+            return Err(AuthError::InvalidState("Duplicate API key id".to_string()));
+        }
+
+        records.insert(record.id.clone(), record);
+
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<ApiKeyRecord>, AuthError> {
+        Ok(self.lock()?.get(id).cloned())
+    }
+
+    async fn revoke(&self, owner: &str, id: &str, at: u64) -> Result<bool, AuthError> {
+        match self.lock()?.get_mut(id) {
+            Some(record) if record.owner == owner && record.revoked_at.is_none() => {
+                record.revoked_at = Some(at);
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn list(&self, owner: &str) -> Result<Vec<ApiKeyRecord>, AuthError> {
+        Ok(self
+            .lock()?
+            .values()
+            .filter(|record| record.owner == owner)
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{token::now, AuthError};
+
+/// What happened in an audit event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    AccountCreated,
+    AccountDeleted,
+    LoginSucceeded,
+    /// A sign in was rejected; the detail says why
+    LoginFailed,
+    /// An authenticator was confirmed and MFA turned on
+    MfaEnrolled,
+    MfaVerified,
+    MfaFailed,
+    MfaDisabled,
+    PasswordChanged,
+    /// A session was attached to an account
+    SessionStarted,
+    /// A session was destroyed by the user, another of their devices or an administrator
+    SessionRevoked,
+    /// Repeated failures locked the account out; the detail says for how long
+    AccountLocked,
+    /// An already rotated refresh token was presented and its family revoked
+    RefreshTokenReused,
+    /// A stale remember me cookie was presented and the account's remembered sign ins revoked
+    PersistentLoginStolen,
+    /// Credential stuffing or a targeted attack was detected; the detail names the kind and IP
+    AttackDetected,
+    /// A sign in method was linked to an account; the detail names the provider
+    IdentityLinked,
+    IdentityUnlinked,
+    /// Another account's sign in methods were moved onto this one; the detail names it
+    AccountsMerged,
+    /// An administrator was issued a token to act as this account; the detail names them
+    ImpersonationStarted,
+    /// An impersonation token was accepted; the detail names the acting administrator
+    ImpersonationUsed,
+    /// A user who lost their credentials started recovery; the detail lists the steps
+    AccountRecoveryStarted,
+    /// An administrator approved a recovery; the detail names them
+    AccountRecoveryApproved,
+    /// A recovery was refused; the detail says why
+    AccountRecoveryDenied,
+    /// A recovery finished and the account was handed back to the user
+    AccountRecovered,
+    /// A change of address was started; the detail names both addresses
+    EmailChangeRequested,
+    /// Both addresses confirmed a change; the detail names them
+    EmailChanged,
+    /// The owner of the old address called off a change; the detail names both addresses
+    EmailChangeCancelled,
+    /// A sign in looked unusual, e.g. from a new device; the detail lists the reasons
+    RiskySignIn,
+}
+
+impl AuditAction {
+    /// The `snake_case` name used when serialized, e.g. `login_failed`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::AccountCreated => "account_created",
+            AuditAction::AccountDeleted => "account_deleted",
+            AuditAction::LoginSucceeded => "login_succeeded",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::MfaEnrolled => "mfa_enrolled",
+            AuditAction::MfaVerified => "mfa_verified",
+            AuditAction::MfaFailed => "mfa_failed",
+            AuditAction::MfaDisabled => "mfa_disabled",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::SessionStarted => "session_started",
+            AuditAction::SessionRevoked => "session_revoked",
+            AuditAction::AccountLocked => "account_locked",
+            AuditAction::RefreshTokenReused => "refresh_token_reused",
+            AuditAction::PersistentLoginStolen => "persistent_login_stolen",
+            AuditAction::AttackDetected => "attack_detected",
+            AuditAction::IdentityLinked => "identity_linked",
+            AuditAction::IdentityUnlinked => "identity_unlinked",
+            AuditAction::AccountsMerged => "accounts_merged",
+            AuditAction::ImpersonationStarted => "impersonation_started",
+            AuditAction::ImpersonationUsed => "impersonation_used",
+            AuditAction::AccountRecoveryStarted => "account_recovery_started",
+            AuditAction::AccountRecoveryApproved => "account_recovery_approved",
+            AuditAction::AccountRecoveryDenied => "account_recovery_denied",
+            AuditAction::AccountRecovered => "account_recovered",
+            AuditAction::EmailChangeRequested => "email_change_requested",
+            AuditAction::EmailChanged => "email_changed",
+            AuditAction::EmailChangeCancelled => "email_change_cancelled",
+            AuditAction::RiskySignIn => "risky_sign_in",
+        }
+    }
+}
+
+/// One security relevant event, emitted by the flows in this crate to their `AuditSink`
+///
+/// Events never carry secrets: no passwords, codes, tokens or session ids.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub action: AuditAction,
+    /// The user or account the event concerns, if known
+    pub account: Option<String>,
+    /// Why an action failed or what it affected, e.g. `wrong password`
+    pub detail: Option<String>,
+    /// Unix timestamp in seconds
+    pub at: u64,
+}
+
+impl AuditEvent {
+    /// An event that happened now
+    pub fn new(action: AuditAction) -> Result<Self, AuthError> {
+        Ok(Self {
+            action,
+            account: None,
+            detail: None,
+            at: now()?,
+        })
+    }
+
+    pub fn account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// Receives audit events, e.g. to write them to a log pipeline or an append only table
+///
+/// Flows wait for `record` and fail with its error, so an event is never silently lost; a sink
+/// that should not hold up sign ins can queue events and return straight away.
+pub trait AuditSink {
+    fn record(&self, event: AuditEvent) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+impl<T: AuditSink + Send + Sync> AuditSink for Arc<T> {
+    fn record(&self, event: AuditEvent) -> impl Future<Output = Result<(), AuthError>> + Send {
+        (**self).record(event)
+    }
+}
+
+/// Discards every event; the sink flows use until one is configured
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoAudit;
+
+impl AuditSink for NoAudit {
+    async fn record(&self, _event: AuditEvent) -> Result<(), AuthError> {
+        Ok(())
+    }
+}
+
+/// Keeps events in process memory, oldest first
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::account::{Accounts, MemoryCredentialStore, MemoryUserStore};
+/// use lonewolf_auth_toolkit::audit::{AuditAction, MemoryAuditSink};
+/// use lonewolf_auth_toolkit::password::argon2::Argon2Params;
+/// use lonewolf_auth_toolkit::session::{MemorySessionStore, SessionManager};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let audit = Arc::new(MemoryAuditSink::default());
+///     let accounts = Accounts::new(MemoryUserStore::default(), MemoryCredentialStore::default())
+///         .params(Argon2Params::default().memory_kib(1024).iterations(1))
+///         .audit(audit.clone());
+///     let sessions = SessionManager::new(MemorySessionStore::default()).audit(audit.clone());
+///
+///     let user = accounts.register("someone@example.com", None, "correct horse battery staple").await?;
+///     assert!(accounts.authenticate("someone@example.com", "Tr0ub4dor&3").await.is_err());
+///
+///     let mut session = sessions.create().await?;
+///     sessions.sign_in(&mut session, &user.id).await?;
+///     sessions.revoke_all(&user.id).await?;
+///
+///     let actions: Vec<_> = audit.events()?.into_iter().map(|event| event.action).collect();
+///     assert_eq!(
+///         actions,
+///         [
+///             AuditAction::AccountCreated,
+///             AuditAction::LoginFailed,
+///             AuditAction::SessionStarted,
+///             AuditAction::SessionRevoked,
+///         ]
+///     );
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryAuditSink {
+    events: Mutex<Vec<AuditEvent>>,
+}
+
+impl MemoryAuditSink {
+    /// Every event recorded so far
+    pub fn events(&self) -> Result<Vec<AuditEvent>, AuthError> {
+        Ok(self
+            .events
+            .lock()
+            .map_err(|_| AuthError::backend("Audit sink lock poisoned"))?
+            .clone())
+    }
+}
+
+impl AuditSink for MemoryAuditSink {
+    async fn record(&self, event: AuditEvent) -> Result<(), AuthError> {
+        self.events
+            .lock()
+            .map_err(|_| AuthError::backend("Audit sink lock poisoned"))?
+            .push(event);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,327 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+use crate::AuthError;
+
+/// What is being attempted: who, on what, and in which circumstances
+///
+/// `subject`, `resource` and `context` are JSON objects of attributes, such as the user's
+/// department, the document's owner or the current hour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub subject: Json,
+    pub action: String,
+    pub resource: Json,
+    pub context: Json,
+}
+
+impl Request {
+    pub fn new(subject: Json, action: impl Into<String>, resource: Json) -> Self {
+        Self {
+            subject,
+            action: action.into(),
+            resource,
+            context: Json::Null,
+        }
+    }
+
+    pub fn context(mut self, context: Json) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Resolve a path such as `subject.department` or `resource.owner.id`
+    fn attribute(&self, path: &str) -> Option<&Json> {
+        let mut parts = path.split('.');
+        let mut value = match parts.next()? {
+            "subject" => &self.subject,
+            "resource" => &self.resource,
+            "context" => &self.context,
+            _ => return None,
+        };
+
+        for part in parts {
+            value = value.get(part)?;
+        }
+
+        Some(value).filter(|value| !value.is_null())
+    }
+}
+
+/// One side of a comparison: an attribute path or a literal value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Operand {
+    Attribute { attr: String },
+    Value(Json),
+}
+
+impl Operand {
+    pub fn attr(path: impl Into<String>) -> Self {
+        Self::Attribute { attr: path.into() }
+    }
+
+    pub fn value(value: impl Into<Json>) -> Self {
+        Self::Value(value.into())
+    }
+
+    fn resolve<'a>(&'a self, request: &'a Request) -> Option<&'a Json> {
+        match self {
+            Self::Attribute { attr } => request.attribute(attr),
+            Self::Value(value) => Some(value).filter(|value| !value.is_null()),
+        }
+    }
+}
+
+/// A condition over the request's attributes
+///
+/// Comparisons involving a missing attribute are false, so `subject.id == resource.owner` never
+/// holds just because both are absent. `lt`, `le`, `gt` and `ge` compare numbers, or strings such
+/// as RFC 3339 times. `in` checks the left value is an element of the right array.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+    Eq(Operand, Operand),
+    Ne(Operand, Operand),
+    Lt(Operand, Operand),
+    Le(Operand, Operand),
+    Gt(Operand, Operand),
+    Ge(Operand, Operand),
+    In(Operand, Operand),
+}
+
+impl Condition {
+    pub fn eq(left: Operand, right: Operand) -> Self {
+        Self::Eq(left, right)
+    }
+
+    pub fn ne(left: Operand, right: Operand) -> Self {
+        Self::Ne(left, right)
+    }
+
+    pub fn lt(left: Operand, right: Operand) -> Self {
+        Self::Lt(left, right)
+    }
+
+    pub fn le(left: Operand, right: Operand) -> Self {
+        Self::Le(left, right)
+    }
+
+    pub fn gt(left: Operand, right: Operand) -> Self {
+        Self::Gt(left, right)
+    }
+
+    pub fn ge(left: Operand, right: Operand) -> Self {
+        Self::Ge(left, right)
+    }
+
+    pub fn is_in(element: Operand, array: Operand) -> Self {
+        Self::In(element, array)
+    }
+
+    pub fn all(conditions: impl IntoIterator<Item = Condition>) -> Self {
+        Self::All(conditions.into_iter().collect())
+    }
+
+    pub fn any(conditions: impl IntoIterator<Item = Condition>) -> Self {
+        Self::Any(conditions.into_iter().collect())
+    }
+
+    pub fn negate(condition: Condition) -> Self {
+        Self::Not(Box::new(condition))
+    }
+
+    pub fn evaluate(&self, request: &Request) -> bool {
+        match self {
+            Self::All(conditions) => conditions.iter().all(|c| c.evaluate(request)),
+            Self::Any(conditions) => conditions.iter().any(|c| c.evaluate(request)),
+            Self::Not(condition) => !condition.evaluate(request),
+            Self::Eq(left, right) => compare(request, left, right, |a, b| a == b),
+            Self::Ne(left, right) => compare(request, left, right, |a, b| a != b),
+            Self::Lt(left, right) => ordered(request, left, right, Ordering::is_lt),
+            Self::Le(left, right) => ordered(request, left, right, Ordering::is_le),
+            Self::Gt(left, right) => ordered(request, left, right, Ordering::is_gt),
+            Self::Ge(left, right) => ordered(request, left, right, Ordering::is_ge),
+            Self::In(element, array) => compare(request, element, array, |element, array| {
+                array
+                    .as_array()
+                    .is_some_and(|array| array.contains(element))
+            }),
+        }
+    }
+}
+
+fn compare(
+    request: &Request,
+    left: &Operand,
+    right: &Operand,
+    check: impl Fn(&Json, &Json) -> bool,
+) -> bool {
+    match (left.resolve(request), right.resolve(request)) {
+        (Some(left), Some(right)) => check(left, right),
+        _ => false,
+    }
+}
+
+fn ordered(
+    request: &Request,
+    left: &Operand,
+    right: &Operand,
+    check: impl Fn(Ordering) -> bool,
+) -> bool {
+    compare(request, left, right, |left, right| {
+        let ordering = match (left, right) {
+            (Json::Number(left), Json::Number(right)) => left.as_f64().partial_cmp(&right.as_f64()),
+            (Json::String(left), Json::String(right)) => Some(left.cmp(right)),
+            _ => None,
+        };
+
+        ordering.is_some_and(&check)
+    })
+}
+
+/// Whether a matching rule grants or forbids the action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+/// Applies `effect` to the listed actions when `condition` holds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub effect: Effect,
+    /// Action names, or `*` for every action
+    pub actions: Vec<String>,
+    /// Always applies when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Condition>,
+}
+
+impl Rule {
+    pub fn allow(actions: &[&str]) -> Self {
+        Self::new(Effect::Allow, actions)
+    }
+
+    pub fn deny(actions: &[&str]) -> Self {
+        Self::new(Effect::Deny, actions)
+    }
+
+    fn new(effect: Effect, actions: &[&str]) -> Self {
+        Self {
+            effect,
+            actions: actions.iter().map(|action| action.to_string()).collect(),
+            condition: None,
+        }
+    }
+
+    pub fn when(mut self, condition: Condition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    fn applies(&self, request: &Request) -> bool {
+        self.actions
+            .iter()
+            .any(|action| action == "*" || *action == request.action)
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.evaluate(request))
+    }
+}
+
+/// An attribute based access control policy
+///
+/// A request is allowed when at least one `allow` rule applies and no `deny` rule does; anything
+/// not explicitly allowed is denied. Policies can be built in Rust or deserialized from JSON, or
+/// any other serde format such as YAML.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::authz::abac::{Condition, Operand, Policy, Request, Rule};
+/// use serde_json::json;
+///
+/// // Owners can edit, and nobody edits outside business hours
+/// let policy = Policy::new()
+///     .rule(Rule::allow(&["read"]))
+///     .rule(Rule::allow(&["edit"]).when(Condition::eq(
+///         Operand::attr("subject.id"),
+///         Operand::attr("resource.owner"),
+///     )))
+///     .rule(Rule::deny(&["edit"]).when(Condition::any([
+///         Condition::lt(Operand::attr("context.hour"), Operand::value(9)),
+///         Condition::ge(Operand::attr("context.hour"), Operand::value(17)),
+///     ])));
+///
+/// let document = json!({ "owner": "SomeAccountName" });
+/// let edit = |who: &str, hour: u32| {
+///     Request::new(json!({ "id": who }), "edit", document.clone()).context(json!({ "hour": hour }))
+/// };
+///
+/// assert!(policy.allows(&edit("SomeAccountName", 10)));
+/// assert!(!policy.allows(&edit("SomeAccountName", 20)));
+/// assert!(!policy.allows(&edit("SomeoneElse", 10)));
+///
+/// // The same policy as JSON
+/// let loaded: Policy = serde_json::from_value(json!({
+///     "rules": [
+///         { "effect": "allow", "actions": ["read"] },
+///         { "effect": "allow", "actions": ["edit"],
+///           "condition": { "eq": [{ "attr": "subject.id" }, { "attr": "resource.owner" }] } },
+///         { "effect": "deny", "actions": ["edit"],
+///           "condition": { "any": [
+///               { "lt": [{ "attr": "context.hour" }, 9] },
+///               { "ge": [{ "attr": "context.hour" }, 17] }
+///           ] } }
+///     ]
+/// }))?;
+/// assert_eq!(loaded, policy);
+/// # Ok::<(), serde_json::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Policy {
+    pub rules: Vec<Rule>,
+}
+
+impl Policy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn allows(&self, request: &Request) -> bool {
+        let mut allowed = false;
+
+        for rule in self.rules.iter().filter(|rule| rule.applies(request)) {
+            match rule.effect {
+                Effect::Deny => return false,
+                Effect::Allow => allowed = true,
+            }
+        }
+
+        allowed
+    }
+
+    /// Like `allows`, failing with `AuthError::Verification` when the request is denied
+    pub fn require(&self, request: &Request) -> Result<(), AuthError> {
+        if self.allows(request) {
+            Ok(())
+        } else {
+            Err(AuthError::Verification(format!(
+                "Not allowed to {}",
+                request.action
+            )))
+        }
+    }
+}
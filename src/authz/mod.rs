@@ -0,0 +1,2 @@
+pub mod abac;
+pub mod rbac;
@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::AuthError;
+
+/// A role's own permissions and the roles it inherits from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Role {
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    #[serde(default)]
+    pub inherits: Vec<String>,
+}
+
+impl Role {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn permission(mut self, permission: impl Into<String>) -> Self {
+        self.permissions.push(permission.into());
+        self
+    }
+
+    /// Also grant every permission of `role`
+    pub fn inherits(mut self, role: impl Into<String>) -> Self {
+        self.inherits.push(role.into());
+        self
+    }
+}
+
+/// Role definitions as written in config, before the hierarchy is resolved
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoleDefinitions {
+    pub roles: BTreeMap<String, Role>,
+}
+
+impl RoleDefinitions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn role(mut self, name: impl Into<String>, role: Role) -> Self {
+        self.roles.insert(name.into(), role);
+        self
+    }
+}
+
+/// Role based access control with role hierarchies
+///
+/// Permissions are strings such as `posts:edit`. A granted permission ending in `*` covers every
+/// permission starting with what precedes it, so `posts:*` grants `posts:edit` and `*` grants
+/// everything. Inherited permissions are resolved once when the roles are loaded, which fails on
+/// unknown parents and cycles.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::authz::rbac::Rbac;
+///
+/// let rbac: Rbac = serde_json::from_str(r#"{
+///     "roles": {
+///         "viewer": { "permissions": ["posts:read"] },
+///         "editor": { "permissions": ["posts:edit", "comments:*"], "inherits": ["viewer"] },
+///         "admin": { "permissions": ["*"] }
+///     }
+/// }"#)?;
+///
+/// assert!(rbac.can(&["editor"], "posts:read"));
+/// assert!(rbac.can(&["editor"], "comments:delete"));
+/// assert!(!rbac.can(&["viewer"], "posts:edit"));
+/// assert!(rbac.can(&["viewer", "admin"], "billing:refund"));
+/// # Ok::<(), serde_json::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RoleDefinitions")]
+pub struct Rbac {
+    definitions: RoleDefinitions,
+    resolved: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl TryFrom<RoleDefinitions> for Rbac {
+    type Error = AuthError;
+
+    fn try_from(definitions: RoleDefinitions) -> Result<Self, AuthError> {
+        Self::new(definitions)
+    }
+}
+
+impl Serialize for Rbac {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.definitions.serialize(serializer)
+    }
+}
+
+impl Rbac {
+    pub fn new(definitions: RoleDefinitions) -> Result<Self, AuthError> {
+        let mut resolved = BTreeMap::new();
+
+        for name in definitions.roles.keys() {
+            let mut permissions = BTreeSet::new();
+            collect(&definitions, name, &mut Vec::new(), &mut permissions)?;
+            resolved.insert(name.clone(), permissions);
+        }
+
+        Ok(Self {
+            definitions,
+            resolved,
+        })
+    }
+
+    pub fn definitions(&self) -> &RoleDefinitions {
+        &self.definitions
+    }
+
+    /// Every permission a role has, including inherited ones
+    pub fn permissions(&self, role: &str) -> Option<&BTreeSet<String>> {
+        self.resolved.get(role)
+    }
+
+    /// Whether any of the user's roles grants the permission; unknown roles grant nothing
+    pub fn can<R: AsRef<str>>(&self, user_roles: &[R], permission: &str) -> bool {
+        user_roles
+            .iter()
+            .filter_map(|role| self.resolved.get(role.as_ref()))
+            .flatten()
+            .any(|granted| grants(granted, permission))
+    }
+
+    /// Like `can`, failing with `AuthError::Verification` when the permission is not granted
+    pub fn require<R: AsRef<str>>(
+        &self,
+        user_roles: &[R],
+        permission: &str,
+    ) -> Result<(), AuthError> {
+        if self.can(user_roles, permission) {
+            Ok(())
+        } else {
+            Err(AuthError::Verification(format!(
+                "Missing permission {}",
+                permission
+            )))
+        }
+    }
+}
+
+fn collect(
+    definitions: &RoleDefinitions,
+    name: &str,
+    path: &mut Vec<String>,
+    permissions: &mut BTreeSet<String>,
+) -> Result<(), AuthError> {
+    if path.iter().any(|visited| visited == name) {
+        return Err(AuthError::InvalidInput(format!(
+            "Role {} inherits from itself",
+            name
+        )));
+    }
+
+    let role = definitions
+        .roles
+        .get(name)
+        .ok_or_else(|| AuthError::InvalidInput(format!("Unknown role {}", name)))?;
+
+    permissions.extend(role.permissions.iter().cloned());
+
+    path.push(name.to_string());
+    for parent in &role.inherits {
+        collect(definitions, parent, path, permissions)?;
+    }
+    path.pop();
+
+    Ok(())
+}
+
+fn grants(granted: &str, permission: &str) -> bool {
+    match granted.strip_suffix('*') {
+        Some(prefix) => permission.starts_with(prefix),
+        None => granted == permission,
+    }
+}
@@ -0,0 +1,205 @@
+// `lonewolf`, a command line tool for debugging and operating systems built on this crate
+//
+// Build it with `cargo install lonewolf-auth-toolkit --features cli`.
+
+use std::{
+    env,
+    io::{self, BufRead},
+    process::ExitCode,
+    time::Duration,
+};
+
+use lonewolf_auth_toolkit::{
+    mfa::{
+        blocking::{current_code, verify_with},
+        generate_secret, qr,
+        uri::{OtpAuthUri, OtpKind},
+        TotpConfig,
+    },
+    password,
+    token::{
+        jwt::{JwtSigner, JwtVerifier, SigningKey, VerifyingKey},
+        Claims,
+    },
+    AuthError,
+};
+use serde_json::{Map, Value};
+use zeroize::Zeroizing;
+
+/// Environment variable holding the HS256 secret for the `jwt` commands
+const JWT_SECRET_VAR: &str = "LONEWOLF_JWT_SECRET";
+
+const USAGE: &str = "\
+Usage: lonewolf <command>
+
+Commands:
+  totp new <issuer> <account>       Issue a secret and print its QR code and otpauth:// URI
+  totp code <secret | uri>          Print the current code
+  totp verify <secret | uri> <code> Check a code; exits with 1 if it is wrong
+  password hash [password]          Hash with Argon2id; reads stdin without an argument
+  password verify <hash> [password] Check a password; exits with 1 if it is wrong
+  jwt mint <subject> [--ttl <seconds>] [--issuer <iss>] [--audience <aud>] [--claim <key=value>]...
+                                    Sign an HS256 token with $LONEWOLF_JWT_SECRET
+  jwt verify <token>                Check a token's signature and claims and print them
+  qr <text>                         Render text as a QR code in the terminal";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Returns `false` when a checked code, password or token is wrong
+fn run(args: &[&str]) -> Result<bool, AuthError> {
+    match args {
+        ["totp", "new", issuer, account] => {
+            let uri = OtpAuthUri {
+                kind: OtpKind::Totp,
+                issuer: Some(issuer.to_string()),
+                account_name: account.to_string(),
+                secret: generate_secret(),
+                config: TotpConfig::default(),
+            };
+
+            println!("{}", qr::ascii(&uri.to_string())?);
+            println!("Secret: {}", uri.secret);
+            println!("URI:    {}", uri);
+        }
+        ["totp", "code", secret] => {
+            let (secret, config) = totp_secret(secret)?;
+            println!("{}", current_code(&secret, &config)?);
+        }
+        ["totp", "verify", secret, code] => {
+            let (secret, config) = totp_secret(secret)?;
+            return report(verify_with(code.to_string(), secret, &config)?, "Code");
+        }
+        ["password", "hash", rest @ ..] => {
+            println!("{}", password::hash(&password_arg(rest)?)?);
+        }
+        ["password", "verify", hash, rest @ ..] => {
+            return report(password::verify(&password_arg(rest)?, hash)?, "Password");
+        }
+        ["jwt", "mint", subject, options @ ..] => {
+            println!("{}", mint(subject, options)?);
+        }
+        ["jwt", "verify", token] => {
+            let verifier = JwtVerifier::new().key(None, VerifyingKey::hs256(&jwt_secret()?)?);
+
+            match verifier.verify::<Map<String, Value>>(token) {
+                Ok(claims) => println!("{}", serde_json::to_string_pretty(&claims)?),
+                Err(AuthError::Verification(reason)) => {
+                    eprintln!("Token rejected: {}", reason);
+                    return Ok(false);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        ["qr", text] => println!("{}", qr::ascii(text)?),
+        ["help" | "--help" | "-h"] => println!("{}", USAGE),
+        _ => {
+            return Err(AuthError::InvalidInput(format!(
+                "Unknown command\n\n{}",
+                USAGE
+            )))
+        }
+    }
+
+    Ok(true)
+}
+
+fn report(valid: bool, what: &str) -> Result<bool, AuthError> {
+    println!("{} is {}", what, if valid { "valid" } else { "invalid" });
+
+    Ok(valid)
+}
+
+/// A Base32 secret with the default config, or the secret and config of an `otpauth://` URI
+fn totp_secret(value: &str) -> Result<(String, TotpConfig), AuthError> {
+    if !value.starts_with("otpauth://") {
+        return Ok((value.to_string(), TotpConfig::default()));
+    }
+
+    let uri = OtpAuthUri::parse(value)?;
+    if uri.kind != OtpKind::Totp {
+        return Err(AuthError::InvalidInput(
+            "Only TOTP URIs are supported".to_string(),
+        ));
+    }
+
+    Ok((uri.secret, uri.config))
+}
+
+/// The password argument, or the first line of stdin so it stays out of the shell history
+fn password_arg(rest: &[&str]) -> Result<Zeroizing<String>, AuthError> {
+    match rest {
+        [password] => Ok(Zeroizing::new(password.to_string())),
+        [] => {
+            let mut line = Zeroizing::new(String::new());
+            io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .map_err(AuthError::backend)?;
+
+            let len = line.trim_end_matches(['\r', '\n']).len();
+            line.truncate(len);
+
+            Ok(line)
+        }
+        _ => Err(AuthError::InvalidInput(
+            "Expected a single password".to_string(),
+        )),
+    }
+}
+
+fn jwt_secret() -> Result<Vec<u8>, AuthError> {
+    env::var(JWT_SECRET_VAR)
+        .map(String::into_bytes)
+        .map_err(|_| {
+            AuthError::InvalidInput(format!("Set {} to the signing secret", JWT_SECRET_VAR))
+        })
+}
+
+fn mint(subject: &str, options: &[&str]) -> Result<String, AuthError> {
+    let mut ttl = Duration::from_secs(900);
+    let mut custom = Map::new();
+    let mut issuer = None;
+    let mut audiences = Vec::new();
+
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let value = options
+            .next()
+            .ok_or_else(|| AuthError::InvalidInput(format!("{} needs a value", option)))?;
+
+        match *option {
+            "--ttl" => ttl = Duration::from_secs(value.parse()?),
+            "--issuer" => issuer = Some(*value),
+            "--audience" => audiences.push(*value),
+            "--claim" => {
+                let (key, value) = value.split_once('=').ok_or_else(|| {
+                    AuthError::InvalidInput("Claims are written as key=value".to_string())
+                })?;
+                custom.insert(key.to_string(), Value::String(value.to_string()));
+            }
+            other => return Err(AuthError::InvalidInput(format!("Unknown option {}", other))),
+        }
+    }
+
+    let mut claims = Claims::new(custom, ttl)?.subject(subject);
+    if let Some(issuer) = issuer {
+        claims = claims.issuer(issuer);
+    }
+    for audience in audiences {
+        claims = claims.audience(audience);
+    }
+
+    JwtSigner::new(SigningKey::hs256(&jwt_secret()?)?).sign(&claims)
+}
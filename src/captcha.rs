@@ -0,0 +1,253 @@
+use std::future::Future;
+
+use serde::Deserialize;
+
+use crate::{
+    http::{HttpClient, HttpRequest},
+    otel, AuthError,
+};
+
+/// A CAPTCHA service with a `siteverify` style endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptchaProvider {
+    /// Google reCAPTCHA v2 or v3
+    ReCaptcha,
+    HCaptcha,
+    /// Cloudflare Turnstile
+    Turnstile,
+}
+
+impl CaptchaProvider {
+    pub fn verify_url(&self) -> &'static str {
+        match self {
+            Self::ReCaptcha => "https://www.google.com/recaptcha/api/siteverify",
+            Self::HCaptcha => "https://api.hcaptcha.com/siteverify",
+            Self::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/siteverify",
+        }
+    }
+
+    /// The form field the widget puts its response token in
+    pub fn response_field(&self) -> &'static str {
+        match self {
+            Self::ReCaptcha => "g-recaptcha-response",
+            Self::HCaptcha => "h-captcha-response",
+            Self::Turnstile => "cf-turnstile-response",
+        }
+    }
+}
+
+/// What the provider reported about a solved challenge
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptchaOutcome {
+    /// The site the challenge was solved on
+    pub hostname: Option<String>,
+    /// The action the widget was rendered with, for reCAPTCHA v3 and Turnstile
+    pub action: Option<String>,
+    /// From 0.0, likely a bot, to 1.0, likely a human; reCAPTCHA v3 and hCaptcha Enterprise only
+    pub score: Option<f64>,
+    /// When the challenge was solved, as an ISO 8601 timestamp
+    pub challenge_ts: Option<String>,
+}
+
+/// Checks the response token a CAPTCHA widget produced
+///
+/// Fails with `AuthError::Verification` when the challenge was not solved or does not meet the
+/// verifier's requirements. Implementations other than `Captcha` can wrap other services, or
+/// accept everything in tests.
+pub trait CaptchaVerifier {
+    /// `remote_ip` is the user's address, which providers use as a further signal
+    fn verify(
+        &self,
+        response: &str,
+        remote_ip: Option<&str>,
+    ) -> impl Future<Output = Result<CaptchaOutcome, AuthError>> + Send;
+}
+
+#[derive(Deserialize)]
+struct SiteVerify {
+    success: bool,
+    #[serde(default)]
+    hostname: Option<String>,
+    #[serde(default)]
+    action: Option<String>,
+    #[serde(default)]
+    score: Option<f64>,
+    #[serde(default)]
+    challenge_ts: Option<String>,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+/// Verifies reCAPTCHA, hCaptcha and Turnstile responses with the provider's API
+///
+/// Require a CAPTCHA once sign in or sign up looks suspicious, e.g. after a failed attempt
+/// recorded by `rate_limit::lockout::Lockout`, and pass the response field from the submitted
+/// form to `verify`. Setting `hostname` and `action` stops tokens solved on other sites or forms
+/// being replayed, and `min_score` rejects low reCAPTCHA v3 scores.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::captcha::{Captcha, CaptchaVerifier};
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// struct FakeTurnstile;
+///
+/// impl HttpClient for FakeTurnstile {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         let body = String::from_utf8(request.body).unwrap();
+///         let json = if body.contains("response=SolvedToken") {
+///             r#"{"success":true,"hostname":"app.example.com","action":"login","error-codes":[]}"#
+///         } else {
+///             r#"{"success":false,"error-codes":["invalid-input-response"]}"#
+///         };
+///
+///         Ok(HttpResponse { status: 200, headers: vec![], body: json.as_bytes().to_vec() })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let captcha = Captcha::turnstile(FakeTurnstile, "SomeSecretKey")
+///         .hostname("app.example.com")
+///         .action("login");
+///
+///     let outcome = captcha.verify("SolvedToken", Some("203.0.113.7")).await?;
+///     assert_eq!(outcome.hostname.as_deref(), Some("app.example.com"));
+///
+///     assert!(captcha.verify("GuessedToken", None).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Captcha<C> {
+    client: C,
+    provider: CaptchaProvider,
+    secret: String,
+    hostname: Option<String>,
+    action: Option<String>,
+    min_score: Option<f64>,
+}
+
+impl<C: HttpClient> Captcha<C> {
+    pub fn new(client: C, provider: CaptchaProvider, secret: impl Into<String>) -> Self {
+        Self {
+            client,
+            provider,
+            secret: secret.into(),
+            hostname: None,
+            action: None,
+            min_score: None,
+        }
+    }
+
+    pub fn recaptcha(client: C, secret: impl Into<String>) -> Self {
+        Self::new(client, CaptchaProvider::ReCaptcha, secret)
+    }
+
+    pub fn hcaptcha(client: C, secret: impl Into<String>) -> Self {
+        Self::new(client, CaptchaProvider::HCaptcha, secret)
+    }
+
+    pub fn turnstile(client: C, secret: impl Into<String>) -> Self {
+        Self::new(client, CaptchaProvider::Turnstile, secret)
+    }
+
+    /// Only accept challenges solved on this site
+    pub fn hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = Some(hostname.into());
+        self
+    }
+
+    /// Only accept challenges from a widget rendered with this action
+    pub fn action(mut self, action: impl Into<String>) -> Self {
+        self.action = Some(action.into());
+        self
+    }
+
+    /// Reject scores below this, and responses without a score
+    pub fn min_score(mut self, min_score: f64) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    pub fn provider(&self) -> CaptchaProvider {
+        self.provider
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+}
+
+impl<C: HttpClient + Sync> CaptchaVerifier for Captcha<C> {
+    async fn verify(
+        &self,
+        response: &str,
+        remote_ip: Option<&str>,
+    ) -> Result<CaptchaOutcome, AuthError> {
+        if response.is_empty() {
+            return Err(AuthError::Verification(
+                "CAPTCHA was not completed".to_string(),
+            ));
+        }
+
+        let mut params = vec![("secret", self.secret.as_str()), ("response", response)];
+        if let Some(remote_ip) = remote_ip {
+            params.push(("remoteip", remote_ip));
+        }
+
+        let reply = otel::send(
+            &self.client,
+            HttpRequest::post_form(self.provider.verify_url(), &params),
+        )
+        .await?;
+
+        if !reply.is_success() {
+            return Err(AuthError::backend(format!(
+                "CAPTCHA verification endpoint returned status {}",
+                reply.status
+            )));
+        }
+
+        let result: SiteVerify = serde_json::from_slice(&reply.body)?;
+
+        if !result.success {
+            return Err(AuthError::Verification(format!(
+                "CAPTCHA verification failed: {}",
+                result.error_codes.join(", ")
+            )));
+        }
+
+        if let Some(hostname) = &self.hostname {
+            if result.hostname.as_ref() != Some(hostname) {
+                return Err(AuthError::Verification(
+                    "CAPTCHA was solved on another site".to_string(),
+                ));
+            }
+        }
+
+        if let Some(action) = &self.action {
+            if result.action.as_ref() != Some(action) {
+                return Err(AuthError::Verification(
+                    "CAPTCHA was solved for another action".to_string(),
+                ));
+            }
+        }
+
+        if let Some(min_score) = self.min_score {
+            if result.score.is_none_or(|score| score < min_score) {
+                return Err(AuthError::Verification(
+                    "CAPTCHA score is too low".to_string(),
+                ));
+            }
+        }
+
+        Ok(CaptchaOutcome {
+            hostname: result.hostname,
+            action: result.action,
+            score: result.score,
+            challenge_ts: result.challenge_ts,
+        })
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::AuthError;
+
+static TIME_SOURCE: OnceLock<fn() -> Duration> = OnceLock::new();
+
+/// Replace the system clock for the whole process, failing with `AuthError::InvalidState` if a
+/// time source is already set
+///
+/// `wasm32-unknown-unknown` has no system clock (`SystemTime::now` panics there), so browser and
+/// edge builds install one backed by the host, such as `js_sys::Date::now`. TOTP, token and code
+/// expiry and rate limits all read the time through `since_epoch`.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::clock::{set_time_source, since_epoch};
+///
+/// // E.g. `Duration::from_millis(js_sys::Date::now() as u64)`
+/// fn host_clock() -> Duration {
+///     Duration::from_millis(1_700_000_000_000)
+/// }
+///
+/// set_time_source(host_clock)?;
+/// assert_eq!(since_epoch()?.as_secs(), 1_700_000_000);
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+pub fn set_time_source(source: fn() -> Duration) -> Result<(), AuthError> {
+    TIME_SOURCE
+        .set(source)
+        .map_err(|_| AuthError::InvalidState("A time source is already set".to_string()))
+}
+
+/// Time since the Unix epoch from the installed time source, or else the system clock
+pub fn since_epoch() -> Result<Duration, AuthError> {
+    match TIME_SOURCE.get() {
+        Some(source) => Ok(source()),
+        None => Ok(SystemTime::now().duration_since(UNIX_EPOCH)?),
+    }
+}
+
+/// A source of the current time, so TOTP checks, token expiry and session timeouts can be tested
+/// at any instant
+pub trait Clock {
+    /// Time since the Unix epoch
+    fn since_epoch(&self) -> Result<Duration, AuthError>;
+
+    /// Unix timestamp in seconds
+    fn now(&self) -> Result<u64, AuthError> {
+        Ok(self.since_epoch()?.as_secs())
+    }
+}
+
+impl<T: Clock> Clock for Arc<T> {
+    fn since_epoch(&self) -> Result<Duration, AuthError> {
+        (**self).since_epoch()
+    }
+}
+
+impl<T: Clock> Clock for &T {
+    fn since_epoch(&self) -> Result<Duration, AuthError> {
+        (**self).since_epoch()
+    }
+}
+
+/// The process wide time source: the system clock unless `set_time_source` replaced it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn since_epoch(&self) -> Result<Duration, AuthError> {
+        since_epoch()
+    }
+}
+
+/// A clock that only moves when told to; clones share the same time
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::clock::{Clock, MockClock};
+///
+/// let clock = MockClock::at(1_700_000_000);
+/// let shared = clock.clone();
+///
+/// clock.advance(Duration::from_secs(90));
+/// assert_eq!(shared.now()?, 1_700_000_090);
+///
+/// shared.set(Duration::from_secs(59));
+/// assert_eq!(clock.now()?, 59);
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    millis: Arc<AtomicU64>,
+}
+
+impl MockClock {
+    /// A clock stopped at this Unix timestamp in seconds
+    pub fn at(unix_seconds: u64) -> Self {
+        let clock = Self::default();
+        clock.set(Duration::from_secs(unix_seconds));
+        clock
+    }
+
+    /// Move to this time since the Unix epoch, which may be in the past
+    pub fn set(&self, since_epoch: Duration) {
+        self.millis
+            .store(since_epoch.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.millis
+            .fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn since_epoch(&self) -> Result<Duration, AuthError> {
+        Ok(Duration::from_millis(self.millis.load(Ordering::SeqCst)))
+    }
+}
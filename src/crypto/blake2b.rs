@@ -0,0 +1,160 @@
+const IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+const SIGMA: [[usize; 16]; 12] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+];
+
+const BLOCK_LEN: usize = 128;
+
+/// BLAKE2b (RFC 7693) with a variable output length, as Argon2 and PASETO require
+pub(crate) struct Blake2b {
+    state: [u64; 8],
+    buffer: [u8; BLOCK_LEN],
+    buffered: usize,
+    length: u128,
+    output_len: usize,
+}
+
+impl Blake2b {
+    /// `output_len` must be between 1 and 64 bytes
+    pub(crate) fn new(output_len: usize) -> Self {
+        debug_assert!((1..=64).contains(&output_len));
+
+        let mut state = IV;
+        state[0] ^= 0x01010000 ^ output_len as u64;
+
+        Self {
+            state,
+            buffer: [0; BLOCK_LEN],
+            buffered: 0,
+            length: 0,
+            output_len,
+        }
+    }
+
+    /// Keyed BLAKE2b, used as a MAC; `key` must be at most 64 bytes
+    pub(crate) fn new_keyed(output_len: usize, key: &[u8]) -> Self {
+        debug_assert!(key.len() <= 64);
+
+        let mut hasher = Self::new(output_len);
+        hasher.state[0] ^= (key.len() as u64) << 8;
+
+        if !key.is_empty() {
+            let mut block = [0u8; BLOCK_LEN];
+            block[..key.len()].copy_from_slice(key);
+            hasher.update(&block);
+        }
+
+        hasher
+    }
+
+    pub(crate) fn update(&mut self, mut input: &[u8]) -> &mut Self {
+        while !input.is_empty() {
+            // The final block must be compressed with the last block flag, so a full buffer is only
+            // compressed once more input arrives
+            if self.buffered == BLOCK_LEN {
+                self.length += BLOCK_LEN as u128;
+                let block = self.buffer;
+                self.compress(&block, false);
+                self.buffered = 0;
+            }
+
+            let take = (BLOCK_LEN - self.buffered).min(input.len());
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&input[..take]);
+            self.buffered += take;
+            input = &input[take..];
+        }
+
+        self
+    }
+
+    pub(crate) fn finalize(mut self) -> Vec<u8> {
+        self.length += self.buffered as u128;
+        self.buffer[self.buffered..].fill(0);
+        let block = self.buffer;
+        self.compress(&block, true);
+
+        self.state
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .take(self.output_len)
+            .collect()
+    }
+
+    fn compress(&mut self, block: &[u8; BLOCK_LEN], last: bool) {
+        let mut message = [0u64; 16];
+        for (word, chunk) in message.iter_mut().zip(block.chunks_exact(8)) {
+            *word = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+
+        let mut v = [0u64; 16];
+        v[..8].copy_from_slice(&self.state);
+        v[8..].copy_from_slice(&IV);
+        v[12] ^= self.length as u64;
+        v[13] ^= (self.length >> 64) as u64;
+        if last {
+            v[14] = !v[14];
+        }
+
+        for sigma in SIGMA.iter() {
+            let mut mix = |a: usize, b: usize, c: usize, d: usize, x: u64, y: u64| {
+                v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+                v[d] = (v[d] ^ v[a]).rotate_right(32);
+                v[c] = v[c].wrapping_add(v[d]);
+                v[b] = (v[b] ^ v[c]).rotate_right(24);
+                v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+                v[d] = (v[d] ^ v[a]).rotate_right(16);
+                v[c] = v[c].wrapping_add(v[d]);
+                v[b] = (v[b] ^ v[c]).rotate_right(63);
+            };
+
+            mix(0, 4, 8, 12, message[sigma[0]], message[sigma[1]]);
+            mix(1, 5, 9, 13, message[sigma[2]], message[sigma[3]]);
+            mix(2, 6, 10, 14, message[sigma[4]], message[sigma[5]]);
+            mix(3, 7, 11, 15, message[sigma[6]], message[sigma[7]]);
+            mix(0, 5, 10, 15, message[sigma[8]], message[sigma[9]]);
+            mix(1, 6, 11, 12, message[sigma[10]], message[sigma[11]]);
+            mix(2, 7, 8, 13, message[sigma[12]], message[sigma[13]]);
+            mix(3, 4, 9, 14, message[sigma[14]], message[sigma[15]]);
+        }
+
+        for (index, word) in self.state.iter_mut().enumerate() {
+            *word ^= v[index] ^ v[index + 8];
+        }
+    }
+}
+
+/// Hash `input` to `output_len` bytes in one call
+#[cfg(feature = "password")]
+pub(crate) fn blake2b(output_len: usize, input: &[u8]) -> Vec<u8> {
+    let mut hasher = Blake2b::new(output_len);
+    hasher.update(input);
+    hasher.finalize()
+}
+
+/// MAC `input` under `key`, producing `output_len` bytes
+pub(crate) fn blake2b_keyed(output_len: usize, key: &[u8], input: &[u8]) -> Vec<u8> {
+    let mut hasher = Blake2b::new_keyed(output_len, key);
+    hasher.update(input);
+    hasher.finalize()
+}
@@ -0,0 +1,128 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::AuthError;
+
+use super::{sealed::Sealer, HmacKey};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Minimum length in bytes of a master key
+pub const MIN_MASTER_KEY_LEN: usize = 32;
+
+/// Length in bytes of a `derive_key` subkey
+pub const SUBKEY_LEN: usize = 32;
+
+/// Most bytes HKDF-SHA256 can derive for one purpose, 255 blocks
+pub const MAX_DERIVED_LEN: usize = 255 * 32;
+
+/// A master key that deterministically derives independent subkeys with HKDF-SHA256 (RFC 5869)
+///
+/// Each purpose string gives an unrelated key, so one secret in a KMS or environment variable
+/// can supply the cookie signing key, the sealing key and the pepper, and leaking one subkey
+/// reveals neither the master key nor the others. Put a version in the purpose, such as
+/// `cookies/v2`, to rotate one subkey without touching the rest.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::crypto::kdf::MasterKey;
+/// use lonewolf_auth_toolkit::crypto::{hmac_sign, hmac_verify, HmacKeys};
+///
+/// let master = MasterKey::new(b"a master key from the environment, 32+ bytes")?;
+///
+/// let cookies = HmacKeys::new(1, master.hmac_key("cookies/v1")?);
+/// let sealer = master.sealer(1, "totp-secrets/v1")?;
+/// let pepper = master.derive_key("pepper/v1");
+///
+/// assert_ne!(master.derive_key("pepper/v1"), master.derive_key("pepper/v2"));
+/// let restarted = MasterKey::new(b"a master key from the environment, 32+ bytes")?;
+/// assert_eq!(pepper, restarted.derive_key("pepper/v1"));
+///
+/// let signature = hmac_sign(&cookies, b"remember_me=SomeAccountName");
+/// assert!(hmac_verify(&cookies, b"remember_me=SomeAccountName", &signature)?);
+///
+/// let sealed = sealer.seal(b"GEZDGNBVGY3TQOJQ", b"account-1")?;
+/// assert_eq!(sealer.open(&sealed, b"account-1")?, b"GEZDGNBVGY3TQOJQ");
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Clone)]
+pub struct MasterKey {
+    prk: Zeroizing<[u8; 32]>,
+}
+
+impl MasterKey {
+    pub fn new(key: &[u8]) -> Result<Self, AuthError> {
+        Self::with_salt(key, &[])
+    }
+
+    /// Like `new`, mixing in a non secret salt such as a deployment id
+    pub fn with_salt(key: &[u8], salt: &[u8]) -> Result<Self, AuthError> {
+        if key.len() < MIN_MASTER_KEY_LEN {
+            return Err(AuthError::InvalidInput(format!(
+                "Master keys must be at least {} bytes",
+                MIN_MASTER_KEY_LEN
+            )));
+        }
+
+        // HKDF-Extract; an empty salt stands for a block of zeros
+        let mut mac = HmacSha256::new_from_slice(salt).expect("HMAC accepts any key length");
+        mac.update(key);
+
+        Ok(Self {
+            prk: Zeroizing::new(mac.finalize().into_bytes().into()),
+        })
+    }
+
+    /// Derive `len` bytes for `purpose`, failing if `len` exceeds `MAX_DERIVED_LEN`
+    pub fn derive(&self, purpose: &str, len: usize) -> Result<Vec<u8>, AuthError> {
+        if len > MAX_DERIVED_LEN {
+            return Err(AuthError::InvalidInput(format!(
+                "HKDF can derive at most {} bytes",
+                MAX_DERIVED_LEN
+            )));
+        }
+
+        // HKDF-Expand: T(i) = HMAC(PRK, T(i - 1) || info || i)
+        let mut output = Vec::with_capacity(len);
+        let mut block = Zeroizing::new(Vec::new());
+        let mut counter = 1u8;
+
+        while output.len() < len {
+            let mut mac =
+                HmacSha256::new_from_slice(&*self.prk).expect("HMAC accepts any key length");
+            mac.update(&block);
+            mac.update(purpose.as_bytes());
+            mac.update(&[counter]);
+            *block = mac.finalize().into_bytes().to_vec();
+
+            let take = block.len().min(len - output.len());
+            output.extend_from_slice(&block[..take]);
+            counter = counter.wrapping_add(1);
+        }
+
+        Ok(output)
+    }
+
+    /// A 32 byte subkey for `purpose`, e.g. for `PepperedHasher` or `SignedCookies`
+    pub fn derive_key(&self, purpose: &str) -> [u8; SUBKEY_LEN] {
+        let mut key = [0u8; SUBKEY_LEN];
+        key.copy_from_slice(
+            &self
+                .derive(purpose, SUBKEY_LEN)
+                .expect("32 bytes is in range"),
+        );
+
+        key
+    }
+
+    /// An HMAC-SHA256 key for `purpose`
+    pub fn hmac_key(&self, purpose: &str) -> Result<HmacKey, AuthError> {
+        HmacKey::sha256(&*Zeroizing::new(self.derive_key(purpose)))
+    }
+
+    /// A `Sealer` whose current key is derived for `purpose` and tagged with `version`
+    pub fn sealer(&self, version: u32, purpose: &str) -> Result<Sealer, AuthError> {
+        Sealer::new(version, &*Zeroizing::new(self.derive_key(purpose)))
+    }
+}
@@ -0,0 +1,35 @@
+#[cfg(feature = "aws-kms")]
+pub mod aws_kms;
+pub mod kdf;
+pub mod provider;
+pub mod sealed;
+#[cfg(feature = "vault")]
+pub mod vault;
+
+pub(crate) mod blake2b;
+#[cfg(feature = "token")]
+pub(crate) mod xchacha20;
+
+mod signing;
+
+use subtle::ConstantTimeEq;
+
+pub use signing::{hmac_sign, hmac_verify, HmacAlgorithm, HmacKey, HmacKeys, MIN_HMAC_KEY_LEN};
+
+/// Compare two secrets in constant time
+///
+/// The time taken depends only on the lengths of the inputs, never on where they first differ,
+/// so it is safe for comparing codes, tokens and MACs supplied by a client.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::crypto::ct_eq;
+///
+/// assert!(ct_eq("123456", "123456"));
+/// assert!(!ct_eq("123456", "654321"));
+/// ```
+pub fn ct_eq<A: AsRef<[u8]>, B: AsRef<[u8]>>(a: A, b: B) -> bool {
+    let (a, b) = (a.as_ref(), b.as_ref());
+
+    a.len() == b.len() && bool::from(a.ct_eq(b))
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use ring::{
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+
+use crate::AuthError;
+
+use super::sealed::{generate_key, Sealer};
+
+/// The signature algorithms a `KeyProvider` can be asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureAlgorithm {
+    /// ECDSA on P-256 with SHA-256
+    Es256,
+    /// RSASSA-PKCS1-v1_5 with SHA-256
+    Rs256,
+    /// Ed25519
+    EdDsa,
+}
+
+/// Signs and encrypts with keys held by a key management service
+///
+/// The private key never enters application memory: only the message to sign, or the secret to
+/// encrypt, is sent to the service. Use it with `token::jwt::RemoteJwtSigner` and
+/// `token::paseto::RemotePasetoSigner` for tokens, and instead of `sealed::Sealer` for secrets
+/// stored at rest. Implemented for AWS KMS by `aws_kms::AwsKms` and HashiCorp Vault by
+/// `vault::VaultTransit`, and by `MemoryKeyProvider` for tests.
+pub trait KeyProvider {
+    /// Sign `message` with the key `key_id`
+    ///
+    /// Signatures are in the form JWS and PASETO expect: `r || s` for ES256, PKCS#1 v1.5 for RS256
+    /// and the 64 byte signature for Ed25519.
+    fn sign(
+        &self,
+        key_id: &str,
+        algorithm: SignatureAlgorithm,
+        message: &[u8],
+    ) -> impl Future<Output = Result<Vec<u8>, AuthError>> + Send;
+
+    /// Encrypt `plaintext` with the key `key_id`, returning an opaque value to store
+    ///
+    /// `associated_data` is authenticated but not stored, as with `sealed::Sealer`.
+    fn encrypt(
+        &self,
+        key_id: &str,
+        plaintext: &[u8],
+        associated_data: &[u8],
+    ) -> impl Future<Output = Result<String, AuthError>> + Send;
+
+    /// Decrypt a value from `encrypt`, failing with `AuthError::Verification` if it was altered or
+    /// `associated_data` differs
+    fn decrypt(
+        &self,
+        key_id: &str,
+        ciphertext: &str,
+        associated_data: &[u8],
+    ) -> impl Future<Output = Result<Vec<u8>, AuthError>> + Send;
+}
+
+impl<T: KeyProvider> KeyProvider for Arc<T> {
+    fn sign(
+        &self,
+        key_id: &str,
+        algorithm: SignatureAlgorithm,
+        message: &[u8],
+    ) -> impl Future<Output = Result<Vec<u8>, AuthError>> + Send {
+        (**self).sign(key_id, algorithm, message)
+    }
+
+    fn encrypt(
+        &self,
+        key_id: &str,
+        plaintext: &[u8],
+        associated_data: &[u8],
+    ) -> impl Future<Output = Result<String, AuthError>> + Send {
+        (**self).encrypt(key_id, plaintext, associated_data)
+    }
+
+    fn decrypt(
+        &self,
+        key_id: &str,
+        ciphertext: &str,
+        associated_data: &[u8],
+    ) -> impl Future<Output = Result<Vec<u8>, AuthError>> + Send {
+        (**self).decrypt(key_id, ciphertext, associated_data)
+    }
+}
+
+enum MemoryKey {
+    Es256(EcdsaKeyPair),
+    EdDsa(Ed25519KeyPair),
+    Aead(Sealer),
+}
+
+/// Generates and holds keys in process memory, standing in for a key management service in
+/// tests and local development
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::crypto::provider::{KeyProvider, MemoryKeyProvider};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let keys = MemoryKeyProvider::default();
+///     keys.generate_aead("totp-secrets")?;
+///
+///     let sealed = keys.encrypt("totp-secrets", b"GEZDGNBVGY3TQOJQ", b"account-1").await?;
+///
+///     assert_eq!(keys.decrypt("totp-secrets", &sealed, b"account-1").await?, b"GEZDGNBVGY3TQOJQ");
+///     assert!(keys.decrypt("totp-secrets", &sealed, b"account-2").await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Default)]
+pub struct MemoryKeyProvider {
+    keys: Mutex<HashMap<String, MemoryKey>>,
+}
+
+impl MemoryKeyProvider {
+    /// Generate a P-256 key, returning its uncompressed public point for
+    /// `token::jwt::VerifyingKey::es256_point`
+    pub fn generate_es256(&self, key_id: &str) -> Result<Vec<u8>, AuthError> {
+        let rng = SystemRandom::new();
+        let pair = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+            .ok()
+            .and_then(|pkcs8| {
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                    .ok()
+            })
+            .ok_or_else(|| AuthError::backend("Failed to generate P-256 key"))?;
+        let public_key = pair.public_key().as_ref().to_vec();
+
+        self.lock()?
+            .insert(key_id.to_string(), MemoryKey::Es256(pair));
+
+        Ok(public_key)
+    }
+
+    /// Generate an Ed25519 key, returning its 32 byte public key
+    pub fn generate_ed25519(&self, key_id: &str) -> Result<Vec<u8>, AuthError> {
+        let pair = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .ok()
+            .and_then(|pkcs8| Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).ok())
+            .ok_or_else(|| AuthError::backend("Failed to generate Ed25519 key"))?;
+        let public_key = pair.public_key().as_ref().to_vec();
+
+        self.lock()?
+            .insert(key_id.to_string(), MemoryKey::EdDsa(pair));
+
+        Ok(public_key)
+    }
+
+    /// Generate an AES-256-GCM key for `encrypt` and `decrypt`
+    pub fn generate_aead(&self, key_id: &str) -> Result<(), AuthError> {
+        let sealer = Sealer::new(1, &generate_key())?;
+
+        self.lock()?
+            .insert(key_id.to_string(), MemoryKey::Aead(sealer));
+
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, MemoryKey>>, AuthError> {
+        self.keys
+            .lock()
+            .map_err(|_| AuthError::backend("Key provider lock poisoned"))
+    }
+
+    fn with_key<T>(
+        &self,
+        key_id: &str,
+        f: impl FnOnce(&MemoryKey) -> Result<T, AuthError>,
+    ) -> Result<T, AuthError> {
+        let keys = self.lock()?;
+        let key = keys
+            .get(key_id)
+            .ok_or_else(|| AuthError::NotFound(format!("No key {}", key_id)))?;
+
+        f(key)
+    }
+}
+
+impl KeyProvider for MemoryKeyProvider {
+    async fn sign(
+        &self,
+        key_id: &str,
+        algorithm: SignatureAlgorithm,
+        message: &[u8],
+    ) -> Result<Vec<u8>, AuthError> {
+        self.with_key(key_id, |key| match (key, algorithm) {
+            (MemoryKey::Es256(pair), SignatureAlgorithm::Es256) => pair
+                .sign(&SystemRandom::new(), message)
+                .map(|signature| signature.as_ref().to_vec())
+                .map_err(|_| AuthError::backend("Failed to sign with P-256 key")),
+            (MemoryKey::EdDsa(pair), SignatureAlgorithm::EdDsa) => {
+                Ok(pair.sign(message).as_ref().to_vec())
+            }
+            _ => Err(AuthError::InvalidInput(format!(
+                "Key {} cannot sign with {:?}",
+                key_id, algorithm
+            ))),
+        })
+    }
+
+    async fn encrypt(
+        &self,
+        key_id: &str,
+        plaintext: &[u8],
+        associated_data: &[u8],
+    ) -> Result<String, AuthError> {
+        self.with_key(key_id, |key| match key {
+            MemoryKey::Aead(sealer) => sealer.seal(plaintext, associated_data),
+            _ => Err(AuthError::InvalidInput(format!(
+                "Key {} cannot encrypt",
+                key_id
+            ))),
+        })
+    }
+
+    async fn decrypt(
+        &self,
+        key_id: &str,
+        ciphertext: &str,
+        associated_data: &[u8],
+    ) -> Result<Vec<u8>, AuthError> {
+        self.with_key(key_id, |key| match key {
+            MemoryKey::Aead(sealer) => sealer.open(ciphertext, associated_data),
+            _ => Err(AuthError::InvalidInput(format!(
+                "Key {} cannot decrypt",
+                key_id
+            ))),
+        })
+    }
+}
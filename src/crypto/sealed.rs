@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{thread_rng, RngCore};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+
+use crate::AuthError;
+
+/// Length in bytes of an AES-256-GCM key
+pub const KEY_LEN: usize = 32;
+
+/// Encrypts secrets for storage with AES-256-GCM, tagging each value with the key version
+///
+/// Values are sealed with the current key and opened with whichever key version they name, so
+/// after adding a new current key old values keep opening until they are resealed.
+///
+/// Sealed values look like `<version>.<base64url(nonce || ciphertext || tag)>`. The associated
+/// data is authenticated but not stored; pass something that identifies the owning record (such
+/// as the account id) so a sealed value cannot be copied onto another record.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::crypto::sealed::{generate_key, Sealer};
+///
+/// let old = Sealer::new(1, &generate_key()).unwrap();
+/// let sealed = old.seal(b"GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ", b"account-1").unwrap();
+///
+/// let rotated = old.rotate(2, &generate_key()).unwrap();
+///
+/// assert!(rotated.needs_reseal(&sealed));
+///
+/// let resealed = rotated.reseal(&sealed, b"account-1").unwrap();
+///
+/// assert!(!rotated.needs_reseal(&resealed));
+/// assert_eq!(rotated.open(&resealed, b"account-1").unwrap(), b"GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+/// assert!(rotated.open(&resealed, b"account-2").is_err());
+/// ```
+pub struct Sealer {
+    current: u32,
+    keys: BTreeMap<u32, LessSafeKey>,
+}
+
+impl Sealer {
+    /// Create a sealer whose current key is `key` with the given version
+    pub fn new(version: u32, key: &[u8]) -> Result<Self, AuthError> {
+        let mut keys = BTreeMap::new();
+        keys.insert(version, build_key(key)?);
+
+        Ok(Self {
+            current: version,
+            keys,
+        })
+    }
+
+    /// Add an older key that is only used to open existing values
+    pub fn with_previous(mut self, version: u32, key: &[u8]) -> Result<Self, AuthError> {
+        if version == self.current {
+            return Err(AuthError::InvalidInput(
+                "Previous key version clashes with the current key".to_string(),
+            ));
+        }
+
+        self.keys.insert(version, build_key(key)?);
+
+        Ok(self)
+    }
+
+    /// Make `key` the current key, keeping every existing key for opening
+    pub fn rotate(mut self, version: u32, key: &[u8]) -> Result<Self, AuthError> {
+        if self.keys.contains_key(&version) {
+            return Err(AuthError::InvalidInput(
+                "Key version is already in use".to_string(),
+            ));
+        }
+
+        self.keys.insert(version, build_key(key)?);
+        self.current = version;
+
+        Ok(self)
+    }
+
+    /// Version of the key new values are sealed with
+    pub fn current_version(&self) -> u32 {
+        self.current
+    }
+
+    /// Encrypt a value with the current key
+    pub fn seal(&self, plaintext: &[u8], associated_data: &[u8]) -> Result<String, AuthError> {
+        let key = &self.keys[&self.current];
+        let mut nonce = [0u8; NONCE_LEN];
+        thread_rng().fill_bytes(&mut nonce);
+
+        let mut in_out = plaintext.to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(associated_data),
+            &mut in_out,
+        )
+        .map_err(|_| AuthError::InvalidInput("Failed to seal value".to_string()))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&in_out);
+
+        Ok(format!(
+            "{}.{}",
+            self.current,
+            URL_SAFE_NO_PAD.encode(payload)
+        ))
+    }
+
+    /// Decrypt a value sealed with any known key version
+    pub fn open(&self, sealed: &str, associated_data: &[u8]) -> Result<Vec<u8>, AuthError> {
+        let (version, payload) = parse(sealed)?;
+        let key = self.keys.get(&version).ok_or_else(|| {
+            AuthError::Verification(format!("Unknown sealing key version {}", version))
+        })?;
+
+        if payload.len() < NONCE_LEN + AES_256_GCM.tag_len() {
+            return Err(AuthError::Malformed(
+                "Sealed value is too short".to_string(),
+            ));
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| AuthError::Malformed("Sealed value has an invalid nonce".to_string()))?;
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = key
+            .open_in_place(nonce, Aad::from(associated_data), &mut in_out)
+            .map_err(|_| {
+                AuthError::Verification("Sealed value failed authentication".to_string())
+            })?;
+
+        Ok(plaintext.to_vec())
+    }
+
+    /// Whether a value was sealed with a key other than the current one
+    pub fn needs_reseal(&self, sealed: &str) -> bool {
+        parse(sealed).map_or(true, |(version, _)| version != self.current)
+    }
+
+    /// Open a value and seal it again with the current key
+    pub fn reseal(&self, sealed: &str, associated_data: &[u8]) -> Result<String, AuthError> {
+        let plaintext = self.open(sealed, associated_data)?;
+
+        self.seal(&plaintext, associated_data)
+    }
+}
+
+/// Generate a random AES-256-GCM key
+pub fn generate_key() -> [u8; KEY_LEN] {
+    let mut key = [0u8; KEY_LEN];
+    thread_rng().fill_bytes(&mut key);
+
+    key
+}
+
+fn build_key(key: &[u8]) -> Result<LessSafeKey, AuthError> {
+    if key.len() != KEY_LEN {
+        return Err(AuthError::InvalidInput(
+            "Sealing keys must be 32 bytes".to_string(),
+        ));
+    }
+
+    let key = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| AuthError::InvalidInput("Invalid sealing key".to_string()))?;
+
+    Ok(LessSafeKey::new(key))
+}
+
+fn parse(sealed: &str) -> Result<(u32, Vec<u8>), AuthError> {
+    let (version, payload) = sealed.split_once('.').ok_or_else(|| {
+        AuthError::Malformed("Sealed value is missing its key version".to_string())
+    })?;
+
+    Ok((version.parse()?, URL_SAFE_NO_PAD.decode(payload)?))
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha512};
+use zeroize::Zeroizing;
+
+use crate::AuthError;
+
+use super::ct_eq;
+
+/// Minimum length in bytes of an HMAC signing key
+pub const MIN_HMAC_KEY_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HmacAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl HmacAlgorithm {
+    /// The raw MAC of `message`, for formats that expect a bare tag such as webhook headers
+    pub fn mac(&self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        match self {
+            HmacAlgorithm::Sha256 => {
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+                mac.update(message);
+                mac.finalize().into_bytes().to_vec()
+            }
+            HmacAlgorithm::Sha512 => {
+                let mut mac =
+                    Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts any key length");
+                mac.update(message);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+}
+
+/// A secret key together with the algorithm it signs with
+#[derive(Clone)]
+pub struct HmacKey {
+    algorithm: HmacAlgorithm,
+    key: Zeroizing<Vec<u8>>,
+}
+
+impl HmacKey {
+    pub fn sha256(key: &[u8]) -> Result<Self, AuthError> {
+        Self::new(HmacAlgorithm::Sha256, key)
+    }
+
+    pub fn sha512(key: &[u8]) -> Result<Self, AuthError> {
+        Self::new(HmacAlgorithm::Sha512, key)
+    }
+
+    pub fn new(algorithm: HmacAlgorithm, key: &[u8]) -> Result<Self, AuthError> {
+        if key.len() < MIN_HMAC_KEY_LEN {
+            return Err(AuthError::InvalidInput(format!(
+                "HMAC keys must be at least {} bytes",
+                MIN_HMAC_KEY_LEN
+            )));
+        }
+
+        Ok(Self {
+            algorithm,
+            key: Zeroizing::new(key.to_vec()),
+        })
+    }
+
+    pub fn algorithm(&self) -> HmacAlgorithm {
+        self.algorithm
+    }
+
+    fn mac(&self, message: &[u8]) -> Vec<u8> {
+        self.algorithm.mac(&self.key, message)
+    }
+}
+
+/// A current HMAC key plus older keys that still verify, each tagged with a version
+///
+/// Signatures look like `<version>.<base64url(tag)>`, so after `rotate` values signed with an
+/// earlier key keep verifying until that key is retired. Bind each signature to its purpose by
+/// putting it in the message, e.g. `b"webhook:" + body`, so a tag made for one use is rejected
+/// by another.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::crypto::{hmac_sign, hmac_verify, HmacKey, HmacKeys};
+///
+/// let keys = HmacKeys::new(1, HmacKey::sha256(b"the first key, at least 32 bytes long")?);
+/// let old = hmac_sign(&keys, b"download:/reports/42?expires=1700000000");
+///
+/// let keys = keys.rotate(2, HmacKey::sha512(b"the second key, at least 32 bytes long")?)?;
+/// let new = hmac_sign(&keys, b"download:/reports/42?expires=1700000000");
+///
+/// assert!(new.starts_with("2."));
+/// assert!(hmac_verify(&keys, b"download:/reports/42?expires=1700000000", &old)?);
+/// assert!(!hmac_verify(&keys, b"download:/reports/43?expires=1700000000", &new)?);
+///
+/// let keys = keys.retire(1)?;
+/// assert!(!hmac_verify(&keys, b"download:/reports/42?expires=1700000000", &old)?);
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Clone)]
+pub struct HmacKeys {
+    current: u32,
+    keys: BTreeMap<u32, HmacKey>,
+}
+
+impl HmacKeys {
+    /// Create a key set whose current key has the given version
+    pub fn new(version: u32, key: HmacKey) -> Self {
+        Self {
+            current: version,
+            keys: BTreeMap::from([(version, key)]),
+        }
+    }
+
+    /// Add an older key that is only used to verify existing signatures
+    pub fn with_previous(mut self, version: u32, key: HmacKey) -> Result<Self, AuthError> {
+        self.check_unused(version)?;
+        self.keys.insert(version, key);
+
+        Ok(self)
+    }
+
+    /// Make `key` the current key, keeping every existing key for verifying
+    pub fn rotate(mut self, version: u32, key: HmacKey) -> Result<Self, AuthError> {
+        self.check_unused(version)?;
+        self.keys.insert(version, key);
+        self.current = version;
+
+        Ok(self)
+    }
+
+    /// Stop accepting signatures made with an old key
+    pub fn retire(mut self, version: u32) -> Result<Self, AuthError> {
+        if version == self.current {
+            return Err(AuthError::InvalidInput(
+                "The current key cannot be retired".to_string(),
+            ));
+        }
+
+        self.keys.remove(&version);
+
+        Ok(self)
+    }
+
+    /// Version of the key new signatures are made with
+    pub fn current_version(&self) -> u32 {
+        self.current
+    }
+
+    fn check_unused(&self, version: u32) -> Result<(), AuthError> {
+        if self.keys.contains_key(&version) {
+            return Err(AuthError::InvalidInput(
+                "Key version is already in use".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Sign `message` with the current key
+pub fn hmac_sign(keys: &HmacKeys, message: &[u8]) -> String {
+    let tag = keys.keys[&keys.current].mac(message);
+
+    format!("{}.{}", keys.current, URL_SAFE_NO_PAD.encode(tag))
+}
+
+/// Check a signature made by `hmac_sign` with any key still in the set, in constant time
+///
+/// Returns `Ok(false)` for a wrong tag or a retired or unknown key version, and an error only if
+/// the signature is not in the `<version>.<tag>` format.
+pub fn hmac_verify(keys: &HmacKeys, message: &[u8], signature: &str) -> Result<bool, AuthError> {
+    let (version, tag) = signature
+        .split_once('.')
+        .ok_or_else(|| AuthError::Malformed("Signature is missing its key version".to_string()))?;
+    let (version, tag): (u32, _) = (version.parse()?, URL_SAFE_NO_PAD.decode(tag)?);
+
+    Ok(keys
+        .keys
+        .get(&version)
+        .is_some_and(|key| ct_eq(key.mac(message), tag)))
+}
@@ -0,0 +1,248 @@
+use std::env;
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use serde_json::{json, Value as Json};
+use zeroize::Zeroizing;
+
+use crate::{
+    http::{HttpClient, HttpRequest, HttpResponse},
+    otel, AuthError,
+};
+
+use super::provider::{KeyProvider, SignatureAlgorithm};
+
+/// Where Vault mounts the Transit secrets engine unless configured otherwise
+pub const DEFAULT_MOUNT: &str = "transit";
+
+/// Signs and encrypts with keys in HashiCorp Vault's Transit secrets engine
+///
+/// Key ids are Transit key names. Signing keys must be of type `ecdsa-p256`, `rsa-2048` or larger,
+/// or `ed25519`; encryption keys `aes256-gcm96` or `chacha20-poly1305`. The token needs `update`
+/// on the `sign`, `encrypt` and `decrypt` paths of the keys it uses. Ciphertexts are Vault's own
+/// `vault:v1:...` strings, so they keep decrypting after the key is rotated in Vault.
+///
+/// ### Example
+/// ```rust
+/// use base64::{engine::general_purpose::STANDARD, Engine};
+/// use lonewolf_auth_toolkit::crypto::provider::KeyProvider;
+/// use lonewolf_auth_toolkit::crypto::vault::VaultTransit;
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::AuthError;
+/// use serde_json::{json, Value};
+///
+/// /// Encrypts by prefixing, as far as this example can tell
+/// struct FakeVault;
+///
+/// impl HttpClient for FakeVault {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         assert_eq!(request.headers[1], ("X-Vault-Token".to_string(), "hvs.token".to_string()));
+///         let body: Value = serde_json::from_slice(&request.body).unwrap();
+///
+///         let data = if request.url.contains("/encrypt/") {
+///             json!({ "ciphertext": format!("vault:v1:{}", body["plaintext"].as_str().unwrap()) })
+///         } else {
+///             let ciphertext = body["ciphertext"].as_str().unwrap();
+///             json!({ "plaintext": ciphertext.trim_start_matches("vault:v1:") })
+///         };
+///         Ok(HttpResponse {
+///             status: 200,
+///             headers: Vec::new(),
+///             body: json!({ "data": data }).to_string().into_bytes(),
+///         })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let vault = VaultTransit::new(FakeVault, "https://vault.example.com:8200", "hvs.token");
+///
+///     let sealed = vault.encrypt("totp-secrets", b"GEZDGNBVGY3TQOJQ", b"").await?;
+///     assert!(sealed.starts_with("vault:v1:"));
+///     assert_eq!(vault.decrypt("totp-secrets", &sealed, b"").await?, b"GEZDGNBVGY3TQOJQ");
+///
+///     Ok(())
+/// }
+/// ```
+pub struct VaultTransit<C> {
+    client: C,
+    address: String,
+    token: Zeroizing<String>,
+    mount: String,
+    namespace: Option<String>,
+}
+
+impl<C: HttpClient> VaultTransit<C> {
+    /// `address` is Vault's base URL, e.g. `https://vault.example.com:8200`
+    pub fn new(client: C, address: &str, token: &str) -> Self {
+        Self {
+            client,
+            address: address.trim_end_matches('/').to_string(),
+            token: Zeroizing::new(token.to_string()),
+            mount: DEFAULT_MOUNT.to_string(),
+            namespace: None,
+        }
+    }
+
+    /// Configure from `VAULT_ADDR`, `VAULT_TOKEN` and, on Vault Enterprise, `VAULT_NAMESPACE`
+    pub fn from_env(client: C) -> Result<Self, AuthError> {
+        let vault = Self::new(client, &env_var("VAULT_ADDR")?, &env_var("VAULT_TOKEN")?);
+
+        Ok(match env::var("VAULT_NAMESPACE") {
+            Ok(namespace) => vault.namespace(&namespace),
+            Err(_) => vault,
+        })
+    }
+
+    /// Path the Transit engine is mounted at, `transit` by default
+    pub fn mount(mut self, mount: &str) -> Self {
+        self.mount = mount.trim_matches('/').to_string();
+        self
+    }
+
+    /// Vault Enterprise namespace the mount lives in
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.to_string());
+        self
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    async fn call(&self, path: &str, body: Json) -> Result<Json, AuthError> {
+        let url = format!("{}/v1/{}/{}", self.address, self.mount, path);
+        let mut request = HttpRequest::post_json(url, body.to_string().into_bytes())
+            .header("X-Vault-Token", self.token.as_str());
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+
+        let response = otel::send(&self.client, request).await?;
+        if !response.is_success() {
+            return Err(error(&response));
+        }
+
+        let mut body: Json = serde_json::from_slice(&response.body)?;
+
+        Ok(body["data"].take())
+    }
+}
+
+impl<C: HttpClient + Sync> KeyProvider for VaultTransit<C> {
+    async fn sign(
+        &self,
+        key_id: &str,
+        algorithm: SignatureAlgorithm,
+        message: &[u8],
+    ) -> Result<Vec<u8>, AuthError> {
+        let (path, body) = match algorithm {
+            // JWS marshaling returns r || s rather than an ASN.1 signature
+            SignatureAlgorithm::Es256 => (
+                format!("sign/{}/sha2-256", key_id),
+                json!({ "input": STANDARD.encode(message), "marshaling_algorithm": "jws" }),
+            ),
+            SignatureAlgorithm::Rs256 => (
+                format!("sign/{}/sha2-256", key_id),
+                json!({ "input": STANDARD.encode(message), "signature_algorithm": "pkcs1v15" }),
+            ),
+            SignatureAlgorithm::EdDsa => (
+                format!("sign/{}", key_id),
+                json!({ "input": STANDARD.encode(message) }),
+            ),
+        };
+
+        let data = self.call(&path, body).await?;
+        let signature = versioned(&data["signature"])?;
+
+        Ok(match algorithm {
+            SignatureAlgorithm::Es256 => URL_SAFE_NO_PAD.decode(signature)?,
+            _ => STANDARD.decode(signature)?,
+        })
+    }
+
+    async fn encrypt(
+        &self,
+        key_id: &str,
+        plaintext: &[u8],
+        associated_data: &[u8],
+    ) -> Result<String, AuthError> {
+        let mut body = json!({ "plaintext": STANDARD.encode(plaintext) });
+        if !associated_data.is_empty() {
+            body["associated_data"] = STANDARD.encode(associated_data).into();
+        }
+
+        let data = self.call(&format!("encrypt/{}", key_id), body).await?;
+
+        data["ciphertext"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| AuthError::backend("Vault returned no ciphertext"))
+    }
+
+    async fn decrypt(
+        &self,
+        key_id: &str,
+        ciphertext: &str,
+        associated_data: &[u8],
+    ) -> Result<Vec<u8>, AuthError> {
+        let mut body = json!({ "ciphertext": ciphertext });
+        if !associated_data.is_empty() {
+            body["associated_data"] = STANDARD.encode(associated_data).into();
+        }
+
+        let data = self
+            .call(&format!("decrypt/{}", key_id), body)
+            .await
+            .map_err(|error| match error {
+                AuthError::InvalidInput(_) => {
+                    AuthError::Verification("Ciphertext could not be decrypted".to_string())
+                }
+                error => error,
+            })?;
+        let plaintext = data["plaintext"]
+            .as_str()
+            .ok_or_else(|| AuthError::backend("Vault returned no plaintext"))?;
+
+        Ok(STANDARD.decode(plaintext)?)
+    }
+}
+
+/// Strip the `vault:v<version>:` prefix from a signature
+fn versioned(value: &Json) -> Result<&str, AuthError> {
+    value
+        .as_str()
+        .and_then(|value| value.strip_prefix("vault:v"))
+        .and_then(|value| value.split_once(':'))
+        .map(|(_, value)| value)
+        .ok_or_else(|| AuthError::backend("Vault returned no signature"))
+}
+
+/// Vault's error body, e.g. `{"errors": ["permission denied"]}`
+fn error(response: &HttpResponse) -> AuthError {
+    let errors = serde_json::from_slice::<Json>(&response.body)
+        .ok()
+        .and_then(|body| {
+            let errors = body["errors"].as_array()?;
+            Some(
+                errors
+                    .iter()
+                    .filter_map(Json::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )
+        })
+        .unwrap_or_else(|| format!("HTTP {}", response.status));
+
+    match response.status {
+        400 => AuthError::InvalidInput(format!("Vault rejected the request: {}", errors)),
+        403 => AuthError::backend("Vault rejected the token"),
+        _ => AuthError::backend(format!("Vault error: {}", errors)),
+    }
+}
+
+fn env_var(name: &str) -> Result<String, AuthError> {
+    env::var(name).map_err(|_| AuthError::InvalidInput(format!("Set {}", name)))
+}
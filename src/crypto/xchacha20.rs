@@ -0,0 +1,100 @@
+const CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+/// XOR `data` with the XChaCha20 keystream for `key` and a 24 byte `nonce`, starting at block 0
+///
+/// This is the unauthenticated stream cipher from draft-irtf-cfrg-xchacha; callers must MAC the
+/// ciphertext themselves.
+pub(crate) fn xchacha20(key: &[u8; 32], nonce: &[u8; 24], data: &mut [u8]) {
+    let subkey = hchacha20(key, nonce[..16].try_into().unwrap());
+
+    let mut chacha_nonce = [0u8; 12];
+    chacha_nonce[4..].copy_from_slice(&nonce[16..]);
+
+    chacha20(&subkey, &chacha_nonce, data);
+}
+
+/// RFC 8439 ChaCha20 with a 32 bit block counter starting at 0
+fn chacha20(key: &[u8; 32], nonce: &[u8; 12], data: &mut [u8]) {
+    let mut state = initial_state(key, &nonce_words(nonce, 0));
+
+    for chunk in data.chunks_mut(64) {
+        let mut block = state;
+        rounds(&mut block);
+
+        let keystream = block
+            .iter()
+            .zip(state.iter())
+            .flat_map(|(mixed, original)| mixed.wrapping_add(*original).to_le_bytes());
+
+        for (byte, key_byte) in chunk.iter_mut().zip(keystream) {
+            *byte ^= key_byte;
+        }
+
+        state[12] = state[12].wrapping_add(1);
+    }
+}
+
+/// Derive a subkey from the first 16 bytes of an extended nonce
+fn hchacha20(key: &[u8; 32], nonce: &[u8; 16]) -> [u8; 32] {
+    let mut words = [0u32; 4];
+    for (word, chunk) in words.iter_mut().zip(nonce.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+
+    let mut state = initial_state(key, &words);
+    rounds(&mut state);
+
+    let mut subkey = [0u8; 32];
+    for (chunk, word) in subkey
+        .chunks_exact_mut(4)
+        .zip(state[..4].iter().chain(state[12..].iter()))
+    {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+
+    subkey
+}
+
+fn nonce_words(nonce: &[u8; 12], counter: u32) -> [u32; 4] {
+    let mut words = [counter, 0, 0, 0];
+    for (word, chunk) in words[1..].iter_mut().zip(nonce.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+
+    words
+}
+
+fn initial_state(key: &[u8; 32], tail: &[u32; 4]) -> [u32; 16] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&CONSTANTS);
+    for (word, chunk) in state[4..12].iter_mut().zip(key.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    state[12..].copy_from_slice(tail);
+
+    state
+}
+
+fn rounds(state: &mut [u32; 16]) {
+    let quarter = |state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize| {
+        state[a] = state[a].wrapping_add(state[b]);
+        state[d] = (state[d] ^ state[a]).rotate_left(16);
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_left(12);
+        state[a] = state[a].wrapping_add(state[b]);
+        state[d] = (state[d] ^ state[a]).rotate_left(8);
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_left(7);
+    };
+
+    for _ in 0..10 {
+        quarter(state, 0, 4, 8, 12);
+        quarter(state, 1, 5, 9, 13);
+        quarter(state, 2, 6, 10, 14);
+        quarter(state, 3, 7, 11, 15);
+        quarter(state, 0, 5, 10, 15);
+        quarter(state, 1, 6, 11, 12);
+        quarter(state, 2, 7, 8, 13);
+        quarter(state, 3, 4, 9, 14);
+    }
+}
@@ -0,0 +1,314 @@
+use std::future::Future;
+
+#[cfg(feature = "mfa")]
+use crate::mfa::otp::OtpChannel;
+#[cfg(feature = "token")]
+use crate::token::magic_link::MagicLinkSender;
+use crate::AuthError;
+
+/// An email ready to hand to a mail provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    /// The plain text body
+    pub text: String,
+    /// The HTML body, sent as an alternative to `text`
+    pub html: Option<String>,
+}
+
+/// Delivers email, implemented for SMTP, SES, Postmark, SendGrid, ...
+pub trait EmailSender {
+    fn send(&self, message: EmailMessage) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// A subject and body with `{{name}}` placeholders, such as `{{code}}` and `{{link}}`
+///
+/// Values are HTML escaped in the HTML body, and line breaks are removed from them in the subject
+/// so a value cannot add headers. Rendering fails with `AuthError::InvalidInput` if a placeholder
+/// has no value.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::email::EmailTemplate;
+///
+/// let template = EmailTemplate::new("Your code for {{app}}", "Your code is {{code}}")
+///     .html("<p>Your code is <b>{{ code }}</b></p>");
+///
+/// let values = [("app", "Example"), ("code", "123456")];
+/// let message = template.render("someone@example.com", &values)?;
+///
+/// assert_eq!(message.subject, "Your code for Example");
+/// assert_eq!(message.text, "Your code is 123456");
+/// assert_eq!(message.html.as_deref(), Some("<p>Your code is <b>123456</b></p>"));
+///
+/// assert!(template.render("someone@example.com", &[("code", "123456")]).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailTemplate {
+    subject: String,
+    text: String,
+    html: Option<String>,
+}
+
+impl EmailTemplate {
+    pub fn new(subject: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            text: text.into(),
+            html: None,
+        }
+    }
+
+    /// Also send an HTML body
+    pub fn html(mut self, html: impl Into<String>) -> Self {
+        self.html = Some(html.into());
+        self
+    }
+
+    /// The default message for a one time code, using `{{code}}`
+    pub fn otp() -> Self {
+        Self::new(
+            "Your verification code",
+            "Your verification code is {{code}}\n\nIf you did not ask for it, ignore this email.",
+        )
+        .html(
+            "<p>Your verification code is <strong>{{code}}</strong></p>\
+             <p>If you did not ask for it, ignore this email.</p>",
+        )
+    }
+
+    /// The default message for a magic sign in link, using `{{link}}`
+    pub fn magic_link() -> Self {
+        Self::new(
+            "Your sign in link",
+            "Sign in by opening this link:\n\n{{link}}\n\nIf you did not ask for it, ignore this \
+             email.",
+        )
+        .html(
+            "<p><a href=\"{{link}}\">Sign in</a></p>\
+             <p>If you did not ask for it, ignore this email.</p>",
+        )
+    }
+
+    /// The default message confirming an email address, using `{{link}}`
+    pub fn verification() -> Self {
+        Self::new(
+            "Confirm your email address",
+            "Confirm your email address by opening this link:\n\n{{link}}",
+        )
+        .html("<p><a href=\"{{link}}\">Confirm your email address</a></p>")
+    }
+
+    /// The default message for a password reset, using `{{link}}`
+    pub fn password_reset() -> Self {
+        Self::new(
+            "Reset your password",
+            "Reset your password by opening this link:\n\n{{link}}\n\nIf you did not ask for it, \
+             your password has not been changed.",
+        )
+        .html(
+            "<p><a href=\"{{link}}\">Reset your password</a></p>\
+             <p>If you did not ask for it, your password has not been changed.</p>",
+        )
+    }
+
+    /// Fill in the placeholders for a message to `to`
+    pub fn render(&self, to: &str, values: &[(&str, &str)]) -> Result<EmailMessage, AuthError> {
+        Ok(EmailMessage {
+            to: to.to_string(),
+            subject: fill(&self.subject, values, |value| {
+                value.replace(['\r', '\n'], " ")
+            })?,
+            text: fill(&self.text, values, str::to_string)?,
+            html: self
+                .html
+                .as_deref()
+                .map(|html| fill(html, values, escape_html))
+                .transpose()?,
+        })
+    }
+}
+
+/// Sends the emails of the one time code, magic link, email verification and password reset
+/// flows through an `EmailSender`
+///
+/// Each flow uses its default template unless another is set. `Mailer` is a `MagicLinkSender`, so
+/// it can be passed straight to `MagicLinks`.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::email::{EmailMessage, EmailSender, EmailTemplate, Mailer};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct Outbox(Mutex<Vec<EmailMessage>>);
+///
+/// impl EmailSender for Outbox {
+///     async fn send(&self, message: EmailMessage) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().push(message);
+///         Ok(())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let mailer = Mailer::new(Outbox::default())
+///         .otp_template(EmailTemplate::new("Example sign in code", "Your code is {{code}}"));
+///
+///     mailer.send_code("someone@example.com", "123456").await?;
+///     mailer
+///         .send_password_reset("someone@example.com", "https://app.example.com/reset?token=abc")
+///         .await?;
+///
+///     let outbox = mailer.sender().0.lock().unwrap();
+///     assert_eq!(outbox[0].text, "Your code is 123456");
+///     assert_eq!(outbox[1].subject, "Reset your password");
+///     assert!(outbox[1].text.contains("https://app.example.com/reset?token=abc"));
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Mailer<E> {
+    sender: E,
+    otp: EmailTemplate,
+    magic_link: EmailTemplate,
+    verification: EmailTemplate,
+    password_reset: EmailTemplate,
+}
+
+impl<E: EmailSender> Mailer<E> {
+    pub fn new(sender: E) -> Self {
+        Self {
+            sender,
+            otp: EmailTemplate::otp(),
+            magic_link: EmailTemplate::magic_link(),
+            verification: EmailTemplate::verification(),
+            password_reset: EmailTemplate::password_reset(),
+        }
+    }
+
+    pub fn otp_template(mut self, template: EmailTemplate) -> Self {
+        self.otp = template;
+        self
+    }
+
+    pub fn magic_link_template(mut self, template: EmailTemplate) -> Self {
+        self.magic_link = template;
+        self
+    }
+
+    pub fn verification_template(mut self, template: EmailTemplate) -> Self {
+        self.verification = template;
+        self
+    }
+
+    pub fn password_reset_template(mut self, template: EmailTemplate) -> Self {
+        self.password_reset = template;
+        self
+    }
+
+    pub fn sender(&self) -> &E {
+        &self.sender
+    }
+
+    /// Send a code issued by `mfa::email::EmailOtp`
+    pub async fn send_code(&self, to: &str, code: &str) -> Result<(), AuthError> {
+        self.send_template(&self.otp, to, &[("code", code)]).await
+    }
+
+    /// Send a sign in link issued by `token::magic_link::MagicLinks`
+    pub async fn send_magic_link(&self, to: &str, link: &str) -> Result<(), AuthError> {
+        self.send_template(&self.magic_link, to, &[("link", link)])
+            .await
+    }
+
+    /// Send a link carrying a token from `token::email_verification::EmailVerifier`
+    pub async fn send_verification(&self, to: &str, link: &str) -> Result<(), AuthError> {
+        self.send_template(&self.verification, to, &[("link", link)])
+            .await
+    }
+
+    /// Send a link carrying a token from `password::reset::PasswordResets`
+    pub async fn send_password_reset(&self, to: &str, link: &str) -> Result<(), AuthError> {
+        self.send_template(&self.password_reset, to, &[("link", link)])
+            .await
+    }
+
+    /// Render any template and send it
+    pub async fn send_template(
+        &self,
+        template: &EmailTemplate,
+        to: &str,
+        values: &[(&str, &str)],
+    ) -> Result<(), AuthError> {
+        self.sender.send(template.render(to, values)?).await
+    }
+}
+
+#[cfg(feature = "mfa")]
+impl<E: EmailSender + Sync> OtpChannel for Mailer<E> {
+    const NAME: &'static str = "email";
+
+    async fn deliver(&self, email: &str, code: &str) -> Result<(), AuthError> {
+        self.send_code(email, code).await
+    }
+}
+
+#[cfg(feature = "token")]
+impl<E: EmailSender + Sync> MagicLinkSender for Mailer<E> {
+    async fn send(&self, email: &str, link: &str) -> Result<(), AuthError> {
+        self.send_magic_link(email, link).await
+    }
+}
+
+fn fill(
+    template: &str,
+    values: &[(&str, &str)],
+    encode: impl Fn(&str) -> String,
+) -> Result<String, AuthError> {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..].find("}}").ok_or_else(|| {
+            AuthError::InvalidInput("Template has an unclosed placeholder".to_string())
+        })? + start;
+        let name = rest[start + 2..end].trim();
+
+        let value = values
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| {
+                AuthError::InvalidInput(format!("Template placeholder {} has no value", name))
+            })?;
+
+        filled.push_str(&rest[..start]);
+        filled.push_str(&encode(value));
+        rest = &rest[end + 2..];
+    }
+    filled.push_str(rest);
+
+    Ok(filled)
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
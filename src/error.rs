@@ -0,0 +1,152 @@
+use std::{
+    array::TryFromSliceError,
+    num::{ParseIntError, TryFromIntError},
+    string::FromUtf8Error,
+    time::SystemTimeError,
+};
+
+#[cfg(feature = "serde")]
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::rate_limit::RateLimited;
+
+/// Every way an operation in this crate can fail
+///
+/// A wrong code is not an error: verification functions return `Ok(false)` or `Ok(None)` for it,
+/// so an `Err` always means the request could not be evaluated.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::{mfa::verify, AuthError};
+///
+/// #[tokio::main]
+/// pub async fn main() {
+///     match verify("123456".to_string(), "2SHORT".to_string()).await {
+///         Ok(true) => println!("Signed in"),
+///         Ok(false) => println!("401 Unauthorized"),
+///         Err(AuthError::MalformedSecret(_)) => println!("500 Internal Server Error"),
+///         Err(AuthError::RateLimited(limited)) => println!("429 retry in {:?}", limited.retry_after),
+///         Err(error) => println!("{}", error),
+///     }
+/// }
+/// ```
+#[derive(Debug, Error)]
+pub enum AuthError {
+    /// An argument or configuration value was rejected
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    /// A stored secret could not be decoded or is too short to use
+    #[error("Malformed secret: {0}")]
+    MalformedSecret(String),
+
+    /// Encoded data such as a URI, token, CBOR or JSON could not be parsed
+    #[error("Malformed data: {0}")]
+    Malformed(String),
+
+    /// A signature, MAC, ciphertext or ceremony check failed
+    #[error("Verification failed: {0}")]
+    Verification(String),
+
+    /// The operation is not allowed in the current state, e.g. confirming an expired enrollment
+    #[error("Invalid state: {0}")]
+    InvalidState(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
+
+    #[error("System clock is before the Unix epoch")]
+    Clock(#[from] SystemTimeError),
+
+    /// A store, provider or notifier supplied by the caller failed
+    #[error("Backend error: {0}")]
+    Backend(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl AuthError {
+    /// Wrap an error from a store or provider implementation
+    pub fn backend<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> Self {
+        AuthError::Backend(error.into())
+    }
+
+    /// A stable `snake_case` name for the variant, e.g. `rate_limited`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AuthError::InvalidInput(_) => "invalid_input",
+            AuthError::MalformedSecret(_) => "malformed_secret",
+            AuthError::Malformed(_) => "malformed",
+            AuthError::Verification(_) => "verification",
+            AuthError::InvalidState(_) => "invalid_state",
+            AuthError::NotFound(_) => "not_found",
+            AuthError::RateLimited(_) => "rate_limited",
+            AuthError::Clock(_) => "clock",
+            AuthError::Backend(_) => "backend",
+        }
+    }
+}
+
+/// Serializes as `{ "kind": ..., "message": ... }` plus `retry_after` in seconds when rate
+/// limited, for JSON error responses
+///
+/// Backend errors only say "Backend error", so database and provider details never reach clients.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// let error = AuthError::NotFound("API key".to_string());
+///
+/// assert_eq!(
+///     serde_json::to_string(&error)?,
+///     r#"{"kind":"not_found","message":"Not found: API key"}"#
+/// );
+/// # Ok::<(), serde_json::Error>(())
+/// ```
+#[cfg(feature = "serde")]
+impl Serialize for AuthError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let retry_after = match self {
+            AuthError::RateLimited(limited) => Some(limited.retry_after.as_secs()),
+            _ => None,
+        };
+        let message = match self {
+            AuthError::Backend(_) => "Backend error".to_string(),
+            error => error.to_string(),
+        };
+
+        let mut state =
+            serializer.serialize_struct("AuthError", if retry_after.is_some() { 3 } else { 2 })?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &message)?;
+        if let Some(retry_after) = retry_after {
+            state.serialize_field("retry_after", &retry_after)?;
+        }
+        state.end()
+    }
+}
+
+macro_rules! malformed_from {
+    ($($source:ty),*) => {
+        $(
+            impl From<$source> for AuthError {
+                fn from(error: $source) -> Self {
+                    AuthError::Malformed(error.to_string())
+                }
+            }
+        )*
+    };
+}
+
+malformed_from!(
+    base64::DecodeError,
+    serde_json::Error,
+    url::ParseError,
+    FromUtf8Error,
+    TryFromSliceError,
+    ParseIntError,
+    TryFromIntError
+);
@@ -0,0 +1,421 @@
+use std::{
+    future::Future,
+    sync::{Mutex, MutexGuard},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "oauth")]
+use crate::oauth::social::SocialProfile;
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditSink, NoAudit},
+    token::now,
+    AuthError,
+};
+
+/// The provider name of password sign ins, whose subject is the user's id
+pub const PASSWORD: &str = "password";
+
+/// The provider name of passkeys, whose subject is the base64url credential id
+pub const WEBAUTHN: &str = "webauthn";
+
+/// A way of signing in that belongs to an account
+///
+/// `provider` and `subject` identify it globally: the provider's stable id for the user, such as
+/// an OIDC `sub`, never their email, which can change or be reassigned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LinkedIdentity {
+    pub account: String,
+    /// E.g. `password`, `webauthn`, `google` or `github`
+    pub provider: String,
+    pub subject: String,
+    /// Lowercased
+    pub email: Option<String>,
+    pub email_verified: bool,
+    /// Unix timestamp in seconds
+    pub linked_at: u64,
+}
+
+/// An identity presented at sign in, before it is known which account it belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityClaim {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+impl IdentityClaim {
+    pub fn new(provider: impl Into<String>, subject: impl Into<String>) -> Self {
+        Self {
+            provider: provider.into(),
+            subject: subject.into(),
+            email: None,
+            email_verified: false,
+        }
+    }
+
+    pub fn email(mut self, email: impl Into<String>, verified: bool) -> Self {
+        self.email = Some(email.into().to_lowercase());
+        self.email_verified = verified;
+        self
+    }
+}
+
+#[cfg(feature = "oauth")]
+impl From<&SocialProfile> for IdentityClaim {
+    fn from(profile: &SocialProfile) -> Self {
+        let claim = Self::new(profile.provider, profile.id.clone());
+
+        match &profile.email {
+            Some(email) => claim.email(email.clone(), profile.email_verified),
+            None => claim,
+        }
+    }
+}
+
+/// Where a presented identity leads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// The identity is linked; sign in to its account
+    Linked(LinkedIdentity),
+    /// The identity is unknown but its email is used by these identities of other accounts
+    ///
+    /// Do not sign in or link automatically: ask the user to sign in to the existing account
+    /// with one of these methods, then `link` the new one.
+    Conflict(Vec<LinkedIdentity>),
+    /// Neither the identity nor its email is known; create an account and `link` it
+    New,
+}
+
+/// Persists linked identities
+///
+/// `insert` must fail with `AuthError::InvalidState` when the provider and subject are already
+/// linked, checked atomically, e.g. by a unique index, so one identity never reaches two
+/// accounts. `reassign` must move every identity in one statement or transaction.
+pub trait IdentityStore {
+    fn insert(
+        &self,
+        identity: LinkedIdentity,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn find(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> impl Future<Output = Result<Option<LinkedIdentity>, AuthError>> + Send;
+
+    /// Every identity whose lowercased email is `email`, across accounts
+    fn find_by_email(
+        &self,
+        email: &str,
+    ) -> impl Future<Output = Result<Vec<LinkedIdentity>, AuthError>> + Send;
+
+    fn list(
+        &self,
+        account: &str,
+    ) -> impl Future<Output = Result<Vec<LinkedIdentity>, AuthError>> + Send;
+
+    /// Returns `false` if the account has no such identity
+    fn remove(
+        &self,
+        account: &str,
+        provider: &str,
+        subject: &str,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Move every identity of `from` to `to`, returning how many moved
+    fn reassign(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> impl Future<Output = Result<usize, AuthError>> + Send;
+}
+
+/// Links several ways of signing in, such as a password, Google, GitHub and passkeys, to one
+/// account
+///
+/// At each sign in, `resolve` the presented identity. A known identity leads to its account. An
+/// unknown one whose email another account already uses is a `Conflict`: linking it by email
+/// alone would let anyone who registers that address at a careless provider take the account
+/// over, so the user must first prove they own the existing account. Linking, unlinking and
+/// merging are recorded to the audit sink.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::identity::{
+///     Identities, IdentityClaim, MemoryIdentityStore, Resolution, PASSWORD,
+/// };
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let identities = Identities::new(MemoryIdentityStore::default());
+///     let password = IdentityClaim::new(PASSWORD, "SomeUserId").email("alice@example.com", true);
+///     identities.link("SomeUserId", &password).await?;
+///
+///     // The same person signs in with Google for the first time
+///     let google = IdentityClaim::new("google", "1049").email("Alice@example.com", true);
+///     let Resolution::Conflict(existing) = identities.resolve(&google).await? else {
+///         panic!("expected a conflict");
+///     };
+///     assert_eq!(existing[0].provider, PASSWORD);
+///
+///     // After they sign in with their password, Google is linked too
+///     identities.link("SomeUserId", &google).await?;
+///     assert!(matches!(identities.resolve(&google).await?, Resolution::Linked(_)));
+///
+///     // The last sign in method cannot be removed
+///     identities.unlink("SomeUserId", "google", "1049").await?;
+///     assert!(identities.unlink("SomeUserId", PASSWORD, "SomeUserId").await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Identities<S, A = NoAudit> {
+    store: S,
+    audit: A,
+}
+
+impl<S: IdentityStore> Identities<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            audit: NoAudit,
+        }
+    }
+}
+
+impl<S: IdentityStore, A: AuditSink> Identities<S, A> {
+    /// Record links, unlinks and merges to `audit`
+    pub fn audit<B: AuditSink>(self, audit: B) -> Identities<S, B> {
+        Identities {
+            store: self.store,
+            audit,
+        }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub async fn resolve(&self, claim: &IdentityClaim) -> Result<Resolution, AuthError> {
+        if let Some(identity) = self.store.find(&claim.provider, &claim.subject).await? {
+            return Ok(Resolution::Linked(identity));
+        }
+
+        let existing = match &claim.email {
+            Some(email) => self.store.find_by_email(email).await?,
+            None => Vec::new(),
+        };
+
+        if existing.is_empty() {
+            Ok(Resolution::New)
+        } else {
+            Ok(Resolution::Conflict(existing))
+        }
+    }
+
+    pub async fn list(&self, account: &str) -> Result<Vec<LinkedIdentity>, AuthError> {
+        self.store.list(account).await
+    }
+
+    /// Link an identity to an account the user has just signed in to
+    ///
+    /// Linking an identity the account already has returns it unchanged. Fails with
+    /// `AuthError::InvalidState` if another account has it; merge the accounts instead.
+    pub async fn link(
+        &self,
+        account: &str,
+        claim: &IdentityClaim,
+    ) -> Result<LinkedIdentity, AuthError> {
+        if let Some(identity) = self.store.find(&claim.provider, &claim.subject).await? {
+            if identity.account != account {
+                return Err(AuthError::InvalidState(
+                    "Identity is linked to another account".to_string(),
+                ));
+            }
+
+            return Ok(identity);
+        }
+
+        let identity = LinkedIdentity {
+            account: account.to_string(),
+            provider: claim.provider.clone(),
+            subject: claim.subject.clone(),
+            email: claim.email.clone(),
+            email_verified: claim.email_verified,
+            linked_at: now()?,
+        };
+        self.store.insert(identity.clone()).await?;
+
+        let event = AuditEvent::new(AuditAction::IdentityLinked)?
+            .account(account)
+            .detail(&identity.provider);
+        self.audit.record(event).await?;
+
+        Ok(identity)
+    }
+
+    /// Remove a sign in method, returning `false` if the account does not have it
+    ///
+    /// Fails with `AuthError::InvalidState` for the account's last one, which would lock the user
+    /// out.
+    pub async fn unlink(
+        &self,
+        account: &str,
+        provider: &str,
+        subject: &str,
+    ) -> Result<bool, AuthError> {
+        let identities = self.store.list(account).await?;
+        let linked = identities
+            .iter()
+            .any(|identity| identity.provider == provider && identity.subject == subject);
+        if !linked {
+            return Ok(false);
+        }
+        if identities.len() == 1 {
+            return Err(AuthError::InvalidState(
+                "Cannot unlink an account's last sign in method".to_string(),
+            ));
+        }
+
+        let removed = self.store.remove(account, provider, subject).await?;
+        if removed {
+            let event = AuditEvent::new(AuditAction::IdentityUnlinked)?
+                .account(account)
+                .detail(provider);
+            self.audit.record(event).await?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Move every sign in method of `from` onto `into`, returning how many moved
+    ///
+    /// Only merge after the user has signed in to both accounts in the same session, and move
+    /// or delete the rest of `from`'s data afterwards. Fails with `AuthError::InvalidState` when
+    /// both accounts have a password, since only one can remain; unlink one first.
+    pub async fn merge(&self, from: &str, into: &str) -> Result<usize, AuthError> {
+        if from == into {
+            return Err(AuthError::InvalidInput(
+                "Cannot merge an account into itself".to_string(),
+            ));
+        }
+
+        let has_password = |identities: &[LinkedIdentity]| {
+            identities
+                .iter()
+                .any(|identity| identity.provider == PASSWORD)
+        };
+        if has_password(&self.store.list(from).await?)
+            && has_password(&self.store.list(into).await?)
+        {
+            return Err(AuthError::InvalidState(
+                "Both accounts have a password; unlink one before merging".to_string(),
+            ));
+        }
+
+        let moved = self.store.reassign(from, into).await?;
+
+        let event = AuditEvent::new(AuditAction::AccountsMerged)?
+            .account(into)
+            .detail(from);
+        self.audit.record(event).await?;
+
+        Ok(moved)
+    }
+}
+
+/// Keeps linked identities in process memory
+#[derive(Debug, Default)]
+pub struct MemoryIdentityStore {
+    identities: Mutex<Vec<LinkedIdentity>>,
+}
+
+impl MemoryIdentityStore {
+    fn lock(&self) -> Result<MutexGuard<'_, Vec<LinkedIdentity>>, AuthError> {
+        self.identities
+            .lock()
+            .map_err(|_| AuthError::backend("Identity store lock poisoned"))
+    }
+}
+
+impl IdentityStore for MemoryIdentityStore {
+    async fn insert(&self, identity: LinkedIdentity) -> Result<(), AuthError> {
+        let mut identities = self.lock()?;
+
+        if identities.iter().any(|existing| {
+            existing.provider == identity.provider && existing.subject == identity.subject
+        }) {
+            return Err(AuthError::InvalidState(
+                "Identity is already linked".to_string(),
+            ));
+        }
+        identities.push(identity);
+
+        Ok(())
+    }
+
+    async fn find(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<LinkedIdentity>, AuthError> {
+        Ok(self
+            .lock()?
+            .iter()
+            .find(|identity| identity.provider == provider && identity.subject == subject)
+            .cloned())
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Vec<LinkedIdentity>, AuthError> {
+        let email = email.to_lowercase();
+
+        Ok(self
+            .lock()?
+            .iter()
+            .filter(|identity| identity.email.as_deref() == Some(email.as_str()))
+            .cloned()
+            .collect())
+    }
+
+    async fn list(&self, account: &str) -> Result<Vec<LinkedIdentity>, AuthError> {
+        Ok(self
+            .lock()?
+            .iter()
+            .filter(|identity| identity.account == account)
+            .cloned()
+            .collect())
+    }
+
+    async fn remove(
+        &self,
+        account: &str,
+        provider: &str,
+        subject: &str,
+    ) -> Result<bool, AuthError> {
+        let mut identities = self.lock()?;
+        let before = identities.len();
+        identities.retain(|identity| {
+            !(identity.account == account
+                && identity.provider == provider
+                && identity.subject == subject)
+        });
+
+        Ok(identities.len() != before)
+    }
+
+    async fn reassign(&self, from: &str, to: &str) -> Result<usize, AuthError> {
+        let mut moved = 0;
+        for identity in self.lock()?.iter_mut() {
+            if identity.account == from {
+                identity.account = to.to_string();
+                moved += 1;
+            }
+        }
+
+        Ok(moved)
+    }
+}
@@ -0,0 +1,271 @@
+use std::future::Future;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value as Json;
+
+use crate::{
+    clock::Clock,
+    token::{
+        jwt::JwtVerifier,
+        opaque::{OpaqueTokenStore, OpaqueTokens},
+        paseto::{PasetoLocal, PasetoVerifier},
+        Claims,
+    },
+    AuthError,
+};
+#[cfg(feature = "oauth")]
+use crate::{http::HttpClient, oauth::introspection::IntrospectionClient};
+
+use super::{bearer_token, Identity};
+
+/// Turns a bearer token into the identity it was issued to
+///
+/// Fails with `AuthError::Verification` for unknown, expired or forged tokens.
+pub trait TokenResolver {
+    fn resolve(&self, token: &str) -> impl Future<Output = Result<Identity, AuthError>> + Send;
+}
+
+/// Access token JWTs, checked against the verifier's keys and policy
+///
+/// The identity is the `sub` claim. Scopes come from a space separated `scope` claim or an `scp`
+/// array, roles from a `roles` array, and the token counts as MFA verified when its `amr` claim
+/// (RFC 8176) contains `mfa`. Every claim is kept in `attributes`.
+impl<C: Clock + Sync> TokenResolver for JwtVerifier<C> {
+    async fn resolve(&self, token: &str) -> Result<Identity, AuthError> {
+        identity(self.verify::<Json>(token)?)
+    }
+}
+
+/// Public PASETO tokens, with claims mapped to the identity as for JWTs
+impl TokenResolver for PasetoVerifier {
+    async fn resolve(&self, token: &str) -> Result<Identity, AuthError> {
+        identity(self.verify::<Json>(token)?)
+    }
+}
+
+/// Local PASETO tokens, with claims mapped to the identity as for JWTs
+impl TokenResolver for PasetoLocal {
+    async fn resolve(&self, token: &str) -> Result<Identity, AuthError> {
+        identity(self.decrypt::<Json>(token)?)
+    }
+}
+
+/// Opaque tokens, looked up by hash in their store
+impl<S: OpaqueTokenStore + Sync> TokenResolver for OpaqueTokens<S> {
+    async fn resolve(&self, token: &str) -> Result<Identity, AuthError> {
+        let stored = self
+            .lookup(token)
+            .await?
+            .ok_or_else(|| AuthError::Verification("Token is invalid or expired".to_string()))?;
+
+        Ok(Identity::new(stored.subject))
+    }
+}
+
+/// Opaque tokens issued by another authorization server, checked at its introspection endpoint
+///
+/// The identity is the `sub` member, or `username` when there is none. Scopes come from
+/// `scope`, and members outside RFC 7662 are kept in `attributes`.
+#[cfg(feature = "oauth")]
+impl<C: HttpClient + Sync> TokenResolver for IntrospectionClient<C> {
+    async fn resolve(&self, token: &str) -> Result<Identity, AuthError> {
+        let introspection = self
+            .verify(token)
+            .await?
+            .ok_or_else(|| AuthError::Verification("Token is invalid or expired".to_string()))?;
+        let subject = introspection
+            .subject
+            .clone()
+            .or_else(|| introspection.username.clone())
+            .ok_or_else(|| AuthError::Verification("Token has no subject".to_string()))?;
+
+        let mut identity = Identity::new(subject);
+        identity.scopes = introspection.scopes().map(str::to_string).collect();
+        identity.attributes = Json::Object(introspection.extra);
+
+        Ok(identity)
+    }
+}
+
+/// Authenticates requests by their `Authorization: Bearer` header
+///
+/// This is the framework independent part of a bearer token middleware, such as a tower `Layer`:
+/// call `authenticate` with the request's `Authorization` header and store the resulting identity
+/// in the request's extensions, or answer with `status_code` of the error.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::integrations::{bearer::BearerAuth, status_code};
+/// use lonewolf_auth_toolkit::token::jwt::{JwtSigner, JwtVerifier, SigningKey, VerifyingKey};
+/// use lonewolf_auth_toolkit::token::Claims;
+/// use serde_json::json;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let secret = b"an example secret of at least 32 bytes";
+///     let signer = JwtSigner::new(SigningKey::hs256(secret)?);
+///     let auth = BearerAuth::new(JwtVerifier::new().key(None, VerifyingKey::hs256(secret)?));
+///
+///     let custom = json!({ "scope": "reports:read reports:export", "amr": ["pwd", "mfa"] });
+///     let claims = Claims::new(custom, Duration::from_secs(900))?.subject("SomeAccountName");
+///     let header = format!("Bearer {}", signer.sign(&claims)?);
+///
+///     let identity = auth.authenticate(Some(&header)).await?;
+///     assert_eq!(identity.subject, "SomeAccountName");
+///     assert!(identity.has_scope("reports:export"));
+///     assert!(identity.mfa_verified);
+///
+///     let missing = auth.authenticate(None).await.unwrap_err();
+///     assert_eq!(status_code(&missing), 401);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct BearerAuth<R> {
+    resolver: R,
+}
+
+impl<R: TokenResolver> BearerAuth<R> {
+    pub fn new(resolver: R) -> Self {
+        Self { resolver }
+    }
+
+    pub fn resolver(&self) -> &R {
+        &self.resolver
+    }
+
+    /// Resolve the caller from an `Authorization` header; fails with `AuthError::Verification`
+    /// when it is missing or its token is not accepted
+    pub async fn authenticate(&self, authorization: Option<&str>) -> Result<Identity, AuthError> {
+        let token = authorization
+            .and_then(bearer_token)
+            .ok_or_else(|| AuthError::Verification("Missing bearer token".to_string()))?;
+
+        self.resolver.resolve(token).await
+    }
+
+    /// Like `authenticate`, but `None` without an `Authorization` header, so a request guard can
+    /// forward anonymous requests with `GuardOutcome`
+    pub async fn try_authenticate(
+        &self,
+        authorization: Option<&str>,
+    ) -> Result<Option<Identity>, AuthError> {
+        match authorization {
+            Some(_) => self.authenticate(authorization).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Like `authenticate`, also deserializing the token's claims into `T`
+    ///
+    /// Claims that do not fit `T`, such as a missing `tenant` field, fail with
+    /// `AuthError::Verification` like a bad token. Use `Authenticated::require` for checks on
+    /// the claims that should answer `403` instead.
+    pub async fn authenticate_as<T: DeserializeOwned>(
+        &self,
+        authorization: Option<&str>,
+    ) -> Result<Authenticated<T>, AuthError> {
+        let identity = self.authenticate(authorization).await?;
+
+        Ok(Authenticated {
+            claims: identity.claims()?,
+            identity,
+        })
+    }
+}
+
+/// The caller of a request together with their token's claims as the application's own type
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::integrations::{bearer::BearerAuth, status_code};
+/// use lonewolf_auth_toolkit::token::jwt::{JwtSigner, JwtVerifier, SigningKey, VerifyingKey};
+/// use lonewolf_auth_toolkit::token::Claims;
+/// use serde::Deserialize;
+/// use serde_json::json;
+///
+/// #[derive(Debug, Deserialize)]
+/// struct ApiClaims {
+///     tenant: String,
+///     plan: String,
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let secret = b"an example secret of at least 32 bytes";
+///     let signer = JwtSigner::new(SigningKey::hs256(secret)?);
+///     let auth = BearerAuth::new(JwtVerifier::new().key(None, VerifyingKey::hs256(secret)?));
+///     let bearer = |custom| -> Result<String, anyhow::Error> {
+///         let claims = Claims::new(custom, Duration::from_secs(900))?.subject("SomeAccountName");
+///         Ok(format!("Bearer {}", signer.sign(&claims)?))
+///     };
+///
+///     let header = bearer(json!({ "tenant": "acme", "plan": "free" }))?;
+///     let caller = auth.authenticate_as::<ApiClaims>(Some(&header)).await?;
+///     assert_eq!(caller.identity.subject, "SomeAccountName");
+///     assert_eq!(caller.claims.tenant, "acme");
+///
+///     // A valid token for the wrong plan is forbidden
+///     let upgrade = caller.require(|claims| claims.plan == "pro", "Requires the pro plan");
+///     assert_eq!(status_code(&upgrade.unwrap_err()), 403);
+///
+///     // A token without the application's claims is rejected like a bad token
+///     let header = bearer(json!({ "scope": "reports:read" }))?;
+///     let rejected = auth.authenticate_as::<ApiClaims>(Some(&header)).await;
+///     assert_eq!(status_code(&rejected.unwrap_err()), 401);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Authenticated<T> {
+    pub identity: Identity,
+    pub claims: T,
+}
+
+impl<T> Authenticated<T> {
+    /// Fail with `AuthError::InvalidState`, a `403`, unless `allowed` accepts the claims
+    pub fn require(&self, allowed: impl FnOnce(&T) -> bool, reason: &str) -> Result<(), AuthError> {
+        if allowed(&self.claims) {
+            Ok(())
+        } else {
+            Err(AuthError::InvalidState(reason.to_string()))
+        }
+    }
+}
+
+fn identity(claims: Claims<Json>) -> Result<Identity, AuthError> {
+    let subject = claims
+        .subject
+        .ok_or_else(|| AuthError::Verification("Token has no subject".to_string()))?;
+    let custom = claims.custom;
+
+    let mut identity = Identity::new(subject);
+    identity.scopes = match (&custom["scope"], &custom["scp"]) {
+        (Json::String(scope), _) => scope.split_whitespace().map(str::to_string).collect(),
+        (_, Json::Array(_)) => strings(&custom["scp"]),
+        _ => Vec::new(),
+    };
+    identity.roles = strings(&custom["roles"]);
+    identity.mfa_verified = strings(&custom["amr"]).iter().any(|method| method == "mfa");
+    identity.attributes = custom;
+
+    Ok(identity)
+}
+
+fn strings(value: &Json) -> Vec<String> {
+    value
+        .as_array()
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(Json::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,337 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+
+use crate::{
+    account::{CredentialStore, UserStore},
+    audit::{AuditSink, NoAudit},
+    rate_limit::lockout::LockoutStore,
+    service::{AuthService, LoginOutcome},
+    session::{
+        cookie::{self, Cookie, SameSite},
+        csrf::Csrf,
+        Session, SessionStore,
+    },
+    AuthError,
+};
+
+use super::{session::MFA_SESSION_KEY, status_code};
+
+/// Name of the session cookie unless another is set
+pub const DEFAULT_SESSION_COOKIE: &str = "__Host-session";
+
+/// Name of the cookie holding a pending MFA challenge
+pub const DEFAULT_CHALLENGE_COOKIE: &str = "__Host-mfa-challenge";
+
+/// What the browser sent, as far as the endpoints need it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BffRequest<'a> {
+    /// The `Cookie` header
+    pub cookie: Option<&'a str>,
+    /// The `X-CSRF-Token` header
+    pub csrf_token: Option<&'a str>,
+    /// The JSON body
+    pub body: &'a [u8],
+}
+
+/// What to answer with: the status, a `Set-Cookie` header per cookie and a JSON body
+#[derive(Debug, Clone, PartialEq)]
+pub struct BffResponse {
+    pub status: u16,
+    pub cookies: Vec<Cookie>,
+    pub body: Json,
+}
+
+impl BffResponse {
+    fn ok(body: Json) -> Self {
+        Self {
+            status: 200,
+            cookies: Vec::new(),
+            body,
+        }
+    }
+
+    fn cookie(mut self, cookie: Cookie) -> Self {
+        self.cookies.push(cookie);
+        self
+    }
+
+    /// The error as `{"kind", "message"}` with its `status_code`, plus `retry_after` in seconds
+    /// when rate limited; server errors only say "Internal error"
+    pub fn error(error: &AuthError) -> Self {
+        let status = status_code(error);
+        let message = if status >= 500 {
+            "Internal error".to_string()
+        } else {
+            error.to_string()
+        };
+
+        let mut body = json!({ "kind": error.kind(), "message": message });
+        if let AuthError::RateLimited(limited) = error {
+            body["retry_after"] = json!(limited.retry_after.as_secs());
+        }
+
+        Self {
+            status,
+            cookies: Vec::new(),
+            body,
+        }
+    }
+}
+
+impl From<Result<BffResponse, AuthError>> for BffResponse {
+    fn from(result: Result<BffResponse, AuthError>) -> Self {
+        result.unwrap_or_else(|error| Self::error(&error))
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginBody {
+    identifier: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+struct MfaBody {
+    code: String,
+}
+
+/// Backend for frontend endpoints for single page apps: login, MFA, refresh and logout
+///
+/// Each method is the body of one JSON endpoint; mount them in any framework, e.g. as
+/// `POST /auth/login`, `/auth/mfa`, `/auth/refresh` and `/auth/logout`, and turn the
+/// `BffResponse` into the framework's response. The browser never sees a token: the session id
+/// lives in an `HttpOnly`, `Secure`, `SameSite=Strict` cookie, and so does a pending MFA
+/// challenge. Signed in responses also set a double submit CSRF cookie and return its token as
+/// `csrf_token`, for the app to send in the `X-CSRF-Token` header of every later request;
+/// `refresh` and `logout` check it, and so should the app's own endpoints, with `Csrf::check`.
+///
+/// Sessions are signed in as `SessionAuth` expects, including the MFA flag, so the API behind
+/// the BFF can authenticate requests with the same cookie. Serve the SPA and these endpoints
+/// from one origin and reject cross origin requests to them.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::account::{Accounts, MemoryCredentialStore, MemoryUserStore};
+/// use lonewolf_auth_toolkit::integrations::bff::{Bff, BffRequest};
+/// use lonewolf_auth_toolkit::password::argon2::Argon2Params;
+/// use lonewolf_auth_toolkit::rate_limit::lockout::{Lockout, MemoryLockoutStore};
+/// use lonewolf_auth_toolkit::service::AuthService;
+/// use lonewolf_auth_toolkit::session::csrf::Csrf;
+/// use lonewolf_auth_toolkit::session::{MemorySessionStore, SessionManager};
+/// use serde_json::json;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let accounts = Accounts::new(MemoryUserStore::default(), MemoryCredentialStore::default())
+///         .params(Argon2Params::default().memory_kib(1024).iterations(1));
+///     let service = AuthService::new(
+///         accounts,
+///         SessionManager::new(MemorySessionStore::default()),
+///         Lockout::new(MemoryLockoutStore::default()),
+///     );
+///     let bff = Bff::new(service, Csrf::new(&[7u8; 32])?);
+///     let password = "correct horse battery staple";
+///     bff.service().accounts().register("someone@example.com", None, password).await?;
+///
+///     let body = json!({ "identifier": "someone@example.com", "password": "Tr0ub4dor&3" });
+///     let body = body.to_string();
+///     let request = BffRequest { body: body.as_bytes(), ..Default::default() };
+///     assert_eq!(bff.login(&request).await.status, 401);
+///
+///     let body = json!({ "identifier": "someone@example.com", "password": password });
+///     let body = body.to_string();
+///     let response = bff.login(&BffRequest { body: body.as_bytes(), ..Default::default() }).await;
+///     assert_eq!(response.status, 200);
+///     assert_eq!(response.body["status"], "signed_in");
+///
+///     // The browser sends the cookies back, and the app the CSRF token from the body
+///     let cookies: Vec<String> = response
+///         .cookies
+///         .iter()
+///         .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+///         .collect();
+///     let cookie = cookies.join("; ");
+///     let csrf_token = response.body["csrf_token"].as_str();
+///
+///     let request = BffRequest { cookie: Some(&cookie), csrf_token, body: b"" };
+///     assert_eq!(bff.refresh(&request).await.status, 200);
+///
+///     let forged = BffRequest { cookie: Some(&cookie), csrf_token: None, body: b"" };
+///     assert_eq!(bff.logout(&forged).await.status, 403);
+///
+///     let response = bff.logout(&request).await;
+///     assert_eq!(response.status, 200);
+///     assert!(response.cookies.iter().all(|cookie| cookie.to_string().contains("Max-Age=0")));
+///     assert_eq!(bff.refresh(&request).await.status, 401);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Bff<U, C, S, L, A = NoAudit> {
+    service: AuthService<U, C, S, L, A>,
+    csrf: Csrf,
+    session_cookie: String,
+    challenge_cookie: String,
+}
+
+impl<U, C, S, L, A> Bff<U, C, S, L, A>
+where
+    U: UserStore,
+    C: CredentialStore,
+    S: SessionStore,
+    L: LockoutStore,
+    A: AuditSink,
+{
+    pub fn new(service: AuthService<U, C, S, L, A>, csrf: Csrf) -> Self {
+        Self {
+            service,
+            csrf,
+            session_cookie: DEFAULT_SESSION_COOKIE.to_string(),
+            challenge_cookie: DEFAULT_CHALLENGE_COOKIE.to_string(),
+        }
+    }
+
+    pub fn session_cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.session_cookie = name.into();
+        self
+    }
+
+    pub fn challenge_cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.challenge_cookie = name.into();
+        self
+    }
+
+    pub fn service(&self) -> &AuthService<U, C, S, L, A> {
+        &self.service
+    }
+
+    /// `{"identifier", "password"}`: signs in, or sets the challenge cookie and answers
+    /// `{"status": "mfa_required"}` for `complete_mfa`
+    pub async fn login(&self, request: &BffRequest<'_>) -> BffResponse {
+        self.try_login(request).await.into()
+    }
+
+    /// `{"code"}`: completes the challenge from `login` with a TOTP code and signs in
+    pub async fn complete_mfa(&self, request: &BffRequest<'_>) -> BffResponse {
+        self.try_complete_mfa(request).await.into()
+    }
+
+    /// Keeps the session alive, re-issuing its cookies and CSRF token, and says who is signed in
+    ///
+    /// Answers `401` once the session has expired, for the app to show its sign in page.
+    pub async fn refresh(&self, request: &BffRequest<'_>) -> BffResponse {
+        self.try_refresh(request).await.into()
+    }
+
+    /// Ends the session and clears the cookies
+    pub async fn logout(&self, request: &BffRequest<'_>) -> BffResponse {
+        self.try_logout(request).await.into()
+    }
+
+    async fn try_login(&self, request: &BffRequest<'_>) -> Result<BffResponse, AuthError> {
+        let body: LoginBody = parse(request.body)?;
+
+        match self.service.login(&body.identifier, &body.password).await? {
+            LoginOutcome::SignedIn(session) => self.signed_in(&session),
+            LoginOutcome::MfaRequired(challenge) => {
+                let now = self.service.sessions().now()?;
+                let max_age = Duration::from_secs(challenge.expires_at.saturating_sub(now));
+                let cookie =
+                    strict(Cookie::new(&self.challenge_cookie, &challenge.id)?).max_age(max_age);
+
+                Ok(BffResponse::ok(json!({
+                    "status": "mfa_required",
+                    "expires_at": challenge.expires_at,
+                }))
+                .cookie(cookie))
+            }
+        }
+    }
+
+    async fn try_complete_mfa(&self, request: &BffRequest<'_>) -> Result<BffResponse, AuthError> {
+        let body: MfaBody = parse(request.body)?;
+        let challenge = request
+            .cookie
+            .and_then(|header| cookie::find(header, &self.challenge_cookie))
+            .ok_or_else(|| AuthError::Verification("No MFA challenge is pending".to_string()))?;
+
+        let mut session = self.service.complete_mfa(challenge, &body.code).await?;
+        let sessions = self.service.sessions();
+        session.insert(MFA_SESSION_KEY, sessions.now()?)?;
+        sessions.save(&mut session).await?;
+
+        let cleared = strict(Cookie::new(&self.challenge_cookie, "")?).removal();
+
+        Ok(self.signed_in(&session)?.cookie(cleared))
+    }
+
+    async fn try_refresh(&self, request: &BffRequest<'_>) -> Result<BffResponse, AuthError> {
+        let mut session = self
+            .load(request)
+            .await?
+            .filter(|session| session.account().is_some())
+            .ok_or_else(|| AuthError::Verification("Not signed in".to_string()))?;
+        self.csrf
+            .check("POST", &session, request.csrf_token, request.cookie)?;
+
+        self.service.sessions().touch(&mut session).await?;
+
+        self.signed_in(&session)
+    }
+
+    async fn try_logout(&self, request: &BffRequest<'_>) -> Result<BffResponse, AuthError> {
+        let mut response = BffResponse::ok(json!({ "status": "signed_out" }))
+            .cookie(strict(Cookie::new(&self.session_cookie, "")?).removal());
+
+        if let Some(session) = self.load(request).await? {
+            self.csrf
+                .check("POST", &session, request.csrf_token, request.cookie)?;
+            self.service.logout(session.id()).await?;
+
+            let (_, csrf_cookie) = self.csrf.cookie(&session)?;
+            response = response.cookie(csrf_cookie.removal());
+        }
+
+        Ok(response)
+    }
+
+    async fn load(&self, request: &BffRequest<'_>) -> Result<Option<Session>, AuthError> {
+        match request
+            .cookie
+            .and_then(|header| cookie::find(header, &self.session_cookie))
+        {
+            Some(id) => self.service.sessions().load(id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// The session and CSRF cookies and the body describing a signed in session
+    fn signed_in(&self, session: &Session) -> Result<BffResponse, AuthError> {
+        let now = self.service.sessions().now()?;
+        let max_age = Duration::from_secs(session.expires_at().saturating_sub(now));
+        let session_cookie =
+            strict(Cookie::new(&self.session_cookie, session.id())?).max_age(max_age);
+        let (csrf_token, csrf_cookie) = self.csrf.cookie(session)?;
+
+        Ok(BffResponse::ok(json!({
+            "status": "signed_in",
+            "account": session.account(),
+            "mfa_verified": session.get::<u64>(MFA_SESSION_KEY)?.is_some(),
+            "expires_at": session.expires_at(),
+            "csrf_token": csrf_token,
+        }))
+        .cookie(session_cookie)
+        .cookie(csrf_cookie.max_age(max_age)))
+    }
+}
+
+fn strict(cookie: Cookie) -> Cookie {
+    cookie.same_site(SameSite::Strict)
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(body: &[u8]) -> Result<T, AuthError> {
+    serde_json::from_slice(body)
+        .map_err(|error| AuthError::InvalidInput(format!("Invalid request body: {}", error)))
+}
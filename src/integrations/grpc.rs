@@ -0,0 +1,171 @@
+use serde_json::json;
+
+use crate::{
+    mtls::{
+        certificate::Certificate, ClientCertVerifier, IdentityMap, NoRevocationCheck,
+        RevocationCheck,
+    },
+    AuthError,
+};
+
+use super::{bearer::TokenResolver, bearer_token, Identity};
+
+/// The metadata key gRPC clients send bearer tokens under
+pub const AUTHORIZATION_METADATA: &str = "authorization";
+
+/// The gRPC status code a server should answer with when a call is rejected, as `tonic::Code`
+///
+/// Failed credentials are `UNAUTHENTICATED` (16), a caller lacking MFA, a role or a scope is
+/// `PERMISSION_DENIED` (7), and an exhausted rate limit is `RESOURCE_EXHAUSTED` (8). Store and
+/// clock failures are `INTERNAL` (13) and their message should not be shown to the caller.
+pub fn grpc_code(error: &AuthError) -> i32 {
+    match error {
+        AuthError::InvalidInput(_) | AuthError::Malformed(_) => 3,
+        AuthError::Verification(_) => 16,
+        AuthError::InvalidState(_) => 7,
+        AuthError::NotFound(_) => 5,
+        AuthError::RateLimited(_) => 8,
+        AuthError::MalformedSecret(_) | AuthError::Clock(_) | AuthError::Backend(_) => 13,
+    }
+}
+
+/// Rejects every bearer token, for services that only accept mTLS
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoBearer;
+
+impl TokenResolver for NoBearer {
+    async fn resolve(&self, _token: &str) -> Result<Identity, AuthError> {
+        Err(AuthError::Verification(
+            "Bearer tokens are not accepted".to_string(),
+        ))
+    }
+}
+
+/// Authenticates incoming gRPC calls by bearer token or client certificate
+///
+/// This is the framework independent part of a tonic interceptor, mirroring `BearerAuth` for
+/// HTTP: call `authenticate` with the `authorization` metadata value and the peer's certificate
+/// chain (`Request::peer_certs` in tonic), then insert the identity into the request's extensions,
+/// or answer with a status of `grpc_code` of the error. Resolvers are async, so run it from a
+/// tower layer around the service rather than tonic's synchronous `Interceptor`. The crate does
+/// not depend on tonic, so that layer is the application's.
+///
+/// A call with an `authorization` entry is authenticated by its token, so a user's token wins
+/// over the certificate of the service relaying it. Otherwise the certificate is verified and
+/// mapped to a service identity, whose `attributes` hold the certificate's `fingerprint`.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::integrations::grpc::{grpc_code, GrpcAuth};
+/// use lonewolf_auth_toolkit::mtls::{certificate::Certificate, ClientCertVerifier};
+/// use lonewolf_auth_toolkit::mtls::{Identifier, IdentityMap};
+/// use lonewolf_auth_toolkit::token::jwt::{JwtSigner, JwtVerifier, SigningKey, VerifyingKey};
+/// use lonewolf_auth_toolkit::token::Claims;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let secret = b"an example secret of at least 32 bytes";
+///     let signer = JwtSigner::new(SigningKey::hs256(secret)?);
+///     let billing = Identifier::Uri("spiffe://example.com/ns/prod/sa/billing".into());
+///
+///     let auth = GrpcAuth::new()
+///         .bearer(JwtVerifier::new().key(None, VerifyingKey::hs256(secret)?))
+///         .mtls(
+///             ClientCertVerifier::new(include_str!("../mtls/testdata/ca.pem"))?,
+///             IdentityMap::new().map(billing, "billing"),
+///         );
+///
+///     let claims = Claims::new((), Duration::from_secs(900))?.subject("SomeAccountName");
+///     let metadata = format!("Bearer {}", signer.sign(&claims)?);
+///     let caller = auth.authenticate(Some(&metadata), None).await?;
+///     assert_eq!(caller.subject, "SomeAccountName");
+///
+///     let chain = Certificate::parse_pem(include_str!("../mtls/testdata/chain.pem"))?;
+///     let service = auth.authenticate(None, Some(&chain)).await?;
+///     assert_eq!(service.subject, "billing");
+///
+///     let anonymous = auth.authenticate(None, None).await.unwrap_err();
+///     assert_eq!(grpc_code(&anonymous), 16);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct GrpcAuth<R = NoBearer, V = NoRevocationCheck> {
+    resolver: R,
+    mtls: Option<(ClientCertVerifier<V>, IdentityMap)>,
+}
+
+impl GrpcAuth {
+    /// Accept neither tokens nor certificates until `bearer` or `mtls` is configured
+    pub fn new() -> Self {
+        Self {
+            resolver: NoBearer,
+            mtls: None,
+        }
+    }
+}
+
+impl Default for GrpcAuth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: TokenResolver, V: RevocationCheck> GrpcAuth<R, V> {
+    /// Accept bearer tokens the resolver accepts
+    pub fn bearer<T: TokenResolver>(self, resolver: T) -> GrpcAuth<T, V> {
+        GrpcAuth {
+            resolver,
+            mtls: self.mtls,
+        }
+    }
+
+    /// Accept client certificates the verifier accepts and `identities` maps
+    pub fn mtls<W: RevocationCheck>(
+        self,
+        verifier: ClientCertVerifier<W>,
+        identities: IdentityMap,
+    ) -> GrpcAuth<R, W> {
+        GrpcAuth {
+            resolver: self.resolver,
+            mtls: Some((verifier, identities)),
+        }
+    }
+
+    pub fn resolver(&self) -> &R {
+        &self.resolver
+    }
+
+    /// Resolve the caller from the `authorization` metadata or the peer's certificate chain
+    ///
+    /// Fails with `AuthError::Verification` when neither is present or accepted.
+    pub async fn authenticate(
+        &self,
+        authorization: Option<&str>,
+        peer_certificates: Option<&[Certificate]>,
+    ) -> Result<Identity, AuthError> {
+        if let Some(authorization) = authorization {
+            let token = bearer_token(authorization)
+                .ok_or_else(|| AuthError::Verification("Missing bearer token".to_string()))?;
+
+            return self.resolver.resolve(token).await;
+        }
+
+        let (chain, (verifier, identities)) = match (peer_certificates, &self.mtls) {
+            (Some(chain), Some(mtls)) if !chain.is_empty() => (chain, mtls),
+            _ => return Err(AuthError::Verification("Missing credentials".to_string())),
+        };
+
+        verifier.verify(chain).await?;
+        let subject = identities.identify(&chain[0]).ok_or_else(|| {
+            AuthError::Verification("Client certificate has no known identity".to_string())
+        })?;
+
+        let mut identity = Identity::new(subject);
+        identity.attributes = json!({ "fingerprint": chain[0].fingerprint() });
+
+        Ok(identity)
+    }
+}
@@ -0,0 +1,182 @@
+use std::{fmt, marker::PhantomData, sync::Arc};
+
+use crate::{authz::rbac::Rbac, AuthError};
+
+use super::Identity;
+
+/// A requirement a route places on the caller's identity
+///
+/// Framework guards and middleware run `check` against the resolved `Identity`. Failures are
+/// `AuthError::InvalidState`, which `status_code` maps to `403`: the caller is known but not
+/// allowed.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::authz::rbac::{Rbac, Role, RoleDefinitions};
+/// use lonewolf_auth_toolkit::integrations::{guard::Guard, status_code, Identity};
+///
+/// let rbac = Arc::new(Rbac::new(
+///     RoleDefinitions::new()
+///         .role("viewer", Role::new().permission("reports:read"))
+///         .role("admin", Role::new().permission("*").inherits("viewer")),
+/// )?);
+///
+/// // Admins with MFA, or any token granted the reports:read scope
+/// let guard = Guard::any([
+///     Guard::all([Guard::Mfa, Guard::permission(rbac.clone(), "reports:export")]),
+///     Guard::scope("reports:read"),
+/// ]);
+///
+/// let mut admin = Identity::new("SomeAccountName");
+/// admin.roles.push("admin".to_string());
+/// assert_eq!(status_code(&guard.check(&admin).unwrap_err()), 403);
+///
+/// admin.mfa_verified = true;
+/// guard.check(&admin)?;
+///
+/// let mut service = Identity::new("SomeServiceAccount");
+/// service.scopes.push("reports:read".to_string());
+/// assert!(guard.allows(&service));
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub enum Guard {
+    /// A second factor was verified
+    Mfa,
+    /// The caller holds the role itself, without considering inheritance
+    Role(String),
+    /// The caller's token was granted the scope
+    Scope(String),
+    /// One of the caller's roles grants the permission under the definitions
+    Permission(Arc<Rbac>, String),
+    All(Vec<Guard>),
+    Any(Vec<Guard>),
+}
+
+impl Guard {
+    pub fn role(role: impl Into<String>) -> Self {
+        Self::Role(role.into())
+    }
+
+    pub fn scope(scope: impl Into<String>) -> Self {
+        Self::Scope(scope.into())
+    }
+
+    pub fn permission(rbac: Arc<Rbac>, permission: impl Into<String>) -> Self {
+        Self::Permission(rbac, permission.into())
+    }
+
+    pub fn all(guards: impl IntoIterator<Item = Guard>) -> Self {
+        Self::All(guards.into_iter().collect())
+    }
+
+    pub fn any(guards: impl IntoIterator<Item = Guard>) -> Self {
+        Self::Any(guards.into_iter().collect())
+    }
+
+    pub fn allows(&self, identity: &Identity) -> bool {
+        self.check(identity).is_ok()
+    }
+
+    /// Fail with `AuthError::InvalidState` describing the unmet requirement
+    pub fn check(&self, identity: &Identity) -> Result<(), AuthError> {
+        let forbidden = |message: String| Err(AuthError::InvalidState(message));
+
+        match self {
+            Self::Mfa => identity.require_mfa(),
+            Self::Role(role) if identity.has_role(role) => Ok(()),
+            Self::Role(role) => forbidden(format!("Missing role {}", role)),
+            Self::Scope(scope) if identity.has_scope(scope) => Ok(()),
+            Self::Scope(scope) => forbidden(format!("Missing scope {}", scope)),
+            Self::Permission(rbac, permission) if rbac.can(&identity.roles, permission) => Ok(()),
+            Self::Permission(_, permission) => {
+                forbidden(format!("Missing permission {}", permission))
+            }
+            Self::All(guards) => guards.iter().try_for_each(|guard| guard.check(identity)),
+            Self::Any(guards) => {
+                let mut last = forbidden("No requirement is met".to_string());
+
+                for guard in guards {
+                    match guard.check(identity) {
+                        Ok(()) => return Ok(()),
+                        Err(error) => last = Err(error),
+                    }
+                }
+
+                last
+            }
+        }
+    }
+}
+
+/// A guard named by a type, for frameworks whose request guards are types
+///
+/// Rocket's `FromRequest` and Axum's extractors pick what to check from the handler's argument
+/// types, so `Authorized<Admin>` in a signature can require the `admin` role with no further
+/// code in the handler. This crate does not depend on either framework, so the extractor or
+/// guard impl calling `Authorized::check` is left to the application.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::integrations::guard::{Authorized, Guard, Requirement};
+/// use lonewolf_auth_toolkit::integrations::{status_code, Identity};
+///
+/// struct Admin;
+///
+/// impl Requirement for Admin {
+///     fn guard() -> Guard {
+///         Guard::all([Guard::Mfa, Guard::role("admin")])
+///     }
+/// }
+///
+/// let mut caller = Identity::new("SomeAccountName");
+/// caller.roles.push("admin".to_string());
+/// let forbidden = Authorized::<Admin>::check(caller.clone()).unwrap_err();
+/// assert_eq!(status_code(&forbidden), 403);
+///
+/// caller.mfa_verified = true;
+/// let admin = Authorized::<Admin>::check(caller)?;
+/// assert_eq!(admin.identity.subject, "SomeAccountName");
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+pub trait Requirement {
+    fn guard() -> Guard;
+}
+
+/// An identity that met `R`'s guard
+pub struct Authorized<R> {
+    pub identity: Identity,
+    requirement: PhantomData<fn() -> R>,
+}
+
+impl<R: Requirement> Authorized<R> {
+    /// Fail with `AuthError::InvalidState` unless the identity meets `R`'s guard
+    pub fn check(identity: Identity) -> Result<Self, AuthError> {
+        R::guard().check(&identity)?;
+
+        Ok(Self {
+            identity,
+            requirement: PhantomData,
+        })
+    }
+}
+
+// Derives would needlessly require `R` itself to be `Clone` and `Debug`
+impl<R> Clone for Authorized<R> {
+    fn clone(&self) -> Self {
+        Self {
+            identity: self.identity.clone(),
+            requirement: PhantomData,
+        }
+    }
+}
+
+impl<R> fmt::Debug for Authorized<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Authorized")
+            .field("identity", &self.identity)
+            .finish()
+    }
+}
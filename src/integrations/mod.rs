@@ -0,0 +1,151 @@
+#[cfg(all(feature = "mfa", feature = "password"))]
+pub mod bff;
+pub mod bearer;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod guard;
+pub mod session;
+pub mod step_up;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value as Json;
+
+use crate::AuthError;
+
+/// The authenticated caller of a request, for handlers and guards
+///
+/// Built by an authenticator such as `session::SessionAuth` from the request's cookies or
+/// headers. `attributes` carries anything else the application needs, such as token claims.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub subject: String,
+    /// Whether a second factor was verified for this sign in
+    pub mfa_verified: bool,
+    pub roles: Vec<String>,
+    pub scopes: Vec<String>,
+    pub attributes: Json,
+}
+
+impl Identity {
+    pub fn new(subject: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            mfa_verified: false,
+            roles: Vec::new(),
+            scopes: Vec::new(),
+            attributes: Json::Null,
+        }
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|held| held == role)
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+
+    /// The acting party's `sub` when the token has an `act` claim, e.g. an impersonating admin
+    pub fn impersonator(&self) -> Option<&str> {
+        self.attributes.get("act")?.get("sub")?.as_str()
+    }
+
+    /// The application's own claims, deserialized from `attributes`
+    ///
+    /// For tokens these are the claims other than `iss`, `sub`, `aud`, `exp`, `nbf`, `iat` and
+    /// `jti`. Fails with `AuthError::Verification`, a `401`, if they do not fit `T`.
+    pub fn claims<T: DeserializeOwned>(&self) -> Result<T, AuthError> {
+        serde_json::from_value(self.attributes.clone()).map_err(|error| {
+            AuthError::Verification(format!("Token claims are invalid: {}", error))
+        })
+    }
+
+    /// Fail with `AuthError::InvalidState` unless a second factor was verified
+    pub fn require_mfa(&self) -> Result<(), AuthError> {
+        if self.mfa_verified {
+            Ok(())
+        } else {
+            Err(AuthError::InvalidState(
+                "Multi factor authentication is required".to_string(),
+            ))
+        }
+    }
+}
+
+/// The token in an `Authorization: Bearer <token>` header
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::integrations::bearer_token;
+///
+/// assert_eq!(bearer_token("Bearer abc.def.ghi"), Some("abc.def.ghi"));
+/// assert_eq!(bearer_token("bearer abc.def.ghi"), Some("abc.def.ghi"));
+/// assert_eq!(bearer_token("Basic dXNlcjpwYXNz"), None);
+/// ```
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    let token = token.trim();
+
+    (scheme.eq_ignore_ascii_case("Bearer") && !token.is_empty()).then_some(token)
+}
+
+/// The HTTP status a web framework should answer with when a request is rejected
+///
+/// Failed credentials are `401`, a signed in caller lacking MFA, a role or a scope is `403`, and
+/// an exhausted rate limit is `429`; add `Retry-After` from `RateLimited::retry_after`. Store and
+/// clock failures are `500` and should not be shown to the caller.
+pub fn status_code(error: &AuthError) -> u16 {
+    match error {
+        AuthError::InvalidInput(_) | AuthError::Malformed(_) => 400,
+        AuthError::Verification(_) => 401,
+        AuthError::InvalidState(_) => 403,
+        AuthError::NotFound(_) => 404,
+        AuthError::RateLimited(_) => 429,
+        AuthError::MalformedSecret(_) | AuthError::Clock(_) | AuthError::Backend(_) => 500,
+    }
+}
+
+/// How a request guard resolves: succeed, forward to the next route or fail with a status
+///
+/// Requests without credentials are forwarded, so a later route such as a sign in redirect can
+/// handle them; credentials that were presented but rejected fail with the status from
+/// `status_code`. The variants map one to one onto the `Outcome` of Rocket's request guards.
+///
+/// There is no `rocket` feature and no `FromRequest` impl: Rocket could not be added as a
+/// dependency when this was written, so applications write the few lines of `FromRequest`
+/// themselves, resolving the identity with e.g. `SessionAuth` or `BearerAuth` and converting
+/// this outcome.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::integrations::{GuardOutcome, Identity};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// let signed_in: Result<Option<Identity>, AuthError> = Ok(Some(Identity::new("SomeAccountName")));
+/// assert!(matches!(GuardOutcome::from(signed_in), GuardOutcome::Success(_)));
+///
+/// let anonymous: Result<Option<Identity>, AuthError> = Ok(None);
+/// assert!(matches!(GuardOutcome::from(anonymous), GuardOutcome::Forward));
+///
+/// let rejected: Result<Option<Identity>, AuthError> =
+///     Err(AuthError::Verification("Token has expired".to_string()));
+/// assert!(matches!(GuardOutcome::from(rejected), GuardOutcome::Error(401, _)));
+/// ```
+#[derive(Debug)]
+pub enum GuardOutcome<T> {
+    Success(T),
+    /// No credentials were presented; let the next matching route try
+    Forward,
+    /// The HTTP status to answer with and why
+    Error(u16, AuthError),
+}
+
+impl<T> From<Result<Option<T>, AuthError>> for GuardOutcome<T> {
+    fn from(result: Result<Option<T>, AuthError>) -> Self {
+        match result {
+            Ok(Some(value)) => Self::Success(value),
+            Ok(None) => Self::Forward,
+            Err(error) => Self::Error(status_code(&error), error),
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use std::time::Duration;
+
+use crate::{
+    audit::{AuditSink, NoAudit},
+    clock::{Clock, SystemClock},
+    crypto::ct_eq,
+    session::{
+        cookie::{self, Cookie},
+        Session, SessionManager, SessionStore,
+    },
+    token::generate_token,
+    AuthError,
+};
+
+use super::{
+    step_up::{Factor, StepUp, StepUpChallenge, StepUpPolicy, Verification, DEFAULT_CHALLENGE_TTL},
+    Identity,
+};
+
+/// Name of the session cookie unless another is set
+pub const DEFAULT_COOKIE_NAME: &str = "__Host-session";
+
+/// Session data key holding the Unix timestamp MFA was completed at
+pub const MFA_SESSION_KEY: &str = "mfa_verified_at";
+
+/// Session data key holding the signed in account's roles
+pub const ROLES_SESSION_KEY: &str = "roles";
+
+/// Session data key holding the latest `step_up::Verification` of each factor
+pub const STEP_UP_SESSION_KEY: &str = "step_up";
+
+/// Session data key holding the pending `step_up::StepUpChallenge`
+pub const STEP_UP_CHALLENGE_KEY: &str = "step_up_challenge";
+
+/// Authenticates requests by their session cookie
+///
+/// This is the framework independent part of a session layer: call `load` with the request's
+/// `Cookie` header before the handler runs, `identity` to resolve the caller, and send `cookie`
+/// back whenever the session's id changed. A session only identifies a caller once it is signed
+/// in, and only counts as MFA verified after `complete_mfa`.
+///
+/// ### Example
+/// ```rust
+/// use std::{collections::HashMap, sync::Mutex};
+///
+/// use lonewolf_auth_toolkit::integrations::{session::SessionAuth, status_code};
+/// use lonewolf_auth_toolkit::session::{SessionManager, SessionRecord, SessionStore};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, SessionRecord>>);
+///
+/// impl SessionStore for MemoryStore {
+///     async fn load(&self, id_hash: &str) -> Result<Option<SessionRecord>, AuthError> {
+///         Ok(self.0.lock().unwrap().get(id_hash).cloned())
+///     }
+///
+///     async fn save(&self, record: SessionRecord) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(record.id_hash.clone(), record);
+///         Ok(())
+///     }
+///
+///     async fn update(&self, record: SessionRecord) -> Result<bool, AuthError> {
+///         let mut sessions = self.0.lock().unwrap();
+///         match sessions.get_mut(&record.id_hash) {
+///             Some(current) => *current = record,
+///             None => return Ok(false),
+///         }
+///         Ok(true)
+///     }
+///
+///     async fn delete(&self, id_hash: &str) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().remove(id_hash);
+///         Ok(())
+///     }
+///
+///     async fn rotate(&self, old_hash: &str, record: SessionRecord) -> Result<bool, AuthError> {
+///         let mut sessions = self.0.lock().unwrap();
+///         if sessions.remove(old_hash).is_none() {
+///             return Ok(false);
+///         }
+///         sessions.insert(record.id_hash.clone(), record);
+///         Ok(true)
+///     }
+///
+///     async fn list(&self, _account: &str) -> Result<Vec<SessionRecord>, AuthError> {
+///         Ok(Vec::new())
+///     }
+///
+///     async fn delete_all(&self, _account: &str) -> Result<usize, AuthError> {
+///         Ok(0)
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let auth = SessionAuth::new(SessionManager::new(MemoryStore::default()));
+///
+///     // After checking the password
+///     let mut session = auth.sessions().create().await?;
+///     auth.sessions().sign_in(&mut session, "SomeAccountName").await?;
+///     let header = auth.cookie(&session)?.to_string();
+///
+///     // On the next request
+///     let cookie = header.split(';').next().unwrap();
+///     let mut session = auth.load(Some(cookie)).await?.unwrap();
+///     let identity = auth.identity(&session)?;
+///     assert_eq!(identity.subject, "SomeAccountName");
+///
+///     // Routes that require MFA reject the session until a second factor is verified
+///     let error = identity.require_mfa().unwrap_err();
+///     assert_eq!(status_code(&error), 403);
+///
+///     auth.complete_mfa(&mut session).await?;
+///     auth.identity(&session)?.require_mfa()?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct SessionAuth<S, A = NoAudit, C = SystemClock> {
+    sessions: SessionManager<S, A, C>,
+    cookie_name: String,
+}
+
+impl<S: SessionStore, A: AuditSink, C: Clock> SessionAuth<S, A, C> {
+    pub fn new(sessions: SessionManager<S, A, C>) -> Self {
+        Self {
+            sessions,
+            cookie_name: DEFAULT_COOKIE_NAME.to_string(),
+        }
+    }
+
+    pub fn cookie_name(mut self, cookie_name: impl Into<String>) -> Self {
+        self.cookie_name = cookie_name.into();
+        self
+    }
+
+    pub fn sessions(&self) -> &SessionManager<S, A, C> {
+        &self.sessions
+    }
+
+    /// The request's session, touched so it stays alive, or `None` without a valid cookie
+    pub async fn load(&self, cookie_header: Option<&str>) -> Result<Option<Session>, AuthError> {
+        let id = match cookie_header.and_then(|header| cookie::find(header, &self.cookie_name)) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        let mut session = match self.sessions.load(id).await? {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+        self.sessions.touch(&mut session).await?;
+
+        Ok(Some(session))
+    }
+
+    /// Who a signed in session belongs to; fails with `AuthError::Verification` otherwise
+    pub fn identity(&self, session: &Session) -> Result<Identity, AuthError> {
+        let account = session
+            .account()
+            .ok_or_else(|| AuthError::Verification("Not signed in".to_string()))?;
+
+        let mut identity = Identity::new(account);
+        identity.mfa_verified = session.get::<u64>(MFA_SESSION_KEY)?.is_some();
+        identity.roles = session
+            .get::<Vec<String>>(ROLES_SESSION_KEY)?
+            .unwrap_or_default();
+
+        Ok(identity)
+    }
+
+    /// Record that a second factor was verified, rotating the session id
+    pub async fn complete_mfa(&self, session: &mut Session) -> Result<(), AuthError> {
+        session.insert(MFA_SESSION_KEY, self.sessions.now()?)?;
+        self.sessions.save(session).await?;
+
+        self.sessions.rotate(session).await
+    }
+
+    /// Check the session re-authenticated recently enough for `operation`
+    ///
+    /// Returns `StepUp::Required` with a new challenge, saved in the session, when it did not;
+    /// answer the request with the challenge and retry the operation after `complete_step_up`.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// use lonewolf_auth_toolkit::integrations::{
+    ///     session::SessionAuth,
+    ///     step_up::{Factor, StepUp, StepUpPolicy},
+    /// };
+    /// use lonewolf_auth_toolkit::session::{MemorySessionStore, SessionManager};
+    ///
+    /// #[tokio::main]
+    /// pub async fn main() -> Result<(), anyhow::Error> {
+    ///     let auth = SessionAuth::new(SessionManager::new(MemorySessionStore::default()));
+    ///     let mut session = auth.sessions().create().await?;
+    ///     auth.sessions().sign_in(&mut session, "SomeAccountName").await?;
+    ///
+    ///     let delete_account = StepUpPolicy::new(Duration::from_secs(5 * 60))
+    ///         .factors([Factor::Totp, Factor::WebAuthn]);
+    ///
+    ///     let step_up = auth.require_step_up(&mut session, "delete_account", &delete_account);
+    ///     let challenge = match step_up.await? {
+    ///         StepUp::Required(challenge) => challenge,
+    ///         StepUp::Fresh(_) => unreachable!(),
+    ///     };
+    ///
+    ///     // After checking the TOTP code the user entered
+    ///     let challenge = auth.complete_step_up(&mut session, &challenge.id, Factor::Totp).await?;
+    ///     assert_eq!(challenge.satisfied.map(|verified| verified.factor), Some(Factor::Totp));
+    ///
+    ///     let step_up = auth.require_step_up(&mut session, "delete_account", &delete_account);
+    ///     assert!(matches!(step_up.await?, StepUp::Fresh(verified) if verified.factor == Factor::Totp));
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn require_step_up(
+        &self,
+        session: &mut Session,
+        operation: &str,
+        policy: &StepUpPolicy,
+    ) -> Result<StepUp, AuthError> {
+        let now = self.sessions.now()?;
+
+        let fresh = session
+            .get::<Vec<Verification>>(STEP_UP_SESSION_KEY)?
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|verification| policy.is_satisfied_by(verification, now))
+            .max_by_key(|verification| verification.at);
+        if let Some(verification) = fresh {
+            return Ok(StepUp::Fresh(verification));
+        }
+
+        let challenge = StepUpChallenge {
+            id: generate_token(),
+            operation: operation.to_string(),
+            factors: policy.accepted(),
+            issued_at: now,
+            expires_at: now + DEFAULT_CHALLENGE_TTL.as_secs(),
+            satisfied: None,
+        };
+        session.insert(STEP_UP_CHALLENGE_KEY, &challenge)?;
+        self.sessions.save(session).await?;
+
+        Ok(StepUp::Required(challenge))
+    }
+
+    /// Record that `factor` was verified for the pending challenge, rotating the session id
+    ///
+    /// Call only after the factor itself was checked. Fails with `AuthError::InvalidState` if the
+    /// challenge is unknown or expired and `AuthError::InvalidInput` if it does not accept
+    /// `factor`. A factor other than a password also completes MFA for the session.
+    pub async fn complete_step_up(
+        &self,
+        session: &mut Session,
+        challenge_id: &str,
+        factor: Factor,
+    ) -> Result<StepUpChallenge, AuthError> {
+        let now = self.sessions.now()?;
+
+        let mut challenge = session
+            .get::<StepUpChallenge>(STEP_UP_CHALLENGE_KEY)?
+            .filter(|challenge| ct_eq(&challenge.id, challenge_id) && challenge.expires_at > now)
+            .ok_or_else(|| {
+                AuthError::InvalidState("No step up challenge is pending".to_string())
+            })?;
+        if !challenge.factors.contains(&factor) {
+            return Err(AuthError::InvalidInput(
+                "The challenge does not accept this factor".to_string(),
+            ));
+        }
+
+        let verification = Verification { factor, at: now };
+        let mut verifications = session
+            .get::<Vec<Verification>>(STEP_UP_SESSION_KEY)?
+            .unwrap_or_default();
+        verifications.retain(|previous| previous.factor != factor);
+        verifications.push(verification);
+
+        session.insert(STEP_UP_SESSION_KEY, verifications)?;
+        session.remove(STEP_UP_CHALLENGE_KEY);
+        if factor != Factor::Password {
+            session.insert(MFA_SESSION_KEY, now)?;
+        }
+        self.sessions.save(session).await?;
+        self.sessions.rotate(session).await?;
+
+        challenge.satisfied = Some(verification);
+
+        Ok(challenge)
+    }
+
+    /// The `Set-Cookie` value carrying the session's id, lasting as long as the session
+    pub fn cookie(&self, session: &Session) -> Result<Cookie, AuthError> {
+        let max_age = session.expires_at().saturating_sub(self.sessions.now()?);
+
+        Ok(Cookie::new(&self.cookie_name, session.id())?.max_age(Duration::from_secs(max_age)))
+    }
+
+    /// Destroy the session and return the cookie that clears it from the browser
+    pub async fn sign_out(&self, session: &Session) -> Result<Cookie, AuthError> {
+        self.sessions.destroy(session.id()).await?;
+
+        Ok(self.cookie(session)?.removal())
+    }
+}
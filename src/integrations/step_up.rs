@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// How long the user has to answer a step up challenge
+pub const DEFAULT_CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// A factor the user can re-authenticate with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Factor {
+    Password,
+    Totp,
+    #[serde(rename = "webauthn")]
+    WebAuthn,
+    RecoveryCode,
+    Sms,
+    Email,
+    Push,
+}
+
+/// A successful re-authentication: which factor was verified and when
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Verification {
+    pub factor: Factor,
+    /// Unix timestamp in seconds
+    pub at: u64,
+}
+
+/// How fresh a verification a sensitive operation demands, and with which factors
+///
+/// Keep one policy per operation, e.g. five minutes and any second factor to change an email
+/// address, or one minute and only WebAuthn to delete an account. Without `factors` any factor
+/// except a password counts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepUpPolicy {
+    max_age: Duration,
+    factors: Vec<Factor>,
+}
+
+impl StepUpPolicy {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            factors: Vec::new(),
+        }
+    }
+
+    /// Accept only these factors
+    pub fn factors(mut self, factors: impl IntoIterator<Item = Factor>) -> Self {
+        self.factors = factors.into_iter().collect();
+        self
+    }
+
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+
+    /// Whether `factor` can satisfy the policy
+    pub fn accepts(&self, factor: Factor) -> bool {
+        if self.factors.is_empty() {
+            factor != Factor::Password
+        } else {
+            self.factors.contains(&factor)
+        }
+    }
+
+    /// Whether `verification` satisfies the policy at the Unix timestamp `now`
+    pub fn is_satisfied_by(&self, verification: &Verification, now: u64) -> bool {
+        self.accepts(verification.factor)
+            && now.saturating_sub(verification.at) <= self.max_age.as_secs()
+    }
+
+    pub(super) fn accepted(&self) -> Vec<Factor> {
+        if self.factors.is_empty() {
+            [
+                Factor::Totp,
+                Factor::WebAuthn,
+                Factor::RecoveryCode,
+                Factor::Sms,
+                Factor::Email,
+                Factor::Push,
+            ]
+            .to_vec()
+        } else {
+            self.factors.clone()
+        }
+    }
+}
+
+/// A pending demand to re-authenticate before an operation, kept in the session
+///
+/// Send `id` and `factors` to the client so it can prompt for one of them; `satisfied` is filled
+/// in once `SessionAuth::complete_step_up` accepts a factor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepUpChallenge {
+    pub id: String,
+    /// The operation that asked for it, e.g. "delete_account"
+    pub operation: String,
+    /// Factors that can satisfy it
+    pub factors: Vec<Factor>,
+    /// Unix timestamp in seconds
+    pub issued_at: u64,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+    pub satisfied: Option<Verification>,
+}
+
+/// The outcome of `SessionAuth::require_step_up`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepUp {
+    /// The session re-authenticated recently enough; go ahead
+    Fresh(Verification),
+    /// The user must verify a factor first
+    Required(StepUpChallenge),
+}
@@ -0,0 +1,387 @@
+use std::collections::BTreeSet;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use zeroize::Zeroizing;
+
+use crate::{authz::rbac::Rbac, AuthError};
+
+use super::{escape_dn_value, Entry, Filter, LdapClient, Scope};
+
+/// Placeholder replaced with the escaped username in a bind template
+pub const USERNAME_PLACEHOLDER: &str = "{username}";
+
+/// Attribute Active Directory and OpenLDAP's `memberOf` overlay list a user's groups in
+const MEMBER_OF: &str = "memberOf";
+
+/// Maps directory groups to `Rbac` role names
+///
+/// A group is matched, ignoring case, either by its full DN or by the value of its first RDN,
+/// so `CN=Admins,OU=Groups,DC=corp,DC=example,DC=com` matches both that DN and `Admins`. Several
+/// groups can map to the same role and one group to several roles.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::authz::rbac::Rbac;
+/// use lonewolf_auth_toolkit::ldap::authenticator::GroupMapping;
+///
+/// let rbac: Rbac = serde_json::from_str(r#"{
+///     "roles": {
+///         "viewer": { "permissions": ["posts:read"] },
+///         "admin": { "permissions": ["*"] }
+///     }
+/// }"#)?;
+///
+/// let mapping = GroupMapping::new()
+///     .map("Domain Users", "viewer")
+///     .map("CN=Admins,OU=Groups,DC=corp,DC=example,DC=com", "admin");
+/// mapping.check(&rbac)?;
+///
+/// let roles = mapping.roles(&[
+///     "CN=Domain Users,CN=Users,DC=corp,DC=example,DC=com",
+///     "cn=admins,ou=groups,dc=corp,dc=example,dc=com",
+/// ]);
+/// assert_eq!(roles, ["admin", "viewer"]);
+/// assert!(rbac.can(&roles, "billing:refund"));
+///
+/// assert!(GroupMapping::new().map("Auditors", "auditor").check(&rbac).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupMapping {
+    groups: Vec<(String, String)>,
+}
+
+impl GroupMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Grant `role` to members of `group`, a DN or a group name
+    pub fn map(mut self, group: impl Into<String>, role: impl Into<String>) -> Self {
+        self.groups.push((group.into(), role.into()));
+        self
+    }
+
+    /// The roles granted by the given group DNs, sorted and without duplicates
+    pub fn roles<G: AsRef<str>>(&self, groups: &[G]) -> Vec<String> {
+        let mut roles = BTreeSet::new();
+
+        for group in groups {
+            let group = group.as_ref();
+            let name = first_rdn_value(group);
+
+            for (mapped, role) in &self.groups {
+                if mapped.eq_ignore_ascii_case(group)
+                    || name
+                        .as_ref()
+                        .is_some_and(|name| mapped.eq_ignore_ascii_case(name))
+                {
+                    roles.insert(role.clone());
+                }
+            }
+        }
+
+        roles.into_iter().collect()
+    }
+
+    /// Fail with `AuthError::InvalidInput` if a group maps to a role `rbac` does not define
+    pub fn check(&self, rbac: &Rbac) -> Result<(), AuthError> {
+        match self
+            .groups
+            .iter()
+            .find(|(_, role)| rbac.permissions(role).is_none())
+        {
+            Some((group, role)) => Err(AuthError::InvalidInput(format!(
+                "Group {} maps to unknown role {}",
+                group, role
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A user who authenticated against the directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LdapUser {
+    pub dn: String,
+    /// The username as the directory spells it
+    pub username: String,
+    /// DNs of the user's groups
+    pub groups: Vec<String>,
+    /// Roles the groups map to
+    pub roles: Vec<String>,
+    /// The user's entry, with the attributes asked for with `LdapAuthenticator::attributes`
+    pub entry: Entry,
+}
+
+#[derive(Clone)]
+enum Bind {
+    Anonymous,
+    Service {
+        dn: String,
+        password: Zeroizing<String>,
+    },
+    Template(String),
+}
+
+#[derive(Debug, Clone)]
+enum Groups {
+    MemberOf,
+    Search { base: String, nested: bool },
+}
+
+/// Authenticates usernames and passwords by binding to an LDAP or Active Directory server
+///
+/// The user's entry is found by searching `user_base` for `user_attribute`, e.g. `uid` on
+/// OpenLDAP or `sAMAccountName` on Active Directory, then the password is checked by binding as
+/// that entry. The search runs as one of:
+///
+/// - A service account set with `service_account`, the usual setup for Active Directory.
+/// - The user, with `bind_template` turning the username into something the server accepts as a
+///   bind name, such as `uid={username},ou=people,dc=example,dc=com` or the user principal name
+///   `{username}@corp.example.com`.
+/// - Anonymous, if neither is set.
+///
+/// Groups are read from the user's `memberOf` attribute unless `group_search` or
+/// `nested_group_search` is set, and mapped to roles with the `GroupMapping`. Each call leaves
+/// the connection bound as the user, so do not share it with other work in between.
+///
+/// ### Example
+/// ```rust,no_run
+/// use lonewolf_auth_toolkit::ldap::{
+///     authenticator::{GroupMapping, LdapAuthenticator},
+///     LdapClient,
+/// };
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let mapping = GroupMapping::new()
+///         .map("Helpdesk", "support")
+///         .map("Domain Admins", "admin");
+///     let authenticator = LdapAuthenticator::new("OU=Staff,DC=corp,DC=example", "sAMAccountName")
+///         .service_account("CN=svc-auth,OU=Service,DC=corp,DC=example", "SomePassword")
+///         .nested_group_search("OU=Groups,DC=corp,DC=example")
+///         .attributes(["mail", "displayName"])
+///         .mapping(mapping);
+///
+///     let ldap = LdapClient::connect("dc1.corp.example.com:389").await?;
+///     match authenticator.authenticate(&ldap, "alice", "SomeUserPassword").await? {
+///         Some(user) => println!("{} has roles {:?}", user.dn, user.roles),
+///         None => println!("Wrong username or password"),
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct LdapAuthenticator {
+    user_base: String,
+    user_attribute: String,
+    user_filter: Option<Filter>,
+    bind: Bind,
+    groups: Groups,
+    attributes: Vec<String>,
+    mapping: GroupMapping,
+}
+
+impl LdapAuthenticator {
+    pub fn new(user_base: impl Into<String>, user_attribute: impl Into<String>) -> Self {
+        Self {
+            user_base: user_base.into(),
+            user_attribute: user_attribute.into(),
+            user_filter: None,
+            bind: Bind::Anonymous,
+            groups: Groups::MemberOf,
+            attributes: Vec::new(),
+            mapping: GroupMapping::new(),
+        }
+    }
+
+    /// Search for users as this account
+    pub fn service_account(mut self, dn: impl Into<String>, password: impl Into<String>) -> Self {
+        self.bind = Bind::Service {
+            dn: dn.into(),
+            password: Zeroizing::new(password.into()),
+        };
+        self
+    }
+
+    /// Bind as the user first, with `{username}` in `template` replaced by the escaped username
+    pub fn bind_template(mut self, template: impl Into<String>) -> Result<Self, AuthError> {
+        let template = template.into();
+        if !template.contains(USERNAME_PLACEHOLDER) {
+            return Err(AuthError::InvalidInput(format!(
+                "LDAP bind templates must contain {}",
+                USERNAME_PLACEHOLDER
+            )));
+        }
+
+        self.bind = Bind::Template(template);
+        Ok(self)
+    }
+
+    /// Only accept users that also match `filter`, e.g. members of an application group
+    pub fn user_filter(mut self, filter: Filter) -> Self {
+        self.user_filter = Some(filter);
+        self
+    }
+
+    /// Find groups by searching `base` for groups listing the user as a `member`
+    pub fn group_search(mut self, base: impl Into<String>) -> Self {
+        self.groups = Groups::Search {
+            base: base.into(),
+            nested: false,
+        };
+        self
+    }
+
+    /// Like `group_search`, also finding groups the user is in through other groups; Active
+    /// Directory only
+    pub fn nested_group_search(mut self, base: impl Into<String>) -> Self {
+        self.groups = Groups::Search {
+            base: base.into(),
+            nested: true,
+        };
+        self
+    }
+
+    /// Also read these attributes of the user's entry
+    pub fn attributes<I, A>(mut self, attributes: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<String>,
+    {
+        self.attributes = attributes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn mapping(mut self, mapping: GroupMapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+
+    /// The user if the password is right; `None` for an unknown user or wrong password
+    ///
+    /// Empty passwords never authenticate, since servers accept them as anonymous binds.
+    pub async fn authenticate<S: AsyncRead + AsyncWrite + Unpin + Send>(
+        &self,
+        client: &LdapClient<S>,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<LdapUser>, AuthError> {
+        if username.is_empty() || username.chars().any(char::is_control) || password.is_empty() {
+            return Ok(None);
+        }
+
+        match &self.bind {
+            Bind::Anonymous => {}
+            Bind::Service { dn, password } => {
+                if !client.bind(dn, password).await? {
+                    return Err(AuthError::backend("LDAP service account bind was rejected"));
+                }
+            }
+            Bind::Template(template) => {
+                let name = template.replace(USERNAME_PLACEHOLDER, &escape_dn_value(username));
+                if !client.bind(&name, password).await? {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let entry = match self.find_user(client, username).await? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        // Look groups up before binding as the user, who may not be allowed to read them
+        let groups = match &self.groups {
+            Groups::MemberOf => entry.values(MEMBER_OF),
+            Groups::Search { base, nested } => {
+                let filter = if *nested {
+                    Filter::in_chain("member", entry.dn.as_str())
+                } else {
+                    Filter::equal("member", entry.dn.as_str())
+                };
+                client
+                    .search(base, Scope::Subtree, &filter, &["1.1"])
+                    .await?
+                    .into_iter()
+                    .map(|group| group.dn)
+                    .collect()
+            }
+        };
+
+        if !matches!(self.bind, Bind::Template(_)) && !client.bind(&entry.dn, password).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(LdapUser {
+            dn: entry.dn.clone(),
+            username: entry
+                .first(&self.user_attribute)
+                .unwrap_or_else(|| username.to_string()),
+            roles: self.mapping.roles(&groups),
+            groups,
+            entry,
+        }))
+    }
+
+    async fn find_user<S: AsyncRead + AsyncWrite + Unpin + Send>(
+        &self,
+        client: &LdapClient<S>,
+        username: &str,
+    ) -> Result<Option<Entry>, AuthError> {
+        let mut filter = vec![Filter::equal(self.user_attribute.as_str(), username)];
+        filter.extend(self.user_filter.clone());
+
+        let mut attributes = vec![self.user_attribute.as_str()];
+        if matches!(self.groups, Groups::MemberOf) {
+            attributes.push(MEMBER_OF);
+        }
+        attributes.extend(self.attributes.iter().map(String::as_str));
+
+        let mut entries = client
+            .search(
+                &self.user_base,
+                Scope::Subtree,
+                &Filter::And(filter),
+                &attributes,
+            )
+            .await?;
+
+        match entries.len() {
+            0 => Ok(None),
+            1 => Ok(entries.pop()),
+            _ => Err(AuthError::backend(format!(
+                "LDAP user search for {} matched more than one entry",
+                username
+            ))),
+        }
+    }
+}
+
+/// The unescaped value of a DN's first RDN, e.g. `Admins` for `CN=Admins,OU=Groups`
+fn first_rdn_value(dn: &str) -> Option<String> {
+    let (_, rest) = dn.split_once('=')?;
+
+    // Hex escapes are UTF-8 bytes, so collect bytes and decode at the end
+    let mut value = Vec::new();
+    let mut bytes = rest.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b',' | b'+' => break,
+            b'\\' => {
+                let escaped = bytes.next()?;
+                if escaped.is_ascii_hexdigit() {
+                    let pair = [escaped, bytes.next()?];
+                    value.push(u8::from_str_radix(std::str::from_utf8(&pair).ok()?, 16).ok()?);
+                } else {
+                    value.push(escaped);
+                }
+            }
+            _ => value.push(byte),
+        }
+    }
+
+    Some(String::from_utf8_lossy(&value).trim().to_string())
+}
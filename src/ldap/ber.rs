@@ -0,0 +1,116 @@
+use crate::AuthError;
+
+pub(super) const BOOLEAN: u8 = 0x01;
+pub(super) const INTEGER: u8 = 0x02;
+pub(super) const OCTET_STRING: u8 = 0x04;
+pub(super) const ENUMERATED: u8 = 0x0a;
+pub(super) const SEQUENCE: u8 = 0x30;
+pub(super) const SET: u8 = 0x31;
+
+/// Encode one element with a definite length
+pub(super) fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut element = vec![tag];
+
+    if contents.len() < 0x80 {
+        element.push(contents.len() as u8);
+    } else {
+        let len = contents.len().to_be_bytes();
+        let start = len
+            .iter()
+            .position(|byte| *byte != 0)
+            .unwrap_or(len.len() - 1);
+        element.push(0x80 | (len.len() - start) as u8);
+        element.extend_from_slice(&len[start..]);
+    }
+
+    element.extend_from_slice(contents);
+    element
+}
+
+/// Encode an integer in the fewest two's complement bytes
+pub(super) fn integer(tag: u8, value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+
+    let mut start = 0;
+    while start < bytes.len() - 1
+        && ((bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+
+    tlv(tag, &bytes[start..])
+}
+
+/// Length of the contents from the octets after the tag, and how many of them it took
+///
+/// Lengths of more than four octets are refused, so a message can never claim more than 4 GiB.
+pub(super) fn length(octets: &[u8]) -> Result<(usize, usize), AuthError> {
+    let malformed = || AuthError::backend("Malformed LDAP message");
+    let first = *octets.first().ok_or_else(malformed)?;
+
+    if first < 0x80 {
+        return Ok((first.into(), 1));
+    }
+
+    let count = usize::from(first & 0x7f);
+    if count == 0 || count > 4 {
+        return Err(AuthError::backend("Unsupported LDAP message length"));
+    }
+    let len = octets
+        .get(1..=count)
+        .ok_or_else(malformed)?
+        .iter()
+        .fold(0, |len, byte| (len << 8) | usize::from(*byte));
+
+    Ok((len, 1 + count))
+}
+
+/// Walks the elements inside a constructed element
+pub(super) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The next element's tag and contents
+    pub(super) fn next(&mut self) -> Result<(u8, &'a [u8]), AuthError> {
+        let malformed = || AuthError::backend("Malformed LDAP message");
+
+        let (&tag, rest) = self.data.split_first().ok_or_else(malformed)?;
+        let (len, header) = length(rest)?;
+
+        let end = header.checked_add(len).ok_or_else(malformed)?;
+        let contents = rest.get(header..end).ok_or_else(malformed)?;
+        self.data = &rest[end..];
+
+        Ok((tag, contents))
+    }
+
+    /// The next element's contents, which must have `tag`
+    pub(super) fn expect(&mut self, tag: u8) -> Result<&'a [u8], AuthError> {
+        match self.next()? {
+            (found, contents) if found == tag => Ok(contents),
+            _ => Err(AuthError::backend("Unexpected element in LDAP message")),
+        }
+    }
+
+    pub(super) fn integer(&mut self, tag: u8) -> Result<i64, AuthError> {
+        let contents = self.expect(tag)?;
+        if contents.is_empty() || contents.len() > 8 {
+            return Err(AuthError::backend("Malformed integer in LDAP message"));
+        }
+
+        let sign = if contents[0] & 0x80 != 0 { -1 } else { 0 };
+        Ok(contents
+            .iter()
+            .fold(sign, |value, byte| (value << 8) | i64::from(*byte)))
+    }
+}
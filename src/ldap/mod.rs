@@ -0,0 +1,422 @@
+use std::collections::BTreeMap;
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+};
+
+use crate::AuthError;
+
+pub mod authenticator;
+mod ber;
+
+/// Largest LDAP message read from the server
+pub const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// Result code of a successful operation
+const SUCCESS: i64 = 0;
+
+/// Result code of a bind with a wrong DN or password
+const INVALID_CREDENTIALS: i64 = 49;
+
+/// Active Directory's `LDAP_MATCHING_RULE_IN_CHAIN`, which follows nested group membership
+const IN_CHAIN_RULE: &str = "1.2.840.113556.1.4.1941";
+
+const BIND_REQUEST: u8 = 0x60;
+const BIND_RESPONSE: u8 = 0x61;
+const UNBIND_REQUEST: u8 = 0x42;
+const SEARCH_REQUEST: u8 = 0x63;
+const SEARCH_RESULT_ENTRY: u8 = 0x64;
+const SEARCH_RESULT_DONE: u8 = 0x65;
+const SEARCH_RESULT_REFERENCE: u8 = 0x73;
+const SIMPLE_AUTHENTICATION: u8 = 0x80;
+
+/// How far below the base a search looks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Only the base entry itself
+    Base,
+    /// The base's direct children
+    OneLevel,
+    /// The base and everything below it
+    Subtree,
+}
+
+/// A search filter
+///
+/// Values are sent as they are, so unlike filter strings nothing needs escaping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+    /// `(attribute=value)`
+    Equal(String, String),
+    /// `(attribute=*)`
+    Present(String),
+    /// `(attribute:1.2.840.113556.1.4.1941:=dn)`, which on Active Directory also matches entries
+    /// that reach `dn` through nested groups
+    InChain(String, String),
+}
+
+impl Filter {
+    pub fn equal(attribute: impl Into<String>, value: impl Into<String>) -> Self {
+        Self::Equal(attribute.into(), value.into())
+    }
+
+    pub fn present(attribute: impl Into<String>) -> Self {
+        Self::Present(attribute.into())
+    }
+
+    pub fn in_chain(attribute: impl Into<String>, dn: impl Into<String>) -> Self {
+        Self::InChain(attribute.into(), dn.into())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::And(filters) => ber::tlv(
+                0xa0,
+                &filters.iter().flat_map(Self::encode).collect::<Vec<_>>(),
+            ),
+            Self::Or(filters) => ber::tlv(
+                0xa1,
+                &filters.iter().flat_map(Self::encode).collect::<Vec<_>>(),
+            ),
+            Self::Not(filter) => ber::tlv(0xa2, &filter.encode()),
+            Self::Equal(attribute, value) => {
+                let mut contents = ber::tlv(ber::OCTET_STRING, attribute.as_bytes());
+                contents.extend(ber::tlv(ber::OCTET_STRING, value.as_bytes()));
+                ber::tlv(0xa3, &contents)
+            }
+            Self::Present(attribute) => ber::tlv(0x87, attribute.as_bytes()),
+            Self::InChain(attribute, dn) => {
+                let mut contents = ber::tlv(0x81, IN_CHAIN_RULE.as_bytes());
+                contents.extend(ber::tlv(0x82, attribute.as_bytes()));
+                contents.extend(ber::tlv(0x83, dn.as_bytes()));
+                ber::tlv(0xa9, &contents)
+            }
+        }
+    }
+}
+
+/// An entry returned by a search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub dn: String,
+    /// Values by lowercased attribute name, since attribute names are case insensitive
+    pub attributes: BTreeMap<String, Vec<Vec<u8>>>,
+}
+
+impl Entry {
+    /// The attribute's values as text, replacing invalid UTF-8
+    pub fn values(&self, attribute: &str) -> Vec<String> {
+        self.attributes
+            .get(&attribute.to_ascii_lowercase())
+            .into_iter()
+            .flatten()
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+            .collect()
+    }
+
+    pub fn first(&self, attribute: &str) -> Option<String> {
+        self.values(attribute).into_iter().next()
+    }
+}
+
+struct Connection<S> {
+    stream: BufStream<S>,
+    next_id: i32,
+}
+
+/// A minimal LDAPv3 client over a single connection, supporting simple binds and searches
+///
+/// Operations are serialised over the connection. Simple binds send the password as it is, so
+/// connect to `ldaps://` servers by passing a TLS stream to `new`; plain TCP is only safe on a
+/// trusted network. Referrals are not followed.
+///
+/// ### Example
+/// ```rust,no_run
+/// use lonewolf_auth_toolkit::ldap::{Filter, LdapClient, Scope};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let ldap = LdapClient::connect("ldap.example.com:389").await?;
+///
+///     assert!(ldap.bind("uid=alice,ou=people,dc=example,dc=com", "SomePassword").await?);
+///
+///     let filter = Filter::equal("member", "uid=alice,ou=people,dc=example,dc=com");
+///     let groups = ldap.search("ou=groups,dc=example,dc=com", Scope::Subtree, &filter, &["cn"]);
+///     for group in groups.await? {
+///         println!("{}", group.first("cn").unwrap_or(group.dn));
+///     }
+///
+///     ldap.unbind().await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct LdapClient<S = TcpStream> {
+    connection: Mutex<Connection<S>>,
+}
+
+impl LdapClient<TcpStream> {
+    pub async fn connect(address: impl ToSocketAddrs) -> Result<Self, AuthError> {
+        let stream = TcpStream::connect(address)
+            .await
+            .map_err(AuthError::backend)?;
+
+        Ok(Self::new(stream))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> LdapClient<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            connection: Mutex::new(Connection {
+                stream: BufStream::new(stream),
+                next_id: 0,
+            }),
+        }
+    }
+
+    /// Simple bind; `false` if the server rejected the DN or password
+    ///
+    /// An empty password is refused with `AuthError::InvalidInput`, since servers treat it as an
+    /// unauthenticated bind that succeeds for any DN.
+    pub async fn bind(&self, dn: &str, password: &str) -> Result<bool, AuthError> {
+        if password.is_empty() {
+            return Err(AuthError::InvalidInput(
+                "LDAP bind password is empty".to_string(),
+            ));
+        }
+
+        let mut request = ber::integer(ber::INTEGER, 3);
+        request.extend(ber::tlv(ber::OCTET_STRING, dn.as_bytes()));
+        request.extend(ber::tlv(SIMPLE_AUTHENTICATION, password.as_bytes()));
+
+        let mut code = None;
+        self.exchange(ber::tlv(BIND_REQUEST, &request), |tag, contents| {
+            if tag != BIND_RESPONSE {
+                return Err(AuthError::backend("Unexpected LDAP bind response"));
+            }
+            code = Some(result(contents)?);
+            Ok(true)
+        })
+        .await?;
+
+        match code {
+            Some((SUCCESS, _)) => Ok(true),
+            Some((INVALID_CREDENTIALS, _)) => Ok(false),
+            Some((code, message)) => Err(failed("bind", code, &message)),
+            None => Err(AuthError::backend("LDAP server sent no bind response")),
+        }
+    }
+
+    /// Every entry matching `filter`, with only the given `attributes`
+    ///
+    /// An empty `attributes` returns all user attributes; `["1.1"]` returns none.
+    pub async fn search(
+        &self,
+        base: &str,
+        scope: Scope,
+        filter: &Filter,
+        attributes: &[&str],
+    ) -> Result<Vec<Entry>, AuthError> {
+        let scope = match scope {
+            Scope::Base => 0,
+            Scope::OneLevel => 1,
+            Scope::Subtree => 2,
+        };
+
+        let mut request = ber::tlv(ber::OCTET_STRING, base.as_bytes());
+        request.extend(ber::integer(ber::ENUMERATED, scope));
+        // Never dereference aliases, no size or time limit and return values too
+        request.extend(ber::integer(ber::ENUMERATED, 0));
+        request.extend(ber::integer(ber::INTEGER, 0));
+        request.extend(ber::integer(ber::INTEGER, 0));
+        request.extend(ber::tlv(ber::BOOLEAN, &[0x00]));
+        request.extend(filter.encode());
+        let attributes = attributes
+            .iter()
+            .flat_map(|attribute| ber::tlv(ber::OCTET_STRING, attribute.as_bytes()))
+            .collect::<Vec<_>>();
+        request.extend(ber::tlv(ber::SEQUENCE, &attributes));
+
+        // Keep reading after a malformed entry so the connection stays in sync
+        let mut entries = Vec::new();
+        let mut code = None;
+        self.exchange(ber::tlv(SEARCH_REQUEST, &request), |tag, contents| {
+            match tag {
+                SEARCH_RESULT_ENTRY => entries.push(entry(contents)),
+                SEARCH_RESULT_REFERENCE => {}
+                SEARCH_RESULT_DONE => {
+                    code = Some(result(contents)?);
+                    return Ok(true);
+                }
+                _ => return Err(AuthError::backend("Unexpected LDAP search response")),
+            }
+            Ok(false)
+        })
+        .await?;
+
+        match code {
+            Some((SUCCESS, _)) => entries.into_iter().collect(),
+            Some((code, message)) => Err(failed("search", code, &message)),
+            None => Err(AuthError::backend("LDAP server sent no search result")),
+        }
+    }
+
+    /// Tell the server the connection is no longer needed
+    pub async fn unbind(&self) -> Result<(), AuthError> {
+        let mut connection = self.connection.lock().await;
+        let (_, message) = message(&mut connection, &ber::tlv(UNBIND_REQUEST, &[]));
+
+        connection
+            .stream
+            .write_all(&message)
+            .await
+            .map_err(AuthError::backend)?;
+        connection.stream.flush().await.map_err(AuthError::backend)
+    }
+
+    /// Send one request and pass its responses to `handle` until it returns `true`
+    async fn exchange<F>(&self, operation: Vec<u8>, mut handle: F) -> Result<(), AuthError>
+    where
+        F: FnMut(u8, &[u8]) -> Result<bool, AuthError> + Send,
+    {
+        let mut connection = self.connection.lock().await;
+        let (id, request) = message(&mut connection, &operation);
+
+        connection
+            .stream
+            .write_all(&request)
+            .await
+            .map_err(AuthError::backend)?;
+        connection
+            .stream
+            .flush()
+            .await
+            .map_err(AuthError::backend)?;
+
+        loop {
+            let contents = read_message(&mut connection.stream).await?;
+            let mut reader = ber::Reader::new(&contents);
+            let message_id = reader.integer(ber::INTEGER)?;
+            let (tag, body) = reader.next()?;
+
+            // Message id 0 is an unsolicited notification, in practice a notice of disconnection
+            if message_id == 0 {
+                let (code, message) = result(body).unwrap_or((0, String::new()));
+                return Err(failed("connection", code, &message));
+            }
+            if message_id == i64::from(id) && handle(tag, body)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Wrap an operation in an `LDAPMessage` with the connection's next message id
+fn message<S>(connection: &mut Connection<S>, operation: &[u8]) -> (i32, Vec<u8>) {
+    connection.next_id = connection.next_id.wrapping_add(1).max(1);
+
+    let mut message = ber::integer(ber::INTEGER, connection.next_id.into());
+    message.extend_from_slice(operation);
+
+    (connection.next_id, ber::tlv(ber::SEQUENCE, &message))
+}
+
+/// Read one `LDAPMessage` and return its contents
+async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Vec<u8>, AuthError> {
+    let closed = |_| AuthError::backend("LDAP connection closed");
+
+    let mut header = [0; 6];
+    reader.read_exact(&mut header[..2]).await.map_err(closed)?;
+    if header[0] != ber::SEQUENCE {
+        return Err(AuthError::backend("Malformed LDAP message"));
+    }
+
+    let extra = match header[1] {
+        len if len > 0x80 => usize::from(len & 0x7f).min(4),
+        _ => 0,
+    };
+    reader
+        .read_exact(&mut header[2..2 + extra])
+        .await
+        .map_err(closed)?;
+
+    let (len, _) = ber::length(&header[1..2 + extra])?;
+    if len > MAX_MESSAGE_LEN {
+        return Err(AuthError::backend("LDAP message is too large"));
+    }
+
+    let mut contents = vec![0; len];
+    reader.read_exact(&mut contents).await.map_err(closed)?;
+
+    Ok(contents)
+}
+
+/// The result code and diagnostic message of an `LDAPResult`
+fn result(contents: &[u8]) -> Result<(i64, String), AuthError> {
+    let mut reader = ber::Reader::new(contents);
+    let code = reader.integer(ber::ENUMERATED)?;
+    reader.expect(ber::OCTET_STRING)?;
+    let message = reader.expect(ber::OCTET_STRING)?;
+
+    Ok((code, String::from_utf8_lossy(message).into_owned()))
+}
+
+fn entry(contents: &[u8]) -> Result<Entry, AuthError> {
+    let mut reader = ber::Reader::new(contents);
+    let dn = String::from_utf8_lossy(reader.expect(ber::OCTET_STRING)?).into_owned();
+
+    let mut attributes = BTreeMap::new();
+    let mut list = ber::Reader::new(reader.expect(ber::SEQUENCE)?);
+    while !list.is_empty() {
+        let mut attribute = ber::Reader::new(list.expect(ber::SEQUENCE)?);
+        let name =
+            String::from_utf8_lossy(attribute.expect(ber::OCTET_STRING)?).to_ascii_lowercase();
+
+        let mut values = Vec::new();
+        let mut set = ber::Reader::new(attribute.expect(ber::SET)?);
+        while !set.is_empty() {
+            values.push(set.expect(ber::OCTET_STRING)?.to_vec());
+        }
+
+        attributes
+            .entry(name)
+            .or_insert_with(Vec::new)
+            .extend(values);
+    }
+
+    Ok(Entry { dn, attributes })
+}
+
+fn failed(operation: &str, code: i64, message: &str) -> AuthError {
+    AuthError::backend(format!(
+        "LDAP {} failed with result code {}: {}",
+        operation, code, message
+    ))
+}
+
+/// Escape a value for use in a DN, such as a username in `uid={},ou=people,dc=example,dc=com`
+pub fn escape_dn_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let last = value.chars().count().saturating_sub(1);
+
+    for (index, c) in value.chars().enumerate() {
+        match c {
+            '\\' | ',' | '+' | '"' | '<' | '>' | ';' | '=' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\0' => escaped.push_str("\\00"),
+            ' ' if index == 0 || index == last => escaped.push_str("\\ "),
+            '#' if index == 0 => escaped.push_str("\\#"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
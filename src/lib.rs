@@ -1 +1,46 @@
+#[cfg(all(feature = "mfa", feature = "password"))]
+pub mod account;
+pub mod apikey;
+pub mod audit;
+pub mod authz;
+pub mod captcha;
+pub mod clock;
+pub mod crypto;
+pub mod email;
+pub mod error;
+pub mod http;
+pub mod identity;
+#[cfg(feature = "integrations")]
+pub mod integrations;
+#[cfg(feature = "ldap")]
+pub mod ldap;
+pub mod metrics;
+#[cfg(feature = "mfa")]
 pub mod mfa;
+pub mod mtls;
+#[cfg(feature = "oauth")]
+pub mod oauth;
+pub mod otel;
+#[cfg(feature = "password")]
+pub mod password;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod pow;
+pub mod rate_limit;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "saml")]
+pub mod saml;
+#[cfg(all(feature = "mfa", feature = "password", feature = "session"))]
+pub mod service;
+#[cfg(feature = "session")]
+pub mod session;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod tenant;
+pub mod token;
+pub mod trace;
+pub mod webauthn;
+pub mod webhook;
+
+pub use error::AuthError;
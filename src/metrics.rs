@@ -0,0 +1,305 @@
+// Counters and histograms for operators to alert on; without the `metrics` feature every hook
+// compiles to nothing
+
+#[cfg(feature = "metrics")]
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+    time::Duration,
+};
+
+#[cfg(feature = "metrics")]
+use crate::AuthError;
+
+/// Counter of finished verifications, labelled `operation` and `outcome`
+///
+/// Outcomes are `success`, `rejected`, e.g. a wrong code, and `error`.
+pub const VERIFICATIONS: &str = "lonewolf_auth_verifications_total";
+
+/// Histogram of verification durations in seconds, labelled `operation`
+pub const VERIFICATION_DURATION: &str = "lonewolf_auth_verification_duration_seconds";
+
+/// Histogram of password hash and verify durations in seconds, labelled `algorithm`
+pub const HASH_DURATION: &str = "lonewolf_auth_password_hash_duration_seconds";
+
+/// Counter of issued tokens, labelled `kind`: `jwt`, `paseto_local`, `paseto_public`, `opaque`,
+/// `refresh` or `remember_me`
+pub const TOKENS_ISSUED: &str = "lonewolf_auth_tokens_issued_total";
+
+/// Counter of lockouts, labelled `kind`: `lockout` or `hard_lock`
+pub const LOCKOUTS: &str = "lonewolf_auth_lockouts_total";
+
+/// Histogram bucket upper bounds in seconds, from a fast token check to a slow password hash
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Receives every metric update, e.g. to forward it to the `metrics` crate or an OpenTelemetry
+/// meter
+///
+/// Labels never carry secrets or per user values, so their cardinality stays bounded.
+#[cfg(feature = "metrics")]
+pub trait Recorder: Send + Sync {
+    fn increment(&self, name: &'static str, labels: &[(&'static str, &str)]);
+
+    fn observe(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64);
+}
+
+#[cfg(feature = "metrics")]
+impl<T: Recorder> Recorder for Arc<T> {
+    fn increment(&self, name: &'static str, labels: &[(&'static str, &str)]) {
+        (**self).increment(name, labels)
+    }
+
+    fn observe(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        (**self).observe(name, labels, value)
+    }
+}
+
+#[cfg(feature = "metrics")]
+static RECORDER: OnceLock<Box<dyn Recorder>> = OnceLock::new();
+
+/// Send every metric update to `recorder`
+///
+/// Fails with `AuthError::InvalidState` if a recorder is already set.
+#[cfg(feature = "metrics")]
+pub fn set_recorder(recorder: impl Recorder + 'static) -> Result<(), AuthError> {
+    RECORDER
+        .set(Box::new(recorder))
+        .map_err(|_| AuthError::InvalidState("A metrics recorder is already set".to_string()))
+}
+
+#[cfg(feature = "metrics")]
+type Labels = Vec<(&'static str, String)>;
+
+#[cfg(feature = "metrics")]
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Default)]
+struct Metrics {
+    counters: BTreeMap<&'static str, BTreeMap<Labels, u64>>,
+    histograms: BTreeMap<&'static str, BTreeMap<Labels, Histogram>>,
+}
+
+/// Keeps metrics in process memory and renders them in the Prometheus text format
+///
+/// Serve `render` from a `/metrics` endpoint for Prometheus to scrape. Alert on, for example, a
+/// rise in `rejected` verifications, any `lockout`, or the hash duration creeping up as servers
+/// get busier.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::metrics::{set_recorder, PrometheusRecorder};
+/// use lonewolf_auth_toolkit::mfa::verify;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let metrics = Arc::new(PrometheusRecorder::default());
+///     set_recorder(metrics.clone())?;
+///
+///     // A malformed secret counts as an error
+///     assert!(verify("123456".to_string(), "2SHORT".to_string()).await.is_err());
+///
+///     let text = metrics.render()?;
+///     assert!(text.contains(
+///         r#"lonewolf_auth_verifications_total{operation="mfa.verify",outcome="error"} 1"#
+///     ));
+///     assert!(text.contains("# TYPE lonewolf_auth_verification_duration_seconds histogram"));
+///
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "metrics")]
+pub struct PrometheusRecorder {
+    buckets: Vec<f64>,
+    metrics: Mutex<Metrics>,
+}
+
+#[cfg(feature = "metrics")]
+impl Default for PrometheusRecorder {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUCKETS.to_vec())
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl PrometheusRecorder {
+    /// Use these histogram bucket upper bounds, in ascending order
+    pub fn new(buckets: Vec<f64>) -> Self {
+        Self {
+            buckets,
+            metrics: Mutex::default(),
+        }
+    }
+
+    /// Every metric in the Prometheus text exposition format
+    pub fn render(&self) -> Result<String, AuthError> {
+        let metrics = self.lock()?;
+        let mut text = String::new();
+
+        for (name, series) in &metrics.counters {
+            let _ = writeln!(text, "# TYPE {} counter", name);
+            for (labels, value) in series {
+                let _ = writeln!(text, "{}{} {}", name, format_labels(labels, None), value);
+            }
+        }
+
+        for (name, series) in &metrics.histograms {
+            let _ = writeln!(text, "# TYPE {} histogram", name);
+            for (labels, histogram) in series {
+                for (bound, count) in self.buckets.iter().zip(&histogram.buckets) {
+                    let le = bound.to_string();
+                    let labels = format_labels(labels, Some(&le));
+                    let _ = writeln!(text, "{}_bucket{} {}", name, labels, count);
+                }
+                let labels_inf = format_labels(labels, Some("+Inf"));
+                let labels = format_labels(labels, None);
+                let _ = writeln!(text, "{}_bucket{} {}", name, labels_inf, histogram.count);
+                let _ = writeln!(text, "{}_sum{} {}", name, labels, histogram.sum);
+                let _ = writeln!(text, "{}_count{} {}", name, labels, histogram.count);
+            }
+        }
+
+        Ok(text)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Metrics>, AuthError> {
+        self.metrics
+            .lock()
+            .map_err(|_| AuthError::backend("Metrics lock poisoned"))
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Recorder for PrometheusRecorder {
+    fn increment(&self, name: &'static str, labels: &[(&'static str, &str)]) {
+        if let Ok(mut metrics) = self.lock() {
+            *metrics
+                .counters
+                .entry(name)
+                .or_default()
+                .entry(owned(labels))
+                .or_default() += 1;
+        }
+    }
+
+    fn observe(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        if let Ok(mut metrics) = self.lock() {
+            let histogram = metrics
+                .histograms
+                .entry(name)
+                .or_default()
+                .entry(owned(labels))
+                .or_insert_with(|| Histogram {
+                    buckets: vec![0; self.buckets.len()],
+                    sum: 0.0,
+                    count: 0,
+                });
+
+            for (bound, count) in self.buckets.iter().zip(&mut histogram.buckets) {
+                if value <= *bound {
+                    *count += 1;
+                }
+            }
+            histogram.sum += value;
+            histogram.count += 1;
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn owned(labels: &[(&'static str, &str)]) -> Labels {
+    labels
+        .iter()
+        .map(|(name, value)| (*name, value.to_string()))
+        .collect()
+}
+
+#[cfg(feature = "metrics")]
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn recorder() -> Option<&'static dyn Recorder> {
+    RECORDER.get().map(|recorder| recorder.as_ref())
+}
+
+/// Count a finished verification and record how long it took, called by `trace::instrument`
+#[cfg(feature = "metrics")]
+pub(crate) fn verification(operation: &'static str, outcome: &'static str, elapsed: Duration) {
+    if let Some(recorder) = recorder() {
+        let labels = [("operation", operation)];
+        recorder.increment(
+            VERIFICATIONS,
+            &[("operation", operation), ("outcome", outcome)],
+        );
+        recorder.observe(VERIFICATION_DURATION, &labels, elapsed.as_secs_f64());
+    }
+}
+
+/// Run a password hash or verify, recording its duration
+#[cfg(all(feature = "metrics", feature = "password"))]
+pub(crate) fn hashing<T>(algorithm: &'static str, f: impl FnOnce() -> T) -> T {
+    let started = std::time::Instant::now();
+    let result = f();
+    if let Some(recorder) = recorder() {
+        let elapsed = started.elapsed().as_secs_f64();
+        recorder.observe(HASH_DURATION, &[("algorithm", algorithm)], elapsed);
+    }
+
+    result
+}
+
+#[cfg(all(not(feature = "metrics"), feature = "password"))]
+pub(crate) fn hashing<T>(_algorithm: &'static str, f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+/// Count an issued token
+#[cfg(feature = "metrics")]
+pub(crate) fn token_issued(kind: &'static str) {
+    if let Some(recorder) = recorder() {
+        recorder.increment(TOKENS_ISSUED, &[("kind", kind)]);
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn token_issued(_kind: &'static str) {}
+
+/// Count an account being locked
+#[cfg(feature = "metrics")]
+pub(crate) fn lockout(kind: &'static str) {
+    if let Some(recorder) = recorder() {
+        recorder.increment(LOCKOUTS, &[("kind", kind)]);
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn lockout(_kind: &'static str) {}
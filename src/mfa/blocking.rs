@@ -0,0 +1,231 @@
+use crate::{
+    clock::{Clock, SystemClock},
+    trace, AuthError,
+};
+
+use super::{
+    build_totp, matching_step, outcome_at, provision,
+    qr::{self, QrStyle},
+    TotpConfig, VerifyOutcome, WindowCodes,
+};
+
+/// Like `mfa::generate`, for applications without an async runtime
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::blocking::generate;
+///
+/// let (qr, secret) = generate("SomeIssuer".to_string(), "SomeAccountName".to_string())?;
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+pub fn generate(issuer: String, account_name: String) -> Result<(String, String), AuthError> {
+    generate_with(issuer, account_name, &TotpConfig::default())
+}
+
+/// Like `mfa::generate_with`
+pub fn generate_with(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+) -> Result<(String, String), AuthError> {
+    let (url, secret_string) = provision(issuer, account_name, config)?;
+
+    Ok((qr::base64(&url)?, secret_string))
+}
+
+/// Like `mfa::generate_png`
+pub fn generate_png(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+) -> Result<(Vec<u8>, String), AuthError> {
+    let (url, secret_string) = provision(issuer, account_name, config)?;
+
+    Ok((qr::png(&url)?, secret_string))
+}
+
+/// Like `mfa::generate_png_styled`
+pub fn generate_png_styled(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+    style: &QrStyle,
+) -> Result<(Vec<u8>, String), AuthError> {
+    let (url, secret_string) = provision(issuer, account_name, config)?;
+
+    Ok((qr::png_with(&url, style)?, secret_string))
+}
+
+/// Like `mfa::generate_svg`
+pub fn generate_svg(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+) -> Result<(String, String), AuthError> {
+    let (url, secret_string) = provision(issuer, account_name, config)?;
+
+    Ok((qr::svg(&url)?, secret_string))
+}
+
+/// Like `mfa::generate_svg_styled`
+pub fn generate_svg_styled(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+    style: &QrStyle,
+) -> Result<(String, String), AuthError> {
+    let (url, secret_string) = provision(issuer, account_name, config)?;
+
+    Ok((qr::svg_with(&url, style)?, secret_string))
+}
+
+/// Like `mfa::generate_ascii`, e.g. to print a QR code from a CLI
+pub fn generate_ascii(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+) -> Result<(String, String), AuthError> {
+    let (url, secret_string) = provision(issuer, account_name, config)?;
+
+    Ok((qr::ascii(&url)?, secret_string))
+}
+
+/// Like `mfa::verify`, for applications without an async runtime
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::{blocking::verify, generate_secret};
+///
+/// let verified = verify("123456".to_string(), generate_secret())?;
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+pub fn verify(code: String, secret: String) -> Result<bool, AuthError> {
+    verify_with(code, secret, &TotpConfig::default())
+}
+
+/// Like `mfa::verify_with`
+pub fn verify_with(code: String, secret: String, config: &TotpConfig) -> Result<bool, AuthError> {
+    Ok(verify_with_offset(code, secret, config)?.is_some())
+}
+
+/// Like `mfa::verify_with_offset`
+pub fn verify_with_offset(
+    code: String,
+    secret: String,
+    config: &TotpConfig,
+) -> Result<Option<i64>, AuthError> {
+    offset_at(&code, &secret, config, &SystemClock)
+}
+
+/// Like `mfa::verify_with_clock`
+pub fn verify_with_clock(
+    code: String,
+    secret: String,
+    config: &TotpConfig,
+    clock: &impl Clock,
+) -> Result<bool, AuthError> {
+    Ok(offset_at(&code, &secret, config, clock)?.is_some())
+}
+
+/// Like `mfa::verify_outcome`
+pub fn verify_outcome(
+    code: &str,
+    secret: &str,
+    config: &TotpConfig,
+) -> Result<VerifyOutcome, AuthError> {
+    verify_outcome_with_clock(code, secret, config, &SystemClock)
+}
+
+/// Like `mfa::verify_outcome_with_clock`
+pub fn verify_outcome_with_clock(
+    code: &str,
+    secret: &str,
+    config: &TotpConfig,
+    clock: &impl Clock,
+) -> Result<VerifyOutcome, AuthError> {
+    config.validate()?;
+
+    trace::instrument_sync("mfa.verify", &[], || {
+        Ok(outcome_at(code, secret, config, clock.now()?))
+    })
+}
+
+fn offset_at(
+    code: &str,
+    secret: &str,
+    config: &TotpConfig,
+    clock: &impl Clock,
+) -> Result<Option<i64>, AuthError> {
+    trace::instrument_sync("mfa.verify", &[], || {
+        Ok(matching_step(code, secret, config, clock.now()?)?.map(|(offset, _)| offset))
+    })
+}
+
+/// The code an authenticator app shows for `secret` right now, e.g. for tests and support tools
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::blocking::{current_code, verify};
+/// use lonewolf_auth_toolkit::mfa::TotpConfig;
+///
+/// let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+/// let code = current_code(secret, &TotpConfig::default())?;
+///
+/// assert!(verify(code, secret.to_string())?);
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+pub fn current_code(secret: &str, config: &TotpConfig) -> Result<String, AuthError> {
+    current_code_with_clock(secret, config, &SystemClock)
+}
+
+/// Like `current_code`, at the time `clock` reads
+pub fn current_code_with_clock(
+    secret: &str,
+    config: &TotpConfig,
+    clock: &impl Clock,
+) -> Result<String, AuthError> {
+    let totp = build_totp(secret, config)?;
+    let step = clock.now()? / config.step;
+
+    Ok(totp.generate(step * config.step))
+}
+
+/// The codes for the step before, the current step and the step after, e.g. for a server that
+/// sends codes to a partner whose clock may be a step off
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::clock::MockClock;
+/// use lonewolf_auth_toolkit::mfa::blocking::{
+///     codes_for_window_with_clock, current_code_with_clock,
+/// };
+/// use lonewolf_auth_toolkit::mfa::TotpConfig;
+///
+/// let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+/// let config = TotpConfig::default();
+/// let codes = codes_for_window_with_clock(secret, &config, &MockClock::at(1_700_000_030))?;
+///
+/// let previous = current_code_with_clock(secret, &config, &MockClock::at(1_700_000_000))?;
+/// let next = current_code_with_clock(secret, &config, &MockClock::at(1_700_000_060))?;
+/// assert_eq!((codes.previous, codes.next), (previous, next));
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+pub fn codes_for_window(secret: &str, config: &TotpConfig) -> Result<WindowCodes, AuthError> {
+    codes_for_window_with_clock(secret, config, &SystemClock)
+}
+
+/// Like `codes_for_window`, at the time `clock` reads
+pub fn codes_for_window_with_clock(
+    secret: &str,
+    config: &TotpConfig,
+    clock: &impl Clock,
+) -> Result<WindowCodes, AuthError> {
+    let totp = build_totp(secret, config)?;
+    let step = clock.now()? / config.step;
+
+    Ok(WindowCodes {
+        previous: totp.generate(step.saturating_sub(1) * config.step),
+        current: totp.generate(step * config.step),
+        next: totp.generate((step + 1) * config.step),
+    })
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{crypto::ct_eq, rate_limit::RateLimited, token::now, AuthError};
+
+use super::generate_numeric_code;
+
+/// How long a code stays valid, for every channel but email
+pub const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+/// How many wrong guesses are allowed before the code is discarded, for every channel
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// A code that has been issued and is waiting to be verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PendingCode {
+    pub code_hash: String,
+    /// Unix timestamp in seconds; 0 for codes stored before it was recorded
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub issued_at: u64,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+    pub attempts: u32,
+}
+
+/// Holds at most one pending out of band code per key
+///
+/// Keys are namespaced by the flow using them (`sms:`, `email:`), so one store can back several.
+pub trait CodeStore {
+    /// Store a code for the key, replacing any pending one
+    fn save(
+        &self,
+        key: &str,
+        code: PendingCode,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn load(
+        &self,
+        key: &str,
+    ) -> impl Future<Output = Result<Option<PendingCode>, AuthError>> + Send;
+
+    /// Atomically add one to the attempts of the pending code, returning the new count
+    fn increment_attempts(&self, key: &str) -> impl Future<Output = Result<u32, AuthError>> + Send;
+
+    fn remove(&self, key: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Remove the pending code only if its hash is still `code_hash`, returning whether it did
+    ///
+    /// Must be one atomic compare and delete (e.g. `DELETE ... WHERE key = $1 AND code_hash =
+    /// $2`), so two requests carrying the same code cannot both use it.
+    fn consume(
+        &self,
+        key: &str,
+        code_hash: &str,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Store the hash of a freshly generated code under the key
+pub(crate) async fn save<S: CodeStore>(
+    store: &S,
+    key: &str,
+    code: &str,
+    ttl: Duration,
+) -> Result<(), AuthError> {
+    let now = now()?;
+    let pending = PendingCode {
+        code_hash: hash_code(key, code),
+        issued_at: now,
+        expires_at: now + ttl.as_secs(),
+        attempts: 0,
+    };
+
+    store.save(key, pending).await
+}
+
+/// Generate and store a new code for the key, unless the pending one was issued less than
+/// `cooldown` ago
+///
+/// Fails with `AuthError::RateLimited` during the cooldown, so a resend button cannot be used to
+/// flood the recipient.
+pub(crate) async fn issue<S: CodeStore>(
+    store: &S,
+    key: &str,
+    digits: usize,
+    ttl: Duration,
+    cooldown: Duration,
+) -> Result<String, AuthError> {
+    if let Some(pending) = store.load(key).await? {
+        let now = now()?;
+        let ready_at = pending.issued_at + cooldown.as_secs();
+        if pending.expires_at > now && ready_at > now {
+            let retry_after = Duration::from_secs(ready_at - now);
+            return Err(RateLimited { retry_after }.into());
+        }
+    }
+
+    let code = generate_numeric_code(digits);
+    save(store, key, &code, ttl).await?;
+
+    Ok(code)
+}
+
+/// Check a code against the pending one, counting the attempt and consuming it on success
+pub(crate) async fn verify<S: CodeStore>(
+    store: &S,
+    key: &str,
+    code: &str,
+    max_attempts: u32,
+) -> Result<bool, AuthError> {
+    let pending = match store.load(key).await? {
+        Some(pending) => pending,
+        None => return Ok(false),
+    };
+
+    if pending.expires_at <= now()? {
+        store.remove(key).await?;
+        return Ok(false);
+    }
+
+    // A racing request may have used or replaced the code since it was loaded
+    let attempts = match store.increment_attempts(key).await {
+        Ok(attempts) => attempts,
+        Err(AuthError::NotFound(_)) => return Ok(false),
+        Err(error) => return Err(error),
+    };
+    if attempts > max_attempts {
+        store.remove(key).await?;
+        return Ok(false);
+    }
+
+    if !ct_eq(hash_code(key, code.trim()), &pending.code_hash) {
+        return Ok(false);
+    }
+
+    store.consume(key, &pending.code_hash).await
+}
+
+/// Bind the hash to the key so a stored hash cannot be reused for another recipient
+fn hash_code(key: &str, code: &str) -> String {
+    let digest = Sha256::digest(format!("{}:{}", key, code).as_bytes());
+
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Keeps pending codes in process memory, dropping expired ones as new ones are saved
+#[derive(Debug, Default)]
+pub struct MemoryCodeStore {
+    codes: Mutex<HashMap<String, PendingCode>>,
+}
+
+impl MemoryCodeStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, PendingCode>>, AuthError> {
+        self.codes
+            .lock()
+            .map_err(|_| AuthError::backend("Code store lock poisoned"))
+    }
+}
+
+impl CodeStore for MemoryCodeStore {
+    async fn save(&self, key: &str, code: PendingCode) -> Result<(), AuthError> {
+        let now = now()?;
+        let mut codes = self.lock()?;
+
+        codes.retain(|_, code| code.expires_at > now);
+        codes.insert(key.to_string(), code);
+
+        Ok(())
+    }
+
+    async fn load(&self, key: &str) -> Result<Option<PendingCode>, AuthError> {
+        Ok(self.lock()?.get(key).cloned())
+    }
+
+    async fn increment_attempts(&self, key: &str) -> Result<u32, AuthError> {
+        let mut codes = self.lock()?;
+        let code = codes
+            .get_mut(key)
+            .ok_or_else(|| AuthError::NotFound("No pending code".to_string()))?;
+        code.attempts += 1;
+
+        Ok(code.attempts)
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), AuthError> {
+        self.lock()?.remove(key);
+
+        Ok(())
+    }
+
+    async fn consume(&self, key: &str, code_hash: &str) -> Result<bool, AuthError> {
+        let mut codes = self.lock()?;
+        match codes.get(key) {
+            Some(code) if ct_eq(&code.code_hash, code_hash) => {
+                codes.remove(key);
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
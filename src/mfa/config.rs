@@ -0,0 +1,186 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use totp_rs::Algorithm;
+
+use crate::AuthError;
+
+/// The characters Steam Guard codes are drawn from
+pub(crate) const STEAM_ALPHABET: &str = "23456789BCDFGHJKMNPQRTVWXY";
+
+/// Steam Guard codes are always 5 characters long
+const STEAM_DIGITS: usize = 5;
+
+/// TOTP parameters shared by `generate_with` and `verify_with`
+///
+/// The default matches RFC 6238 and the plain `generate`/`verify` functions:
+/// 6 digits, a 30 second step, SHA-1 and a verification window of one step either side.
+/// Configs built by hand are checked with `validate` before use, and deserializing one fails
+/// when it is invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UncheckedTotpConfig"))]
+pub struct TotpConfig {
+    pub digits: usize,
+    pub step: u64,
+    pub algorithm: Algorithm,
+    pub window: u8,
+}
+
+impl Default for TotpConfig {
+    fn default() -> Self {
+        Self {
+            digits: 6,
+            step: 30,
+            algorithm: Algorithm::SHA1,
+            window: 1,
+        }
+    }
+}
+
+/// A `TotpConfig` as deserialized, before `validate`
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct UncheckedTotpConfig {
+    digits: usize,
+    step: u64,
+    algorithm: Algorithm,
+    window: u8,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<UncheckedTotpConfig> for TotpConfig {
+    type Error = AuthError;
+
+    fn try_from(unchecked: UncheckedTotpConfig) -> Result<Self, AuthError> {
+        let config = TotpConfig {
+            digits: unchecked.digits,
+            step: unchecked.step,
+            algorithm: unchecked.algorithm,
+            window: unchecked.window,
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+}
+
+impl TotpConfig {
+    /// Steam Guard's variant: 5 characters from Steam's alphabet over 30 second steps
+    ///
+    /// Codes are compared case-insensitively, as users often type what the app shows in lower
+    /// case.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use lonewolf_auth_toolkit::mfa::blocking::{current_code, verify_with};
+    /// use lonewolf_auth_toolkit::mfa::TotpConfig;
+    ///
+    /// let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    /// let config = TotpConfig::steam();
+    /// let code = current_code(secret, &config)?;
+    ///
+    /// assert_eq!(code.len(), 5);
+    /// assert!(verify_with(code.to_lowercase(), secret.to_string(), &config)?);
+    /// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+    /// ```
+    pub fn steam() -> Self {
+        Self {
+            digits: STEAM_DIGITS,
+            algorithm: Algorithm::Steam,
+            ..Self::default()
+        }
+    }
+
+    /// Check the digits suit the algorithm and the step is not zero
+    ///
+    /// ### Example
+    /// ```rust
+    /// use lonewolf_auth_toolkit::mfa::{blocking::current_code, TotpConfig};
+    ///
+    /// let config = TotpConfig { step: 0, ..TotpConfig::default() };
+    ///
+    /// assert!(config.validate().is_err());
+    /// assert!(current_code("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ", &config).is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), AuthError> {
+        if self.algorithm == Algorithm::Steam {
+            if self.digits != STEAM_DIGITS {
+                return Err(AuthError::InvalidInput(
+                    "Steam codes must be 5 characters".to_string(),
+                ));
+            }
+        } else if !(6..=8).contains(&self.digits) {
+            return Err(AuthError::InvalidInput(
+                "TOTP digits must be between 6 and 8".to_string(),
+            ));
+        }
+
+        if self.step == 0 {
+            return Err(AuthError::InvalidInput(
+                "TOTP step must be greater than zero".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds a validated `TotpConfig`
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::{Algorithm, TotpBuilder};
+///
+/// let config = TotpBuilder::new()
+///     .digits(8)
+///     .step(60)
+///     .algorithm(Algorithm::SHA256)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(config.digits, 8);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TotpBuilder {
+    config: TotpConfig,
+}
+
+impl TotpBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of digits in each code, 6 to 8, or 5 for `Algorithm::Steam`
+    pub fn digits(mut self, digits: usize) -> Self {
+        self.config.digits = digits;
+        self
+    }
+
+    /// Length of a time step in seconds
+    pub fn step(mut self, step: u64) -> Self {
+        self.config.step = step;
+        self
+    }
+
+    /// HMAC hash algorithm; `Algorithm::Steam` also sets the 5 characters Steam Guard shows
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        if algorithm == Algorithm::Steam {
+            self.config.digits = STEAM_DIGITS;
+        }
+        self.config.algorithm = algorithm;
+        self
+    }
+
+    /// Number of steps before and after the current one that `verify` will accept,
+    /// to tolerate clock drift on the user's device
+    pub fn window(mut self, window: u8) -> Self {
+        self.config.window = window;
+        self
+    }
+
+    pub fn build(self) -> Result<TotpConfig, AuthError> {
+        self.config.validate()?;
+
+        Ok(self.config)
+    }
+}
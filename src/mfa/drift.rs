@@ -0,0 +1,198 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::{crypto::ct_eq, token::now, AuthError};
+
+use super::{build_totp, matching_step, TotpConfig};
+
+/// Furthest, in time steps, a device's clock may be assumed to be off
+pub const DEFAULT_MAX_DRIFT: i64 = 10;
+
+/// Persists how many time steps each enrollment's device is ahead (positive) or behind
+pub trait DriftStore {
+    fn load(&self, key: &str) -> impl Future<Output = Result<Option<i64>, AuthError>> + Send;
+
+    fn save(&self, key: &str, drift: i64) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Verifies TOTP codes relative to each device's observed clock drift (RFC 6238 section 6)
+///
+/// When a code matches at a non-zero offset within the window, the offset is added to the drift
+/// stored for the key, and later codes are looked for around the drifted step. A device whose
+/// clock is consistently a step or two off therefore stops failing at the edge of the window.
+/// Drift is capped at `max_drift` steps either way. `resync` recovers a device that drifted
+/// beyond the window from two consecutive codes.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::clock::{Clock, MockClock, SystemClock};
+/// use lonewolf_auth_toolkit::mfa::blocking::current_code_with_clock;
+/// use lonewolf_auth_toolkit::mfa::drift::{DriftTracker, MemoryDriftStore};
+/// use lonewolf_auth_toolkit::mfa::TotpConfig;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+///     let config = TotpConfig::default();
+///     let drift = DriftTracker::new(MemoryDriftStore::default());
+///
+///     // A phone whose clock runs three minutes fast
+///     let phone = MockClock::at(SystemClock.now()? + 180);
+///     let code = |phone: &MockClock| current_code_with_clock(secret, &config, phone);
+///
+///     assert!(!drift.verify("SomeAccountName", &code(&phone)?, secret, &config).await?);
+///
+///     // Two consecutive codes bring it back in sync
+///     let first = code(&phone)?;
+///     phone.advance(Duration::from_secs(30));
+///     let second = code(&phone)?;
+///     assert!(drift.resync("SomeAccountName", &first, &second, secret, &config).await?);
+///
+///     phone.advance(Duration::from_secs(30));
+///     assert!(drift.verify("SomeAccountName", &code(&phone)?, secret, &config).await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct DriftTracker<S> {
+    store: S,
+    max_drift: i64,
+}
+
+impl<S: DriftStore> DriftTracker<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            max_drift: DEFAULT_MAX_DRIFT,
+        }
+    }
+
+    /// Furthest, in time steps, drift is tracked and `resync` searches
+    pub fn max_drift(mut self, steps: i64) -> Self {
+        self.max_drift = steps.abs();
+        self
+    }
+
+    /// The drift recorded for the key, in time steps
+    pub async fn drift(&self, key: &str) -> Result<Option<i64>, AuthError> {
+        self.store.load(key).await
+    }
+
+    /// Forget the key's drift, e.g. when the user enrolls a new device
+    pub async fn reset(&self, key: &str) -> Result<(), AuthError> {
+        self.store.save(key, 0).await
+    }
+
+    /// Like `mfa::verify_with`, allowing for the drift recorded for the key
+    pub async fn verify(
+        &self,
+        key: &str,
+        code: &str,
+        secret: &str,
+        config: &TotpConfig,
+    ) -> Result<bool, AuthError> {
+        Ok(self.verify_step(key, code, secret, config).await?.is_some())
+    }
+
+    /// Like `verify`, returning the matched time step, e.g. for `replay::UsedStepStore`
+    pub async fn verify_step(
+        &self,
+        key: &str,
+        code: &str,
+        secret: &str,
+        config: &TotpConfig,
+    ) -> Result<Option<u64>, AuthError> {
+        let drift = self.store.load(key).await?.unwrap_or(0);
+        let time = shift(now()?, drift, config);
+
+        let (offset, step) = match matching_step(code, secret, config, time)? {
+            Some(matched) => matched,
+            None => return Ok(None),
+        };
+
+        let updated = (drift + offset).clamp(-self.max_drift, self.max_drift);
+        if updated != drift {
+            self.store.save(key, updated).await?;
+        }
+
+        Ok(Some(step))
+    }
+
+    /// Re-establish the drift from two consecutive codes, searching `max_drift` steps either way
+    ///
+    /// Returns `false`, leaving the drift unchanged, unless `second` is the code right after
+    /// `first`. Asking for two codes keeps the wider search from making codes easier to guess.
+    pub async fn resync(
+        &self,
+        key: &str,
+        first: &str,
+        second: &str,
+        secret: &str,
+        config: &TotpConfig,
+    ) -> Result<bool, AuthError> {
+        let totp = build_totp(secret, config)?;
+        let current_step = (now()? / config.step) as i64;
+
+        for drift in (-self.max_drift..=self.max_drift).rev() {
+            let step = current_step + drift - 1;
+            if step < 0 {
+                continue;
+            }
+
+            let step = step as u64;
+            // Evaluate both so the search takes the same time whether or not the first matched
+            let first_matches = ct_eq(first, totp.generate(step * config.step));
+            let second_matches = ct_eq(second, totp.generate((step + 1) * config.step));
+
+            if first_matches && second_matches {
+                self.store.save(key, drift).await?;
+
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// `time` moved by `drift` steps
+fn shift(time: u64, drift: i64, config: &TotpConfig) -> u64 {
+    let offset = drift.unsigned_abs() * config.step;
+
+    if drift < 0 {
+        time.saturating_sub(offset)
+    } else {
+        time.saturating_add(offset)
+    }
+}
+
+/// Keeps each key's drift in process memory
+#[derive(Debug, Default)]
+pub struct MemoryDriftStore {
+    drifts: Mutex<HashMap<String, i64>>,
+}
+
+impl MemoryDriftStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, i64>>, AuthError> {
+        self.drifts
+            .lock()
+            .map_err(|_| AuthError::backend("Drift store lock poisoned"))
+    }
+}
+
+impl DriftStore for MemoryDriftStore {
+    async fn load(&self, key: &str) -> Result<Option<i64>, AuthError> {
+        Ok(self.lock()?.get(key).copied())
+    }
+
+    async fn save(&self, key: &str, drift: i64) -> Result<(), AuthError> {
+        self.lock()?.insert(key.to_string(), drift);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use crate::{otel, AuthError};
+
+use super::{
+    code::{self, CodeStore},
+    otp::DEFAULT_COOLDOWN,
+};
+
+pub use super::code::DEFAULT_MAX_ATTEMPTS;
+
+/// How long an email code stays valid, longer than SMS to allow for slow delivery
+pub const DEFAULT_TTL: Duration = Duration::from_secs(600);
+
+/// Issues numeric codes bound to an email address and verifies them
+///
+/// Delivery is left to the caller: `issue` returns the code to put in the message, e.g. with
+/// `email::Mailer::send_code`. To have codes sent for you, use `otp::Otp` with a `Mailer` as the
+/// channel and the address as the key; the codes are stored under the same keys. Addresses are
+/// compared case-insensitively.
+///
+/// ### Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::mfa::code::{CodeStore, PendingCode};
+/// use lonewolf_auth_toolkit::mfa::email::EmailOtp;
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, PendingCode>>);
+///
+/// impl CodeStore for MemoryStore {
+///     async fn save(&self, key: &str, code: PendingCode) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(key.to_string(), code);
+///         Ok(())
+///     }
+///
+///     async fn load(&self, key: &str) -> Result<Option<PendingCode>, AuthError> {
+///         Ok(self.0.lock().unwrap().get(key).cloned())
+///     }
+///
+///     async fn increment_attempts(&self, key: &str) -> Result<u32, AuthError> {
+///         let mut codes = self.0.lock().unwrap();
+///         let code = codes.get_mut(key).ok_or_else(|| AuthError::NotFound("No pending code".to_string()))?;
+///         code.attempts += 1;
+///         Ok(code.attempts)
+///     }
+///
+///     async fn remove(&self, key: &str) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().remove(key);
+///         Ok(())
+///     }
+///
+///     async fn consume(&self, key: &str, code_hash: &str) -> Result<bool, AuthError> {
+///         let mut codes = self.0.lock().unwrap();
+///         if codes.get(key).is_some_and(|code| code.code_hash == code_hash) {
+///             return Ok(codes.remove(key).is_some());
+///         }
+///         Ok(false)
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let email = EmailOtp::new(MemoryStore::default()).digits(8)?;
+///     let code = email.issue("Someone@Example.com").await?;
+///
+///     assert_eq!(code.len(), 8);
+///     assert!(!email.verify("other@example.com", code.clone()).await?);
+///     assert!(email.verify("someone@example.com", code.clone()).await?);
+///     assert!(!email.verify("someone@example.com", code).await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct EmailOtp<S> {
+    store: S,
+    digits: usize,
+    ttl: Duration,
+    max_attempts: u32,
+    cooldown: Duration,
+}
+
+impl<S: CodeStore> EmailOtp<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            digits: 6,
+            ttl: DEFAULT_TTL,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+
+    /// Length of the generated codes, 6 to 8
+    pub fn digits(mut self, digits: usize) -> Result<Self, AuthError> {
+        if !(6..=8).contains(&digits) {
+            return Err(AuthError::InvalidInput(
+                "Email codes must be between 6 and 8 digits".to_string(),
+            ));
+        }
+
+        self.digits = digits;
+
+        Ok(self)
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Minimum time between two codes for the same address, `otp::DEFAULT_COOLDOWN` by default
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Generate a new code for the address, replacing any pending one
+    ///
+    /// Fails with `AuthError::RateLimited` if a code was issued for the address within the
+    /// cooldown.
+    pub async fn issue(&self, email: &str) -> Result<String, AuthError> {
+        let key = store_key(email);
+        let issue = code::issue(&self.store, &key, self.digits, self.ttl, self.cooldown);
+
+        otel::span("mfa.email.issue", Some(&key), issue).await
+    }
+
+    /// Check a code entered by the user, consuming it on success
+    pub async fn verify(&self, email: &str, code: String) -> Result<bool, AuthError> {
+        let key = store_key(email);
+        let verify = code::verify(&self.store, &key, &code, self.max_attempts);
+
+        otel::span("mfa.email.verify", Some(&key), verify).await
+    }
+}
+
+fn store_key(email: &str) -> String {
+    format!("email:{}", email.trim().to_lowercase())
+}
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{token::now, AuthError};
+
+use super::{generate_with, verify_with, TotpConfig};
+
+/// How long a user has to confirm a new TOTP secret
+pub const DEFAULT_TTL: Duration = Duration::from_secs(600);
+
+/// Where an enrollment is in its lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum EnrollmentState {
+    /// Waiting for the user to enter a code from the new secret
+    Pending,
+    /// The user proved possession and the secret may be used for sign in
+    Confirmed,
+    /// The enrollment was not confirmed in time and must be started again
+    Expired,
+}
+
+/// A TOTP secret that only becomes active once the user confirms a code from it
+///
+/// The fields are public so the enrollment can be persisted between the request that shows the
+/// QR code and the one that confirms it.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::enrollment::{Enrollment, EnrollmentState, DEFAULT_TTL};
+/// use lonewolf_auth_toolkit::mfa::TotpConfig;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let (mut enrollment, qr_code) = Enrollment::start(
+///         "SomeIssuer".to_string(),
+///         "SomeAccountName".to_string(),
+///         &TotpConfig::default(),
+///         DEFAULT_TTL,
+///     )
+///     .await?;
+///
+///     assert_eq!(enrollment.state()?, EnrollmentState::Pending);
+///     assert_eq!(enrollment.active_secret()?, None);
+///
+///     let confirmed = enrollment.confirm("123456".to_string()).await?;
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Enrollment {
+    pub account: String,
+    pub secret: String,
+    pub config: TotpConfig,
+    /// Unix timestamp in seconds after which an unconfirmed enrollment expires
+    pub expires_at: u64,
+    /// Unix timestamp in seconds of the successful confirmation
+    pub confirmed_at: Option<u64>,
+}
+
+impl Enrollment {
+    /// Generate a new secret for the account, returning the pending enrollment and its QR code
+    pub async fn start(
+        issuer: String,
+        account_name: String,
+        config: &TotpConfig,
+        ttl: Duration,
+    ) -> Result<(Self, String), AuthError> {
+        let (qr_code, secret) = generate_with(issuer, account_name.clone(), config).await?;
+        let enrollment = Self {
+            account: account_name,
+            secret,
+            config: *config,
+            expires_at: now()? + ttl.as_secs(),
+            confirmed_at: None,
+        };
+
+        Ok((enrollment, qr_code))
+    }
+
+    pub fn state(&self) -> Result<EnrollmentState, AuthError> {
+        if self.confirmed_at.is_some() {
+            Ok(EnrollmentState::Confirmed)
+        } else if self.expires_at <= now()? {
+            Ok(EnrollmentState::Expired)
+        } else {
+            Ok(EnrollmentState::Pending)
+        }
+    }
+
+    /// Activate the enrollment if `code` matches the new secret
+    ///
+    /// Returns `false` for a wrong code, leaving the enrollment pending so the user can retry.
+    /// Confirming an expired or already confirmed enrollment is an error.
+    pub async fn confirm(&mut self, code: String) -> Result<bool, AuthError> {
+        match self.state()? {
+            EnrollmentState::Pending => {}
+            EnrollmentState::Confirmed => {
+                return Err(AuthError::InvalidState(
+                    "Enrollment is already confirmed".to_string(),
+                ))
+            }
+            EnrollmentState::Expired => {
+                return Err(AuthError::InvalidState(
+                    "Enrollment has expired".to_string(),
+                ))
+            }
+        }
+
+        if !verify_with(code, self.secret.clone(), &self.config).await? {
+            return Ok(false);
+        }
+
+        self.confirmed_at = Some(now()?);
+
+        Ok(true)
+    }
+
+    /// The secret to verify sign in codes against, only once the enrollment is confirmed
+    pub fn active_secret(&self) -> Result<Option<&str>, AuthError> {
+        match self.state()? {
+            EnrollmentState::Confirmed => Ok(Some(&self.secret)),
+            _ => Ok(None),
+        }
+    }
+}
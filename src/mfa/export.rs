@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{crypto::sealed::Sealer, token::now, AuthError};
+
+use super::{
+    recovery::RecoveryCodeStore,
+    registry::{Factor, FactorStore},
+};
+
+/// Version of the export format written by `MfaExport::seal`
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Prefix of sealed exports, followed by the format version
+const PREFIX: &str = "lwmfa";
+
+/// A snapshot of an account's second factors, for moving them between environments or backups
+///
+/// TOTP secrets and WebAuthn credentials are exported as they are. Recovery codes are only stored
+/// hashed, so just the number left is recorded; prompt the user to generate new ones after an
+/// import. Sealed exports are `lwmfa<format version>.<sealed JSON>` and are encrypted with a
+/// `Sealer`, so the key rotation rules for stored secrets apply to them too. Opening fails with
+/// `AuthError::Verification` for the wrong key and `AuthError::Malformed` for anything that is not
+/// an export.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::crypto::sealed::{generate_key, Sealer};
+/// use lonewolf_auth_toolkit::mfa::export::MfaExport;
+/// use lonewolf_auth_toolkit::mfa::recovery::{self, MemoryRecoveryCodeStore};
+/// use lonewolf_auth_toolkit::mfa::registry::{Factor, FactorKind, FactorStore, MemoryFactorStore};
+/// use lonewolf_auth_toolkit::mfa::TotpConfig;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let staging = MemoryFactorStore::default();
+///     let codes = MemoryRecoveryCodeStore::default();
+///     staging
+///         .insert(Factor {
+///             id: "SomeFactorId".to_string(),
+///             account: "SomeAccountName".to_string(),
+///             label: "Phone".to_string(),
+///             kind: FactorKind::Totp {
+///                 secret: "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string(),
+///                 config: TotpConfig::default(),
+///             },
+///             created_at: 1_700_000_000,
+///         })
+///         .await?;
+///     recovery::generate(&codes, "SomeAccountName").await?;
+///
+///     let sealer = Sealer::new(1, &generate_key())?;
+///     let export = MfaExport::collect(&staging, &codes, "SomeAccountName").await?;
+///     let blob = export.seal(&sealer)?;
+///
+///     let production = MemoryFactorStore::default();
+///     let export = MfaExport::open(&blob, &sealer)?;
+///     assert_eq!(export.recovery_codes_remaining, 10);
+///     assert_eq!(export.import(&production, "SomeOtherAccountName").await?, 1);
+///     assert_eq!(production.list("SomeOtherAccountName").await?.len(), 1);
+///
+///     // Importing again skips the factors that are already there
+///     let export = MfaExport::open(&blob, &sealer)?;
+///     assert_eq!(export.import(&production, "SomeOtherAccountName").await?, 0);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MfaExport {
+    pub account: String,
+    /// Unix timestamp in seconds
+    pub exported_at: u64,
+    pub factors: Vec<Factor>,
+    pub recovery_codes_remaining: usize,
+}
+
+impl MfaExport {
+    /// Gather the account's factors and recovery code count
+    pub async fn collect<F: FactorStore, R: RecoveryCodeStore>(
+        factors: &F,
+        recovery_codes: &R,
+        account: &str,
+    ) -> Result<Self, AuthError> {
+        Ok(Self {
+            account: account.to_string(),
+            exported_at: now()?,
+            factors: factors.list(account).await?,
+            recovery_codes_remaining: recovery_codes.remaining(account).await?,
+        })
+    }
+
+    /// Encrypt the export with the sealer's current key
+    pub fn seal(&self, sealer: &Sealer) -> Result<String, AuthError> {
+        let json = Zeroizing::new(serde_json::to_vec(self)?);
+
+        Ok(format!(
+            "{}{}.{}",
+            PREFIX,
+            FORMAT_VERSION,
+            sealer.seal(&json, &associated_data(FORMAT_VERSION))?
+        ))
+    }
+
+    /// Decrypt an export sealed with any of the sealer's key versions
+    pub fn open(blob: &str, sealer: &Sealer) -> Result<Self, AuthError> {
+        let (version, sealed) = blob
+            .strip_prefix(PREFIX)
+            .and_then(|rest| rest.split_once('.'))
+            .ok_or_else(|| AuthError::Malformed("Not an MFA export".to_string()))?;
+        let version: u32 = version
+            .parse()
+            .map_err(|_| AuthError::Malformed("Not an MFA export".to_string()))?;
+        if version != FORMAT_VERSION {
+            return Err(AuthError::Malformed(format!(
+                "Unsupported MFA export version {}",
+                version
+            )));
+        }
+
+        let json = Zeroizing::new(sealer.open(sealed, &associated_data(version))?);
+
+        serde_json::from_slice(&json)
+            .map_err(|error| AuthError::Malformed(format!("Invalid MFA export: {}", error)))
+    }
+
+    /// Add the exported factors to `account`, returning how many were added
+    ///
+    /// Factors keep their ids, labels and creation times. Ones the account already has, by id,
+    /// are skipped, so an interrupted import can be run again.
+    pub async fn import<F: FactorStore>(
+        self,
+        store: &F,
+        account: &str,
+    ) -> Result<usize, AuthError> {
+        let existing: Vec<String> = store
+            .list(account)
+            .await?
+            .into_iter()
+            .map(|factor| factor.id)
+            .collect();
+
+        let mut imported = 0;
+        for mut factor in self.factors {
+            if existing.contains(&factor.id) {
+                continue;
+            }
+
+            factor.account = account.to_string();
+            store.insert(factor).await?;
+            imported += 1;
+        }
+
+        Ok(imported)
+    }
+}
+
+/// Binds the sealed JSON to the export format, so other sealed values cannot pass as exports
+fn associated_data(version: u32) -> Vec<u8> {
+    format!("{}{}", PREFIX, version).into_bytes()
+}
@@ -0,0 +1,264 @@
+use std::{collections::HashMap, future::Future, sync::Mutex};
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use crate::{crypto::ct_eq, AuthError};
+
+use super::{
+    decode_secret, generate_secret, qr,
+    uri::{OtpAuthUri, OtpKind},
+    TotpConfig,
+};
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// How many counter values ahead of the stored counter `verify` will search
+pub const DEFAULT_LOOK_AHEAD: u64 = 10;
+
+/// How many counter values ahead of the stored counter `resync` will search
+pub const DEFAULT_RESYNC_WINDOW: u64 = 100;
+
+/// Persists the HOTP moving factor for each secret
+///
+/// `advance` must only move the counter when the stored value still equals `expected`,
+/// so two concurrent verifications of the same code cannot both succeed.
+pub trait CounterStore {
+    /// Load the next expected counter for the given key
+    fn load(&self, key: &str) -> impl Future<Output = Result<u64, AuthError>> + Send;
+
+    /// Move the counter for the given key from `expected` to `next`, returning `false` if it had
+    /// already been moved by someone else
+    fn advance(
+        &self,
+        key: &str,
+        expected: u64,
+        next: u64,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Compute the 6 digit HOTP code for a secret at the given counter (RFC 4226)
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::hotp::generate_code;
+///
+/// let code = generate_code("12345678901234567890".to_string(), 0);
+///
+/// assert_eq!(code, "755224");
+///
+/// let code = generate_code("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string(), 0);
+///
+/// assert_eq!(code, "755224");
+/// ```
+pub fn generate_code(secret: String, counter: u64) -> String {
+    let mut mac =
+        HmacSha1::new_from_slice(&decode_secret(&secret)).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let result = mac.finalize().into_bytes();
+
+    let offset = (result[result.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        result[offset],
+        result[offset + 1],
+        result[offset + 2],
+        result[offset + 3],
+    ]) & 0x7fff_ffff;
+
+    format!("{:06}", binary % 1_000_000)
+}
+
+/// Generate a HOTP 6 Digit QR Code
+///
+/// Returns the base64 encoded PNG QR code and the Base32 secret. The provisioning URI starts
+/// the counter at zero.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::hotp::generate;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let result = generate("SomeIssuer".to_string(), "SomeAccountName".to_string()).await?;
+///
+///     println!("{:?}", result.0);
+///     println!("{:?}", result.1);
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn generate(issuer: String, account_name: String) -> Result<(String, String), AuthError> {
+    if issuer.contains(':') || account_name.contains(':') {
+        return Err(AuthError::InvalidInput(
+            "Issuer and account name must not contain ':'".to_string(),
+        ));
+    }
+
+    let secret_string = generate_secret();
+    let url = OtpAuthUri {
+        kind: OtpKind::Hotp { counter: 0 },
+        issuer: Some(issuer),
+        account_name,
+        secret: secret_string.clone(),
+        config: TotpConfig::default(),
+    }
+    .to_string();
+
+    Ok((qr::base64(&url)?, secret_string))
+}
+
+/// Verify a HOTP 6 Digit Code
+///
+/// Searches `counter..=counter + DEFAULT_LOOK_AHEAD` and returns the counter to store for the
+/// next verification, or `None` when the code does not match. A match on the last possible
+/// counter fails with `AuthError::InvalidState`, as there is no counter left to store.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::hotp::{generate_code, verify};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let next_counter = verify("287082".to_string(), "12345678901234567890".to_string(), 0).await?;
+///
+///     assert_eq!(next_counter, Some(2));
+///
+///     let last = generate_code("12345678901234567890".to_string(), u64::MAX);
+///     assert!(verify(last, "12345678901234567890".to_string(), u64::MAX).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn verify(code: String, secret: String, counter: u64) -> Result<Option<u64>, AuthError> {
+    find_counter(&code, &secret, counter, DEFAULT_LOOK_AHEAD)
+}
+
+/// Verify a HOTP 6 Digit Code against the counter held in a `CounterStore`
+///
+/// The stored counter is advanced past the matching value so the code cannot be used again.
+pub async fn verify_with_store<S: CounterStore>(
+    store: &S,
+    key: &str,
+    code: String,
+    secret: String,
+) -> Result<bool, AuthError> {
+    let counter = store.load(key).await?;
+
+    match find_counter(&code, &secret, counter, DEFAULT_LOOK_AHEAD)? {
+        Some(next) => store.advance(key, counter, next).await,
+        None => Ok(false),
+    }
+}
+
+/// Resynchronize a token whose counter has drifted beyond the look-ahead window
+///
+/// Per RFC 4226 section 7.4 the user submits two consecutive codes, which must match
+/// consecutive counters within `window` of the stored counter.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::hotp::resync;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let secret = "12345678901234567890".to_string();
+///     let next_counter = resync("399871".to_string(), "520489".to_string(), secret, 0, 100).await?;
+///
+///     assert_eq!(next_counter, Some(10));
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn resync(
+    first_code: String,
+    second_code: String,
+    secret: String,
+    counter: u64,
+    window: u64,
+) -> Result<Option<u64>, AuthError> {
+    let matched = (counter..=counter.saturating_add(window)).find(|candidate| {
+        ct_eq(generate_code(secret.clone(), *candidate), &first_code)
+            && candidate
+                .checked_add(1)
+                .is_some_and(|next| ct_eq(generate_code(secret.clone(), next), &second_code))
+    });
+
+    matched.map(|matched| after(matched, 2)).transpose()
+}
+
+/// Resynchronize a token against the counter held in a `CounterStore`
+pub async fn resync_with_store<S: CounterStore>(
+    store: &S,
+    key: &str,
+    first_code: String,
+    second_code: String,
+    secret: String,
+) -> Result<bool, AuthError> {
+    let counter = store.load(key).await?;
+
+    match resync(
+        first_code,
+        second_code,
+        secret,
+        counter,
+        DEFAULT_RESYNC_WINDOW,
+    )
+    .await?
+    {
+        Some(next) => store.advance(key, counter, next).await,
+        None => Ok(false),
+    }
+}
+
+/// Returns the counter after the first match in `counter..=counter + window`
+fn find_counter(
+    code: &str,
+    secret: &str,
+    counter: u64,
+    window: u64,
+) -> Result<Option<u64>, AuthError> {
+    (counter..=counter.saturating_add(window))
+        .find(|candidate| ct_eq(generate_code(secret.to_string(), *candidate), code))
+        .map(|matched| after(matched, 1))
+        .transpose()
+}
+
+/// The counter `steps` past `matched`, failing once the counter space is used up
+fn after(matched: u64, steps: u64) -> Result<u64, AuthError> {
+    matched
+        .checked_add(steps)
+        .ok_or_else(|| AuthError::InvalidState("HOTP counter is exhausted".to_string()))
+}
+
+/// Keeps HOTP counters in process memory; keys start at counter 0
+#[derive(Debug, Default)]
+pub struct MemoryCounterStore {
+    counters: Mutex<HashMap<String, u64>>,
+}
+
+impl CounterStore for MemoryCounterStore {
+    async fn load(&self, key: &str) -> Result<u64, AuthError> {
+        Ok(self
+            .counters
+            .lock()
+            .map_err(|_| AuthError::backend("Counter store lock poisoned"))?
+            .get(key)
+            .copied()
+            .unwrap_or(0))
+    }
+
+    async fn advance(&self, key: &str, expected: u64, next: u64) -> Result<bool, AuthError> {
+        let mut counters = self
+            .counters
+            .lock()
+            .map_err(|_| AuthError::backend("Counter store lock poisoned"))?;
+
+        let counter = counters.entry(key.to_string()).or_insert(0);
+        if *counter != expected {
+            return Ok(false);
+        }
+        *counter = next;
+
+        Ok(true)
+    }
+}
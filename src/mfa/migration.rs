@@ -0,0 +1,257 @@
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD},
+    Engine,
+};
+use url::Url;
+
+use crate::AuthError;
+
+use super::{
+    uri::{OtpAuthUri, OtpKind},
+    Algorithm, TotpConfig,
+};
+
+const BASE32: base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+/// The accounts in one `otpauth-migration://` QR code from Google Authenticator's export
+///
+/// Large exports are split over several QR codes that share a `batch_id`; import all
+/// `batch_size` of them to get every account.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::migration::MigrationBatch;
+/// use lonewolf_auth_toolkit::mfa::uri::OtpKind;
+///
+/// let batch = MigrationBatch::parse("otpauth-migration://offline?data=CjUKCkhlbGxvId6tvu8SGEV4YW1wbGU6YWxpY2VAZ29vZ2xlLmNvbRoHRXhhbXBsZSABKAEwAgolChQxMjM0NTY3ODkwMTIzNDU2Nzg5MBIDYm9iGgAgAigCMAE4BxABGAEgACi5YA%3D%3D")?;
+///
+/// assert_eq!(batch.batch_size, 1);
+/// assert_eq!(batch.entries.len(), 2);
+///
+/// let alice = &batch.entries[0];
+/// assert_eq!(alice.kind, OtpKind::Totp);
+/// assert_eq!(alice.issuer.as_deref(), Some("Example"));
+/// assert_eq!(alice.account_name, "alice@google.com");
+/// assert_eq!(alice.secret, "JBSWY3DPEHPK3PXP");
+///
+/// let bob = &batch.entries[1];
+/// assert_eq!(bob.kind, OtpKind::Hotp { counter: 7 });
+/// assert_eq!(bob.config.digits, 8);
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationBatch {
+    pub entries: Vec<OtpAuthUri>,
+    /// Position of this QR code in the export, from 0
+    pub batch_index: u32,
+    /// Number of QR codes in the export
+    pub batch_size: u32,
+    pub batch_id: i32,
+}
+
+impl MigrationBatch {
+    /// Parse an `otpauth-migration://offline?data=...` URI
+    ///
+    /// Fails with `AuthError::Malformed` for anything that is not a valid export, including
+    /// entries using MD5, which no verifier in this crate supports.
+    pub fn parse(uri: &str) -> Result<Self, AuthError> {
+        let url = Url::parse(uri)?;
+
+        if url.scheme() != "otpauth-migration" {
+            return Err(AuthError::Malformed(
+                "URI scheme must be otpauth-migration".to_string(),
+            ));
+        }
+
+        // Exports sometimes leave `+` unescaped, which query decoding turns into a space
+        let data = url
+            .query_pairs()
+            .find(|(key, _)| key == "data")
+            .map(|(_, value)| value.replace(' ', "+"))
+            .ok_or_else(|| AuthError::Malformed("URI is missing the data".to_string()))?;
+        let payload = STANDARD
+            .decode(&data)
+            .or_else(|_| STANDARD_NO_PAD.decode(data.trim_end_matches('=')))?;
+
+        let mut batch = Self {
+            entries: Vec::new(),
+            batch_index: 0,
+            batch_size: 1,
+            batch_id: 0,
+        };
+
+        for field in Fields::new(&payload) {
+            match field? {
+                (1, Value::Bytes(parameters)) => batch.entries.push(entry(parameters)?),
+                (3, Value::Varint(size)) => batch.batch_size = u32::try_from(size)?,
+                (4, Value::Varint(index)) => batch.batch_index = u32::try_from(index)?,
+                // int32 fields are sign extended to 64 bits on the wire
+                (5, Value::Varint(id)) => batch.batch_id = id as i64 as i32,
+                _ => {}
+            }
+        }
+
+        Ok(batch)
+    }
+}
+
+/// Decode one `OtpParameters` message
+fn entry(message: &[u8]) -> Result<OtpAuthUri, AuthError> {
+    let mut secret = Vec::new();
+    let mut name = String::new();
+    let mut issuer = String::new();
+    let mut config = TotpConfig::default();
+    let mut hotp = false;
+    let mut counter = 0;
+
+    for field in Fields::new(message) {
+        match field? {
+            (1, Value::Bytes(bytes)) => secret = bytes.to_vec(),
+            (2, Value::Bytes(bytes)) => name = String::from_utf8(bytes.to_vec())?,
+            (3, Value::Bytes(bytes)) => issuer = String::from_utf8(bytes.to_vec())?,
+            (4, Value::Varint(algorithm)) => {
+                config.algorithm = match algorithm {
+                    0 | 1 => Algorithm::SHA1,
+                    2 => Algorithm::SHA256,
+                    3 => Algorithm::SHA512,
+                    _ => {
+                        return Err(AuthError::Malformed(format!(
+                            "Unsupported algorithm in export for {}",
+                            name
+                        )))
+                    }
+                }
+            }
+            (5, Value::Varint(digits)) => config.digits = if digits == 2 { 8 } else { 6 },
+            (6, Value::Varint(kind)) => hotp = kind == 1,
+            (7, Value::Varint(value)) => counter = value,
+            _ => {}
+        }
+    }
+
+    if secret.is_empty() {
+        return Err(AuthError::MalformedSecret(format!(
+            "Export entry {} has no secret",
+            name
+        )));
+    }
+
+    // Names are often `Issuer:account`, like the label of an otpauth URI
+    let account_name = match name.split_once(':') {
+        Some((label_issuer, account_name)) if issuer.is_empty() || label_issuer == issuer => {
+            if issuer.is_empty() {
+                issuer = label_issuer.to_string();
+            }
+            account_name.trim_start().to_string()
+        }
+        _ => name,
+    };
+
+    Ok(OtpAuthUri {
+        kind: if hotp {
+            OtpKind::Hotp { counter }
+        } else {
+            OtpKind::Totp
+        },
+        issuer: Some(issuer).filter(|issuer| !issuer.is_empty()),
+        account_name,
+        secret: base32::encode(BASE32, &secret),
+        config,
+    })
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    /// A fixed width field, which the export format does not use
+    Other,
+}
+
+/// The `(field number, value)` pairs of a protobuf message
+struct Fields<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn varint(&mut self) -> Result<u64, AuthError> {
+        let mut value = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.data.split_first().ok_or_else(truncated)?;
+            self.data = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(AuthError::Malformed(
+            "Export varint is too long".to_string(),
+        ))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], AuthError> {
+        if self.data.len() < len {
+            return Err(truncated());
+        }
+
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+
+        Ok(taken)
+    }
+
+    fn field(&mut self) -> Result<(u64, Value<'a>), AuthError> {
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Other
+            }
+            2 => {
+                let len = usize::try_from(self.varint()?)?;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Other
+            }
+            wire_type => {
+                return Err(AuthError::Malformed(format!(
+                    "Unsupported wire type {} in export",
+                    wire_type
+                )))
+            }
+        };
+
+        Ok((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u64, Value<'a>), AuthError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let field = self.field();
+        if field.is_err() {
+            // Stop after the first error rather than reading garbage
+            self.data = &[];
+        }
+
+        Some(field)
+    }
+}
+
+fn truncated() -> AuthError {
+    AuthError::Malformed("Export data is truncated".to_string())
+}
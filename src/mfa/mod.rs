@@ -1,10 +1,55 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+pub mod blocking;
+pub mod code;
+pub mod drift;
+pub mod email;
+pub mod enrollment;
+#[cfg(feature = "serde")]
+pub mod export;
+pub mod hotp;
+pub mod migration;
+pub mod otp;
+pub mod phone;
+pub mod push;
+pub mod qr;
+pub mod recovery;
+pub mod registry;
+pub mod replay;
+pub mod rotation;
+pub mod sms;
+pub mod trusted;
+#[cfg(feature = "twilio")]
+pub mod twilio;
+pub mod uri;
 
-use anyhow::Error;
-use rand::{thread_rng, Rng};
-use totp_rs::{Rfc6238, TOTP};
+mod config;
+mod outcome;
+mod secret;
 
-/// Generate a random string
+use qr::QrStyle;
+use totp_rs::TOTP;
+use uri::{OtpAuthUri, OtpKind};
+
+use crate::{
+    clock::Clock,
+    crypto::ct_eq,
+    rate_limit::Throttle,
+    token::random::{Alphabet, RandomToken},
+    AuthError,
+};
+
+pub use config::{TotpBuilder, TotpConfig};
+pub use outcome::{VerifyOutcome, EXPIRED_STEPS};
+pub use secret::{
+    decode_secret, generate_numeric_code, generate_secret, Secret, DEFAULT_SECRET_BYTES,
+    MIN_SECRET_BYTES,
+};
+pub use totp_rs::Algorithm;
+
+/// Generate a random string of 32 random bytes in hex
+///
+/// Not for TOTP: used as a secret, the hex text itself becomes the key, carrying only 4 bits of
+/// entropy per byte. Use `generate_secret` or `Secret` for that, and `token::random::RandomToken`
+/// for other lengths, alphabets or a prefix.
 ///
 /// ### Example
 /// ```rust
@@ -13,15 +58,14 @@ use totp_rs::{Rfc6238, TOTP};
 /// let random_string = generate_random_string();
 /// ```
 pub fn generate_random_string() -> String {
-    let mut rng = thread_rng();
-    let random_bytes: [u8; 32] = rng.gen();
-    let hex_string: String = random_bytes.iter().map(|b| format!("{:02x}", b)).collect();
-
-    hex_string
+    RandomToken::new().alphabet(Alphabet::Hex).generate()
 }
 
 /// Generate a TOTP 6 Digit QR Code
 ///
+/// Returns the base64 encoded PNG QR code and the Base32 secret to store for the account. Nothing
+/// here waits on I/O; `blocking::generate` is the same without an async runtime.
+///
 /// ### Example
 /// ```rust
 /// use lonewolf_auth_toolkit::mfa::generate;
@@ -29,51 +73,574 @@ pub fn generate_random_string() -> String {
 /// #[tokio::main]
 /// pub async fn main() -> Result<(), anyhow::Error> {
 ///     let result = generate("SomeIssuer".to_string(), "SomeAccountName".to_string()).await?;
-/// 
+///
+///     println!("{:?}", result.0);
+///     println!("{:?}", result.1);
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn generate(issuer: String, account_name: String) -> Result<(String, String), AuthError> {
+    generate_with(issuer, account_name, &TotpConfig::default()).await
+}
+
+/// Generate a TOTP QR Code using a custom configuration
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::{generate_with, Algorithm, TotpBuilder};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = TotpBuilder::new().digits(8).algorithm(Algorithm::SHA512).build()?;
+///     let result = generate_with("SomeIssuer".to_string(), "SomeAccountName".to_string(), &config).await?;
+///
 ///     println!("{:?}", result.0);
 ///     println!("{:?}", result.1);
-/// 
+///
 ///     Ok(())
 /// }
 /// ```
-pub async fn generate(issuer: String, account_name: String) -> Result<(String, String), Error> {
-    let secret_string = generate_random_string();
-    let mut rfc = Rfc6238::with_defaults(secret_string.clone().into_bytes().to_vec())?;
+pub async fn generate_with(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+) -> Result<(String, String), AuthError> {
+    blocking::generate_with(issuer, account_name, config)
+}
 
-    rfc.digits(6)?;
-    rfc.issuer(issuer);
-    rfc.account_name(account_name);
+/// Generate a TOTP QR Code as raw PNG bytes, ready to be served as `image/png`
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::{generate_png, TotpConfig};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let (png, secret) = generate_png("SomeIssuer".to_string(), "SomeAccountName".to_string(), &TotpConfig::default()).await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn generate_png(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+) -> Result<(Vec<u8>, String), AuthError> {
+    blocking::generate_png(issuer, account_name, config)
+}
 
-    let totp = TOTP::from_rfc6238(rfc)?;
-    let qr_code = totp.get_qr_base64();
+/// Generate a TOTP QR Code as an SVG document
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::{generate_svg, TotpConfig};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let (svg, secret) = generate_svg("SomeIssuer".to_string(), "SomeAccountName".to_string(), &TotpConfig::default()).await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn generate_svg(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+) -> Result<(String, String), AuthError> {
+    blocking::generate_svg(issuer, account_name, config)
+}
 
-    match qr_code {
-        Ok(qr_code) => Ok((qr_code, secret_string)),
-        Err(error) => Err(Error::msg(error)),
-    }
+/// Generate a TOTP QR Code as PNG bytes drawn with a custom style, e.g. brand colours and a logo
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::qr::{Color, QrStyle};
+/// use lonewolf_auth_toolkit::mfa::{generate_png_styled, TotpConfig};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let style = QrStyle::new().module_size(6).foreground(Color::hex("#1B2A4A")?);
+///     let (png, secret) = generate_png_styled("SomeIssuer".to_string(), "SomeAccountName".to_string(), &TotpConfig::default(), &style).await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn generate_png_styled(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+    style: &QrStyle,
+) -> Result<(Vec<u8>, String), AuthError> {
+    blocking::generate_png_styled(issuer, account_name, config, style)
+}
+
+/// Generate a TOTP QR Code as an SVG document drawn with a custom style
+pub async fn generate_svg_styled(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+    style: &QrStyle,
+) -> Result<(String, String), AuthError> {
+    blocking::generate_svg_styled(issuer, account_name, config, style)
+}
+
+/// Generate a TOTP QR Code rendered as text for a terminal
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::{generate_ascii, TotpConfig};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let (qr, secret) = generate_ascii("SomeIssuer".to_string(), "SomeAccountName".to_string(), &TotpConfig::default()).await?;
+///
+///     println!("{}", qr);
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn generate_ascii(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+) -> Result<(String, String), AuthError> {
+    blocking::generate_ascii(issuer, account_name, config)
 }
 
 /// Verify a TOTP 6 Digit Code
-/// 
+///
+/// Codes from one step either side of the current one are also accepted. `blocking::verify` is
+/// the same without an async runtime.
+///
 /// ### Example
 /// ```rust
 /// use lonewolf_auth_toolkit::mfa::verify;
-/// 
+///
 /// #[tokio::main]
 /// pub async fn main() -> Result<(), anyhow::Error> {
 ///     let verified = verify("123456".to_string(), "5BAD23B477D625825019A4C895E8C5B8D22A88D3193E6928B7FC7AEFF1CC578F2A9551A1919ADE27EC50E48DFD4A2F95D9B52636C141E5B5FADE5C24A0EC71E7".to_string()).await?;
-/// 
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn verify(code: String, secret: String) -> Result<bool, AuthError> {
+    verify_with(code, secret, &TotpConfig::default()).await
+}
+
+/// Verify a TOTP Code using a custom configuration
+///
+/// The configuration must match the one the secret was provisioned with.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::{verify_with, Algorithm, TotpBuilder};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = TotpBuilder::new().digits(8).algorithm(Algorithm::SHA512).build()?;
+///     let verified = verify_with("12345678".to_string(), "5BAD23B477D625825019A4C895E8C5B8".to_string(), &config).await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn verify_with(
+    code: String,
+    secret: String,
+    config: &TotpConfig,
+) -> Result<bool, AuthError> {
+    Ok(verify_with_offset(code, secret, config).await?.is_some())
+}
+
+/// Verify a TOTP Code and report which step offset it matched
+///
+/// Every step within `config.window` of the current one is checked. The offset is negative when
+/// the device clock is behind the server, which callers can record to track drift.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::{verify_with_offset, TotpBuilder};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = TotpBuilder::new().window(2).build()?;
+///     let offset = verify_with_offset("123456".to_string(), "5BAD23B477D625825019A4C895E8C5B8".to_string(), &config).await?;
+///
+///     if let Some(offset) = offset {
+///         println!("Matched {} steps away", offset);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn verify_with_offset(
+    code: String,
+    secret: String,
+    config: &TotpConfig,
+) -> Result<Option<i64>, AuthError> {
+    blocking::verify_with_offset(code, secret, config)
+}
+
+/// Like `verify_with`, reading the time from `clock`, e.g. a `MockClock` in tests
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::clock::MockClock;
+/// use lonewolf_auth_toolkit::mfa::{blocking::current_code_with_clock, verify_with_clock, TotpConfig};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+///     let config = TotpConfig::default();
+///     let clock = MockClock::at(1_700_000_000);
+///
+///     let code = current_code_with_clock(secret, &config, &clock)?;
+///     assert!(verify_with_clock(code.clone(), secret.to_string(), &config, &clock).await?);
+///
+///     // Two steps later the code is outside the window
+///     clock.advance(Duration::from_secs(60));
+///     assert!(!verify_with_clock(code, secret.to_string(), &config, &clock).await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn verify_with_clock(
+    code: String,
+    secret: String,
+    config: &TotpConfig,
+    clock: &impl Clock,
+) -> Result<bool, AuthError> {
+    blocking::verify_with_clock(code, secret, config, clock)
+}
+
+/// Verify a TOTP Code and report why it was rejected
+///
+/// Unlike `verify_with`, a malformed code or unusable secret is an outcome rather than an error,
+/// and a code from just before the window is reported as `Expired` instead of simply wrong.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::clock::MockClock;
+/// use lonewolf_auth_toolkit::mfa::{
+///     blocking::current_code_with_clock, verify_outcome_with_clock, TotpConfig, VerifyOutcome,
+/// };
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+///     let config = TotpConfig::default();
+///     let clock = MockClock::at(1_700_000_000);
+///     let code = current_code_with_clock(secret, &config, &clock)?;
+///
+///     let outcome = verify_outcome_with_clock(&code, secret, &config, &clock).await?;
+///     assert_eq!(outcome, VerifyOutcome::Matched { offset: 0 });
+///
+///     clock.advance(Duration::from_secs(90));
+///     let outcome = verify_outcome_with_clock(&code, secret, &config, &clock).await?;
+///     assert_eq!(outcome, VerifyOutcome::Expired { offset: -3 });
+///     println!("Rejected: {}", outcome.reason());
+///
+///     let outcome = verify_outcome_with_clock("12 34", secret, &config, &clock).await?;
+///     assert_eq!(outcome, VerifyOutcome::MalformedCode);
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn verify_outcome(
+    code: &str,
+    secret: &str,
+    config: &TotpConfig,
+) -> Result<VerifyOutcome, AuthError> {
+    blocking::verify_outcome(code, secret, config)
+}
+
+/// Like `verify_outcome`, reading the time from `clock`
+pub async fn verify_outcome_with_clock(
+    code: &str,
+    secret: &str,
+    config: &TotpConfig,
+    clock: &impl Clock,
+) -> Result<VerifyOutcome, AuthError> {
+    blocking::verify_outcome_with_clock(code, secret, config, clock)
+}
+
+/// The codes an authenticator app shows for a secret around one moment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowCodes {
+    /// The code of the step before the current one
+    pub previous: String,
+    pub current: String,
+    /// The code of the step after the current one
+    pub next: String,
+}
+
+/// The code an authenticator app shows for `secret` right now, e.g. for tests, CLIs and
+/// services that sign in to a partner's TOTP protected API
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::{current_code, verify_with, TotpConfig};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+///     let config = TotpConfig::default();
+///     let code = current_code(secret, &config).await?;
+///
+///     assert!(verify_with(code, secret.to_string(), &config).await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn current_code(secret: &str, config: &TotpConfig) -> Result<String, AuthError> {
+    blocking::current_code(secret, config)
+}
+
+/// Like `current_code`, at the time `clock` reads
+pub async fn current_code_with_clock(
+    secret: &str,
+    config: &TotpConfig,
+    clock: &impl Clock,
+) -> Result<String, AuthError> {
+    blocking::current_code_with_clock(secret, config, clock)
+}
+
+/// The codes for the previous, current and next step, all of which a default `verify` accepts
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::clock::MockClock;
+/// use lonewolf_auth_toolkit::mfa::{codes_for_window_with_clock, verify_with_clock, TotpConfig};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+///     let config = TotpConfig::default();
+///     let clock = MockClock::at(1_700_000_000);
+///     let codes = codes_for_window_with_clock(secret, &config, &clock).await?;
+///
+///     for code in [codes.previous, codes.current, codes.next] {
+///         assert!(verify_with_clock(code, secret.to_string(), &config, &clock).await?);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn codes_for_window(secret: &str, config: &TotpConfig) -> Result<WindowCodes, AuthError> {
+    blocking::codes_for_window(secret, config)
+}
+
+/// Like `codes_for_window`, at the time `clock` reads
+pub async fn codes_for_window_with_clock(
+    secret: &str,
+    config: &TotpConfig,
+    clock: &impl Clock,
+) -> Result<WindowCodes, AuthError> {
+    blocking::codes_for_window_with_clock(secret, config, clock)
+}
+
+/// Verify a TOTP Code, counting the attempt against a rate limiter
+///
+/// Fails with `AuthError::RateLimited` once `key` has run out of attempts, before the code is
+/// checked. A successful verification resets the key's attempts. Takes a fixed window
+/// `RateLimiter` or a token bucket or sliding window `Limiter`.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::mfa::{verify_limited, TotpConfig};
+/// use lonewolf_auth_toolkit::rate_limit::{MemoryAttemptStore, RateLimiter};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let limiter = RateLimiter::new(MemoryAttemptStore::default(), 5, Duration::from_secs(300));
+///     let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string();
+///
+///     match verify_limited(&limiter, "SomeAccountName", "123456".to_string(), secret, &TotpConfig::default()).await {
+///         Ok(verified) => println!("Verified: {}", verified),
+///         Err(AuthError::RateLimited(limited)) => println!("Retry in {:?}", limited.retry_after),
+///         Err(error) => return Err(error.into()),
+///     }
+///
 ///     Ok(())
 /// }
 /// ```
-pub async fn verify(code: String, secret: String) -> Result<bool, Error> {
-    let mut rfc = Rfc6238::with_defaults(secret.clone().into_bytes().to_vec())?;
+pub async fn verify_limited<L: Throttle>(
+    limiter: &L,
+    key: &str,
+    code: String,
+    secret: String,
+    config: &TotpConfig,
+) -> Result<bool, AuthError> {
+    limiter.attempt(key).await?;
+
+    let verified = verify_with(code, secret, config).await?;
+
+    if verified {
+        limiter.reset(key).await?;
+    }
+
+    Ok(verified)
+}
+
+/// Find the step within the window around `time` that produced `code`, as `(offset, step)`
+pub(crate) fn matching_step(
+    code: &str,
+    secret: &str,
+    config: &TotpConfig,
+    time: u64,
+) -> Result<Option<(i64, u64)>, AuthError> {
+    let totp = build_totp(secret, config)?;
+    let code = normalize(code, config);
+
+    Ok(find_step(
+        &totp,
+        &code,
+        config,
+        time,
+        window_offsets(config),
+    ))
+}
+
+/// Classify `code` against the steps around `time`
+pub(crate) fn outcome_at(
+    code: &str,
+    secret: &str,
+    config: &TotpConfig,
+    time: u64,
+) -> VerifyOutcome {
+    let code = normalize(code, config);
+    let well_formed = match config.algorithm {
+        Algorithm::Steam => code.chars().all(|c| config::STEAM_ALPHABET.contains(c)),
+        _ => code.bytes().all(|byte| byte.is_ascii_digit()),
+    };
+    if code.len() != config.digits || !well_formed {
+        return VerifyOutcome::MalformedCode;
+    }
+    let code = code.as_str();
+    let Ok(totp) = build_totp(secret, config) else {
+        return VerifyOutcome::InvalidSecret;
+    };
+
+    if let Some((offset, _)) = find_step(&totp, code, config, time, window_offsets(config)) {
+        return VerifyOutcome::Matched { offset };
+    }
 
-    rfc.digits(6)?;
+    let window = config.window as i64;
+    let expired = (1..=EXPIRED_STEPS as i64).map(|distance| -(window + distance));
+    match find_step(&totp, code, config, time, expired) {
+        Some((offset, _)) => VerifyOutcome::Expired { offset },
+        None => VerifyOutcome::Mismatch,
+    }
+}
+
+/// Steam codes are upper case but users often type them in lower case
+fn normalize(code: &str, config: &TotpConfig) -> String {
+    match config.algorithm {
+        Algorithm::Steam => code.to_ascii_uppercase(),
+        _ => code.to_string(),
+    }
+}
+
+/// The current step, then each step within the window, nearest first
+fn window_offsets(config: &TotpConfig) -> impl Iterator<Item = i64> {
+    let window = config.window as i64;
+
+    std::iter::once(0).chain((1..=window).flat_map(|distance| [-distance, distance]))
+}
+
+fn find_step(
+    totp: &TOTP,
+    code: &str,
+    config: &TotpConfig,
+    time: u64,
+    offsets: impl Iterator<Item = i64>,
+) -> Option<(i64, u64)> {
+    let current_step = (time / config.step) as i64;
+
+    for offset in offsets {
+        let step = current_step + offset;
+
+        if step < 0 {
+            continue;
+        }
+
+        if ct_eq(code, totp.generate(step as u64 * config.step)) {
+            return Some((offset, step as u64));
+        }
+    }
+
+    None
+}
+
+/// Create a new secret and the provisioning URL to encode in the QR code
+fn provision(
+    issuer: String,
+    account_name: String,
+    config: &TotpConfig,
+) -> Result<(String, String), AuthError> {
+    if issuer.contains(':') || account_name.contains(':') {
+        return Err(AuthError::InvalidInput(
+            "Issuer and account name must not contain ':'".to_string(),
+        ));
+    }
+
+    let secret_string = generate_secret();
+
+    build_totp(&secret_string, config)?;
+
+    let uri = OtpAuthUri {
+        kind: OtpKind::Totp,
+        issuer: Some(issuer),
+        account_name,
+        secret: secret_string.clone(),
+        config: *config,
+    };
+
+    Ok((uri.to_string(), secret_string))
+}
+
+fn build_totp(secret: &str, config: &TotpConfig) -> Result<TOTP, AuthError> {
+    config.validate()?;
+    let secret = decode_secret(secret);
 
-    let totp = TOTP::from_rfc6238(rfc)?;
-    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-    let token = totp.generate(time);
+    // `TOTP::new` only accepts the RFC 6238 digit counts, so check Steam secrets here
+    if config.algorithm == Algorithm::Steam {
+        if secret.len() < 16 {
+            return Err(AuthError::MalformedSecret(
+                "Secret must be at least 128 bits".to_string(),
+            ));
+        }
 
-    Ok(code == token)
-}
\ No newline at end of file
+        let totp = TOTP::new_unchecked(
+            config.algorithm,
+            config.digits,
+            0,
+            config.step,
+            secret,
+            None,
+            String::new(),
+        );
+
+        return Ok(totp);
+    }
+
+    let totp = TOTP::new(
+        config.algorithm,
+        config.digits,
+        0,
+        config.step,
+        secret,
+        None,
+        String::new(),
+    )
+    .map_err(|error| AuthError::MalformedSecret(error.to_string()))?;
+
+    Ok(totp)
+}
@@ -0,0 +1,151 @@
+use std::{future::Future, time::Duration};
+
+use crate::{otel, AuthError};
+
+use super::code::{self, CodeStore};
+
+pub use super::code::{DEFAULT_MAX_ATTEMPTS, DEFAULT_TTL};
+
+/// How long after sending a code another one can be sent to the same key
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Delivers one time codes over one medium, e.g. SMS, email or a push notification
+///
+/// Implemented for `sms::SmsChannel` and `email::Mailer`. Push approvals through `push::PushMfa`
+/// carry no code, but a notifier that shows a code on the device fits here too.
+pub trait OtpChannel {
+    /// Namespaces this channel's codes in a shared `CodeStore`, e.g. `sms`
+    const NAME: &'static str;
+
+    /// Send `code` to `recipient`, a phone number, address or device id
+    fn deliver(
+        &self,
+        recipient: &str,
+        code: &str,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Sends numeric codes over any `OtpChannel` and verifies them
+///
+/// Only a hash of each code is stored, bound to its key. A code is single use, expires after the
+/// TTL and is discarded after too many wrong guesses. Sending again within the cooldown fails
+/// with `AuthError::RateLimited`; afterwards the new code replaces the pending one.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::mfa::code::MemoryCodeStore;
+/// use lonewolf_auth_toolkit::mfa::otp::{Otp, OtpChannel};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// /// Posts codes into a chat app
+/// #[derive(Default)]
+/// struct Chat(Mutex<Vec<String>>);
+///
+/// impl OtpChannel for Chat {
+///     const NAME: &'static str = "chat";
+///
+///     async fn deliver(&self, _recipient: &str, code: &str) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().push(code.to_string());
+///         Ok(())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let otp = Otp::new(Chat::default(), MemoryCodeStore::default());
+///
+///     otp.send("SomeAccountName", "@someone").await?;
+///     assert!(matches!(
+///         otp.send("SomeAccountName", "@someone").await,
+///         Err(AuthError::RateLimited(_))
+///     ));
+///
+///     let code = otp.channel().0.lock().unwrap()[0].clone();
+///     assert!(otp.verify("SomeAccountName", code.clone()).await?);
+///     assert!(!otp.verify("SomeAccountName", code).await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Otp<C, S> {
+    channel: C,
+    store: S,
+    digits: usize,
+    ttl: Duration,
+    max_attempts: u32,
+    cooldown: Duration,
+}
+
+impl<C: OtpChannel, S: CodeStore> Otp<C, S> {
+    pub fn new(channel: C, store: S) -> Self {
+        Self {
+            channel,
+            store,
+            digits: 6,
+            ttl: DEFAULT_TTL,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+
+    /// Length of the generated codes
+    pub fn digits(mut self, digits: usize) -> Self {
+        self.digits = digits;
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Minimum time between two codes for the same key
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn channel(&self) -> &C {
+        &self.channel
+    }
+
+    /// Generate a new code for the key and deliver it to the recipient
+    pub async fn send(&self, key: &str, recipient: &str) -> Result<(), AuthError> {
+        let key = self.key(key);
+
+        otel::span("mfa.otp.send", Some(&key), self.dispatch(&key, recipient)).await
+    }
+
+    /// Check a code entered by the user, consuming it on success
+    pub async fn verify(&self, key: &str, code: String) -> Result<bool, AuthError> {
+        let key = self.key(key);
+
+        otel::span("mfa.otp.verify", Some(&key), self.check(&key, &code)).await
+    }
+
+    pub(crate) fn channel_mut(&mut self) -> &mut C {
+        &mut self.channel
+    }
+
+    pub(crate) async fn dispatch(&self, key: &str, recipient: &str) -> Result<(), AuthError> {
+        let code = code::issue(&self.store, key, self.digits, self.ttl, self.cooldown).await?;
+
+        self.channel.deliver(recipient, &code).await
+    }
+
+    pub(crate) async fn check(&self, key: &str, code: &str) -> Result<bool, AuthError> {
+        code::verify(&self.store, key, code, self.max_attempts).await
+    }
+
+    /// Namespace the key by channel so one store can back several
+    pub(crate) fn key(&self, key: &str) -> String {
+        format!("{}:{}", C::NAME, key)
+    }
+}
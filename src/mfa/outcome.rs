@@ -0,0 +1,46 @@
+/// How many steps before the window a code is still recognised as expired rather than wrong
+pub const EXPIRED_STEPS: u8 = 4;
+
+/// Why a TOTP code was or was not accepted, from `verify_outcome`
+///
+/// Only `Matched` means the code is valid. The other variants let an application tell the user
+/// what went wrong, e.g. "that code has expired, wait for the next one", and log failures
+/// precisely. Avoid showing `InvalidSecret` to users; it means the stored secret is broken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyOutcome {
+    /// The code belongs to the step `offset` steps from the current one, within the window
+    Matched { offset: i64 },
+    /// The code belongs to a step up to `EXPIRED_STEPS` before the window, `offset` steps ago
+    Expired { offset: i64 },
+    /// The code is well formed, but no step near the current one produced it
+    Mismatch,
+    /// The code is not the configured number of ASCII digits
+    MalformedCode,
+    /// The secret cannot be used, e.g. it is shorter than 128 bits
+    InvalidSecret,
+}
+
+impl VerifyOutcome {
+    pub fn is_match(&self) -> bool {
+        matches!(self, Self::Matched { .. })
+    }
+
+    /// The matched step offset, for drift tracking
+    pub fn offset(&self) -> Option<i64> {
+        match self {
+            Self::Matched { offset } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// A stable snake case name for logs and metrics
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Matched { .. } => "matched",
+            Self::Expired { .. } => "expired",
+            Self::Mismatch => "mismatch",
+            Self::MalformedCode => "malformed_code",
+            Self::InvalidSecret => "invalid_secret",
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::{fmt, ops::RangeInclusive};
+
+use crate::AuthError;
+
+/// E.164 allows at most 15 digits including the country code
+const MAX_DIGITS: usize = 15;
+
+/// Shortest national number accepted for countries without a length rule below
+const MIN_NATIONAL_DIGITS: usize = 4;
+
+/// Two digit country calling codes; every other code starting 2 to 9 has three digits
+const TWO_DIGIT_CODES: &[u16] = &[
+    20, 27, 30, 31, 32, 33, 34, 36, 39, 40, 41, 43, 44, 45, 46, 47, 48, 49, 51, 52, 53, 54, 55, 56,
+    57, 58, 60, 61, 62, 63, 64, 65, 66, 81, 82, 84, 86, 90, 91, 92, 93, 94, 95, 98,
+];
+
+/// Numbering rules for the countries numbers are checked and typed in more detail for
+struct Region {
+    region: &'static str,
+    code: u16,
+    /// Dialled before national numbers within the country and dropped internationally
+    trunk: &'static str,
+    lengths: RangeInclusive<usize>,
+    /// National number prefixes assigned to mobiles; empty where they cannot be told apart
+    mobile: &'static [&'static str],
+}
+
+const REGIONS: &[Region] = &[
+    Region {
+        region: "US",
+        code: 1,
+        trunk: "1",
+        lengths: 10..=10,
+        mobile: &[],
+    },
+    Region {
+        region: "CA",
+        code: 1,
+        trunk: "1",
+        lengths: 10..=10,
+        mobile: &[],
+    },
+    Region {
+        region: "ZA",
+        code: 27,
+        trunk: "0",
+        lengths: 9..=9,
+        mobile: &["6", "7", "81", "82", "83", "84"],
+    },
+    Region {
+        region: "FR",
+        code: 33,
+        trunk: "0",
+        lengths: 9..=9,
+        mobile: &["6", "7"],
+    },
+    Region {
+        region: "NL",
+        code: 31,
+        trunk: "0",
+        lengths: 9..=9,
+        mobile: &["6"],
+    },
+    Region {
+        region: "GB",
+        code: 44,
+        trunk: "0",
+        lengths: 9..=10,
+        mobile: &["7"],
+    },
+    Region {
+        region: "DE",
+        code: 49,
+        trunk: "0",
+        lengths: 6..=13,
+        mobile: &["15", "16", "17"],
+    },
+    Region {
+        region: "AU",
+        code: 61,
+        trunk: "0",
+        lengths: 9..=9,
+        mobile: &["4"],
+    },
+    Region {
+        region: "IN",
+        code: 91,
+        trunk: "0",
+        lengths: 10..=10,
+        mobile: &["6", "7", "8", "9"],
+    },
+    Region {
+        region: "NG",
+        code: 234,
+        trunk: "0",
+        lengths: 8..=10,
+        mobile: &["70", "80", "81", "90", "91"],
+    },
+    Region {
+        region: "KE",
+        code: 254,
+        trunk: "0",
+        lengths: 9..=9,
+        mobile: &["1", "7"],
+    },
+    Region {
+        region: "IE",
+        code: 353,
+        trunk: "0",
+        lengths: 7..=9,
+        mobile: &["8"],
+    },
+];
+
+/// What kind of line a number belongs to, as far as its prefix tells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberType {
+    Mobile,
+    /// Outside the country's mobile ranges: a landline, toll free or premium number, none of
+    /// which can receive text messages
+    FixedLine,
+    /// The country's mobile numbers cannot be told apart by prefix, or it has no rules here
+    Unknown,
+}
+
+/// A phone number in canonical E.164 form
+///
+/// `parse` accepts the ways people type numbers: with `+` or `00` and a country code, or a
+/// national number with its trunk prefix given the country it is in, plus spaces, dashes, dots
+/// and brackets. Numbers for a few common countries are checked against their national lengths
+/// and typed by prefix; elsewhere only E.164's limits apply. This is a basic sanity check before
+/// spending SMS credits, not a replacement for a full numbering database.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::phone::{NumberType, PhoneNumber};
+///
+/// let number = PhoneNumber::parse("082 123 4567", Some("ZA"))?;
+/// assert_eq!(number.to_string(), "+27821234567");
+/// assert_eq!(number.region(), Some("ZA"));
+/// assert_eq!(number.number_type(), NumberType::Mobile);
+///
+/// let number = PhoneNumber::parse("+44 (0)20 7946 0958", None)?;
+/// assert_eq!(number.e164(), "+442079460958");
+/// assert_eq!(number.number_type(), NumberType::FixedLine);
+///
+/// assert!(PhoneNumber::parse("0821234567", None).is_err());
+/// assert!(PhoneNumber::parse("+27 82 123", None).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhoneNumber {
+    country_code: u16,
+    national: String,
+    region: Option<&'static str>,
+    number_type: NumberType,
+}
+
+impl PhoneNumber {
+    /// Parse a number, reading national numbers as dialled in `default_region`
+    ///
+    /// `default_region` is an ISO 3166 country code such as `ZA`. Fails with
+    /// `AuthError::InvalidInput` for numbers that cannot be valid.
+    pub fn parse(input: &str, default_region: Option<&str>) -> Result<Self, AuthError> {
+        let invalid = |reason: &str| Err(AuthError::InvalidInput(reason.to_string()));
+
+        let input = input.trim();
+        let (international, rest) = match input.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => match input.strip_prefix("00") {
+                Some(rest) => (true, rest),
+                None => (false, input),
+            },
+        };
+
+        let mut digits = String::with_capacity(rest.len());
+        for c in rest.chars() {
+            match c {
+                '0'..='9' => digits.push(c),
+                ' ' | '-' | '.' | '(' | ')' | '/' => {}
+                _ => return invalid("Phone number contains invalid characters"),
+            }
+        }
+
+        let default_region = match default_region {
+            Some(region) => Some(
+                REGIONS
+                    .iter()
+                    .find(|known| known.region.eq_ignore_ascii_case(region.trim()))
+                    .ok_or_else(|| {
+                        AuthError::InvalidInput(format!("Unknown phone region {}", region))
+                    })?,
+            ),
+            None => None,
+        };
+
+        let (country_code, mut national) = if international {
+            let code_len = calling_code_len(&digits);
+            if code_len == 0 || digits.len() <= code_len {
+                return invalid("Phone number has no valid country code");
+            }
+
+            let (code, national) = digits.split_at(code_len);
+            (code.parse::<u16>()?, national.to_string())
+        } else {
+            match default_region {
+                Some(region) => (region.code, digits),
+                None => return invalid("Phone number needs a country code"),
+            }
+        };
+
+        // Countries sharing a code, like the US and Canada, share numbering rules but cannot be
+        // told apart without the default region
+        let mut sharing = REGIONS.iter().filter(|region| region.code == country_code);
+        let (rules, region) = match default_region {
+            Some(default) if default.code == country_code => (Some(default), Some(default.region)),
+            _ => {
+                let rules = sharing.next();
+                let region = rules.filter(|_| sharing.next().is_none());
+                (rules, region.map(|rules| rules.region))
+            }
+        };
+
+        let number_type = match rules {
+            Some(rules) => {
+                // Trunk prefixes are dialled nationally, and often written as +44 (0)20...
+                if let Some(stripped) = national.strip_prefix(rules.trunk) {
+                    if rules.lengths.contains(&stripped.len()) {
+                        national = stripped.to_string();
+                    }
+                }
+                if !rules.lengths.contains(&national.len())
+                    || national.starts_with(rules.trunk)
+                    || national.starts_with('0')
+                {
+                    return invalid("Phone number is not a valid number for its country");
+                }
+
+                if rules.mobile.is_empty() {
+                    NumberType::Unknown
+                } else if rules
+                    .mobile
+                    .iter()
+                    .any(|prefix| national.starts_with(prefix))
+                {
+                    NumberType::Mobile
+                } else {
+                    NumberType::FixedLine
+                }
+            }
+            None => NumberType::Unknown,
+        };
+
+        if national.len() < MIN_NATIONAL_DIGITS
+            || country_code.to_string().len() + national.len() > MAX_DIGITS
+        {
+            return invalid("Phone number is too short or too long");
+        }
+
+        Ok(Self {
+            country_code,
+            national,
+            region,
+            number_type,
+        })
+    }
+
+    /// `+` followed by the country code and national number, e.g. `+27821234567`
+    pub fn e164(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn country_code(&self) -> u16 {
+        self.country_code
+    }
+
+    /// The national significant number, without any trunk prefix
+    pub fn national_number(&self) -> &str {
+        &self.national
+    }
+
+    /// ISO 3166 country code, if it is one with rules here and the number is not ambiguous
+    pub fn region(&self) -> Option<&'static str> {
+        self.region
+    }
+
+    pub fn number_type(&self) -> NumberType {
+        self.number_type
+    }
+}
+
+impl fmt::Display for PhoneNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{}{}", self.country_code, self.national)
+    }
+}
+
+/// Country calling codes are prefix free, so their length follows from the first digits
+fn calling_code_len(digits: &str) -> usize {
+    match digits.as_bytes() {
+        [b'0', ..] | [] => 0,
+        [b'1' | b'7', ..] => 1,
+        [_, _, ..] if TWO_DIGIT_CODES.contains(&digits[..2].parse().unwrap_or(0)) => 2,
+        [_, _, _, ..] => 3,
+        _ => 0,
+    }
+}
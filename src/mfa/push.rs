@@ -0,0 +1,408 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use ring::signature::{UnparsedPublicKey, ED25519};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::{crypto::MIN_HMAC_KEY_LEN, otel, token::now, AuthError};
+
+use super::generate_secret;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long the user has to respond to a push challenge
+pub const DEFAULT_TTL: Duration = Duration::from_secs(120);
+
+/// Where a push challenge is in its lifecycle
+///
+/// Challenges start `Pending` and move exactly once to one of the other states.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PushStatus {
+    Pending,
+    Approved,
+    Denied,
+    Expired,
+}
+
+/// A mobile device enrolled for push approval
+///
+/// The app generates an Ed25519 key pair on the device and registers the public key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PushDevice {
+    pub id: String,
+    pub account: String,
+    pub public_key: Vec<u8>,
+}
+
+/// A request for the user to approve a sign in on their device
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PushChallenge {
+    pub id: String,
+    pub account: String,
+    pub device_id: String,
+    /// Human readable description shown to the user, e.g. "Sign in from Firefox on Windows"
+    pub context: String,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+    pub status: PushStatus,
+    /// Server signed token the device must echo back in its response
+    pub token: String,
+}
+
+/// The device's answer to a challenge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushResponse {
+    pub token: String,
+    pub approved: bool,
+    /// Ed25519 signature by the device key over `PushResponse::message(token, approved)`
+    pub signature: Vec<u8>,
+}
+
+impl PushResponse {
+    /// The bytes the device signs to approve or deny the challenge behind `token`
+    pub fn message(token: &str, approved: bool) -> Vec<u8> {
+        let decision = if approved { "approve" } else { "deny" };
+
+        format!("{}:{}", token, decision).into_bytes()
+    }
+}
+
+/// Delivers challenges to devices, e.g. through FCM or APNs
+pub trait PushNotifier {
+    fn notify(
+        &self,
+        device: &PushDevice,
+        challenge: &PushChallenge,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Persists push challenges between creation, the device response and polling
+pub trait PushChallengeStore {
+    fn save(&self, challenge: PushChallenge) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn load(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<PushChallenge>, AuthError>> + Send;
+
+    /// Atomically move a `Pending` challenge to `status`, returning `false` if it was no longer
+    /// pending
+    fn resolve(
+        &self,
+        id: &str,
+        status: PushStatus,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Creates push approval challenges and records the device's decision
+///
+/// ### Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::mfa::push::{
+///     PushChallenge, PushChallengeStore, PushDevice, PushMfa, PushNotifier, PushResponse, PushStatus,
+/// };
+/// use lonewolf_auth_toolkit::AuthError;
+/// use ring::signature::{Ed25519KeyPair, KeyPair};
+///
+/// #[derive(Default)]
+/// struct Outbox(Mutex<Vec<String>>);
+///
+/// impl PushNotifier for Outbox {
+///     async fn notify(&self, _device: &PushDevice, challenge: &PushChallenge) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().push(challenge.token.clone());
+///         Ok(())
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, PushChallenge>>);
+///
+/// impl PushChallengeStore for MemoryStore {
+///     async fn save(&self, challenge: PushChallenge) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(challenge.id.clone(), challenge);
+///         Ok(())
+///     }
+///
+///     async fn load(&self, id: &str) -> Result<Option<PushChallenge>, AuthError> {
+///         Ok(self.0.lock().unwrap().get(id).cloned())
+///     }
+///
+///     async fn resolve(&self, id: &str, status: PushStatus) -> Result<bool, AuthError> {
+///         let mut challenges = self.0.lock().unwrap();
+///
+///         match challenges.get_mut(id) {
+///             Some(challenge) if challenge.status == PushStatus::Pending => {
+///                 challenge.status = status;
+///                 Ok(true)
+///             }
+///             _ => Ok(false),
+///         }
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let rng = ring::rand::SystemRandom::new();
+///     let device_key = Ed25519KeyPair::from_pkcs8(Ed25519KeyPair::generate_pkcs8(&rng).unwrap().as_ref()).unwrap();
+///     let device = PushDevice {
+///         id: "phone-1".to_string(),
+///         account: "SomeAccountName".to_string(),
+///         public_key: device_key.public_key().as_ref().to_vec(),
+///     };
+///
+///     let push = PushMfa::new(Outbox::default(), MemoryStore::default(), &[7u8; 32])?;
+///     let challenge = push.create(&device, "Sign in from Firefox on Windows").await?;
+///
+///     assert_eq!(push.status(&challenge.id).await?, PushStatus::Pending);
+///
+///     // On the device
+///     let token = push.notifier().0.lock().unwrap()[0].clone();
+///     let signature = device_key.sign(&PushResponse::message(&token, true)).as_ref().to_vec();
+///     let status = push.respond(&device, PushResponse { token, approved: true, signature }).await?;
+///
+///     assert_eq!(status, PushStatus::Approved);
+///     assert_eq!(push.status(&challenge.id).await?, PushStatus::Approved);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct PushMfa<N, S> {
+    notifier: N,
+    store: S,
+    signing_key: Zeroizing<Vec<u8>>,
+    ttl: Duration,
+}
+
+impl<N: PushNotifier, S: PushChallengeStore> PushMfa<N, S> {
+    /// `signing_key` authenticates challenge tokens and should be a random server secret of at
+    /// least `MIN_HMAC_KEY_LEN` bytes
+    pub fn new(notifier: N, store: S, signing_key: &[u8]) -> Result<Self, AuthError> {
+        if signing_key.len() < MIN_HMAC_KEY_LEN {
+            return Err(AuthError::InvalidInput(format!(
+                "Push challenge signing keys must be at least {} bytes",
+                MIN_HMAC_KEY_LEN
+            )));
+        }
+
+        Ok(Self {
+            notifier,
+            store,
+            signing_key: Zeroizing::new(signing_key.to_vec()),
+            ttl: DEFAULT_TTL,
+        })
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn notifier(&self) -> &N {
+        &self.notifier
+    }
+
+    /// Create a challenge and send it to the device
+    pub async fn create(
+        &self,
+        device: &PushDevice,
+        context: &str,
+    ) -> Result<PushChallenge, AuthError> {
+        let id = generate_secret();
+        let create = async {
+            let expires_at = now()? + self.ttl.as_secs();
+            let challenge = PushChallenge {
+                token: self.sign(&id, &device.account, expires_at),
+                id: id.clone(),
+                account: device.account.clone(),
+                device_id: device.id.clone(),
+                context: context.to_string(),
+                expires_at,
+                status: PushStatus::Pending,
+            };
+
+            self.store.save(challenge.clone()).await?;
+            self.notifier.notify(device, &challenge).await?;
+
+            Ok(challenge)
+        };
+
+        otel::span("mfa.push.create", Some(&id), create).await
+    }
+
+    /// Record the device's decision, returning the challenge's resulting status
+    ///
+    /// The token must be one this server issued for the device, and the response must be signed
+    /// by the device key. Responses arriving after expiry leave the challenge `Expired`.
+    pub async fn respond(
+        &self,
+        device: &PushDevice,
+        response: PushResponse,
+    ) -> Result<PushStatus, AuthError> {
+        let flow = response.token.split('.').next().map(str::to_string);
+        otel::span(
+            "mfa.push.respond",
+            flow.as_deref(),
+            self.resolve(device, response),
+        )
+        .await
+    }
+
+    async fn resolve(
+        &self,
+        device: &PushDevice,
+        response: PushResponse,
+    ) -> Result<PushStatus, AuthError> {
+        let id = response
+            .token
+            .split('.')
+            .next()
+            .ok_or_else(|| AuthError::Malformed("Malformed push token".to_string()))?;
+        let challenge = self
+            .store
+            .load(id)
+            .await?
+            .ok_or_else(|| AuthError::NotFound("Unknown push challenge".to_string()))?;
+
+        if challenge.device_id != device.id || challenge.account != device.account {
+            return Err(AuthError::Verification(
+                "Push challenge was sent to a different device".to_string(),
+            ));
+        }
+
+        self.verify_token(&response.token, &challenge.account)?;
+
+        UnparsedPublicKey::new(&ED25519, &device.public_key)
+            .verify(
+                &PushResponse::message(&response.token, response.approved),
+                &response.signature,
+            )
+            .map_err(|_| {
+                AuthError::Verification("Push response signature is invalid".to_string())
+            })?;
+
+        if challenge.status != PushStatus::Pending {
+            return Ok(challenge.status);
+        }
+
+        let status = if challenge.expires_at <= now()? {
+            PushStatus::Expired
+        } else if response.approved {
+            PushStatus::Approved
+        } else {
+            PushStatus::Denied
+        };
+
+        if self.store.resolve(id, status).await? {
+            return Ok(status);
+        }
+
+        self.load_status(id).await
+    }
+
+    /// Current status of a challenge, for the waiting sign in page to poll
+    pub async fn status(&self, id: &str) -> Result<PushStatus, AuthError> {
+        otel::span("mfa.push.status", Some(id), self.load_status(id)).await
+    }
+
+    async fn load_status(&self, id: &str) -> Result<PushStatus, AuthError> {
+        let challenge = self
+            .store
+            .load(id)
+            .await?
+            .ok_or_else(|| AuthError::NotFound("Unknown push challenge".to_string()))?;
+
+        if challenge.status == PushStatus::Pending && challenge.expires_at <= now()? {
+            self.store.resolve(id, PushStatus::Expired).await?;
+
+            return Ok(self
+                .store
+                .load(id)
+                .await?
+                .map_or(PushStatus::Expired, |challenge| challenge.status));
+        }
+
+        Ok(challenge.status)
+    }
+
+    fn mac(&self, id: &str, account: &str, expires_at: u64) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.signing_key).expect("HMAC accepts any key length");
+        mac.update(format!("{}.{}.{}", id, account, expires_at).as_bytes());
+        mac
+    }
+
+    fn sign(&self, id: &str, account: &str, expires_at: u64) -> String {
+        let tag = self.mac(id, account, expires_at).finalize().into_bytes();
+
+        format!("{}.{}.{}", id, expires_at, URL_SAFE_NO_PAD.encode(tag))
+    }
+
+    fn verify_token(&self, token: &str, account: &str) -> Result<(), AuthError> {
+        let mut parts = token.split('.');
+        let (id, expires_at, tag) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(expires_at), Some(tag), None) => (id, expires_at.parse()?, tag),
+            _ => return Err(AuthError::Malformed("Malformed push token".to_string())),
+        };
+
+        self.mac(id, account, expires_at)
+            .verify_slice(&URL_SAFE_NO_PAD.decode(tag)?)
+            .map_err(|_| AuthError::Verification("Push token signature is invalid".to_string()))
+    }
+}
+
+/// Keeps push challenges in process memory, dropping expired ones as new ones are saved
+#[derive(Debug, Default)]
+pub struct MemoryPushChallengeStore {
+    challenges: Mutex<HashMap<String, PushChallenge>>,
+}
+
+impl MemoryPushChallengeStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, PushChallenge>>, AuthError> {
+        self.challenges
+            .lock()
+            .map_err(|_| AuthError::backend("Push challenge store lock poisoned"))
+    }
+}
+
+impl PushChallengeStore for MemoryPushChallengeStore {
+    async fn save(&self, challenge: PushChallenge) -> Result<(), AuthError> {
+        let now = now()?;
+        let mut challenges = self.lock()?;
+
+        challenges.retain(|_, challenge| challenge.expires_at > now);
+        challenges.insert(challenge.id.clone(), challenge);
+
+        Ok(())
+    }
+
+    async fn load(&self, id: &str) -> Result<Option<PushChallenge>, AuthError> {
+        Ok(self.lock()?.get(id).cloned())
+    }
+
+    async fn resolve(&self, id: &str, status: PushStatus) -> Result<bool, AuthError> {
+        match self.lock()?.get_mut(id) {
+            Some(challenge) if challenge.status == PushStatus::Pending => {
+                challenge.status = status;
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use totp_rs::qrcodegen_image::{
+    self,
+    image::{
+        codecs::png::PngEncoder,
+        imageops::{self, FilterType},
+        ColorType, ImageEncoder, Rgba, RgbaImage,
+    },
+    qrcodegen::{QrCode, QrCodeEcc},
+};
+
+use crate::AuthError;
+
+/// Modules of blank space drawn around the SVG and ASCII renderings
+const QUIET_ZONE: i32 = 4;
+
+/// Widest an embedded logo may be, as a fraction of the symbol, so High error correction can
+/// still recover the modules it covers
+const LOGO_FRACTION: f64 = 0.2;
+
+/// How much of a QR symbol can be damaged and still read (ISO/IEC 18004)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorCorrection {
+    /// About 7%
+    Low,
+    /// About 15%
+    #[default]
+    Medium,
+    /// About 25%
+    Quartile,
+    /// About 30%
+    High,
+}
+
+impl ErrorCorrection {
+    fn to_qr(self) -> QrCodeEcc {
+        match self {
+            Self::Low => QrCodeEcc::Low,
+            Self::Medium => QrCodeEcc::Medium,
+            Self::Quartile => QrCodeEcc::Quartile,
+            Self::High => QrCodeEcc::High,
+        }
+    }
+}
+
+/// An opaque RGB colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Color {
+    pub const BLACK: Self = Self::rgb(0, 0, 0);
+    pub const WHITE: Self = Self::rgb(255, 255, 255);
+
+    pub const fn rgb(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    /// A CSS style `#RRGGBB` colour, with or without the `#`
+    pub fn hex(hex: &str) -> Result<Self, AuthError> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        let channel = |index: usize| {
+            hex.get(index..index + 2)
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+                .ok_or_else(|| AuthError::InvalidInput(format!("Invalid colour #{}", hex)))
+        };
+        if hex.len() != 6 {
+            return Err(AuthError::InvalidInput(format!("Invalid colour #{}", hex)));
+        }
+
+        Ok(Self::rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    fn to_hex(self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.red, self.green, self.blue)
+    }
+
+    fn to_rgba(self) -> Rgba<u8> {
+        Rgba([self.red, self.green, self.blue, 255])
+    }
+}
+
+/// How `png_with` and `svg_with` draw a QR code, e.g. to match product branding
+///
+/// The default matches `png` and `svg`: 8 pixels per module, a 4 module margin, Medium error
+/// correction, and black on white. Scanners need enough contrast between the colours, and most
+/// expect a darker foreground. A logo is drawn over the centre of the symbol, which forces High
+/// error correction so the modules it covers can still be read.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::qr::{png_with, svg_with, Color, ErrorCorrection, QrStyle};
+///
+/// let style = QrStyle::new()
+///     .module_size(10)
+///     .margin(2)
+///     .error_correction(ErrorCorrection::Quartile)
+///     .foreground(Color::hex("#1B2A4A")?)
+///     .background(Color::rgb(250, 250, 245));
+///
+/// let url = "otpauth://totp/SomeIssuer:SomeAccountName?secret=MZXW6YTBOI";
+/// let png = png_with(url, &style)?;
+/// assert_eq!(&png[1..4], b"PNG");
+///
+/// let svg = svg_with(url, &style.logo(png.clone()))?;
+/// assert!(svg.contains("fill=\"#1B2A4A\""));
+/// assert!(svg.contains("<image"));
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QrStyle {
+    module_size: u32,
+    margin: u32,
+    error_correction: ErrorCorrection,
+    foreground: Color,
+    background: Color,
+    logo: Option<Vec<u8>>,
+}
+
+impl Default for QrStyle {
+    fn default() -> Self {
+        Self {
+            module_size: 8,
+            margin: QUIET_ZONE as u32,
+            error_correction: ErrorCorrection::Medium,
+            foreground: Color::BLACK,
+            background: Color::WHITE,
+            logo: None,
+        }
+    }
+}
+
+impl QrStyle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pixels per module in PNG renderings; SVG renderings scale freely
+    pub fn module_size(mut self, module_size: u32) -> Self {
+        self.module_size = module_size;
+        self
+    }
+
+    /// Modules of blank space around the symbol; scanners expect at least 4
+    pub fn margin(mut self, margin: u32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    pub fn error_correction(mut self, error_correction: ErrorCorrection) -> Self {
+        self.error_correction = error_correction;
+        self
+    }
+
+    pub fn foreground(mut self, foreground: Color) -> Self {
+        self.foreground = foreground;
+        self
+    }
+
+    pub fn background(mut self, background: Color) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// A PNG image to draw in the centre, scaled to at most a fifth of the symbol's width
+    pub fn logo(mut self, png: impl Into<Vec<u8>>) -> Self {
+        self.logo = Some(png.into());
+        self
+    }
+
+    fn encode(&self, text: &str) -> Result<QrCode, AuthError> {
+        let ecc = match self.logo {
+            Some(_) => ErrorCorrection::High,
+            None => self.error_correction,
+        };
+
+        QrCode::encode_text(text, ecc.to_qr())
+            .map_err(|error| AuthError::InvalidInput(error.to_string()))
+    }
+
+    /// The decoded logo and its box in modules from the image's top left, as `(x, y, w, h)`
+    fn logo_box(&self, qr: &QrCode) -> Result<Option<(RgbaImage, [f64; 4])>, AuthError> {
+        let Some(png) = &self.logo else {
+            return Ok(None);
+        };
+        let logo = qrcodegen_image::image::load_from_memory(png)
+            .map_err(|error| AuthError::InvalidInput(format!("Invalid logo: {}", error)))?
+            .to_rgba8();
+        if logo.width() == 0 || logo.height() == 0 {
+            return Err(AuthError::InvalidInput("Logo is empty".to_string()));
+        }
+
+        let largest = f64::from(qr.size()) * LOGO_FRACTION;
+        let scale = largest / f64::from(logo.width().max(logo.height()));
+        let (width, height) = (
+            f64::from(logo.width()) * scale,
+            f64::from(logo.height()) * scale,
+        );
+        let centre = f64::from(self.margin) + f64::from(qr.size()) / 2.0;
+
+        Ok(Some((
+            logo,
+            [centre - width / 2.0, centre - height / 2.0, width, height],
+        )))
+    }
+}
+
+/// Render text as a base64 encoded PNG QR code
+pub fn base64(text: &str) -> Result<String, AuthError> {
+    qrcodegen_image::draw_base64(text).map_err(AuthError::InvalidInput)
+}
+
+/// Render text as a PNG QR code
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::qr::png;
+///
+/// let bytes = png("otpauth://totp/SomeIssuer:SomeAccountName?secret=MZXW6YTBOI").unwrap();
+///
+/// assert_eq!(&bytes[1..4], b"PNG");
+/// ```
+pub fn png(text: &str) -> Result<Vec<u8>, AuthError> {
+    qrcodegen_image::draw_png(text).map_err(AuthError::InvalidInput)
+}
+
+/// Render text as a PNG QR code drawn with `style`
+pub fn png_with(text: &str, style: &QrStyle) -> Result<Vec<u8>, AuthError> {
+    if style.module_size == 0 {
+        return Err(AuthError::InvalidInput(
+            "QR module size must be at least 1".to_string(),
+        ));
+    }
+
+    let qr = style.encode(text)?;
+    let module = style.module_size;
+    let dimension = (qr.size() as u32 + style.margin * 2)
+        .checked_mul(module)
+        .filter(|dimension| *dimension <= 16_384)
+        .ok_or_else(|| AuthError::InvalidInput("QR image is too large".to_string()))?;
+    let mut image = RgbaImage::from_pixel(dimension, dimension, style.background.to_rgba());
+
+    for y in 0..qr.size() {
+        for x in 0..qr.size() {
+            if !qr.get_module(x, y) {
+                continue;
+            }
+
+            let (left, top) = (
+                (x as u32 + style.margin) * module,
+                (y as u32 + style.margin) * module,
+            );
+            for pixel_y in top..top + module {
+                for pixel_x in left..left + module {
+                    image.put_pixel(pixel_x, pixel_y, style.foreground.to_rgba());
+                }
+            }
+        }
+    }
+
+    if let Some((logo, [x, y, width, height])) = style.logo_box(&qr)? {
+        let pixels = |modules: f64| (modules * f64::from(module)).round() as u32;
+        let (x, y) = (pixels(x), pixels(y));
+        let (width, height) = (pixels(width).max(1), pixels(height).max(1));
+
+        for pixel_y in y..y + height {
+            for pixel_x in x..x + width {
+                image.put_pixel(pixel_x, pixel_y, style.background.to_rgba());
+            }
+        }
+        let logo = imageops::resize(&logo, width, height, FilterType::Triangle);
+        imageops::overlay(&mut image, &logo, i64::from(x), i64::from(y));
+    }
+
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(image.as_raw(), dimension, dimension, ColorType::Rgba8)
+        .map_err(|error| AuthError::backend(format!("Failed to encode PNG: {}", error)))?;
+
+    Ok(png)
+}
+
+/// Render text as an SVG QR code
+///
+/// Each module is one unit in the view box, so the image scales cleanly to any size.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::qr::svg;
+///
+/// let svg = svg("otpauth://totp/SomeIssuer:SomeAccountName?secret=MZXW6YTBOI").unwrap();
+///
+/// assert!(svg.starts_with("<svg"));
+/// ```
+pub fn svg(text: &str) -> Result<String, AuthError> {
+    svg_with(text, &QrStyle::default())
+}
+
+/// Render text as an SVG QR code drawn with `style`
+pub fn svg_with(text: &str, style: &QrStyle) -> Result<String, AuthError> {
+    let qr = style.encode(text)?;
+    let margin = style.margin as i32;
+    let dimension = qr.size() + margin * 2;
+    let mut path = String::new();
+
+    for y in 0..qr.size() {
+        for x in 0..qr.size() {
+            if qr.get_module(x, y) {
+                path.push_str(&format!("M{},{}h1v1h-1z", x + margin, y + margin));
+            }
+        }
+    }
+
+    let mut logo = String::new();
+    if let (Some(png), Some((_, [x, y, width, height]))) = (&style.logo, style.logo_box(&qr)?) {
+        logo = format!(
+            concat!(
+                "<rect x=\"{0}\" y=\"{1}\" width=\"{2}\" height=\"{3}\" fill=\"{4}\"/>",
+                "<image x=\"{0}\" y=\"{1}\" width=\"{2}\" height=\"{3}\" ",
+                "href=\"data:image/png;base64,{5}\"/>"
+            ),
+            x,
+            y,
+            width,
+            height,
+            style.background.to_hex(),
+            STANDARD.encode(png)
+        );
+    }
+
+    Ok(format!(
+        concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {0} {0}\" shape-rendering=\"crispEdges\">",
+            "<rect width=\"100%\" height=\"100%\" fill=\"{2}\"/>",
+            "<path d=\"{1}\" fill=\"{3}\"/>",
+            "{4}",
+            "</svg>"
+        ),
+        dimension,
+        path,
+        style.background.to_hex(),
+        style.foreground.to_hex(),
+        logo
+    ))
+}
+
+/// Render text as a QR code for display in a terminal
+///
+/// Two rows of modules are packed into each line with half block characters, drawn light on
+/// dark so it scans on terminals with a dark background.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::qr::ascii;
+///
+/// let qr = ascii("otpauth://totp/SomeIssuer:SomeAccountName?secret=MZXW6YTBOI").unwrap();
+///
+/// println!("{}", qr);
+/// ```
+pub fn ascii(text: &str) -> Result<String, AuthError> {
+    let qr = encode(text)?;
+    let light = |x: i32, y: i32| !qr.get_module(x, y);
+    let range = -QUIET_ZONE..qr.size() + QUIET_ZONE;
+    let mut output = String::new();
+
+    for y in range.clone().step_by(2) {
+        for x in range.clone() {
+            // Coordinates outside the symbol are reported as light by qrcodegen
+            let block = match (light(x, y), light(x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            };
+
+            output.push(block);
+        }
+
+        output.push('\n');
+    }
+
+    Ok(output)
+}
+
+fn encode(text: &str) -> Result<QrCode, AuthError> {
+    QrCode::encode_text(text, QrCodeEcc::Medium)
+        .map_err(|error| AuthError::InvalidInput(error.to_string()))
+}
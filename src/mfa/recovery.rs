@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+};
+
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+use crate::{crypto::ct_eq, AuthError};
+
+/// How many recovery codes `generate` issues
+pub const DEFAULT_CODE_COUNT: usize = 10;
+
+/// Characters used in recovery codes, without look-alikes such as `0`/`o` and `1`/`l`
+const ALPHABET: &[u8] = b"23456789abcdefghjkmnpqrstuvwxyz";
+
+/// Persists hashed recovery codes per account
+///
+/// `consume` must remove the hash and report whether it was present in a single atomic
+/// operation (e.g. `DELETE ... RETURNING` or Redis `SREM`) so a code can only be used once.
+pub trait RecoveryCodeStore {
+    /// Replace every stored hash for the account with a new set
+    fn replace(
+        &self,
+        account: &str,
+        hashes: Vec<String>,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Remove the hash for the account, returning `true` if it existed
+    fn consume(
+        &self,
+        account: &str,
+        hash: &str,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Number of unused codes left for the account
+    fn remaining(&self, account: &str) -> impl Future<Output = Result<usize, AuthError>> + Send;
+}
+
+/// Generate a set of plain text recovery codes formatted as `xxxxx-xxxxx`
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::recovery::generate_codes;
+///
+/// let codes = generate_codes(10);
+///
+/// assert_eq!(codes.len(), 10);
+/// assert_eq!(codes[0].len(), 11);
+/// ```
+pub fn generate_codes(count: usize) -> Vec<String> {
+    let mut rng = thread_rng();
+
+    (0..count)
+        .map(|_| {
+            let mut code: String = (0..10)
+                .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+                .collect();
+            code.insert(5, '-');
+            code
+        })
+        .collect()
+}
+
+/// Hash a recovery code for storage
+///
+/// Input is normalised first, so `ABCDE-FGHJK`, `abcde fghjk` and `abcdefghjk` hash the same.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::recovery::hash_code;
+///
+/// assert_eq!(hash_code("ABCDE-FGHJK"), hash_code("abcdefghjk"));
+/// ```
+pub fn hash_code(code: &str) -> String {
+    let normalised: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    let digest = Sha256::digest(normalised.as_bytes());
+
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Generate a fresh set of recovery codes for an account, replacing any existing ones
+///
+/// Only the hashes are stored; the returned plain text codes should be shown to the user once.
+pub async fn generate<S: RecoveryCodeStore>(
+    store: &S,
+    account: &str,
+) -> Result<Vec<String>, AuthError> {
+    let codes = generate_codes(DEFAULT_CODE_COUNT);
+    let hashes = codes.iter().map(|code| hash_code(code)).collect();
+
+    store.replace(account, hashes).await?;
+
+    Ok(codes)
+}
+
+/// Verify a recovery code and consume it so it cannot be used again
+///
+/// ### Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::mfa::recovery::{generate, verify, RecoveryCodeStore};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, Vec<String>>>);
+///
+/// impl RecoveryCodeStore for MemoryStore {
+///     async fn replace(&self, account: &str, hashes: Vec<String>) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(account.to_string(), hashes);
+///         Ok(())
+///     }
+///
+///     async fn consume(&self, account: &str, hash: &str) -> Result<bool, AuthError> {
+///         let mut accounts = self.0.lock().unwrap();
+///         let hashes = accounts.entry(account.to_string()).or_default();
+///         let before = hashes.len();
+///         hashes.retain(|stored| stored != hash);
+///         Ok(hashes.len() < before)
+///     }
+///
+///     async fn remaining(&self, account: &str) -> Result<usize, AuthError> {
+///         Ok(self.0.lock().unwrap().get(account).map_or(0, |hashes| hashes.len()))
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let store = MemoryStore::default();
+///     let codes = generate(&store, "SomeAccountName").await?;
+///
+///     assert!(verify(&store, "SomeAccountName", codes[0].clone()).await?);
+///     assert!(!verify(&store, "SomeAccountName", codes[0].clone()).await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn verify<S: RecoveryCodeStore>(
+    store: &S,
+    account: &str,
+    code: String,
+) -> Result<bool, AuthError> {
+    store.consume(account, &hash_code(&code)).await
+}
+
+/// Number of unused recovery codes left for an account
+pub async fn remaining<S: RecoveryCodeStore>(store: &S, account: &str) -> Result<usize, AuthError> {
+    store.remaining(account).await
+}
+
+/// Keeps hashed recovery codes in process memory
+#[derive(Debug, Default)]
+pub struct MemoryRecoveryCodeStore {
+    hashes: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl MemoryRecoveryCodeStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, Vec<String>>>, AuthError> {
+        self.hashes
+            .lock()
+            .map_err(|_| AuthError::backend("Recovery code store lock poisoned"))
+    }
+}
+
+impl RecoveryCodeStore for MemoryRecoveryCodeStore {
+    async fn replace(&self, account: &str, hashes: Vec<String>) -> Result<(), AuthError> {
+        self.lock()?.insert(account.to_string(), hashes);
+
+        Ok(())
+    }
+
+    async fn consume(&self, account: &str, hash: &str) -> Result<bool, AuthError> {
+        let mut accounts = self.lock()?;
+        let hashes = match accounts.get_mut(account) {
+            Some(hashes) => hashes,
+            None => return Ok(false),
+        };
+
+        match hashes.iter().position(|stored| ct_eq(stored, hash)) {
+            Some(index) => {
+                hashes.swap_remove(index);
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn remaining(&self, account: &str) -> Result<usize, AuthError> {
+        Ok(self.lock()?.get(account).map_or(0, Vec::len))
+    }
+}
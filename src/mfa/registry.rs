@@ -0,0 +1,295 @@
+use std::{
+    collections::HashSet,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{token::now, webauthn::Credential, AuthError};
+
+use super::{generate_secret, verify_with, TotpConfig};
+
+/// The kind of second factor and the data needed to verify it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum FactorKind {
+    Totp {
+        secret: String,
+        config: TotpConfig,
+    },
+    #[cfg_attr(feature = "serde", serde(rename = "webauthn"))]
+    WebAuthn {
+        credential: Credential,
+    },
+    /// The codes themselves live in a `RecoveryCodeStore`
+    RecoveryCodes,
+}
+
+/// A second factor enrolled on an account
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Factor {
+    pub id: String,
+    pub account: String,
+    /// User chosen name, e.g. "Work phone" or "YubiKey"
+    pub label: String,
+    pub kind: FactorKind,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+}
+
+/// How many of an account's factors must pass for sign in to succeed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FactorPolicy {
+    Any,
+    AtLeast(usize),
+    All,
+}
+
+impl FactorPolicy {
+    /// Whether `passed` distinct factors out of `enrolled` satisfy the policy
+    ///
+    /// Accounts without any factors never satisfy a policy.
+    pub fn is_satisfied(&self, passed: usize, enrolled: usize) -> bool {
+        if enrolled == 0 {
+            return false;
+        }
+
+        match self {
+            FactorPolicy::Any => passed >= 1,
+            FactorPolicy::AtLeast(required) => passed >= (*required).max(1),
+            FactorPolicy::All => passed >= enrolled,
+        }
+    }
+}
+
+/// Persists the factors enrolled on each account
+pub trait FactorStore {
+    fn list(&self, account: &str) -> impl Future<Output = Result<Vec<Factor>, AuthError>> + Send;
+
+    fn insert(&self, factor: Factor) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Returns `false` if the account has no factor with this id
+    fn set_label(
+        &self,
+        account: &str,
+        id: &str,
+        label: String,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Returns `false` if the account has no factor with this id
+    fn remove(
+        &self,
+        account: &str,
+        id: &str,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Manages several second factors per account and decides when enough of them have passed
+///
+/// Each factor is verified with its own module, e.g. `webauthn::Webauthn::finish_authentication`
+/// or `recovery::verify`, and the ids of the factors that passed are checked against the policy.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::mfa::registry::{Factor, FactorKind, FactorPolicy, FactorStore, MfaRegistry};
+/// use lonewolf_auth_toolkit::mfa::TotpConfig;
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<Vec<Factor>>);
+///
+/// impl FactorStore for MemoryStore {
+///     async fn list(&self, account: &str) -> Result<Vec<Factor>, AuthError> {
+///         Ok(self.0.lock().unwrap().iter().filter(|factor| factor.account == account).cloned().collect())
+///     }
+///
+///     async fn insert(&self, factor: Factor) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().push(factor);
+///         Ok(())
+///     }
+///
+///     async fn set_label(&self, account: &str, id: &str, label: String) -> Result<bool, AuthError> {
+///         let mut factors = self.0.lock().unwrap();
+///
+///         match factors.iter_mut().find(|factor| factor.account == account && factor.id == id) {
+///             Some(factor) => {
+///                 factor.label = label;
+///                 Ok(true)
+///             }
+///             None => Ok(false),
+///         }
+///     }
+///
+///     async fn remove(&self, account: &str, id: &str) -> Result<bool, AuthError> {
+///         let mut factors = self.0.lock().unwrap();
+///         let before = factors.len();
+///         factors.retain(|factor| !(factor.account == account && factor.id == id));
+///
+///         Ok(factors.len() != before)
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let registry = MfaRegistry::new(MemoryStore::default()).policy(FactorPolicy::Any);
+///     let phone = registry
+///         .add("SomeAccountName", "Phone".to_string(), FactorKind::Totp {
+///             secret: "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string(),
+///             config: TotpConfig::default(),
+///         })
+///         .await?;
+///     registry.add("SomeAccountName", "Recovery codes".to_string(), FactorKind::RecoveryCodes).await?;
+///
+///     registry.label("SomeAccountName", &phone.id, "Work phone".to_string()).await?;
+///
+///     assert_eq!(registry.list("SomeAccountName").await?.len(), 2);
+///     assert!(registry.is_satisfied("SomeAccountName", &[phone.id.as_str()]).await?);
+///     assert!(!registry.is_satisfied("SomeAccountName", &[]).await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct MfaRegistry<S> {
+    store: S,
+    policy: FactorPolicy,
+}
+
+impl<S: FactorStore> MfaRegistry<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            policy: FactorPolicy::Any,
+        }
+    }
+
+    pub fn policy(mut self, policy: FactorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub async fn list(&self, account: &str) -> Result<Vec<Factor>, AuthError> {
+        self.store.list(account).await
+    }
+
+    pub async fn add(
+        &self,
+        account: &str,
+        label: String,
+        kind: FactorKind,
+    ) -> Result<Factor, AuthError> {
+        let factor = Factor {
+            id: generate_secret(),
+            account: account.to_string(),
+            label,
+            kind,
+            created_at: now()?,
+        };
+
+        self.store.insert(factor.clone()).await?;
+
+        Ok(factor)
+    }
+
+    /// Rename a factor, returning `false` if the account has no factor with this id
+    pub async fn label(&self, account: &str, id: &str, label: String) -> Result<bool, AuthError> {
+        self.store.set_label(account, id, label).await
+    }
+
+    /// Remove a factor, returning `false` if the account has no factor with this id
+    pub async fn remove(&self, account: &str, id: &str) -> Result<bool, AuthError> {
+        self.store.remove(account, id).await
+    }
+
+    /// Find the TOTP factor `code` belongs to, if any
+    pub async fn verify_totp(
+        &self,
+        account: &str,
+        code: String,
+    ) -> Result<Option<Factor>, AuthError> {
+        for factor in self.store.list(account).await? {
+            if let FactorKind::Totp { secret, config } = &factor.kind {
+                if verify_with(code.clone(), secret.clone(), config).await? {
+                    return Ok(Some(factor));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Whether the factors with the given ids satisfy the policy for this account
+    ///
+    /// Ids that do not belong to the account are ignored and duplicates are counted once.
+    pub async fn is_satisfied(&self, account: &str, passed: &[&str]) -> Result<bool, AuthError> {
+        let factors = self.store.list(account).await?;
+        let passed: HashSet<&str> = passed.iter().copied().collect();
+        let count = factors
+            .iter()
+            .filter(|factor| passed.contains(factor.id.as_str()))
+            .count();
+
+        Ok(self.policy.is_satisfied(count, factors.len()))
+    }
+}
+
+/// Keeps enrolled factors in process memory
+#[derive(Debug, Default)]
+pub struct MemoryFactorStore {
+    factors: Mutex<Vec<Factor>>,
+}
+
+impl MemoryFactorStore {
+    fn lock(&self) -> Result<MutexGuard<'_, Vec<Factor>>, AuthError> {
+        self.factors
+            .lock()
+            .map_err(|_| AuthError::backend("Factor store lock poisoned"))
+    }
+}
+
+impl FactorStore for MemoryFactorStore {
+    async fn list(&self, account: &str) -> Result<Vec<Factor>, AuthError> {
+        Ok(self
+            .lock()?
+            .iter()
+            .filter(|factor| factor.account == account)
+            .cloned()
+            .collect())
+    }
+
+    async fn insert(&self, factor: Factor) -> Result<(), AuthError> {
+        self.lock()?.push(factor);
+
+        Ok(())
+    }
+
+    async fn set_label(&self, account: &str, id: &str, label: String) -> Result<bool, AuthError> {
+        match self
+            .lock()?
+            .iter_mut()
+            .find(|factor| factor.account == account && factor.id == id)
+        {
+            Some(factor) => {
+                factor.label = label;
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn remove(&self, account: &str, id: &str) -> Result<bool, AuthError> {
+        let mut factors = self.lock()?;
+        let before = factors.len();
+        factors.retain(|factor| !(factor.account == account && factor.id == id));
+
+        Ok(factors.len() < before)
+    }
+}
@@ -0,0 +1,92 @@
+use std::{collections::HashMap, future::Future, sync::Mutex};
+
+use crate::{token::now, AuthError};
+
+use super::{matching_step, TotpConfig};
+
+/// Remembers the last time step accepted for each secret
+///
+/// `mark_used` must compare and update in one atomic operation (e.g. a conditional `UPDATE` or
+/// a Redis Lua script) so two requests racing with the same code cannot both be accepted.
+pub trait UsedStepStore {
+    /// Record `step` as used for the key, returning `false` if it is not later than the last
+    /// step recorded
+    fn mark_used(
+        &self,
+        key: &str,
+        step: u64,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Verify a TOTP Code and reject it if it, or a code from a later step, was already accepted
+///
+/// ### Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::mfa::replay::{verify_once, UsedStepStore};
+/// use lonewolf_auth_toolkit::mfa::TotpConfig;
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, u64>>);
+///
+/// impl UsedStepStore for MemoryStore {
+///     async fn mark_used(&self, key: &str, step: u64) -> Result<bool, AuthError> {
+///         let mut steps = self.0.lock().unwrap();
+///
+///         match steps.get(key) {
+///             Some(last) if *last >= step => Ok(false),
+///             _ => {
+///                 steps.insert(key.to_string(), step);
+///                 Ok(true)
+///             }
+///         }
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let store = MemoryStore::default();
+///     let verified = verify_once(&store, "SomeAccountName", "123456".to_string(), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string(), &TotpConfig::default()).await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn verify_once<S: UsedStepStore>(
+    store: &S,
+    key: &str,
+    code: String,
+    secret: String,
+    config: &TotpConfig,
+) -> Result<bool, AuthError> {
+    match matching_step(&code, &secret, config, now()?)? {
+        Some((_, step)) => store.mark_used(key, step).await,
+        None => Ok(false),
+    }
+}
+
+/// Keeps the last accepted step for each key in process memory
+#[derive(Debug, Default)]
+pub struct MemoryUsedStepStore {
+    steps: Mutex<HashMap<String, u64>>,
+}
+
+impl UsedStepStore for MemoryUsedStepStore {
+    async fn mark_used(&self, key: &str, step: u64) -> Result<bool, AuthError> {
+        let mut steps = self
+            .steps
+            .lock()
+            .map_err(|_| AuthError::backend("Used step store lock poisoned"))?;
+
+        match steps.get(key) {
+            Some(last) if *last >= step => Ok(false),
+            _ => {
+                steps.insert(key.to_string(), step);
+
+                Ok(true)
+            }
+        }
+    }
+}
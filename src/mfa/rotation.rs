@@ -0,0 +1,366 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{crypto::ct_eq, token::now, AuthError};
+
+use super::{generate_with, verify_with, TotpConfig};
+
+/// The TOTP secrets held for an account while a rotation may be in progress
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TotpSecrets {
+    /// The secret codes are currently verified against
+    pub active: String,
+    /// A newly issued secret the user has not yet confirmed
+    pub pending: Option<String>,
+    /// The secret the last rotation replaced, still accepted until `previous_until`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub previous: Option<String>,
+    /// Unix timestamp in seconds; 0 when there is no grace period
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub previous_until: u64,
+}
+
+/// Which of an account's secrets produced a code, from `verify_matched`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretMatch {
+    /// The active secret
+    Active,
+    /// The secret a rotation replaced, within its grace period
+    ///
+    /// The user has a device that still holds the old secret; consider prompting them to update
+    /// it before the grace period ends.
+    Previous,
+}
+
+/// Persists each account's active and pending TOTP secrets
+pub trait SecretStore {
+    fn load(
+        &self,
+        account: &str,
+    ) -> impl Future<Output = Result<Option<TotpSecrets>, AuthError>> + Send;
+
+    /// Store a pending secret for the account, replacing any earlier pending one
+    fn set_pending(
+        &self,
+        account: &str,
+        pending: String,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Atomically make the pending secret active, only if it still equals `pending`. Returns
+    /// `false` when another rotation replaced it in the meantime.
+    ///
+    /// The replaced secret becomes `previous`, accepted until the Unix timestamp
+    /// `previous_until`.
+    fn promote(
+        &self,
+        account: &str,
+        pending: &str,
+        previous_until: u64,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    fn clear_pending(&self, account: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Issue a new secret for an account without disturbing the active one
+///
+/// Returns the QR code and secret for the user to add to their authenticator. Until
+/// `confirm_rotation` succeeds, codes keep verifying against the old secret.
+pub async fn start_rotation<S: SecretStore>(
+    store: &S,
+    issuer: String,
+    account: &str,
+    config: &TotpConfig,
+) -> Result<(String, String), AuthError> {
+    if store.load(account).await?.is_none() {
+        return Err(AuthError::NotFound(
+            "Account has no TOTP secret to rotate".to_string(),
+        ));
+    }
+
+    let (qr_code, secret) = generate_with(issuer, account.to_string(), config).await?;
+
+    store.set_pending(account, secret.clone()).await?;
+
+    Ok((qr_code, secret))
+}
+
+/// Confirm the user holds the new secret and retire the old one
+///
+/// Returns `false`, leaving the old secret active, if the code does not match the pending secret.
+///
+/// ### Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::mfa::rotation::{confirm_rotation, start_rotation, SecretStore, TotpSecrets};
+/// use lonewolf_auth_toolkit::mfa::TotpConfig;
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, TotpSecrets>>);
+///
+/// impl SecretStore for MemoryStore {
+///     async fn load(&self, account: &str) -> Result<Option<TotpSecrets>, AuthError> {
+///         Ok(self.0.lock().unwrap().get(account).cloned())
+///     }
+///
+///     async fn set_pending(&self, account: &str, pending: String) -> Result<(), AuthError> {
+///         if let Some(secrets) = self.0.lock().unwrap().get_mut(account) {
+///             secrets.pending = Some(pending);
+///         }
+///         Ok(())
+///     }
+///
+///     async fn promote(
+///         &self,
+///         account: &str,
+///         pending: &str,
+///         previous_until: u64,
+///     ) -> Result<bool, AuthError> {
+///         let mut accounts = self.0.lock().unwrap();
+///
+///         match accounts.get_mut(account) {
+///             Some(secrets) if secrets.pending.as_deref() == Some(pending) => {
+///                 let pending = secrets.pending.take().unwrap();
+///                 let previous = std::mem::replace(&mut secrets.active, pending);
+///                 secrets.previous = Some(previous);
+///                 secrets.previous_until = previous_until;
+///                 Ok(true)
+///             }
+///             _ => Ok(false),
+///         }
+///     }
+///
+///     async fn clear_pending(&self, account: &str) -> Result<(), AuthError> {
+///         if let Some(secrets) = self.0.lock().unwrap().get_mut(account) {
+///             secrets.pending = None;
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let store = MemoryStore::default();
+///     store.0.lock().unwrap().insert("SomeAccountName".to_string(), TotpSecrets {
+///         active: "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string(),
+///         pending: None,
+///         previous: None,
+///         previous_until: 0,
+///     });
+///
+///     let config = TotpConfig::default();
+///     let (qr_code, secret) = start_rotation(&store, "SomeIssuer".to_string(), "SomeAccountName", &config).await?;
+///     let confirmed = confirm_rotation(&store, "SomeAccountName", "123456".to_string(), &config).await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn confirm_rotation<S: SecretStore>(
+    store: &S,
+    account: &str,
+    code: String,
+    config: &TotpConfig,
+) -> Result<bool, AuthError> {
+    confirm_rotation_with_grace(store, account, code, config, Duration::ZERO).await
+}
+
+/// Like `confirm_rotation`, but keep accepting codes from the old secret for `grace`
+///
+/// Gives users with several devices, or a backup of the old secret, time to switch over without
+/// being locked out mid-transition. Use no grace when rotating because a secret leaked.
+pub async fn confirm_rotation_with_grace<S: SecretStore>(
+    store: &S,
+    account: &str,
+    code: String,
+    config: &TotpConfig,
+    grace: Duration,
+) -> Result<bool, AuthError> {
+    let pending = match store
+        .load(account)
+        .await?
+        .and_then(|secrets| secrets.pending)
+    {
+        Some(pending) => pending,
+        None => {
+            return Err(AuthError::InvalidState(
+                "No TOTP rotation is in progress".to_string(),
+            ))
+        }
+    };
+
+    if !verify_with(code, pending.clone(), config).await? {
+        return Ok(false);
+    }
+
+    let previous_until = if grace.is_zero() {
+        0
+    } else {
+        now()? + grace.as_secs()
+    };
+
+    store.promote(account, &pending, previous_until).await
+}
+
+/// Abandon a rotation, keeping the old secret
+pub async fn cancel_rotation<S: SecretStore>(store: &S, account: &str) -> Result<(), AuthError> {
+    store.clear_pending(account).await
+}
+
+/// Verify a code against the account's active secret, or the previous one during its grace
+/// period
+pub async fn verify<S: SecretStore>(
+    store: &S,
+    account: &str,
+    code: String,
+    config: &TotpConfig,
+) -> Result<bool, AuthError> {
+    Ok(verify_matched(store, account, code, config)
+        .await?
+        .is_some())
+}
+
+/// Like `verify`, but report which secret the code matched
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::mfa::blocking::current_code;
+/// use lonewolf_auth_toolkit::mfa::rotation::{
+///     confirm_rotation_with_grace, start_rotation, verify_matched, MemorySecretStore, SecretMatch,
+/// };
+/// use lonewolf_auth_toolkit::mfa::TotpConfig;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let store = MemorySecretStore::default();
+///     let old = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+///     store.insert("SomeAccountName", old.to_string())?;
+///
+///     let config = TotpConfig::default();
+///     let account = "SomeAccountName";
+///     let (_, new) = start_rotation(&store, "SomeIssuer".to_string(), account, &config).await?;
+///
+///     let code = current_code(&new, &config)?;
+///     let grace = Duration::from_secs(24 * 60 * 60);
+///     assert!(confirm_rotation_with_grace(&store, account, code, &config, grace).await?);
+///
+///     // A device that still holds the old secret keeps working for a day
+///     let code = current_code(old, &config)?;
+///     let matched = verify_matched(&store, account, code, &config).await?;
+///     assert_eq!(matched, Some(SecretMatch::Previous));
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn verify_matched<S: SecretStore>(
+    store: &S,
+    account: &str,
+    code: String,
+    config: &TotpConfig,
+) -> Result<Option<SecretMatch>, AuthError> {
+    let secrets = match store.load(account).await? {
+        Some(secrets) => secrets,
+        None => return Ok(None),
+    };
+
+    if verify_with(code.clone(), secrets.active, config).await? {
+        return Ok(Some(SecretMatch::Active));
+    }
+
+    match secrets.previous {
+        Some(previous) if secrets.previous_until > now()? => {
+            let matched = verify_with(code, previous, config).await?;
+
+            Ok(matched.then_some(SecretMatch::Previous))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Keeps each account's TOTP secrets in process memory
+///
+/// `SecretStore` only rotates secrets, so seed accounts with `insert` once they enroll.
+#[derive(Debug, Default)]
+pub struct MemorySecretStore {
+    secrets: Mutex<HashMap<String, TotpSecrets>>,
+}
+
+impl MemorySecretStore {
+    /// Store the account's active secret, dropping any pending rotation
+    pub fn insert(&self, account: &str, active: String) -> Result<(), AuthError> {
+        self.lock()?.insert(
+            account.to_string(),
+            TotpSecrets {
+                active,
+                pending: None,
+                previous: None,
+                previous_until: 0,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, TotpSecrets>>, AuthError> {
+        self.secrets
+            .lock()
+            .map_err(|_| AuthError::backend("Secret store lock poisoned"))
+    }
+}
+
+impl SecretStore for MemorySecretStore {
+    async fn load(&self, account: &str) -> Result<Option<TotpSecrets>, AuthError> {
+        Ok(self.lock()?.get(account).cloned())
+    }
+
+    async fn set_pending(&self, account: &str, pending: String) -> Result<(), AuthError> {
+        if let Some(secrets) = self.lock()?.get_mut(account) {
+            secrets.pending = Some(pending);
+        }
+
+        Ok(())
+    }
+
+    async fn promote(
+        &self,
+        account: &str,
+        pending: &str,
+        previous_until: u64,
+    ) -> Result<bool, AuthError> {
+        match self.lock()?.get_mut(account) {
+            Some(secrets)
+                if secrets
+                    .pending
+                    .as_deref()
+                    .is_some_and(|stored| ct_eq(stored, pending)) =>
+            {
+                let previous = std::mem::replace(&mut secrets.active, pending.to_string());
+                secrets.pending = None;
+                secrets.previous = (previous_until > 0).then_some(previous);
+                secrets.previous_until = previous_until;
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn clear_pending(&self, account: &str) -> Result<(), AuthError> {
+        if let Some(secrets) = self.lock()?.get_mut(account) {
+            secrets.pending = None;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::{thread_rng, Rng, RngCore};
+use zeroize::Zeroizing;
+
+use crate::AuthError;
+
+const BASE32: base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+/// Secret length recommended by RFC 4226, the output size of HMAC-SHA1
+pub const DEFAULT_SECRET_BYTES: usize = 20;
+
+/// The shortest secret RFC 4226 allows, 128 bits
+pub const MIN_SECRET_BYTES: usize = 16;
+
+/// Raw random key bytes for TOTP and HOTP, wiped from memory when dropped
+///
+/// The bytes themselves are the HMAC key; the encodings are only for storage and transport.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::{decode_secret, Secret};
+///
+/// let secret = Secret::with_len(32)?;
+/// let base32 = secret.to_base32();
+///
+/// assert_eq!(secret.as_bytes().len(), 32);
+/// assert_eq!(decode_secret(&base32), secret.as_bytes());
+/// assert_eq!(Secret::from_base32(&base32)?.to_hex(), secret.to_hex());
+/// assert!(Secret::with_len(10).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Clone)]
+pub struct Secret(Zeroizing<Vec<u8>>);
+
+impl Secret {
+    /// `DEFAULT_SECRET_BYTES` random bytes
+    pub fn generate() -> Self {
+        Self::random(DEFAULT_SECRET_BYTES)
+    }
+
+    /// `len` random bytes, at least `MIN_SECRET_BYTES`
+    pub fn with_len(len: usize) -> Result<Self, AuthError> {
+        if len < MIN_SECRET_BYTES {
+            return Err(AuthError::InvalidInput(format!(
+                "Secrets must be at least {} bytes",
+                MIN_SECRET_BYTES
+            )));
+        }
+
+        Ok(Self::random(len))
+    }
+
+    /// Decode a Base32 secret, e.g. one entered from another authenticator
+    pub fn from_base32(secret: &str) -> Result<Self, AuthError> {
+        let compact: String = secret.chars().filter(|c| !c.is_whitespace()).collect();
+        let bytes = base32::decode(BASE32, &compact.to_uppercase())
+            .ok_or_else(|| AuthError::MalformedSecret("Secret is not valid Base32".to_string()))?;
+
+        Ok(Self(Zeroizing::new(bytes)))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Unpadded RFC 4648 Base32, the format authenticator apps and `otpauth://` URIs expect
+    pub fn to_base32(&self) -> String {
+        base32::encode(BASE32, &self.0)
+    }
+
+    /// Lower case hex, as hardware token seed files use
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Padded standard Base64
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(&*self.0)
+    }
+
+    fn random(len: usize) -> Self {
+        let mut bytes = Zeroizing::new(vec![0; len]);
+        thread_rng().fill_bytes(&mut bytes);
+
+        Self(bytes)
+    }
+}
+
+/// Generate a random Base32 encoded secret of `DEFAULT_SECRET_BYTES`
+///
+/// This is the format authenticator apps expect when a key is entered manually. Use `Secret` for
+/// other lengths or encodings.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::generate_secret;
+///
+/// let secret = generate_secret();
+/// ```
+pub fn generate_secret() -> String {
+    Secret::generate().to_base32()
+}
+
+/// Generate a random numeric code of the given length, for codes delivered out of band
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::generate_numeric_code;
+///
+/// let code = generate_numeric_code(6);
+///
+/// assert_eq!(code.len(), 6);
+/// ```
+pub fn generate_numeric_code(digits: usize) -> String {
+    let mut rng = thread_rng();
+
+    (0..digits)
+        .map(|_| char::from(b'0' + rng.gen_range(0..10)))
+        .collect()
+}
+
+/// Decode a secret into the key bytes used for HMAC
+///
+/// Upper case Base32 (optionally padded or grouped with spaces) is decoded. Anything else is
+/// treated as a legacy secret from `generate_random_string` and its bytes are used as-is, so
+/// secrets stored by earlier versions keep verifying.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::decode_secret;
+///
+/// assert_eq!(decode_secret("MZXW6YTBOI"), b"foobar".to_vec());
+/// assert_eq!(decode_secret("5bad23b4"), b"5bad23b4".to_vec());
+/// ```
+pub fn decode_secret(secret: &str) -> Vec<u8> {
+    let compact: String = secret.chars().filter(|c| !c.is_whitespace()).collect();
+    let is_base32 = !compact.is_empty()
+        && compact
+            .trim_end_matches('=')
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || ('2'..='7').contains(&c));
+
+    if is_base32 {
+        if let Some(bytes) = base32::decode(BASE32, &compact) {
+            return bytes;
+        }
+    }
+
+    secret.as_bytes().to_vec()
+}
@@ -0,0 +1,232 @@
+use std::{future::Future, time::Duration};
+
+use crate::{otel, AuthError};
+
+use super::{
+    code::CodeStore,
+    otp::{Otp, OtpChannel},
+    phone::{NumberType, PhoneNumber},
+};
+
+pub use super::code::{DEFAULT_MAX_ATTEMPTS, DEFAULT_TTL};
+
+/// Delivers text messages, implemented for Twilio, Vonage, an internal gateway, ...
+pub trait SmsProvider {
+    fn send(
+        &self,
+        phone_number: &str,
+        message: &str,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Sends short numeric codes by SMS and verifies them
+///
+/// Built on `otp::Otp`: only a hash of each code is stored. A code is single use, expires after
+/// the TTL and is discarded after too many wrong guesses, after which a new code must be sent.
+/// Numbers are normalized to E.164 with `phone::PhoneNumber` before anything is sent, and invalid
+/// or fixed line numbers are rejected with `AuthError::InvalidInput`.
+///
+/// ### Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::mfa::code::{CodeStore, PendingCode};
+/// use lonewolf_auth_toolkit::mfa::sms::{SmsOtp, SmsProvider};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct Outbox(Mutex<Vec<String>>);
+///
+/// impl SmsProvider for Outbox {
+///     async fn send(&self, _phone_number: &str, message: &str) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().push(message.to_string());
+///         Ok(())
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, PendingCode>>);
+///
+/// impl CodeStore for MemoryStore {
+///     async fn save(&self, key: &str, code: PendingCode) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(key.to_string(), code);
+///         Ok(())
+///     }
+///
+///     async fn load(&self, key: &str) -> Result<Option<PendingCode>, AuthError> {
+///         Ok(self.0.lock().unwrap().get(key).cloned())
+///     }
+///
+///     async fn increment_attempts(&self, key: &str) -> Result<u32, AuthError> {
+///         let mut codes = self.0.lock().unwrap();
+///         let code = codes.get_mut(key).ok_or_else(|| AuthError::NotFound("No pending code".to_string()))?;
+///         code.attempts += 1;
+///         Ok(code.attempts)
+///     }
+///
+///     async fn remove(&self, key: &str) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().remove(key);
+///         Ok(())
+///     }
+///
+///     async fn consume(&self, key: &str, code_hash: &str) -> Result<bool, AuthError> {
+///         let mut codes = self.0.lock().unwrap();
+///         if codes.get(key).is_some_and(|code| code.code_hash == code_hash) {
+///             return Ok(codes.remove(key).is_some());
+///         }
+///         Ok(false)
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let sms = SmsOtp::new(Outbox::default(), MemoryStore::default()).region("ZA");
+///
+///     // Landlines are rejected before anything is sent
+///     assert!(sms.send("SomeAccountName", "021 123 4567").await.is_err());
+///
+///     let phone_number = sms.normalize("082 123 4567")?;
+///     assert_eq!(phone_number.e164(), "+27821234567");
+///     sms.send("SomeAccountName", "082 123 4567").await?;
+///
+///     let message = sms.provider().0.lock().unwrap()[0].clone();
+///     let code = message.rsplit(' ').next().unwrap().to_string();
+///
+///     assert!(sms.verify("SomeAccountName", code.clone()).await?);
+///     assert!(!sms.verify("SomeAccountName", code).await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct SmsOtp<P, S> {
+    otp: Otp<SmsChannel<P>, S>,
+}
+
+impl<P: SmsProvider + Sync, S: CodeStore> SmsOtp<P, S> {
+    pub fn new(provider: P, store: S) -> Self {
+        Self {
+            otp: Otp::new(SmsChannel::new(provider), store).ttl(DEFAULT_TTL),
+        }
+    }
+
+    /// Length of the generated codes
+    pub fn digits(mut self, digits: usize) -> Self {
+        self.otp = self.otp.digits(digits);
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.otp = self.otp.ttl(ttl);
+        self
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.otp = self.otp.max_attempts(max_attempts);
+        self
+    }
+
+    /// Minimum time between two codes for the same key, `otp::DEFAULT_COOLDOWN` by default
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.otp = self.otp.cooldown(cooldown);
+        self
+    }
+
+    /// Message text sent to the user, `{code}` is replaced with the code
+    pub fn template(mut self, template: String) -> Self {
+        self.otp.channel_mut().template = template;
+        self
+    }
+
+    /// ISO 3166 country code national numbers without a country code are read in, e.g. `ZA`
+    pub fn region(mut self, region: &str) -> Self {
+        self.otp.channel_mut().region = Some(region.to_string());
+        self
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.otp.channel().provider
+    }
+
+    /// Check a number the way `send` does, to store it canonically when the user enrolls it
+    pub fn normalize(&self, phone_number: &str) -> Result<PhoneNumber, AuthError> {
+        self.otp.channel().normalize(phone_number)
+    }
+
+    /// Generate a new code for the key and text it to the phone number
+    ///
+    /// Fails with `AuthError::InvalidInput`, before a code is issued, if the number cannot receive
+    /// texts, and with `AuthError::RateLimited` if a code was sent for the key within the cooldown.
+    pub async fn send(&self, key: &str, phone_number: &str) -> Result<(), AuthError> {
+        let phone_number = self.normalize(phone_number)?.e164();
+        let key = self.otp.key(key);
+        let send = self.otp.dispatch(&key, &phone_number);
+
+        otel::span("mfa.sms.send", Some(&key), send).await
+    }
+
+    /// Check a code entered by the user, consuming it on success
+    pub async fn verify(&self, key: &str, code: String) -> Result<bool, AuthError> {
+        let key = self.otp.key(key);
+        let verify = self.otp.check(&key, &code);
+
+        otel::span("mfa.sms.verify", Some(&key), verify).await
+    }
+}
+
+/// Texts codes through an `SmsProvider`, for use with `otp::Otp`
+pub struct SmsChannel<P> {
+    provider: P,
+    template: String,
+    region: Option<String>,
+}
+
+impl<P: SmsProvider> SmsChannel<P> {
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider,
+            template: "Your verification code is {code}".to_string(),
+            region: None,
+        }
+    }
+
+    /// ISO 3166 country code national numbers without a country code are read in, e.g. `ZA`
+    pub fn region(mut self, region: &str) -> Self {
+        self.region = Some(region.to_string());
+        self
+    }
+
+    /// Parse the number as E.164, rejecting numbers that cannot receive texts
+    pub fn normalize(&self, phone_number: &str) -> Result<PhoneNumber, AuthError> {
+        let number = PhoneNumber::parse(phone_number, self.region.as_deref())?;
+        if number.number_type() == NumberType::FixedLine {
+            return Err(AuthError::InvalidInput(
+                "Phone number cannot receive text messages".to_string(),
+            ));
+        }
+
+        Ok(number)
+    }
+
+    /// Message text sent to the user, `{code}` is replaced with the code
+    pub fn template(mut self, template: String) -> Self {
+        self.template = template;
+        self
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+}
+
+impl<P: SmsProvider + Sync> OtpChannel for SmsChannel<P> {
+    const NAME: &'static str = "sms";
+
+    async fn deliver(&self, phone_number: &str, code: &str) -> Result<(), AuthError> {
+        let phone_number = self.normalize(phone_number)?.e164();
+
+        self.provider
+            .send(&phone_number, &self.template.replace("{code}", code))
+            .await
+    }
+}
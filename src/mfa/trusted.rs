@@ -0,0 +1,294 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::{
+    crypto::{ct_eq, MIN_HMAC_KEY_LEN},
+    token::{generate_token, hash_token, now},
+    AuthError,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long a device skips MFA after it is trusted
+pub const DEFAULT_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A device a user chose to remember after passing MFA on it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TrustedDevice {
+    /// SHA-256 of the token's id; safe to show the user as a handle for `revoke`
+    pub id_hash: String,
+    pub account: String,
+    /// SHA-256 of the fingerprint the device presented when it was trusted
+    pub fingerprint_hash: String,
+    /// User facing name, e.g. "Firefox on Windows"
+    pub label: String,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+}
+
+/// Persists trusted devices
+pub trait TrustedDeviceStore {
+    fn save(&self, device: TrustedDevice) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn get(
+        &self,
+        id_hash: &str,
+    ) -> impl Future<Output = Result<Option<TrustedDevice>, AuthError>> + Send;
+
+    fn list(
+        &self,
+        account: &str,
+    ) -> impl Future<Output = Result<Vec<TrustedDevice>, AuthError>> + Send;
+
+    /// Returns `false` if there was no device with this hash
+    fn delete(&self, id_hash: &str) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Delete every device of the account, returning how many were deleted
+    fn delete_all(&self, account: &str) -> impl Future<Output = Result<usize, AuthError>> + Send;
+}
+
+/// "Remember this device": long lived tokens that let a device skip MFA
+///
+/// After a successful MFA check, `trust` returns a token `<id>.<expires>.<signature>` to keep in
+/// a long lived cookie. Its HMAC binds it to the account and to a hash of a device fingerprint,
+/// such as the user agent plus a value kept in local storage, so a stolen cookie fails on another
+/// device. Only a hash of the id is stored, and each device can be revoked on its own.
+///
+/// `verify` returns `Ok(false)` for a token that is expired, revoked, tampered with, for another
+/// account or from another device; the caller then asks for MFA as usual.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::trusted::{MemoryTrustedDeviceStore, TrustedDevices};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let devices = TrustedDevices::new(MemoryTrustedDeviceStore::default(), &[7u8; 32])?;
+///     let fingerprint = "Mozilla/5.0 (Windows NT 10.0) Firefox/126.0|b5c1e0";
+///
+///     // MFA just succeeded and the user ticked "remember this device"
+///     let token = devices.trust("SomeAccountName", fingerprint, "Firefox on Windows").await?;
+///
+///     // On the next sign in the MFA step can be skipped
+///     assert!(devices.verify(&token, "SomeAccountName", fingerprint).await?);
+///     assert!(!devices.verify(&token, "SomeAccountName", "curl/8.7.1|b5c1e0").await?);
+///     assert!(!devices.verify(&token, "SomeOtherAccount", fingerprint).await?);
+///
+///     // Forgetting the device from a security settings page
+///     let device = devices.list("SomeAccountName").await?.remove(0);
+///     assert!(devices.revoke("SomeAccountName", &device.id_hash).await?);
+///     assert!(!devices.verify(&token, "SomeAccountName", fingerprint).await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct TrustedDevices<S> {
+    store: S,
+    signing_key: Zeroizing<Vec<u8>>,
+    ttl: Duration,
+}
+
+impl<S: TrustedDeviceStore> TrustedDevices<S> {
+    pub fn new(store: S, signing_key: &[u8]) -> Result<Self, AuthError> {
+        if signing_key.len() < MIN_HMAC_KEY_LEN {
+            return Err(AuthError::InvalidInput(format!(
+                "Device token signing keys must be at least {} bytes",
+                MIN_HMAC_KEY_LEN
+            )));
+        }
+
+        Ok(Self {
+            store,
+            signing_key: Zeroizing::new(signing_key.to_vec()),
+            ttl: DEFAULT_TTL,
+        })
+    }
+
+    /// How long a trusted device skips MFA
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Remember the device; call only after MFA succeeded on it
+    pub async fn trust(
+        &self,
+        account: &str,
+        fingerprint: &str,
+        label: &str,
+    ) -> Result<String, AuthError> {
+        let id = generate_token();
+        let now = now()?;
+        let expires_at = now + self.ttl.as_secs();
+        let fingerprint_hash = hash_token(fingerprint);
+
+        let tag = self
+            .mac(&id, account, &fingerprint_hash, expires_at)
+            .finalize()
+            .into_bytes();
+
+        self.store
+            .save(TrustedDevice {
+                id_hash: hash_token(&id),
+                account: account.to_string(),
+                fingerprint_hash,
+                label: label.to_string(),
+                created_at: now,
+                expires_at,
+            })
+            .await?;
+
+        Ok(format!(
+            "{}.{}.{}",
+            id,
+            expires_at,
+            URL_SAFE_NO_PAD.encode(tag)
+        ))
+    }
+
+    /// Whether the token from this device lets `account` skip MFA
+    pub async fn verify(
+        &self,
+        token: &str,
+        account: &str,
+        fingerprint: &str,
+    ) -> Result<bool, AuthError> {
+        let mut parts = token.split('.');
+        let (id, expires_at, tag) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(expires_at), Some(tag), None) => {
+                match (expires_at.parse::<u64>(), URL_SAFE_NO_PAD.decode(tag)) {
+                    (Ok(expires_at), Ok(tag)) => (id, expires_at, tag),
+                    _ => return Ok(false),
+                }
+            }
+            _ => return Ok(false),
+        };
+
+        if expires_at <= now()? {
+            return Ok(false);
+        }
+
+        let fingerprint_hash = hash_token(fingerprint);
+        if self
+            .mac(id, account, &fingerprint_hash, expires_at)
+            .verify_slice(&tag)
+            .is_err()
+        {
+            return Ok(false);
+        }
+
+        Ok(self
+            .store
+            .get(&hash_token(id))
+            .await?
+            .is_some_and(|device| {
+                device.account == account
+                    && device.expires_at == expires_at
+                    && ct_eq(&device.fingerprint_hash, &fingerprint_hash)
+            }))
+    }
+
+    /// The account's unexpired trusted devices, newest first
+    pub async fn list(&self, account: &str) -> Result<Vec<TrustedDevice>, AuthError> {
+        let now = now()?;
+
+        let mut devices: Vec<_> = self
+            .store
+            .list(account)
+            .await?
+            .into_iter()
+            .filter(|device| device.expires_at > now)
+            .collect();
+        devices.sort_by_key(|device| Reverse(device.created_at));
+
+        Ok(devices)
+    }
+
+    /// Forget one of the account's devices by its `id_hash`
+    ///
+    /// Returns `false` if no such device belongs to the account.
+    pub async fn revoke(&self, account: &str, id_hash: &str) -> Result<bool, AuthError> {
+        match self.store.get(id_hash).await? {
+            Some(device) if device.account == account => self.store.delete(id_hash).await,
+            _ => Ok(false),
+        }
+    }
+
+    /// Forget every device of the account, e.g. after a password reset or when MFA is changed
+    pub async fn revoke_all(&self, account: &str) -> Result<usize, AuthError> {
+        self.store.delete_all(account).await
+    }
+
+    fn mac(&self, id: &str, account: &str, fingerprint_hash: &str, expires_at: u64) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.signing_key).expect("HMAC accepts any key length");
+        mac.update(format!("{}.{}.{}.{}", id, account, fingerprint_hash, expires_at).as_bytes());
+        mac
+    }
+}
+
+/// Keeps trusted devices in process memory, dropping expired ones as new ones are saved
+#[derive(Debug, Default)]
+pub struct MemoryTrustedDeviceStore {
+    devices: Mutex<HashMap<String, TrustedDevice>>,
+}
+
+impl MemoryTrustedDeviceStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, TrustedDevice>>, AuthError> {
+        self.devices
+            .lock()
+            .map_err(|_| AuthError::backend("Trusted device store lock poisoned"))
+    }
+}
+
+impl TrustedDeviceStore for MemoryTrustedDeviceStore {
+    async fn save(&self, device: TrustedDevice) -> Result<(), AuthError> {
+        let now = now()?;
+        let mut devices = self.lock()?;
+
+        devices.retain(|_, device| device.expires_at > now);
+        devices.insert(device.id_hash.clone(), device);
+
+        Ok(())
+    }
+
+    async fn get(&self, id_hash: &str) -> Result<Option<TrustedDevice>, AuthError> {
+        Ok(self.lock()?.get(id_hash).cloned())
+    }
+
+    async fn list(&self, account: &str) -> Result<Vec<TrustedDevice>, AuthError> {
+        Ok(self
+            .lock()?
+            .values()
+            .filter(|device| device.account == account)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete(&self, id_hash: &str) -> Result<bool, AuthError> {
+        Ok(self.lock()?.remove(id_hash).is_some())
+    }
+
+    async fn delete_all(&self, account: &str) -> Result<usize, AuthError> {
+        let mut devices = self.lock()?;
+        let before = devices.len();
+        devices.retain(|_, device| device.account != account);
+
+        Ok(before - devices.len())
+    }
+}
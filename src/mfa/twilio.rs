@@ -0,0 +1,256 @@
+use std::{env, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+use crate::{
+    http::{HttpClient, HttpRequest, HttpResponse},
+    otel,
+    rate_limit::RateLimited,
+    AuthError,
+};
+
+use super::sms::SmsProvider;
+
+/// Twilio's REST API
+pub const DEFAULT_BASE_URL: &str = "https://api.twilio.com";
+
+/// How many times a message rejected with `429 Too Many Requests` is sent again
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Wait before the first retry when Twilio sends no `Retry-After`, doubled on each retry
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait between retries, whatever `Retry-After` asks for
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Where messages are sent from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sender {
+    /// A Twilio phone number in E.164 format, or an alphanumeric sender id
+    Number(String),
+    /// A Messaging Service SID (`MG...`), letting Twilio pick the number from its pool
+    MessagingService(String),
+}
+
+/// Sends SMS codes through Twilio's Programmable Messaging API
+///
+/// Requests rejected with `429` are retried after `Retry-After`, or with exponential backoff,
+/// up to `max_retries` times before failing with `AuthError::RateLimited`. A recipient Twilio
+/// cannot deliver to, such as an invalid or unsubscribed number, fails with
+/// `AuthError::InvalidInput`; anything else Twilio rejects fails with `AuthError::Backend`.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::mfa::sms::SmsProvider;
+/// use lonewolf_auth_toolkit::mfa::twilio::{Sender, TwilioSms};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct FakeTwilio(Mutex<Vec<HttpRequest>>);
+///
+/// impl HttpClient for FakeTwilio {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         let mut requests = self.0.lock().unwrap();
+///         requests.push(request);
+///
+///         // The first attempt is throttled
+///         let status = if requests.len() == 1 { 429 } else { 201 };
+///         Ok(HttpResponse {
+///             status,
+///             headers: vec![("Retry-After".to_string(), "0".to_string())],
+///             body: br#"{"sid": "SM123"}"#.to_vec(),
+///         })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let twilio = TwilioSms::new(
+///         FakeTwilio::default(),
+///         "AC00000000000000000000000000000000",
+///         "auth-token",
+///         Sender::MessagingService("MG00000000000000000000000000000000".to_string()),
+///     )?;
+///
+///     twilio.send("+27821234567", "Your verification code is 123456").await?;
+///
+///     let requests = twilio.client().0.lock().unwrap();
+///     assert_eq!(requests.len(), 2);
+///     assert!(requests[1].url.ends_with("/Messages.json"));
+///     assert!(String::from_utf8_lossy(&requests[1].body).contains("MessagingServiceSid=MG"));
+///
+///     Ok(())
+/// }
+/// ```
+pub struct TwilioSms<C> {
+    client: C,
+    account_sid: String,
+    auth_token: Zeroizing<String>,
+    sender: Sender,
+    base_url: String,
+    max_retries: u32,
+}
+
+impl<C: HttpClient> TwilioSms<C> {
+    pub fn new(
+        client: C,
+        account_sid: &str,
+        auth_token: &str,
+        sender: Sender,
+    ) -> Result<Self, AuthError> {
+        if !account_sid.starts_with("AC") {
+            return Err(AuthError::InvalidInput(
+                "Twilio account SIDs start with AC".to_string(),
+            ));
+        }
+        if matches!(&sender, Sender::MessagingService(sid) if !sid.starts_with("MG")) {
+            return Err(AuthError::InvalidInput(
+                "Twilio Messaging Service SIDs start with MG".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            client,
+            account_sid: account_sid.to_string(),
+            auth_token: Zeroizing::new(auth_token.to_string()),
+            sender,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            max_retries: DEFAULT_MAX_RETRIES,
+        })
+    }
+
+    /// Configure from `TWILIO_ACCOUNT_SID`, `TWILIO_AUTH_TOKEN` and either
+    /// `TWILIO_MESSAGING_SERVICE_SID` or `TWILIO_FROM_NUMBER`
+    pub fn from_env(client: C) -> Result<Self, AuthError> {
+        let sender = match (
+            env::var("TWILIO_MESSAGING_SERVICE_SID"),
+            env::var("TWILIO_FROM_NUMBER"),
+        ) {
+            (Ok(sid), _) => Sender::MessagingService(sid),
+            (_, Ok(number)) => Sender::Number(number),
+            _ => {
+                return Err(AuthError::InvalidInput(
+                    "Set TWILIO_MESSAGING_SERVICE_SID or TWILIO_FROM_NUMBER".to_string(),
+                ))
+            }
+        };
+
+        Self::new(
+            client,
+            &env_var("TWILIO_ACCOUNT_SID")?,
+            &env_var("TWILIO_AUTH_TOKEN")?,
+            sender,
+        )
+    }
+
+    /// Send to another API host, e.g. a regional edge or a test server
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    fn request(&self, phone_number: &str, message: &str) -> HttpRequest {
+        let url = format!(
+            "{}/2010-04-01/Accounts/{}/Messages.json",
+            self.base_url, self.account_sid
+        );
+        let (sender_param, sender) = match &self.sender {
+            Sender::Number(number) => ("From", number),
+            Sender::MessagingService(sid) => ("MessagingServiceSid", sid),
+        };
+
+        HttpRequest::post_form(
+            url,
+            &[
+                ("To", phone_number),
+                (sender_param, sender),
+                ("Body", message),
+            ],
+        )
+        .header(
+            "Authorization",
+            format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", self.account_sid, *self.auth_token))
+            ),
+        )
+    }
+}
+
+impl<C: HttpClient + Sync> SmsProvider for TwilioSms<C> {
+    async fn send(&self, phone_number: &str, message: &str) -> Result<(), AuthError> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut retries = 0;
+
+        loop {
+            let response = otel::send(&self.client, self.request(phone_number, message)).await?;
+
+            if response.is_success() {
+                return Ok(());
+            }
+
+            if response.status != 429 {
+                return Err(error(&response));
+            }
+
+            let wait = retry_after(&response).unwrap_or(backoff).min(MAX_BACKOFF);
+            if retries >= self.max_retries {
+                return Err(AuthError::RateLimited(RateLimited { retry_after: wait }));
+            }
+
+            tokio::time::sleep(wait).await;
+            backoff *= 2;
+            retries += 1;
+        }
+    }
+}
+
+/// Twilio's error body, e.g. `{"code": 21211, "message": "The 'To' number is not valid", ...}`
+#[derive(Deserialize)]
+struct ErrorResponse {
+    code: Option<u32>,
+    message: Option<String>,
+}
+
+fn error(response: &HttpResponse) -> AuthError {
+    let body = serde_json::from_slice::<ErrorResponse>(&response.body).ok();
+    let code = body.as_ref().and_then(|body| body.code);
+    let message = body
+        .and_then(|body| body.message)
+        .unwrap_or_else(|| format!("HTTP {}", response.status));
+
+    match (response.status, code) {
+        (401 | 403, _) => AuthError::backend("Twilio rejected the account SID or auth token"),
+        // Invalid, unreachable, landline, unsubscribed or blocked recipients
+        (400, Some(21211 | 21214 | 21408 | 21610 | 21612 | 21614)) => {
+            AuthError::InvalidInput(format!("Cannot text this number: {}", message))
+        }
+        (_, Some(code)) => AuthError::backend(format!("Twilio error {}: {}", code, message)),
+        _ => AuthError::backend(format!("Twilio error: {}", message)),
+    }
+}
+
+fn retry_after(response: &HttpResponse) -> Option<Duration> {
+    response
+        .header("Retry-After")
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+fn env_var(name: &str) -> Result<String, AuthError> {
+    env::var(name).map_err(|_| AuthError::InvalidInput(format!("Set {}", name)))
+}
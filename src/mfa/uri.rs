@@ -0,0 +1,216 @@
+use std::{fmt, str::FromStr};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use totp_rs::Algorithm;
+use url::Url;
+
+use crate::AuthError;
+
+use super::TotpConfig;
+
+/// Whether a provisioning URI describes a time or counter based secret
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum OtpKind {
+    Totp,
+    Hotp { counter: u64 },
+}
+
+/// A typed `otpauth://` provisioning URI, as encoded in authenticator QR codes
+///
+/// See the Key URI Format used by Google Authenticator; `window` on the config is a verifier
+/// setting and is not carried in the URI.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mfa::uri::{OtpAuthUri, OtpKind};
+/// use lonewolf_auth_toolkit::mfa::{Algorithm, TotpBuilder};
+///
+/// let uri = OtpAuthUri {
+///     kind: OtpKind::Totp,
+///     issuer: Some("Some Issuer".to_string()),
+///     account_name: "someone@example.com".to_string(),
+///     secret: "MZXW6YTBOI".to_string(),
+///     config: TotpBuilder::new().digits(8).algorithm(Algorithm::SHA256).build().unwrap(),
+/// };
+///
+/// let text = uri.to_string();
+///
+/// assert_eq!(text, "otpauth://totp/Some%20Issuer:someone%40example.com?secret=MZXW6YTBOI&issuer=Some%20Issuer&algorithm=SHA256&digits=8");
+/// assert_eq!(text.parse::<OtpAuthUri>().unwrap(), uri);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OtpAuthUri {
+    pub kind: OtpKind,
+    pub issuer: Option<String>,
+    pub account_name: String,
+    /// Base32 encoded secret
+    pub secret: String,
+    pub config: TotpConfig,
+}
+
+impl OtpAuthUri {
+    /// Parse an `otpauth://totp/...` or `otpauth://hotp/...` URI
+    ///
+    /// ### Example
+    /// ```rust
+    /// use lonewolf_auth_toolkit::mfa::uri::{OtpAuthUri, OtpKind};
+    ///
+    /// let uri = OtpAuthUri::parse("otpauth://hotp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&counter=7").unwrap();
+    ///
+    /// assert_eq!(uri.kind, OtpKind::Hotp { counter: 7 });
+    /// assert_eq!(uri.issuer.as_deref(), Some("ACME Co"));
+    /// assert_eq!(uri.account_name, "john.doe@email.com");
+    /// ```
+    pub fn parse(uri: &str) -> Result<Self, AuthError> {
+        let url = Url::parse(uri)?;
+
+        if url.scheme() != "otpauth" {
+            return Err(AuthError::Malformed(
+                "URI scheme must be otpauth".to_string(),
+            ));
+        }
+
+        let mut counter = None;
+        let mut steam = false;
+        let mut secret = None;
+        let mut issuer = None;
+        let mut config = TotpConfig::default();
+
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "secret" => secret = Some(value.to_uppercase()),
+                "issuer" => issuer = Some(value.to_string()),
+                "algorithm" => {
+                    config.algorithm = match value.to_uppercase().as_str() {
+                        "SHA1" => Algorithm::SHA1,
+                        "SHA256" => Algorithm::SHA256,
+                        "SHA512" => Algorithm::SHA512,
+                        _ => {
+                            return Err(AuthError::Malformed(format!(
+                                "Unsupported algorithm {}",
+                                value
+                            )))
+                        }
+                    }
+                }
+                "digits" => config.digits = value.parse()?,
+                "period" => config.step = value.parse()?,
+                "counter" => counter = Some(value.parse::<u64>()?),
+                "encoder" => steam = value.eq_ignore_ascii_case("steam"),
+                _ => {}
+            }
+        }
+
+        let kind = match url.host_str() {
+            Some("totp") => OtpKind::Totp,
+            Some("steam") => {
+                steam = true;
+                OtpKind::Totp
+            }
+            Some("hotp") => OtpKind::Hotp {
+                counter: counter.ok_or_else(|| {
+                    AuthError::Malformed("HOTP URI is missing the counter".to_string())
+                })?,
+            },
+            _ => {
+                return Err(AuthError::Malformed(
+                    "URI type must be totp, hotp or steam".to_string(),
+                ))
+            }
+        };
+
+        if steam {
+            config = TotpConfig {
+                step: config.step,
+                ..TotpConfig::steam()
+            };
+        }
+
+        let secret =
+            secret.ok_or_else(|| AuthError::Malformed("URI is missing the secret".to_string()))?;
+
+        if base32::decode(base32::Alphabet::RFC4648 { padding: false }, &secret).is_none() {
+            return Err(AuthError::MalformedSecret(
+                "URI secret is not valid Base32".to_string(),
+            ));
+        }
+
+        let label = urlencoding::decode(url.path().trim_start_matches('/'))?.to_string();
+        let account_name = match label.split_once(':') {
+            Some((label_issuer, account_name)) => {
+                issuer.get_or_insert_with(|| label_issuer.to_string());
+                account_name.trim_start().to_string()
+            }
+            None => label,
+        };
+
+        Ok(Self {
+            kind,
+            issuer,
+            account_name,
+            secret,
+            config,
+        })
+    }
+}
+
+impl fmt::Display for OtpAuthUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let defaults = TotpConfig::default();
+        let account_name = urlencoding::encode(&self.account_name);
+        let mut params = vec![format!("secret={}", self.secret)];
+
+        let label = match &self.issuer {
+            Some(issuer) => {
+                let issuer = urlencoding::encode(issuer);
+                params.push(format!("issuer={}", issuer));
+                format!("{}:{}", issuer, account_name)
+            }
+            None => account_name.to_string(),
+        };
+
+        // Steam's algorithm and length are implied by the `steam` type
+        let steam = self.config.algorithm == Algorithm::Steam;
+
+        if self.config.algorithm != defaults.algorithm && !steam {
+            params.push(format!("algorithm={}", self.config.algorithm));
+        }
+
+        if self.config.digits != defaults.digits && !steam {
+            params.push(format!("digits={}", self.config.digits));
+        }
+
+        let kind = match self.kind {
+            OtpKind::Totp => {
+                if self.config.step != defaults.step {
+                    params.push(format!("period={}", self.config.step));
+                }
+
+                if steam {
+                    "steam"
+                } else {
+                    "totp"
+                }
+            }
+            OtpKind::Hotp { counter } => {
+                params.push(format!("counter={}", counter));
+
+                "hotp"
+            }
+        };
+
+        write!(f, "otpauth://{}/{}?{}", kind, label, params.join("&"))
+    }
+}
+
+impl FromStr for OtpAuthUri {
+    type Err = AuthError;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        Self::parse(uri)
+    }
+}
@@ -0,0 +1,551 @@
+use std::net::IpAddr;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    digest::{digest, SHA256},
+    signature::{self, UnparsedPublicKey, VerificationAlgorithm},
+};
+
+use crate::AuthError;
+
+const RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const P384: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+
+const SHA256_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const SHA384_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const SHA512_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const ECDSA_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+
+const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x0f];
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+const BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1d, 0x13];
+const EXTENDED_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25];
+const SUBJECT_KEY_IDENTIFIER: &[u8] = &[0x55, 0x1d, 0x0e];
+const AUTHORITY_KEY_IDENTIFIER: &[u8] = &[0x55, 0x1d, 0x23];
+
+/// `id-kp-clientAuth`, 1.3.6.1.5.5.7.3.2
+const CLIENT_AUTH: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x02];
+/// `anyExtendedKeyUsage`, 2.5.29.37.0
+const ANY_EXTENDED_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25, 0x00];
+
+/// `digitalSignature` and `keyCertSign` in the first octet of a key usage bit string
+const DIGITAL_SIGNATURE: u8 = 0x80;
+const KEY_CERT_SIGN: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PublicKey {
+    Rsa,
+    P256,
+    P384,
+    Ed25519,
+    Unsupported,
+}
+
+/// A parsed X.509 certificate
+///
+/// Only what client certificate checks need is decoded: names, validity, key, basic
+/// constraints, key usage and subject alternative names. Signatures can be RSA PKCS#1 with
+/// SHA-256 to SHA-512, ECDSA on P-256 or P-384, or Ed25519.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mtls::certificate::Certificate;
+///
+/// let chain = Certificate::parse_pem(include_str!("testdata/chain.pem"))?;
+/// let client = &chain[0];
+///
+/// assert_eq!(client.common_name(), Some("billing"));
+/// assert_eq!(client.uris(), ["spiffe://example.com/ns/prod/sa/billing"]);
+/// assert_eq!(client.dns_names(), ["billing.internal.example.com"]);
+/// assert!(client.allows_client_auth());
+/// assert!(!client.is_ca());
+///
+/// assert!(chain[1].is_ca());
+/// assert!(client.is_signed_by(&chain[1]));
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    der: Vec<u8>,
+    tbs: Vec<u8>,
+    serial: Vec<u8>,
+    issuer: Vec<u8>,
+    subject: Vec<u8>,
+    common_name: Option<String>,
+    not_before: u64,
+    not_after: u64,
+    key: PublicKey,
+    public_key: Vec<u8>,
+    signature_algorithm: Vec<u8>,
+    signature: Vec<u8>,
+    ca: bool,
+    path_len: Option<u32>,
+    key_usage: Option<u8>,
+    extended_key_usage: Option<Vec<Vec<u8>>>,
+    dns_names: Vec<String>,
+    emails: Vec<String>,
+    uris: Vec<String>,
+    ips: Vec<IpAddr>,
+    /// Every extension's OID and `OCTET STRING` contents, for checks beyond this module's own
+    extensions: Vec<(Vec<u8>, Vec<u8>)>,
+    unknown_critical: bool,
+}
+
+impl Certificate {
+    pub fn from_der(der: &[u8]) -> Result<Self, AuthError> {
+        let (certificate, rest) = read(der, 0x30)?;
+        if !rest.is_empty() {
+            return Err(malformed());
+        }
+
+        let (tbs_contents, after_tbs) = read(certificate, 0x30)?;
+        let tbs = certificate[..certificate.len() - after_tbs.len()].to_vec();
+        let (outer_algorithm, rest) = read(after_tbs, 0x30)?;
+        let (signature, _) = bit_string(rest)?;
+
+        let mut tbs_rest = tbs_contents;
+        if tbs_rest.first() == Some(&0xa0) {
+            tbs_rest = read(tbs_rest, 0xa0)?.1;
+        }
+        let (serial, rest) = read(tbs_rest, 0x02)?;
+        let (inner_algorithm, rest) = read(rest, 0x30)?;
+        if inner_algorithm != outer_algorithm {
+            return Err(malformed());
+        }
+        let (issuer, rest) = read(rest, 0x30)?;
+        let (validity, rest) = read(rest, 0x30)?;
+        let (subject, rest) = read(rest, 0x30)?;
+        let (public_key_info, mut rest) = read(rest, 0x30)?;
+
+        let (not_before, validity) = time(validity)?;
+        let (not_after, _) = time(validity)?;
+
+        let (algorithm, key_rest) = read(public_key_info, 0x30)?;
+        let (key_oid, parameters) = read(algorithm, 0x06)?;
+        let key = match key_oid {
+            RSA_ENCRYPTION => PublicKey::Rsa,
+            EC_PUBLIC_KEY => match read(parameters, 0x06).map(|(curve, _)| curve) {
+                Ok(P256) => PublicKey::P256,
+                Ok(P384) => PublicKey::P384,
+                _ => PublicKey::Unsupported,
+            },
+            ED25519 => PublicKey::Ed25519,
+            _ => PublicKey::Unsupported,
+        };
+        let (public_key, _) = bit_string(key_rest)?;
+
+        let mut parsed = Self {
+            der: der.to_vec(),
+            tbs,
+            serial: serial.to_vec(),
+            issuer: issuer.to_vec(),
+            subject: subject.to_vec(),
+            common_name: attribute(subject, COMMON_NAME)?,
+            not_before,
+            not_after,
+            key,
+            public_key: public_key.to_vec(),
+            signature_algorithm: read(outer_algorithm, 0x06)?.0.to_vec(),
+            signature: signature.to_vec(),
+            ca: false,
+            path_len: None,
+            key_usage: None,
+            extended_key_usage: None,
+            dns_names: Vec::new(),
+            emails: Vec::new(),
+            uris: Vec::new(),
+            ips: Vec::new(),
+            extensions: Vec::new(),
+            unknown_critical: false,
+        };
+
+        // Skip the unique ids, then read the extensions if there are any
+        for tag in [0x81, 0xa1, 0x82, 0xa2] {
+            if rest.first() == Some(&tag) {
+                rest = read(rest, tag)?.1;
+            }
+        }
+        if rest.first() == Some(&0xa3) {
+            let (extensions, _) = read(read(rest, 0xa3)?.0, 0x30)?;
+            parsed.read_extensions(extensions)?;
+        }
+
+        Ok(parsed)
+    }
+
+    /// Every `CERTIFICATE` block in PEM text, e.g. a CA bundle or a chain from a proxy header
+    pub fn parse_pem(pem: &str) -> Result<Vec<Self>, AuthError> {
+        let mut certificates = Vec::new();
+
+        let mut rest = pem;
+        while let Some(start) = rest.find("-----BEGIN CERTIFICATE-----") {
+            let body = &rest[start + "-----BEGIN CERTIFICATE-----".len()..];
+            let end = body.find("-----END CERTIFICATE-----").ok_or_else(|| {
+                AuthError::Malformed("Certificate PEM block is not terminated".to_string())
+            })?;
+
+            let encoded = body[..end]
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<String>();
+            let der = STANDARD
+                .decode(encoded)
+                .map_err(|_| AuthError::Malformed("Certificate is not valid PEM".to_string()))?;
+            certificates.push(Self::from_der(&der)?);
+
+            rest = &body[end..];
+        }
+
+        if certificates.is_empty() {
+            return Err(AuthError::Malformed("No certificates in PEM".to_string()));
+        }
+
+        Ok(certificates)
+    }
+
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// The SHA-256 of the DER encoding as lowercase hex, as pinned in most proxies' configs
+    pub fn fingerprint(&self) -> String {
+        hex(digest(&SHA256, &self.der).as_ref())
+    }
+
+    /// The serial number as lowercase hex without leading zeros
+    pub fn serial(&self) -> String {
+        let serial = hex(&self.serial);
+        match serial.trim_start_matches('0') {
+            "" => "0".to_string(),
+            trimmed => trimmed.to_string(),
+        }
+    }
+
+    pub fn common_name(&self) -> Option<&str> {
+        self.common_name.as_deref()
+    }
+
+    pub fn dns_names(&self) -> &[String] {
+        &self.dns_names
+    }
+
+    pub fn emails(&self) -> &[String] {
+        &self.emails
+    }
+
+    /// URI subject alternative names, such as SPIFFE ids
+    pub fn uris(&self) -> &[String] {
+        &self.uris
+    }
+
+    pub fn ips(&self) -> &[IpAddr] {
+        &self.ips
+    }
+
+    /// Unix timestamp the certificate is valid from
+    pub fn not_before(&self) -> u64 {
+        self.not_before
+    }
+
+    /// Unix timestamp the certificate is valid until
+    pub fn not_after(&self) -> u64 {
+        self.not_after
+    }
+
+    pub fn is_valid_at(&self, now: u64) -> bool {
+        self.not_before <= now && now <= self.not_after
+    }
+
+    /// Whether basic constraints mark this as a CA certificate
+    pub fn is_ca(&self) -> bool {
+        self.ca
+    }
+
+    /// How many intermediate CAs may follow this one, if limited
+    pub fn path_len(&self) -> Option<u32> {
+        self.path_len
+    }
+
+    /// Whether key usage and extended key usage, when present, allow TLS client authentication
+    pub fn allows_client_auth(&self) -> bool {
+        let key_usage = self
+            .key_usage
+            .is_none_or(|usage| usage & DIGITAL_SIGNATURE != 0);
+        let extended_key_usage = self.extended_key_usage.as_ref().is_none_or(|usages| {
+            usages
+                .iter()
+                .any(|usage| usage == CLIENT_AUTH || usage == ANY_EXTENDED_KEY_USAGE)
+        });
+
+        key_usage && extended_key_usage
+    }
+
+    /// Whether key usage, when present, allows signing certificates
+    pub fn allows_cert_sign(&self) -> bool {
+        self.key_usage
+            .is_none_or(|usage| usage & KEY_CERT_SIGN != 0)
+    }
+
+    /// Whether a critical extension this parser does not understand is present, in which case
+    /// the certificate must be rejected
+    pub fn has_unknown_critical_extension(&self) -> bool {
+        self.unknown_critical
+    }
+
+    /// The first subject attribute with this OID, e.g. the organizational unit
+    pub(crate) fn subject_attribute(&self, oid: &[u8]) -> Option<String> {
+        attribute(&self.subject, oid).ok().flatten()
+    }
+
+    pub(crate) fn has_empty_subject(&self) -> bool {
+        self.subject.is_empty()
+    }
+
+    /// The `subjectPublicKey` bits: a SEC1 point, an Ed25519 key or a DER `RSAPublicKey`
+    pub(crate) fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// The contents of the extension with this OID, if present
+    pub(crate) fn extension(&self, oid: &[u8]) -> Option<&[u8]> {
+        self.extensions
+            .iter()
+            .find(|(known, _)| known == oid)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    pub(crate) fn extended_key_usages(&self) -> Option<&[Vec<u8>]> {
+        self.extended_key_usage.as_deref()
+    }
+
+    /// Whether this certificate's key verifies `signature` over `message`
+    pub(crate) fn verifies(
+        &self,
+        algorithm: &'static dyn VerificationAlgorithm,
+        message: &[u8],
+        signature: &[u8],
+    ) -> bool {
+        UnparsedPublicKey::new(algorithm, &self.public_key)
+            .verify(message, signature)
+            .is_ok()
+    }
+
+    /// Whether `issuer` issued this certificate: its subject is this certificate's issuer and its
+    /// key verifies the signature
+    pub fn is_signed_by(&self, issuer: &Certificate) -> bool {
+        if self.issuer != issuer.subject {
+            return false;
+        }
+
+        let algorithm: &'static dyn VerificationAlgorithm =
+            match (self.signature_algorithm.as_slice(), issuer.key) {
+                (SHA256_WITH_RSA, PublicKey::Rsa) => &signature::RSA_PKCS1_2048_8192_SHA256,
+                (SHA384_WITH_RSA, PublicKey::Rsa) => &signature::RSA_PKCS1_2048_8192_SHA384,
+                (SHA512_WITH_RSA, PublicKey::Rsa) => &signature::RSA_PKCS1_2048_8192_SHA512,
+                (ECDSA_WITH_SHA256, PublicKey::P256) => &signature::ECDSA_P256_SHA256_ASN1,
+                (ECDSA_WITH_SHA384, PublicKey::P256) => &signature::ECDSA_P256_SHA384_ASN1,
+                (ECDSA_WITH_SHA256, PublicKey::P384) => &signature::ECDSA_P384_SHA256_ASN1,
+                (ECDSA_WITH_SHA384, PublicKey::P384) => &signature::ECDSA_P384_SHA384_ASN1,
+                (ED25519, PublicKey::Ed25519) => &signature::ED25519,
+                _ => return false,
+            };
+
+        UnparsedPublicKey::new(algorithm, &issuer.public_key)
+            .verify(&self.tbs, &self.signature)
+            .is_ok()
+    }
+
+    fn read_extensions(&mut self, mut extensions: &[u8]) -> Result<(), AuthError> {
+        while !extensions.is_empty() {
+            let (extension, rest) = read(extensions, 0x30)?;
+            extensions = rest;
+
+            let (oid, rest) = read(extension, 0x06)?;
+            let (critical, rest) = match rest.first() {
+                Some(0x01) => {
+                    let (critical, rest) = read(rest, 0x01)?;
+                    (critical.first().is_some_and(|byte| *byte != 0), rest)
+                }
+                _ => (false, rest),
+            };
+            let (value, _) = read(rest, 0x04)?;
+            self.extensions.push((oid.to_vec(), value.to_vec()));
+
+            match oid {
+                BASIC_CONSTRAINTS => {
+                    let (mut constraints, _) = read(value, 0x30)?;
+                    if constraints.first() == Some(&0x01) {
+                        let (ca, rest) = read(constraints, 0x01)?;
+                        self.ca = ca.first().is_some_and(|byte| *byte != 0);
+                        constraints = rest;
+                    }
+                    if constraints.first() == Some(&0x02) {
+                        let (len, _) = read(constraints, 0x02)?;
+                        self.path_len = Some(
+                            len.iter()
+                                .try_fold(0u32, |len, byte| {
+                                    len.checked_mul(256).map(|len| len + u32::from(*byte))
+                                })
+                                .ok_or_else(malformed)?,
+                        );
+                    }
+                }
+                KEY_USAGE => {
+                    let (bits, _) = bit_string(value)?;
+                    self.key_usage = Some(bits.first().copied().unwrap_or(0));
+                }
+                EXTENDED_KEY_USAGE => {
+                    let (mut usages, _) = read(value, 0x30)?;
+                    let mut oids = Vec::new();
+                    while !usages.is_empty() {
+                        let (usage, rest) = read(usages, 0x06)?;
+                        oids.push(usage.to_vec());
+                        usages = rest;
+                    }
+                    self.extended_key_usage = Some(oids);
+                }
+                SUBJECT_ALT_NAME => {
+                    let (mut names, _) = read(value, 0x30)?;
+                    while !names.is_empty() {
+                        let (tag, name, rest) = next(names)?;
+                        names = rest;
+
+                        let text = || String::from_utf8_lossy(name).into_owned();
+                        match tag {
+                            0x81 => self.emails.push(text()),
+                            0x82 => self.dns_names.push(text()),
+                            0x86 => self.uris.push(text()),
+                            0x87 => match name.len() {
+                                4 => self.ips.push(IpAddr::from(<[u8; 4]>::try_from(name)?)),
+                                16 => self.ips.push(IpAddr::from(<[u8; 16]>::try_from(name)?)),
+                                _ => return Err(malformed()),
+                            },
+                            _ => {}
+                        }
+                    }
+                }
+                SUBJECT_KEY_IDENTIFIER | AUTHORITY_KEY_IDENTIFIER => {}
+                _ if critical => self.unknown_critical = true,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The first attribute with the OID in a DER encoded name
+fn attribute(mut name: &[u8], wanted: &[u8]) -> Result<Option<String>, AuthError> {
+    while !name.is_empty() {
+        let (mut set, rest) = read(name, 0x31)?;
+        name = rest;
+
+        while !set.is_empty() {
+            let (attribute, rest) = read(set, 0x30)?;
+            set = rest;
+
+            let (oid, value) = read(attribute, 0x06)?;
+            if oid == wanted {
+                let (_, value, _) = next(value)?;
+                return Ok(Some(String::from_utf8_lossy(value).into_owned()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// A `UTCTime` or `GeneralizedTime` as a Unix timestamp, and whatever follows it
+fn time(input: &[u8]) -> Result<(u64, &[u8]), AuthError> {
+    let (tag, value, rest) = next(input)?;
+    let value = std::str::from_utf8(value).map_err(|_| malformed())?;
+
+    let (year, value) = match tag {
+        0x17 => {
+            let year: i64 = value.get(..2).ok_or_else(malformed)?.parse()?;
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                &value[2..],
+            )
+        }
+        0x18 => (value.get(..4).ok_or_else(malformed)?.parse()?, &value[4..]),
+        _ => return Err(malformed()),
+    };
+    if value.len() != 11 || !value.ends_with('Z') {
+        return Err(malformed());
+    }
+
+    let field = |index: usize| -> Result<i64, AuthError> { Ok(value[index..index + 2].parse()?) };
+    let (month, day) = (field(0)?, field(2)?);
+    let seconds = field(4)? * 3600 + field(6)? * 60 + field(8)?;
+
+    // Days since the epoch from a proleptic Gregorian date
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Ok((u64::try_from(days * 86_400 + seconds)?, rest))
+}
+
+/// The contents of a `BIT STRING` without its unused bits octet
+fn bit_string(input: &[u8]) -> Result<(&[u8], &[u8]), AuthError> {
+    let (bits, rest) = read(input, 0x03)?;
+    let (_, bits) = bits.split_first().ok_or_else(malformed)?;
+
+    Ok((bits, rest))
+}
+
+/// Split a DER value with the given tag into its contents and whatever follows it
+pub(crate) fn read(input: &[u8], tag: u8) -> Result<(&[u8], &[u8]), AuthError> {
+    match next(input)? {
+        (found, contents, rest) if found == tag => Ok((contents, rest)),
+        _ => Err(malformed()),
+    }
+}
+
+/// The next DER value's tag, contents and whatever follows it
+pub(crate) fn next(input: &[u8]) -> Result<(u8, &[u8], &[u8]), AuthError> {
+    let (&tag, rest) = input.split_first().ok_or_else(malformed)?;
+
+    let (&first, rest) = rest.split_first().ok_or_else(malformed)?;
+    let (length, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > 4 || rest.len() < count {
+            return Err(malformed());
+        }
+
+        let length = rest[..count]
+            .iter()
+            .fold(0usize, |length, &byte| (length << 8) | usize::from(byte));
+        (length, &rest[count..])
+    };
+
+    if rest.len() < length {
+        return Err(malformed());
+    }
+    let (contents, rest) = rest.split_at(length);
+
+    Ok((tag, contents, rest))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn malformed() -> AuthError {
+    AuthError::Malformed("Certificate is not valid DER".to_string())
+}
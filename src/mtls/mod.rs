@@ -0,0 +1,293 @@
+use std::{collections::HashSet, future::Future};
+
+use crate::{token::now, AuthError};
+
+use certificate::Certificate;
+
+pub mod certificate;
+
+/// Most certificates accepted in a chain, including the client's own
+pub const MAX_CHAIN_LEN: usize = 8;
+
+/// Decides whether a certificate in a client's chain was revoked
+///
+/// Implementations typically look the serial up in a CRL downloaded from the issuer's
+/// distribution point, or ask its OCSP responder.
+pub trait RevocationCheck {
+    fn is_revoked(
+        &self,
+        certificate: &Certificate,
+        issuer: &Certificate,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Treats no certificate as revoked
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoRevocationCheck;
+
+impl RevocationCheck for NoRevocationCheck {
+    async fn is_revoked(&self, _: &Certificate, _: &Certificate) -> Result<bool, AuthError> {
+        Ok(false)
+    }
+}
+
+/// Revoked serial numbers, e.g. from a CRL or a list of decommissioned services
+///
+/// Serials are matched whichever CA issued them, which is safe with the random 128 bit or longer
+/// serials CAs are required to use.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RevokedSerials {
+    serials: HashSet<String>,
+}
+
+impl RevokedSerials {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Revoke a serial given as hex, with or without `:` separators
+    pub fn revoke(mut self, serial: &str) -> Self {
+        self.serials.insert(normalize_hex(serial));
+        self
+    }
+}
+
+impl RevocationCheck for RevokedSerials {
+    async fn is_revoked(
+        &self,
+        certificate: &Certificate,
+        _: &Certificate,
+    ) -> Result<bool, AuthError> {
+        Ok(self.serials.contains(&certificate.serial()))
+    }
+}
+
+/// Verifies client certificate chains for mutual TLS between services
+///
+/// The chain is the client's certificate followed by any intermediates, as presented in the TLS
+/// handshake or forwarded by a proxy. It is accepted if it leads to one of the CA certificates
+/// through CA certificates allowed to sign, every certificate is within its validity period and
+/// path length limits, none has a critical extension this crate does not understand or is
+/// revoked, and the client's certificate allows client authentication. Errors are
+/// `AuthError::Verification` saying what was wrong.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mtls::{
+///     certificate::Certificate, ClientCertVerifier, RevokedSerials,
+/// };
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let verifier = ClientCertVerifier::new(include_str!("testdata/ca.pem"))?;
+///
+///     let chain = Certificate::parse_pem(include_str!("testdata/chain.pem"))?;
+///     verifier.verify(&chain).await?;
+///
+///     // The client certificate alone is missing its intermediate CA
+///     assert!(verifier.verify(&chain[..1]).await.is_err());
+///     assert!(verifier.verify_at(&chain, chain[0].not_after() + 1).await.is_err());
+///
+///     let self_signed = Certificate::parse_pem(include_str!("testdata/selfsigned.pem"))?;
+///     assert!(verifier.verify(&self_signed).await.is_err());
+///
+///     let revoked = RevokedSerials::new().revoke(&chain[0].serial());
+///     let verifier = verifier.revocation(revoked);
+///     assert!(verifier.verify(&chain).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct ClientCertVerifier<R = NoRevocationCheck> {
+    roots: Vec<Certificate>,
+    revocation: R,
+}
+
+impl ClientCertVerifier {
+    /// Trust the CA certificates in a PEM bundle
+    pub fn new(ca_bundle: &str) -> Result<Self, AuthError> {
+        Ok(Self {
+            roots: Certificate::parse_pem(ca_bundle)?,
+            revocation: NoRevocationCheck,
+        })
+    }
+}
+
+impl<R: RevocationCheck> ClientCertVerifier<R> {
+    pub fn revocation<C: RevocationCheck>(self, revocation: C) -> ClientCertVerifier<C> {
+        ClientCertVerifier {
+            roots: self.roots,
+            revocation,
+        }
+    }
+
+    pub fn roots(&self) -> &[Certificate] {
+        &self.roots
+    }
+
+    pub async fn verify(&self, chain: &[Certificate]) -> Result<(), AuthError> {
+        self.verify_at(chain, now()?).await
+    }
+
+    /// Like `verify` at the Unix timestamp `now`
+    pub async fn verify_at(&self, chain: &[Certificate], now: u64) -> Result<(), AuthError> {
+        let client = chain.first().ok_or_else(|| invalid("is missing"))?;
+        if chain.len() > MAX_CHAIN_LEN {
+            return Err(invalid("chain is too long"));
+        }
+
+        check(client, now)?;
+        if !client.allows_client_auth() {
+            return Err(invalid("is not allowed for client authentication"));
+        }
+
+        let mut current = client;
+        for intermediates in 0..MAX_CHAIN_LEN {
+            if let Some(root) = self.roots.iter().find(|root| current.is_signed_by(root)) {
+                check(root, now)?;
+                check_path_len(root, intermediates)?;
+                self.check_revocation(current, root).await?;
+
+                return Ok(());
+            }
+
+            let issuer = chain[1..]
+                .iter()
+                .find(|issuer| {
+                    issuer.is_ca() && issuer.allows_cert_sign() && current.is_signed_by(issuer)
+                })
+                .ok_or_else(|| invalid("is not issued by a trusted CA"))?;
+            check(issuer, now)?;
+            check_path_len(issuer, intermediates)?;
+            self.check_revocation(current, issuer).await?;
+
+            current = issuer;
+        }
+
+        Err(invalid("chain is too long"))
+    }
+
+    async fn check_revocation(
+        &self,
+        certificate: &Certificate,
+        issuer: &Certificate,
+    ) -> Result<(), AuthError> {
+        if self.revocation.is_revoked(certificate, issuer).await? {
+            return Err(invalid("chain contains a revoked certificate"));
+        }
+
+        Ok(())
+    }
+}
+
+fn check(certificate: &Certificate, now: u64) -> Result<(), AuthError> {
+    if !certificate.is_valid_at(now) {
+        return Err(invalid(
+            "chain contains an expired or not yet valid certificate",
+        ));
+    }
+    if certificate.has_unknown_critical_extension() {
+        return Err(invalid("chain contains an unsupported critical extension"));
+    }
+
+    Ok(())
+}
+
+/// Fail if more intermediates follow `issuer` than its basic constraints allow
+fn check_path_len(issuer: &Certificate, intermediates: usize) -> Result<(), AuthError> {
+    match issuer.path_len() {
+        Some(len) if intermediates > usize::try_from(len)? => {
+            Err(invalid("chain exceeds a CA's path length limit"))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn invalid(problem: &str) -> AuthError {
+    AuthError::Verification(format!("Client certificate {}", problem))
+}
+
+fn normalize_hex(hex: &str) -> String {
+    let hex = hex.replace(':', "").to_ascii_lowercase();
+    match hex.trim_start_matches('0') {
+        "" => "0".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Something in a client certificate that identifies the service presenting it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identifier {
+    CommonName(String),
+    /// A DNS subject alternative name, compared ignoring case
+    Dns(String),
+    /// An email subject alternative name, compared ignoring case
+    Email(String),
+    /// A URI subject alternative name, such as a SPIFFE id
+    Uri(String),
+    /// The certificate's SHA-256 fingerprint as hex, with or without `:` separators
+    Fingerprint(String),
+}
+
+impl Identifier {
+    pub fn matches(&self, certificate: &Certificate) -> bool {
+        match self {
+            Self::CommonName(name) => certificate.common_name() == Some(name.as_str()),
+            Self::Dns(name) => certificate
+                .dns_names()
+                .iter()
+                .any(|dns| dns.eq_ignore_ascii_case(name)),
+            Self::Email(email) => certificate
+                .emails()
+                .iter()
+                .any(|found| found.eq_ignore_ascii_case(email)),
+            Self::Uri(uri) => certificate.uris().iter().any(|found| found == uri),
+            Self::Fingerprint(fingerprint) => {
+                fingerprint.replace(':', "").to_ascii_lowercase() == certificate.fingerprint()
+            }
+        }
+    }
+}
+
+/// Maps verified client certificates to the identities of the services holding them
+///
+/// Mappings are tried in the order they were added and the first match wins.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mtls::{certificate::Certificate, Identifier, IdentityMap};
+///
+/// let identities = IdentityMap::new()
+///     .map(Identifier::Uri("spiffe://example.com/ns/prod/sa/billing".into()), "billing")
+///     .map(Identifier::Dns("reports.internal.example.com".into()), "reports");
+///
+/// let chain = Certificate::parse_pem(include_str!("testdata/chain.pem"))?;
+/// assert_eq!(identities.identify(&chain[0]), Some("billing"));
+/// assert_eq!(identities.identify(&chain[1]), None);
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdentityMap {
+    identities: Vec<(Identifier, String)>,
+}
+
+impl IdentityMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn map(mut self, identifier: Identifier, identity: impl Into<String>) -> Self {
+        self.identities.push((identifier, identity.into()));
+        self
+    }
+
+    /// The identity of the first mapping the certificate matches
+    ///
+    /// Only pass certificates `ClientCertVerifier` accepted.
+    pub fn identify(&self, certificate: &Certificate) -> Option<&str> {
+        self.identities
+            .iter()
+            .find(|(identifier, _)| identifier.matches(certificate))
+            .map(|(_, identity)| identity.as_str())
+    }
+}
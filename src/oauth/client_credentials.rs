@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+
+use crate::{http::HttpClient, token::now, AuthError};
+
+use super::{OAuthClient, TokenResponse};
+
+/// How long before expiry a cached token is replaced
+pub const DEFAULT_REFRESH_BEFORE: Duration = Duration::from_secs(60);
+
+/// How long a token is reused when the provider does not say when it expires
+pub const DEFAULT_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+impl<C: HttpClient> OAuthClient<C> {
+    /// Request a token for the client itself with the client credentials grant (RFC 6749 4.4)
+    ///
+    /// `params` are sent along with the configured scopes, e.g. an `audience` or `resource`.
+    pub async fn client_credentials(
+        &self,
+        params: &[(&str, &str)],
+    ) -> Result<TokenResponse, AuthError> {
+        if self.config.client_secret.is_none() {
+            return Err(AuthError::InvalidState(
+                "The client credentials grant needs a client secret".to_string(),
+            ));
+        }
+
+        let scope = self.config.scopes.join(" ");
+        let mut form = vec![("grant_type", "client_credentials")];
+        if !scope.is_empty() {
+            form.push(("scope", &scope));
+        }
+        form.extend_from_slice(params);
+
+        self.token_request(&form).await
+    }
+}
+
+struct CachedToken {
+    token: TokenResponse,
+    refresh_at: u64,
+}
+
+/// Machine to machine access tokens, fetched with the client credentials grant and cached
+///
+/// `access_token` returns the cached token until shortly before it expires, then fetches a new
+/// one. Concurrent callers wait for a single fetch rather than each asking the provider. Call
+/// `invalidate` when an API rejects the token, e.g. after the provider rotated its keys.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::oauth::client_credentials::ClientCredentials;
+/// use lonewolf_auth_toolkit::oauth::{OAuthClient, ProviderConfig};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// struct FakeProvider {
+///     fetches: AtomicUsize,
+/// }
+///
+/// impl HttpClient for FakeProvider {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         let body = String::from_utf8(request.body).unwrap();
+///         assert!(body.contains("grant_type=client_credentials"));
+///         assert!(body.contains("audience=https%3A%2F%2Fapi.example.com"));
+///
+///         let fetch = self.fetches.fetch_add(1, Ordering::SeqCst);
+///         let body = format!(
+///             r#"{{"access_token":"SomeAccessToken{}","token_type":"Bearer","expires_in":3600}}"#,
+///             fetch
+///         );
+///
+///         Ok(HttpResponse { status: 200, headers: vec![], body: body.into_bytes() })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = ProviderConfig::new(
+///         "SomeService",
+///         "https://auth.example.com/authorize",
+///         "https://auth.example.com/token",
+///         "",
+///     )
+///     .client_secret("SomeClientSecret")
+///     .scope("reports:read");
+///     let oauth = OAuthClient::new(FakeProvider { fetches: AtomicUsize::new(0) }, config);
+///
+///     let tokens = ClientCredentials::new(oauth).param("audience", "https://api.example.com");
+///
+///     assert_eq!(tokens.access_token().await?, "SomeAccessToken0");
+///     assert_eq!(tokens.access_token().await?, "SomeAccessToken0");
+///
+///     tokens.invalidate().await;
+///     assert_eq!(tokens.access_token().await?, "SomeAccessToken1");
+///
+///     Ok(())
+/// }
+/// ```
+pub struct ClientCredentials<C> {
+    oauth: OAuthClient<C>,
+    params: Vec<(String, String)>,
+    refresh_before: Duration,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+impl<C: HttpClient> ClientCredentials<C> {
+    pub fn new(oauth: OAuthClient<C>) -> Self {
+        Self {
+            oauth,
+            params: Vec::new(),
+            refresh_before: DEFAULT_REFRESH_BEFORE,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Send this parameter with every token request, e.g. `audience` or `resource`
+    pub fn param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn refresh_before(mut self, refresh_before: Duration) -> Self {
+        self.refresh_before = refresh_before;
+        self
+    }
+
+    pub fn oauth(&self) -> &OAuthClient<C> {
+        &self.oauth
+    }
+
+    /// A current access token, fetching a new one if needed
+    pub async fn access_token(&self) -> Result<String, AuthError> {
+        Ok(self.token().await?.access_token)
+    }
+
+    /// The full token response of a current token
+    pub async fn token(&self) -> Result<TokenResponse, AuthError> {
+        let mut cached = self.cached.lock().await;
+        let now = now()?;
+
+        if let Some(cached) = cached.as_ref().filter(|cached| now < cached.refresh_at) {
+            return Ok(cached.token.clone());
+        }
+
+        let params: Vec<_> = self
+            .params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        let token = self.oauth.client_credentials(&params).await?;
+
+        let lifetime = token.expires_in.unwrap_or(DEFAULT_LIFETIME.as_secs());
+        // Tokens shorter lived than the refresh margin are still reused for half their lifetime
+        let margin = self.refresh_before.as_secs().min(lifetime / 2);
+        *cached = Some(CachedToken {
+            token: token.clone(),
+            refresh_at: now + lifetime - margin,
+        });
+
+        Ok(token)
+    }
+
+    /// Forget the cached token so the next call fetches a new one
+    pub async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+}
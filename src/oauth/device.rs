@@ -0,0 +1,179 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{http::HttpClient, AuthError};
+
+use super::{parse_token_response, ErrorResponse, OAuthClient, TokenResponse};
+
+/// Polling interval when the provider does not give one (RFC 8628 3.2)
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How much to slow down polling each time the provider answers `slow_down`
+pub const SLOW_DOWN_STEP: Duration = Duration::from_secs(5);
+
+/// A device authorization response: what to show the user and what to poll with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    /// The code the user types in at `verification_uri`
+    pub user_code: String,
+    pub verification_uri: String,
+    /// `verification_uri` with the user code filled in, e.g. for a QR code
+    #[serde(default)]
+    pub verification_uri_complete: Option<String>,
+    /// Seconds until the codes expire
+    pub expires_in: u64,
+    /// Seconds to wait between polls
+    #[serde(default)]
+    pub interval: Option<u64>,
+}
+
+/// The outcome of a single poll of the token endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DevicePoll {
+    /// The user has not finished yet; poll again after the interval
+    Pending,
+    /// Polling too fast; add `SLOW_DOWN_STEP` to the interval
+    SlowDown,
+    Complete(TokenResponse),
+}
+
+/// The device authorization grant (RFC 8628) for CLIs, TVs and other input constrained devices
+///
+/// `device_authorization` returns a user code and URL to show the user, then
+/// `wait_for_device_token` polls the token endpoint until they approve, backing off on
+/// `slow_down`. Denial and expiry become `AuthError::Verification`; running out of time before
+/// the codes expire becomes `AuthError::InvalidState`. The provider config needs a
+/// `device_authorization_endpoint`; its redirect URI is not used.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::oauth::{OAuthClient, ProviderConfig};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// struct FakeProvider {
+///     polls: AtomicUsize,
+/// }
+///
+/// impl HttpClient for FakeProvider {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         let body = if request.url.ends_with("/device") {
+///             r#"{"device_code":"SomeDeviceCode","user_code":"WDJB-MJHT","verification_uri":"https://provider.example.com/device","expires_in":900,"interval":1}"#
+///         } else if self.polls.fetch_add(1, Ordering::SeqCst) == 0 {
+///             r#"{"error":"authorization_pending"}"#
+///         } else {
+///             r#"{"access_token":"SomeAccessToken","token_type":"Bearer"}"#
+///         };
+///
+///         let status = if body.contains("error") { 400 } else { 200 };
+///         Ok(HttpResponse { status, headers: vec![], body: body.as_bytes().to_vec() })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = ProviderConfig::new(
+///         "SomeClientId",
+///         "https://provider.example.com/authorize",
+///         "https://provider.example.com/token",
+///         "",
+///     )
+///     .device_authorization_endpoint("https://provider.example.com/device");
+///
+///     let oauth = OAuthClient::new(FakeProvider { polls: AtomicUsize::new(0) }, config);
+///
+///     let device = oauth.device_authorization().await?;
+///     println!("Visit {} and enter {}", device.verification_uri, device.user_code);
+///
+///     let tokens = oauth.wait_for_device_token(&device).await?;
+///     assert_eq!(tokens.access_token, "SomeAccessToken");
+///
+///     Ok(())
+/// }
+/// ```
+impl<C: HttpClient> OAuthClient<C> {
+    /// Request a device code and user code
+    pub async fn device_authorization(&self) -> Result<DeviceAuthorization, AuthError> {
+        let endpoint = self
+            .config
+            .device_authorization_endpoint
+            .as_deref()
+            .ok_or_else(|| {
+                AuthError::InvalidState("Provider has no device authorization endpoint".to_string())
+            })?;
+
+        let scope = self.config.scopes.join(" ");
+        let mut params = Vec::new();
+        if !scope.is_empty() {
+            params.push(("scope", scope.as_str()));
+        }
+
+        let response = self.post(endpoint, &params).await?;
+
+        if let Ok(error) = serde_json::from_slice::<ErrorResponse>(&response.body) {
+            return Err(AuthError::Verification(format!(
+                "OAuth error {}",
+                error.error
+            )));
+        }
+
+        if !response.is_success() {
+            return Err(AuthError::backend(format!(
+                "Device authorization endpoint returned status {}",
+                response.status
+            )));
+        }
+
+        Ok(serde_json::from_slice(&response.body)?)
+    }
+
+    /// Poll the token endpoint once
+    pub async fn poll_device_token(&self, device_code: &str) -> Result<DevicePoll, AuthError> {
+        let response = self
+            .post(
+                &self.config.token_endpoint,
+                &[
+                    ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                    ("device_code", device_code),
+                ],
+            )
+            .await?;
+
+        match serde_json::from_slice::<ErrorResponse>(&response.body) {
+            Ok(error) if error.error == "authorization_pending" => Ok(DevicePoll::Pending),
+            Ok(error) if error.error == "slow_down" => Ok(DevicePoll::SlowDown),
+            _ => parse_token_response(response).map(DevicePoll::Complete),
+        }
+    }
+
+    /// Poll until the user approves or denies the request, or the codes expire
+    pub async fn wait_for_device_token(
+        &self,
+        device: &DeviceAuthorization,
+    ) -> Result<TokenResponse, AuthError> {
+        let deadline = Instant::now() + Duration::from_secs(device.expires_in);
+        let mut interval = device
+            .interval
+            .map_or(DEFAULT_INTERVAL, Duration::from_secs);
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            if Instant::now() >= deadline {
+                return Err(AuthError::InvalidState(
+                    "Device code expired before the user approved it".to_string(),
+                ));
+            }
+
+            match self.poll_device_token(&device.device_code).await? {
+                DevicePoll::Pending => {}
+                DevicePoll::SlowDown => interval += SLOW_DOWN_STEP,
+                DevicePoll::Complete(tokens) => return Ok(tokens),
+            }
+        }
+    }
+}
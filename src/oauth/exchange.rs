@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{http::HttpClient, otel, AuthError};
+
+use super::{parse_token_response, OAuthClient};
+
+/// Token type identifiers (RFC 8693 3)
+pub mod token_type {
+    pub const ACCESS_TOKEN: &str = "urn:ietf:params:oauth:token-type:access_token";
+    pub const REFRESH_TOKEN: &str = "urn:ietf:params:oauth:token-type:refresh_token";
+    pub const ID_TOKEN: &str = "urn:ietf:params:oauth:token-type:id_token";
+    pub const JWT: &str = "urn:ietf:params:oauth:token-type:jwt";
+    pub const SAML1: &str = "urn:ietf:params:oauth:token-type:saml1";
+    pub const SAML2: &str = "urn:ietf:params:oauth:token-type:saml2";
+}
+
+/// The token exchange grant type
+pub const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+
+/// A token exchange request (RFC 8693 2.1)
+///
+/// Without an actor token the issued token simply represents the subject, which is
+/// impersonation. With one it also names the actor, usually in an `act` claim, which is
+/// delegation: a service calling another on behalf of a user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenExchange {
+    subject_token: String,
+    subject_token_type: String,
+    actor: Option<(String, String)>,
+    requested_token_type: Option<String>,
+    audiences: Vec<String>,
+    resources: Vec<String>,
+    scopes: Vec<String>,
+}
+
+impl TokenExchange {
+    /// Exchange `subject_token`, whose type is one of the `token_type` identifiers
+    pub fn new(subject_token: impl Into<String>, subject_token_type: impl Into<String>) -> Self {
+        Self {
+            subject_token: subject_token.into(),
+            subject_token_type: subject_token_type.into(),
+            actor: None,
+            requested_token_type: None,
+            audiences: Vec::new(),
+            resources: Vec::new(),
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Act on behalf of the subject with the acting party's own token
+    pub fn actor(
+        mut self,
+        actor_token: impl Into<String>,
+        actor_token_type: impl Into<String>,
+    ) -> Self {
+        self.actor = Some((actor_token.into(), actor_token_type.into()));
+        self
+    }
+
+    pub fn requested_token_type(mut self, token_type: impl Into<String>) -> Self {
+        self.requested_token_type = Some(token_type.into());
+        self
+    }
+
+    /// Add the logical name of a service the token is for
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        self.audiences.push(audience.into());
+        self
+    }
+
+    /// Add the URI of a resource the token is for
+    pub fn resource(mut self, resource: impl Into<String>) -> Self {
+        self.resources.push(resource.into());
+        self
+    }
+
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scopes.push(scope.into());
+        self
+    }
+}
+
+/// A successful token exchange response (RFC 8693 2.2.1)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExchangedToken {
+    /// The issued token, which is not necessarily an access token
+    pub access_token: String,
+    /// The `token_type` identifier of the issued token
+    pub issued_token_type: String,
+    /// `Bearer`, or `N_A` when the issued token is not an access token
+    pub token_type: String,
+    /// Seconds until the token expires
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    #[serde(default)]
+    pub scope: Option<String>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+/// The token exchange grant (RFC 8693) for delegation and impersonation between services
+///
+/// A service holding a user's token asks the authorization server for a token it can use to call
+/// another service as that user, typically narrowed to that service's audience and scopes.
+/// Refusals from the authorization server, such as `invalid_target`, become
+/// `AuthError::Verification`.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::oauth::exchange::{token_type, TokenExchange};
+/// use lonewolf_auth_toolkit::oauth::{OAuthClient, ProviderConfig};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// struct FakeProvider;
+///
+/// impl HttpClient for FakeProvider {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         let body = String::from_utf8(request.body).unwrap();
+///         assert!(body.contains("subject_token=SomeUserToken"));
+///         assert!(body.contains("actor_token=SomeServiceToken"));
+///         assert!(body.contains("audience=reports"));
+///
+///         Ok(HttpResponse {
+///             status: 200,
+///             headers: vec![],
+///             body: br#"{"access_token":"SomeDelegatedToken","issued_token_type":"urn:ietf:params:oauth:token-type:access_token","token_type":"Bearer","expires_in":300}"#.to_vec(),
+///         })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = ProviderConfig::new(
+///         "SomeService",
+///         "https://auth.example.com/authorize",
+///         "https://auth.example.com/token",
+///         "",
+///     )
+///     .client_secret("SomeClientSecret");
+///     let oauth = OAuthClient::new(FakeProvider, config);
+///
+///     let exchange = TokenExchange::new("SomeUserToken", token_type::ACCESS_TOKEN)
+///         .actor("SomeServiceToken", token_type::ACCESS_TOKEN)
+///         .audience("reports")
+///         .scope("reports:read");
+///
+///     let token = oauth.exchange_token(&exchange).await?;
+///     assert_eq!(token.access_token, "SomeDelegatedToken");
+///     assert_eq!(token.issued_token_type, token_type::ACCESS_TOKEN);
+///
+///     Ok(())
+/// }
+/// ```
+impl<C: HttpClient> OAuthClient<C> {
+    pub async fn exchange_token(
+        &self,
+        exchange: &TokenExchange,
+    ) -> Result<ExchangedToken, AuthError> {
+        let scope = exchange.scopes.join(" ");
+        let mut params = vec![
+            ("grant_type", GRANT_TYPE),
+            ("subject_token", exchange.subject_token.as_str()),
+            ("subject_token_type", exchange.subject_token_type.as_str()),
+        ];
+        if let Some((actor_token, actor_token_type)) = &exchange.actor {
+            params.push(("actor_token", actor_token));
+            params.push(("actor_token_type", actor_token_type));
+        }
+        if let Some(requested_token_type) = &exchange.requested_token_type {
+            params.push(("requested_token_type", requested_token_type));
+        }
+        params.extend(
+            exchange
+                .audiences
+                .iter()
+                .map(|audience| ("audience", audience.as_str())),
+        );
+        params.extend(
+            exchange
+                .resources
+                .iter()
+                .map(|resource| ("resource", resource.as_str())),
+        );
+        if !scope.is_empty() {
+            params.push(("scope", &scope));
+        }
+
+        let exchange =
+            async { parse_token_response(self.post(&self.config.token_endpoint, &params).await?) };
+
+        otel::span("oauth.token_exchange", None, exchange).await
+    }
+}
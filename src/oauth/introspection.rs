@@ -0,0 +1,262 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value as Json};
+
+use crate::{
+    http::HttpClient,
+    otel,
+    token::{deserialize_audience, hash_token, now},
+    AuthError,
+};
+
+use super::{client_request, ClientAuth};
+
+/// How long introspection results are reused unless another TTL is set
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Most tokens whose results are cached at once
+pub const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+
+/// Cached responses by token hash, with the Unix timestamp they are reused until
+type Cache = HashMap<String, (Introspection, u64)>;
+
+/// An introspection response (RFC 7662 2.2)
+///
+/// Only `active` is required; authorization servers include the other members for active
+/// tokens. Members outside the RFC are kept in `extra`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Introspection {
+    pub active: bool,
+    /// Space separated scopes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    #[serde(rename = "exp", default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    #[serde(rename = "iat", default, skip_serializing_if = "Option::is_none")]
+    pub issued_at: Option<u64>,
+    #[serde(rename = "nbf", default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<u64>,
+    #[serde(rename = "sub", default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(
+        rename = "aud",
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "deserialize_audience"
+    )]
+    pub audience: Vec<String>,
+    #[serde(rename = "iss", default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    #[serde(rename = "jti", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Json>,
+}
+
+impl Introspection {
+    pub fn scopes(&self) -> impl Iterator<Item = &str> {
+        self.scope.iter().flat_map(|scope| scope.split_whitespace())
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes().any(|granted| granted == scope)
+    }
+
+    /// The members outside RFC 7662 as the application's own type
+    pub fn claims<T: DeserializeOwned>(&self) -> Result<T, AuthError> {
+        Ok(serde_json::from_value(Json::Object(self.extra.clone()))?)
+    }
+
+    /// Whether the token is active and inside its validity window at `now`
+    fn is_active_at(&self, now: u64) -> bool {
+        self.active
+            && self.expires_at.is_none_or(|expires_at| now < expires_at)
+            && self.not_before.is_none_or(|not_before| not_before <= now)
+    }
+}
+
+/// Validates opaque access tokens with an authorization server's introspection endpoint
+/// (RFC 7662)
+///
+/// The resource server authenticates to the endpoint as a registered client, by default with
+/// HTTP Basic. `verify` caches results by token hash for the cache TTL, but never past a token's
+/// `exp`, so a token revoked at the authorization server can keep working here for up to the
+/// TTL; `cache_ttl(Duration::ZERO)` turns caching off. Endpoint failures are
+/// `AuthError::Backend`.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::oauth::introspection::IntrospectionClient;
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// struct FakeServer;
+///
+/// impl HttpClient for FakeServer {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         let body = String::from_utf8(request.body).unwrap();
+///         let response = if body.contains("token=SomeAccessToken") {
+///             r#"{"active":true,"sub":"SomeAccountName","scope":"reports:read","tenant":"acme"}"#
+///         } else {
+///             r#"{"active":false}"#
+///         };
+///
+///         Ok(HttpResponse { status: 200, headers: vec![], body: response.as_bytes().to_vec() })
+///     }
+/// }
+///
+/// #[derive(serde::Deserialize)]
+/// struct TenantClaims {
+///     tenant: String,
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let introspection =
+///         IntrospectionClient::new(FakeServer, "https://auth.example.com/introspect", "SomeApi")
+///             .client_secret("SomeClientSecret");
+///
+///     let token = introspection.verify("SomeAccessToken").await?.unwrap();
+///     assert_eq!(token.subject.as_deref(), Some("SomeAccountName"));
+///     assert!(token.has_scope("reports:read"));
+///     assert_eq!(token.claims::<TenantClaims>()?.tenant, "acme");
+///
+///     assert!(introspection.verify("SomeRevokedToken").await?.is_none());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct IntrospectionClient<C> {
+    client: C,
+    endpoint: String,
+    client_id: String,
+    client_secret: Option<String>,
+    client_auth: ClientAuth,
+    cache_ttl: Duration,
+    cache_capacity: usize,
+    cache: Mutex<Cache>,
+}
+
+impl<C: HttpClient> IntrospectionClient<C> {
+    pub fn new(client: C, endpoint: impl Into<String>, client_id: impl Into<String>) -> Self {
+        Self {
+            client,
+            endpoint: endpoint.into(),
+            client_id: client_id.into(),
+            client_secret: None,
+            client_auth: ClientAuth::Basic,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn client_secret(mut self, client_secret: impl Into<String>) -> Self {
+        self.client_secret = Some(client_secret.into());
+        self
+    }
+
+    pub fn client_auth(mut self, client_auth: ClientAuth) -> Self {
+        self.client_auth = client_auth;
+        self
+    }
+
+    pub fn cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    pub fn cache_capacity(mut self, cache_capacity: usize) -> Self {
+        self.cache_capacity = cache_capacity;
+        self
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Ask the endpoint about a token, without the cache
+    pub async fn introspect(&self, token: &str) -> Result<Introspection, AuthError> {
+        let request = client_request(
+            &self.endpoint,
+            &[("token", token), ("token_type_hint", "access_token")],
+            &self.client_id,
+            self.client_secret.as_deref(),
+            self.client_auth,
+        );
+        let response = otel::send(&self.client, request).await?;
+
+        if !response.is_success() {
+            return Err(AuthError::backend(format!(
+                "Introspection endpoint returned status {}",
+                response.status
+            )));
+        }
+
+        serde_json::from_slice(&response.body).map_err(AuthError::backend)
+    }
+
+    /// The token's introspection if it is active and unexpired; `None` otherwise
+    pub async fn verify(&self, token: &str) -> Result<Option<Introspection>, AuthError> {
+        let hash = hash_token(token);
+        let now = now()?;
+
+        let cached = self
+            .lock()?
+            .get(&hash)
+            .filter(|(_, cached_until)| now < *cached_until)
+            .map(|(introspection, _)| introspection.clone());
+        let introspection = match cached {
+            Some(introspection) => introspection,
+            None => {
+                let introspection = self.introspect(token).await?;
+                self.remember(hash, &introspection, now)?;
+                introspection
+            }
+        };
+
+        Ok(introspection.is_active_at(now).then_some(introspection))
+    }
+
+    fn remember(
+        &self,
+        hash: String,
+        introspection: &Introspection,
+        now: u64,
+    ) -> Result<(), AuthError> {
+        let mut cached_until = now + self.cache_ttl.as_secs();
+        if introspection.active {
+            if let Some(expires_at) = introspection.expires_at {
+                cached_until = cached_until.min(expires_at);
+            }
+        }
+        if cached_until <= now {
+            return Ok(());
+        }
+
+        let mut cache = self.lock()?;
+        cache.retain(|_, (_, until)| now < *until);
+        if cache.len() < self.cache_capacity {
+            cache.insert(hash, (introspection.clone(), cached_until));
+        }
+
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Cache>, AuthError> {
+        self.cache
+            .lock()
+            .map_err(|_| AuthError::backend("Introspection cache lock poisoned"))
+    }
+}
@@ -0,0 +1,376 @@
+pub mod client_credentials;
+pub mod device;
+pub mod exchange;
+pub mod introspection;
+pub mod oidc;
+pub mod pkce;
+pub mod social;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    crypto::ct_eq,
+    http::{HttpClient, HttpRequest, HttpResponse},
+    otel,
+    token::generate_token,
+    AuthError,
+};
+
+pub use pkce::Pkce;
+
+/// How the client authenticates to the token endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// HTTP Basic with the client id and secret, which servers must support (RFC 6749 2.3.1)
+    Basic,
+    /// `client_id` and `client_secret` in the form body
+    Post,
+}
+
+/// Where a provider's endpoints are and how this application is registered with it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderConfig {
+    client_id: String,
+    client_secret: Option<String>,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    device_authorization_endpoint: Option<String>,
+    redirect_uri: String,
+    scopes: Vec<String>,
+    client_auth: ClientAuth,
+}
+
+impl ProviderConfig {
+    pub fn new(
+        client_id: impl Into<String>,
+        authorization_endpoint: impl Into<String>,
+        token_endpoint: impl Into<String>,
+        redirect_uri: impl Into<String>,
+    ) -> Self {
+        Self {
+            client_id: client_id.into(),
+            client_secret: None,
+            authorization_endpoint: authorization_endpoint.into(),
+            token_endpoint: token_endpoint.into(),
+            device_authorization_endpoint: None,
+            redirect_uri: redirect_uri.into(),
+            scopes: Vec::new(),
+            client_auth: ClientAuth::Basic,
+        }
+    }
+
+    /// Confidential clients only; public clients such as SPAs rely on PKCE alone
+    pub fn client_secret(mut self, client_secret: impl Into<String>) -> Self {
+        self.client_secret = Some(client_secret.into());
+        self
+    }
+
+    /// Request this scope; may be called more than once
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scopes.push(scope.into());
+        self
+    }
+
+    /// Enables the device authorization grant
+    pub fn device_authorization_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.device_authorization_endpoint = Some(endpoint.into());
+        self
+    }
+
+    pub fn client_auth(mut self, client_auth: ClientAuth) -> Self {
+        self.client_auth = client_auth;
+        self
+    }
+
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+}
+
+/// What to remember, e.g. in the session, between redirecting the user and handling the callback
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingAuthorization {
+    pub state: String,
+    pub pkce_verifier: String,
+    /// Set by `OidcClient` to bind the ID token to this sign in
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+/// An authorization URL to redirect the user to, and the values needed to finish the flow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub pending: PendingAuthorization,
+}
+
+/// A successful token endpoint response (RFC 6749 5.1)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    /// Seconds until the access token expires
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Only returned by OpenID Connect providers
+    #[serde(default)]
+    pub id_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+/// An OAuth 2.0 client for the authorization code grant with PKCE
+///
+/// 1. `authorize` builds the URL to redirect the user to, with a random `state` and PKCE
+///    challenge; store the returned `PendingAuthorization` in the user's session.
+/// 2. The provider redirects back with `code` and `state`; `callback` checks the state against the
+///    pending authorization and exchanges the code for tokens.
+/// 3. `refresh` swaps a refresh token for new tokens.
+///
+/// Token endpoint errors such as `invalid_grant` become `AuthError::Verification`.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::oauth::{OAuthClient, ProviderConfig};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// struct FakeProvider;
+///
+/// impl HttpClient for FakeProvider {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         let body = String::from_utf8(request.body).unwrap();
+///         assert!(body.contains("grant_type=authorization_code"));
+///         assert!(body.contains("code_verifier="));
+///
+///         Ok(HttpResponse {
+///             status: 200,
+///             headers: vec![],
+///             body: br#"{"access_token":"SomeAccessToken","token_type":"Bearer","expires_in":3600}"#.to_vec(),
+///         })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = ProviderConfig::new(
+///         "SomeClientId",
+///         "https://provider.example.com/authorize",
+///         "https://provider.example.com/token",
+///         "https://app.example.com/callback",
+///     )
+///     .client_secret("SomeClientSecret")
+///     .scope("profile");
+///
+///     let oauth = OAuthClient::new(FakeProvider, config);
+///
+///     let request = oauth.authorize()?;
+///     assert!(request.url.contains("code_challenge_method=S256"));
+///
+///     // The provider redirects back to https://app.example.com/callback?code=...&state=...
+///     let state = request.pending.state.clone();
+///     let tokens = oauth.callback(&request.pending, &state, "SomeCode").await?;
+///     assert_eq!(tokens.access_token, "SomeAccessToken");
+///
+///     // A callback that was not started by this user is rejected
+///     assert!(oauth.callback(&request.pending, "forged", "SomeCode").await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct OAuthClient<C> {
+    client: C,
+    config: ProviderConfig,
+}
+
+impl<C: HttpClient> OAuthClient<C> {
+    pub fn new(client: C, config: ProviderConfig) -> Self {
+        Self { client, config }
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    pub fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+
+    /// Build an authorization URL with a fresh `state` and PKCE challenge
+    pub fn authorize(&self) -> Result<AuthorizationRequest, AuthError> {
+        self.authorize_with(&[])
+    }
+
+    /// Like `authorize`, adding provider specific parameters such as `prompt` or `login_hint`
+    pub fn authorize_with(
+        &self,
+        extra: &[(&str, &str)],
+    ) -> Result<AuthorizationRequest, AuthError> {
+        let pkce = Pkce::generate();
+        let pending = PendingAuthorization {
+            state: generate_token(),
+            pkce_verifier: pkce.verifier,
+            nonce: None,
+        };
+
+        let mut url = Url::parse(&self.config.authorization_endpoint)?;
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("response_type", "code")
+                .append_pair("client_id", &self.config.client_id)
+                .append_pair("redirect_uri", &self.config.redirect_uri)
+                .append_pair("state", &pending.state)
+                .append_pair("code_challenge", &pkce.challenge)
+                .append_pair("code_challenge_method", "S256");
+
+            if !self.config.scopes.is_empty() {
+                query.append_pair("scope", &self.config.scopes.join(" "));
+            }
+
+            for (key, value) in extra {
+                query.append_pair(key, value);
+            }
+        }
+
+        Ok(AuthorizationRequest {
+            url: url.into(),
+            pending,
+        })
+    }
+
+    /// Check the returned `state` and exchange the code for tokens
+    pub async fn callback(
+        &self,
+        pending: &PendingAuthorization,
+        state: &str,
+        code: &str,
+    ) -> Result<TokenResponse, AuthError> {
+        let callback = async {
+            if !ct_eq(state, &pending.state) {
+                return Err(AuthError::Verification(
+                    "OAuth state does not match".to_string(),
+                ));
+            }
+
+            self.exchange_code(code, &pending.pkce_verifier).await
+        };
+
+        otel::span("oauth.callback", Some(&pending.state), callback).await
+    }
+
+    /// Exchange an authorization code for tokens without checking `state`
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        pkce_verifier: &str,
+    ) -> Result<TokenResponse, AuthError> {
+        let params = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_uri),
+            ("code_verifier", pkce_verifier),
+        ];
+        let exchange = self.token_request(&params);
+
+        otel::span("oauth.exchange_code", None, exchange).await
+    }
+
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenResponse, AuthError> {
+        let params = [
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ];
+        let refresh = self.token_request(&params);
+
+        otel::span("oauth.refresh", None, refresh).await
+    }
+
+    async fn token_request(&self, params: &[(&str, &str)]) -> Result<TokenResponse, AuthError> {
+        parse_token_response(self.post(&self.config.token_endpoint, params).await?)
+    }
+
+    /// POST a form to one of the provider's endpoints, authenticating as the client
+    async fn post(&self, url: &str, params: &[(&str, &str)]) -> Result<HttpResponse, AuthError> {
+        let request = client_request(
+            url,
+            params,
+            &self.config.client_id,
+            self.config.client_secret.as_deref(),
+            self.config.client_auth,
+        );
+
+        otel::send(&self.client, request).await
+    }
+}
+
+/// A form POST authenticated as the client with `client_auth`, or identifying a public client
+fn client_request(
+    url: &str,
+    params: &[(&str, &str)],
+    client_id: &str,
+    client_secret: Option<&str>,
+    client_auth: ClientAuth,
+) -> HttpRequest {
+    let mut params = params.to_vec();
+    let mut authorization = None;
+
+    match (client_secret, client_auth) {
+        (Some(secret), ClientAuth::Basic) => {
+            authorization = Some(format!(
+                "Basic {}",
+                STANDARD.encode(format!(
+                    "{}:{}",
+                    urlencoding::encode(client_id),
+                    urlencoding::encode(secret)
+                ))
+            ));
+        }
+        (Some(secret), ClientAuth::Post) => {
+            params.push(("client_id", client_id));
+            params.push(("client_secret", secret));
+        }
+        (None, _) => params.push(("client_id", client_id)),
+    }
+
+    let mut request = HttpRequest::post_form(url, &params).header("Accept", "application/json");
+    if let Some(authorization) = authorization {
+        request = request.header("Authorization", authorization);
+    }
+
+    request
+}
+
+fn parse_token_response<T: DeserializeOwned>(response: HttpResponse) -> Result<T, AuthError> {
+    // Some providers, such as GitHub, report errors with a 200 status
+    if let Ok(error) = serde_json::from_slice::<ErrorResponse>(&response.body) {
+        return Err(AuthError::Verification(match error.error_description {
+            Some(description) => format!("OAuth error {}: {}", error.error, description),
+            None => format!("OAuth error {}", error.error),
+        }));
+    }
+
+    if !response.is_success() {
+        return Err(AuthError::backend(format!(
+            "Token endpoint returned status {}",
+            response.status
+        )));
+    }
+
+    Ok(serde_json::from_slice(&response.body)?)
+}
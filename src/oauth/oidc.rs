@@ -0,0 +1,342 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    crypto::ct_eq,
+    http::{HttpClient, HttpRequest},
+    otel,
+    token::{generate_token, jwks::JwksClient, jwt::JwtVerifier, Claims, ValidationPolicy},
+    AuthError,
+};
+
+use super::{
+    AuthorizationRequest, OAuthClient, PendingAuthorization, ProviderConfig, TokenResponse,
+};
+
+/// The parts of an OpenID provider's discovery document the client uses
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveryDocument {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+    #[serde(default)]
+    pub device_authorization_endpoint: Option<String>,
+    #[serde(default)]
+    pub userinfo_endpoint: Option<String>,
+    #[serde(default)]
+    pub end_session_endpoint: Option<String>,
+    #[serde(default)]
+    pub scopes_supported: Vec<String>,
+}
+
+impl DiscoveryDocument {
+    /// Fetch `<issuer>/.well-known/openid-configuration`
+    ///
+    /// Errors if the document names a different issuer, as OpenID Connect Discovery requires.
+    pub async fn fetch<C: HttpClient>(client: &C, issuer: &str) -> Result<Self, AuthError> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let response = otel::send(
+            client,
+            HttpRequest::get(url).header("Accept", "application/json"),
+        )
+        .await?;
+
+        if !response.is_success() {
+            return Err(AuthError::backend(format!(
+                "Discovery endpoint returned status {}",
+                response.status
+            )));
+        }
+
+        let document: Self = serde_json::from_slice(&response.body)?;
+
+        if document.issuer != issuer {
+            return Err(AuthError::Verification(
+                "Discovery document is for a different issuer".to_string(),
+            ));
+        }
+
+        Ok(document)
+    }
+
+    /// A provider config using the discovered endpoints and the `openid` scope
+    pub fn provider_config(
+        &self,
+        client_id: impl Into<String>,
+        redirect_uri: impl Into<String>,
+    ) -> ProviderConfig {
+        let config = ProviderConfig::new(
+            client_id,
+            &self.authorization_endpoint,
+            &self.token_endpoint,
+            redirect_uri,
+        )
+        .scope("openid");
+
+        match &self.device_authorization_endpoint {
+            Some(endpoint) => config.device_authorization_endpoint(endpoint),
+            None => config,
+        }
+    }
+}
+
+/// The standard OpenID Connect claims carried in an ID token, besides the registered ones
+///
+/// Which profile claims are present depends on the requested scopes and the provider.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StandardClaims {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    /// The client the token was issued to, when it has several audiences
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub picture: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+}
+
+/// The tokens from a completed sign in and the validated ID token claims
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidcLogin {
+    pub tokens: TokenResponse,
+    pub id_token: Claims<StandardClaims>,
+}
+
+/// An OpenID Connect relying party on top of `OAuthClient`
+///
+/// `authorize` adds a `nonce` to the pending authorization and `callback` validates the returned
+/// ID token: its signature against the provider's JWKS, `iss` against the discovered issuer, `aud`
+/// and `azp` against the client id, and `nonce` against the pending authorization.
+///
+/// ### Example
+/// ```rust
+/// use std::{sync::{Arc, Mutex}, time::Duration};
+///
+/// use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::oauth::oidc::{DiscoveryDocument, OidcClient, StandardClaims};
+/// use lonewolf_auth_toolkit::token::jwt::{JwtSigner, SigningKey};
+/// use lonewolf_auth_toolkit::token::Claims;
+/// use lonewolf_auth_toolkit::AuthError;
+/// use ring::{
+///     rand::SystemRandom,
+///     signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+/// };
+///
+/// #[derive(Clone)]
+/// struct FakeIdp {
+///     jwks: String,
+///     id_token: Arc<Mutex<String>>,
+/// }
+///
+/// impl HttpClient for FakeIdp {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         let body = match request.url.as_str() {
+///             "https://idp.example.com/.well-known/openid-configuration" => r#"{
+///                 "issuer": "https://idp.example.com",
+///                 "authorization_endpoint": "https://idp.example.com/authorize",
+///                 "token_endpoint": "https://idp.example.com/token",
+///                 "jwks_uri": "https://idp.example.com/jwks"
+///             }"#.to_string(),
+///             "https://idp.example.com/jwks" => self.jwks.clone(),
+///             _ => format!(
+///                 r#"{{"access_token":"SomeAccessToken","token_type":"Bearer","id_token":"{}"}}"#,
+///                 self.id_token.lock().unwrap()
+///             ),
+///         };
+///
+///         Ok(HttpResponse { status: 200, headers: vec![], body: body.into_bytes() })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let rng = SystemRandom::new();
+///     let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+///     let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+///     let point = pair.public_key().as_ref();
+///
+///     let idp = FakeIdp {
+///         jwks: format!(
+///             r#"{{"keys":[{{"kty":"EC","crv":"P-256","kid":"idp-1","x":"{}","y":"{}"}}]}}"#,
+///             URL_SAFE_NO_PAD.encode(&point[1..33]),
+///             URL_SAFE_NO_PAD.encode(&point[33..]),
+///         ),
+///         id_token: Arc::new(Mutex::new(String::new())),
+///     };
+///
+///     let document = DiscoveryDocument::fetch(&idp, "https://idp.example.com").await?;
+///     let config = document.provider_config("SomeClientId", "https://app.example.com/callback");
+///     let oidc = OidcClient::new(idp.clone(), document, config);
+///
+///     let request = oidc.authorize()?;
+///
+///     // The provider signs an ID token echoing the nonce from the authorization URL
+///     let claims = Claims::new(
+///         StandardClaims {
+///             nonce: request.pending.nonce.clone(),
+///             email: Some("someone@example.com".to_string()),
+///             ..Default::default()
+///         },
+///         Duration::from_secs(300),
+///     )?
+///     .issuer("https://idp.example.com")
+///     .subject("SomeProviderUserId")
+///     .audience("SomeClientId");
+///     let signer = JwtSigner::new(SigningKey::es256_pkcs8(pkcs8.as_ref())).kid("idp-1");
+///     *idp.id_token.lock().unwrap() = signer.sign(&claims)?;
+///
+///     let state = request.pending.state.clone();
+///     let login = oidc.callback(&request.pending, &state, "SomeCode").await?;
+///
+///     assert_eq!(login.id_token.subject.as_deref(), Some("SomeProviderUserId"));
+///     assert_eq!(login.id_token.custom.email.as_deref(), Some("someone@example.com"));
+///
+///     // The same ID token is rejected for a different sign in attempt
+///     let other = oidc.authorize()?;
+///     let state = other.pending.state.clone();
+///     assert!(oidc.callback(&other.pending, &state, "SomeCode").await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct OidcClient<C> {
+    oauth: OAuthClient<C>,
+    jwks: JwksClient<C>,
+    verifier: JwtVerifier,
+    document: DiscoveryDocument,
+}
+
+impl<C: HttpClient + Clone + Sync> OidcClient<C> {
+    /// The `openid` scope is added to the config if it is missing
+    pub fn new(client: C, document: DiscoveryDocument, mut config: ProviderConfig) -> Self {
+        if !config.scopes.iter().any(|scope| scope == "openid") {
+            config = config.scope("openid");
+        }
+
+        let verifier = JwtVerifier::new().policy(
+            ValidationPolicy::new()
+                .issuer(&document.issuer)
+                .audience(config.client_id()),
+        );
+
+        Self {
+            jwks: JwksClient::new(client.clone(), &document.jwks_uri),
+            oauth: OAuthClient::new(client, config),
+            verifier,
+            document,
+        }
+    }
+
+    pub fn oauth(&self) -> &OAuthClient<C> {
+        &self.oauth
+    }
+
+    pub fn document(&self) -> &DiscoveryDocument {
+        &self.document
+    }
+
+    /// Build an authorization URL with a fresh `state`, PKCE challenge and `nonce`
+    pub fn authorize(&self) -> Result<AuthorizationRequest, AuthError> {
+        self.authorize_with(&[])
+    }
+
+    /// Like `authorize`, adding provider specific parameters such as `prompt` or `login_hint`
+    pub fn authorize_with(
+        &self,
+        extra: &[(&str, &str)],
+    ) -> Result<AuthorizationRequest, AuthError> {
+        let nonce = generate_token();
+
+        let mut params = extra.to_vec();
+        params.push(("nonce", &nonce));
+
+        let mut request = self.oauth.authorize_with(&params)?;
+        request.pending.nonce = Some(nonce);
+
+        Ok(request)
+    }
+
+    /// Check the returned `state`, exchange the code and validate the ID token
+    pub async fn callback(
+        &self,
+        pending: &PendingAuthorization,
+        state: &str,
+        code: &str,
+    ) -> Result<OidcLogin, AuthError> {
+        let tokens = self.oauth.callback(pending, state, code).await?;
+        let id_token = tokens
+            .id_token
+            .as_deref()
+            .ok_or_else(|| AuthError::Verification("Token response has no ID token".to_string()))?;
+
+        Ok(OidcLogin {
+            id_token: self
+                .validate_id_token(id_token, pending.nonce.as_deref())
+                .await?,
+            tokens,
+        })
+    }
+
+    /// Validate an ID token, requiring its `nonce` to match when one is given
+    pub async fn validate_id_token(
+        &self,
+        id_token: &str,
+        nonce: Option<&str>,
+    ) -> Result<Claims<StandardClaims>, AuthError> {
+        let claims = self
+            .jwks
+            .verify::<StandardClaims>(&self.verifier, id_token)
+            .await?;
+
+        if claims.subject.is_none() {
+            return Err(AuthError::Verification(
+                "ID token has no subject".to_string(),
+            ));
+        }
+
+        if let Some(nonce) = nonce {
+            if !claims
+                .custom
+                .nonce
+                .as_ref()
+                .is_some_and(|claimed| ct_eq(claimed, nonce))
+            {
+                return Err(AuthError::Verification(
+                    "ID token nonce does not match".to_string(),
+                ));
+            }
+        }
+
+        let client_id = self.oauth.config().client_id();
+        let azp_required = claims.audience.len() > 1;
+
+        match claims.custom.azp.as_deref() {
+            Some(azp) if azp != client_id => Err(AuthError::Verification(
+                "ID token was issued to another client".to_string(),
+            )),
+            None if azp_required => Err(AuthError::Verification(
+                "ID token has several audiences but no azp".to_string(),
+            )),
+            _ => Ok(claims),
+        }
+    }
+}
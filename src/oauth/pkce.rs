@@ -0,0 +1,136 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+
+use crate::{crypto::ct_eq, token::generate_token, AuthError};
+
+/// Shortest code verifier allowed (RFC 7636 4.1)
+pub const MIN_VERIFIER_LEN: usize = 43;
+
+/// Longest code verifier allowed (RFC 7636 4.1)
+pub const MAX_VERIFIER_LEN: usize = 128;
+
+/// How a code challenge was derived from its verifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeMethod {
+    S256,
+    /// The challenge is the verifier itself; only for clients that cannot hash
+    Plain,
+}
+
+impl ChallengeMethod {
+    /// Parse a `code_challenge_method` parameter, which defaults to `plain` when absent
+    pub fn parse(method: Option<&str>) -> Result<Self, AuthError> {
+        match method {
+            Some("S256") => Ok(Self::S256),
+            Some("plain") | None => Ok(Self::Plain),
+            Some(method) => Err(AuthError::InvalidInput(format!(
+                "Unsupported code challenge method {}",
+                method
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::S256 => "S256",
+            Self::Plain => "plain",
+        }
+    }
+}
+
+/// A PKCE code verifier and its S256 challenge (RFC 7636)
+///
+/// Clients use `generate`; authorization servers check the challenge when the authorization
+/// request arrives and `verify` the verifier sent with the token request.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::oauth::pkce::{ChallengeMethod, Pkce};
+///
+/// // The client keeps the verifier and sends the challenge to the authorization endpoint
+/// let pkce = Pkce::generate();
+///
+/// // The authorization server stores the challenge and method with the authorization code
+/// Pkce::validate_challenge(&pkce.challenge)?;
+/// let method = ChallengeMethod::parse(Some("S256"))?;
+///
+/// // The token request must present the matching verifier
+/// Pkce::verify(&pkce.verifier, &pkce.challenge, method)?;
+/// assert!(Pkce::verify(&Pkce::generate().verifier, &pkce.challenge, method).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pkce {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+impl Pkce {
+    pub fn generate() -> Self {
+        let verifier = generate_token();
+
+        Self {
+            challenge: Self::challenge(&verifier),
+            verifier,
+        }
+    }
+
+    /// The S256 challenge for a verifier
+    pub fn challenge(verifier: &str) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+    }
+
+    /// Check a `code_verifier` is 43 to 128 unreserved characters
+    pub fn validate_verifier(verifier: &str) -> Result<(), AuthError> {
+        validate("Code verifier", verifier)
+    }
+
+    /// Check a `code_challenge` has the same shape a verifier must have
+    pub fn validate_challenge(challenge: &str) -> Result<(), AuthError> {
+        validate("Code challenge", challenge)
+    }
+
+    /// Check a token request's `code_verifier` against the challenge from the authorization
+    /// request
+    pub fn verify(
+        verifier: &str,
+        challenge: &str,
+        method: ChallengeMethod,
+    ) -> Result<(), AuthError> {
+        Self::validate_verifier(verifier)?;
+
+        let derived = match method {
+            ChallengeMethod::S256 => Self::challenge(verifier),
+            ChallengeMethod::Plain => verifier.to_string(),
+        };
+
+        if ct_eq(derived, challenge) {
+            Ok(())
+        } else {
+            Err(AuthError::Verification(
+                "Code verifier does not match the challenge".to_string(),
+            ))
+        }
+    }
+}
+
+fn validate(what: &str, value: &str) -> Result<(), AuthError> {
+    if !(MIN_VERIFIER_LEN..=MAX_VERIFIER_LEN).contains(&value.len()) {
+        return Err(AuthError::InvalidInput(format!(
+            "{} must be {} to {} characters",
+            what, MIN_VERIFIER_LEN, MAX_VERIFIER_LEN
+        )));
+    }
+
+    if !value
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || b"-._~".contains(&byte))
+    {
+        return Err(AuthError::InvalidInput(format!(
+            "{} has characters outside A-Z, a-z, 0-9 and -._~",
+            what
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,303 @@
+use serde_json::Value as Json;
+
+use crate::{
+    http::{HttpClient, HttpRequest},
+    otel, AuthError,
+};
+
+use super::{
+    AuthorizationRequest, ClientAuth, OAuthClient, PendingAuthorization, ProviderConfig,
+    TokenResponse,
+};
+
+/// A social login provider with known endpoints, scopes and user info format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Provider {
+    Google,
+    GitHub,
+    /// Microsoft identity platform; the tenant is `common`, `organizations`, `consumers` or a
+    /// directory id
+    Microsoft {
+        tenant: String,
+    },
+}
+
+/// A signed in user's profile in the same shape whichever provider they used
+///
+/// Link accounts by `provider` and `id`; only trust `email` for linking when `email_verified`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocialProfile {
+    pub provider: &'static str,
+    /// The provider's stable id for the user
+    pub id: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub username: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+/// The tokens from a completed social sign in and the user's profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocialLogin {
+    pub tokens: TokenResponse,
+    pub profile: SocialProfile,
+}
+
+impl Provider {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Google => "google",
+            Self::GitHub => "github",
+            Self::Microsoft { .. } => "microsoft",
+        }
+    }
+
+    /// A provider config with the provider's endpoints and the scopes needed for `profile`
+    pub fn config(
+        &self,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        redirect_uri: impl Into<String>,
+    ) -> ProviderConfig {
+        let config = match self {
+            Self::Google => ProviderConfig::new(
+                client_id,
+                "https://accounts.google.com/o/oauth2/v2/auth",
+                "https://oauth2.googleapis.com/token",
+                redirect_uri,
+            )
+            .scope("openid")
+            .scope("email")
+            .scope("profile"),
+            Self::GitHub => ProviderConfig::new(
+                client_id,
+                "https://github.com/login/oauth/authorize",
+                "https://github.com/login/oauth/access_token",
+                redirect_uri,
+            )
+            .scope("read:user")
+            .scope("user:email")
+            .client_auth(ClientAuth::Post),
+            Self::Microsoft { tenant } => ProviderConfig::new(
+                client_id,
+                format!(
+                    "https://login.microsoftonline.com/{}/oauth2/v2.0/authorize",
+                    tenant
+                ),
+                format!(
+                    "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+                    tenant
+                ),
+                redirect_uri,
+            )
+            .scope("openid")
+            .scope("email")
+            .scope("profile"),
+        };
+
+        config.client_secret(client_secret)
+    }
+
+    /// Fetch the signed in user's profile with their access token
+    ///
+    /// Microsoft does not say whether an email address was verified, so its profiles never have
+    /// `email_verified` set.
+    pub async fn profile<C: HttpClient>(
+        &self,
+        client: &C,
+        access_token: &str,
+    ) -> Result<SocialProfile, AuthError> {
+        match self {
+            Self::Google => {
+                let info = get(
+                    client,
+                    "https://openidconnect.googleapis.com/v1/userinfo",
+                    access_token,
+                )
+                .await?;
+
+                Ok(SocialProfile {
+                    provider: self.name(),
+                    id: string(&info, "sub").ok_or_else(|| missing("sub"))?,
+                    email: string(&info, "email"),
+                    email_verified: info["email_verified"].as_bool().unwrap_or(false),
+                    name: string(&info, "name"),
+                    username: None,
+                    avatar_url: string(&info, "picture"),
+                })
+            }
+            Self::GitHub => {
+                let user = get(client, "https://api.github.com/user", access_token).await?;
+                let emails =
+                    get(client, "https://api.github.com/user/emails", access_token).await?;
+
+                let email = emails.as_array().and_then(|emails| {
+                    emails.iter().find(|email| {
+                        email["primary"].as_bool() == Some(true)
+                            && email["verified"].as_bool() == Some(true)
+                    })
+                });
+
+                Ok(SocialProfile {
+                    provider: self.name(),
+                    id: user["id"]
+                        .as_u64()
+                        .map(|id| id.to_string())
+                        .ok_or_else(|| missing("id"))?,
+                    email: email.and_then(|email| string(email, "email")),
+                    email_verified: email.is_some(),
+                    name: string(&user, "name"),
+                    username: string(&user, "login"),
+                    avatar_url: string(&user, "avatar_url"),
+                })
+            }
+            Self::Microsoft { .. } => {
+                let info = get(
+                    client,
+                    "https://graph.microsoft.com/oidc/userinfo",
+                    access_token,
+                )
+                .await?;
+
+                Ok(SocialProfile {
+                    provider: self.name(),
+                    id: string(&info, "sub").ok_or_else(|| missing("sub"))?,
+                    email: string(&info, "email"),
+                    email_verified: false,
+                    name: string(&info, "name"),
+                    username: None,
+                    avatar_url: None,
+                })
+            }
+        }
+    }
+}
+
+/// Sign in with a preset provider in a few lines
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::oauth::social::{Provider, SocialClient};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// struct FakeGitHub;
+///
+/// impl HttpClient for FakeGitHub {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         let body = match request.url.as_str() {
+///             "https://github.com/login/oauth/access_token" => {
+///                 r#"{"access_token":"SomeAccessToken","token_type":"bearer","scope":"read:user,user:email"}"#
+///             }
+///             "https://api.github.com/user" => {
+///                 r#"{"id":583231,"login":"octocat","name":"The Octocat","avatar_url":"https://avatars.githubusercontent.com/u/583231"}"#
+///             }
+///             "https://api.github.com/user/emails" => {
+///                 r#"[{"email":"octocat@github.com","primary":true,"verified":true}]"#
+///             }
+///             _ => unreachable!(),
+///         };
+///
+///         Ok(HttpResponse { status: 200, headers: vec![], body: body.as_bytes().to_vec() })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let github = SocialClient::new(
+///         FakeGitHub,
+///         Provider::GitHub,
+///         "SomeClientId",
+///         "SomeClientSecret",
+///         "https://app.example.com/callback/github",
+///     );
+///
+///     let request = github.authorize()?;
+///     let state = request.pending.state.clone();
+///
+///     let login = github.callback(&request.pending, &state, "SomeCode").await?;
+///
+///     assert_eq!(login.profile.id, "583231");
+///     assert_eq!(login.profile.username.as_deref(), Some("octocat"));
+///     assert!(login.profile.email_verified);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct SocialClient<C> {
+    provider: Provider,
+    oauth: OAuthClient<C>,
+}
+
+impl<C: HttpClient> SocialClient<C> {
+    pub fn new(
+        client: C,
+        provider: Provider,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        redirect_uri: impl Into<String>,
+    ) -> Self {
+        let config = provider.config(client_id, client_secret, redirect_uri);
+
+        Self {
+            oauth: OAuthClient::new(client, config),
+            provider,
+        }
+    }
+
+    pub fn provider(&self) -> &Provider {
+        &self.provider
+    }
+
+    pub fn oauth(&self) -> &OAuthClient<C> {
+        &self.oauth
+    }
+
+    pub fn authorize(&self) -> Result<AuthorizationRequest, AuthError> {
+        self.oauth.authorize()
+    }
+
+    /// Check the returned `state`, exchange the code and fetch the user's profile
+    pub async fn callback(
+        &self,
+        pending: &PendingAuthorization,
+        state: &str,
+        code: &str,
+    ) -> Result<SocialLogin, AuthError> {
+        let tokens = self.oauth.callback(pending, state, code).await?;
+        let profile = self
+            .provider
+            .profile(self.oauth.client(), &tokens.access_token)
+            .await?;
+
+        Ok(SocialLogin { tokens, profile })
+    }
+}
+
+async fn get<C: HttpClient>(client: &C, url: &str, access_token: &str) -> Result<Json, AuthError> {
+    let request = HttpRequest::get(url)
+        .header("Authorization", format!("Bearer {}", access_token))
+        .header("Accept", "application/json")
+        // GitHub rejects requests without a user agent
+        .header("User-Agent", "lonewolf-auth-toolkit");
+
+    let response = otel::send(client, request).await?;
+
+    if !response.is_success() {
+        return Err(AuthError::backend(format!(
+            "User info endpoint returned status {}",
+            response.status
+        )));
+    }
+
+    Ok(serde_json::from_slice(&response.body)?)
+}
+
+fn string(value: &Json, key: &str) -> Option<String> {
+    value[key].as_str().map(str::to_string)
+}
+
+fn missing(claim: &str) -> AuthError {
+    AuthError::Malformed(format!("User info has no {}", claim))
+}
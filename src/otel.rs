@@ -0,0 +1,433 @@
+// OpenTelemetry style spans for the multi-step flows and outbound HTTP calls; without the `otel`
+// feature every hook compiles to a plain call
+
+use std::future::Future;
+#[cfg(feature = "otel")]
+use std::{
+    cell::Cell,
+    pin::Pin,
+    sync::OnceLock,
+    task::{Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "otel")]
+use rand::{thread_rng, RngCore};
+
+#[cfg(feature = "otel")]
+use crate::{clock, http::Method, token::hash_token};
+use crate::{
+    http::{HttpClient, HttpRequest, HttpResponse},
+    AuthError,
+};
+
+/// The W3C Trace Context header carrying a span's context to the services it calls
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Identifies a span within a distributed trace
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    /// Whether the trace is being recorded; unsampled spans are propagated but not exported
+    pub sampled: bool,
+}
+
+#[cfg(feature = "otel")]
+impl SpanContext {
+    /// Read a `traceparent` header, e.g. from the request being handled
+    ///
+    /// Fails with `AuthError::Malformed` for anything but a version `00` header with non zero ids.
+    pub fn parse(traceparent: &str) -> Result<Self, AuthError> {
+        let malformed = || AuthError::Malformed("Invalid traceparent header".to_string());
+
+        let parts: Vec<&str> = traceparent.trim().split('-').collect();
+        let [version, trace_id, span_id, flags] = parts[..] else {
+            return Err(malformed());
+        };
+        if version != "00" {
+            return Err(malformed());
+        }
+
+        let mut context = Self {
+            trace_id: [0; 16],
+            span_id: [0; 8],
+            sampled: decode_hex(flags, &mut [0; 1]).ok_or_else(malformed)?[0] & 1 == 1,
+        };
+        decode_hex(trace_id, &mut context.trace_id).ok_or_else(malformed)?;
+        decode_hex(span_id, &mut context.span_id).ok_or_else(malformed)?;
+        if context.trace_id == [0; 16] || context.span_id == [0; 8] {
+            return Err(malformed());
+        }
+
+        Ok(context)
+    }
+
+    /// The context as a `traceparent` header value
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            encode_hex(&self.trace_id),
+            encode_hex(&self.span_id),
+            u8::from(self.sampled)
+        )
+    }
+
+    fn child(parent: Option<Self>) -> Self {
+        let mut rng = thread_rng();
+        let mut context = match parent {
+            Some(parent) => parent,
+            None => {
+                let mut trace_id = [0; 16];
+                rng.fill_bytes(&mut trace_id);
+                Self {
+                    trace_id,
+                    span_id: [0; 8],
+                    sampled: true,
+                }
+            }
+        };
+        rng.fill_bytes(&mut context.span_id);
+
+        context
+    }
+}
+
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    /// A step of a flow within this service
+    Internal,
+    /// An outbound HTTP call
+    Client,
+}
+
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpanStatus {
+    Ok,
+    Error(String),
+}
+
+/// One finished span, to hand to an OpenTelemetry exporter
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanData {
+    /// Dotted flow step, e.g. `oauth.callback` or `magic_link.consume`, or the HTTP method for
+    /// outbound calls
+    pub name: &'static str,
+    pub kind: SpanKind,
+    pub context: SpanContext,
+    pub parent_span_id: Option<[u8; 8]>,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    /// Non secret attributes, e.g. `server.address` or `auth.flow.id`
+    pub attributes: Vec<(&'static str, String)>,
+    pub status: SpanStatus,
+}
+
+#[cfg(feature = "otel")]
+type Exporter = Box<dyn Fn(&SpanData) + Send + Sync>;
+
+#[cfg(feature = "otel")]
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+
+#[cfg(feature = "otel")]
+thread_local! {
+    static CURRENT: Cell<Option<SpanContext>> = const { Cell::new(None) };
+}
+
+/// Send every finished, sampled span to `exporter`, e.g. to forward it to an OpenTelemetry
+/// `SpanExporter`
+///
+/// OAuth code exchanges, token exchanges and refreshes, push, SMS and email MFA challenges and
+/// magic links are recorded as spans, with every outbound HTTP call as a client span below them.
+/// Outbound requests carry a `traceparent` header so the providers' own spans join the trace.
+/// Steps of one flow that run in separate requests, such as `magic_link.issue` and
+/// `magic_link.consume`, share an `auth.flow.id` attribute derived from the flow's state, which
+/// is not itself secret. Fails with `AuthError::InvalidState` if an exporter is already set.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Mutex;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use lonewolf_auth_toolkit::clock;
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::oauth::{OAuthClient, ProviderConfig};
+/// use lonewolf_auth_toolkit::otel::{self, SpanContext, SpanKind, TRACEPARENT};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// static SPANS: Mutex<Vec<(&'static str, SpanKind, [u8; 16])>> = Mutex::new(Vec::new());
+///
+/// struct FakeProvider;
+///
+/// impl HttpClient for FakeProvider {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         let (_, traceparent) = request
+///             .headers
+///             .iter()
+///             .find(|(name, _)| name == TRACEPARENT)
+///             .unwrap();
+///         assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+///
+///         Ok(HttpResponse {
+///             status: 200,
+///             headers: vec![],
+///             body: br#"{"access_token":"SomeAccessToken","token_type":"Bearer"}"#.to_vec(),
+///         })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     // Span times come from the installed time source, like every other timestamp
+///     clock::set_time_source(|| Duration::from_secs(1_700_000_000))?;
+///     otel::set_exporter(|span| {
+///         assert_eq!(span.start, UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+///         let entry = (span.name, span.kind, span.context.trace_id);
+///         SPANS.lock().unwrap().push(entry);
+///     })?;
+///
+///     let config = ProviderConfig::new(
+///         "SomeClientId",
+///         "https://provider.example.com/authorize",
+///         "https://provider.example.com/token",
+///         "https://app.example.com/callback",
+///     );
+///     let oauth = OAuthClient::new(FakeProvider, config);
+///     let request = oauth.authorize()?;
+///     let state = request.pending.state.clone();
+///
+///     // Continue the trace of the incoming callback request
+///     let parent = SpanContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")?;
+///     let callback = oauth.callback(&request.pending, &state, "SomeCode");
+///     otel::with_parent(parent, callback).await?;
+///
+///     let spans = SPANS.lock().unwrap();
+///     let names: Vec<_> = spans.iter().map(|(name, _, _)| *name).collect();
+///     assert_eq!(names, ["POST", "oauth.exchange_code", "oauth.callback"]);
+///     assert_eq!(spans[0].1, SpanKind::Client);
+///     assert!(spans.iter().all(|(_, _, trace_id)| *trace_id == parent.trace_id));
+///
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "otel")]
+pub fn set_exporter(exporter: impl Fn(&SpanData) + Send + Sync + 'static) -> Result<(), AuthError> {
+    EXPORTER
+        .set(Box::new(exporter))
+        .map_err(|_| AuthError::InvalidState("A span exporter is already set".to_string()))
+}
+
+/// The span being run on this task, if any
+#[cfg(feature = "otel")]
+pub fn current() -> Option<SpanContext> {
+    CURRENT.with(Cell::get)
+}
+
+/// Run `future` as part of the trace `parent`, e.g. the context from the handled request's
+/// `traceparent` header, so the spans it records become children of that span
+#[cfg(feature = "otel")]
+pub fn with_parent<F: Future>(parent: SpanContext, future: F) -> impl Future<Output = F::Output> {
+    Scoped {
+        context: parent,
+        future: Box::pin(future),
+    }
+}
+
+/// Makes `context` the current span whenever the future is polled
+#[cfg(feature = "otel")]
+struct Scoped<F> {
+    context: SpanContext,
+    future: Pin<Box<F>>,
+}
+
+#[cfg(feature = "otel")]
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        struct Restore(Option<SpanContext>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| current.set(self.0));
+            }
+        }
+
+        let _restore = Restore(CURRENT.with(|current| current.replace(Some(self.context))));
+        self.future.as_mut().poll(cx)
+    }
+}
+
+/// A span that has started but not ended
+#[cfg(feature = "otel")]
+struct Started {
+    name: &'static str,
+    kind: SpanKind,
+    context: SpanContext,
+    parent_span_id: Option<[u8; 8]>,
+    start: SystemTime,
+}
+
+#[cfg(feature = "otel")]
+impl Started {
+    /// `None` when there is neither a trace to continue nor an exporter to start one for
+    fn new(name: &'static str, kind: SpanKind) -> Option<Self> {
+        let parent = current();
+        if parent.is_none() && EXPORTER.get().is_none() {
+            return None;
+        }
+
+        Some(Self {
+            name,
+            kind,
+            context: SpanContext::child(parent),
+            parent_span_id: parent.map(|parent| parent.span_id),
+            start: timestamp(),
+        })
+    }
+
+    fn end(self, attributes: Vec<(&'static str, String)>, status: SpanStatus) {
+        let Some(exporter) = EXPORTER.get() else {
+            return;
+        };
+        if !self.context.sampled {
+            return;
+        }
+
+        exporter(&SpanData {
+            name: self.name,
+            kind: self.kind,
+            context: self.context,
+            parent_span_id: self.parent_span_id,
+            start: self.start,
+            end: timestamp(),
+            attributes,
+            status,
+        });
+    }
+}
+
+/// Span times come from `clock::since_epoch`, so they follow an installed time source
+#[cfg(feature = "otel")]
+fn timestamp() -> SystemTime {
+    UNIX_EPOCH + clock::since_epoch().unwrap_or(Duration::ZERO)
+}
+
+/// Run `future` as the flow step `name`, tagged with a digest of `flow`, the state shared by the
+/// flow's steps
+#[cfg(feature = "otel")]
+pub(crate) async fn span<T>(
+    name: &'static str,
+    flow: Option<&str>,
+    future: impl Future<Output = Result<T, AuthError>>,
+) -> Result<T, AuthError> {
+    let Some(span) = Started::new(name, SpanKind::Internal) else {
+        return future.await;
+    };
+
+    let result = with_parent(span.context, future).await;
+
+    let attributes = flow
+        .map(|flow| ("auth.flow.id", hash_token(flow)[..16].to_string()))
+        .into_iter()
+        .collect();
+    span.end(attributes, status(&result));
+
+    result
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) async fn span<T>(
+    _name: &'static str,
+    _flow: Option<&str>,
+    future: impl Future<Output = Result<T, AuthError>>,
+) -> Result<T, AuthError> {
+    future.await
+}
+
+/// Send `request` as a client span, propagating its context in a `traceparent` header
+#[cfg(feature = "otel")]
+pub(crate) async fn send<C: HttpClient>(
+    client: &C,
+    mut request: HttpRequest,
+) -> Result<HttpResponse, AuthError> {
+    let method = match request.method {
+        Method::Get => "GET",
+        Method::Post => "POST",
+        Method::Put => "PUT",
+        Method::Delete => "DELETE",
+    };
+    let Some(span) = Started::new(method, SpanKind::Client) else {
+        return client.send(request).await;
+    };
+
+    let mut attributes = vec![("http.request.method", method.to_string())];
+    if let Some(host) = url::Url::parse(&request.url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+    {
+        attributes.push(("server.address", host));
+    }
+    request
+        .headers
+        .retain(|(name, _)| !name.eq_ignore_ascii_case(TRACEPARENT));
+    request
+        .headers
+        .push((TRACEPARENT.to_string(), span.context.traceparent()));
+
+    let result = client.send(request).await;
+
+    let status = match &result {
+        Ok(response) => {
+            attributes.push(("http.response.status_code", response.status.to_string()));
+            if response.status >= 400 {
+                SpanStatus::Error(format!("HTTP status {}", response.status))
+            } else {
+                SpanStatus::Ok
+            }
+        }
+        Err(error) => SpanStatus::Error(error.to_string()),
+    };
+    span.end(attributes, status);
+
+    result
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) async fn send<C: HttpClient>(
+    client: &C,
+    request: HttpRequest,
+) -> Result<HttpResponse, AuthError> {
+    client.send(request).await
+}
+
+#[cfg(feature = "otel")]
+fn status<T>(result: &Result<T, AuthError>) -> SpanStatus {
+    match result {
+        Ok(_) => SpanStatus::Ok,
+        Err(error) => SpanStatus::Error(error.to_string()),
+    }
+}
+
+#[cfg(feature = "otel")]
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Fill `out` from lowercase hex of exactly its length
+#[cfg(feature = "otel")]
+fn decode_hex<'a>(hex: &str, out: &'a mut [u8]) -> Option<&'a [u8]> {
+    if hex.len() != out.len() * 2 || hex.bytes().any(|b| b.is_ascii_uppercase()) {
+        return None;
+    }
+
+    for (index, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(index * 2..index * 2 + 2)?, 16).ok()?;
+    }
+
+    Some(out)
+}
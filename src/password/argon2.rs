@@ -0,0 +1,477 @@
+use zeroize::Zeroizing;
+
+use crate::{
+    crypto::{
+        blake2b::{blake2b, Blake2b},
+        ct_eq,
+    },
+    metrics, AuthError,
+};
+
+use super::{
+    generate_salt,
+    phc::{self, Phc},
+    PasswordHasher,
+};
+
+const VERSION: u32 = 0x13;
+const ARGON2ID: u32 = 2;
+const BLOCK_WORDS: usize = 128;
+const SYNC_POINTS: u32 = 4;
+
+/// Argon2id cost parameters
+///
+/// The defaults follow the OWASP recommendation of 19 MiB, two passes and one lane.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::argon2::Argon2Params;
+///
+/// let params = Argon2Params::default().memory_kib(65536).iterations(3);
+///
+/// assert_eq!(params.memory_kib, 65536);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Params {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub output_len: usize,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        Self {
+            memory_kib: 19456,
+            iterations: 2,
+            parallelism: 1,
+            output_len: 32,
+        }
+    }
+}
+
+impl Argon2Params {
+    pub fn memory_kib(mut self, memory_kib: u32) -> Self {
+        self.memory_kib = memory_kib;
+        self
+    }
+
+    pub fn iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    pub fn parallelism(mut self, parallelism: u32) -> Self {
+        self.parallelism = parallelism;
+        self
+    }
+
+    pub fn output_len(mut self, output_len: usize) -> Self {
+        self.output_len = output_len;
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), AuthError> {
+        if !(1..=255).contains(&self.parallelism) {
+            return Err(AuthError::InvalidInput(
+                "Argon2 parallelism must be between 1 and 255".to_string(),
+            ));
+        }
+
+        if self.memory_kib < 8 * self.parallelism {
+            return Err(AuthError::InvalidInput(
+                "Argon2 memory must be at least 8 KiB per lane".to_string(),
+            ));
+        }
+
+        if self.iterations == 0 {
+            return Err(AuthError::InvalidInput(
+                "Argon2 iterations must be greater than zero".to_string(),
+            ));
+        }
+
+        if self.output_len < 4 {
+            return Err(AuthError::InvalidInput(
+                "Argon2 output must be at least 4 bytes".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+type Block = [u64; BLOCK_WORDS];
+
+/// Derive a raw Argon2id (RFC 9106) tag from a password and salt
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::argon2::{argon2id, Argon2Params};
+///
+/// let params = Argon2Params::default().memory_kib(64).iterations(1);
+/// let tag = argon2id(b"password", b"somesalt", &params).unwrap();
+/// let hex: String = tag.iter().map(|byte| format!("{:02x}", byte)).collect();
+///
+/// assert_eq!(hex, "729c7a54441bc13559bdca71348c4e554599e719c08a952601ed5c83618c1bbd");
+/// ```
+pub fn argon2id(password: &[u8], salt: &[u8], params: &Argon2Params) -> Result<Vec<u8>, AuthError> {
+    params.validate()?;
+
+    if salt.len() < 8 {
+        return Err(AuthError::InvalidInput(
+            "Argon2 salt must be at least 8 bytes".to_string(),
+        ));
+    }
+
+    let lanes = params.parallelism;
+    let segment_length = params.memory_kib / (SYNC_POINTS * lanes);
+    let lane_length = segment_length * SYNC_POINTS;
+    let block_count = lane_length * lanes;
+
+    let mut initial = Blake2b::new(64);
+    for value in [
+        lanes,
+        params.output_len as u32,
+        params.memory_kib,
+        params.iterations,
+        VERSION,
+        ARGON2ID,
+    ] {
+        initial.update(&value.to_le_bytes());
+    }
+    for input in [password, salt, &[], &[]] {
+        initial.update(&(input.len() as u32).to_le_bytes());
+        initial.update(input);
+    }
+    let initial = Zeroizing::new(initial.finalize());
+
+    // Every block depends on the password, so none may outlive the hash
+    let mut memory = Zeroizing::new(vec![[0u64; BLOCK_WORDS]; block_count as usize]);
+
+    for lane in 0..lanes {
+        for column in 0..2u32 {
+            let mut input = initial.clone();
+            input.extend_from_slice(&column.to_le_bytes());
+            input.extend_from_slice(&lane.to_le_bytes());
+
+            memory[(lane * lane_length + column) as usize] = to_block(&hash_long(1024, &input));
+        }
+    }
+
+    let instance = Instance {
+        lanes,
+        segment_length,
+        lane_length,
+        block_count,
+        iterations: params.iterations,
+    };
+
+    for pass in 0..params.iterations {
+        for slice in 0..SYNC_POINTS {
+            for lane in 0..lanes {
+                instance.fill_segment(&mut memory, pass, slice, lane);
+            }
+        }
+    }
+
+    let mut last = Zeroizing::new(memory[(lane_length - 1) as usize]);
+    for lane in 1..lanes {
+        let block = &memory[(lane * lane_length + lane_length - 1) as usize];
+        last.iter_mut().zip(block).for_each(|(a, b)| *a ^= b);
+    }
+
+    let last: Zeroizing<Vec<u8>> =
+        Zeroizing::new(last.iter().flat_map(|word| word.to_le_bytes()).collect());
+
+    Ok(hash_long(params.output_len, &last))
+}
+
+struct Instance {
+    lanes: u32,
+    segment_length: u32,
+    lane_length: u32,
+    block_count: u32,
+    iterations: u32,
+}
+
+impl Instance {
+    fn fill_segment(&self, memory: &mut [Block], pass: u32, slice: u32, lane: u32) {
+        let data_independent = pass == 0 && slice < SYNC_POINTS / 2;
+        let zero = [0u64; BLOCK_WORDS];
+        let mut address_input = [0u64; BLOCK_WORDS];
+        let mut addresses = [0u64; BLOCK_WORDS];
+
+        if data_independent {
+            address_input[..6].copy_from_slice(&[
+                pass as u64,
+                lane as u64,
+                slice as u64,
+                self.block_count as u64,
+                self.iterations as u64,
+                ARGON2ID as u64,
+            ]);
+        }
+
+        let next_addresses = |address_input: &mut Block, addresses: &mut Block| {
+            address_input[6] += 1;
+            *addresses = compress(&zero, address_input, None);
+            *addresses = compress(&zero, addresses, None);
+        };
+
+        let starting_index = if pass == 0 && slice == 0 {
+            if data_independent {
+                next_addresses(&mut address_input, &mut addresses);
+            }
+            2
+        } else {
+            0
+        };
+
+        let segment_start = lane * self.lane_length + slice * self.segment_length;
+
+        for index in starting_index..self.segment_length {
+            let current = segment_start + index;
+            // The first block of a lane follows on from the last block of the same lane
+            let previous = if current.is_multiple_of(self.lane_length) {
+                current + self.lane_length - 1
+            } else {
+                current - 1
+            };
+
+            let pseudo_random = if data_independent {
+                if (index as usize).is_multiple_of(BLOCK_WORDS) {
+                    next_addresses(&mut address_input, &mut addresses);
+                }
+                addresses[index as usize % BLOCK_WORDS]
+            } else {
+                memory[previous as usize][0]
+            };
+
+            let reference_lane = if pass == 0 && slice == 0 {
+                lane
+            } else {
+                ((pseudo_random >> 32) % self.lanes as u64) as u32
+            };
+            let reference_index = self.reference_index(
+                pass,
+                slice,
+                index,
+                pseudo_random as u32,
+                reference_lane == lane,
+            );
+            let reference = memory[(reference_lane * self.lane_length + reference_index) as usize];
+            let existing = (pass > 0).then(|| memory[current as usize]);
+
+            memory[current as usize] =
+                compress(&memory[previous as usize], &reference, existing.as_ref());
+        }
+    }
+
+    fn reference_index(
+        &self,
+        pass: u32,
+        slice: u32,
+        index: u32,
+        pseudo_random: u32,
+        same_lane: bool,
+    ) -> u32 {
+        let area = if pass == 0 {
+            if slice == 0 || same_lane {
+                slice * self.segment_length + index - 1
+            } else if index == 0 {
+                slice * self.segment_length - 1
+            } else {
+                slice * self.segment_length
+            }
+        } else if same_lane {
+            self.lane_length - self.segment_length + index - 1
+        } else if index == 0 {
+            self.lane_length - self.segment_length - 1
+        } else {
+            self.lane_length - self.segment_length
+        } as u64;
+
+        let relative = pseudo_random as u64;
+        let relative = (relative * relative) >> 32;
+        let relative = area - 1 - ((area * relative) >> 32);
+
+        let start = if pass == 0 || slice == SYNC_POINTS - 1 {
+            0
+        } else {
+            (slice + 1) * self.segment_length
+        } as u64;
+
+        ((start + relative) % self.lane_length as u64) as u32
+    }
+}
+
+/// The Argon2 compression function G, XORed into `existing` on passes after the first
+fn compress(x: &Block, y: &Block, existing: Option<&Block>) -> Block {
+    let mut r = [0u64; BLOCK_WORDS];
+    r.iter_mut()
+        .zip(x.iter().zip(y))
+        .for_each(|(r, (x, y))| *r = x ^ y);
+
+    let mut z = r;
+
+    for row in 0..8 {
+        let base = row * 16;
+        permute(&mut z, std::array::from_fn(|word| base + word));
+    }
+
+    for column in 0..8 {
+        let base = column * 2;
+        permute(
+            &mut z,
+            std::array::from_fn(|word| base + (word / 2) * 16 + word % 2),
+        );
+    }
+
+    let mut output = [0u64; BLOCK_WORDS];
+    for word in 0..BLOCK_WORDS {
+        output[word] = z[word] ^ r[word] ^ existing.map_or(0, |existing| existing[word]);
+    }
+
+    output
+}
+
+/// The BLAKE2b round without message words, using the multiplication hardened mixing of Argon2
+fn permute(block: &mut Block, indices: [usize; 16]) {
+    let mut mix = |a: usize, b: usize, c: usize, d: usize| {
+        let (a, b, c, d) = (indices[a], indices[b], indices[c], indices[d]);
+        let multiply = |x: u64, y: u64| {
+            2u64.wrapping_mul(x & 0xffffffff)
+                .wrapping_mul(y & 0xffffffff)
+        };
+
+        block[a] = block[a]
+            .wrapping_add(block[b])
+            .wrapping_add(multiply(block[a], block[b]));
+        block[d] = (block[d] ^ block[a]).rotate_right(32);
+        block[c] = block[c]
+            .wrapping_add(block[d])
+            .wrapping_add(multiply(block[c], block[d]));
+        block[b] = (block[b] ^ block[c]).rotate_right(24);
+        block[a] = block[a]
+            .wrapping_add(block[b])
+            .wrapping_add(multiply(block[a], block[b]));
+        block[d] = (block[d] ^ block[a]).rotate_right(16);
+        block[c] = block[c]
+            .wrapping_add(block[d])
+            .wrapping_add(multiply(block[c], block[d]));
+        block[b] = (block[b] ^ block[c]).rotate_right(63);
+    };
+
+    mix(0, 4, 8, 12);
+    mix(1, 5, 9, 13);
+    mix(2, 6, 10, 14);
+    mix(3, 7, 11, 15);
+    mix(0, 5, 10, 15);
+    mix(1, 6, 11, 12);
+    mix(2, 7, 8, 13);
+    mix(3, 4, 9, 14);
+}
+
+/// The variable length hash H' built from BLAKE2b
+fn hash_long(output_len: usize, input: &[u8]) -> Vec<u8> {
+    let mut prefixed = (output_len as u32).to_le_bytes().to_vec();
+    prefixed.extend_from_slice(input);
+
+    if output_len <= 64 {
+        return blake2b(output_len, &prefixed);
+    }
+
+    let mut value = blake2b(64, &prefixed);
+    let mut output = value[..32].to_vec();
+
+    while output_len - output.len() > 64 {
+        value = blake2b(64, &value);
+        output.extend_from_slice(&value[..32]);
+    }
+
+    output.extend_from_slice(&blake2b(output_len - output.len(), &value));
+    output
+}
+
+fn to_block(bytes: &[u8]) -> Block {
+    let mut block = [0u64; BLOCK_WORDS];
+    for (word, chunk) in block.iter_mut().zip(bytes.chunks_exact(8)) {
+        *word = u64::from_le_bytes(chunk.try_into().unwrap());
+    }
+
+    block
+}
+
+/// Argon2id as a `PasswordHasher`, producing `$argon2id$v=19$m=...,t=...,p=...$<salt>$<hash>`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Argon2Hasher {
+    params: Argon2Params,
+}
+
+impl Argon2Hasher {
+    pub fn new(params: Argon2Params) -> Self {
+        Self { params }
+    }
+}
+
+impl PasswordHasher for Argon2Hasher {
+    fn hash(&self, password: &str) -> Result<String, AuthError> {
+        let salt = generate_salt();
+        let tag = metrics::hashing("argon2id", || {
+            argon2id(password.as_bytes(), &salt, &self.params)
+        })?;
+
+        Ok(phc::format(
+            "argon2id",
+            Some(VERSION),
+            &[
+                ("m", self.params.memory_kib.to_string()),
+                ("t", self.params.iterations.to_string()),
+                ("p", self.params.parallelism.to_string()),
+            ],
+            &salt,
+            &tag,
+        ))
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, AuthError> {
+        let (params, phc) = parse(hash)?;
+        let tag = metrics::hashing("argon2id", || {
+            argon2id(password.as_bytes(), &phc.salt, &params)
+        })?;
+
+        Ok(ct_eq(tag, phc.hash))
+    }
+
+    fn recognizes(&self, hash: &str) -> bool {
+        hash.starts_with("$argon2id$")
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        parse(hash).map_or(true, |(params, _)| {
+            params.memory_kib < self.params.memory_kib
+                || params.iterations < self.params.iterations
+                || params.parallelism < self.params.parallelism
+                || params.output_len < self.params.output_len
+        })
+    }
+}
+
+fn parse(hash: &str) -> Result<(Argon2Params, Phc<'_>), AuthError> {
+    let phc = Phc::parse(hash)?;
+
+    if phc.id != "argon2id" || phc.version != Some("19") {
+        return Err(AuthError::Malformed(
+            "Password hash is not Argon2id version 19".to_string(),
+        ));
+    }
+
+    let params = Argon2Params {
+        memory_kib: phc.param("m")?,
+        iterations: phc.param("t")?,
+        parallelism: phc.param("p")?,
+        output_len: phc.hash.len(),
+    };
+
+    Ok((params, phc))
+}
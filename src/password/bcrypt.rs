@@ -0,0 +1,61 @@
+use crate::{metrics, AuthError};
+
+use super::PasswordHasher;
+
+/// bcrypt as a `PasswordHasher`, for databases holding `$2a$`, `$2b$` or `$2y$` hashes
+///
+/// bcrypt only considers the first 72 bytes of a password.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::{BcryptHasher, PasswordHasher};
+///
+/// let hasher = BcryptHasher::new(4);
+/// let hashed = hasher.hash("correct horse battery staple").unwrap();
+///
+/// assert!(hasher.verify("correct horse battery staple", &hashed).unwrap());
+/// assert!(BcryptHasher::default().needs_rehash(&hashed));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BcryptHasher {
+    cost: u32,
+}
+
+impl Default for BcryptHasher {
+    fn default() -> Self {
+        Self {
+            cost: ::bcrypt::DEFAULT_COST,
+        }
+    }
+}
+
+impl BcryptHasher {
+    /// `cost` is the base 2 logarithm of the work factor, between 4 and 31
+    pub fn new(cost: u32) -> Self {
+        Self { cost }
+    }
+}
+
+impl PasswordHasher for BcryptHasher {
+    fn hash(&self, password: &str) -> Result<String, AuthError> {
+        metrics::hashing("bcrypt", || ::bcrypt::hash(password, self.cost))
+            .map_err(|error| AuthError::InvalidInput(error.to_string()))
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, AuthError> {
+        metrics::hashing("bcrypt", || ::bcrypt::verify(password, hash))
+            .map_err(|error| AuthError::Malformed(error.to_string()))
+    }
+
+    fn recognizes(&self, hash: &str) -> bool {
+        ["$2a$", "$2b$", "$2y$"]
+            .iter()
+            .any(|prefix| hash.starts_with(prefix))
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        hash.get(4..6)
+            .and_then(|cost| cost.parse::<u32>().ok())
+            .is_none_or(|cost| cost < self.cost)
+    }
+}
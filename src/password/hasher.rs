@@ -0,0 +1,123 @@
+use crate::AuthError;
+
+/// A password hashing scheme that produces and verifies self describing hash strings
+///
+/// Implemented by `Argon2Hasher`, `BcryptHasher`, `ScryptHasher` and `Pbkdf2Hasher`, any of which
+/// can be wrapped in a `PepperedHasher`. Combine them with `HasherChain` to keep verifying legacy
+/// hashes while new ones use the preferred scheme.
+pub trait PasswordHasher {
+    fn hash(&self, password: &str) -> Result<String, AuthError>;
+
+    /// Returns `false` for a wrong password and an error if the hash cannot be parsed
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, AuthError>;
+
+    /// Whether `hash` was produced by this scheme, judged by its prefix
+    fn recognizes(&self, hash: &str) -> bool;
+
+    /// Whether `hash` uses weaker parameters than this hasher is configured with
+    fn needs_rehash(&self, _hash: &str) -> bool {
+        false
+    }
+}
+
+/// The outcome of `HasherChain::verify`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    Invalid,
+    Valid,
+    /// The password is correct but the stored hash is outdated; store this replacement
+    Upgrade(String),
+}
+
+impl Verification {
+    pub fn is_valid(&self) -> bool {
+        !matches!(self, Verification::Invalid)
+    }
+}
+
+type BoxedHasher = Box<dyn PasswordHasher + Send + Sync>;
+
+/// Hashes with a primary scheme and verifies against it or any legacy scheme
+///
+/// A successful verification against a legacy hash, or a primary hash with outdated parameters,
+/// returns a fresh primary hash so the stored value is upgraded on the user's next sign in.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::{
+///     Argon2Hasher, BcryptHasher, HasherChain, PasswordHasher, Pbkdf2Hasher, Verification,
+/// };
+///
+/// let legacy = BcryptHasher::new(4).hash("correct horse battery staple").unwrap();
+///
+/// let chain = HasherChain::new(Argon2Hasher::default())
+///     .legacy(BcryptHasher::default())
+///     .legacy(Pbkdf2Hasher::default());
+///
+/// match chain.verify("correct horse battery staple", &legacy).unwrap() {
+///     Verification::Upgrade(upgraded) => assert!(upgraded.starts_with("$argon2id$")),
+///     other => panic!("expected an upgrade, got {:?}", other),
+/// }
+///
+/// assert_eq!(chain.verify("Tr0ub4dor&3", &legacy).unwrap(), Verification::Invalid);
+/// assert!(chain.needs_rehash(&legacy));
+/// ```
+pub struct HasherChain {
+    primary: BoxedHasher,
+    legacy: Vec<BoxedHasher>,
+}
+
+impl HasherChain {
+    pub fn new<H: PasswordHasher + Send + Sync + 'static>(primary: H) -> Self {
+        Self {
+            primary: Box::new(primary),
+            legacy: Vec::new(),
+        }
+    }
+
+    /// Accept hashes from another scheme, upgrading them on successful verification
+    pub fn legacy<H: PasswordHasher + Send + Sync + 'static>(mut self, hasher: H) -> Self {
+        self.legacy.push(Box::new(hasher));
+        self
+    }
+
+    /// Hash a new password with the primary scheme
+    pub fn hash(&self, password: &str) -> Result<String, AuthError> {
+        self.primary.hash(password)
+    }
+
+    /// Whether `hash` would be upgraded by `verify`, without needing the password
+    ///
+    /// Useful for reporting how many stored hashes still use a legacy scheme or outdated cost.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        !self.primary.recognizes(hash) || self.primary.needs_rehash(hash)
+    }
+
+    pub fn verify(&self, password: &str, hash: &str) -> Result<Verification, AuthError> {
+        if self.primary.recognizes(hash) {
+            if !self.primary.verify(password, hash)? {
+                return Ok(Verification::Invalid);
+            }
+
+            if self.primary.needs_rehash(hash) {
+                return Ok(Verification::Upgrade(self.primary.hash(password)?));
+            }
+
+            return Ok(Verification::Valid);
+        }
+
+        let hasher = self
+            .legacy
+            .iter()
+            .find(|hasher| hasher.recognizes(hash))
+            .ok_or_else(|| {
+                AuthError::Malformed("Password hash uses an unrecognised scheme".to_string())
+            })?;
+
+        if !hasher.verify(password, hash)? {
+            return Ok(Verification::Invalid);
+        }
+
+        Ok(Verification::Upgrade(self.primary.hash(password)?))
+    }
+}
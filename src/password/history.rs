@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::AuthError;
+
+use super::PasswordHasher;
+
+/// How many previous passwords are remembered by default
+pub const DEFAULT_REMEMBER: usize = 5;
+
+/// Persists each account's previous password hashes
+pub trait PasswordHistoryStore {
+    /// Up to `limit` hashes for the account, newest first
+    fn recent(
+        &self,
+        account: &str,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<String>, AuthError>> + Send;
+
+    /// Record a new hash and discard all but the newest `keep`, atomically
+    fn push(
+        &self,
+        account: &str,
+        hash: String,
+        keep: usize,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Rejects new passwords that match any of an account's last few passwords
+///
+/// The current password counts as one of them, so record it with `record` when the account is
+/// created. Every remembered hash is verified on each check, so a deep history multiplies the cost
+/// of a password change by the cost of the hasher.
+///
+/// ### Example
+/// ```rust
+/// use std::{collections::HashMap, sync::Mutex};
+///
+/// use lonewolf_auth_toolkit::password::{
+///     argon2::Argon2Params,
+///     history::{PasswordHistory, PasswordHistoryStore},
+///     Argon2Hasher,
+/// };
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, Vec<String>>>);
+///
+/// impl PasswordHistoryStore for MemoryStore {
+///     async fn recent(&self, account: &str, limit: usize) -> Result<Vec<String>, AuthError> {
+///         let history = self.0.lock().unwrap();
+///         Ok(history.get(account).map(|hashes| hashes.iter().take(limit).cloned().collect()).unwrap_or_default())
+///     }
+///
+///     async fn push(&self, account: &str, hash: String, keep: usize) -> Result<(), AuthError> {
+///         let mut history = self.0.lock().unwrap();
+///         let hashes = history.entry(account.to_string()).or_default();
+///         hashes.insert(0, hash);
+///         hashes.truncate(keep);
+///         Ok(())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let hasher = Argon2Hasher::new(Argon2Params::default().memory_kib(1024));
+///     let history = PasswordHistory::new(MemoryStore::default(), hasher).remember(2);
+///
+///     assert!(history.change("SomeAccountName", "first password").await?.is_some());
+///     assert!(history.change("SomeAccountName", "second password").await?.is_some());
+///
+///     assert!(history.change("SomeAccountName", "second password").await?.is_none());
+///     assert!(history.is_reused("SomeAccountName", "first password").await?);
+///
+///     history.change("SomeAccountName", "third password").await?;
+///
+///     // Only the last two are remembered
+///     assert!(!history.is_reused("SomeAccountName", "first password").await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct PasswordHistory<S, H> {
+    store: S,
+    hasher: H,
+    remember: usize,
+}
+
+impl<S: PasswordHistoryStore, H: PasswordHasher> PasswordHistory<S, H> {
+    pub fn new(store: S, hasher: H) -> Self {
+        Self {
+            store,
+            hasher,
+            remember: DEFAULT_REMEMBER,
+        }
+    }
+
+    /// How many previous passwords, including the current one, may not be reused
+    pub fn remember(mut self, remember: usize) -> Self {
+        self.remember = remember.max(1);
+        self
+    }
+
+    /// Whether the password matches one of the remembered hashes
+    ///
+    /// Errors if a remembered hash was not produced by this hasher.
+    pub async fn is_reused(&self, account: &str, password: &str) -> Result<bool, AuthError> {
+        for hash in self.store.recent(account, self.remember).await? {
+            if self.hasher.verify(password, &hash)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Remember a hash the application has already stored as the account's password
+    pub async fn record(&self, account: &str, hash: String) -> Result<(), AuthError> {
+        self.store.push(account, hash, self.remember).await
+    }
+
+    /// Hash and remember a new password, or return `None` if it was used recently
+    ///
+    /// The returned hash is the one to store as the account's password.
+    pub async fn change(&self, account: &str, password: &str) -> Result<Option<String>, AuthError> {
+        if self.is_reused(account, password).await? {
+            return Ok(None);
+        }
+
+        let hash = self.hasher.hash(password)?;
+        self.record(account, hash.clone()).await?;
+
+        Ok(Some(hash))
+    }
+}
+
+/// Keeps previous password hashes in process memory
+#[derive(Debug, Default)]
+pub struct MemoryPasswordHistoryStore {
+    hashes: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl MemoryPasswordHistoryStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, Vec<String>>>, AuthError> {
+        self.hashes
+            .lock()
+            .map_err(|_| AuthError::backend("Password history store lock poisoned"))
+    }
+}
+
+impl PasswordHistoryStore for MemoryPasswordHistoryStore {
+    async fn recent(&self, account: &str, limit: usize) -> Result<Vec<String>, AuthError> {
+        Ok(self
+            .lock()?
+            .get(account)
+            .map(|hashes| hashes.iter().take(limit).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn push(&self, account: &str, hash: String, keep: usize) -> Result<(), AuthError> {
+        let mut accounts = self.lock()?;
+        let hashes = accounts.entry(account.to_string()).or_default();
+
+        hashes.insert(0, hash);
+        hashes.truncate(keep);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+pub mod argon2;
+pub mod bcrypt;
+pub mod history;
+pub mod pbkdf2;
+pub mod pepper;
+pub mod policy;
+pub mod pwned;
+pub mod reset;
+pub mod scrypt;
+
+mod hasher;
+mod phc;
+mod strength;
+
+use rand::{thread_rng, RngCore};
+
+use crate::AuthError;
+
+use argon2::Argon2Params;
+
+pub use argon2::Argon2Hasher;
+pub use bcrypt::BcryptHasher;
+pub use hasher::{HasherChain, PasswordHasher, Verification};
+pub use pbkdf2::Pbkdf2Hasher;
+pub use scrypt::ScryptHasher;
+pub use strength::{strength, Feedback, Score};
+
+/// Length of the random salt generated for each hash
+pub const SALT_LEN: usize = 16;
+
+/// Hash a password with Argon2id using the default parameters
+///
+/// The result is a PHC string such as `$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>`, which
+/// records everything needed to verify it later.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::{hash, verify};
+///
+/// let hashed = hash("correct horse battery staple").unwrap();
+///
+/// assert!(hashed.starts_with("$argon2id$v=19$"));
+/// assert!(verify("correct horse battery staple", &hashed).unwrap());
+/// assert!(!verify("Tr0ub4dor&3", &hashed).unwrap());
+/// ```
+pub fn hash(password: &str) -> Result<String, AuthError> {
+    hash_with(password, &Argon2Params::default())
+}
+
+/// Hash a password with Argon2id using custom cost parameters
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::{argon2::Argon2Params, hash_with};
+///
+/// let params = Argon2Params::default().memory_kib(8192).iterations(3);
+/// let hashed = hash_with("correct horse battery staple", &params).unwrap();
+///
+/// assert!(hashed.starts_with("$argon2id$v=19$m=8192,t=3,p=1$"));
+/// ```
+pub fn hash_with(password: &str, params: &Argon2Params) -> Result<String, AuthError> {
+    Argon2Hasher::new(*params).hash(password)
+}
+
+/// Verify a password against a PHC string produced by `hash`
+///
+/// Returns `false` for a wrong password and an error if the hash itself cannot be parsed.
+pub fn verify(password: &str, hash: &str) -> Result<bool, AuthError> {
+    Argon2Hasher::default().verify(password, hash)
+}
+
+/// Whether a stored hash should be replaced after the next successful `verify`
+///
+/// True when the hash is not Argon2id or was produced with weaker parameters than `params`, such
+/// as after raising the memory cost. Hashes that cannot be parsed also need replacing.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::{argon2::Argon2Params, hash_with, needs_rehash, verify};
+///
+/// let old = Argon2Params::default().memory_kib(8192);
+/// let stored = hash_with("correct horse battery staple", &old).unwrap();
+///
+/// if verify("correct horse battery staple", &stored).unwrap()
+///     && needs_rehash(&stored, &Argon2Params::default())
+/// {
+///     let upgraded = hash_with("correct horse battery staple", &Argon2Params::default()).unwrap();
+///     assert!(!needs_rehash(&upgraded, &Argon2Params::default()));
+/// }
+///
+/// assert!(needs_rehash("$2b$04$abcdefghijklmnopqrstuu", &Argon2Params::default()));
+/// ```
+pub fn needs_rehash(hash: &str, params: &Argon2Params) -> bool {
+    let hasher = Argon2Hasher::new(*params);
+
+    !hasher.recognizes(hash) || hasher.needs_rehash(hash)
+}
+
+pub(crate) fn generate_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0u8; SALT_LEN];
+    thread_rng().fill_bytes(&mut salt);
+
+    salt
+}
@@ -0,0 +1,148 @@
+use std::num::NonZeroU32;
+
+use ring::pbkdf2::{self, PBKDF2_HMAC_SHA256, PBKDF2_HMAC_SHA512};
+
+use crate::{metrics, AuthError};
+
+use super::{
+    generate_salt,
+    phc::{self, Phc},
+    PasswordHasher,
+};
+
+/// The PRF used by PBKDF2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pbkdf2Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Pbkdf2Algorithm {
+    fn id(&self) -> &'static str {
+        match self {
+            Pbkdf2Algorithm::Sha256 => "pbkdf2-sha256",
+            Pbkdf2Algorithm::Sha512 => "pbkdf2-sha512",
+        }
+    }
+
+    fn ring(&self) -> pbkdf2::Algorithm {
+        match self {
+            Pbkdf2Algorithm::Sha256 => PBKDF2_HMAC_SHA256,
+            Pbkdf2Algorithm::Sha512 => PBKDF2_HMAC_SHA512,
+        }
+    }
+
+    fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "pbkdf2-sha256" => Some(Pbkdf2Algorithm::Sha256),
+            "pbkdf2-sha512" => Some(Pbkdf2Algorithm::Sha512),
+            _ => None,
+        }
+    }
+}
+
+/// PBKDF2 as a `PasswordHasher`, producing `$pbkdf2-sha256$i=...$<salt>$<hash>`
+///
+/// The default of 600,000 SHA-256 iterations follows the OWASP recommendation.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::{pbkdf2::Pbkdf2Algorithm, PasswordHasher, Pbkdf2Hasher};
+///
+/// let hasher = Pbkdf2Hasher::new(Pbkdf2Algorithm::Sha512, 1000);
+/// let hashed = hasher.hash("correct horse battery staple").unwrap();
+///
+/// assert!(hashed.starts_with("$pbkdf2-sha512$i=1000$"));
+/// assert!(hasher.verify("correct horse battery staple", &hashed).unwrap());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pbkdf2Hasher {
+    algorithm: Pbkdf2Algorithm,
+    iterations: u32,
+}
+
+impl Default for Pbkdf2Hasher {
+    fn default() -> Self {
+        Self {
+            algorithm: Pbkdf2Algorithm::Sha256,
+            iterations: 600_000,
+        }
+    }
+}
+
+impl Pbkdf2Hasher {
+    pub fn new(algorithm: Pbkdf2Algorithm, iterations: u32) -> Self {
+        Self {
+            algorithm,
+            iterations,
+        }
+    }
+}
+
+impl PasswordHasher for Pbkdf2Hasher {
+    fn hash(&self, password: &str) -> Result<String, AuthError> {
+        let iterations = NonZeroU32::new(self.iterations).ok_or_else(|| {
+            AuthError::InvalidInput("PBKDF2 iterations must be greater than zero".to_string())
+        })?;
+        let salt = generate_salt();
+        let mut output = [0u8; 32];
+
+        metrics::hashing("pbkdf2", || {
+            pbkdf2::derive(
+                self.algorithm.ring(),
+                iterations,
+                &salt,
+                password.as_bytes(),
+                &mut output,
+            )
+        });
+
+        Ok(phc::format(
+            self.algorithm.id(),
+            None,
+            &[("i", self.iterations.to_string())],
+            &salt,
+            &output,
+        ))
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, AuthError> {
+        let (algorithm, iterations, phc) = parse(hash)?;
+
+        Ok(metrics::hashing("pbkdf2", || {
+            pbkdf2::verify(
+                algorithm.ring(),
+                iterations,
+                &phc.salt,
+                password.as_bytes(),
+                &phc.hash,
+            )
+        })
+        .is_ok())
+    }
+
+    fn recognizes(&self, hash: &str) -> bool {
+        hash.starts_with("$pbkdf2-sha256$") || hash.starts_with("$pbkdf2-sha512$")
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        parse(hash).map_or(true, |(algorithm, iterations, _)| {
+            algorithm != self.algorithm || iterations.get() < self.iterations
+        })
+    }
+}
+
+fn parse(hash: &str) -> Result<(Pbkdf2Algorithm, NonZeroU32, Phc<'_>), AuthError> {
+    let phc = Phc::parse(hash)?;
+    let algorithm = Pbkdf2Algorithm::from_id(phc.id)
+        .ok_or_else(|| AuthError::Malformed("Password hash is not PBKDF2".to_string()))?;
+    let iterations = NonZeroU32::new(phc.param("i")?).ok_or_else(|| {
+        AuthError::Malformed("PBKDF2 iterations must be greater than zero".to_string())
+    })?;
+
+    if phc.hash.is_empty() {
+        return Err(AuthError::Malformed("PBKDF2 hash is empty".to_string()));
+    }
+
+    Ok((algorithm, iterations, phc))
+}
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::{crypto::MIN_HMAC_KEY_LEN, AuthError};
+
+use super::PasswordHasher;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const PREFIX: &str = "$pepper$k=";
+
+/// Wraps another hasher, keying each password with a server side secret before it is hashed
+///
+/// The password is replaced by `base64(HMAC-SHA256(pepper, password))`, so a leaked database is
+/// useless without the pepper, which should live outside it (in a KMS or environment variable).
+/// Hashes look like `$pepper$k=<version>` followed by the inner hash, such as
+/// `$pepper$k=1$argon2id$v=19$...`.
+///
+/// Peppers are versioned like sealing keys. After `rotate`, hashes made with an older pepper still
+/// verify and report `needs_rehash`, so `HasherChain` upgrades them as users sign in.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::{
+///     argon2::Argon2Params, pepper::PepperedHasher, Argon2Hasher, HasherChain, PasswordHasher,
+///     Verification,
+/// };
+///
+/// let argon2 = Argon2Hasher::new(Argon2Params::default().memory_kib(1024));
+/// let old = PepperedHasher::new(argon2, 1, &[1u8; 32]).unwrap();
+/// let stored = old.hash("correct horse battery staple").unwrap();
+///
+/// assert!(stored.starts_with("$pepper$k=1$argon2id$"));
+///
+/// let rotated = old.rotate(2, &[2u8; 32]).unwrap();
+/// assert!(rotated.needs_rehash(&stored));
+///
+/// let chain = HasherChain::new(rotated);
+///
+/// match chain.verify("correct horse battery staple", &stored).unwrap() {
+///     Verification::Upgrade(upgraded) => assert!(upgraded.starts_with("$pepper$k=2$")),
+///     other => panic!("expected an upgrade, got {:?}", other),
+/// }
+/// ```
+pub struct PepperedHasher<H> {
+    inner: H,
+    current: u32,
+    peppers: BTreeMap<u32, Zeroizing<Vec<u8>>>,
+}
+
+impl<H: PasswordHasher> PepperedHasher<H> {
+    /// Pepper passwords hashed by `inner` with `pepper`, tagged with the given version
+    pub fn new(inner: H, version: u32, pepper: &[u8]) -> Result<Self, AuthError> {
+        let mut peppers = BTreeMap::new();
+        peppers.insert(version, check_pepper(pepper)?);
+
+        Ok(Self {
+            inner,
+            current: version,
+            peppers,
+        })
+    }
+
+    /// Add an older pepper that is only used to verify existing hashes
+    pub fn with_previous(mut self, version: u32, pepper: &[u8]) -> Result<Self, AuthError> {
+        if version == self.current {
+            return Err(AuthError::InvalidInput(
+                "Previous pepper version clashes with the current pepper".to_string(),
+            ));
+        }
+
+        self.peppers.insert(version, check_pepper(pepper)?);
+
+        Ok(self)
+    }
+
+    /// Make `pepper` the current pepper, keeping every existing pepper for verification
+    pub fn rotate(mut self, version: u32, pepper: &[u8]) -> Result<Self, AuthError> {
+        if self.peppers.contains_key(&version) {
+            return Err(AuthError::InvalidInput(
+                "Pepper version is already in use".to_string(),
+            ));
+        }
+
+        self.peppers.insert(version, check_pepper(pepper)?);
+        self.current = version;
+
+        Ok(self)
+    }
+
+    /// Version of the pepper new hashes are made with
+    pub fn current_version(&self) -> u32 {
+        self.current
+    }
+
+    fn pepper(&self, version: u32, password: &str) -> Result<Zeroizing<String>, AuthError> {
+        let pepper = self.peppers.get(&version).ok_or_else(|| {
+            AuthError::Verification(format!("Unknown pepper version {}", version))
+        })?;
+
+        let mut mac = HmacSha256::new_from_slice(pepper).expect("HMAC accepts any key length");
+        mac.update(password.as_bytes());
+
+        let tag = Zeroizing::new(<[u8; 32]>::from(mac.finalize().into_bytes()));
+
+        Ok(Zeroizing::new(STANDARD_NO_PAD.encode(tag)))
+    }
+}
+
+impl<H: PasswordHasher> PasswordHasher for PepperedHasher<H> {
+    fn hash(&self, password: &str) -> Result<String, AuthError> {
+        let inner = self.inner.hash(&self.pepper(self.current, password)?)?;
+
+        Ok(format!("{}{}{}", PREFIX, self.current, inner))
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, AuthError> {
+        let (version, inner) = parse(hash)?;
+
+        self.inner.verify(&self.pepper(version, password)?, inner)
+    }
+
+    fn recognizes(&self, hash: &str) -> bool {
+        parse(hash).is_ok_and(|(_, inner)| self.inner.recognizes(inner))
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        parse(hash).map_or(true, |(version, inner)| {
+            version != self.current || self.inner.needs_rehash(inner)
+        })
+    }
+}
+
+fn check_pepper(pepper: &[u8]) -> Result<Zeroizing<Vec<u8>>, AuthError> {
+    if pepper.len() < MIN_HMAC_KEY_LEN {
+        return Err(AuthError::InvalidInput(format!(
+            "Pepper must be at least {} bytes",
+            MIN_HMAC_KEY_LEN
+        )));
+    }
+
+    Ok(Zeroizing::new(pepper.to_vec()))
+}
+
+fn parse(hash: &str) -> Result<(u32, &str), AuthError> {
+    let rest = hash
+        .strip_prefix(PREFIX)
+        .ok_or_else(|| AuthError::Malformed("Password hash is not peppered".to_string()))?;
+    let split = rest.find('$').ok_or_else(|| {
+        AuthError::Malformed("Peppered hash is missing its inner hash".to_string())
+    })?;
+    let (version, inner) = rest.split_at(split);
+
+    Ok((version.parse()?, inner))
+}
@@ -0,0 +1,82 @@
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+
+use crate::AuthError;
+
+/// A parsed PHC string: `$<id>[$v=<version>]$<param>=<value>,...$<salt>$<hash>`
+pub(crate) struct Phc<'a> {
+    pub(crate) id: &'a str,
+    pub(crate) version: Option<&'a str>,
+    pub(crate) params: Vec<(&'a str, &'a str)>,
+    pub(crate) salt: Vec<u8>,
+    pub(crate) hash: Vec<u8>,
+}
+
+impl<'a> Phc<'a> {
+    pub(crate) fn parse(input: &'a str) -> Result<Self, AuthError> {
+        let malformed = || AuthError::Malformed("Password hash is not a PHC string".to_string());
+        let mut parts: Vec<&str> = input.split('$').collect();
+
+        if parts.len() < 5 || !parts[0].is_empty() {
+            return Err(malformed());
+        }
+
+        let version = match parts[2].strip_prefix("v=") {
+            Some(version) => {
+                parts.remove(2);
+                Some(version)
+            }
+            None => None,
+        };
+
+        if parts.len() != 5 {
+            return Err(malformed());
+        }
+
+        let params = parts[2]
+            .split(',')
+            .map(|param| param.split_once('=').ok_or_else(malformed))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            id: parts[1],
+            version,
+            params,
+            salt: STANDARD_NO_PAD.decode(parts[3])?,
+            hash: STANDARD_NO_PAD.decode(parts[4])?,
+        })
+    }
+
+    /// Look up a numeric parameter
+    pub(crate) fn param<T: std::str::FromStr>(&self, name: &str) -> Result<T, AuthError> {
+        self.params
+            .iter()
+            .find(|(key, _)| *key == name)
+            .and_then(|(_, value)| value.parse().ok())
+            .ok_or_else(|| {
+                AuthError::Malformed(format!("Password hash is missing parameter {}", name))
+            })
+    }
+}
+
+pub(crate) fn format(
+    id: &str,
+    version: Option<u32>,
+    params: &[(&str, String)],
+    salt: &[u8],
+    hash: &[u8],
+) -> String {
+    let version = version.map_or(String::new(), |version| format!("$v={}", version));
+    let params: Vec<String> = params
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+
+    format!(
+        "${}{}${}${}${}",
+        id,
+        version,
+        params.join(","),
+        STANDARD_NO_PAD.encode(salt),
+        STANDARD_NO_PAD.encode(hash)
+    )
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+
+use super::strength;
+
+/// A reason a password was rejected by a `PasswordPolicy`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    TooShort {
+        min: usize,
+    },
+    TooLong {
+        max: usize,
+    },
+    MissingLowercase,
+    MissingUppercase,
+    MissingDigit,
+    MissingSymbol,
+    /// The password contains this banned word, ignoring case
+    BannedWord(String),
+    ContainsUsername,
+    TooWeak {
+        score: u8,
+        min: u8,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::TooShort { min } => write!(f, "Must be at least {} characters", min),
+            Violation::TooLong { max } => write!(f, "Must be at most {} characters", max),
+            Violation::MissingLowercase => write!(f, "Must contain a lowercase letter"),
+            Violation::MissingUppercase => write!(f, "Must contain an uppercase letter"),
+            Violation::MissingDigit => write!(f, "Must contain a digit"),
+            Violation::MissingSymbol => write!(f, "Must contain a symbol"),
+            Violation::BannedWord(word) => write!(f, "Must not contain \"{}\"", word),
+            Violation::ContainsUsername => write!(f, "Must not contain your username"),
+            Violation::TooWeak { .. } => write!(f, "Is too easy to guess"),
+        }
+    }
+}
+
+/// Rules a new password must satisfy, configurable per tenant
+///
+/// By default only a length of 8 to 128 characters is required and the username is rejected,
+/// in line with NIST SP 800-63B. Length is counted in characters, not bytes.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::policy::{PasswordPolicy, Violation};
+///
+/// let policy = PasswordPolicy::new()
+///     .min_length(10)
+///     .require_digit()
+///     .banned_words(["acme", "password"]);
+///
+/// assert_eq!(policy.validate("correct horse 42", Some("connor")), Ok(()));
+///
+/// let violations = policy.validate("AcmeConnor", Some("connor")).unwrap_err();
+/// assert_eq!(violations, vec![
+///     Violation::MissingDigit,
+///     Violation::BannedWord("acme".to_string()),
+///     Violation::ContainsUsername,
+/// ]);
+///
+/// for violation in violations {
+///     println!("{}", violation);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    require_lowercase: bool,
+    require_uppercase: bool,
+    require_digit: bool,
+    require_symbol: bool,
+    banned_words: Vec<String>,
+    reject_username: bool,
+    min_strength: Option<u8>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 128,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            banned_words: Vec::new(),
+            reject_username: true,
+            min_strength: None,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn min_length(mut self, min_length: usize) -> Self {
+        self.min_length = min_length;
+        self
+    }
+
+    /// An upper bound keeps hashing cost predictable; it should be at least 64
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    pub fn require_lowercase(mut self) -> Self {
+        self.require_lowercase = true;
+        self
+    }
+
+    pub fn require_uppercase(mut self) -> Self {
+        self.require_uppercase = true;
+        self
+    }
+
+    pub fn require_digit(mut self) -> Self {
+        self.require_digit = true;
+        self
+    }
+
+    pub fn require_symbol(mut self) -> Self {
+        self.require_symbol = true;
+        self
+    }
+
+    /// Reject passwords containing any of these words, ignoring case
+    pub fn banned_words<I, W>(mut self, words: I) -> Self
+    where
+        I: IntoIterator<Item = W>,
+        W: Into<String>,
+    {
+        self.banned_words
+            .extend(words.into_iter().map(|word| word.into().to_lowercase()));
+        self
+    }
+
+    pub fn reject_username(mut self, reject_username: bool) -> Self {
+        self.reject_username = reject_username;
+        self
+    }
+
+    /// Require a `strength` score of at least `min`, from 0 to 4
+    pub fn min_strength(mut self, min: u8) -> Self {
+        self.min_strength = Some(min);
+        self
+    }
+
+    /// Check a password, returning every rule it breaks
+    pub fn validate(&self, password: &str, username: Option<&str>) -> Result<(), Vec<Violation>> {
+        let mut violations = Vec::new();
+        let length = password.chars().count();
+        let lower = password.to_lowercase();
+
+        if length < self.min_length {
+            violations.push(Violation::TooShort {
+                min: self.min_length,
+            });
+        }
+
+        if length > self.max_length {
+            violations.push(Violation::TooLong {
+                max: self.max_length,
+            });
+        }
+
+        let has = |test: fn(&char) -> bool| password.chars().any(|c| test(&c));
+
+        if self.require_lowercase && !has(|c| c.is_lowercase()) {
+            violations.push(Violation::MissingLowercase);
+        }
+
+        if self.require_uppercase && !has(|c| c.is_uppercase()) {
+            violations.push(Violation::MissingUppercase);
+        }
+
+        if self.require_digit && !has(|c| c.is_numeric()) {
+            violations.push(Violation::MissingDigit);
+        }
+
+        if self.require_symbol && !has(|c| !c.is_alphanumeric() && !c.is_whitespace()) {
+            violations.push(Violation::MissingSymbol);
+        }
+
+        violations.extend(
+            self.banned_words
+                .iter()
+                .filter(|word| !word.is_empty() && lower.contains(word.as_str()))
+                .map(|word| Violation::BannedWord(word.clone())),
+        );
+
+        let username = username.map(str::to_lowercase);
+
+        if let Some(username) = &username {
+            if self.reject_username && username.chars().count() >= 3 && lower.contains(username) {
+                violations.push(Violation::ContainsUsername);
+            }
+        }
+
+        if let Some(min) = self.min_strength {
+            let user_inputs: Vec<&str> = username.iter().map(String::as_str).collect();
+            let score = strength(password, &user_inputs).score;
+
+            if score < min {
+                violations.push(Violation::TooWeak { score, min });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use sha1::{Digest, Sha1};
+
+use crate::{
+    http::{HttpClient, HttpRequest},
+    otel, AuthError,
+};
+
+/// The Have I Been Pwned Pwned Passwords range endpoint
+pub const RANGE_API: &str = "https://api.pwnedpasswords.com/range/";
+
+/// Checks passwords against Have I Been Pwned without revealing them
+///
+/// Only the first five hex characters of the password's SHA-1 hash are sent. The API returns
+/// every suffix sharing that prefix, padded with decoys, and the match is done locally.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::password::pwned::PwnedPasswords;
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// struct FakeApi;
+///
+/// impl HttpClient for FakeApi {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         // Only the 5 character prefix of the hash leaves the machine
+///         let prefix = request.url.rsplit('/').next().unwrap();
+///         assert_eq!(prefix.len(), 5);
+///
+///         let body = match prefix {
+///             "5BAA6" => "1E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n0018A45C4D1DEF81644B54AB7F969B88D65:0",
+///             _ => "0018A45C4D1DEF81644B54AB7F969B88D65:0",
+///         };
+///
+///         Ok(HttpResponse {
+///             status: 200,
+///             headers: Vec::new(),
+///             body: body.as_bytes().to_vec(),
+///         })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let pwned = PwnedPasswords::new(FakeApi);
+///
+///     assert_eq!(pwned.occurrences("password").await?, 9545824);
+///     assert!(!pwned.is_pwned("correct horse battery staple").await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct PwnedPasswords<C> {
+    client: C,
+    base_url: String,
+}
+
+impl<C: HttpClient> PwnedPasswords<C> {
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            base_url: RANGE_API.to_string(),
+        }
+    }
+
+    /// Use a mirror of the range API, such as a self hosted copy of the dataset
+    pub fn base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    /// How many times the password appears in known breaches
+    pub async fn occurrences(&self, password: &str) -> Result<u64, AuthError> {
+        let hash = sha1_hex(password);
+        let (prefix, suffix) = hash.split_at(5);
+
+        let request =
+            HttpRequest::get(format!("{}{}", self.base_url, prefix)).header("Add-Padding", "true");
+        let response = otel::send(&self.client, request).await?;
+
+        if !response.is_success() {
+            return Err(AuthError::backend(format!(
+                "Pwned Passwords returned status {}",
+                response.status
+            )));
+        }
+
+        for line in String::from_utf8(response.body)?.lines() {
+            if let Some((candidate, count)) = line.trim().split_once(':') {
+                if candidate.eq_ignore_ascii_case(suffix) {
+                    return Ok(count.parse()?);
+                }
+            }
+        }
+
+        Ok(0)
+    }
+
+    pub async fn is_pwned(&self, password: &str) -> Result<bool, AuthError> {
+        Ok(self.occurrences(password).await? > 0)
+    }
+}
+
+/// An offline set of breached password hashes for air-gapped deployments
+///
+/// Build it once from the downloadable SHA-1 Pwned Passwords list with `insert_sha1_hex`, ship
+/// the bytes from `to_bytes`, and load them with `from_bytes`. Lookups can return false
+/// positives at the configured rate but never false negatives.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::pwned::PwnedBloomFilter;
+///
+/// let mut filter = PwnedBloomFilter::new(1000, 0.001);
+/// filter.insert_sha1_hex("5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824").unwrap();
+///
+/// let filter = PwnedBloomFilter::from_bytes(&filter.to_bytes()).unwrap();
+///
+/// assert!(filter.is_pwned("password"));
+/// assert!(!filter.is_pwned("correct horse battery staple"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PwnedBloomFilter {
+    bits: Vec<u64>,
+    bit_count: u64,
+    hash_count: u32,
+}
+
+impl PwnedBloomFilter {
+    /// Size a filter for `expected` hashes with the given false positive rate
+    pub fn new(expected: u64, false_positive_rate: f64) -> Self {
+        let expected = expected.max(1) as f64;
+        let rate = false_positive_rate.clamp(1e-12, 0.5);
+        let bit_count = (-expected * rate.ln() / std::f64::consts::LN_2.powi(2)).ceil() as u64;
+        let bit_count = bit_count.max(64);
+        let hash_count = ((bit_count as f64 / expected) * std::f64::consts::LN_2).round() as u32;
+
+        Self {
+            bits: vec![0; bit_count.div_ceil(64) as usize],
+            bit_count,
+            hash_count: hash_count.clamp(1, 32),
+        }
+    }
+
+    pub fn insert(&mut self, password: &str) {
+        self.insert_digest(&Sha1::digest(password.as_bytes()));
+    }
+
+    /// Add a line from the Pwned Passwords SHA-1 list, with or without its `:count` suffix
+    pub fn insert_sha1_hex(&mut self, line: &str) -> Result<(), AuthError> {
+        let hex = line.split(':').next().unwrap_or_default().trim();
+
+        if hex.len() != 40 {
+            return Err(AuthError::Malformed(
+                "SHA-1 hashes must be 40 hex characters".to_string(),
+            ));
+        }
+
+        let digest = (0..20)
+            .map(|index| u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()?;
+
+        self.insert_digest(&digest);
+
+        Ok(())
+    }
+
+    pub fn is_pwned(&self, password: &str) -> bool {
+        self.positions(&Sha1::digest(password.as_bytes()))
+            .all(|position| self.bits[(position / 64) as usize] & (1 << (position % 64)) != 0)
+    }
+
+    /// Serialize as the bit count and hash count followed by the bit array, all little endian
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12 + self.bits.len() * 8);
+        bytes.extend_from_slice(&self.bit_count.to_le_bytes());
+        bytes.extend_from_slice(&self.hash_count.to_le_bytes());
+        self.bits
+            .iter()
+            .for_each(|word| bytes.extend_from_slice(&word.to_le_bytes()));
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AuthError> {
+        if bytes.len() < 12 || !(bytes.len() - 12).is_multiple_of(8) {
+            return Err(AuthError::Malformed(
+                "Bloom filter data has an invalid length".to_string(),
+            ));
+        }
+
+        let bit_count = u64::from_le_bytes(bytes[..8].try_into()?);
+        let hash_count = u32::from_le_bytes(bytes[8..12].try_into()?);
+        let bits: Vec<u64> = bytes[12..]
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        if bit_count == 0 || bits.len() as u64 != bit_count.div_ceil(64) || hash_count == 0 {
+            return Err(AuthError::Malformed(
+                "Bloom filter header does not match its data".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            bits,
+            bit_count,
+            hash_count,
+        })
+    }
+
+    fn insert_digest(&mut self, digest: &[u8]) {
+        for position in self.positions(digest).collect::<Vec<_>>() {
+            self.bits[(position / 64) as usize] |= 1 << (position % 64);
+        }
+    }
+
+    /// Double hashing over two independent halves of the SHA-1 digest
+    fn positions<'a>(&'a self, digest: &[u8]) -> impl Iterator<Item = u64> + 'a {
+        let first = u64::from_le_bytes(digest[..8].try_into().unwrap());
+        let second = u64::from_le_bytes(digest[8..16].try_into().unwrap()) | 1;
+
+        (0..self.hash_count as u64)
+            .map(move |index| first.wrapping_add(index.wrapping_mul(second)) % self.bit_count)
+    }
+}
+
+fn sha1_hex(password: &str) -> String {
+    Sha1::digest(password.as_bytes())
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect()
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::{crypto::MIN_HMAC_KEY_LEN, token::now, AuthError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long a password reset link stays valid
+pub const DEFAULT_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Issues password reset tokens that stop working once the password changes
+///
+/// A token is `<account>.<expires>.<signature>`, where the HMAC also covers the account's current
+/// password hash. Nothing needs storing: resetting the password changes the hash, which
+/// invalidates the token and every other outstanding one, so each can only be used once.
+///
+/// Look up the account with `account`, then call `verify` with its current hash both when showing
+/// the reset form and when accepting the new password. Accounts without a password can pass an
+/// empty hash.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::{self, reset::PasswordResets};
+///
+/// let resets = PasswordResets::new(&[7u8; 32])?;
+/// let current = password::hash("correct horse battery staple")?;
+///
+/// // Email https://app.example.com/reset?token=... to the account's address
+/// let token = resets.issue("SomeAccountName", &current)?;
+///
+/// let account = resets.account(&token)?;
+/// assert_eq!(resets.verify(&token, &current)?, account);
+///
+/// // Once the password is changed the link is dead
+/// let changed = password::hash("a brand new passphrase")?;
+/// assert!(resets.verify(&token, &changed).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+pub struct PasswordResets {
+    signing_key: Zeroizing<Vec<u8>>,
+    ttl: Duration,
+}
+
+impl PasswordResets {
+    pub fn new(signing_key: &[u8]) -> Result<Self, AuthError> {
+        if signing_key.len() < MIN_HMAC_KEY_LEN {
+            return Err(AuthError::InvalidInput(format!(
+                "Password reset signing keys must be at least {} bytes",
+                MIN_HMAC_KEY_LEN
+            )));
+        }
+
+        Ok(Self {
+            signing_key: Zeroizing::new(signing_key.to_vec()),
+            ttl: DEFAULT_TTL,
+        })
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// A reset token for the account, bound to its current password hash
+    pub fn issue(&self, account: &str, password_hash: &str) -> Result<String, AuthError> {
+        let account = URL_SAFE_NO_PAD.encode(account);
+        let expires_at = now()? + self.ttl.as_secs();
+        let tag = self
+            .mac(&account, expires_at, password_hash)
+            .finalize()
+            .into_bytes();
+
+        Ok(format!(
+            "{}.{}.{}",
+            account,
+            expires_at,
+            URL_SAFE_NO_PAD.encode(tag)
+        ))
+    }
+
+    /// The account a token claims to be for, so its password hash can be loaded; not verified
+    pub fn account(&self, token: &str) -> Result<String, AuthError> {
+        let (account, _, _) = split(token)?;
+
+        Ok(String::from_utf8(URL_SAFE_NO_PAD.decode(account)?)?)
+    }
+
+    /// Check a token against the account's current password hash, returning the account
+    pub fn verify(&self, token: &str, password_hash: &str) -> Result<String, AuthError> {
+        let (account, expires_at, tag) = split(token)?;
+
+        self.mac(account, expires_at, password_hash)
+            .verify_slice(&URL_SAFE_NO_PAD.decode(tag)?)
+            .map_err(|_| {
+                AuthError::Verification("Password reset token is invalid or used".to_string())
+            })?;
+
+        if expires_at <= now()? {
+            return Err(AuthError::Verification(
+                "Password reset token has expired".to_string(),
+            ));
+        }
+
+        self.account(token)
+    }
+
+    fn mac(&self, account: &str, expires_at: u64, password_hash: &str) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.signing_key).expect("HMAC accepts any key length");
+        mac.update(format!("{}.{}.{}", account, expires_at, password_hash).as_bytes());
+        mac
+    }
+}
+
+fn split(token: &str) -> Result<(&str, u64, &str), AuthError> {
+    let mut parts = token.split('.');
+
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(account), Some(expires_at), Some(tag), None) => {
+            Ok((account, expires_at.parse()?, tag))
+        }
+        _ => Err(AuthError::Malformed(
+            "Malformed password reset token".to_string(),
+        )),
+    }
+}
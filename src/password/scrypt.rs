@@ -0,0 +1,254 @@
+use std::num::NonZeroU32;
+
+use ring::pbkdf2::{self, PBKDF2_HMAC_SHA256};
+use zeroize::Zeroizing;
+
+use crate::{crypto::ct_eq, metrics, AuthError};
+
+use super::{
+    generate_salt,
+    phc::{self, Phc},
+    PasswordHasher,
+};
+
+/// scrypt cost parameters
+///
+/// The defaults of N = 2^17, r = 8 and p = 1 follow the OWASP recommendation and use 128 MiB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScryptParams {
+    /// Base 2 logarithm of the CPU/memory cost N
+    pub log_n: u8,
+    pub block_size: u32,
+    pub parallelism: u32,
+}
+
+impl Default for ScryptParams {
+    fn default() -> Self {
+        Self {
+            log_n: 17,
+            block_size: 8,
+            parallelism: 1,
+        }
+    }
+}
+
+impl ScryptParams {
+    pub fn new(log_n: u8, block_size: u32, parallelism: u32) -> Self {
+        Self {
+            log_n,
+            block_size,
+            parallelism,
+        }
+    }
+
+    fn validate(&self) -> Result<(), AuthError> {
+        if self.block_size == 0 || self.parallelism == 0 {
+            return Err(AuthError::InvalidInput(
+                "scrypt block size and parallelism must be greater than zero".to_string(),
+            ));
+        }
+
+        if self.log_n == 0 || self.log_n as u64 >= 16 * self.block_size as u64 || self.log_n >= 32 {
+            return Err(AuthError::InvalidInput(
+                "scrypt N must be a power of two below 2^(16 r)".to_string(),
+            ));
+        }
+
+        if self.block_size as u64 * self.parallelism as u64 >= 1 << 30 {
+            return Err(AuthError::InvalidInput(
+                "scrypt r * p must be below 2^30".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Derive a raw scrypt (RFC 7914) key from a password and salt
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::scrypt::{scrypt, ScryptParams};
+///
+/// let key = scrypt(b"password", b"NaCl", &ScryptParams::new(10, 8, 16), 64).unwrap();
+///
+/// assert_eq!(&key[..4], &[0xfd, 0xba, 0xbe, 0x1c]);
+/// ```
+pub fn scrypt(
+    password: &[u8],
+    salt: &[u8],
+    params: &ScryptParams,
+    output_len: usize,
+) -> Result<Vec<u8>, AuthError> {
+    params.validate()?;
+
+    let r = params.block_size as usize;
+    let n = 1usize << params.log_n;
+    let one = NonZeroU32::MIN;
+
+    // Every block depends on the password, so none may outlive the hash
+    let mut blocks = Zeroizing::new(vec![0u8; params.parallelism as usize * 128 * r]);
+    pbkdf2::derive(PBKDF2_HMAC_SHA256, one, salt, password, &mut blocks);
+
+    for block in blocks.chunks_exact_mut(128 * r) {
+        ro_mix(block, n, r);
+    }
+
+    let mut output = vec![0u8; output_len];
+    pbkdf2::derive(PBKDF2_HMAC_SHA256, one, &blocks, password, &mut output);
+
+    Ok(output)
+}
+
+fn ro_mix(block: &mut [u8], n: usize, r: usize) {
+    let words = 32 * r;
+    let mut x: Zeroizing<Vec<u32>> = Zeroizing::new(
+        block
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect(),
+    );
+    let mut v = Zeroizing::new(vec![0u32; words * n]);
+    let mut scratch = Zeroizing::new(vec![0u32; words]);
+
+    for i in 0..n {
+        v[i * words..(i + 1) * words].copy_from_slice(&x);
+        block_mix(&mut x, &mut scratch, r);
+    }
+
+    for _ in 0..n {
+        let j = (x[words - 16] as usize) & (n - 1);
+        x.iter_mut()
+            .zip(&v[j * words..(j + 1) * words])
+            .for_each(|(x, v)| *x ^= v);
+        block_mix(&mut x, &mut scratch, r);
+    }
+
+    for (chunk, word) in block.chunks_exact_mut(4).zip(x.iter()) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+}
+
+fn block_mix(b: &mut [u32], scratch: &mut [u32], r: usize) {
+    let mut x = [0u32; 16];
+    x.copy_from_slice(&b[(2 * r - 1) * 16..]);
+
+    for i in 0..2 * r {
+        x.iter_mut()
+            .zip(&b[i * 16..(i + 1) * 16])
+            .for_each(|(x, b)| *x ^= b);
+        salsa20_8(&mut x);
+
+        // Even blocks go to the first half of the output and odd blocks to the second
+        let position = (i / 2 + (i % 2) * r) * 16;
+        scratch[position..position + 16].copy_from_slice(&x);
+    }
+
+    b.copy_from_slice(scratch);
+}
+
+fn salsa20_8(block: &mut [u32; 16]) {
+    let mut x = *block;
+
+    for _ in 0..4 {
+        let mut quarter = |a: usize, b: usize, c: usize, d: usize| {
+            x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+            x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+            x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+            x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+        };
+
+        quarter(0, 4, 8, 12);
+        quarter(5, 9, 13, 1);
+        quarter(10, 14, 2, 6);
+        quarter(15, 3, 7, 11);
+        quarter(0, 1, 2, 3);
+        quarter(5, 6, 7, 4);
+        quarter(10, 11, 8, 9);
+        quarter(15, 12, 13, 14);
+    }
+
+    block
+        .iter_mut()
+        .zip(x)
+        .for_each(|(block, x)| *block = block.wrapping_add(x));
+}
+
+/// scrypt as a `PasswordHasher`, producing `$scrypt$ln=...,r=...,p=...$<salt>$<hash>`
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::{scrypt::ScryptParams, PasswordHasher, ScryptHasher};
+///
+/// let hasher = ScryptHasher::new(ScryptParams::new(10, 8, 1));
+/// let hashed = hasher.hash("correct horse battery staple").unwrap();
+///
+/// assert!(hashed.starts_with("$scrypt$ln=10,r=8,p=1$"));
+/// assert!(hasher.verify("correct horse battery staple", &hashed).unwrap());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScryptHasher {
+    params: ScryptParams,
+}
+
+impl ScryptHasher {
+    pub fn new(params: ScryptParams) -> Self {
+        Self { params }
+    }
+}
+
+impl PasswordHasher for ScryptHasher {
+    fn hash(&self, password: &str) -> Result<String, AuthError> {
+        let salt = generate_salt();
+        let key = metrics::hashing("scrypt", || {
+            scrypt(password.as_bytes(), &salt, &self.params, 32)
+        })?;
+
+        Ok(phc::format(
+            "scrypt",
+            None,
+            &[
+                ("ln", self.params.log_n.to_string()),
+                ("r", self.params.block_size.to_string()),
+                ("p", self.params.parallelism.to_string()),
+            ],
+            &salt,
+            &key,
+        ))
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, AuthError> {
+        let (params, phc) = parse(hash)?;
+        let key = metrics::hashing("scrypt", || {
+            scrypt(password.as_bytes(), &phc.salt, &params, phc.hash.len())
+        })?;
+
+        Ok(ct_eq(key, phc.hash))
+    }
+
+    fn recognizes(&self, hash: &str) -> bool {
+        hash.starts_with("$scrypt$")
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        parse(hash).map_or(true, |(params, _)| {
+            params.log_n < self.params.log_n
+                || params.block_size < self.params.block_size
+                || params.parallelism < self.params.parallelism
+        })
+    }
+}
+
+fn parse(hash: &str) -> Result<(ScryptParams, Phc<'_>), AuthError> {
+    let phc = Phc::parse(hash)?;
+
+    if phc.id != "scrypt" || phc.hash.is_empty() {
+        return Err(AuthError::Malformed(
+            "Password hash is not scrypt".to_string(),
+        ));
+    }
+
+    let params = ScryptParams::new(phc.param("ln")?, phc.param("r")?, phc.param("p")?);
+
+    Ok((params, phc))
+}
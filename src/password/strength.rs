@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+
+/// Passwords longer than this are only analysed up to this many characters
+const MAX_ANALYSED: usize = 100;
+
+/// Ranked by frequency in public breach corpora, most common first, separated by whitespace
+const COMMON_PASSWORDS: &str = "\
+    123456 password 123456789 12345678 12345 qwerty 1234567 111111 1234567890 123123 abc123 1234 \
+    password1 iloveyou 1q2w3e4r 000000 qwerty123 zaq12wsx dragon sunshine princess letmein \
+    654321 monkey 1qaz2wsx 123321 qwertyuiop superman asdfghjkl trustno1 welcome football \
+    baseball master shadow michael jennifer hunter ashley charlie jordan killer batman access \
+    secret admin login starwars freedom whatever qazwsx ninja mustang passw0rd hello summer \
+    winter spring autumn flower soccer hockey pepper cheese computer internet orange purple \
+    silver golden banana chocolate cookie tigger buster ginger maggie daniel thomas robert \
+    andrew joshua matthew anthony jessica hannah london google apple samsung pokemon minecraft \
+    liverpool chelsea arsenal love angel lovely family friends forever money heaven happy lucky \
+    magic music pass test guest root changeme default abcdef abcd1234 azerty zxcvbnm asdf qwer \
+    blink182 666666 888888 121212 7777777 987654321";
+
+const KEYBOARD_ROWS: &[&str] = &["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+const L33T: &[(char, &[char])] = &[
+    ('4', &['a']),
+    ('@', &['a']),
+    ('8', &['b']),
+    ('(', &['c']),
+    ('3', &['e']),
+    ('6', &['g']),
+    ('1', &['i', 'l']),
+    ('!', &['i']),
+    ('|', &['i', 'l']),
+    ('0', &['o']),
+    ('$', &['s']),
+    ('5', &['s']),
+    ('7', &['t']),
+    ('2', &['z']),
+];
+
+/// Why part of a password was easy to guess
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pattern {
+    Dictionary { l33t: bool, capitalised: bool },
+    UserInput,
+    Repeat,
+    Sequence,
+    Keyboard,
+    Year,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Match {
+    start: usize,
+    end: usize,
+    guesses: f64,
+    pattern: Pattern,
+}
+
+/// Actionable advice for improving a weak password
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Feedback {
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+/// How hard a password is to guess
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    /// From 0 (trivially guessable) to 4 (very unguessable), on the same scale as zxcvbn
+    pub score: u8,
+    /// Estimated guesses needed to crack the password, as a base 10 logarithm
+    pub guesses_log10: f64,
+    pub feedback: Feedback,
+}
+
+/// Estimate how many guesses an attacker needs for a password, in the style of zxcvbn
+///
+/// The password is split into the cheapest sequence of recognisable patterns: common passwords
+/// (including l33t and capitalised variants), anything from `user_inputs` such as the user's
+/// name or email, repeats, sequences, keyboard runs and years. Remaining characters are costed as
+/// brute force. Pass values the attacker is likely to know as `user_inputs`.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::password::strength;
+///
+/// assert_eq!(strength("password", &[]).score, 0);
+/// assert_eq!(strength("P@ssw0rd123", &[]).score, 0);
+/// assert!(strength("connor1987", &["connor", "connor@example.com"]).score <= 1);
+/// assert_eq!(strength("correct horse battery staple", &[]).score, 4);
+///
+/// let weak = strength("qwerty", &[]);
+/// println!("{:?}", weak.feedback.warning);
+/// ```
+pub fn strength(password: &str, user_inputs: &[&str]) -> Score {
+    let characters: Vec<char> = password.chars().take(MAX_ANALYSED).collect();
+    let matches = find_matches(&characters, user_inputs);
+    let (guesses_log10, sequence) = cheapest_sequence(characters.len(), &matches);
+
+    let score = match guesses_log10 {
+        guesses if guesses < 3.0 => 0,
+        guesses if guesses < 6.0 => 1,
+        guesses if guesses < 8.0 => 2,
+        guesses if guesses < 10.0 => 3,
+        _ => 4,
+    };
+
+    Score {
+        score,
+        guesses_log10,
+        feedback: feedback(score, &sequence),
+    }
+}
+
+fn find_matches(characters: &[char], user_inputs: &[&str]) -> Vec<Match> {
+    let lower: Vec<char> = characters
+        .iter()
+        .flat_map(|character| character.to_lowercase())
+        .collect();
+
+    // Lowercasing can change the length of some non-ASCII text, so only match when it did not
+    if lower.len() != characters.len() {
+        return Vec::new();
+    }
+
+    let ranked: HashMap<&str, usize> = COMMON_PASSWORDS
+        .split_whitespace()
+        .enumerate()
+        .map(|(rank, word)| (word, rank + 1))
+        .collect();
+    let user_inputs: Vec<String> = user_inputs
+        .iter()
+        .flat_map(|input| input.split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect();
+
+    let mut matches = Vec::new();
+
+    for start in 0..lower.len() {
+        for end in start + 3..=lower.len() {
+            let original: String = characters[start..end].iter().collect();
+            let word: String = lower[start..end].iter().collect();
+            let capitalised = original != word;
+
+            if user_inputs.contains(&word) {
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses: if capitalised { 2.0 } else { 1.0 },
+                    pattern: Pattern::UserInput,
+                });
+            }
+
+            if let Some(rank) = ranked.get(word.as_str()) {
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses: *rank as f64 * if capitalised { 2.0 } else { 1.0 },
+                    pattern: Pattern::Dictionary {
+                        l33t: false,
+                        capitalised,
+                    },
+                });
+            }
+
+            for unl33ted in unl33t(&lower[start..end]) {
+                if unl33ted == word {
+                    continue;
+                }
+
+                if let Some(rank) = ranked.get(unl33ted.as_str()) {
+                    matches.push(Match {
+                        start,
+                        end,
+                        guesses: *rank as f64 * if capitalised { 4.0 } else { 2.0 },
+                        pattern: Pattern::Dictionary {
+                            l33t: true,
+                            capitalised,
+                        },
+                    });
+                }
+
+                if user_inputs.contains(&unl33ted) {
+                    matches.push(Match {
+                        start,
+                        end,
+                        guesses: 2.0,
+                        pattern: Pattern::UserInput,
+                    });
+                }
+            }
+
+            if let Some(guesses) = repeat_guesses(&lower[start..end]) {
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses,
+                    pattern: Pattern::Repeat,
+                });
+            }
+
+            if let Some(guesses) = sequence_guesses(&lower[start..end]) {
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses,
+                    pattern: Pattern::Sequence,
+                });
+            }
+
+            if end - start >= 4 && is_keyboard_run(&word) {
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses: 40.0 * (end - start) as f64,
+                    pattern: Pattern::Keyboard,
+                });
+            }
+
+            if end - start == 4 && is_recent_year(&word) {
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses: 200.0,
+                    pattern: Pattern::Year,
+                });
+            }
+        }
+    }
+
+    matches
+}
+
+/// Every reading of the text with l33t substitutions undone
+fn unl33t(characters: &[char]) -> Vec<String> {
+    let mut readings = vec![String::new()];
+
+    for character in characters {
+        let options: &[char] = L33T
+            .iter()
+            .find(|(l33t, _)| l33t == character)
+            .map_or(std::slice::from_ref(character), |(_, letters)| letters);
+
+        readings = readings
+            .iter()
+            .flat_map(|reading| {
+                options.iter().map(move |option| {
+                    let mut reading = reading.clone();
+                    reading.push(*option);
+                    reading
+                })
+            })
+            .take(16)
+            .collect();
+    }
+
+    readings
+}
+
+fn cardinality(character: char) -> f64 {
+    if character.is_ascii_digit() {
+        10.0
+    } else if character.is_ascii_lowercase() {
+        26.0
+    } else {
+        33.0
+    }
+}
+
+/// Guesses for text made of a shorter unit repeated, such as "aaaa" or "abcabc"
+fn repeat_guesses(characters: &[char]) -> Option<f64> {
+    let length = characters.len();
+
+    (1..=length / 2)
+        .filter(|unit| length.is_multiple_of(*unit))
+        .find(|unit| {
+            characters
+                .chunks(*unit)
+                .all(|chunk| chunk == &characters[..*unit])
+        })
+        .map(|unit| {
+            let unit_guesses: f64 = characters[..unit].iter().map(|c| cardinality(*c)).product();
+
+            unit_guesses.min(1e4) * (length / unit) as f64
+        })
+}
+
+/// Guesses for runs with a constant step of one, such as "abcd" or "9876"
+fn sequence_guesses(characters: &[char]) -> Option<f64> {
+    let steps: Vec<i64> = characters
+        .windows(2)
+        .map(|pair| pair[1] as i64 - pair[0] as i64)
+        .collect();
+
+    let step = steps[0];
+    if step.abs() != 1 || steps.iter().any(|other| *other != step) {
+        return None;
+    }
+
+    let first = characters[0];
+    let start_guesses = if matches!(first, 'a' | 'z' | '0' | '1' | '9') {
+        4.0
+    } else {
+        cardinality(first)
+    };
+    let direction = if step < 0 { 2.0 } else { 1.0 };
+
+    Some(start_guesses * characters.len() as f64 * direction)
+}
+
+fn is_keyboard_run(word: &str) -> bool {
+    let reversed: String = word.chars().rev().collect();
+
+    KEYBOARD_ROWS
+        .iter()
+        .any(|row| row.contains(word) || row.contains(&reversed))
+}
+
+fn is_recent_year(word: &str) -> bool {
+    matches!(word.parse::<u32>(), Ok(1900..=2099)) && word.len() == 4
+}
+
+/// The sequence of matches and brute forced characters with the fewest total guesses
+fn cheapest_sequence(length: usize, matches: &[Match]) -> (f64, Vec<Match>) {
+    let mut best = vec![(0.0f64, None::<Match>); length + 1];
+
+    for end in 1..=length {
+        // Brute forcing a character is costed at ten guesses, as zxcvbn does
+        best[end] = (best[end - 1].0 + 1.0, None);
+
+        for candidate in matches.iter().filter(|candidate| candidate.end == end) {
+            let cost = best[candidate.start].0 + candidate.guesses.log10();
+
+            if cost < best[end].0 {
+                best[end] = (cost, Some(*candidate));
+            }
+        }
+    }
+
+    let mut sequence = Vec::new();
+    let mut position = length;
+    while position > 0 {
+        match best[position].1 {
+            Some(found) => {
+                sequence.push(found);
+                position = found.start;
+            }
+            None => position -= 1,
+        }
+    }
+
+    (best[length].0, sequence)
+}
+
+fn feedback(score: u8, sequence: &[Match]) -> Feedback {
+    if score > 2 {
+        return Feedback::default();
+    }
+
+    let mut suggestions = vec!["Add another word or two. Uncommon words are better.".to_string()];
+
+    let Some(longest) = sequence.iter().max_by_key(|found| found.end - found.start) else {
+        suggestions.push("Use a few words, avoid common phrases.".to_string());
+        suggestions.push("No need for symbols, digits, or uppercase letters.".to_string());
+
+        return Feedback {
+            warning: None,
+            suggestions,
+        };
+    };
+
+    let warning = match longest.pattern {
+        Pattern::Dictionary { l33t, capitalised } => {
+            if capitalised {
+                suggestions.push("Capitalization doesn't help very much.".to_string());
+            }
+            if l33t {
+                suggestions.push(
+                    "Predictable substitutions like '@' instead of 'a' don't help very much."
+                        .to_string(),
+                );
+            }
+
+            "This is similar to a commonly used password."
+        }
+        Pattern::UserInput => "Names and details from your account are easy to guess.",
+        Pattern::Repeat => {
+            suggestions.push("Avoid repeated words and characters.".to_string());
+            "Repeats like \"aaa\" or \"abcabc\" are easy to guess."
+        }
+        Pattern::Sequence => {
+            suggestions.push("Avoid sequences.".to_string());
+            "Sequences like abc or 6543 are easy to guess."
+        }
+        Pattern::Keyboard => {
+            suggestions.push("Use a longer keyboard pattern with more turns.".to_string());
+            "Straight rows of keys are easy to guess."
+        }
+        Pattern::Year => {
+            suggestions.push("Avoid recent years and years associated with you.".to_string());
+            "Recent years are easy to guess."
+        }
+    };
+
+    Feedback {
+        warning: Some(warning.to_string()),
+        suggestions,
+    }
+}
@@ -0,0 +1,43 @@
+-- Users, their credentials, sessions and opaque tokens. Timestamps are Unix seconds.
+
+CREATE TABLE IF NOT EXISTS auth_users (
+    id TEXT PRIMARY KEY,
+    email TEXT NOT NULL UNIQUE,
+    username TEXT UNIQUE,
+    email_verified BOOLEAN NOT NULL DEFAULT FALSE,
+    disabled BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS auth_credentials (
+    user_id TEXT PRIMARY KEY,
+    password_hash TEXT,
+    totp_secret TEXT,
+    mfa TEXT NOT NULL DEFAULT 'disabled' CHECK (mfa IN ('disabled', 'pending', 'enabled')),
+    last_totp_step BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS auth_sessions (
+    id_hash TEXT PRIMARY KEY,
+    account TEXT,
+    data JSONB NOT NULL DEFAULT '{}',
+    created_at BIGINT NOT NULL,
+    last_seen_at BIGINT NOT NULL,
+    idle_timeout BIGINT NOT NULL,
+    absolute_expires_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS auth_sessions_account ON auth_sessions (account);
+CREATE INDEX IF NOT EXISTS auth_sessions_expires_at ON auth_sessions (expires_at);
+
+CREATE TABLE IF NOT EXISTS auth_opaque_tokens (
+    hash TEXT PRIMARY KEY,
+    purpose TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS auth_opaque_tokens_subject ON auth_opaque_tokens (purpose, subject);
+CREATE INDEX IF NOT EXISTS auth_opaque_tokens_expires_at ON auth_opaque_tokens (expires_at);
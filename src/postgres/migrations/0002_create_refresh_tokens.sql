@@ -0,0 +1,13 @@
+-- Refresh tokens, kept as SHA-256 hashes and grouped into families. Timestamps are Unix seconds.
+
+CREATE TABLE IF NOT EXISTS auth_refresh_tokens (
+    hash TEXT PRIMARY KEY,
+    family TEXT NOT NULL,
+    parent TEXT,
+    account TEXT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    rotated BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX IF NOT EXISTS auth_refresh_tokens_family ON auth_refresh_tokens (family);
+CREATE INDEX IF NOT EXISTS auth_refresh_tokens_expires_at ON auth_refresh_tokens (expires_at);
@@ -0,0 +1,708 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, RngCore};
+use ring::pbkdf2;
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+};
+
+use crate::{crypto::ct_eq, AuthError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Migrations creating the tables the Postgres backed stores use, in order
+///
+/// `PostgresClient::migrate` applies the ones a database has not seen yet. They can also be copied into
+/// an application's own migration tool.
+pub const MIGRATIONS: &[(i32, &str)] = &[
+    (1, include_str!("migrations/0001_create_auth.sql")),
+    (2, include_str!("migrations/0002_create_refresh_tokens.sql")),
+];
+
+/// Where and as whom to connect
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostgresConfig {
+    pub user: String,
+    pub password: Option<String>,
+    pub database: String,
+}
+
+impl PostgresConfig {
+    pub fn new(user: impl Into<String>, database: impl Into<String>) -> Self {
+        Self {
+            user: user.into(),
+            password: None,
+            database: database.into(),
+        }
+    }
+
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+}
+
+/// One row of a result, every column in Postgres' text format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row(pub Vec<Option<String>>);
+
+impl Row {
+    /// The column's value, or `None` for `NULL` and missing columns
+    pub fn get(&self, column: usize) -> Option<&str> {
+        self.0.get(column)?.as_deref()
+    }
+
+    /// Like `get`, failing with `AuthError::Backend` for `NULL`
+    pub fn text(&self, column: usize) -> Result<&str, AuthError> {
+        self.get(column)
+            .ok_or_else(|| AuthError::backend(format!("Column {} is NULL", column)))
+    }
+
+    pub fn int(&self, column: usize) -> Result<i64, AuthError> {
+        self.text(column)?
+            .parse()
+            .map_err(|_| AuthError::backend(format!("Column {} is not an integer", column)))
+    }
+
+    pub fn bool(&self, column: usize) -> Result<bool, AuthError> {
+        match self.text(column)? {
+            "t" => Ok(true),
+            "f" => Ok(false),
+            _ => Err(AuthError::backend(format!(
+                "Column {} is not a boolean",
+                column
+            ))),
+        }
+    }
+}
+
+/// A minimal Postgres client speaking the v3 protocol over a single connection, used by the
+/// Postgres backed stores
+///
+/// Queries use the extended protocol with text parameters, so values are never interpolated into
+/// SQL. Password, MD5 and SCRAM-SHA-256 authentication are supported. When the server is not local,
+/// negotiate TLS with `request_tls`, wrap the stream in a TLS client and pass that to `handshake`.
+/// Error responses become `AuthError::Backend`.
+///
+/// A query that is cancelled or breaks before the server reports `ReadyForQuery` would leave its
+/// responses for the next caller, so the client is then poisoned and every later call fails; open
+/// a new client to recover.
+///
+/// ### Example
+/// ```rust,no_run
+/// use lonewolf_auth_toolkit::postgres::{PostgresClient, PostgresConfig};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = PostgresConfig::new("postgres", "myapp").password("SomePassword");
+///     let postgres = PostgresClient::connect("127.0.0.1:5432", &config).await?;
+///     postgres.migrate().await?;
+///
+///     let rows = postgres.query("SELECT $1::int + 1", &[Some("41")]).await?;
+///     assert_eq!(rows[0].get(0), Some("42"));
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// A query dropped before the server finishes answering, e.g. on a timeout, poisons the client
+/// ```rust
+/// use std::time::Duration;
+/// use lonewolf_auth_toolkit::postgres::{PostgresClient, PostgresConfig};
+/// use tokio::io::AsyncWriteExt;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     // A server that accepts the login, AuthenticationOk then ReadyForQuery, and then goes quiet
+///     let (stream, mut server) = tokio::io::duplex(1024);
+///     server.write_all(b"R\0\0\0\x08\0\0\0\0Z\0\0\0\x05I").await?;
+///
+///     let config = PostgresConfig::new("postgres", "myapp");
+///     let postgres = PostgresClient::handshake(stream, &config).await?;
+///
+///     let pending = postgres.query("SELECT 1", &[]);
+///     assert!(tokio::time::timeout(Duration::from_millis(10), pending).await.is_err());
+///
+///     // The late rows of the first query must never reach this one
+///     assert!(postgres.query("SELECT 2", &[]).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct PostgresClient<S = TcpStream> {
+    connection: Mutex<Connection<S>>,
+}
+
+struct Connection<S> {
+    stream: BufStream<S>,
+    /// Set while a query is in flight and left set if it never reaches `ReadyForQuery`
+    poisoned: bool,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Connection<S> {
+    /// Send `message` and read its responses up to `ReadyForQuery`, poisoning the connection if
+    /// that is never reached, e.g. because the future was dropped
+    async fn exchange(
+        &mut self,
+        message: &[u8],
+        handle: impl FnMut(u8, &[u8]) -> Result<(), AuthError>,
+    ) -> Result<(), AuthError> {
+        if self.poisoned {
+            return Err(AuthError::backend(
+                "Postgres connection is out of sync after an unfinished query",
+            ));
+        }
+
+        self.poisoned = true;
+        send(&mut self.stream, message).await?;
+        let outcome = finish(&mut self.stream, handle).await?;
+        self.poisoned = false;
+
+        outcome
+    }
+}
+
+impl PostgresClient<TcpStream> {
+    pub async fn connect(
+        address: impl ToSocketAddrs,
+        config: &PostgresConfig,
+    ) -> Result<Self, AuthError> {
+        let stream = TcpStream::connect(address)
+            .await
+            .map_err(AuthError::backend)?;
+
+        Self::handshake(stream, config).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> PostgresClient<S> {
+    /// Start up and authenticate over an established stream
+    ///
+    /// With SCRAM-SHA-256 the server must prove it knows the password too, otherwise this fails
+    /// with `AuthError::Verification`.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use lonewolf_auth_toolkit::{
+    ///     postgres::{PostgresClient, PostgresConfig},
+    ///     AuthError,
+    /// };
+    /// use tokio::io::AsyncWriteExt;
+    ///
+    /// #[tokio::main]
+    /// pub async fn main() -> Result<(), anyhow::Error> {
+    ///     // A server that asks for SCRAM-SHA-256 and then accepts without signing the exchange
+    ///     let (stream, mut server) = tokio::io::duplex(1024);
+    ///     server.write_all(b"R\0\0\0\x17\0\0\0\x0aSCRAM-SHA-256\0\0").await?;
+    ///     server.write_all(b"R\0\0\0\x08\0\0\0\0Z\0\0\0\x05I").await?;
+    ///
+    ///     let config = PostgresConfig::new("postgres", "myapp").password("SomePassword");
+    ///     let handshake = PostgresClient::handshake(stream, &config).await;
+    ///     assert!(matches!(handshake, Err(AuthError::Verification(_))));
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn handshake(stream: S, config: &PostgresConfig) -> Result<Self, AuthError> {
+        let mut connection = BufStream::new(stream);
+
+        let mut startup = Vec::new();
+        startup.extend_from_slice(&196608i32.to_be_bytes());
+        for (key, value) in [("user", &config.user), ("database", &config.database)] {
+            put_str(&mut startup, key);
+            put_str(&mut startup, value);
+        }
+        startup.push(0);
+
+        let mut message = (i32::try_from(startup.len())? + 4).to_be_bytes().to_vec();
+        message.extend(startup);
+        send(&mut connection, &message).await?;
+
+        authenticate(&mut connection, config).await?;
+
+        // Parameter statuses and the backend key until the server is ready
+        finish(&mut connection, |_, _| Ok(())).await??;
+
+        Ok(Self {
+            connection: Mutex::new(Connection {
+                stream: connection,
+                poisoned: false,
+            }),
+        })
+    }
+
+    /// Run a statement with `$1`, `$2`, ... parameters and return its rows
+    pub async fn query(&self, sql: &str, params: &[Option<&str>]) -> Result<Vec<Row>, AuthError> {
+        Ok(self.run(sql, params).await?.0)
+    }
+
+    /// Run a statement and return how many rows it inserted, updated or deleted
+    pub async fn execute(&self, sql: &str, params: &[Option<&str>]) -> Result<u64, AuthError> {
+        Ok(self.run(sql, params).await?.1)
+    }
+
+    /// Run several `;` separated statements without parameters, e.g. a migration
+    pub async fn batch(&self, sql: &str) -> Result<(), AuthError> {
+        let mut query = Vec::new();
+        put_str(&mut query, sql);
+
+        let message = frame(b'Q', &query)?;
+        let mut connection = self.connection.lock().await;
+
+        connection.exchange(&message, |_, _| Ok(())).await
+    }
+
+    /// Apply the `MIGRATIONS` the database has not seen yet, each in its own transaction
+    pub async fn migrate(&self) -> Result<(), AuthError> {
+        self.batch(
+            "CREATE TABLE IF NOT EXISTS auth_migrations (version INTEGER PRIMARY KEY, \
+             applied_at TIMESTAMPTZ NOT NULL DEFAULT now())",
+        )
+        .await?;
+
+        let applied = self
+            .query("SELECT version FROM auth_migrations", &[])
+            .await?
+            .iter()
+            .map(|row| row.int(0))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (version, sql) in MIGRATIONS {
+            if applied.contains(&i64::from(*version)) {
+                continue;
+            }
+
+            self.batch(&format!(
+                "BEGIN; {} INSERT INTO auth_migrations (version) VALUES ({}); COMMIT;",
+                sql, version
+            ))
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn run(&self, sql: &str, params: &[Option<&str>]) -> Result<(Vec<Row>, u64), AuthError> {
+        let mut parse = Vec::new();
+        put_str(&mut parse, "");
+        put_str(&mut parse, sql);
+        parse.extend_from_slice(&0i16.to_be_bytes());
+
+        let mut bind = Vec::new();
+        put_str(&mut bind, "");
+        put_str(&mut bind, "");
+        bind.extend_from_slice(&0i16.to_be_bytes());
+        bind.extend_from_slice(&i16::try_from(params.len())?.to_be_bytes());
+        for param in params {
+            match param {
+                Some(value) => {
+                    bind.extend_from_slice(&i32::try_from(value.len())?.to_be_bytes());
+                    bind.extend_from_slice(value.as_bytes());
+                }
+                None => bind.extend_from_slice(&(-1i32).to_be_bytes()),
+            }
+        }
+        bind.extend_from_slice(&0i16.to_be_bytes());
+
+        let mut execute = Vec::new();
+        put_str(&mut execute, "");
+        execute.extend_from_slice(&0i32.to_be_bytes());
+
+        let mut buffer = frame(b'P', &parse)?;
+        buffer.extend(frame(b'B', &bind)?);
+        buffer.extend(frame(b'E', &execute)?);
+        buffer.extend(frame(b'S', &[])?);
+
+        let mut connection = self.connection.lock().await;
+
+        let mut rows = Vec::new();
+        let mut affected = 0;
+        connection
+            .exchange(&buffer, |kind, body| {
+                match kind {
+                    b'D' => rows.push(data_row(body)?),
+                    b'C' => affected = command_count(body),
+                    _ => {}
+                }
+
+                Ok(())
+            })
+            .await?;
+
+        Ok((rows, affected))
+    }
+}
+
+/// Ask the server to switch a fresh connection to TLS with an `SSLRequest`
+///
+/// On success the caller runs the TLS client handshake over `stream`, e.g. with `tokio-rustls`, and
+/// passes the TLS stream to `PostgresClient::handshake`. A server that declines fails with
+/// `AuthError::Backend` rather than falling back to plaintext.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::postgres::request_tls;
+/// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let (mut stream, mut server) = tokio::io::duplex(64);
+///     server.write_all(b"S").await?;
+///     request_tls(&mut stream).await?;
+///
+///     let mut request = [0u8; 8];
+///     server.read_exact(&mut request).await?;
+///     assert_eq!(request, [0, 0, 0, 8, 4, 210, 22, 47]);
+///
+///     // A server without TLS says no
+///     let (mut stream, mut server) = tokio::io::duplex(64);
+///     server.write_all(b"N").await?;
+///     assert!(request_tls(&mut stream).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn request_tls<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+) -> Result<(), AuthError> {
+    let mut request = 8i32.to_be_bytes().to_vec();
+    request.extend_from_slice(&80877103i32.to_be_bytes());
+    stream
+        .write_all(&request)
+        .await
+        .map_err(AuthError::backend)?;
+    stream.flush().await.map_err(AuthError::backend)?;
+
+    // Read only the one byte answer, anything after it must come through the TLS session
+    let mut answer = [0u8; 1];
+    stream
+        .read_exact(&mut answer)
+        .await
+        .map_err(AuthError::backend)?;
+
+    match &answer {
+        b"S" => Ok(()),
+        b"N" => Err(AuthError::backend(
+            "The Postgres server does not accept TLS",
+        )),
+        _ => Err(AuthError::backend("Unexpected answer to the TLS request")),
+    }
+}
+
+async fn authenticate<S: AsyncRead + AsyncWrite + Unpin + Send>(
+    connection: &mut BufStream<S>,
+    config: &PostgresConfig,
+) -> Result<(), AuthError> {
+    let password = || {
+        config
+            .password
+            .as_deref()
+            .ok_or_else(|| AuthError::InvalidInput("The server requires a password".to_string()))
+    };
+
+    let mut scram: Option<(String, String)> = None;
+    let mut server_signature: Option<Vec<u8>> = None;
+
+    loop {
+        let (kind, body) = read_message(connection).await?;
+
+        match kind {
+            b'E' => return Err(error_response(&body)),
+            b'R' => {}
+            _ => {
+                return Err(AuthError::backend(
+                    "Unexpected message during authentication",
+                ))
+            }
+        }
+
+        let (code, data) = split_int(&body)?;
+        match code {
+            // Once SCRAM has started only a verified server signature may lead here
+            0 if scram.is_some() || server_signature.is_some() => {
+                return Err(AuthError::Verification(
+                    "Postgres server skipped SCRAM verification".to_string(),
+                ))
+            }
+            0 => break,
+            // Cleartext password
+            3 => {
+                let mut message = Vec::new();
+                put_str(&mut message, password()?);
+                send(connection, &frame(b'p', &message)?).await?;
+            }
+            // MD5 with a four byte salt
+            5 => {
+                let inner = format!(
+                    "{:x}",
+                    md5::compute(format!("{}{}", password()?, config.user))
+                );
+                let mut salted = inner.into_bytes();
+                salted.extend_from_slice(data.get(..4).unwrap_or_default());
+
+                let mut message = Vec::new();
+                put_str(&mut message, &format!("md5{:x}", md5::compute(salted)));
+                send(connection, &frame(b'p', &message)?).await?;
+            }
+            // SASL: the server lists its mechanisms
+            10 => {
+                let mechanisms = String::from_utf8_lossy(data);
+                if !mechanisms.split('\0').any(|name| name == "SCRAM-SHA-256") {
+                    return Err(AuthError::backend(
+                        "The server offers no supported SASL mechanism",
+                    ));
+                }
+
+                let mut nonce = [0u8; 18];
+                thread_rng().fill_bytes(&mut nonce);
+                let client_first_bare = format!("n=,r={}", STANDARD.encode(nonce));
+                let client_first = format!("n,,{}", client_first_bare);
+
+                let mut message = Vec::new();
+                put_str(&mut message, "SCRAM-SHA-256");
+                message.extend_from_slice(&i32::try_from(client_first.len())?.to_be_bytes());
+                message.extend_from_slice(client_first.as_bytes());
+                send(connection, &frame(b'p', &message)?).await?;
+
+                scram = Some((client_first_bare, STANDARD.encode(nonce)));
+            }
+            // SASL continue: the server's nonce, salt and iteration count
+            11 => {
+                let (client_first_bare, client_nonce) = scram
+                    .take()
+                    .ok_or_else(|| AuthError::backend("SCRAM exchange out of order"))?;
+                let server_first = String::from_utf8(data.to_vec())?;
+                let (client_final, signature) = scram_final(
+                    password()?,
+                    &client_first_bare,
+                    &client_nonce,
+                    &server_first,
+                )?;
+
+                send(connection, &frame(b'p', client_final.as_bytes())?).await?;
+                server_signature = Some(signature);
+            }
+            // SASL final: prove the server knew the password too
+            12 => {
+                let expected = server_signature
+                    .take()
+                    .ok_or_else(|| AuthError::backend("SCRAM exchange out of order"))?;
+                let received = String::from_utf8_lossy(data)
+                    .strip_prefix("v=")
+                    .map(|signature| STANDARD.decode(signature.trim()))
+                    .transpose()?
+                    .unwrap_or_default();
+
+                if !ct_eq(&expected, &received) {
+                    return Err(AuthError::Verification(
+                        "Postgres server failed SCRAM verification".to_string(),
+                    ));
+                }
+            }
+            _ => {
+                return Err(AuthError::backend(format!(
+                    "Unsupported Postgres authentication method {}",
+                    code
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The client final message and the server signature to expect, per RFC 5802
+fn scram_final(
+    password: &str,
+    client_first_bare: &str,
+    client_nonce: &str,
+    server_first: &str,
+) -> Result<(String, Vec<u8>), AuthError> {
+    let attribute = |name: &str| {
+        server_first
+            .split(',')
+            .find_map(|part| part.strip_prefix(name))
+            .ok_or_else(|| AuthError::backend("Malformed SCRAM server message"))
+    };
+
+    let nonce = attribute("r=")?;
+    if !nonce.starts_with(client_nonce) {
+        return Err(AuthError::Verification(
+            "Postgres server altered the SCRAM nonce".to_string(),
+        ));
+    }
+    let salt = STANDARD.decode(attribute("s=")?)?;
+    let iterations = attribute("i=")?
+        .parse::<u32>()?
+        .try_into()
+        .map_err(|_| AuthError::backend("SCRAM iteration count is zero"))?;
+
+    let mut salted = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        password.as_bytes(),
+        &mut salted,
+    );
+
+    let hmac = |key: &[u8], message: &[u8]| {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(message);
+        mac.finalize().into_bytes().to_vec()
+    };
+
+    let client_key = hmac(&salted, b"Client Key");
+    let stored_key = Sha256::digest(&client_key);
+    let without_proof = format!("c=biws,r={}", nonce);
+    let auth_message = format!("{},{},{}", client_first_bare, server_first, without_proof);
+
+    let signature = hmac(&stored_key, auth_message.as_bytes());
+    let proof: Vec<u8> = client_key
+        .iter()
+        .zip(signature)
+        .map(|(key, signature)| key ^ signature)
+        .collect();
+
+    let server_key = hmac(&salted, b"Server Key");
+
+    Ok((
+        format!("{},p={}", without_proof, STANDARD.encode(proof)),
+        hmac(&server_key, auth_message.as_bytes()),
+    ))
+}
+
+/// Read messages until `ReadyForQuery`, passing each to `handle`
+///
+/// The outer error is a connection that broke before `ReadyForQuery`, the inner one the first error
+/// response or `handle` failure.
+async fn finish<S: AsyncRead + AsyncWrite + Unpin + Send>(
+    connection: &mut BufStream<S>,
+    mut handle: impl FnMut(u8, &[u8]) -> Result<(), AuthError>,
+) -> Result<Result<(), AuthError>, AuthError> {
+    let mut error = None;
+
+    loop {
+        let (kind, body) = read_message(connection).await?;
+
+        match kind {
+            b'Z' => break,
+            b'E' => error = error.or(Some(error_response(&body))),
+            _ => {
+                if let Err(failed) = handle(kind, &body) {
+                    error = error.or(Some(failed));
+                }
+            }
+        }
+    }
+
+    Ok(error.map_or(Ok(()), Err))
+}
+
+fn frame(kind: u8, body: &[u8]) -> Result<Vec<u8>, AuthError> {
+    let mut message = vec![kind];
+    message.extend_from_slice(&(i32::try_from(body.len())? + 4).to_be_bytes());
+    message.extend_from_slice(body);
+
+    Ok(message)
+}
+
+async fn send<S: AsyncWrite + AsyncRead + Unpin>(
+    connection: &mut BufStream<S>,
+    message: &[u8],
+) -> Result<(), AuthError> {
+    connection
+        .write_all(message)
+        .await
+        .map_err(AuthError::backend)?;
+
+    connection.flush().await.map_err(AuthError::backend)
+}
+
+async fn read_message<S: AsyncRead + AsyncWrite + Unpin>(
+    connection: &mut BufStream<S>,
+) -> Result<(u8, Vec<u8>), AuthError> {
+    let mut header = [0u8; 5];
+    connection
+        .read_exact(&mut header)
+        .await
+        .map_err(AuthError::backend)?;
+
+    let len = i32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+    let mut body = vec![0; usize::try_from(len)?.saturating_sub(4)];
+    connection
+        .read_exact(&mut body)
+        .await
+        .map_err(AuthError::backend)?;
+
+    Ok((header[0], body))
+}
+
+fn put_str(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(value.as_bytes());
+    buffer.push(0);
+}
+
+fn split_int(body: &[u8]) -> Result<(i32, &[u8]), AuthError> {
+    match body.split_first_chunk::<4>() {
+        Some((int, rest)) => Ok((i32::from_be_bytes(*int), rest)),
+        None => Err(AuthError::backend("Truncated Postgres message")),
+    }
+}
+
+fn data_row(body: &[u8]) -> Result<Row, AuthError> {
+    let truncated = || AuthError::backend("Truncated Postgres data row");
+
+    let (count, mut rest) = body.split_first_chunk::<2>().ok_or_else(truncated)?;
+    let mut columns = Vec::with_capacity(usize::from(u16::from_be_bytes(*count)));
+
+    for _ in 0..u16::from_be_bytes(*count) {
+        let (len, after) = split_int(rest)?;
+
+        if len < 0 {
+            columns.push(None);
+            rest = after;
+        } else {
+            let (value, after) = after
+                .split_at_checked(usize::try_from(len)?)
+                .ok_or_else(truncated)?;
+            columns.push(Some(String::from_utf8(value.to_vec())?));
+            rest = after;
+        }
+    }
+
+    Ok(Row(columns))
+}
+
+/// The row count at the end of a command tag such as `INSERT 0 1` or `DELETE 3`
+fn command_count(body: &[u8]) -> u64 {
+    String::from_utf8_lossy(body)
+        .trim_end_matches('\0')
+        .rsplit(' ')
+        .next()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0)
+}
+
+/// The message and SQLSTATE of an `ErrorResponse`
+fn error_response(body: &[u8]) -> AuthError {
+    let mut message = None;
+    let mut code = None;
+
+    for field in body.split(|&byte| byte == 0) {
+        match field.split_first() {
+            Some((b'M', value)) => message = Some(String::from_utf8_lossy(value).into_owned()),
+            Some((b'C', value)) => code = Some(String::from_utf8_lossy(value).into_owned()),
+            _ => {}
+        }
+    }
+
+    AuthError::backend(format!(
+        "{} ({})",
+        message.as_deref().unwrap_or("Postgres error"),
+        code.as_deref().unwrap_or("unknown")
+    ))
+}
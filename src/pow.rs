@@ -0,0 +1,264 @@
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    captcha::{CaptchaOutcome, CaptchaVerifier},
+    crypto::{hmac_sign, hmac_verify, HmacKeys},
+    rate_limit::detection::{Detection, Response},
+    token::{generate_token, hash_token, nonce::NonceStore, now},
+    AuthError,
+};
+
+/// Leading zero bits a solution needs unless configured otherwise, about 65 000 hashes
+pub const DEFAULT_DIFFICULTY: u8 = 16;
+
+/// Bits added per step of the detector's `Response`, each making the work 16 times harder
+pub const DIFFICULTY_STEP: u8 = 4;
+
+/// The highest difficulty challenges are issued with, so a browser can still solve them
+pub const MAX_DIFFICULTY: u8 = 28;
+
+/// How long a challenge can be solved and redeemed for
+pub const DEFAULT_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// A challenge to send to the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    /// The opaque string the client hashes, `<difficulty>.<expires>.<random>.<signature>`
+    pub value: String,
+    pub difficulty: u8,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+}
+
+/// Proof of work challenges, a CAPTCHA alternative that needs no third party or user interaction
+///
+/// The server issues a signed challenge with a difficulty `d`; the client searches for a decimal
+/// counter such that `SHA-256(challenge ":" counter)` starts with `d` zero bits, and sends both
+/// back. Checking takes one hash, while solving takes about `2^d`, which is unnoticeable for a
+/// person but makes bulk requests expensive. Challenges are stateless until redeemed, then
+/// recorded in the nonce store so each is accepted once.
+///
+/// `challenge_for` raises the difficulty with a `Detector`'s verdict, by `DIFFICULTY_STEP` bits
+/// per step of `Response`, so likely bots do far more work than normal users. As a
+/// `CaptchaVerifier` it takes `<challenge>:<counter>` responses.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::crypto::{HmacKey, HmacKeys};
+/// use lonewolf_auth_toolkit::pow::{self, ProofOfWork};
+/// use lonewolf_auth_toolkit::token::nonce::MemoryNonceStore;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let keys = HmacKeys::new(1, HmacKey::sha256(b"a proof of work key, at least 32 bytes")?);
+///     let pow = ProofOfWork::new(keys, MemoryNonceStore::default()).difficulty(8)?;
+///
+///     let challenge = pow.challenge()?;
+///
+///     // Normally done by the client, e.g. in a web worker
+///     let solution = pow::solve(&challenge.value)?;
+///
+///     pow.verify(&challenge.value, &solution).await?;
+///
+///     // Each challenge is only accepted once
+///     assert!(pow.verify(&challenge.value, &solution).await.is_err());
+///
+///     // Changing the difficulty invalidates the signature
+///     let easier = challenge.value.replacen("8.", "1.", 1);
+///     assert!(pow.verify(&easier, &solution).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct ProofOfWork<S> {
+    keys: HmacKeys,
+    nonces: S,
+    difficulty: u8,
+    ttl: Duration,
+}
+
+impl<S: NonceStore> ProofOfWork<S> {
+    pub fn new(keys: HmacKeys, nonces: S) -> Self {
+        Self {
+            keys,
+            nonces,
+            difficulty: DEFAULT_DIFFICULTY,
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// The difficulty for normal traffic, at most `MAX_DIFFICULTY`
+    pub fn difficulty(mut self, difficulty: u8) -> Result<Self, AuthError> {
+        check_difficulty(difficulty)?;
+        self.difficulty = difficulty;
+
+        Ok(self)
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// A challenge at the configured difficulty
+    pub fn challenge(&self) -> Result<Challenge, AuthError> {
+        self.challenge_with(self.difficulty)
+    }
+
+    /// A challenge whose difficulty follows the detector's verdict on the client
+    pub fn challenge_for(&self, detection: &Detection) -> Result<Challenge, AuthError> {
+        self.challenge_with(self.difficulty_for(detection))
+    }
+
+    /// The difficulty `challenge_for` uses, capped at `MAX_DIFFICULTY`
+    pub fn difficulty_for(&self, detection: &Detection) -> u8 {
+        let steps = match detection.response {
+            Response::Allow => 0,
+            Response::RequireCaptcha => 1,
+            Response::Lock => 2,
+            Response::Alert => 3,
+        };
+
+        (self.difficulty + steps * DIFFICULTY_STEP).min(MAX_DIFFICULTY)
+    }
+
+    pub fn challenge_with(&self, difficulty: u8) -> Result<Challenge, AuthError> {
+        check_difficulty(difficulty)?;
+
+        let expires_at = now()? + self.ttl.as_secs();
+        let unsigned = format!("{}.{}.{}", difficulty, expires_at, generate_token());
+        let signature = hmac_sign(&self.keys, &signed_message(&unsigned));
+
+        Ok(Challenge {
+            value: format!("{}.{}", unsigned, signature),
+            difficulty,
+            expires_at,
+        })
+    }
+
+    /// Accept a solved challenge once
+    ///
+    /// Fails with `AuthError::Verification` when the challenge was not issued by this server, has
+    /// expired or was already redeemed, or `solution` does not meet its difficulty.
+    pub async fn verify(&self, challenge: &str, solution: &str) -> Result<(), AuthError> {
+        let invalid = || AuthError::Verification("Proof of work is invalid".to_string());
+
+        let mut parts = challenge.splitn(4, '.');
+        let (difficulty, expires_at, random, signature) =
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(difficulty), Some(expires_at), Some(random), Some(signature)) => {
+                    (difficulty, expires_at, random, signature)
+                }
+                _ => return Err(invalid()),
+            };
+        let unsigned = format!("{}.{}.{}", difficulty, expires_at, random);
+        if !hmac_verify(&self.keys, &signed_message(&unsigned), signature).unwrap_or(false) {
+            return Err(invalid());
+        }
+
+        let difficulty: u8 = difficulty.parse().map_err(|_| invalid())?;
+        let expires_at: u64 = expires_at.parse().map_err(|_| invalid())?;
+        if expires_at <= now()? {
+            return Err(AuthError::Verification(
+                "Proof of work challenge has expired".to_string(),
+            ));
+        }
+
+        let well_formed = !solution.is_empty()
+            && solution.len() <= 20
+            && solution.bytes().all(|b| b.is_ascii_digit());
+        if !well_formed || leading_zeros(challenge, solution) < u32::from(difficulty) {
+            return Err(invalid());
+        }
+
+        if !self
+            .nonces
+            .insert(&hash_token(challenge), expires_at)
+            .await?
+        {
+            return Err(AuthError::Verification(
+                "Proof of work challenge was already used".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: NonceStore + Sync> CaptchaVerifier for ProofOfWork<S> {
+    async fn verify(
+        &self,
+        response: &str,
+        _remote_ip: Option<&str>,
+    ) -> Result<CaptchaOutcome, AuthError> {
+        let (challenge, solution) = response
+            .rsplit_once(':')
+            .ok_or_else(|| AuthError::Verification("Proof of work is invalid".to_string()))?;
+        ProofOfWork::verify(self, challenge, solution).await?;
+
+        Ok(CaptchaOutcome {
+            hostname: None,
+            action: None,
+            score: None,
+            challenge_ts: None,
+        })
+    }
+}
+
+/// Find a solution to `challenge` by brute force, as a client would
+///
+/// Meant for Rust clients and tests; browsers do the same search in JavaScript. Fails with
+/// `AuthError::Malformed` if the challenge does not name a difficulty up to `MAX_DIFFICULTY`.
+pub fn solve(challenge: &str) -> Result<String, AuthError> {
+    let difficulty: u8 = challenge
+        .split('.')
+        .next()
+        .and_then(|difficulty| difficulty.parse().ok())
+        .filter(|difficulty| *difficulty <= MAX_DIFFICULTY)
+        .ok_or_else(|| AuthError::Malformed("Not a proof of work challenge".to_string()))?;
+
+    let mut counter: u64 = 0;
+    loop {
+        let solution = counter.to_string();
+        if leading_zeros(challenge, &solution) >= u32::from(difficulty) {
+            return Ok(solution);
+        }
+        counter += 1;
+    }
+}
+
+fn check_difficulty(difficulty: u8) -> Result<(), AuthError> {
+    if difficulty > MAX_DIFFICULTY {
+        return Err(AuthError::InvalidInput(format!(
+            "Proof of work difficulty must be at most {}",
+            MAX_DIFFICULTY
+        )));
+    }
+
+    Ok(())
+}
+
+/// Binds the signature to proof of work, so other HMAC signatures made with the keys do not pass
+fn signed_message(unsigned: &str) -> Vec<u8> {
+    format!("pow:{}", unsigned).into_bytes()
+}
+
+fn leading_zeros(challenge: &str, solution: &str) -> u32 {
+    let digest = Sha256::new()
+        .chain_update(challenge.as_bytes())
+        .chain_update(b":")
+        .chain_update(solution.as_bytes())
+        .finalize();
+
+    let mut zeros = 0;
+    for byte in digest {
+        zeros += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+
+    zeros
+}
@@ -0,0 +1,328 @@
+use std::{
+    collections::HashSet,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditSink, NoAudit},
+    token::now,
+    AuthError,
+};
+
+/// How far back failures are correlated
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Failures from one IP or on one account before a CAPTCHA is asked for
+pub const DEFAULT_CAPTCHA_AFTER: usize = 3;
+
+/// Distinct accounts one IP may fail on before it counts as credential stuffing
+pub const DEFAULT_STUFFING_ACCOUNTS: usize = 10;
+
+/// Failures on one account, from any number of IPs, before it counts as a targeted attack
+pub const DEFAULT_TARGETED_FAILURES: usize = 10;
+
+/// One failed sign in or MFA attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    /// The account the attempt was for; unknown identifiers count too
+    pub account: String,
+    pub ip: String,
+    /// Unix timestamp in seconds
+    pub at: u64,
+}
+
+/// Keeps recent failures so they can be looked up by IP and by account
+///
+/// Failures older than the `retain` passed to `record` may be dropped. SQL stores can index
+/// a table on `(ip, at)` and `(account, at)`; Redis stores can use one sorted set per key.
+pub trait FailureStore {
+    fn record(
+        &self,
+        failure: Failure,
+        retain: Duration,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Failures from `ip` at or after the Unix timestamp `since`
+    fn by_ip(
+        &self,
+        ip: &str,
+        since: u64,
+    ) -> impl Future<Output = Result<Vec<Failure>, AuthError>> + Send;
+
+    /// Failures on `account` at or after the Unix timestamp `since`
+    fn by_account(
+        &self,
+        account: &str,
+        since: u64,
+    ) -> impl Future<Output = Result<Vec<Failure>, AuthError>> + Send;
+}
+
+impl<T: FailureStore> FailureStore for Arc<T> {
+    fn record(
+        &self,
+        failure: Failure,
+        retain: Duration,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send {
+        (**self).record(failure, retain)
+    }
+
+    fn by_ip(
+        &self,
+        ip: &str,
+        since: u64,
+    ) -> impl Future<Output = Result<Vec<Failure>, AuthError>> + Send {
+        (**self).by_ip(ip, since)
+    }
+
+    fn by_account(
+        &self,
+        account: &str,
+        since: u64,
+    ) -> impl Future<Output = Result<Vec<Failure>, AuthError>> + Send {
+        (**self).by_account(account, since)
+    }
+}
+
+/// What recent failures look like
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Classification {
+    Normal,
+    /// More failures than a user mistyping would make
+    Suspicious,
+    /// One account failing again and again, possibly from many IPs
+    TargetedAttack,
+    /// One IP trying leaked credentials against many accounts
+    CredentialStuffing,
+}
+
+/// What the application should do next, from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Response {
+    Allow,
+    /// Ask for a CAPTCHA before the next attempt from this IP or on this account
+    RequireCaptcha,
+    /// Lock the account, e.g. with `Lockout::hard_lock_after` or by disabling it
+    Lock,
+    /// Block the IP and alert an operator; locking accounts would only punish the victims
+    Alert,
+}
+
+/// The verdict on a failure, with the counts it was based on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detection {
+    pub classification: Classification,
+    pub response: Response,
+    /// Failures from the IP in the window
+    pub ip_failures: usize,
+    /// Distinct accounts the IP failed on in the window
+    pub ip_accounts: usize,
+    /// Failures on the account in the window
+    pub account_failures: usize,
+    /// Distinct IPs the account failed from in the window
+    pub account_ips: usize,
+}
+
+/// Correlates failed attempts across IPs, accounts and time to tell attacks from typos
+///
+/// Each failure is compared with the others in the last `window`. An IP failing on
+/// `stuffing_accounts` distinct accounts is credential stuffing and should be blocked; an account
+/// failing `targeted_failures` times is under targeted attack and should be locked, however many
+/// IPs the attempts come from. Below that, `captcha_after` failures from an IP or on an account
+/// ask for a CAPTCHA. Attacks are recorded as `AuditAction::AttackDetected` to the audit sink.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::rate_limit::detection::{
+///     Classification, Detector, MemoryFailureStore, Response,
+/// };
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let detector = Detector::new(MemoryFailureStore::default()).stuffing_accounts(5);
+///
+///     let first = detector.record_failure("alice", "198.51.100.7").await?;
+///     assert_eq!(first.response, Response::Allow);
+///
+///     let mut last = first;
+///     for account in ["bob", "carol", "dave", "erin"] {
+///         last = detector.record_failure(account, "198.51.100.7").await?;
+///     }
+///
+///     assert_eq!(last.classification, Classification::CredentialStuffing);
+///     assert_eq!(last.response, Response::Alert);
+///
+///     // The same accounts are fine from a different IP
+///     let other = detector.record_failure("frank", "203.0.113.9").await?;
+///     assert_eq!(other.classification, Classification::Normal);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Detector<S, A = NoAudit> {
+    store: S,
+    window: Duration,
+    captcha_after: usize,
+    stuffing_accounts: usize,
+    targeted_failures: usize,
+    audit: A,
+}
+
+impl<S: FailureStore> Detector<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            window: DEFAULT_WINDOW,
+            captcha_after: DEFAULT_CAPTCHA_AFTER,
+            stuffing_accounts: DEFAULT_STUFFING_ACCOUNTS,
+            targeted_failures: DEFAULT_TARGETED_FAILURES,
+            audit: NoAudit,
+        }
+    }
+}
+
+impl<S: FailureStore, A: AuditSink> Detector<S, A> {
+    /// Record detected attacks to `audit`
+    pub fn audit<B: AuditSink>(self, audit: B) -> Detector<S, B> {
+        Detector {
+            store: self.store,
+            window: self.window,
+            captcha_after: self.captcha_after,
+            stuffing_accounts: self.stuffing_accounts,
+            targeted_failures: self.targeted_failures,
+            audit,
+        }
+    }
+
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn captcha_after(mut self, failures: usize) -> Self {
+        self.captcha_after = failures;
+        self
+    }
+
+    pub fn stuffing_accounts(mut self, accounts: usize) -> Self {
+        self.stuffing_accounts = accounts;
+        self
+    }
+
+    pub fn targeted_failures(mut self, failures: usize) -> Self {
+        self.targeted_failures = failures;
+        self
+    }
+
+    /// Record a failed attempt and classify the activity around it
+    pub async fn record_failure(&self, account: &str, ip: &str) -> Result<Detection, AuthError> {
+        let now = now()?;
+        let failure = Failure {
+            account: account.to_string(),
+            ip: ip.to_string(),
+            at: now,
+        };
+        self.store.record(failure, self.window).await?;
+
+        let detection = self.assess(account, ip, now).await?;
+
+        let kind = match detection.classification {
+            Classification::CredentialStuffing => "credential stuffing",
+            Classification::TargetedAttack => "targeted attack",
+            _ => return Ok(detection),
+        };
+        let event = AuditEvent::new(AuditAction::AttackDetected)?
+            .account(account)
+            .detail(format!("{} from {}", kind, ip));
+        self.audit.record(event).await?;
+
+        Ok(detection)
+    }
+
+    /// Classify the activity around an account and IP without recording anything, e.g. to ask
+    /// for a CAPTCHA before the attempt
+    pub async fn check(&self, account: &str, ip: &str) -> Result<Detection, AuthError> {
+        self.assess(account, ip, now()?).await
+    }
+
+    async fn assess(&self, account: &str, ip: &str, now: u64) -> Result<Detection, AuthError> {
+        let since = now.saturating_sub(self.window.as_secs());
+        let from_ip = self.store.by_ip(ip, since).await?;
+        let on_account = self.store.by_account(account, since).await?;
+
+        let ip_accounts = distinct(from_ip.iter().map(|failure| &failure.account));
+        let account_ips = distinct(on_account.iter().map(|failure| &failure.ip));
+
+        let (classification, response) = if ip_accounts >= self.stuffing_accounts {
+            (Classification::CredentialStuffing, Response::Alert)
+        } else if on_account.len() >= self.targeted_failures {
+            (Classification::TargetedAttack, Response::Lock)
+        } else if from_ip.len().max(on_account.len()) >= self.captcha_after {
+            (Classification::Suspicious, Response::RequireCaptcha)
+        } else {
+            (Classification::Normal, Response::Allow)
+        };
+
+        Ok(Detection {
+            classification,
+            response,
+            ip_failures: from_ip.len(),
+            ip_accounts,
+            account_failures: on_account.len(),
+            account_ips,
+        })
+    }
+}
+
+fn distinct<'a>(values: impl Iterator<Item = &'a String>) -> usize {
+    values.collect::<HashSet<_>>().len()
+}
+
+/// Keeps recent failures in process memory
+///
+/// Suitable for a single instance; deployments with several instances need a shared store.
+#[derive(Debug, Default)]
+pub struct MemoryFailureStore {
+    failures: Mutex<Vec<Failure>>,
+}
+
+impl MemoryFailureStore {
+    fn matching(
+        &self,
+        since: u64,
+        filter: impl Fn(&Failure) -> bool,
+    ) -> Result<Vec<Failure>, AuthError> {
+        Ok(self
+            .failures
+            .lock()
+            .map_err(|_| AuthError::backend("Failure store lock poisoned"))?
+            .iter()
+            .filter(|failure| failure.at >= since && filter(failure))
+            .cloned()
+            .collect())
+    }
+}
+
+impl FailureStore for MemoryFailureStore {
+    async fn record(&self, failure: Failure, retain: Duration) -> Result<(), AuthError> {
+        let mut failures = self
+            .failures
+            .lock()
+            .map_err(|_| AuthError::backend("Failure store lock poisoned"))?;
+
+        let oldest = failure.at.saturating_sub(retain.as_secs());
+        failures.retain(|existing| existing.at >= oldest);
+        failures.push(failure);
+
+        Ok(())
+    }
+
+    async fn by_ip(&self, ip: &str, since: u64) -> Result<Vec<Failure>, AuthError> {
+        self.matching(since, |failure| failure.ip == ip)
+    }
+
+    async fn by_account(&self, account: &str, since: u64) -> Result<Vec<Failure>, AuthError> {
+        self.matching(since, |failure| failure.account == account)
+    }
+}
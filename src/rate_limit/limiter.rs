@@ -0,0 +1,270 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::{clock, AuthError};
+
+use super::RateLimited;
+
+/// How a `Limiter` decides whether a hit is allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Allows bursts of up to `capacity` hits, with one more becoming available every `refill`
+    TokenBucket { capacity: u32, refill: Duration },
+    /// Allows at most `limit` hits in any period of length `window`
+    SlidingWindow { limit: u32, window: Duration },
+}
+
+impl Strategy {
+    pub fn token_bucket(capacity: u32, refill: Duration) -> Self {
+        Self::TokenBucket { capacity, refill }
+    }
+
+    pub fn sliding_window(limit: u32, window: Duration) -> Self {
+        Self::SlidingWindow { limit, window }
+    }
+}
+
+/// The outcome of a hit, for `X-RateLimit-Remaining` and `Retry-After` style headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub allowed: bool,
+    /// Further hits that would be allowed right now
+    pub remaining: u32,
+    /// How long until the next hit is allowed; zero when `allowed`
+    pub retry_after: Duration,
+}
+
+/// Keeps the state behind a `Limiter`
+///
+/// `hit` must check and update the key's state in a single atomic operation, e.g. a Lua script in
+/// Redis, so concurrent requests cannot exceed the limit. Denied hits are not recorded. `now` is
+/// the time since the Unix epoch.
+pub trait LimitStore {
+    fn hit(
+        &self,
+        key: &str,
+        strategy: Strategy,
+        now: Duration,
+    ) -> impl Future<Output = Result<Decision, AuthError>> + Send;
+
+    /// Forget the key's state
+    fn reset(&self, key: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Limits hits per key, such as an IP address, account or email address
+///
+/// Fails with `AuthError::RateLimited` once a key is over its limit. Use separate key prefixes,
+/// e.g. `login:ip:` and `login:account:`, for limits that should not share state.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::rate_limit::{Limiter, MemoryLimitStore, Strategy};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     // Bursts of 3, then one sign in attempt every 20 seconds
+///     let limiter = Limiter::new(
+///         MemoryLimitStore::default(),
+///         Strategy::token_bucket(3, Duration::from_secs(20)),
+///     )?;
+///
+///     let decision = limiter.attempt("login:ip:203.0.113.7").await?;
+///     assert_eq!(decision.remaining, 2);
+///
+///     limiter.attempt("login:ip:203.0.113.7").await?;
+///     limiter.attempt("login:ip:203.0.113.7").await?;
+///
+///     match limiter.attempt("login:ip:203.0.113.7").await {
+///         Err(AuthError::RateLimited(limited)) => assert!(limited.retry_after.as_secs() <= 20),
+///         other => panic!("expected to be limited, got {:?}", other),
+///     }
+///
+///     // Other keys are unaffected
+///     limiter.attempt("login:ip:198.51.100.2").await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Limiter<S> {
+    store: S,
+    strategy: Strategy,
+}
+
+impl<S: LimitStore> Limiter<S> {
+    pub fn new(store: S, strategy: Strategy) -> Result<Self, AuthError> {
+        let period = match strategy {
+            Strategy::TokenBucket { refill, .. } => refill,
+            Strategy::SlidingWindow { window, .. } => window,
+        };
+
+        if period.as_millis() == 0 {
+            return Err(AuthError::InvalidInput(
+                "Rate limit periods must be at least a millisecond".to_string(),
+            ));
+        }
+
+        Ok(Self { store, strategy })
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Use up one hit for the key, failing with `RateLimited` once it is over the limit
+    pub async fn attempt(&self, key: &str) -> Result<Decision, AuthError> {
+        let now = clock::since_epoch()?;
+        let decision = self.store.hit(key, self.strategy, now).await?;
+
+        if !decision.allowed {
+            return Err(RateLimited {
+                retry_after: decision.retry_after,
+            }
+            .into());
+        }
+
+        Ok(decision)
+    }
+
+    /// Clear the key's state, typically after a successful verification
+    pub async fn reset(&self, key: &str) -> Result<(), AuthError> {
+        self.store.reset(key).await
+    }
+}
+
+enum State {
+    /// When the bucket will be full again, in milliseconds since the epoch
+    Bucket(u64),
+    /// Times of the hits in the window, oldest first
+    Window(VecDeque<u64>),
+}
+
+/// Keeps limiter state in process memory
+///
+/// Suitable for a single instance; deployments with several instances need a shared store.
+#[derive(Default)]
+pub struct MemoryLimitStore {
+    states: Mutex<HashMap<String, (State, u64)>>,
+}
+
+impl LimitStore for MemoryLimitStore {
+    async fn hit(
+        &self,
+        key: &str,
+        strategy: Strategy,
+        now: Duration,
+    ) -> Result<Decision, AuthError> {
+        let now = u64::try_from(now.as_millis())?;
+        let mut states = self
+            .states
+            .lock()
+            .map_err(|_| AuthError::backend("Limit store lock poisoned"))?;
+
+        states.retain(|_, (_, forget_at)| *forget_at > now);
+
+        match strategy {
+            Strategy::TokenBucket { capacity, refill } => {
+                let interval = u64::try_from(refill.as_millis())?;
+                let full_at = match states.get(key) {
+                    Some((State::Bucket(full_at), _)) => *full_at,
+                    _ => now,
+                };
+                let (decision, full_at) = token_bucket(capacity, interval, full_at, now);
+
+                if decision.allowed {
+                    states.insert(key.to_string(), (State::Bucket(full_at), full_at));
+                }
+
+                Ok(decision)
+            }
+            Strategy::SlidingWindow { limit, window } => {
+                let window = u64::try_from(window.as_millis())?;
+                let mut hits = match states.remove(key) {
+                    Some((State::Window(hits), _)) => hits,
+                    _ => VecDeque::new(),
+                };
+
+                while hits.front().is_some_and(|&hit| hit + window <= now) {
+                    hits.pop_front();
+                }
+
+                let count = u32::try_from(hits.len())?;
+                let decision = if count >= limit {
+                    let retry_after = hits.front().map_or(window, |&oldest| oldest + window - now);
+
+                    Decision {
+                        allowed: false,
+                        remaining: 0,
+                        retry_after: Duration::from_millis(retry_after),
+                    }
+                } else {
+                    hits.push_back(now);
+
+                    Decision {
+                        allowed: true,
+                        remaining: limit - count - 1,
+                        retry_after: Duration::ZERO,
+                    }
+                };
+
+                if let Some(&newest) = hits.back() {
+                    states.insert(key.to_string(), (State::Window(hits), newest + window));
+                }
+
+                Ok(decision)
+            }
+        }
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), AuthError> {
+        self.states
+            .lock()
+            .map_err(|_| AuthError::backend("Limit store lock poisoned"))?
+            .remove(key);
+
+        Ok(())
+    }
+}
+
+/// A token bucket tracked as the time it will next be full, returning the new time if allowed
+///
+/// Each hit pushes that time `interval` further out; a hit is allowed while the bucket would be
+/// full again within `capacity` intervals.
+fn token_bucket(capacity: u32, interval: u64, full_at: u64, now: u64) -> (Decision, u64) {
+    let burst = u64::from(capacity) * interval;
+    let full_at = full_at.max(now) + interval;
+
+    if full_at - now > burst {
+        let retry_after = Duration::from_millis(full_at - now - burst);
+
+        return (
+            Decision {
+                allowed: false,
+                remaining: 0,
+                retry_after,
+            },
+            full_at,
+        );
+    }
+
+    let remaining = (burst - (full_at - now)) / interval;
+
+    (
+        Decision {
+            allowed: true,
+            remaining: u32::try_from(remaining).unwrap_or(u32::MAX),
+            retry_after: Duration::ZERO,
+        },
+        full_at,
+    )
+}
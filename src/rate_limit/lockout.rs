@@ -0,0 +1,324 @@
+use std::{collections::HashMap, future::Future, sync::Mutex, time::Duration};
+
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditSink, NoAudit},
+    metrics,
+    token::now,
+    AuthError,
+};
+
+use super::RateLimited;
+
+/// Failures allowed before the first lockout
+pub const DEFAULT_FREE_ATTEMPTS: u32 = 5;
+
+/// The first lockout; doubles with each further failure
+pub const DEFAULT_BASE_DELAY: Duration = Duration::from_secs(30);
+
+/// Longest a single backoff lockout lasts
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// How long after the last failure the count is forgotten
+pub const DEFAULT_RESET_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Recent failures for one identity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Failures {
+    pub count: u32,
+    /// Unix timestamp in seconds
+    pub last_at: u64,
+}
+
+/// Counts failures per identity
+///
+/// `record_failure` must increment the count and set `last_at` in a single atomic operation, e.g.
+/// Redis `HINCRBY` in a `MULTI` or an upsert, and forget the failures `reset_after` the last one.
+pub trait LockoutStore {
+    fn record_failure(
+        &self,
+        key: &str,
+        now: u64,
+        reset_after: Duration,
+    ) -> impl Future<Output = Result<Failures, AuthError>> + Send;
+
+    fn get(&self, key: &str) -> impl Future<Output = Result<Option<Failures>, AuthError>> + Send;
+
+    fn clear(&self, key: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Where an identity stands, for showing "3 attempts left" or "try again at 10:42"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockoutStatus {
+    pub failures: u32,
+    /// Failures left before backoff or a hard lock begins
+    pub remaining_attempts: u32,
+    /// Unix timestamp in seconds when a backoff lockout ends
+    pub locked_until: Option<u64>,
+    /// Locked until `unlock` is called, e.g. by support or a recovery flow
+    pub hard_locked: bool,
+}
+
+impl LockoutStatus {
+    pub fn is_locked(&self, now: u64) -> bool {
+        self.hard_locked || self.locked_until.is_some_and(|until| until > now)
+    }
+}
+
+/// Locks out identities after repeated failed sign in or MFA attempts
+///
+/// Once `free_attempts` failures have been made the identity is locked for `base_delay`, doubling
+/// with each further failure up to `max_delay`. With `hard_lock_after` set, that many
+/// failures lock it until `unlock`. Failures are forgotten `reset_after` the last one, and a
+/// success clears them.
+///
+/// `check` fails with `AuthError::RateLimited` during a backoff and `AuthError::InvalidState` when
+/// hard locked. Key by account rather than IP so distributed guessing is still caught, and pair
+/// with an IP based `Limiter` so attackers cannot lock everyone out cheaply. With the `redis`
+/// feature, `RedisLimitStore` shares failures between instances. The failure that starts a
+/// lockout or a hard lock is recorded as `AuditAction::AccountLocked` to the audit sink.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::rate_limit::lockout::{Lockout, MemoryLockoutStore};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let lockout = Lockout::new(MemoryLockoutStore::default())
+///         .free_attempts(2)
+///         .base_delay(Duration::from_secs(60))
+///         .hard_lock_after(10);
+///
+///     lockout.check("SomeAccountName").await?;
+///     let status = lockout.record_failure("SomeAccountName").await?;
+///     assert_eq!(status.remaining_attempts, 1);
+///
+///     let status = lockout.record_failure("SomeAccountName").await?;
+///     assert_eq!(status.remaining_attempts, 0);
+///     assert!(status.locked_until.is_some());
+///
+///     match lockout.check("SomeAccountName").await {
+///         Err(AuthError::RateLimited(limited)) => assert!(limited.retry_after.as_secs() <= 60),
+///         other => panic!("expected a lockout, got {:?}", other),
+///     }
+///
+///     // A successful sign in after the lockout clears the failures
+///     lockout.record_success("SomeAccountName").await?;
+///     lockout.check("SomeAccountName").await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Lockout<S, A = NoAudit> {
+    store: S,
+    free_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    hard_lock_after: Option<u32>,
+    reset_after: Duration,
+    audit: A,
+}
+
+impl<S: LockoutStore> Lockout<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            free_attempts: DEFAULT_FREE_ATTEMPTS,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            hard_lock_after: None,
+            reset_after: DEFAULT_RESET_AFTER,
+            audit: NoAudit,
+        }
+    }
+}
+
+impl<S: LockoutStore, A: AuditSink> Lockout<S, A> {
+    /// Record lockouts to `audit`
+    pub fn audit<B: AuditSink>(self, audit: B) -> Lockout<S, B> {
+        Lockout {
+            store: self.store,
+            free_attempts: self.free_attempts,
+            base_delay: self.base_delay,
+            max_delay: self.max_delay,
+            hard_lock_after: self.hard_lock_after,
+            reset_after: self.reset_after,
+            audit,
+        }
+    }
+
+    pub fn free_attempts(mut self, free_attempts: u32) -> Self {
+        self.free_attempts = free_attempts;
+        self
+    }
+
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Lock until `unlock` after this many failures instead of backing off forever
+    pub fn hard_lock_after(mut self, failures: u32) -> Self {
+        self.hard_lock_after = Some(failures);
+        self
+    }
+
+    pub fn reset_after(mut self, reset_after: Duration) -> Self {
+        self.reset_after = reset_after;
+        self
+    }
+
+    pub async fn status(&self, key: &str) -> Result<LockoutStatus, AuthError> {
+        let failures = self.store.get(key).await?.unwrap_or(Failures {
+            count: 0,
+            last_at: 0,
+        });
+
+        Ok(self.evaluate(failures))
+    }
+
+    /// Fail if the identity is locked out; call before checking credentials
+    pub async fn check(&self, key: &str) -> Result<LockoutStatus, AuthError> {
+        let status = self.status(key).await?;
+        let now = now()?;
+
+        if status.hard_locked {
+            return Err(AuthError::InvalidState(
+                "Account is locked after too many failed attempts".to_string(),
+            ));
+        }
+
+        match status.locked_until {
+            Some(until) if until > now => Err(RateLimited {
+                retry_after: Duration::from_secs(until - now),
+            }
+            .into()),
+            _ => Ok(status),
+        }
+    }
+
+    pub async fn record_failure(&self, key: &str) -> Result<LockoutStatus, AuthError> {
+        let failures = self
+            .store
+            .record_failure(key, now()?, self.reset_after)
+            .await?;
+        let status = self.evaluate(failures);
+
+        let (kind, detail) = if status.hard_locked && self.hard_lock_after == Some(failures.count) {
+            ("hard_lock", "hard locked".to_string())
+        } else if failures.count == self.free_attempts && !status.hard_locked {
+            let delay = self.base_delay.min(self.max_delay).as_secs();
+            ("lockout", format!("locked for {} seconds", delay))
+        } else {
+            return Ok(status);
+        };
+        metrics::lockout(kind);
+        let event = AuditEvent::new(AuditAction::AccountLocked)?
+            .account(key)
+            .detail(detail);
+        self.audit.record(event).await?;
+
+        Ok(status)
+    }
+
+    pub async fn record_success(&self, key: &str) -> Result<(), AuthError> {
+        self.store.clear(key).await
+    }
+
+    /// Lift a lockout, including a hard lock
+    pub async fn unlock(&self, key: &str) -> Result<(), AuthError> {
+        self.store.clear(key).await
+    }
+
+    fn evaluate(&self, failures: Failures) -> LockoutStatus {
+        let hard_locked = self
+            .hard_lock_after
+            .is_some_and(|limit| failures.count >= limit);
+        let lock_at = match self.hard_lock_after {
+            Some(limit) => limit.min(self.free_attempts),
+            None => self.free_attempts,
+        };
+
+        let locked_until = (failures.count >= self.free_attempts && !hard_locked).then(|| {
+            let doublings = (failures.count - self.free_attempts).min(31);
+            let delay = self
+                .base_delay
+                .saturating_mul(1 << doublings)
+                .min(self.max_delay);
+
+            failures.last_at + delay.as_secs()
+        });
+
+        LockoutStatus {
+            failures: failures.count,
+            remaining_attempts: lock_at.saturating_sub(failures.count),
+            locked_until,
+            hard_locked,
+        }
+    }
+}
+
+/// Keeps failure counts in process memory
+///
+/// Suitable for a single instance; deployments with several instances need a shared store.
+#[derive(Debug, Default)]
+pub struct MemoryLockoutStore {
+    failures: Mutex<HashMap<String, (Failures, u64)>>,
+}
+
+impl LockoutStore for MemoryLockoutStore {
+    async fn record_failure(
+        &self,
+        key: &str,
+        now: u64,
+        reset_after: Duration,
+    ) -> Result<Failures, AuthError> {
+        let mut failures = self
+            .failures
+            .lock()
+            .map_err(|_| AuthError::backend("Lockout store lock poisoned"))?;
+
+        failures.retain(|_, (_, forget_at)| *forget_at > now);
+
+        let (entry, forget_at) = failures.entry(key.to_string()).or_insert((
+            Failures {
+                count: 0,
+                last_at: now,
+            },
+            now,
+        ));
+        entry.count += 1;
+        entry.last_at = now;
+        *forget_at = now + reset_after.as_secs();
+
+        Ok(*entry)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Failures>, AuthError> {
+        let now = now()?;
+
+        Ok(self
+            .failures
+            .lock()
+            .map_err(|_| AuthError::backend("Lockout store lock poisoned"))?
+            .get(key)
+            .filter(|(_, forget_at)| *forget_at > now)
+            .map(|(failures, _)| *failures))
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AuthError> {
+        self.failures
+            .lock()
+            .map_err(|_| AuthError::backend("Lockout store lock poisoned"))?
+            .remove(key);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+pub mod detection;
+pub mod lockout;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod reputation;
+pub mod risk;
+
+mod limiter;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::AuthError;
+
+pub use limiter::{Decision, LimitStore, Limiter, MemoryLimitStore, Strategy};
+
+/// Counts attempts per key over a fixed window
+///
+/// This maps directly onto Redis `INCR` + `PEXPIRE`, or an upsert on a row with an expiry column.
+pub trait AttemptStore {
+    /// Add one attempt for the key, starting a new window of length `window` if none is active.
+    /// Returns the attempts made in the current window and how long until it resets.
+    fn increment(
+        &self,
+        key: &str,
+        window: Duration,
+    ) -> impl Future<Output = Result<(u32, Duration), AuthError>> + Send;
+
+    /// Forget every attempt recorded for the key
+    fn reset(&self, key: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Returned when a key has used up its attempts for the current window
+///
+/// Surfaces to callers as `AuthError::RateLimited`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited {
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Too many attempts, retry in {} seconds",
+            self.retry_after.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Uses up attempts per key, so callers such as `mfa::verify_limited` can take any limiter
+pub trait Throttle {
+    /// Use up one attempt for the key, failing with `AuthError::RateLimited` once none are left
+    fn attempt(&self, key: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Clear the attempts for the key
+    fn reset(&self, key: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Allows at most `max_attempts` per key within each `cooldown` period
+///
+/// A fixed window; `Limiter` offers token bucket and sliding window strategies that smooth out
+/// bursts at window boundaries.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::rate_limit::{MemoryAttemptStore, RateLimiter};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let limiter = RateLimiter::new(MemoryAttemptStore::default(), 2, Duration::from_secs(300));
+///
+///     limiter.attempt("SomeAccountName").await?;
+///     limiter.attempt("SomeAccountName").await?;
+///
+///     assert!(limiter.attempt("SomeAccountName").await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RateLimiter<S> {
+    store: S,
+    max_attempts: u32,
+    cooldown: Duration,
+}
+
+impl<S: AttemptStore> RateLimiter<S> {
+    pub fn new(store: S, max_attempts: u32, cooldown: Duration) -> Self {
+        Self {
+            store,
+            max_attempts,
+            cooldown,
+        }
+    }
+
+    /// Use up one attempt for the key, failing with `RateLimited` once none are left
+    pub async fn attempt(&self, key: &str) -> Result<(), AuthError> {
+        let (count, retry_after) = self.store.increment(key, self.cooldown).await?;
+
+        if count > self.max_attempts {
+            return Err(RateLimited { retry_after }.into());
+        }
+
+        Ok(())
+    }
+
+    /// Clear the attempts for the key, typically after a successful verification
+    pub async fn reset(&self, key: &str) -> Result<(), AuthError> {
+        self.store.reset(key).await
+    }
+}
+
+impl<S: AttemptStore + Sync> Throttle for RateLimiter<S> {
+    async fn attempt(&self, key: &str) -> Result<(), AuthError> {
+        RateLimiter::attempt(self, key).await
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), AuthError> {
+        RateLimiter::reset(self, key).await
+    }
+}
+
+impl<S: LimitStore + Sync> Throttle for Limiter<S> {
+    async fn attempt(&self, key: &str) -> Result<(), AuthError> {
+        Limiter::attempt(self, key).await?;
+
+        Ok(())
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), AuthError> {
+        Limiter::reset(self, key).await
+    }
+}
+
+/// Keeps attempt counts in process memory
+///
+/// Suitable for a single instance; deployments with several instances need a shared store.
+#[derive(Debug, Default)]
+pub struct MemoryAttemptStore {
+    windows: Mutex<HashMap<String, (u32, Instant)>>,
+}
+
+impl AttemptStore for MemoryAttemptStore {
+    async fn increment(&self, key: &str, window: Duration) -> Result<(u32, Duration), AuthError> {
+        let now = Instant::now();
+        let mut windows = self
+            .windows
+            .lock()
+            .map_err(|_| AuthError::backend("Attempt store lock poisoned"))?;
+
+        windows.retain(|_, (_, resets_at)| *resets_at > now);
+
+        let (count, resets_at) = windows.entry(key.to_string()).or_insert((0, now + window));
+        *count += 1;
+
+        Ok((*count, *resets_at - now))
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), AuthError> {
+        self.windows
+            .lock()
+            .map_err(|_| AuthError::backend("Attempt store lock poisoned"))?
+            .remove(key);
+
+        Ok(())
+    }
+}
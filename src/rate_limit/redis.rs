@@ -0,0 +1,276 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+
+use crate::{
+    redis::{RedisClient, Reply},
+    AuthError,
+};
+
+use super::{
+    lockout::{Failures, LockoutStore},
+    AttemptStore, Decision, LimitStore, Strategy,
+};
+
+/// Prefix for rate limit keys unless another is set
+pub const DEFAULT_PREFIX: &str = "rate_limit:";
+
+/// A token bucket kept as the time it is next full; returns `{allowed, remaining, retry_after}`
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local now = tonumber(ARGV[1])
+local interval = tonumber(ARGV[2])
+local burst = tonumber(ARGV[3]) * interval
+local full_at = math.max(tonumber(redis.call("GET", KEYS[1]) or now), now) + interval
+if full_at - now > burst then
+    return {0, 0, full_at - now - burst}
+end
+redis.call("SET", KEYS[1], string.format("%.0f", full_at), "PX", string.format("%.0f", full_at - now))
+return {1, math.floor((burst - (full_at - now)) / interval), 0}
+"#;
+
+/// A sliding window log kept as a sorted set of hit times; returns `{allowed, remaining, retry_after}`
+const SLIDING_WINDOW_SCRIPT: &str = r#"
+local now = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local limit = tonumber(ARGV[3])
+redis.call("ZREMRANGEBYSCORE", KEYS[1], "-inf", string.format("%.0f", now - window))
+local count = redis.call("ZCARD", KEYS[1])
+if count >= limit then
+    local oldest = redis.call("ZRANGE", KEYS[1], 0, 0, "WITHSCORES")[2]
+    return {0, 0, oldest and tonumber(oldest) + window - now or window}
+end
+redis.call("ZADD", KEYS[1], ARGV[1], ARGV[4])
+redis.call("PEXPIRE", KEYS[1], ARGV[2])
+return {1, limit - count - 1, 0}
+"#;
+
+/// Counts an attempt in a fixed window; returns `{count, milliseconds until the window resets}`
+const FIXED_WINDOW_SCRIPT: &str = r#"
+local count = redis.call("INCR", KEYS[1])
+if count == 1 then
+    redis.call("PEXPIRE", KEYS[1], ARGV[1])
+end
+return {count, redis.call("PTTL", KEYS[1])}
+"#;
+
+/// Records a failure and pushes back when it is forgotten; returns `{count, last_at}`
+const FAILURE_SCRIPT: &str = r#"
+local count = redis.call("HINCRBY", KEYS[1], "count", 1)
+redis.call("HSET", KEYS[1], "last_at", ARGV[1])
+redis.call("EXPIRE", KEYS[1], ARGV[2])
+return {count, tonumber(ARGV[1])}
+"#;
+
+/// Rate limit state shared by every instance through Redis
+///
+/// Implements `LimitStore` for `Limiter`, `AttemptStore` for `RateLimiter` and `LockoutStore` for
+/// `Lockout`. Each update runs as a Lua script, which Redis executes atomically, and keys expire
+/// once their state no longer matters. Give each limiter its own key prefix, or keys, so their
+/// state does not collide.
+///
+/// ### Example
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::rate_limit::{redis::RedisLimitStore, Limiter, Strategy};
+/// use lonewolf_auth_toolkit::redis::RedisClient;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let redis = RedisClient::connect("127.0.0.1:6379").await?;
+///     let limiter = Limiter::new(
+///         RedisLimitStore::new(redis).prefix("myapp:login:"),
+///         Strategy::sliding_window(10, Duration::from_secs(60)),
+///     )?;
+///
+///     limiter.attempt("203.0.113.7").await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RedisLimitStore<S = TcpStream> {
+    client: RedisClient<S>,
+    prefix: String,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> RedisLimitStore<S> {
+    pub fn new(client: RedisClient<S>) -> Self {
+        Self {
+            client,
+            prefix: DEFAULT_PREFIX.to_string(),
+        }
+    }
+
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn client(&self) -> &RedisClient<S> {
+        &self.client
+    }
+
+    fn key(&self, key: &str) -> Vec<u8> {
+        format!("{}{}", self.prefix, key).into_bytes()
+    }
+
+    async fn eval(&self, script: &str, key: &str, args: &[&[u8]]) -> Result<Vec<i64>, AuthError> {
+        let key = self.key(key);
+        let mut command: Vec<&[u8]> = vec![b"EVAL", script.as_bytes(), b"1", &key];
+        command.extend(args);
+
+        match self.client.query(&command).await? {
+            Reply::Array(Some(values)) => values
+                .into_iter()
+                .map(|value| match value {
+                    Reply::Integer(value) => Ok(value),
+                    _ => Err(AuthError::backend("Unexpected reply to rate limit script")),
+                })
+                .collect(),
+            _ => Err(AuthError::backend("Unexpected reply to rate limit script")),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), AuthError> {
+        self.client.query(&[b"DEL", &self.key(key)]).await?;
+
+        Ok(())
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> LimitStore for RedisLimitStore<S> {
+    async fn hit(
+        &self,
+        key: &str,
+        strategy: Strategy,
+        now: Duration,
+    ) -> Result<Decision, AuthError> {
+        let now = now.as_millis().to_string();
+
+        let values = match strategy {
+            Strategy::TokenBucket { capacity, refill } => {
+                let interval = refill.as_millis().to_string();
+                let capacity = capacity.to_string();
+
+                self.eval(
+                    TOKEN_BUCKET_SCRIPT,
+                    key,
+                    &[now.as_bytes(), interval.as_bytes(), capacity.as_bytes()],
+                )
+                .await?
+            }
+            Strategy::SlidingWindow { limit, window } => {
+                let window = window.as_millis().to_string();
+                let limit = limit.to_string();
+                // Hits in the same millisecond need distinct members
+                let member = format!("{}-{:016x}", now, rand::random::<u64>());
+
+                self.eval(
+                    SLIDING_WINDOW_SCRIPT,
+                    key,
+                    &[
+                        now.as_bytes(),
+                        window.as_bytes(),
+                        limit.as_bytes(),
+                        member.as_bytes(),
+                    ],
+                )
+                .await?
+            }
+        };
+
+        match values[..] {
+            [allowed, remaining, retry_after] => Ok(Decision {
+                allowed: allowed == 1,
+                remaining: u32::try_from(remaining)?,
+                retry_after: Duration::from_millis(u64::try_from(retry_after)?),
+            }),
+            _ => Err(AuthError::backend("Unexpected reply to rate limit script")),
+        }
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), AuthError> {
+        self.delete(key).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> AttemptStore for RedisLimitStore<S> {
+    async fn increment(&self, key: &str, window: Duration) -> Result<(u32, Duration), AuthError> {
+        let window = window.as_millis().to_string();
+
+        match self
+            .eval(FIXED_WINDOW_SCRIPT, key, &[window.as_bytes()])
+            .await?[..]
+        {
+            [count, resets_in] => Ok((
+                u32::try_from(count)?,
+                Duration::from_millis(u64::try_from(resets_in.max(0))?),
+            )),
+            _ => Err(AuthError::backend("Unexpected reply to rate limit script")),
+        }
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), AuthError> {
+        self.delete(key).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> LockoutStore for RedisLimitStore<S> {
+    async fn record_failure(
+        &self,
+        key: &str,
+        now: u64,
+        reset_after: Duration,
+    ) -> Result<Failures, AuthError> {
+        let now = now.to_string();
+        let reset_after = reset_after.as_secs().max(1).to_string();
+
+        match self
+            .eval(
+                FAILURE_SCRIPT,
+                key,
+                &[now.as_bytes(), reset_after.as_bytes()],
+            )
+            .await?[..]
+        {
+            [count, last_at] => Ok(Failures {
+                count: u32::try_from(count)?,
+                last_at: u64::try_from(last_at)?,
+            }),
+            _ => Err(AuthError::backend("Unexpected reply to lockout script")),
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Failures>, AuthError> {
+        let reply = self
+            .client
+            .query(&[b"HMGET", &self.key(key), b"count", b"last_at"])
+            .await?;
+
+        let field = |value: &Reply| match value {
+            Reply::Bulk(Some(value)) => Ok(Some(String::from_utf8(value.clone())?.parse()?)),
+            Reply::Bulk(None) => Ok(None),
+            _ => Err(AuthError::backend("Unexpected reply to HMGET")),
+        };
+
+        match reply {
+            Reply::Array(Some(values)) if values.len() == 2 => {
+                match (field(&values[0])?, field(&values[1])?) {
+                    (Some(count), Some(last_at)) => Ok(Some(Failures {
+                        count: u32::try_from(count)?,
+                        last_at,
+                    })),
+                    _ => Ok(None),
+                }
+            }
+            _ => Err(AuthError::backend("Unexpected reply to HMGET")),
+        }
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AuthError> {
+        self.delete(key).await
+    }
+}
@@ -0,0 +1,348 @@
+use std::{future::Future, net::IpAddr, time::Duration};
+
+use crate::{token::now, AuthError};
+
+use super::{
+    detection::{Failure, FailureStore, DEFAULT_CAPTCHA_AFTER, DEFAULT_STUFFING_ACCOUNTS},
+    risk::{Assessment, Login, RiskSignal, Verdict},
+};
+
+/// How far back `LocalReputation` counts an IP's failures
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Feed scores from which traffic is challenged
+pub const DEFAULT_CHALLENGE_SCORE: u8 = 25;
+
+/// Feed scores from which traffic is blocked
+pub const DEFAULT_BLOCK_SCORE: u8 = 75;
+
+/// Judges traffic by the IP it comes from, before a password or MFA code is even checked
+///
+/// `Verdict::StepUp` means challenge the request, e.g. with a CAPTCHA or a second factor, and
+/// `Verdict::Deny` means block it. Consult it centrally from every sign in and MFA endpoint with
+/// `screen`, or add `LocalReputation` and `FeedReputation` to a `risk::RiskEngine` as signals.
+/// Combine several as a tuple to take the most severe verdict.
+pub trait IpReputation {
+    fn check(&self, ip: &str) -> impl Future<Output = Result<Assessment, AuthError>> + Send;
+
+    /// Fail with `AuthError::InvalidState` if `ip` is blocked, otherwise return the assessment
+    fn screen(&self, ip: &str) -> impl Future<Output = Result<Assessment, AuthError>> + Send
+    where
+        Self: Sync,
+    {
+        async move {
+            let assessment = self.check(ip).await?;
+            if assessment.verdict == Verdict::Deny {
+                return Err(AuthError::InvalidState(
+                    "Requests from this address are blocked".to_string(),
+                ));
+            }
+
+            Ok(assessment)
+        }
+    }
+}
+
+impl<A: IpReputation + Sync, B: IpReputation + Sync> IpReputation for (A, B) {
+    async fn check(&self, ip: &str) -> Result<Assessment, AuthError> {
+        let first = self.0.check(ip).await?;
+        let second = self.1.check(ip).await?;
+
+        Ok(first.merge(second))
+    }
+}
+
+/// A single address or a CIDR block such as `203.0.113.0/24` or `2001:db8::/32`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    /// Fails with `AuthError::InvalidInput` for anything but an address or CIDR block
+    pub fn parse(range: &str) -> Result<Self, AuthError> {
+        let invalid = || AuthError::InvalidInput(format!("Invalid IP range {}", range));
+
+        let (address, prefix) = match range.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (range.trim(), None),
+        };
+        let network: IpAddr = address.parse().map_err(|_| invalid())?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+
+        Ok(Self { network, prefix })
+    }
+
+    /// IPv4 addresses mapped into IPv6 match IPv4 ranges
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Judges IPs from this deployment's own observations: fixed allow and deny lists, plus recent
+/// failures
+///
+/// An IP failing on `block_accounts` distinct accounts within the window is credential stuffing
+/// and is blocked; one with `challenge_after` failures is challenged. Allow listed ranges, such
+/// as an office behind one NAT address, skip the heuristics. Share the `FailureStore` with a
+/// `detection::Detector`, e.g. through an `Arc`, or record failures with `record_failure`.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::rate_limit::detection::MemoryFailureStore;
+/// use lonewolf_auth_toolkit::rate_limit::reputation::{IpReputation, LocalReputation};
+/// use lonewolf_auth_toolkit::rate_limit::risk::Verdict;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let reputation = LocalReputation::new(Arc::new(MemoryFailureStore::default()))
+///         .deny("192.0.2.0/24")?
+///         .block_accounts(3);
+///
+///     assert!(reputation.screen("192.0.2.44").await.is_err());
+///     assert_eq!(reputation.check("198.51.100.7").await?.verdict, Verdict::Allow);
+///
+///     for account in ["alice", "bob", "carol"] {
+///         reputation.record_failure(account, "198.51.100.7").await?;
+///     }
+///     assert_eq!(reputation.check("198.51.100.7").await?.verdict, Verdict::Deny);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct LocalReputation<S> {
+    store: S,
+    allow: Vec<IpRange>,
+    deny: Vec<IpRange>,
+    window: Duration,
+    challenge_after: usize,
+    block_accounts: usize,
+}
+
+impl<S: FailureStore + Sync> LocalReputation<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            allow: Vec::new(),
+            deny: Vec::new(),
+            window: DEFAULT_WINDOW,
+            challenge_after: DEFAULT_CAPTCHA_AFTER,
+            block_accounts: DEFAULT_STUFFING_ACCOUNTS,
+        }
+    }
+
+    /// Never challenge or block addresses in `range`, unless they are also deny listed
+    pub fn allow(mut self, range: &str) -> Result<Self, AuthError> {
+        self.allow.push(IpRange::parse(range)?);
+        Ok(self)
+    }
+
+    /// Always block addresses in `range`
+    pub fn deny(mut self, range: &str) -> Result<Self, AuthError> {
+        self.deny.push(IpRange::parse(range)?);
+        Ok(self)
+    }
+
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Failures from one IP within the window before its requests are challenged
+    pub fn challenge_after(mut self, failures: usize) -> Self {
+        self.challenge_after = failures;
+        self
+    }
+
+    /// Distinct accounts one IP may fail on within the window before it is blocked
+    pub fn block_accounts(mut self, accounts: usize) -> Self {
+        self.block_accounts = accounts;
+        self
+    }
+
+    /// Count a failed sign in or MFA attempt against the IP
+    pub async fn record_failure(&self, account: &str, ip: &str) -> Result<(), AuthError> {
+        let failure = Failure {
+            account: account.to_string(),
+            ip: ip.to_string(),
+            at: now()?,
+        };
+
+        self.store.record(failure, self.window).await
+    }
+}
+
+impl<S: FailureStore + Sync> IpReputation for LocalReputation<S> {
+    async fn check(&self, ip: &str) -> Result<Assessment, AuthError> {
+        let address: IpAddr = ip
+            .trim()
+            .parse()
+            .map_err(|_| AuthError::InvalidInput(format!("Invalid IP address {}", ip)))?;
+
+        if self.deny.iter().any(|range| range.contains(address)) {
+            return Ok(Assessment::flag(Verdict::Deny, "deny listed address"));
+        }
+        if self.allow.iter().any(|range| range.contains(address)) {
+            return Ok(Assessment::default());
+        }
+
+        let since = now()?.saturating_sub(self.window.as_secs());
+        let failures = self.store.by_ip(ip, since).await?;
+        let mut accounts: Vec<&str> = failures
+            .iter()
+            .map(|failure| failure.account.as_str())
+            .collect();
+        accounts.sort_unstable();
+        accounts.dedup();
+
+        if accounts.len() >= self.block_accounts {
+            return Ok(Assessment::flag(
+                Verdict::Deny,
+                format!("failed on {} accounts", accounts.len()),
+            ));
+        }
+        if failures.len() >= self.challenge_after {
+            return Ok(Assessment::flag(
+                Verdict::StepUp,
+                format!("{} recent failures", failures.len()),
+            ));
+        }
+
+        Ok(Assessment::default())
+    }
+}
+
+impl<S: FailureStore + Sync> RiskSignal for LocalReputation<S> {
+    async fn assess(&self, login: &Login, _history: &[Login]) -> Result<Assessment, AuthError> {
+        self.check(&login.ip).await
+    }
+}
+
+/// Looks IPs up in an external threat intelligence feed, e.g. AbuseIPDB, Spamhaus or an
+/// internal list shared across services
+pub trait ReputationFeed {
+    /// How likely `ip` is abusive, from 0 to 100; `None` if the feed knows nothing about it
+    fn score(&self, ip: &str) -> impl Future<Output = Result<Option<u8>, AuthError>> + Send;
+}
+
+/// Challenges or blocks IPs by the score an external feed gives them
+///
+/// If the feed cannot be reached, requests are allowed so an outage does not lock every user
+/// out; `fail_open(false)` returns the feed's error instead.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::rate_limit::reputation::{
+///     FeedReputation, IpReputation, ReputationFeed,
+/// };
+/// use lonewolf_auth_toolkit::rate_limit::risk::Verdict;
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// struct Abuse;
+///
+/// impl ReputationFeed for Abuse {
+///     async fn score(&self, ip: &str) -> Result<Option<u8>, AuthError> {
+///         Ok(match ip {
+///             "203.0.113.9" => Some(90),
+///             "198.51.100.7" => Some(40),
+///             _ => None,
+///         })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let reputation = FeedReputation::new(Abuse);
+///
+///     assert_eq!(reputation.check("203.0.113.9").await?.verdict, Verdict::Deny);
+///     assert_eq!(reputation.check("198.51.100.7").await?.verdict, Verdict::StepUp);
+///     assert_eq!(reputation.check("192.0.2.1").await?.verdict, Verdict::Allow);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct FeedReputation<F> {
+    feed: F,
+    challenge_at: u8,
+    block_at: u8,
+    fail_open: bool,
+}
+
+impl<F: ReputationFeed + Sync> FeedReputation<F> {
+    pub fn new(feed: F) -> Self {
+        Self {
+            feed,
+            challenge_at: DEFAULT_CHALLENGE_SCORE,
+            block_at: DEFAULT_BLOCK_SCORE,
+            fail_open: true,
+        }
+    }
+
+    /// Scores from which requests are challenged
+    pub fn challenge_at(mut self, score: u8) -> Self {
+        self.challenge_at = score;
+        self
+    }
+
+    /// Scores from which requests are blocked
+    pub fn block_at(mut self, score: u8) -> Self {
+        self.block_at = score;
+        self
+    }
+
+    /// Allow requests when the feed fails, `true` by default
+    pub fn fail_open(mut self, fail_open: bool) -> Self {
+        self.fail_open = fail_open;
+        self
+    }
+}
+
+impl<F: ReputationFeed + Sync> IpReputation for FeedReputation<F> {
+    async fn check(&self, ip: &str) -> Result<Assessment, AuthError> {
+        let score = match self.feed.score(ip).await {
+            Ok(Some(score)) => score,
+            Ok(None) => return Ok(Assessment::default()),
+            Err(_) if self.fail_open => return Ok(Assessment::default()),
+            Err(error) => return Err(error),
+        };
+
+        let verdict = if score >= self.block_at {
+            Verdict::Deny
+        } else if score >= self.challenge_at {
+            Verdict::StepUp
+        } else {
+            return Ok(Assessment::default());
+        };
+
+        Ok(Assessment::flag(
+            verdict,
+            format!("reputation score {}", score),
+        ))
+    }
+}
+
+impl<F: ReputationFeed + Sync> RiskSignal for FeedReputation<F> {
+    async fn assess(&self, login: &Login, _history: &[Login]) -> Result<Assessment, AuthError> {
+        self.check(&login.ip).await
+    }
+}
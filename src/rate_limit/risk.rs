@@ -0,0 +1,410 @@
+use std::{collections::HashMap, future::Future, sync::Mutex};
+
+#[cfg(all(feature = "mfa", feature = "password"))]
+use crate::account::SignIn;
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditSink, NoAudit},
+    token::now,
+    AuthError,
+};
+
+/// Earlier sign ins kept and compared per account
+pub const DEFAULT_HISTORY: usize = 20;
+
+/// Faster than an airliner, so reaching the new location in time is implausible
+pub const DEFAULT_MAX_SPEED_KMH: f64 = 1000.0;
+
+/// Jumps shorter than this are put down to inaccurate IP geolocation
+pub const DEFAULT_MIN_DISTANCE_KM: f64 = 500.0;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Where a sign in came from, e.g. from a GeoIP lookup on its IP
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    /// Great circle distance in kilometres
+    pub fn distance_km(&self, other: &Location) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.longitude - self.longitude).to_radians();
+
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+/// One sign in, as passed to risk signals and kept in the history
+#[derive(Debug, Clone, PartialEq)]
+pub struct Login {
+    pub account: String,
+    pub ip: String,
+    pub user_agent: String,
+    /// A long lived device identifier, e.g. from a cookie; the user agent stands in without one
+    pub device: Option<String>,
+    pub location: Option<Location>,
+    /// Unix timestamp in seconds
+    pub at: u64,
+}
+
+impl Login {
+    /// A sign in happening now
+    pub fn new(account: &str, ip: &str, user_agent: &str) -> Result<Self, AuthError> {
+        Ok(Self {
+            account: account.to_string(),
+            ip: ip.to_string(),
+            user_agent: user_agent.to_string(),
+            device: None,
+            location: None,
+            at: now()?,
+        })
+    }
+
+    pub fn device(mut self, device: &str) -> Self {
+        self.device = Some(device.to_string());
+        self
+    }
+
+    pub fn location(mut self, location: Location) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    fn same_device(&self, other: &Login) -> bool {
+        match (&self.device, &other.device) {
+            (Some(device), Some(other)) => device == other,
+            _ => self.user_agent == other.user_agent,
+        }
+    }
+}
+
+/// What to do with a sign in, from least to most severe
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+    #[default]
+    Allow,
+    /// Ask for a second factor even if the user would not normally need one
+    StepUp,
+    /// Refuse the sign in and alert the user
+    Deny,
+}
+
+/// The verdict on a sign in, with the reasons behind it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Assessment {
+    pub verdict: Verdict,
+    pub reasons: Vec<String>,
+}
+
+impl Assessment {
+    pub fn flag(verdict: Verdict, reason: impl Into<String>) -> Self {
+        Self {
+            verdict,
+            reasons: vec![reason.into()],
+        }
+    }
+
+    /// Keep the more severe verdict and the reasons of both
+    pub fn merge(mut self, other: Assessment) -> Self {
+        self.verdict = self.verdict.max(other.verdict);
+        self.reasons.extend(other.reasons);
+        self
+    }
+
+    /// Apply the verdict to a successful password check
+    ///
+    /// `StepUp` sets `mfa_required`; users without TOTP can be sent an `mfa::email::EmailOtp`
+    /// code instead. Fails with `AuthError::InvalidState` on `Deny`.
+    #[cfg(all(feature = "mfa", feature = "password"))]
+    pub fn apply(&self, sign_in: &mut SignIn) -> Result<(), AuthError> {
+        match self.verdict {
+            Verdict::Allow => {}
+            Verdict::StepUp => sign_in.mfa_required = true,
+            Verdict::Deny => {
+                return Err(AuthError::InvalidState(
+                    "Sign in was blocked as unusual".to_string(),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A heuristic judging a sign in against the account's earlier ones
+///
+/// Signals that call out to a risk service can ignore `history`. Combine signals as a tuple to
+/// take the most severe verdict.
+pub trait RiskSignal {
+    /// `history` holds the account's earlier sign ins, most recent first
+    fn assess(
+        &self,
+        login: &Login,
+        history: &[Login],
+    ) -> impl Future<Output = Result<Assessment, AuthError>> + Send;
+}
+
+impl<A: RiskSignal + Sync, B: RiskSignal + Sync> RiskSignal for (A, B) {
+    async fn assess(&self, login: &Login, history: &[Login]) -> Result<Assessment, AuthError> {
+        let first = self.0.assess(login, history).await?;
+        let second = self.1.assess(login, history).await?;
+
+        Ok(first.merge(second))
+    }
+}
+
+/// Flags sign ins from a device the account has not signed in from before
+///
+/// An account's first sign in is allowed, as there is nothing to compare it with.
+#[derive(Debug, Clone, Copy)]
+pub struct NewDevice {
+    verdict: Verdict,
+}
+
+impl Default for NewDevice {
+    fn default() -> Self {
+        Self {
+            verdict: Verdict::StepUp,
+        }
+    }
+}
+
+impl NewDevice {
+    /// The verdict for a new device, `StepUp` by default
+    pub fn verdict(mut self, verdict: Verdict) -> Self {
+        self.verdict = verdict;
+        self
+    }
+}
+
+impl RiskSignal for NewDevice {
+    async fn assess(&self, login: &Login, history: &[Login]) -> Result<Assessment, AuthError> {
+        if history.is_empty() || history.iter().any(|earlier| earlier.same_device(login)) {
+            return Ok(Assessment::default());
+        }
+
+        Ok(Assessment::flag(self.verdict, "new device"))
+    }
+}
+
+/// Flags sign ins too far from the previous one to have travelled between them in time
+///
+/// Both sign ins need a location. Jumps under `min_distance_km` are ignored, so IP geolocation
+/// errors and VPNs near the user do not trip it.
+#[derive(Debug, Clone, Copy)]
+pub struct ImpossibleTravel {
+    max_speed_kmh: f64,
+    min_distance_km: f64,
+    verdict: Verdict,
+}
+
+impl Default for ImpossibleTravel {
+    fn default() -> Self {
+        Self {
+            max_speed_kmh: DEFAULT_MAX_SPEED_KMH,
+            min_distance_km: DEFAULT_MIN_DISTANCE_KM,
+            verdict: Verdict::StepUp,
+        }
+    }
+}
+
+impl ImpossibleTravel {
+    pub fn max_speed_kmh(mut self, speed: f64) -> Self {
+        self.max_speed_kmh = speed;
+        self
+    }
+
+    pub fn min_distance_km(mut self, distance: f64) -> Self {
+        self.min_distance_km = distance;
+        self
+    }
+
+    /// The verdict for impossible travel, `StepUp` by default
+    pub fn verdict(mut self, verdict: Verdict) -> Self {
+        self.verdict = verdict;
+        self
+    }
+}
+
+impl RiskSignal for ImpossibleTravel {
+    async fn assess(&self, login: &Login, history: &[Login]) -> Result<Assessment, AuthError> {
+        let Some(here) = login.location else {
+            return Ok(Assessment::default());
+        };
+        let Some((there, previous)) = history
+            .iter()
+            .find_map(|earlier| Some((earlier.location?, earlier)))
+        else {
+            return Ok(Assessment::default());
+        };
+
+        let distance = here.distance_km(&there);
+        if distance < self.min_distance_km {
+            return Ok(Assessment::default());
+        }
+
+        let hours = login.at.saturating_sub(previous.at) as f64 / 3600.0;
+        if hours > 0.0 && distance / hours <= self.max_speed_kmh {
+            return Ok(Assessment::default());
+        }
+
+        Ok(Assessment::flag(
+            self.verdict,
+            format!(
+                "impossible travel of {:.0} km from {}",
+                distance, previous.ip
+            ),
+        ))
+    }
+}
+
+/// Keeps each account's recent sign ins
+pub trait LoginHistory {
+    /// Add a sign in, keeping at most `keep` per account
+    fn record(
+        &self,
+        login: Login,
+        keep: usize,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Up to `limit` of the account's sign ins, most recent first
+    fn recent(
+        &self,
+        account: &str,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<Login>, AuthError>> + Send;
+}
+
+/// Runs risk signals on each sign in, against the account's earlier sign ins
+///
+/// Call `assess` once the password has been verified and `Assessment::apply` the result to the
+/// `SignIn`, so a `StepUp` verdict sends the user through MFA. Call `record` only once the sign
+/// in has fully succeeded, including any step up, so an attacker's device does not become a
+/// known one. Sign ins that are not allowed outright are recorded as `AuditAction::RiskySignIn`.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::rate_limit::risk::{
+///     ImpossibleTravel, Location, Login, MemoryLoginHistory, NewDevice, RiskEngine, Verdict,
+/// };
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let signals = (NewDevice::default(), ImpossibleTravel::default());
+///     let risk = RiskEngine::new(MemoryLoginHistory::default(), signals);
+///
+///     let london = Location { latitude: 51.5, longitude: -0.13 };
+///     let sydney = Location { latitude: -33.87, longitude: 151.21 };
+///
+///     let first = Login::new("alice", "198.51.100.7", "Firefox")?.location(london);
+///     assert_eq!(risk.assess(&first).await?.verdict, Verdict::Allow);
+///     risk.record(first).await?;
+///
+///     // Minutes later, on another browser on the other side of the world
+///     let second = Login::new("alice", "203.0.113.9", "Safari")?.location(sydney);
+///     let assessment = risk.assess(&second).await?;
+///     assert_eq!(assessment.verdict, Verdict::StepUp);
+///     assert_eq!(assessment.reasons.len(), 2);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RiskEngine<H, S, A = NoAudit> {
+    history: H,
+    signal: S,
+    keep: usize,
+    audit: A,
+}
+
+impl<H: LoginHistory, S: RiskSignal> RiskEngine<H, S> {
+    pub fn new(history: H, signal: S) -> Self {
+        Self {
+            history,
+            signal,
+            keep: DEFAULT_HISTORY,
+            audit: NoAudit,
+        }
+    }
+}
+
+impl<H: LoginHistory, S: RiskSignal, A: AuditSink> RiskEngine<H, S, A> {
+    /// Record risky sign ins to `audit`
+    pub fn audit<B: AuditSink>(self, audit: B) -> RiskEngine<H, S, B> {
+        RiskEngine {
+            history: self.history,
+            signal: self.signal,
+            keep: self.keep,
+            audit,
+        }
+    }
+
+    /// How many earlier sign ins to keep and compare per account
+    pub fn history(mut self, keep: usize) -> Self {
+        self.keep = keep;
+        self
+    }
+
+    /// Judge a sign in without recording it
+    pub async fn assess(&self, login: &Login) -> Result<Assessment, AuthError> {
+        let history = self.history.recent(&login.account, self.keep).await?;
+        let assessment = self.signal.assess(login, &history).await?;
+
+        if assessment.verdict != Verdict::Allow {
+            let event = AuditEvent::new(AuditAction::RiskySignIn)?
+                .account(&login.account)
+                .detail(format!(
+                    "{} from {}",
+                    assessment.reasons.join(", "),
+                    login.ip
+                ));
+            self.audit.record(event).await?;
+        }
+
+        Ok(assessment)
+    }
+
+    /// Remember a completed sign in for later comparisons
+    pub async fn record(&self, login: Login) -> Result<(), AuthError> {
+        self.history.record(login, self.keep).await
+    }
+}
+
+/// Keeps recent sign ins in process memory
+///
+/// Suitable for a single instance; deployments with several instances need a shared store.
+#[derive(Debug, Default)]
+pub struct MemoryLoginHistory {
+    logins: Mutex<HashMap<String, Vec<Login>>>,
+}
+
+impl LoginHistory for MemoryLoginHistory {
+    async fn record(&self, login: Login, keep: usize) -> Result<(), AuthError> {
+        let mut logins = self
+            .logins
+            .lock()
+            .map_err(|_| AuthError::backend("Login history lock poisoned"))?;
+
+        let account = logins.entry(login.account.clone()).or_default();
+        account.insert(0, login);
+        account.truncate(keep);
+
+        Ok(())
+    }
+
+    async fn recent(&self, account: &str, limit: usize) -> Result<Vec<Login>, AuthError> {
+        let logins = self
+            .logins
+            .lock()
+            .map_err(|_| AuthError::backend("Login history lock poisoned"))?;
+
+        Ok(logins
+            .get(account)
+            .map(|logins| logins.iter().take(limit).cloned().collect())
+            .unwrap_or_default())
+    }
+}
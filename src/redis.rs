@@ -0,0 +1,237 @@
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+};
+
+use crate::AuthError;
+
+/// A decoded RESP2 reply
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    Status(String),
+    Integer(i64),
+    /// `None` for a nil bulk string, e.g. `GET` on a missing key
+    Bulk(Option<Vec<u8>>),
+    /// `None` for a nil array, e.g. an aborted `EXEC`
+    Array(Option<Vec<Reply>>),
+}
+
+/// A minimal Redis client speaking RESP2 over a single connection, used by the Redis backed stores
+///
+/// Commands are serialised over the connection, which is enough for the short commands the stores
+/// issue. Any `AsyncRead + AsyncWrite` stream works, so a TLS stream can be passed to `new`. Error
+/// replies become `AuthError::Backend`.
+///
+/// A command that is cancelled or fails before all of its replies are read would leave those
+/// replies for the next caller, so the client is then poisoned and every later call fails; open a
+/// new client to recover.
+///
+/// ### Example
+/// ```rust,no_run
+/// use lonewolf_auth_toolkit::redis::{RedisClient, Reply};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let redis = RedisClient::connect("127.0.0.1:6379").await?;
+///     redis.auth(Some("default"), "SomePassword").await?;
+///
+///     redis.query(&[b"SET", b"greeting", b"hello"]).await?;
+///     assert_eq!(redis.query(&[b"GET", b"greeting"]).await?, Reply::Bulk(Some(b"hello".to_vec())));
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// Dropping a command partway, e.g. on a timeout, poisons the client
+/// ```rust
+/// use std::time::Duration;
+/// use lonewolf_auth_toolkit::redis::RedisClient;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     // A server that accepts commands but never replies
+///     let (stream, _server) = tokio::io::duplex(1024);
+///     let redis = RedisClient::new(stream);
+///
+///     let pending = redis.query(&[b"GET", b"session"]);
+///     assert!(tokio::time::timeout(Duration::from_millis(10), pending).await.is_err());
+///
+///     // The late reply to the first GET must never reach this one
+///     assert!(redis.query(&[b"GET", b"other-session"]).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RedisClient<S = TcpStream> {
+    connection: Mutex<Connection<S>>,
+}
+
+struct Connection<S> {
+    stream: BufStream<S>,
+    /// Set while a pipeline is in flight and left set if it never finishes
+    poisoned: bool,
+}
+
+impl RedisClient<TcpStream> {
+    pub async fn connect(address: impl ToSocketAddrs) -> Result<Self, AuthError> {
+        let stream = TcpStream::connect(address)
+            .await
+            .map_err(AuthError::backend)?;
+
+        Ok(Self::new(stream))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> RedisClient<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            connection: Mutex::new(Connection {
+                stream: BufStream::new(stream),
+                poisoned: false,
+            }),
+        }
+    }
+
+    /// Authenticate the connection; `username` requires Redis 6 ACLs
+    pub async fn auth(&self, username: Option<&str>, password: &str) -> Result<(), AuthError> {
+        match username {
+            Some(username) => {
+                self.query(&[b"AUTH", username.as_bytes(), password.as_bytes()])
+                    .await?
+            }
+            None => self.query(&[b"AUTH", password.as_bytes()]).await?,
+        };
+
+        Ok(())
+    }
+
+    /// Send one command and wait for its reply
+    pub async fn query(&self, command: &[&[u8]]) -> Result<Reply, AuthError> {
+        self.pipeline(&[command])
+            .await?
+            .pop()
+            .ok_or_else(|| AuthError::backend("Redis sent no reply"))
+    }
+
+    /// Send several commands in one write and read all of their replies
+    ///
+    /// Wrapping commands in `MULTI` ... `EXEC` makes them atomic; the transaction's replies are
+    /// then the array returned for `EXEC`.
+    pub async fn pipeline(&self, commands: &[&[&[u8]]]) -> Result<Vec<Reply>, AuthError> {
+        let mut buffer = Vec::new();
+        for command in commands {
+            encode(&mut buffer, command);
+        }
+
+        let mut connection = self.connection.lock().await;
+        if connection.poisoned {
+            return Err(AuthError::backend(
+                "Redis connection is out of sync after an unfinished command",
+            ));
+        }
+
+        // Stays set if this future is dropped or the connection breaks before every reply is read
+        connection.poisoned = true;
+        let stream = &mut connection.stream;
+        stream
+            .write_all(&buffer)
+            .await
+            .map_err(AuthError::backend)?;
+        stream.flush().await.map_err(AuthError::backend)?;
+
+        // Read every reply before reporting an error so the connection stays in sync
+        let mut replies = Vec::with_capacity(commands.len());
+        for _ in commands {
+            replies.push(read_reply(stream).await?);
+        }
+        connection.poisoned = false;
+
+        replies.into_iter().collect()
+    }
+}
+
+fn encode(buffer: &mut Vec<u8>, command: &[&[u8]]) {
+    buffer.extend_from_slice(format!("*{}\r\n", command.len()).as_bytes());
+
+    for argument in command {
+        buffer.extend_from_slice(format!("${}\r\n", argument.len()).as_bytes());
+        buffer.extend_from_slice(argument);
+        buffer.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Read one reply; the outer error is a broken connection, the inner one an error reply
+async fn read_reply<R: AsyncBufReadExt + Unpin + Send>(
+    reader: &mut R,
+) -> Result<Result<Reply, AuthError>, AuthError> {
+    let line = read_line(reader).await?;
+    let (kind, rest) = line
+        .split_first()
+        .ok_or_else(|| AuthError::backend("Empty Redis reply"))?;
+    let rest = String::from_utf8_lossy(rest).into_owned();
+
+    let reply = match kind {
+        b'+' => Reply::Status(rest),
+        b'-' => return Ok(Err(AuthError::backend(rest))),
+        b':' => Reply::Integer(parse_int(&rest)?),
+        b'$' => match parse_int(&rest)? {
+            -1 => Reply::Bulk(None),
+            len => {
+                let mut data = vec![0; usize::try_from(len)? + 2];
+                reader
+                    .read_exact(&mut data)
+                    .await
+                    .map_err(AuthError::backend)?;
+                data.truncate(data.len() - 2);
+
+                Reply::Bulk(Some(data))
+            }
+        },
+        b'*' => match parse_int(&rest)? {
+            -1 => Reply::Array(None),
+            len => {
+                // Keep reading after an error item so the rest of the array is consumed
+                let mut items = Vec::new();
+                let mut error = None;
+                for _ in 0..len {
+                    match Box::pin(read_reply(reader)).await? {
+                        Ok(item) => items.push(item),
+                        Err(item) => error = error.or(Some(item)),
+                    }
+                }
+
+                if let Some(error) = error {
+                    return Ok(Err(error));
+                }
+
+                Reply::Array(Some(items))
+            }
+        },
+        _ => return Err(AuthError::backend("Unknown Redis reply type")),
+    };
+
+    Ok(Ok(reply))
+}
+
+async fn read_line<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Result<Vec<u8>, AuthError> {
+    let mut line = Vec::new();
+    reader
+        .read_until(b'\n', &mut line)
+        .await
+        .map_err(AuthError::backend)?;
+
+    if !line.ends_with(b"\r\n") {
+        return Err(AuthError::backend("Redis connection closed"));
+    }
+
+    line.truncate(line.len() - 2);
+
+    Ok(line)
+}
+
+fn parse_int(value: &str) -> Result<i64, AuthError> {
+    value
+        .parse()
+        .map_err(|_| AuthError::backend("Malformed Redis reply"))
+}
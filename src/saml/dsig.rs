@@ -0,0 +1,206 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    digest::{self, SHA256, SHA512},
+    signature::{self, UnparsedPublicKey},
+};
+
+use crate::{crypto::ct_eq, AuthError};
+
+use super::xml::{canonicalize, Element};
+
+const DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+const EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+const ENVELOPED: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+
+/// DER encoding of the rsaEncryption OID, 1.2.840.113549.1.1.1
+const RSA_ENCRYPTION: &[u8] = &[
+    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01,
+];
+
+pub(crate) fn has_signature(element: &Element) -> bool {
+    element.child(DSIG, "Signature").is_some()
+}
+
+/// Verify the enveloped signature over `signed` against the IdP's RSA public keys
+///
+/// Only the profile SAML uses is accepted: one reference to the element's own `ID`, the
+/// enveloped signature and exclusive C14N transforms, and SHA-256 or SHA-512. The key always
+/// comes from configuration, never from `KeyInfo`.
+pub(crate) fn verify(signed: &Element, keys: &[Vec<u8>]) -> Result<(), AuthError> {
+    let mut signatures = signed.children_named(DSIG, "Signature");
+    let signature = signatures.next().ok_or_else(|| invalid("is missing"))?;
+    if signatures.next().is_some() {
+        return Err(invalid("appears more than once"));
+    }
+
+    let signed_info = signature
+        .child(DSIG, "SignedInfo")
+        .ok_or_else(|| invalid("has no SignedInfo"))?;
+
+    let method = signed_info
+        .child(DSIG, "CanonicalizationMethod")
+        .ok_or_else(|| invalid("has no CanonicalizationMethod"))?;
+    if method.attr("Algorithm") != Some(EXC_C14N) {
+        return Err(invalid("uses an unsupported canonicalization method"));
+    }
+    let signed_info_prefixes = inclusive_prefixes(method);
+
+    let algorithm: &'static dyn signature::VerificationAlgorithm = match signed_info
+        .child(DSIG, "SignatureMethod")
+        .and_then(|method| method.attr("Algorithm"))
+    {
+        Some("http://www.w3.org/2001/04/xmldsig-more#rsa-sha256") => {
+            &signature::RSA_PKCS1_2048_8192_SHA256
+        }
+        Some("http://www.w3.org/2001/04/xmldsig-more#rsa-sha512") => {
+            &signature::RSA_PKCS1_2048_8192_SHA512
+        }
+        _ => return Err(invalid("uses an unsupported signature method")),
+    };
+
+    let mut references = signed_info.children_named(DSIG, "Reference");
+    let reference = references
+        .next()
+        .ok_or_else(|| invalid("has no Reference"))?;
+    if references.next().is_some() {
+        return Err(invalid("has more than one Reference"));
+    }
+
+    let id = signed.attr("ID").filter(|id| !id.is_empty());
+    if id.is_none() || reference.attr("URI") != id.map(|id| format!("#{}", id)).as_deref() {
+        return Err(invalid("does not reference the signed element"));
+    }
+
+    let mut reference_prefixes = Vec::new();
+    let mut has_c14n = false;
+    if let Some(transforms) = reference.child(DSIG, "Transforms") {
+        for transform in transforms.children_named(DSIG, "Transform") {
+            match transform.attr("Algorithm") {
+                Some(ENVELOPED) => {}
+                Some(EXC_C14N) => {
+                    has_c14n = true;
+                    reference_prefixes = inclusive_prefixes(transform);
+                }
+                _ => return Err(invalid("uses an unsupported transform")),
+            }
+        }
+    }
+    if !has_c14n {
+        return Err(invalid("reference is not canonicalized"));
+    }
+
+    let digest_algorithm = match reference
+        .child(DSIG, "DigestMethod")
+        .and_then(|method| method.attr("Algorithm"))
+    {
+        Some("http://www.w3.org/2001/04/xmlenc#sha256") => &SHA256,
+        Some("http://www.w3.org/2001/04/xmlenc#sha512") => &SHA512,
+        _ => return Err(invalid("uses an unsupported digest method")),
+    };
+
+    let expected_digest = decode(reference.child(DSIG, "DigestValue"))?;
+    let canonical = canonicalize(signed, Some(signature), &reference_prefixes);
+    let digest = digest::digest(digest_algorithm, canonical.as_bytes());
+
+    if !ct_eq(digest.as_ref(), &expected_digest) {
+        return Err(invalid("digest does not match"));
+    }
+
+    let signature_value = decode(signature.child(DSIG, "SignatureValue"))?;
+    let canonical = canonicalize(signed_info, None, &signed_info_prefixes);
+
+    let verified = keys.iter().any(|key| {
+        UnparsedPublicKey::new(algorithm, key)
+            .verify(canonical.as_bytes(), &signature_value)
+            .is_ok()
+    });
+
+    if verified {
+        Ok(())
+    } else {
+        Err(invalid(
+            "is not valid for the identity provider's certificate",
+        ))
+    }
+}
+
+fn inclusive_prefixes(element: &Element) -> Vec<String> {
+    element
+        .child(EXC_C14N, "InclusiveNamespaces")
+        .and_then(|inclusive| inclusive.attr("PrefixList"))
+        .map(|list| list.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn decode(element: Option<&Element>) -> Result<Vec<u8>, AuthError> {
+    let text = element.ok_or_else(|| invalid("is incomplete"))?.text();
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+
+    Ok(STANDARD.decode(text)?)
+}
+
+fn invalid(problem: &str) -> AuthError {
+    AuthError::Verification(format!("SAML signature {}", problem))
+}
+
+/// The PKCS#1 RSA public key inside an X.509 certificate
+pub(crate) fn rsa_public_key(certificate: &[u8]) -> Result<Vec<u8>, AuthError> {
+    let (certificate, _) = read(certificate, 0x30)?;
+    let (mut tbs, _) = read(certificate, 0x30)?;
+
+    // Optional explicit version
+    if tbs.first() == Some(&0xa0) {
+        tbs = read(tbs, 0xa0)?.1;
+    }
+
+    // Serial number, signature algorithm, issuer, validity and subject
+    for tag in [0x02, 0x30, 0x30, 0x30, 0x30] {
+        tbs = read(tbs, tag)?.1;
+    }
+
+    let (public_key_info, _) = read(tbs, 0x30)?;
+    let (algorithm, rest) = read(public_key_info, 0x30)?;
+    if !algorithm.starts_with(RSA_ENCRYPTION) {
+        return Err(AuthError::InvalidInput(
+            "Identity provider certificate must have an RSA key".to_string(),
+        ));
+    }
+
+    match read(rest, 0x03)?.0.split_first() {
+        Some((0, key)) => Ok(key.to_vec()),
+        _ => Err(malformed_certificate()),
+    }
+}
+
+/// Split a DER value with the given tag into its contents and whatever follows it
+fn read(input: &[u8], tag: u8) -> Result<(&[u8], &[u8]), AuthError> {
+    let (&found, rest) = input.split_first().ok_or_else(malformed_certificate)?;
+    if found != tag {
+        return Err(malformed_certificate());
+    }
+
+    let (&first, rest) = rest.split_first().ok_or_else(malformed_certificate)?;
+    let (length, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return Err(malformed_certificate());
+        }
+
+        let length = rest[..count]
+            .iter()
+            .fold(0usize, |length, &byte| (length << 8) | byte as usize);
+        (length, &rest[count..])
+    };
+
+    if rest.len() < length {
+        return Err(malformed_certificate());
+    }
+
+    Ok(rest.split_at(length))
+}
+
+fn malformed_certificate() -> AuthError {
+    AuthError::MalformedSecret("Identity provider certificate is not valid DER".to_string())
+}
@@ -0,0 +1,568 @@
+mod dsig;
+mod xml;
+
+use std::{collections::BTreeMap, io::Write, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::DateTime;
+use flate2::{write::DeflateEncoder, Compression};
+use url::Url;
+
+use crate::{
+    crypto::ct_eq,
+    token::{generate_token, now},
+    AuthError,
+};
+
+use self::xml::{escape, Element};
+
+const PROTOCOL: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+const ASSERTION: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+const METADATA: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+const SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+const BEARER: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+const HTTP_POST: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+
+/// The default `NameIDFormat` requested from the identity provider
+pub const DEFAULT_NAME_ID_FORMAT: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:unspecified";
+
+/// How far the IdP's clock may drift from ours when checking assertion time windows
+pub const DEFAULT_CLOCK_SKEW: Duration = Duration::from_secs(120);
+
+/// An identity provider this service provider trusts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityProvider {
+    entity_id: String,
+    sso_url: String,
+    keys: Vec<Vec<u8>>,
+}
+
+impl IdentityProvider {
+    /// `sso_url` is the IdP's single sign on endpoint for the HTTP-Redirect or HTTP-POST binding
+    pub fn new(entity_id: impl Into<String>, sso_url: impl Into<String>) -> Self {
+        Self {
+            entity_id: entity_id.into(),
+            sso_url: sso_url.into(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Trust a PEM encoded signing certificate; may be called more than once during key rollover
+    pub fn certificate_pem(self, pem: &str) -> Result<Self, AuthError> {
+        let body: String = pem
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+            .collect();
+
+        let der = STANDARD.decode(body).map_err(|_| {
+            AuthError::MalformedSecret("Identity provider certificate is not valid PEM".to_string())
+        })?;
+
+        self.certificate_der(&der)
+    }
+
+    /// Trust a DER encoded signing certificate, e.g. the decoded `X509Certificate` from metadata
+    pub fn certificate_der(mut self, der: &[u8]) -> Result<Self, AuthError> {
+        self.keys.push(dsig::rsa_public_key(der)?);
+        Ok(self)
+    }
+
+    pub fn entity_id(&self) -> &str {
+        &self.entity_id
+    }
+}
+
+/// A SAML AuthnRequest ready to send to the identity provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthnRequest {
+    /// Remember this, e.g. in the session, and pass it to `validate_response`
+    pub id: String,
+    pub destination: String,
+    pub xml: String,
+}
+
+impl AuthnRequest {
+    /// A URL for the HTTP-Redirect binding, which the user should be redirected to
+    pub fn redirect_url(&self, relay_state: Option<&str>) -> Result<String, AuthError> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(self.xml.as_bytes())
+            .map_err(AuthError::backend)?;
+        let deflated = encoder.finish().map_err(AuthError::backend)?;
+
+        let mut url = Url::parse(&self.destination)?;
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("SAMLRequest", &STANDARD.encode(deflated));
+
+            if let Some(relay_state) = relay_state {
+                query.append_pair("RelayState", relay_state);
+            }
+        }
+
+        Ok(url.into())
+    }
+
+    /// An HTML page that submits the request with the HTTP-POST binding as soon as it loads
+    pub fn post_form(&self, relay_state: Option<&str>) -> String {
+        let relay_state = relay_state
+            .map(|relay_state| {
+                format!(
+                    r#"<input type="hidden" name="RelayState" value="{}"/>"#,
+                    escape(relay_state)
+                )
+            })
+            .unwrap_or_default();
+
+        format!(
+            concat!(
+                r#"<!DOCTYPE html><html><body onload="document.forms[0].submit()">"#,
+                r#"<form method="post" action="{}">"#,
+                r#"<input type="hidden" name="SAMLRequest" value="{}"/>{}"#,
+                r#"<noscript><button type="submit">Continue</button></noscript>"#,
+                "</form></body></html>"
+            ),
+            escape(&self.destination),
+            STANDARD.encode(&self.xml),
+            relay_state
+        )
+    }
+}
+
+/// A validated assertion about the signed in user
+///
+/// Assertions are bearer tokens: record each `id` until `expires_at` and reject any repeat to stop
+/// a captured response from being replayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assertion {
+    pub id: String,
+    pub issuer: String,
+    pub name_id: String,
+    pub name_id_format: Option<String>,
+    /// Identifies the IdP session, for single logout
+    pub session_index: Option<String>,
+    /// Unix timestamp after which the assertion must no longer be accepted
+    pub expires_at: u64,
+    /// Attribute values by name, after any `map_attribute` renames
+    pub attributes: BTreeMap<String, Vec<String>>,
+}
+
+impl Assertion {
+    /// The first value of an attribute
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .get(name)
+            .and_then(|values| values.first())
+            .map(String::as_str)
+    }
+}
+
+/// A SAML 2.0 service provider for enterprise single sign on
+///
+/// `authn_request` starts a sign in, sent to the IdP with either the HTTP-Redirect or HTTP-POST
+/// binding. The IdP posts a `SAMLResponse` back to the assertion consumer service URL, which
+/// `validate_response` checks: the XML signature against the IdP's configured certificate, the
+/// issuer, audience, recipient, time window and `InResponseTo`. Only data covered by the signature
+/// is returned. Encrypted assertions are not supported.
+///
+/// ### Example
+/// ```rust
+/// use base64::{engine::general_purpose::STANDARD, Engine};
+/// use lonewolf_auth_toolkit::saml::{IdentityProvider, ServiceProvider};
+///
+/// let idp = IdentityProvider::new(
+///     "https://idp.example.com/metadata",
+///     "https://idp.example.com/sso",
+/// )
+/// .certificate_pem(include_str!("testdata/idp.pem"))?;
+///
+/// let sp = ServiceProvider::new(
+///     "https://app.example.com/saml/metadata",
+///     "https://app.example.com/saml/acs",
+///     idp,
+/// )
+/// .map_attribute("http://schemas.xmlsoap.org/ws/2005/05/identity/claims/emailaddress", "email");
+///
+/// // Redirect the user to the IdP, remembering the request id
+/// let request = sp.authn_request()?;
+/// let url = request.redirect_url(Some("/dashboard"))?;
+/// assert!(url.starts_with("https://idp.example.com/sso?SAMLRequest="));
+///
+/// // The IdP posts the SAMLResponse form field back to the ACS URL
+/// let response = include_str!("testdata/response.xml");
+/// let assertion = sp.validate_response(&STANDARD.encode(response), Some("_SomeRequestId"))?;
+///
+/// assert_eq!(assertion.name_id, "someone@example.com");
+/// assert_eq!(assertion.attribute("email"), Some("someone@example.com"));
+///
+/// // A response for another sign in attempt, or one that has been tampered with, is rejected
+/// assert!(sp.validate_response(&STANDARD.encode(response), Some(&request.id)).is_err());
+///
+/// let tampered = response.replace("someone@example.com", "admin@example.com");
+/// assert!(sp.validate_response(&STANDARD.encode(tampered), Some("_SomeRequestId")).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceProvider {
+    entity_id: String,
+    acs_url: String,
+    idp: IdentityProvider,
+    name_id_format: String,
+    clock_skew: Duration,
+    allow_unsolicited: bool,
+    attribute_names: Vec<(String, String)>,
+}
+
+impl ServiceProvider {
+    /// `acs_url` is where the IdP posts responses, the assertion consumer service
+    pub fn new(
+        entity_id: impl Into<String>,
+        acs_url: impl Into<String>,
+        idp: IdentityProvider,
+    ) -> Self {
+        Self {
+            entity_id: entity_id.into(),
+            acs_url: acs_url.into(),
+            idp,
+            name_id_format: DEFAULT_NAME_ID_FORMAT.to_string(),
+            clock_skew: DEFAULT_CLOCK_SKEW,
+            allow_unsolicited: false,
+            attribute_names: Vec::new(),
+        }
+    }
+
+    /// Ask for a `NameID` format such as `urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress`
+    pub fn name_id_format(mut self, format: impl Into<String>) -> Self {
+        self.name_id_format = format.into();
+        self
+    }
+
+    pub fn clock_skew(mut self, clock_skew: Duration) -> Self {
+        self.clock_skew = clock_skew;
+        self
+    }
+
+    /// Accept IdP initiated sign ins, which are more exposed to replay and login CSRF
+    pub fn allow_unsolicited(mut self, allow: bool) -> Self {
+        self.allow_unsolicited = allow;
+        self
+    }
+
+    /// Rename an attribute, e.g. from its URI to a short name; may be called more than once
+    pub fn map_attribute(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.attribute_names.push((from.into(), to.into()));
+        self
+    }
+
+    pub fn entity_id(&self) -> &str {
+        &self.entity_id
+    }
+
+    pub fn identity_provider(&self) -> &IdentityProvider {
+        &self.idp
+    }
+
+    /// SP metadata to register with the identity provider
+    pub fn metadata(&self) -> String {
+        format!(
+            concat!(
+                r#"<md:EntityDescriptor xmlns:md="{}" entityID="{}">"#,
+                r#"<md:SPSSODescriptor AuthnRequestsSigned="false" WantAssertionsSigned="true" "#,
+                r#"protocolSupportEnumeration="{}">"#,
+                "<md:NameIDFormat>{}</md:NameIDFormat>",
+                r#"<md:AssertionConsumerService Binding="{}" Location="{}" index="0" isDefault="true"/>"#,
+                "</md:SPSSODescriptor></md:EntityDescriptor>"
+            ),
+            METADATA,
+            escape(&self.entity_id),
+            PROTOCOL,
+            escape(&self.name_id_format),
+            HTTP_POST,
+            escape(&self.acs_url)
+        )
+    }
+
+    /// Start a sign in with a fresh request id
+    pub fn authn_request(&self) -> Result<AuthnRequest, AuthError> {
+        let id = format!("_{}", generate_token());
+        let issue_instant = timestamp(now()?)?;
+
+        let xml = format!(
+            concat!(
+                r#"<samlp:AuthnRequest xmlns:samlp="{}" xmlns:saml="{}" ID="{}" Version="2.0" "#,
+                r#"IssueInstant="{}" Destination="{}" AssertionConsumerServiceURL="{}" "#,
+                r#"ProtocolBinding="{}">"#,
+                "<saml:Issuer>{}</saml:Issuer>",
+                r#"<samlp:NameIDPolicy Format="{}" AllowCreate="true"/>"#,
+                "</samlp:AuthnRequest>"
+            ),
+            PROTOCOL,
+            ASSERTION,
+            id,
+            issue_instant,
+            escape(&self.idp.sso_url),
+            escape(&self.acs_url),
+            HTTP_POST,
+            escape(&self.entity_id),
+            escape(&self.name_id_format)
+        );
+
+        Ok(AuthnRequest {
+            id,
+            destination: self.idp.sso_url.clone(),
+            xml,
+        })
+    }
+
+    /// Validate a base64 `SAMLResponse` from the HTTP-POST binding
+    ///
+    /// `request_id` is the id of the `AuthnRequest` this sign in started with, or `None` for an
+    /// IdP initiated sign in when `allow_unsolicited` is set.
+    pub fn validate_response(
+        &self,
+        saml_response: &str,
+        request_id: Option<&str>,
+    ) -> Result<Assertion, AuthError> {
+        if request_id.is_none() && !self.allow_unsolicited {
+            return Err(AuthError::Verification(
+                "Unsolicited SAML responses are not allowed".to_string(),
+            ));
+        }
+
+        let encoded: String = saml_response
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let response = xml::parse(&String::from_utf8(STANDARD.decode(encoded)?)?)?;
+
+        if !response.is(PROTOCOL, "Response") {
+            return Err(AuthError::Malformed("Not a SAML response".to_string()));
+        }
+
+        // Duplicate ids are how signature wrapping attacks confuse which element was signed
+        let mut ids: Vec<_> = response
+            .descendants()
+            .into_iter()
+            .filter_map(|element| element.attr("ID"))
+            .collect();
+        ids.sort_unstable();
+        if ids.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(AuthError::Malformed(
+                "SAML response has duplicate IDs".to_string(),
+            ));
+        }
+
+        let status = response
+            .child(PROTOCOL, "Status")
+            .and_then(|status| status.child(PROTOCOL, "StatusCode"))
+            .and_then(|code| code.attr("Value"));
+        if status != Some(SUCCESS) {
+            return Err(AuthError::Verification(format!(
+                "SAML response status is {}",
+                status.unwrap_or("missing")
+            )));
+        }
+
+        if response
+            .attr("Destination")
+            .is_some_and(|destination| destination != self.acs_url)
+        {
+            return Err(AuthError::Verification(
+                "SAML response is for another destination".to_string(),
+            ));
+        }
+        check_in_response_to(response.attr("InResponseTo"), request_id)?;
+
+        if response.child(ASSERTION, "EncryptedAssertion").is_some() {
+            return Err(AuthError::InvalidInput(
+                "Encrypted SAML assertions are not supported".to_string(),
+            ));
+        }
+
+        let mut assertions = response.children_named(ASSERTION, "Assertion");
+        let assertion = match (assertions.next(), assertions.next()) {
+            (Some(assertion), None) => assertion,
+            _ => {
+                return Err(AuthError::Malformed(
+                    "SAML response must have exactly one assertion".to_string(),
+                ))
+            }
+        };
+
+        if dsig::has_signature(assertion) {
+            dsig::verify(assertion, &self.idp.keys)?;
+        } else {
+            dsig::verify(&response, &self.idp.keys)?;
+        }
+
+        self.read_assertion(assertion, request_id)
+    }
+
+    /// Check and read an assertion whose signature has been verified
+    fn read_assertion(
+        &self,
+        assertion: &Element,
+        request_id: Option<&str>,
+    ) -> Result<Assertion, AuthError> {
+        let now = now()?;
+        let skew = self.clock_skew.as_secs();
+
+        let issuer = assertion
+            .child(ASSERTION, "Issuer")
+            .map(|issuer| issuer.text().trim().to_string())
+            .unwrap_or_default();
+        if issuer != self.idp.entity_id {
+            return Err(AuthError::Verification(
+                "SAML assertion is from another issuer".to_string(),
+            ));
+        }
+
+        let conditions = assertion
+            .child(ASSERTION, "Conditions")
+            .ok_or_else(|| missing("Conditions"))?;
+        let mut expires_at = required_time(conditions, "NotOnOrAfter")?;
+        if optional_time(conditions, "NotBefore")?.is_some_and(|not_before| now + skew < not_before)
+        {
+            return Err(AuthError::Verification(
+                "SAML assertion is not valid yet".to_string(),
+            ));
+        }
+
+        let audiences: Vec<String> = conditions
+            .children_named(ASSERTION, "AudienceRestriction")
+            .flat_map(|restriction| restriction.children_named(ASSERTION, "Audience"))
+            .map(|audience| audience.text().trim().to_string())
+            .collect();
+        if !audiences.contains(&self.entity_id) {
+            return Err(AuthError::Verification(
+                "SAML assertion is for another audience".to_string(),
+            ));
+        }
+
+        let subject = assertion
+            .child(ASSERTION, "Subject")
+            .ok_or_else(|| missing("Subject"))?;
+        let name_id = subject
+            .child(ASSERTION, "NameID")
+            .ok_or_else(|| missing("NameID"))?;
+
+        let mut confirmed = false;
+        for confirmation in subject.children_named(ASSERTION, "SubjectConfirmation") {
+            let Some(data) = confirmation.child(ASSERTION, "SubjectConfirmationData") else {
+                continue;
+            };
+            if confirmation.attr("Method") != Some(BEARER)
+                || data.attr("Recipient") != Some(self.acs_url.as_str())
+                || check_in_response_to(data.attr("InResponseTo"), request_id).is_err()
+            {
+                continue;
+            }
+
+            let not_on_or_after = required_time(data, "NotOnOrAfter")?;
+            if now < not_on_or_after + skew {
+                confirmed = true;
+                expires_at = expires_at.min(not_on_or_after);
+                break;
+            }
+        }
+        if !confirmed {
+            return Err(AuthError::Verification(
+                "SAML assertion has no valid bearer subject confirmation".to_string(),
+            ));
+        }
+
+        if now >= expires_at + skew {
+            return Err(AuthError::Verification(
+                "SAML assertion has expired".to_string(),
+            ));
+        }
+
+        let session_index = assertion
+            .child(ASSERTION, "AuthnStatement")
+            .and_then(|statement| statement.attr("SessionIndex"))
+            .map(str::to_string);
+
+        let mut attributes: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for attribute in assertion
+            .children_named(ASSERTION, "AttributeStatement")
+            .flat_map(|statement| statement.children_named(ASSERTION, "Attribute"))
+        {
+            let Some(name) = attribute.attr("Name") else {
+                continue;
+            };
+            let name = self
+                .attribute_names
+                .iter()
+                .find(|(from, _)| from == name)
+                .map_or(name, |(_, to)| to.as_str());
+
+            attributes.entry(name.to_string()).or_default().extend(
+                attribute
+                    .children_named(ASSERTION, "AttributeValue")
+                    .map(|value| value.text().trim().to_string()),
+            );
+        }
+
+        Ok(Assertion {
+            id: assertion
+                .attr("ID")
+                .ok_or_else(|| missing("ID"))?
+                .to_string(),
+            issuer,
+            name_id: name_id.text().trim().to_string(),
+            name_id_format: name_id.attr("Format").map(str::to_string),
+            session_index,
+            expires_at,
+            attributes,
+        })
+    }
+}
+
+/// A solicited response must answer our request; an unsolicited one must not claim to
+fn check_in_response_to(
+    in_response_to: Option<&str>,
+    request_id: Option<&str>,
+) -> Result<(), AuthError> {
+    let matches = match (in_response_to, request_id) {
+        (Some(in_response_to), Some(request_id)) => ct_eq(in_response_to, request_id),
+        (None, None) => true,
+        _ => false,
+    };
+
+    if matches {
+        Ok(())
+    } else {
+        Err(AuthError::Verification(
+            "SAML response is not for this sign in".to_string(),
+        ))
+    }
+}
+
+fn optional_time(element: &Element, attribute: &str) -> Result<Option<u64>, AuthError> {
+    element
+        .attr(attribute)
+        .map(|time| {
+            let time = DateTime::parse_from_rfc3339(time).map_err(|_| {
+                AuthError::Malformed(format!("SAML {} is not a valid time", attribute))
+            })?;
+
+            Ok(u64::try_from(time.timestamp())?)
+        })
+        .transpose()
+}
+
+fn required_time(element: &Element, attribute: &str) -> Result<u64, AuthError> {
+    optional_time(element, attribute)?.ok_or_else(|| missing(attribute))
+}
+
+fn timestamp(seconds: u64) -> Result<String, AuthError> {
+    let time = DateTime::from_timestamp(i64::try_from(seconds)?, 0)
+        .ok_or_else(|| AuthError::InvalidInput("Time is out of range".to_string()))?;
+
+    Ok(time.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+fn missing(name: &str) -> AuthError {
+    AuthError::Malformed(format!("SAML assertion has no {}", name))
+}
@@ -0,0 +1,525 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::AuthError;
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Deep enough for any SAML message while keeping hostile input from exhausting the stack
+const MAX_DEPTH: usize = 64;
+
+/// A parsed element with its namespaces resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Element {
+    pub(crate) prefix: Option<String>,
+    pub(crate) local: String,
+    pub(crate) namespace: Option<String>,
+    pub(crate) attributes: Vec<Attribute>,
+    /// Every namespace in scope, keyed by prefix with `""` for the default namespace
+    pub(crate) scope: BTreeMap<String, String>,
+    pub(crate) children: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Attribute {
+    pub(crate) prefix: Option<String>,
+    pub(crate) local: String,
+    pub(crate) namespace: Option<String>,
+    pub(crate) value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    pub(crate) fn is(&self, namespace: &str, local: &str) -> bool {
+        self.namespace.as_deref() == Some(namespace) && self.local == local
+    }
+
+    /// An attribute without a namespace prefix
+    pub(crate) fn attr(&self, local: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.prefix.is_none() && attribute.local == local)
+            .map(|attribute| attribute.value.as_str())
+    }
+
+    pub(crate) fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub(crate) fn children_named<'a>(
+        &'a self,
+        namespace: &'a str,
+        local: &'a str,
+    ) -> impl Iterator<Item = &'a Element> {
+        self.elements()
+            .filter(move |element| element.is(namespace, local))
+    }
+
+    pub(crate) fn child(&self, namespace: &str, local: &str) -> Option<&Element> {
+        self.elements().find(|element| element.is(namespace, local))
+    }
+
+    /// All text inside the element, so a comment cannot truncate a value
+    pub(crate) fn text(&self) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match child {
+                Node::Text(value) => text.push_str(value),
+                Node::Element(element) => text.push_str(&element.text()),
+            }
+        }
+
+        text
+    }
+
+    /// Every element in the tree, this one included
+    pub(crate) fn descendants(&self) -> Vec<&Element> {
+        let mut elements = vec![self];
+        for child in self.elements() {
+            elements.extend(child.descendants());
+        }
+
+        elements
+    }
+
+    fn qualified_name(&self) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}:{}", prefix, self.local),
+            None => self.local.clone(),
+        }
+    }
+}
+
+/// Parse a document, rejecting DTDs so entity expansion attacks are impossible
+pub(crate) fn parse(input: &str) -> Result<Element, AuthError> {
+    let input = input.replace("\r\n", "\n").replace('\r', "\n");
+    let mut parser = Parser {
+        input: &input,
+        position: 0,
+    };
+
+    parser.skip_prefix("\u{feff}");
+    parser.skip_misc()?;
+    let root = parser.element(&BTreeMap::new(), 0)?;
+    parser.skip_misc()?;
+
+    if parser.position != input.len() {
+        return Err(malformed("Content after the root element"));
+    }
+
+    Ok(root)
+}
+
+/// Exclusive XML canonicalization without comments of `apex`, leaving out `exclude`
+///
+/// `inclusive` is the `InclusiveNamespaces` prefix list, with `#default` for the default namespace.
+pub(crate) fn canonicalize(
+    apex: &Element,
+    exclude: Option<&Element>,
+    inclusive: &[String],
+) -> String {
+    let mut output = String::new();
+    write_canonical(apex, exclude, inclusive, &BTreeMap::new(), &mut output);
+
+    output
+}
+
+fn write_canonical(
+    element: &Element,
+    exclude: Option<&Element>,
+    inclusive: &[String],
+    rendered: &BTreeMap<String, String>,
+    output: &mut String,
+) {
+    let mut prefixes = BTreeSet::new();
+    prefixes.insert(element.prefix.clone().unwrap_or_default());
+    for attribute in &element.attributes {
+        if let Some(prefix) = &attribute.prefix {
+            prefixes.insert(prefix.clone());
+        }
+    }
+    for prefix in inclusive {
+        let prefix = if prefix == "#default" { "" } else { prefix };
+        if element.scope.contains_key(prefix) {
+            prefixes.insert(prefix.to_string());
+        }
+    }
+    prefixes.remove("xml");
+
+    let mut in_scope = rendered.clone();
+    let mut declarations = Vec::new();
+    for prefix in prefixes {
+        let uri = element.scope.get(&prefix).cloned().unwrap_or_default();
+
+        // An empty default namespace only needs declaring to undo an inherited one
+        let needed = if prefix.is_empty() && uri.is_empty() {
+            rendered.get("").is_some_and(|uri| !uri.is_empty())
+        } else {
+            rendered.get(&prefix) != Some(&uri)
+        };
+
+        if needed {
+            in_scope.insert(prefix.clone(), uri.clone());
+            declarations.push((prefix, uri));
+        }
+    }
+
+    let name = element.qualified_name();
+    output.push('<');
+    output.push_str(&name);
+
+    for (prefix, uri) in declarations {
+        if prefix.is_empty() {
+            output.push_str(" xmlns=\"");
+        } else {
+            output.push_str(" xmlns:");
+            output.push_str(&prefix);
+            output.push_str("=\"");
+        }
+        escape_attribute(&uri, output);
+        output.push('"');
+    }
+
+    let mut attributes: Vec<_> = element.attributes.iter().collect();
+    attributes.sort_by(|a, b| {
+        (a.namespace.as_deref().unwrap_or(""), &a.local)
+            .cmp(&(b.namespace.as_deref().unwrap_or(""), &b.local))
+    });
+    for attribute in attributes {
+        output.push(' ');
+        if let Some(prefix) = &attribute.prefix {
+            output.push_str(prefix);
+            output.push(':');
+        }
+        output.push_str(&attribute.local);
+        output.push_str("=\"");
+        escape_attribute(&attribute.value, output);
+        output.push('"');
+    }
+    output.push('>');
+
+    for child in &element.children {
+        match child {
+            Node::Text(text) => escape_text(text, output),
+            Node::Element(child) if exclude.is_some_and(|exclude| std::ptr::eq(child, exclude)) => {
+            }
+            Node::Element(child) => write_canonical(child, exclude, inclusive, &in_scope, output),
+        }
+    }
+
+    output.push_str("</");
+    output.push_str(&name);
+    output.push('>');
+}
+
+fn escape_text(text: &str, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '\r' => output.push_str("&#xD;"),
+            c => output.push(c),
+        }
+    }
+}
+
+fn escape_attribute(value: &str, output: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '"' => output.push_str("&quot;"),
+            '\t' => output.push_str("&#x9;"),
+            '\n' => output.push_str("&#xA;"),
+            '\r' => output.push_str("&#xD;"),
+            c => output.push(c),
+        }
+    }
+}
+
+/// Escape a value for use in element content or a double quoted attribute
+pub(crate) fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.input[self.position..]
+    }
+
+    fn skip_prefix(&mut self, prefix: &str) -> bool {
+        if self.rest().starts_with(prefix) {
+            self.position += prefix.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, prefix: &str) -> Result<(), AuthError> {
+        if self.skip_prefix(prefix) {
+            Ok(())
+        } else {
+            Err(malformed(&format!("Expected {}", prefix)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let trimmed = self.rest().trim_start_matches([' ', '\t', '\n']);
+        self.position = self.input.len() - trimmed.len();
+    }
+
+    /// Skip everything up to and including `end`
+    fn skip_past(&mut self, end: &str) -> Result<&str, AuthError> {
+        let rest = &self.input[self.position..];
+        let index = rest
+            .find(end)
+            .ok_or_else(|| malformed(&format!("Unterminated construct, expected {}", end)))?;
+        self.position += index + end.len();
+
+        Ok(&rest[..index])
+    }
+
+    /// Whitespace, comments and processing instructions around the root element
+    fn skip_misc(&mut self) -> Result<(), AuthError> {
+        loop {
+            self.skip_whitespace();
+
+            if self.skip_prefix("<?") {
+                self.skip_past("?>")?;
+            } else if self.skip_prefix("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest().starts_with("<!") {
+                return Err(malformed("DTDs are not allowed"));
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&str, AuthError> {
+        let rest = &self.input[self.position..];
+        let end = rest
+            .find(|c: char| c.is_ascii_whitespace() || "/>=<\"'".contains(c))
+            .unwrap_or(rest.len());
+
+        if end == 0 {
+            return Err(malformed("Expected a name"));
+        }
+        self.position += end;
+
+        Ok(&rest[..end])
+    }
+
+    fn element(
+        &mut self,
+        parent_scope: &BTreeMap<String, String>,
+        depth: usize,
+    ) -> Result<Element, AuthError> {
+        if depth > MAX_DEPTH {
+            return Err(malformed("Document is nested too deeply"));
+        }
+
+        self.expect("<")?;
+        let name = self.name()?.to_string();
+
+        let mut raw_attributes: Vec<(String, String)> = Vec::new();
+        let self_closing = loop {
+            self.skip_whitespace();
+
+            if self.skip_prefix("/>") {
+                break true;
+            }
+            if self.skip_prefix(">") {
+                break false;
+            }
+
+            let attribute = self.name()?.to_string();
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+
+            let quote = if self.skip_prefix("\"") {
+                "\""
+            } else {
+                self.expect("'")?;
+                "'"
+            };
+            let raw = self.skip_past(quote)?;
+            if raw.contains('<') {
+                return Err(malformed("Attribute values cannot contain <"));
+            }
+            let value = decode(&raw.replace(['\t', '\n'], " "))?;
+
+            if raw_attributes.iter().any(|(known, _)| *known == attribute) {
+                return Err(malformed("Duplicate attribute"));
+            }
+            raw_attributes.push((attribute, value));
+        };
+
+        let mut scope = parent_scope.clone();
+        for (attribute, value) in &raw_attributes {
+            if attribute == "xmlns" {
+                scope.insert(String::new(), value.clone());
+            } else if let Some(prefix) = attribute.strip_prefix("xmlns:") {
+                if value.is_empty() {
+                    return Err(malformed("Namespace prefixes cannot be undeclared"));
+                }
+                scope.insert(prefix.to_string(), value.clone());
+            }
+        }
+
+        let (prefix, local) = split_name(&name);
+        let namespace = resolve(&scope, prefix.as_deref(), true)?;
+
+        let mut attributes = Vec::new();
+        for (attribute, value) in raw_attributes {
+            if attribute == "xmlns" || attribute.starts_with("xmlns:") {
+                continue;
+            }
+
+            let (prefix, local) = split_name(&attribute);
+            attributes.push(Attribute {
+                namespace: resolve(&scope, prefix.as_deref(), false)?,
+                prefix,
+                local,
+                value,
+            });
+        }
+
+        let mut element = Element {
+            prefix,
+            local,
+            namespace,
+            attributes,
+            scope,
+            children: Vec::new(),
+        };
+
+        if self_closing {
+            return Ok(element);
+        }
+
+        loop {
+            let rest = self.rest();
+            let text_end = rest.find('<').unwrap_or(rest.len());
+            if text_end > 0 {
+                let text = rest[..text_end].to_string();
+                self.position += text_end;
+                element.children.push(Node::Text(decode(&text)?));
+            }
+
+            if self.position == self.input.len() {
+                return Err(malformed("Unterminated element"));
+            }
+
+            if self.skip_prefix("</") {
+                if self.name()? != name {
+                    return Err(malformed("Mismatched closing tag"));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+
+                return Ok(element);
+            } else if self.skip_prefix("<!--") {
+                self.skip_past("-->")?;
+            } else if self.skip_prefix("<![CDATA[") {
+                let text = self.skip_past("]]>")?.to_string();
+                element.children.push(Node::Text(text));
+            } else if self.skip_prefix("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with("<!") {
+                return Err(malformed("DTDs are not allowed"));
+            } else {
+                let child = self.element(&element.scope, depth + 1)?;
+                element.children.push(Node::Element(child));
+            }
+        }
+    }
+}
+
+fn split_name(name: &str) -> (Option<String>, String) {
+    match name.split_once(':') {
+        Some((prefix, local)) => (Some(prefix.to_string()), local.to_string()),
+        None => (None, name.to_string()),
+    }
+}
+
+fn resolve(
+    scope: &BTreeMap<String, String>,
+    prefix: Option<&str>,
+    use_default: bool,
+) -> Result<Option<String>, AuthError> {
+    match prefix {
+        Some("xml") => Ok(Some(XML_NAMESPACE.to_string())),
+        Some(prefix) => scope
+            .get(prefix)
+            .map(|uri| Some(uri.clone()))
+            .ok_or_else(|| malformed("Undeclared namespace prefix")),
+        None if use_default => Ok(scope.get("").filter(|uri| !uri.is_empty()).cloned()),
+        None => Ok(None),
+    }
+}
+
+/// Replace the predefined entities and character references
+fn decode(text: &str) -> Result<String, AuthError> {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        let end = rest
+            .find(';')
+            .ok_or_else(|| malformed("Unterminated entity reference"))?;
+        let entity = &rest[..end];
+        rest = &rest[end + 1..];
+
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16)
+                } else if let Some(decimal) = entity.strip_prefix('#') {
+                    decimal.parse()
+                } else {
+                    return Err(malformed("Unknown entity reference"));
+                };
+
+                code.ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| malformed("Invalid character reference"))?
+            }
+        };
+        decoded.push(c);
+    }
+    decoded.push_str(rest);
+
+    Ok(decoded)
+}
+
+fn malformed(message: &str) -> AuthError {
+    AuthError::Malformed(format!("Invalid XML: {}", message))
+}
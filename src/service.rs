@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use crate::{
+    account::{Accounts, CredentialStore, UserStore},
+    audit::{AuditSink, NoAudit},
+    rate_limit::lockout::{Lockout, LockoutStore},
+    session::{Session, SessionManager, SessionStore},
+    AuthError,
+};
+
+/// How long a user has to enter their MFA code after their password was accepted
+pub const DEFAULT_CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Session key holding the account an MFA challenge was issued for
+const PENDING_ACCOUNT: &str = "mfa_pending_account";
+
+/// A password was accepted and the user must now enter a code from their authenticator app
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MfaChallenge {
+    /// Hand to the client and pass back to `complete_mfa`; it is not a signed in session
+    pub id: String,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+}
+
+/// What a correct password leads to
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginOutcome {
+    SignedIn(Session),
+    MfaRequired(MfaChallenge),
+}
+
+/// Password sign in, MFA, lockout and sessions wired together for the common flows
+///
+/// `login` checks the identity's lockout, then the password, and either starts a session or
+/// issues an `MfaChallenge`. `complete_mfa` checks the account's MFA lockout and the TOTP code
+/// and starts the session. Failures count towards the lockout under `login:` and `mfa:` keys,
+/// and the components record their usual events to the sink given to `audit`. Use `accounts`,
+/// `sessions` and `lockout` for everything else, such as registration or revoking sessions.
+///
+/// Challenges are short lived anonymous sessions, so they live in the same `SessionStore` and
+/// are rotated away once completed.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::account::{Accounts, MemoryCredentialStore, MemoryUserStore};
+/// use lonewolf_auth_toolkit::audit::MemoryAuditSink;
+/// use lonewolf_auth_toolkit::clock::{Clock, MockClock, SystemClock};
+/// use lonewolf_auth_toolkit::mfa::blocking::{current_code, current_code_with_clock};
+/// use lonewolf_auth_toolkit::mfa::TotpConfig;
+/// use lonewolf_auth_toolkit::password::argon2::Argon2Params;
+/// use lonewolf_auth_toolkit::rate_limit::lockout::{Lockout, MemoryLockoutStore};
+/// use lonewolf_auth_toolkit::service::{AuthService, LoginOutcome};
+/// use lonewolf_auth_toolkit::session::{MemorySessionStore, SessionManager};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let accounts = Accounts::new(MemoryUserStore::default(), MemoryCredentialStore::default())
+///         .params(Argon2Params::default().memory_kib(1024).iterations(1));
+///     let service = AuthService::new(
+///         accounts,
+///         SessionManager::new(MemorySessionStore::default()),
+///         Lockout::new(MemoryLockoutStore::default()).free_attempts(3),
+///     )
+///     .audit(Arc::new(MemoryAuditSink::default()));
+///
+///     let password = "correct horse battery staple";
+///     let user = service.accounts().register("someone@example.com", None, password).await?;
+///
+///     match service.login("someone@example.com", password).await? {
+///         LoginOutcome::SignedIn(session) => assert_eq!(session.account(), Some(&*user.id)),
+///         LoginOutcome::MfaRequired(_) => panic!("MFA is not enabled yet"),
+///     }
+///
+///     let secret = service.accounts().start_totp(&user.id).await?;
+///     let code = current_code(&secret, &TotpConfig::default())?;
+///     assert!(service.accounts().confirm_totp(&user.id, code).await?);
+///
+///     let challenge = match service.login("someone@example.com", password).await? {
+///         LoginOutcome::MfaRequired(challenge) => challenge,
+///         LoginOutcome::SignedIn(_) => panic!("MFA is enabled"),
+///     };
+///     assert!(service.complete_mfa(&challenge.id, "000000").await.is_err());
+///
+///     // Codes are single use, so sign in with the next one, as the app shows 30 seconds later
+///     let later = MockClock::at(SystemClock.now()? + 30);
+///     let code = current_code_with_clock(&secret, &TotpConfig::default(), &later)?;
+///     let session = service.complete_mfa(&challenge.id, &code).await?;
+///     assert_eq!(session.account(), Some(&*user.id));
+///     assert!(service.complete_mfa(&challenge.id, &code).await.is_err());
+///
+///     // Three wrong passwords lock the identity out, even for the right one
+///     for _ in 0..3 {
+///         assert!(service.login("someone@example.com", "Tr0ub4dor&3").await.is_err());
+///     }
+///     assert!(service.login("someone@example.com", password).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct AuthService<U, C, S, L, A = NoAudit> {
+    accounts: Accounts<U, C, A>,
+    sessions: SessionManager<S, A>,
+    lockout: Lockout<L, A>,
+    challenge_ttl: Duration,
+}
+
+impl<U: UserStore, C: CredentialStore, S: SessionStore, L: LockoutStore> AuthService<U, C, S, L> {
+    pub fn new(accounts: Accounts<U, C>, sessions: SessionManager<S>, lockout: Lockout<L>) -> Self {
+        Self {
+            accounts,
+            sessions,
+            lockout,
+            challenge_ttl: DEFAULT_CHALLENGE_TTL,
+        }
+    }
+}
+
+impl<U, C, S, L, A> AuthService<U, C, S, L, A>
+where
+    U: UserStore,
+    C: CredentialStore,
+    S: SessionStore,
+    L: LockoutStore,
+    A: AuditSink,
+{
+    /// Record sign ins, MFA attempts, sessions and lockouts to `audit`, e.g. an `Arc` of a sink
+    pub fn audit<B: AuditSink + Clone>(self, audit: B) -> AuthService<U, C, S, L, B> {
+        AuthService {
+            accounts: self.accounts.audit(audit.clone()),
+            sessions: self.sessions.audit(audit.clone()),
+            lockout: self.lockout.audit(audit),
+            challenge_ttl: self.challenge_ttl,
+        }
+    }
+
+    pub fn challenge_ttl(mut self, challenge_ttl: Duration) -> Self {
+        self.challenge_ttl = challenge_ttl;
+        self
+    }
+
+    pub fn accounts(&self) -> &Accounts<U, C, A> {
+        &self.accounts
+    }
+
+    pub fn sessions(&self) -> &SessionManager<S, A> {
+        &self.sessions
+    }
+
+    pub fn lockout(&self) -> &Lockout<L, A> {
+        &self.lockout
+    }
+
+    /// Check an email or username and password, starting a session unless MFA is required
+    ///
+    /// Fails with `AuthError::RateLimited` or `AuthError::InvalidState` while the identity is
+    /// locked out, and otherwise as `Accounts::authenticate` does.
+    pub async fn login(&self, identifier: &str, password: &str) -> Result<LoginOutcome, AuthError> {
+        let key = format!("login:{}", identifier.trim().to_lowercase());
+        self.lockout.check(&key).await?;
+
+        let signed_in = match self.accounts.authenticate(identifier, password).await {
+            Ok(signed_in) => signed_in,
+            Err(error @ AuthError::Verification(_)) => {
+                self.lockout.record_failure(&key).await?;
+
+                return Err(error);
+            }
+            Err(error) => return Err(error),
+        };
+        self.lockout.record_success(&key).await?;
+
+        if !signed_in.mfa_required {
+            return Ok(LoginOutcome::SignedIn(
+                self.start(&signed_in.user.id).await?,
+            ));
+        }
+
+        let mut challenge = self
+            .sessions
+            .create_with(self.challenge_ttl, self.challenge_ttl)
+            .await?;
+        challenge.insert(PENDING_ACCOUNT, &signed_in.user.id)?;
+        self.sessions.save(&mut challenge).await?;
+
+        Ok(LoginOutcome::MfaRequired(MfaChallenge {
+            id: challenge.id().to_string(),
+            expires_at: challenge.expires_at(),
+        }))
+    }
+
+    /// Check the TOTP code for a challenge from `login` and start the signed in session
+    ///
+    /// Fails with `AuthError::Verification` for unknown or expired challenges and wrong codes,
+    /// and with `AuthError::RateLimited` or `AuthError::InvalidState` while the account's MFA is
+    /// locked out. A wrong code leaves the challenge usable until it expires.
+    pub async fn complete_mfa(&self, challenge: &str, code: &str) -> Result<Session, AuthError> {
+        let invalid = || AuthError::Verification("MFA challenge is invalid or expired".to_string());
+
+        let mut pending = self.sessions.load(challenge).await?.ok_or_else(invalid)?;
+        let account: String = pending.get(PENDING_ACCOUNT)?.ok_or_else(invalid)?;
+
+        let key = format!("mfa:{}", account);
+        self.lockout.check(&key).await?;
+
+        if !self
+            .accounts
+            .verify_totp(&account, code.to_string())
+            .await?
+        {
+            self.lockout.record_failure(&key).await?;
+
+            return Err(AuthError::Verification("Invalid MFA code".to_string()));
+        }
+        self.lockout.record_success(&key).await?;
+
+        // Rotating claims the challenge, so a second completion racing this one fails here
+        self.sessions
+            .rotate(&mut pending)
+            .await
+            .map_err(|_| invalid())?;
+        self.sessions.destroy(pending.id()).await?;
+
+        self.start(&account).await
+    }
+
+    /// End the session with this id
+    pub async fn logout(&self, session: &str) -> Result<(), AuthError> {
+        self.sessions.destroy(session).await
+    }
+
+    async fn start(&self, account: &str) -> Result<Session, AuthError> {
+        let mut session = self.sessions.create().await?;
+        self.sessions.sign_in(&mut session, account).await?;
+
+        Ok(session)
+    }
+}
@@ -0,0 +1,296 @@
+use std::{fmt, time::Duration};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use crate::{
+    crypto::{hmac_sign, hmac_verify, sealed::Sealer, HmacKey, HmacKeys},
+    AuthError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sent on cross site requests too; browsers only accept it together with `Secure`
+    None,
+}
+
+/// A `Set-Cookie` header value
+///
+/// Cookies default to `Path=/; Secure; HttpOnly; SameSite=Lax`, so only loosen what a cookie
+/// really needs. Naming a cookie `__Host-...` and leaving the domain unset additionally stops
+/// subdomains from overwriting it.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::session::cookie::{find, Cookie, SameSite};
+///
+/// let cookie = Cookie::new("__Host-session", "abc123")
+///     .unwrap()
+///     .max_age(Duration::from_secs(3600))
+///     .same_site(SameSite::Strict);
+///
+/// assert_eq!(
+///     cookie.to_string(),
+///     "__Host-session=abc123; Path=/; Max-Age=3600; Secure; HttpOnly; SameSite=Strict"
+/// );
+///
+/// // Reading it back from the request's Cookie header
+/// assert_eq!(find("theme=dark; __Host-session=abc123", "__Host-session"), Some("abc123"));
+///
+/// // Signing out
+/// assert!(cookie.removal().to_string().contains("Max-Age=0"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// Errors if the name or value contains characters that are not allowed in a cookie
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Result<Self, AuthError> {
+        let (name, value) = (name.into(), value.into());
+
+        if name.is_empty() || !name.chars().all(is_name_char) {
+            return Err(AuthError::InvalidInput("Invalid cookie name".to_string()));
+        }
+
+        if !value.chars().all(is_value_char) {
+            return Err(AuthError::InvalidInput(
+                "Invalid cookie value; sign or encrypt it to make it cookie safe".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            name,
+            value,
+            path: Some("/".to_string()),
+            domain: None,
+            max_age: None,
+            secure: true,
+            http_only: true,
+            same_site: Some(SameSite::Lax),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Without a max age the cookie is dropped when the browser closes
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// A cookie with the same name, path and domain that tells the browser to delete this one
+    pub fn removal(&self) -> Self {
+        Self {
+            value: String::new(),
+            max_age: Some(Duration::ZERO),
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+
+        match self.same_site {
+            Some(SameSite::Strict) => write!(f, "; SameSite=Strict"),
+            Some(SameSite::Lax) => write!(f, "; SameSite=Lax"),
+            Some(SameSite::None) => write!(f, "; SameSite=None"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Find a cookie's value in a `Cookie` request header
+pub fn find<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Signs cookie values with HMAC so clients can read but not alter them
+///
+/// Signed values look like `<base64url(value)>.<version>.<base64url(tag)>`, signed as described
+/// for `crypto::HmacKeys`. The tag covers the cookie name too, so a value signed for one cookie is
+/// rejected under another. Build the signer with `from_keys` to rotate keys: cookies signed with
+/// an older key stay valid until that key is retired.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::crypto::{HmacKey, HmacKeys};
+/// use lonewolf_auth_toolkit::session::cookie::SignedCookies;
+///
+/// let keys = HmacKeys::new(1, HmacKey::sha256(b"a cookie signing key, at least 32 bytes")?);
+/// let cookies = SignedCookies::from_keys(keys.clone());
+///
+/// let value = cookies.sign("remember_me", "SomeAccountName");
+///
+/// assert_eq!(cookies.verify("remember_me", &value)?, "SomeAccountName");
+/// assert!(cookies.verify("other_cookie", &value).is_err());
+///
+/// // Rotate to a new key; existing cookies keep verifying
+/// let keys = keys.rotate(2, HmacKey::sha256(b"the next cookie signing key, 32 bytes")?)?;
+/// let cookies = SignedCookies::from_keys(keys.clone());
+/// assert_eq!(cookies.verify("remember_me", &value)?, "SomeAccountName");
+///
+/// // Once the old key is retired they no longer do
+/// let cookies = SignedCookies::from_keys(keys.retire(1)?);
+/// assert!(cookies.verify("remember_me", &value).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+pub struct SignedCookies {
+    keys: HmacKeys,
+}
+
+impl SignedCookies {
+    /// Sign with a single HMAC-SHA256 key, used as key version 1
+    pub fn new(key: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self::from_keys(HmacKeys::new(1, HmacKey::sha256(key)?)))
+    }
+
+    /// Sign with the current key of `keys` and accept any key still in the set
+    pub fn from_keys(keys: HmacKeys) -> Self {
+        Self { keys }
+    }
+
+    pub fn sign(&self, name: &str, value: &str) -> String {
+        let value = URL_SAFE_NO_PAD.encode(value);
+
+        format!(
+            "{}.{}",
+            value,
+            hmac_sign(&self.keys, &message(name, &value))
+        )
+    }
+
+    pub fn verify(&self, name: &str, signed: &str) -> Result<String, AuthError> {
+        let (value, signature) = signed
+            .split_once('.')
+            .ok_or_else(|| AuthError::Malformed("Signed cookie has no tag".to_string()))?;
+
+        if !hmac_verify(&self.keys, &message(name, value), signature)? {
+            return Err(AuthError::Verification(
+                "Cookie signature is invalid".to_string(),
+            ));
+        }
+
+        Ok(String::from_utf8(URL_SAFE_NO_PAD.decode(value)?)?)
+    }
+}
+
+/// Encrypts cookie values with a `Sealer` so clients can neither read nor alter them
+///
+/// The cookie name is used as the associated data, so a value sealed for one cookie cannot be
+/// replayed under another. Key rotation works as it does for the sealer.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::crypto::sealed::{generate_key, Sealer};
+/// use lonewolf_auth_toolkit::session::cookie::EncryptedCookies;
+///
+/// let cookies = EncryptedCookies::new(Sealer::new(1, &generate_key()).unwrap());
+///
+/// let value = cookies.encrypt("session", "SomeSessionId").unwrap();
+///
+/// assert!(!value.contains("SomeSessionId"));
+/// assert_eq!(cookies.decrypt("session", &value).unwrap(), "SomeSessionId");
+/// assert!(cookies.decrypt("other_cookie", &value).is_err());
+/// ```
+pub struct EncryptedCookies {
+    sealer: Sealer,
+}
+
+impl EncryptedCookies {
+    pub fn new(sealer: Sealer) -> Self {
+        Self { sealer }
+    }
+
+    pub fn encrypt(&self, name: &str, value: &str) -> Result<String, AuthError> {
+        self.sealer.seal(value.as_bytes(), name.as_bytes())
+    }
+
+    pub fn decrypt(&self, name: &str, sealed: &str) -> Result<String, AuthError> {
+        Ok(String::from_utf8(
+            self.sealer.open(sealed, name.as_bytes())?,
+        )?)
+    }
+}
+
+fn message(name: &str, value: &str) -> Vec<u8> {
+    format!("cookie:{}={}", name, value).into_bytes()
+}
+
+/// Token characters allowed in a cookie name (RFC 6265)
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?={}".contains(c)
+}
+
+/// Characters allowed in an unquoted cookie value (RFC 6265)
+fn is_value_char(c: char) -> bool {
+    c.is_ascii_graphic() && !"\",;\\".contains(c)
+}
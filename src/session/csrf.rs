@@ -0,0 +1,210 @@
+use crate::{
+    crypto::{ct_eq, hmac_sign, hmac_verify, HmacKey, HmacKeys},
+    token::generate_token,
+    AuthError,
+};
+
+use super::{
+    cookie::{self, Cookie, SameSite},
+    Session,
+};
+
+/// Session data key holding the synchronizer token
+pub const CSRF_SESSION_KEY: &str = "csrf_token";
+
+/// Name of the double submit cookie unless another is set
+pub const DEFAULT_COOKIE_NAME: &str = "__Host-csrf";
+
+/// Header single page apps send the token in
+pub const DEFAULT_HEADER_NAME: &str = "X-CSRF-Token";
+
+/// Issues and checks anti-CSRF tokens bound to a session
+///
+/// Each token is `<nonce>.<version>.<tag>`, an HMAC over the nonce and the session's id hash, so a
+/// token only works with the session it was issued for and an attacker who can plant cookies
+/// cannot mint one. Two patterns are supported:
+///
+/// - Synchronizer token: `issue` keeps the token in the session; render it into forms and check
+///   submissions with `verify_synchronizer`.
+/// - Double submit cookie: `cookie` puts the token in a cookie that JavaScript can read; the
+///   client copies it into a header and `verify_double_submit` compares the two, with nothing
+///   stored server side.
+///
+/// Tokens are tied to the session id, so issue a new one after `SessionManager::rotate`. `check`
+/// combines both patterns for middleware. Keys rotate as for `SignedCookies`: build the checker
+/// with `from_keys` and tokens signed with an older key are accepted until it is retired.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::crypto::{HmacKey, HmacKeys};
+/// use lonewolf_auth_toolkit::session::csrf::Csrf;
+/// use lonewolf_auth_toolkit::session::{MemorySessionStore, SessionManager};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let sessions = SessionManager::new(MemorySessionStore::default());
+///     let csrf = Csrf::new(&[7u8; 32])?;
+///
+///     // Synchronizer token: render it into a hidden form field
+///     let mut session = sessions.create().await?;
+///     let token = csrf.issue(&mut session)?;
+///     sessions.save(&mut session).await?;
+///
+///     assert!(csrf.verify_synchronizer(&session, &token)?);
+///     assert!(!csrf.verify_synchronizer(&session, "forged.token")?);
+///
+///     // Double submit cookie: the client echoes the cookie in the X-CSRF-Token header
+///     let other = sessions.create().await?;
+///     let (token, cookie) = csrf.cookie(&other)?;
+///     let header = cookie.to_string();
+///     let cookie_header = header.split(';').next().unwrap();
+///
+///     assert!(csrf.verify_double_submit(&other, Some(cookie_header), &token));
+///     assert!(!csrf.verify_double_submit(&session, Some(cookie_header), &token));
+///
+///     // In middleware
+///     csrf.check("GET", &other, None, None)?;
+///     csrf.check("POST", &other, Some(&token), Some(cookie_header))?;
+///     assert!(csrf.check("POST", &other, None, Some(cookie_header)).is_err());
+///
+///     // After a key rotation tokens already handed out keep working
+///     let keys = HmacKeys::new(1, HmacKey::sha256(&[7u8; 32])?);
+///     let csrf = Csrf::from_keys(keys.rotate(2, HmacKey::sha256(&[8u8; 32])?)?);
+///     assert!(csrf.verify(&other, &token));
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Csrf {
+    keys: HmacKeys,
+    cookie_name: String,
+}
+
+impl Csrf {
+    /// Sign with a single HMAC-SHA256 key, used as key version 1
+    pub fn new(key: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self::from_keys(HmacKeys::new(1, HmacKey::sha256(key)?)))
+    }
+
+    /// Sign with the current key of `keys` and accept any key still in the set
+    pub fn from_keys(keys: HmacKeys) -> Self {
+        Self {
+            keys,
+            cookie_name: DEFAULT_COOKIE_NAME.to_string(),
+        }
+    }
+
+    pub fn cookie_name(mut self, cookie_name: impl Into<String>) -> Self {
+        self.cookie_name = cookie_name.into();
+        self
+    }
+
+    /// A new token for the session
+    pub fn token(&self, session: &Session) -> String {
+        let nonce = generate_token();
+
+        format!(
+            "{}.{}",
+            nonce,
+            hmac_sign(&self.keys, &message(session, &nonce))
+        )
+    }
+
+    /// Whether `token` was issued for the session
+    pub fn verify(&self, session: &Session, token: &str) -> bool {
+        match token.split_once('.') {
+            Some((nonce, signature)) => {
+                hmac_verify(&self.keys, &message(session, nonce), signature).unwrap_or(false)
+            }
+            None => false,
+        }
+    }
+
+    /// Keep a new synchronizer token in the session and return it; call
+    /// `SessionManager::save` to persist it
+    pub fn issue(&self, session: &mut Session) -> Result<String, AuthError> {
+        let token = self.token(session);
+        session.insert(CSRF_SESSION_KEY, &token)?;
+
+        Ok(token)
+    }
+
+    /// Whether `submitted` is the synchronizer token kept in the session
+    pub fn verify_synchronizer(
+        &self,
+        session: &Session,
+        submitted: &str,
+    ) -> Result<bool, AuthError> {
+        Ok(match session.get::<String>(CSRF_SESSION_KEY)? {
+            Some(stored) => ct_eq(&stored, submitted) && self.verify(session, submitted),
+            None => false,
+        })
+    }
+
+    /// A new token and the `Set-Cookie` value carrying it for the double submit pattern
+    ///
+    /// The cookie is not `HttpOnly`, so the page's scripts can read it, and `SameSite=Strict`.
+    pub fn cookie(&self, session: &Session) -> Result<(String, Cookie), AuthError> {
+        let token = self.token(session);
+        let cookie = Cookie::new(&self.cookie_name, &token)?
+            .http_only(false)
+            .same_site(SameSite::Strict);
+
+        Ok((token, cookie))
+    }
+
+    /// Whether the token in the request's `Cookie` header matches `submitted` and was issued for
+    /// the session
+    pub fn verify_double_submit(
+        &self,
+        session: &Session,
+        cookie_header: Option<&str>,
+        submitted: &str,
+    ) -> bool {
+        match cookie_header.and_then(|header| cookie::find(header, &self.cookie_name)) {
+            Some(cookie) => ct_eq(cookie, submitted) && self.verify(session, submitted),
+            None => false,
+        }
+    }
+
+    /// Check a request before its handler runs
+    ///
+    /// Safe methods (`GET`, `HEAD`, `OPTIONS` and `TRACE`) always pass. Other requests need the
+    /// token from a form field or the `X-CSRF-Token` header as `submitted`; it is checked against
+    /// the session's synchronizer token if there is one and the double submit cookie otherwise.
+    /// Fails with `AuthError::InvalidState`, a `403`, when the token is missing or wrong.
+    pub fn check(
+        &self,
+        method: &str,
+        session: &Session,
+        submitted: Option<&str>,
+        cookie_header: Option<&str>,
+    ) -> Result<(), AuthError> {
+        if ["GET", "HEAD", "OPTIONS", "TRACE"]
+            .iter()
+            .any(|safe| method.eq_ignore_ascii_case(safe))
+        {
+            return Ok(());
+        }
+
+        let valid = match submitted {
+            Some(submitted) if session.get::<String>(CSRF_SESSION_KEY)?.is_some() => {
+                self.verify_synchronizer(session, submitted)?
+            }
+            Some(submitted) => self.verify_double_submit(session, cookie_header, submitted),
+            None => false,
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(AuthError::InvalidState(
+                "CSRF token is missing or invalid".to_string(),
+            ))
+        }
+    }
+}
+
+fn message(session: &Session, nonce: &str) -> Vec<u8> {
+    format!("csrf:{}.{}", session.id_hash(), nonce).into_bytes()
+}
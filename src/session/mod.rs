@@ -0,0 +1,638 @@
+pub mod cookie;
+pub mod csrf;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value as Json};
+
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditSink, NoAudit},
+    clock::{Clock, SystemClock},
+    token::{generate_token, hash_token},
+    trace, AuthError,
+};
+
+/// How long a session lasts without being used
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a session lasts however often it is used
+pub const DEFAULT_MAX_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A session as persisted; the session id itself is only kept as a SHA-256 hash
+///
+/// Each record carries its own timeouts, so sessions created with different lifetimes (such as
+/// "remember me" sessions) expire correctly whichever instance or backend reads them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub id_hash: String,
+    /// The signed in account, if any; stores index sessions by it
+    pub account: Option<String>,
+    pub data: Map<String, Json>,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+    /// Unix timestamp in seconds of the last time the session was saved or touched
+    pub last_seen_at: u64,
+    /// Seconds the session may go unused
+    pub idle_timeout: u64,
+    /// Unix timestamp in seconds after which the session expires however often it is used
+    pub absolute_expires_at: u64,
+}
+
+impl SessionRecord {
+    /// When the session expires unless it is used again, whichever timeout comes first
+    pub fn expires_at(&self) -> u64 {
+        self.last_seen_at
+            .saturating_add(self.idle_timeout)
+            .min(self.absolute_expires_at)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at() <= now
+    }
+}
+
+/// Persists sessions by the hash of their id
+///
+/// Stores need not check expiry: `SessionManager` rejects and deletes expired records on every
+/// read using `SessionRecord::is_expired`. Backends with native expiry should still expire keys
+/// at `SessionRecord::expires_at` so abandoned sessions are cleaned up.
+///
+/// Records with an `account` must also be findable by it through `list` and `delete_all`, e.g. via
+/// an index kept up to date by `save`, `delete` and `rotate`. `rotate` must check for, delete and
+/// replace the old record in a single atomic operation (e.g. a transaction or a Redis script) so a
+/// session is never lost, duplicated or revived when its id changes.
+pub trait SessionStore {
+    fn load(
+        &self,
+        id_hash: &str,
+    ) -> impl Future<Output = Result<Option<SessionRecord>, AuthError>> + Send;
+
+    /// Insert or replace the record
+    fn save(&self, record: SessionRecord) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Replace the record stored under its `id_hash` only if there still is one
+    ///
+    /// Returns `false` and saves nothing otherwise, e.g. `UPDATE ... WHERE id_hash = $1`, so a
+    /// request that loaded the session before it was destroyed or revoked cannot revive it.
+    fn update(&self, record: SessionRecord)
+        -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    fn delete(&self, id_hash: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Replace the record stored under `old_hash` with `record`
+    ///
+    /// Returns `false` and saves nothing if there is no record under `old_hash`, e.g. because the
+    /// session was destroyed by another request.
+    fn rotate(
+        &self,
+        old_hash: &str,
+        record: SessionRecord,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Every record belonging to the account, expired ones included
+    fn list(
+        &self,
+        account: &str,
+    ) -> impl Future<Output = Result<Vec<SessionRecord>, AuthError>> + Send;
+
+    /// Delete every record belonging to the account, returning how many were deleted
+    fn delete_all(&self, account: &str) -> impl Future<Output = Result<usize, AuthError>> + Send;
+}
+
+/// A loaded session and its data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    id: String,
+    record: SessionRecord,
+}
+
+impl Session {
+    /// The id to hand to the client, e.g. in a cookie
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The hash the session is stored under, safe to show the user as a handle for `revoke`
+    pub fn id_hash(&self) -> &str {
+        &self.record.id_hash
+    }
+
+    pub fn account(&self) -> Option<&str> {
+        self.record.account.as_deref()
+    }
+
+    pub fn created_at(&self) -> u64 {
+        self.record.created_at
+    }
+
+    pub fn last_seen_at(&self) -> u64 {
+        self.record.last_seen_at
+    }
+
+    pub fn expires_at(&self) -> u64 {
+        self.record.expires_at()
+    }
+
+    /// Read a value, returning `None` if it is not set
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, AuthError> {
+        self.record
+            .data
+            .get(key)
+            .map(|value| serde_json::from_value(value.clone()))
+            .transpose()
+            .map_err(AuthError::from)
+    }
+
+    /// Set a value; call `SessionManager::save` to persist it
+    pub fn insert<T: Serialize>(&mut self, key: &str, value: T) -> Result<(), AuthError> {
+        self.record
+            .data
+            .insert(key.to_string(), serde_json::to_value(value)?);
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Json> {
+        self.record.data.remove(key)
+    }
+
+    pub fn clear(&mut self) {
+        self.record.data.clear();
+    }
+}
+
+/// Creates, loads and destroys server side sessions
+///
+/// Sessions expire after `idle_timeout` without use and after `max_lifetime` regardless; both can
+/// be overridden per session with `create_with`. Call
+/// `rotate` whenever the user's privileges change, such as after sign in, completing MFA or
+/// gaining a role, so an id planted by an attacker before sign in (session fixation) is useless
+/// afterwards. `sign_in` does both.
+///
+/// Signed in sessions are indexed by account, so an application can list a user's active sessions
+/// (storing details such as the user agent in the session data) and revoke one or all of them.
+///
+/// ### Example
+/// ```rust
+/// use std::{collections::HashMap, sync::Mutex, time::Duration};
+///
+/// use lonewolf_auth_toolkit::session::{SessionManager, SessionRecord, SessionStore};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, SessionRecord>>);
+///
+/// impl SessionStore for MemoryStore {
+///     async fn load(&self, id_hash: &str) -> Result<Option<SessionRecord>, AuthError> {
+///         Ok(self.0.lock().unwrap().get(id_hash).cloned())
+///     }
+///
+///     async fn save(&self, record: SessionRecord) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(record.id_hash.clone(), record);
+///         Ok(())
+///     }
+///
+///     async fn update(&self, record: SessionRecord) -> Result<bool, AuthError> {
+///         let mut sessions = self.0.lock().unwrap();
+///         match sessions.get_mut(&record.id_hash) {
+///             Some(current) => *current = record,
+///             None => return Ok(false),
+///         }
+///         Ok(true)
+///     }
+///
+///     async fn delete(&self, id_hash: &str) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().remove(id_hash);
+///         Ok(())
+///     }
+///
+///     async fn rotate(&self, old_hash: &str, record: SessionRecord) -> Result<bool, AuthError> {
+///         let mut sessions = self.0.lock().unwrap();
+///         if sessions.remove(old_hash).is_none() {
+///             return Ok(false);
+///         }
+///         sessions.insert(record.id_hash.clone(), record);
+///         Ok(true)
+///     }
+///
+///     async fn list(&self, account: &str) -> Result<Vec<SessionRecord>, AuthError> {
+///         let sessions = self.0.lock().unwrap();
+///         Ok(sessions.values().filter(|record| record.account.as_deref() == Some(account)).cloned().collect())
+///     }
+///
+///     async fn delete_all(&self, account: &str) -> Result<usize, AuthError> {
+///         let mut sessions = self.0.lock().unwrap();
+///         let before = sessions.len();
+///         sessions.retain(|_, record| record.account.as_deref() != Some(account));
+///         Ok(before - sessions.len())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let sessions = SessionManager::new(MemoryStore::default());
+///
+///     let mut session = sessions.create().await?;
+///     session.insert("cart", vec!["book"])?;
+///     sessions.save(&mut session).await?;
+///
+///     // Signing in moves the session to a fresh id
+///     let anonymous_id = session.id().to_string();
+///     sessions.sign_in(&mut session, "SomeAccountName").await?;
+///
+///     assert!(sessions.load(&anonymous_id).await?.is_none());
+///
+///     let loaded = sessions.load(session.id()).await?.unwrap();
+///     assert_eq!(loaded.get::<Vec<String>>("cart")?, Some(vec!["book".to_string()]));
+///     assert_eq!(loaded.account(), Some("SomeAccountName"));
+///
+///     // Signing in on a second device, then signing that device out remotely
+///     let mut phone = sessions.create().await?;
+///     sessions.sign_in(&mut phone, "SomeAccountName").await?;
+///     assert_eq!(sessions.list("SomeAccountName").await?.len(), 2);
+///
+///     assert!(sessions.revoke("SomeAccountName", phone.id_hash()).await?);
+///     assert!(sessions.load(phone.id()).await?.is_none());
+///
+///     sessions.destroy(session.id()).await?;
+///     assert!(sessions.load(session.id()).await?.is_none());
+///
+///     // A destroyed session cannot be revived by rotating or saving it
+///     assert!(sessions.rotate(&mut session).await.is_err());
+///     assert!(sessions.save(&mut phone).await.is_err());
+///     assert!(sessions.load(phone.id()).await?.is_none());
+///
+///     // Per session timeouts, e.g. for a "remember me" sign in
+///     let week = Duration::from_secs(7 * 24 * 60 * 60);
+///     let remembered = sessions.create_with(week, 12 * week).await?;
+///     assert!(remembered.expires_at() > session.expires_at());
+///
+///     // Expired sessions are rejected on read
+///     let expired = sessions.create_with(Duration::ZERO, week).await?;
+///     assert!(sessions.load(expired.id()).await?.is_none());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct SessionManager<S, A = NoAudit, C = SystemClock> {
+    store: S,
+    idle_timeout: Duration,
+    max_lifetime: Duration,
+    audit: A,
+    clock: C,
+}
+
+impl<S: SessionStore> SessionManager<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            max_lifetime: DEFAULT_MAX_LIFETIME,
+            audit: NoAudit,
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<S: SessionStore, A: AuditSink, C: Clock> SessionManager<S, A, C> {
+    /// Record sign ins and revoked sessions to `audit`
+    pub fn audit<B: AuditSink>(self, audit: B) -> SessionManager<S, B, C> {
+        SessionManager {
+            store: self.store,
+            idle_timeout: self.idle_timeout,
+            max_lifetime: self.max_lifetime,
+            audit,
+            clock: self.clock,
+        }
+    }
+
+    /// Measure timeouts with `clock` instead of the system time, e.g. a `MockClock` in tests
+    ///
+    /// ### Example
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// use lonewolf_auth_toolkit::clock::MockClock;
+    /// use lonewolf_auth_toolkit::session::{MemorySessionStore, SessionManager};
+    ///
+    /// #[tokio::main]
+    /// pub async fn main() -> Result<(), anyhow::Error> {
+    ///     let clock = MockClock::at(1_700_000_000);
+    ///     let sessions = SessionManager::new(MemorySessionStore::default())
+    ///         .idle_timeout(Duration::from_secs(15 * 60))
+    ///         .clock(clock.clone());
+    ///
+    ///     let session = sessions.create().await?;
+    ///
+    ///     clock.advance(Duration::from_secs(14 * 60));
+    ///     assert!(sessions.load(session.id()).await?.is_some());
+    ///
+    ///     clock.advance(Duration::from_secs(2 * 60));
+    ///     assert!(sessions.load(session.id()).await?.is_none());
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn clock<D: Clock>(self, clock: D) -> SessionManager<S, A, D> {
+        SessionManager {
+            store: self.store,
+            idle_timeout: self.idle_timeout,
+            max_lifetime: self.max_lifetime,
+            audit: self.audit,
+            clock,
+        }
+    }
+
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    pub fn max_lifetime(mut self, max_lifetime: Duration) -> Self {
+        self.max_lifetime = max_lifetime;
+        self
+    }
+
+    /// Start and persist an empty session with the manager's timeouts
+    pub async fn create(&self) -> Result<Session, AuthError> {
+        self.create_with(self.idle_timeout, self.max_lifetime).await
+    }
+
+    /// Start and persist an empty session with its own timeouts
+    pub async fn create_with(
+        &self,
+        idle_timeout: Duration,
+        max_lifetime: Duration,
+    ) -> Result<Session, AuthError> {
+        let id = generate_token();
+        let now = self.clock.now()?;
+
+        let session = Session {
+            record: SessionRecord {
+                id_hash: hash_token(&id),
+                account: None,
+                data: Map::new(),
+                created_at: now,
+                last_seen_at: now,
+                idle_timeout: idle_timeout.as_secs(),
+                absolute_expires_at: now.saturating_add(max_lifetime.as_secs()),
+            },
+            id,
+        };
+
+        self.store.save(session.record.clone()).await?;
+
+        Ok(session)
+    }
+
+    /// The session with this id, or `None` if it does not exist or has expired
+    ///
+    /// Loading a session does not extend it; call `touch` or `save` to do that.
+    pub async fn load(&self, id: &str) -> Result<Option<Session>, AuthError> {
+        trace::instrument("session.load", &[], self.load_record(id)).await
+    }
+
+    async fn load_record(&self, id: &str) -> Result<Option<Session>, AuthError> {
+        let id_hash = hash_token(id);
+
+        let record = match self.store.load(&id_hash).await? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+
+        if record.is_expired(self.clock.now()?) {
+            self.store.delete(&id_hash).await?;
+
+            return Ok(None);
+        }
+
+        Ok(Some(Session {
+            id: id.to_string(),
+            record,
+        }))
+    }
+
+    /// Persist the session's data and reset its idle timeout
+    ///
+    /// Errors with `NotFound` if the session was destroyed or revoked in the meantime, in which
+    /// case nothing is saved and the caller should treat the request as signed out.
+    pub async fn save(&self, session: &mut Session) -> Result<(), AuthError> {
+        session.record.last_seen_at = self.clock.now()?;
+
+        if !self.store.update(session.record.clone()).await? {
+            return Err(AuthError::NotFound("Session no longer exists".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Reset the session's idle timeout, e.g. on each request
+    ///
+    /// The absolute lifetime is never extended. Errors with `NotFound` as `save` does.
+    pub async fn touch(&self, session: &mut Session) -> Result<(), AuthError> {
+        self.save(session).await
+    }
+
+    /// Move the session to a new id, keeping its data and invalidating the old id
+    ///
+    /// Errors with `NotFound` if the session was destroyed in the meantime, in which case the
+    /// session is left unchanged and the caller should start a new one.
+    pub async fn rotate(&self, session: &mut Session) -> Result<(), AuthError> {
+        let id = generate_token();
+
+        let mut record = session.record.clone();
+        record.id_hash = hash_token(&id);
+        record.last_seen_at = self.clock.now()?;
+
+        if !self
+            .store
+            .rotate(&session.record.id_hash, record.clone())
+            .await?
+        {
+            return Err(AuthError::NotFound("Session no longer exists".to_string()));
+        }
+
+        *session = Session { id, record };
+
+        Ok(())
+    }
+
+    /// Attach the session to an account and rotate its id
+    pub async fn sign_in(&self, session: &mut Session, account: &str) -> Result<(), AuthError> {
+        let previous = session.record.account.replace(account.to_string());
+
+        if let Err(error) = self.rotate(session).await {
+            session.record.account = previous;
+
+            return Err(error);
+        }
+
+        self.audit
+            .record(AuditEvent::new(AuditAction::SessionStarted)?.account(account))
+            .await
+    }
+
+    pub async fn destroy(&self, id: &str) -> Result<(), AuthError> {
+        self.store.delete(&hash_token(id)).await
+    }
+
+    /// The account's unexpired sessions, most recently used first
+    pub async fn list(&self, account: &str) -> Result<Vec<SessionRecord>, AuthError> {
+        let now = self.clock.now()?;
+
+        let mut records: Vec<_> = self
+            .store
+            .list(account)
+            .await?
+            .into_iter()
+            .filter(|record| !record.is_expired(now))
+            .collect();
+        records.sort_by_key(|record| Reverse(record.last_seen_at));
+
+        Ok(records)
+    }
+
+    /// Destroy one of the account's sessions by its `id_hash`, e.g. from an active sessions page
+    ///
+    /// Returns `false` if no such session belongs to the account.
+    pub async fn revoke(&self, account: &str, id_hash: &str) -> Result<bool, AuthError> {
+        match self.store.load(id_hash).await? {
+            Some(record) if record.account.as_deref() == Some(account) => {
+                self.store.delete(id_hash).await?;
+                self.record_revoked(account, "1 session").await?;
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Destroy every session of the account ("sign out everywhere"), returning how many there were
+    pub async fn revoke_all(&self, account: &str) -> Result<usize, AuthError> {
+        let revoked = self.store.delete_all(account).await?;
+        self.record_revoked(account, &format!("{} sessions", revoked))
+            .await?;
+
+        Ok(revoked)
+    }
+
+    /// Destroy every other session of the session's account, returning how many there were
+    pub async fn revoke_others(&self, session: &Session) -> Result<usize, AuthError> {
+        let account = match session.account() {
+            Some(account) => account,
+            None => return Ok(0),
+        };
+
+        let mut revoked = 0;
+        for record in self.store.list(account).await? {
+            if record.id_hash != session.record.id_hash {
+                self.store.delete(&record.id_hash).await?;
+                revoked += 1;
+            }
+        }
+
+        self.record_revoked(account, &format!("{} other sessions", revoked))
+            .await?;
+
+        Ok(revoked)
+    }
+
+    /// The current Unix timestamp in seconds by the manager's clock
+    pub fn now(&self) -> Result<u64, AuthError> {
+        self.clock.now()
+    }
+
+    async fn record_revoked(&self, account: &str, detail: &str) -> Result<(), AuthError> {
+        let event = AuditEvent::new(AuditAction::SessionRevoked)?
+            .account(account)
+            .detail(detail);
+
+        self.audit.record(event).await
+    }
+}
+
+/// Keeps sessions in process memory
+///
+/// Expired sessions stay until they are next loaded, when `SessionManager` deletes them.
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<HashMap<String, SessionRecord>>,
+}
+
+impl MemorySessionStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, SessionRecord>>, AuthError> {
+        self.sessions
+            .lock()
+            .map_err(|_| AuthError::backend("Session store lock poisoned"))
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    async fn load(&self, id_hash: &str) -> Result<Option<SessionRecord>, AuthError> {
+        Ok(self.lock()?.get(id_hash).cloned())
+    }
+
+    async fn save(&self, record: SessionRecord) -> Result<(), AuthError> {
+        self.lock()?.insert(record.id_hash.clone(), record);
+
+        Ok(())
+    }
+
+    async fn update(&self, record: SessionRecord) -> Result<bool, AuthError> {
+        match self.lock()?.get_mut(&record.id_hash) {
+            Some(current) => {
+                *current = record;
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn delete(&self, id_hash: &str) -> Result<(), AuthError> {
+        self.lock()?.remove(id_hash);
+
+        Ok(())
+    }
+
+    async fn rotate(&self, old_hash: &str, record: SessionRecord) -> Result<bool, AuthError> {
+        let mut sessions = self.lock()?;
+        if sessions.remove(old_hash).is_none() {
+            return Ok(false);
+        }
+
+        sessions.insert(record.id_hash.clone(), record);
+
+        Ok(true)
+    }
+
+    async fn list(&self, account: &str) -> Result<Vec<SessionRecord>, AuthError> {
+        Ok(self
+            .lock()?
+            .values()
+            .filter(|record| record.account.as_deref() == Some(account))
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_all(&self, account: &str) -> Result<usize, AuthError> {
+        let mut sessions = self.lock()?;
+        let before = sessions.len();
+        sessions.retain(|_, record| record.account.as_deref() != Some(account));
+
+        Ok(before - sessions.len())
+    }
+}
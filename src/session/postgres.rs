@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+
+use crate::{
+    postgres::{PostgresClient, Row},
+    token::now,
+    AuthError,
+};
+
+use super::{SessionRecord, SessionStore};
+
+const COLUMNS: &str =
+    "id_hash, account, data, created_at, last_seen_at, idle_timeout, absolute_expires_at";
+
+/// A `SessionStore` keeping sessions in the `auth_sessions` table created by
+/// `PostgresClient::migrate`
+///
+/// Sessions are indexed by account. `rotate` deletes the old row and inserts the new one in a
+/// single statement, so it is atomic without an explicit transaction and a session destroyed by
+/// another instance is never revived.
+///
+/// Postgres has no native expiry; call `delete_expired` periodically to remove abandoned
+/// sessions.
+///
+/// ### Example
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::postgres::{PostgresClient, PostgresConfig};
+/// use lonewolf_auth_toolkit::session::{postgres::PostgresSessionStore, SessionManager};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = PostgresConfig::new("postgres", "myapp").password("SomePassword");
+///     let postgres = Arc::new(PostgresClient::connect("127.0.0.1:5432", &config).await?);
+///     postgres.migrate().await?;
+///
+///     let sessions = SessionManager::new(PostgresSessionStore::new(postgres.clone()));
+///
+///     let mut session = sessions.create().await?;
+///     sessions.sign_in(&mut session, "SomeAccountName").await?;
+///     assert_eq!(sessions.list("SomeAccountName").await?.len(), 1);
+///
+///     // E.g. from a scheduled job
+///     PostgresSessionStore::new(postgres).delete_expired().await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct PostgresSessionStore<S = TcpStream> {
+    client: Arc<PostgresClient<S>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> PostgresSessionStore<S> {
+    pub fn new(client: Arc<PostgresClient<S>>) -> Self {
+        Self { client }
+    }
+
+    pub fn client(&self) -> &PostgresClient<S> {
+        &self.client
+    }
+
+    /// Delete every expired session, returning how many were deleted
+    pub async fn delete_expired(&self) -> Result<u64, AuthError> {
+        self.client
+            .execute(
+                "DELETE FROM auth_sessions WHERE expires_at <= $1::bigint",
+                &[Some(&now()?.to_string())],
+            )
+            .await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> SessionStore for PostgresSessionStore<S> {
+    async fn load(&self, id_hash: &str) -> Result<Option<SessionRecord>, AuthError> {
+        let sql = format!("SELECT {} FROM auth_sessions WHERE id_hash = $1", COLUMNS);
+
+        self.client
+            .query(&sql, &[Some(id_hash)])
+            .await?
+            .first()
+            .map(record)
+            .transpose()
+    }
+
+    async fn save(&self, record: SessionRecord) -> Result<(), AuthError> {
+        let params = Params::new(&record)?;
+
+        self.client
+            .execute(
+                "INSERT INTO auth_sessions (id_hash, account, data, created_at, last_seen_at, \
+                 idle_timeout, absolute_expires_at, expires_at) \
+                 VALUES ($1, $2, $3::jsonb, $4::bigint, $5::bigint, $6::bigint, $7::bigint, \
+                 $8::bigint) \
+                 ON CONFLICT (id_hash) DO UPDATE SET account = EXCLUDED.account, \
+                 data = EXCLUDED.data, last_seen_at = EXCLUDED.last_seen_at, \
+                 idle_timeout = EXCLUDED.idle_timeout, \
+                 absolute_expires_at = EXCLUDED.absolute_expires_at, \
+                 expires_at = EXCLUDED.expires_at",
+                &params.values(&record),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn update(&self, record: SessionRecord) -> Result<bool, AuthError> {
+        let params = Params::new(&record)?;
+
+        let updated = self
+            .client
+            .execute(
+                "UPDATE auth_sessions SET account = $2, data = $3::jsonb, \
+                 last_seen_at = $4::bigint, idle_timeout = $5::bigint, \
+                 absolute_expires_at = $6::bigint, expires_at = $7::bigint WHERE id_hash = $1",
+                &params.update_values(&record),
+            )
+            .await?;
+
+        Ok(updated == 1)
+    }
+
+    async fn delete(&self, id_hash: &str) -> Result<(), AuthError> {
+        self.client
+            .execute(
+                "DELETE FROM auth_sessions WHERE id_hash = $1",
+                &[Some(id_hash)],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn rotate(&self, old_hash: &str, record: SessionRecord) -> Result<bool, AuthError> {
+        let params = Params::new(&record)?;
+        let mut values = params.values(&record);
+        values.push(Some(old_hash));
+
+        let moved = self
+            .client
+            .execute(
+                "WITH old AS (DELETE FROM auth_sessions WHERE id_hash = $9 RETURNING id_hash) \
+                 INSERT INTO auth_sessions (id_hash, account, data, created_at, last_seen_at, \
+                 idle_timeout, absolute_expires_at, expires_at) \
+                 SELECT $1, $2, $3::jsonb, $4::bigint, $5::bigint, $6::bigint, $7::bigint, \
+                 $8::bigint FROM old",
+                &values,
+            )
+            .await?;
+
+        Ok(moved == 1)
+    }
+
+    async fn list(&self, account: &str) -> Result<Vec<SessionRecord>, AuthError> {
+        let sql = format!("SELECT {} FROM auth_sessions WHERE account = $1", COLUMNS);
+
+        self.client
+            .query(&sql, &[Some(account)])
+            .await?
+            .iter()
+            .map(record)
+            .collect()
+    }
+
+    async fn delete_all(&self, account: &str) -> Result<usize, AuthError> {
+        let deleted = self
+            .client
+            .execute(
+                "DELETE FROM auth_sessions WHERE account = $1",
+                &[Some(account)],
+            )
+            .await?;
+
+        Ok(usize::try_from(deleted)?)
+    }
+}
+
+/// A record's columns in text form, in `INSERT` order after `id_hash` and `account`
+struct Params([String; 6]);
+
+impl Params {
+    fn new(record: &SessionRecord) -> Result<Self, AuthError> {
+        Ok(Self([
+            serde_json::to_string(&record.data)?,
+            record.created_at.to_string(),
+            record.last_seen_at.to_string(),
+            record.idle_timeout.to_string(),
+            record.absolute_expires_at.to_string(),
+            record.expires_at().to_string(),
+        ]))
+    }
+
+    fn values<'a>(&'a self, record: &'a SessionRecord) -> Vec<Option<&'a str>> {
+        let mut values = vec![Some(record.id_hash.as_str()), record.account.as_deref()];
+        values.extend(self.0.iter().map(|value| Some(value.as_str())));
+
+        values
+    }
+
+    /// The values for an `UPDATE`: `id_hash`, `account`, then every column but `created_at`
+    fn update_values<'a>(&'a self, record: &'a SessionRecord) -> Vec<Option<&'a str>> {
+        let mut values = vec![Some(record.id_hash.as_str()), record.account.as_deref()];
+        values.push(Some(self.0[0].as_str()));
+        values.extend(self.0[2..].iter().map(|value| Some(value.as_str())));
+
+        values
+    }
+}
+
+fn record(row: &Row) -> Result<SessionRecord, AuthError> {
+    Ok(SessionRecord {
+        id_hash: row.text(0)?.to_string(),
+        account: row.get(1).map(str::to_string),
+        data: serde_json::from_str(row.text(2)?)?,
+        created_at: u64::try_from(row.int(3)?)?,
+        last_seen_at: u64::try_from(row.int(4)?)?,
+        idle_timeout: u64::try_from(row.int(5)?)?,
+        absolute_expires_at: u64::try_from(row.int(6)?)?,
+    })
+}
@@ -0,0 +1,267 @@
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+
+use crate::{
+    redis::{RedisClient, Reply},
+    AuthError,
+};
+
+use super::{SessionRecord, SessionStore};
+
+/// Prefix for session keys unless another is set
+pub const DEFAULT_PREFIX: &str = "session:";
+
+/// Moves a session to a new key only if the old key still exists, updating the account index
+const ROTATE_SCRIPT: &str = r#"
+if redis.call("DEL", KEYS[1]) == 0 then
+    return 0
+end
+redis.call("SET", KEYS[2], ARGV[1], "EXAT", ARGV[2])
+if KEYS[3] then
+    redis.call("SREM", KEYS[3], ARGV[3])
+    redis.call("SADD", KEYS[3], ARGV[4])
+end
+return 1
+"#;
+
+/// Replaces a session only if its key still exists, adding it to the account index
+const UPDATE_SCRIPT: &str = r#"
+if not redis.call("SET", KEYS[1], ARGV[1], "XX", "EXAT", ARGV[2]) then
+    return 0
+end
+if KEYS[2] then
+    redis.call("SADD", KEYS[2], ARGV[3])
+end
+return 1
+"#;
+
+/// Deletes every session in an account index along with the index itself
+const DELETE_ALL_SCRIPT: &str = r#"
+local deleted = 0
+for _, id_hash in ipairs(redis.call("SMEMBERS", KEYS[1])) do
+    deleted = deleted + redis.call("DEL", ARGV[1] .. id_hash)
+end
+redis.call("DEL", KEYS[1])
+return deleted
+"#;
+
+/// A `SessionStore` keeping each session as a JSON string under `<prefix><id hash>`
+///
+/// Signed in sessions are also indexed in a set under `<prefix>account:<account>`. Deleting a
+/// single session leaves its hash in the set; `list` prunes hashes whose session has gone.
+///
+/// Keys are written with `EXAT` so Redis drops sessions once they expire, which requires Redis
+/// 6.2 or later. `rotate` and `update` run as Lua scripts, which Redis executes atomically, so
+/// every instance sharing the Redis server sees either the old session or the new one and a
+/// session destroyed by another instance is never revived.
+///
+/// ### Example
+/// ```rust,no_run
+/// use lonewolf_auth_toolkit::redis::RedisClient;
+/// use lonewolf_auth_toolkit::session::{redis::RedisSessionStore, SessionManager};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let redis = RedisClient::connect("127.0.0.1:6379").await?;
+///     let sessions = SessionManager::new(RedisSessionStore::new(redis).prefix("myapp:session:"));
+///
+///     let mut session = sessions.create().await?;
+///     session.insert("account", "SomeAccountName")?;
+///     sessions.save(&mut session).await?;
+///
+///     assert!(sessions.load(session.id()).await?.is_some());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RedisSessionStore<S = TcpStream> {
+    client: RedisClient<S>,
+    prefix: String,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> RedisSessionStore<S> {
+    pub fn new(client: RedisClient<S>) -> Self {
+        Self {
+            client,
+            prefix: DEFAULT_PREFIX.to_string(),
+        }
+    }
+
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn client(&self) -> &RedisClient<S> {
+        &self.client
+    }
+
+    fn key(&self, id_hash: &str) -> Vec<u8> {
+        format!("{}{}", self.prefix, id_hash).into_bytes()
+    }
+
+    fn index(&self, account: &str) -> Vec<u8> {
+        format!("{}account:{}", self.prefix, account).into_bytes()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> SessionStore for RedisSessionStore<S> {
+    async fn load(&self, id_hash: &str) -> Result<Option<SessionRecord>, AuthError> {
+        match self.client.query(&[b"GET", &self.key(id_hash)]).await? {
+            Reply::Bulk(Some(json)) => Ok(Some(serde_json::from_slice(&json)?)),
+            Reply::Bulk(None) => Ok(None),
+            _ => Err(AuthError::backend("Unexpected reply to GET")),
+        }
+    }
+
+    async fn save(&self, record: SessionRecord) -> Result<(), AuthError> {
+        let json = serde_json::to_vec(&record)?;
+        let expires_at = record.expires_at().to_string();
+        let key = self.key(&record.id_hash);
+        let set: &[&[u8]] = &[b"SET", &key, &json, b"EXAT", expires_at.as_bytes()];
+
+        let account = match &record.account {
+            Some(account) => account,
+            None => {
+                self.client.query(set).await?;
+
+                return Ok(());
+            }
+        };
+
+        let replies = self
+            .client
+            .pipeline(&[
+                &[b"MULTI"],
+                set,
+                &[b"SADD", &self.index(account), record.id_hash.as_bytes()],
+                &[b"EXEC"],
+            ])
+            .await?;
+
+        match replies.last() {
+            Some(Reply::Array(Some(_))) => Ok(()),
+            _ => Err(AuthError::backend("Saving the session was aborted")),
+        }
+    }
+
+    async fn update(&self, record: SessionRecord) -> Result<bool, AuthError> {
+        let json = serde_json::to_vec(&record)?;
+        let expires_at = record.expires_at().to_string();
+        let key = self.key(&record.id_hash);
+        let index = record.account.as_deref().map(|account| self.index(account));
+
+        let mut command: Vec<&[u8]> = vec![b"EVAL", UPDATE_SCRIPT.as_bytes()];
+        match &index {
+            Some(index) => command.extend([b"2".as_slice(), &key, index]),
+            None => command.extend([b"1".as_slice(), &key]),
+        }
+        command.extend([
+            json.as_slice(),
+            expires_at.as_bytes(),
+            record.id_hash.as_bytes(),
+        ]);
+
+        match self.client.query(&command).await? {
+            Reply::Integer(updated) => Ok(updated == 1),
+            _ => Err(AuthError::backend("Unexpected reply to session update")),
+        }
+    }
+
+    async fn delete(&self, id_hash: &str) -> Result<(), AuthError> {
+        self.client.query(&[b"DEL", &self.key(id_hash)]).await?;
+
+        Ok(())
+    }
+
+    async fn rotate(&self, old_hash: &str, record: SessionRecord) -> Result<bool, AuthError> {
+        let json = serde_json::to_vec(&record)?;
+        let expires_at = record.expires_at().to_string();
+        let old_key = self.key(old_hash);
+        let new_key = self.key(&record.id_hash);
+        let index = record.account.as_deref().map(|account| self.index(account));
+
+        let mut command: Vec<&[u8]> = vec![b"EVAL", ROTATE_SCRIPT.as_bytes()];
+        match &index {
+            Some(index) => command.extend([b"3".as_slice(), &old_key, &new_key, index]),
+            None => command.extend([b"2".as_slice(), &old_key, &new_key]),
+        }
+        command.extend([
+            json.as_slice(),
+            expires_at.as_bytes(),
+            old_hash.as_bytes(),
+            record.id_hash.as_bytes(),
+        ]);
+
+        match self.client.query(&command).await? {
+            Reply::Integer(moved) => Ok(moved == 1),
+            _ => Err(AuthError::backend("Unexpected reply to session rotation")),
+        }
+    }
+
+    async fn list(&self, account: &str) -> Result<Vec<SessionRecord>, AuthError> {
+        let index = self.index(account);
+
+        let hashes = match self.client.query(&[b"SMEMBERS", &index]).await? {
+            Reply::Array(Some(members)) => members
+                .into_iter()
+                .filter_map(|member| match member {
+                    Reply::Bulk(Some(hash)) => Some(hash),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            _ => return Err(AuthError::backend("Unexpected reply to SMEMBERS")),
+        };
+
+        if hashes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let keys: Vec<_> = hashes
+            .iter()
+            .map(|hash| [self.prefix.as_bytes(), hash].concat())
+            .collect();
+        let mut command: Vec<&[u8]> = vec![b"MGET"];
+        command.extend(keys.iter().map(Vec::as_slice));
+
+        let values = match self.client.query(&command).await? {
+            Reply::Array(Some(values)) => values,
+            _ => return Err(AuthError::backend("Unexpected reply to MGET")),
+        };
+
+        let mut records = Vec::new();
+        let mut stale: Vec<&[u8]> = vec![b"SREM", &index];
+        for (hash, value) in hashes.iter().zip(values) {
+            match value {
+                Reply::Bulk(Some(json)) => records.push(serde_json::from_slice(&json)?),
+                _ => stale.push(hash),
+            }
+        }
+
+        if stale.len() > 2 {
+            self.client.query(&stale).await?;
+        }
+
+        Ok(records)
+    }
+
+    async fn delete_all(&self, account: &str) -> Result<usize, AuthError> {
+        let reply = self
+            .client
+            .query(&[
+                b"EVAL",
+                DELETE_ALL_SCRIPT.as_bytes(),
+                b"1",
+                &self.index(account),
+                self.prefix.as_bytes(),
+            ])
+            .await?;
+
+        match reply {
+            Reply::Integer(deleted) => Ok(usize::try_from(deleted)?),
+            _ => Err(AuthError::backend("Unexpected reply to deleting sessions")),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use crate::{
+    sqlite::{Row, SqliteDatabase},
+    token::now,
+    AuthError,
+};
+
+use super::{SessionRecord, SessionStore};
+
+const COLUMNS: &str =
+    "id_hash, account, data, created_at, last_seen_at, idle_timeout, absolute_expires_at";
+
+const INSERT: &str = "INSERT INTO auth_sessions (id_hash, account, data, created_at, \
+                      last_seen_at, idle_timeout, absolute_expires_at, expires_at) \
+                      VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)";
+
+/// A `SessionStore` keeping sessions in the `auth_sessions` table created by
+/// `SqliteDatabase::migrate`
+///
+/// Sessions are indexed by account. `rotate` runs in an immediate transaction, so processes
+/// sharing the database file see either the old session or the new one and a session destroyed
+/// by another process is never revived.
+///
+/// SQLite has no native expiry; call `delete_expired` periodically to remove abandoned sessions.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::session::{sqlite::SqliteSessionStore, SessionManager};
+/// use lonewolf_auth_toolkit::sqlite::SqliteDatabase;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let database = Arc::new(SqliteDatabase::open_in_memory()?);
+///     database.migrate()?;
+///
+///     let sessions = SessionManager::new(SqliteSessionStore::new(database.clone()));
+///
+///     let mut session = sessions.create().await?;
+///     sessions.sign_in(&mut session, "SomeAccountName").await?;
+///     assert_eq!(sessions.list("SomeAccountName").await?.len(), 1);
+///
+///     session.insert("theme", "dark")?;
+///     sessions.save(&mut session).await?;
+///     let loaded = sessions.load(session.id()).await?.unwrap();
+///     assert_eq!(loaded.get::<String>("theme")?.as_deref(), Some("dark"));
+///
+///     // Saving a session signed out elsewhere does not bring it back
+///     sessions.revoke_all("SomeAccountName").await?;
+///     assert!(sessions.save(&mut session).await.is_err());
+///
+///     // E.g. from a scheduled job
+///     SqliteSessionStore::new(database).delete_expired().await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct SqliteSessionStore {
+    database: Arc<SqliteDatabase>,
+}
+
+impl SqliteSessionStore {
+    pub fn new(database: Arc<SqliteDatabase>) -> Self {
+        Self { database }
+    }
+
+    pub fn database(&self) -> &SqliteDatabase {
+        &self.database
+    }
+
+    /// Delete every expired session, returning how many were deleted
+    pub async fn delete_expired(&self) -> Result<u64, AuthError> {
+        self.database.execute(
+            "DELETE FROM auth_sessions WHERE expires_at <= ?1",
+            &[Some(&now()?.to_string())],
+        )
+    }
+}
+
+impl SessionStore for SqliteSessionStore {
+    async fn load(&self, id_hash: &str) -> Result<Option<SessionRecord>, AuthError> {
+        let sql = format!("SELECT {} FROM auth_sessions WHERE id_hash = ?1", COLUMNS);
+
+        self.database
+            .query(&sql, &[Some(id_hash)])?
+            .first()
+            .map(record)
+            .transpose()
+    }
+
+    async fn save(&self, record: SessionRecord) -> Result<(), AuthError> {
+        let params = Params::new(&record)?;
+        let sql = format!(
+            "{} ON CONFLICT (id_hash) DO UPDATE SET account = excluded.account, \
+             data = excluded.data, last_seen_at = excluded.last_seen_at, \
+             idle_timeout = excluded.idle_timeout, \
+             absolute_expires_at = excluded.absolute_expires_at, expires_at = excluded.expires_at",
+            INSERT
+        );
+
+        self.database.execute(&sql, &params.values(&record))?;
+
+        Ok(())
+    }
+
+    async fn update(&self, record: SessionRecord) -> Result<bool, AuthError> {
+        let params = Params::new(&record)?;
+
+        let updated = self.database.execute(
+            "UPDATE auth_sessions SET account = ?2, data = ?3, last_seen_at = ?4, \
+             idle_timeout = ?5, absolute_expires_at = ?6, expires_at = ?7 WHERE id_hash = ?1",
+            &params.update_values(&record),
+        )?;
+
+        Ok(updated == 1)
+    }
+
+    async fn delete(&self, id_hash: &str) -> Result<(), AuthError> {
+        self.database.execute(
+            "DELETE FROM auth_sessions WHERE id_hash = ?1",
+            &[Some(id_hash)],
+        )?;
+
+        Ok(())
+    }
+
+    async fn rotate(&self, old_hash: &str, record: SessionRecord) -> Result<bool, AuthError> {
+        let params = Params::new(&record)?;
+
+        self.database.transaction(|connection| {
+            let deleted = connection.execute(
+                "DELETE FROM auth_sessions WHERE id_hash = ?1",
+                &[Some(old_hash)],
+            )?;
+            if deleted == 0 {
+                return Ok(false);
+            }
+
+            connection.execute(INSERT, &params.values(&record))?;
+
+            Ok(true)
+        })
+    }
+
+    async fn list(&self, account: &str) -> Result<Vec<SessionRecord>, AuthError> {
+        let sql = format!("SELECT {} FROM auth_sessions WHERE account = ?1", COLUMNS);
+
+        self.database
+            .query(&sql, &[Some(account)])?
+            .iter()
+            .map(record)
+            .collect()
+    }
+
+    async fn delete_all(&self, account: &str) -> Result<usize, AuthError> {
+        let deleted = self.database.execute(
+            "DELETE FROM auth_sessions WHERE account = ?1",
+            &[Some(account)],
+        )?;
+
+        Ok(usize::try_from(deleted)?)
+    }
+}
+
+/// A record's columns in text form, in `INSERT` order after `id_hash` and `account`
+struct Params([String; 6]);
+
+impl Params {
+    fn new(record: &SessionRecord) -> Result<Self, AuthError> {
+        Ok(Self([
+            serde_json::to_string(&record.data)?,
+            record.created_at.to_string(),
+            record.last_seen_at.to_string(),
+            record.idle_timeout.to_string(),
+            record.absolute_expires_at.to_string(),
+            record.expires_at().to_string(),
+        ]))
+    }
+
+    fn values<'a>(&'a self, record: &'a SessionRecord) -> Vec<Option<&'a str>> {
+        let mut values = vec![Some(record.id_hash.as_str()), record.account.as_deref()];
+        values.extend(self.0.iter().map(|value| Some(value.as_str())));
+
+        values
+    }
+
+    /// The values for an `UPDATE`: `id_hash`, `account`, then every column but `created_at`
+    fn update_values<'a>(&'a self, record: &'a SessionRecord) -> Vec<Option<&'a str>> {
+        let mut values = vec![Some(record.id_hash.as_str()), record.account.as_deref()];
+        values.push(Some(self.0[0].as_str()));
+        values.extend(self.0[2..].iter().map(|value| Some(value.as_str())));
+
+        values
+    }
+}
+
+fn record(row: &Row) -> Result<SessionRecord, AuthError> {
+    Ok(SessionRecord {
+        id_hash: row.text(0)?.to_string(),
+        account: row.get(1).map(str::to_string),
+        data: serde_json::from_str(row.text(2)?)?,
+        created_at: u64::try_from(row.int(3)?)?,
+        last_seen_at: u64::try_from(row.int(4)?)?,
+        idle_timeout: u64::try_from(row.int(5)?)?,
+        absolute_expires_at: u64::try_from(row.int(6)?)?,
+    })
+}
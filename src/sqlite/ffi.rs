@@ -0,0 +1,70 @@
+// The parts of the SQLite C API `SqliteDatabase` uses, linked against the system library
+
+use std::ffi::{c_char, c_int, c_void};
+
+pub(super) const SQLITE_OK: c_int = 0;
+pub(super) const SQLITE_ROW: c_int = 100;
+pub(super) const SQLITE_DONE: c_int = 101;
+pub(super) const SQLITE_NULL: c_int = 5;
+
+pub(super) const SQLITE_OPEN_READWRITE: c_int = 0x0000_0002;
+pub(super) const SQLITE_OPEN_CREATE: c_int = 0x0000_0004;
+pub(super) const SQLITE_OPEN_FULLMUTEX: c_int = 0x0001_0000;
+
+/// `SQLITE_TRANSIENT`: SQLite copies bound values before the call returns
+pub(super) const SQLITE_TRANSIENT: isize = -1;
+
+#[repr(C)]
+pub(super) struct Sqlite3 {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+pub(super) struct Sqlite3Stmt {
+    _private: [u8; 0],
+}
+
+#[link(name = "sqlite3")]
+extern "C" {
+    pub(super) fn sqlite3_open_v2(
+        filename: *const c_char,
+        db: *mut *mut Sqlite3,
+        flags: c_int,
+        vfs: *const c_char,
+    ) -> c_int;
+    pub(super) fn sqlite3_close_v2(db: *mut Sqlite3) -> c_int;
+    pub(super) fn sqlite3_errmsg(db: *mut Sqlite3) -> *const c_char;
+    pub(super) fn sqlite3_busy_timeout(db: *mut Sqlite3, ms: c_int) -> c_int;
+    pub(super) fn sqlite3_changes(db: *mut Sqlite3) -> c_int;
+    pub(super) fn sqlite3_exec(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        callback: *const c_void,
+        argument: *mut c_void,
+        error: *mut *mut c_char,
+    ) -> c_int;
+    pub(super) fn sqlite3_free(pointer: *mut c_void);
+
+    pub(super) fn sqlite3_prepare_v2(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        len: c_int,
+        statement: *mut *mut Sqlite3Stmt,
+        tail: *mut *const c_char,
+    ) -> c_int;
+    pub(super) fn sqlite3_bind_parameter_count(statement: *mut Sqlite3Stmt) -> c_int;
+    pub(super) fn sqlite3_bind_text(
+        statement: *mut Sqlite3Stmt,
+        index: c_int,
+        value: *const c_char,
+        len: c_int,
+        destructor: isize,
+    ) -> c_int;
+    pub(super) fn sqlite3_bind_null(statement: *mut Sqlite3Stmt, index: c_int) -> c_int;
+    pub(super) fn sqlite3_step(statement: *mut Sqlite3Stmt) -> c_int;
+    pub(super) fn sqlite3_column_count(statement: *mut Sqlite3Stmt) -> c_int;
+    pub(super) fn sqlite3_column_type(statement: *mut Sqlite3Stmt, column: c_int) -> c_int;
+    pub(super) fn sqlite3_column_text(statement: *mut Sqlite3Stmt, column: c_int) -> *const u8;
+    pub(super) fn sqlite3_column_bytes(statement: *mut Sqlite3Stmt, column: c_int) -> c_int;
+    pub(super) fn sqlite3_finalize(statement: *mut Sqlite3Stmt) -> c_int;
+}
@@ -0,0 +1,44 @@
+-- Users, their credentials, sessions and opaque tokens. Timestamps are Unix seconds and booleans
+-- are 0 or 1.
+
+CREATE TABLE IF NOT EXISTS auth_users (
+    id TEXT PRIMARY KEY NOT NULL,
+    email TEXT NOT NULL UNIQUE,
+    username TEXT UNIQUE,
+    email_verified INTEGER NOT NULL DEFAULT 0,
+    disabled INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS auth_credentials (
+    user_id TEXT PRIMARY KEY NOT NULL,
+    password_hash TEXT,
+    totp_secret TEXT,
+    mfa TEXT NOT NULL DEFAULT 'disabled' CHECK (mfa IN ('disabled', 'pending', 'enabled')),
+    last_totp_step INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS auth_sessions (
+    id_hash TEXT PRIMARY KEY NOT NULL,
+    account TEXT,
+    data TEXT NOT NULL DEFAULT '{}',
+    created_at INTEGER NOT NULL,
+    last_seen_at INTEGER NOT NULL,
+    idle_timeout INTEGER NOT NULL,
+    absolute_expires_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS auth_sessions_account ON auth_sessions (account);
+CREATE INDEX IF NOT EXISTS auth_sessions_expires_at ON auth_sessions (expires_at);
+
+CREATE TABLE IF NOT EXISTS auth_opaque_tokens (
+    hash TEXT PRIMARY KEY NOT NULL,
+    purpose TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS auth_opaque_tokens_subject ON auth_opaque_tokens (purpose, subject);
+CREATE INDEX IF NOT EXISTS auth_opaque_tokens_expires_at ON auth_opaque_tokens (expires_at);
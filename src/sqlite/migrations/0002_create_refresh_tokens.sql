@@ -0,0 +1,14 @@
+-- Refresh tokens, kept as SHA-256 hashes and grouped into families. Timestamps are Unix seconds
+-- and booleans are 0 or 1.
+
+CREATE TABLE IF NOT EXISTS auth_refresh_tokens (
+    hash TEXT PRIMARY KEY NOT NULL,
+    family TEXT NOT NULL,
+    parent TEXT,
+    account TEXT NOT NULL,
+    expires_at INTEGER NOT NULL,
+    rotated INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS auth_refresh_tokens_family ON auth_refresh_tokens (family);
+CREATE INDEX IF NOT EXISTS auth_refresh_tokens_expires_at ON auth_refresh_tokens (expires_at);
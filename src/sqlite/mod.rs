@@ -0,0 +1,356 @@
+mod ffi;
+
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    path::Path,
+    ptr,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::AuthError;
+
+use ffi::*;
+
+/// Migrations creating the tables the SQLite backed stores use, in order
+///
+/// `SqliteDatabase::migrate` applies the ones a database has not seen yet.
+pub const MIGRATIONS: &[(i32, &str)] = &[
+    (1, include_str!("migrations/0001_create_auth.sql")),
+    (2, include_str!("migrations/0002_create_refresh_tokens.sql")),
+];
+
+/// How long a write waits for another connection's lock before failing
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One row of a result, every column converted to text
+///
+/// Integers and booleans (stored as `0` and `1`) come back as their decimal text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row(pub Vec<Option<String>>);
+
+impl Row {
+    /// The column's value, or `None` for `NULL` and missing columns
+    pub fn get(&self, column: usize) -> Option<&str> {
+        self.0.get(column)?.as_deref()
+    }
+
+    /// Like `get`, failing with `AuthError::Backend` for `NULL`
+    pub fn text(&self, column: usize) -> Result<&str, AuthError> {
+        self.get(column)
+            .ok_or_else(|| AuthError::backend(format!("Column {} is NULL", column)))
+    }
+
+    pub fn int(&self, column: usize) -> Result<i64, AuthError> {
+        self.text(column)?
+            .parse()
+            .map_err(|_| AuthError::backend(format!("Column {} is not an integer", column)))
+    }
+
+    pub fn bool(&self, column: usize) -> Result<bool, AuthError> {
+        Ok(self.int(column)? != 0)
+    }
+}
+
+/// An open SQLite connection; borrowed inside `SqliteDatabase::transaction`
+pub struct Connection {
+    db: *mut Sqlite3,
+}
+
+// The connection is opened in serialized mode and only ever used behind `SqliteDatabase`'s lock
+unsafe impl Send for Connection {}
+
+impl Connection {
+    /// Run a statement with `?1`, `?2`, ... parameters and return its rows
+    pub fn query(&self, sql: &str, params: &[Option<&str>]) -> Result<Vec<Row>, AuthError> {
+        Ok(self.run(sql, params)?.0)
+    }
+
+    /// Run a statement and return how many rows it inserted, updated or deleted
+    pub fn execute(&self, sql: &str, params: &[Option<&str>]) -> Result<u64, AuthError> {
+        Ok(self.run(sql, params)?.1)
+    }
+
+    /// Run several `;` separated statements without parameters, e.g. a migration
+    pub fn batch(&self, sql: &str) -> Result<(), AuthError> {
+        let sql = c_string(sql)?;
+        let mut message: *mut c_char = ptr::null_mut();
+
+        let code = unsafe {
+            sqlite3_exec(
+                self.db,
+                sql.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                &mut message,
+            )
+        };
+
+        if code == SQLITE_OK {
+            return Ok(());
+        }
+
+        if message.is_null() {
+            return Err(self.error());
+        }
+
+        let error = unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { sqlite3_free(message.cast()) };
+
+        Err(AuthError::backend(error))
+    }
+
+    fn run(&self, sql: &str, params: &[Option<&str>]) -> Result<(Vec<Row>, u64), AuthError> {
+        let sql = c_string(sql)?;
+        let mut raw = ptr::null_mut();
+
+        let code =
+            unsafe { sqlite3_prepare_v2(self.db, sql.as_ptr(), -1, &mut raw, ptr::null_mut()) };
+        if code != SQLITE_OK {
+            return Err(self.error());
+        }
+        if raw.is_null() {
+            return Err(AuthError::InvalidInput("SQL has no statement".to_string()));
+        }
+        let statement = Statement(raw);
+
+        if usize::try_from(unsafe { sqlite3_bind_parameter_count(raw) })? != params.len() {
+            return Err(AuthError::InvalidInput(format!(
+                "Statement takes a different number of parameters than the {} given",
+                params.len()
+            )));
+        }
+
+        for (index, param) in params.iter().enumerate() {
+            let index = c_int::try_from(index + 1)?;
+            let code = match param {
+                Some(value) => unsafe {
+                    sqlite3_bind_text(
+                        raw,
+                        index,
+                        value.as_ptr().cast(),
+                        c_int::try_from(value.len())?,
+                        SQLITE_TRANSIENT,
+                    )
+                },
+                None => unsafe { sqlite3_bind_null(raw, index) },
+            };
+
+            if code != SQLITE_OK {
+                return Err(self.error());
+            }
+        }
+
+        let mut rows = Vec::new();
+        loop {
+            match unsafe { sqlite3_step(raw) } {
+                SQLITE_ROW => rows.push(statement.row()?),
+                SQLITE_DONE => break,
+                _ => return Err(self.error()),
+            }
+        }
+
+        let changes = u64::try_from(unsafe { sqlite3_changes(self.db) })?;
+
+        Ok((rows, changes))
+    }
+
+    fn error(&self) -> AuthError {
+        let message = unsafe { CStr::from_ptr(sqlite3_errmsg(self.db)) };
+
+        AuthError::backend(message.to_string_lossy().into_owned())
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe { sqlite3_close_v2(self.db) };
+    }
+}
+
+struct Statement(*mut Sqlite3Stmt);
+
+impl Statement {
+    fn row(&self) -> Result<Row, AuthError> {
+        let count = unsafe { sqlite3_column_count(self.0) };
+        let mut columns = Vec::with_capacity(usize::try_from(count)?);
+
+        for column in 0..count {
+            if unsafe { sqlite3_column_type(self.0, column) } == SQLITE_NULL {
+                columns.push(None);
+                continue;
+            }
+
+            // Text must be fetched before its length, which may change with the conversion
+            let text = unsafe { sqlite3_column_text(self.0, column) };
+            let len = usize::try_from(unsafe { sqlite3_column_bytes(self.0, column) })?;
+            let bytes = if text.is_null() {
+                Vec::new()
+            } else {
+                unsafe { std::slice::from_raw_parts(text, len) }.to_vec()
+            };
+
+            columns.push(Some(String::from_utf8(bytes)?));
+        }
+
+        Ok(Row(columns))
+    }
+}
+
+impl Drop for Statement {
+    fn drop(&mut self) {
+        unsafe { sqlite3_finalize(self.0) };
+    }
+}
+
+/// A SQLite database for the SQLite backed stores, linked against the system's `libsqlite3`
+///
+/// Suits desktop apps, tests and small self hosted deployments. Statements use bound text
+/// parameters, so values are never interpolated into SQL; SQLite converts them to the column's
+/// type. Queries run synchronously on the calling thread behind a lock, and errors become
+/// `AuthError::Backend`.
+///
+/// The SQLite backed stores are async only to fit their traits: they call into SQLite directly,
+/// so each statement blocks the executor thread that polls the store. A write waiting for another
+/// process's lock on the file, including the `BEGIN IMMEDIATE` of `transaction`, blocks it for up
+/// to `DEFAULT_BUSY_TIMEOUT`, and so does waiting for this database's own lock. Keep the file
+/// on a local disk and give the runtime spare worker threads, or use the Postgres stores for
+/// servers with many concurrent requests.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::sqlite::SqliteDatabase;
+///
+/// let database = SqliteDatabase::open_in_memory()?;
+/// database.migrate()?;
+///
+/// let rows = database.query("SELECT ?1 + 1", &[Some("41")])?;
+/// assert_eq!(rows[0].get(0), Some("42"));
+///
+/// let rolled_back = database.transaction(|connection| {
+///     connection.execute("DELETE FROM auth_users", &[])?;
+///     connection.query("SELECT no_such_column FROM auth_users", &[])
+/// });
+/// assert!(rolled_back.is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+pub struct SqliteDatabase {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteDatabase {
+    /// Open or create the database file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AuthError> {
+        let path = path
+            .as_ref()
+            .to_str()
+            .ok_or_else(|| AuthError::InvalidInput("Path is not valid UTF-8".to_string()))?;
+
+        Self::open_with(path)
+    }
+
+    /// A private database that disappears when dropped
+    pub fn open_in_memory() -> Result<Self, AuthError> {
+        Self::open_with(":memory:")
+    }
+
+    fn open_with(filename: &str) -> Result<Self, AuthError> {
+        let filename = c_string(filename)?;
+        let mut db = ptr::null_mut();
+        let flags = SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE | SQLITE_OPEN_FULLMUTEX;
+
+        let code = unsafe { sqlite3_open_v2(filename.as_ptr(), &mut db, flags, ptr::null()) };
+        if db.is_null() {
+            return Err(AuthError::backend("SQLite could not allocate a connection"));
+        }
+
+        // Closed on drop, also when opening failed
+        let connection = Connection { db };
+        if code != SQLITE_OK {
+            return Err(connection.error());
+        }
+
+        let timeout = c_int::try_from(DEFAULT_BUSY_TIMEOUT.as_millis())?;
+        unsafe { sqlite3_busy_timeout(db, timeout) };
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn query(&self, sql: &str, params: &[Option<&str>]) -> Result<Vec<Row>, AuthError> {
+        self.lock()?.query(sql, params)
+    }
+
+    pub fn execute(&self, sql: &str, params: &[Option<&str>]) -> Result<u64, AuthError> {
+        self.lock()?.execute(sql, params)
+    }
+
+    pub fn batch(&self, sql: &str) -> Result<(), AuthError> {
+        self.lock()?.batch(sql)
+    }
+
+    /// Run `f` in a transaction, committing if it succeeds and rolling back if it fails
+    pub fn transaction<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> Result<T, AuthError>,
+    ) -> Result<T, AuthError> {
+        let connection = self.lock()?;
+        connection.batch("BEGIN IMMEDIATE")?;
+
+        match f(&connection) {
+            Ok(value) => {
+                connection.batch("COMMIT")?;
+                Ok(value)
+            }
+            Err(error) => {
+                connection.batch("ROLLBACK")?;
+                Err(error)
+            }
+        }
+    }
+
+    /// Apply the `MIGRATIONS` the database has not seen yet, each in its own transaction
+    pub fn migrate(&self) -> Result<(), AuthError> {
+        self.batch(
+            "CREATE TABLE IF NOT EXISTS auth_migrations (version INTEGER PRIMARY KEY, \
+             applied_at INTEGER NOT NULL)",
+        )?;
+
+        let applied = self
+            .query("SELECT version FROM auth_migrations", &[])?
+            .iter()
+            .map(|row| row.int(0))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (version, sql) in MIGRATIONS {
+            if applied.contains(&i64::from(*version)) {
+                continue;
+            }
+
+            self.transaction(|connection| {
+                connection.batch(sql)?;
+                connection.execute(
+                    "INSERT INTO auth_migrations (version, applied_at) \
+                     VALUES (?1, strftime('%s', 'now'))",
+                    &[Some(&version.to_string())],
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Connection>, AuthError> {
+        self.connection
+            .lock()
+            .map_err(|_| AuthError::backend("SQLite connection lock poisoned"))
+    }
+}
+
+fn c_string(value: &str) -> Result<CString, AuthError> {
+    CString::new(value)
+        .map_err(|_| AuthError::InvalidInput("Value contains a NUL byte".to_string()))
+}
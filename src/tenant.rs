@@ -0,0 +1,315 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+#[cfg(feature = "mfa")]
+use crate::mfa::{
+    code::{CodeStore, PendingCode},
+    drift::DriftStore,
+    replay::UsedStepStore,
+};
+#[cfg(feature = "session")]
+use crate::session::{SessionRecord, SessionStore};
+use crate::{rate_limit::AttemptStore, AuthError};
+
+/// Longest tenant id accepted
+pub const MAX_TENANT_ID_LEN: usize = 64;
+
+/// Identifies one customer of a multi-tenant platform
+///
+/// Ids are 1 to 64 ASCII letters, digits, `-` or `_`, so one can prefix store keys without two
+/// tenants' keys ever colliding.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TenantId(String);
+
+impl TenantId {
+    pub fn new(id: impl Into<String>) -> Result<Self, AuthError> {
+        let id = id.into();
+
+        if id.is_empty()
+            || id.len() > MAX_TENANT_ID_LEN
+            || !id
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+        {
+            return Err(AuthError::InvalidInput(format!(
+                "Tenant ids are 1 to {} letters, digits, - or _",
+                MAX_TENANT_ID_LEN
+            )));
+        }
+
+        Ok(Self(id))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// `key` in this tenant's namespace
+    pub fn scope(&self, key: &str) -> String {
+        format!("{}:{}", self.0, key)
+    }
+
+    /// The key a `scope`d key was made from, if it belongs to this tenant
+    #[cfg(feature = "session")]
+    fn unscope<'a>(&self, key: &'a str) -> Option<&'a str> {
+        key.strip_prefix(self.0.as_str())?.strip_prefix(':')
+    }
+}
+
+impl fmt::Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// One value per tenant, such as its TOTP issuer, `KeyRing`, `JwtVerifier` or store
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::tenant::{TenantId, Tenants};
+/// use lonewolf_auth_toolkit::token::{
+///     jwt::{SigningKey, VerifyingKey},
+///     keyring::KeyRing,
+///     Claims,
+/// };
+///
+/// let acme = TenantId::new("acme")?;
+/// let globex = TenantId::new("globex")?;
+///
+/// let issuers = Tenants::new()
+///     .with(acme.clone(), "Acme Corp".to_string())
+///     .with(globex.clone(), "Globex".to_string());
+///
+/// let ring = |kid: &str, secret: &[u8]| -> Result<KeyRing, lonewolf_auth_toolkit::AuthError> {
+///     Ok(KeyRing::new(kid, SigningKey::hs256(secret)?, VerifyingKey::hs256(secret)?))
+/// };
+/// let key_rings = Tenants::new()
+///     .with(acme.clone(), ring("acme-1", &[1u8; 32])?)
+///     .with(globex.clone(), ring("globex-1", &[2u8; 32])?);
+///
+/// // The issuer shown in the user's authenticator app
+/// assert_eq!(issuers.get(&acme)?, "Acme Corp");
+///
+/// // A token signed for one tenant does not verify for another
+/// let claims = Claims::new((), std::time::Duration::from_secs(60))?.subject("alice");
+/// let token = key_rings.get(&acme)?.sign(&claims)?;
+/// assert!(key_rings.get(&acme)?.verify::<()>(&token).is_ok());
+/// assert!(key_rings.get(&globex)?.verify::<()>(&token).is_err());
+///
+/// assert!(key_rings.get(&TenantId::new("initech")?).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Tenants<T> {
+    values: HashMap<TenantId, T>,
+}
+
+impl<T> Tenants<T> {
+    pub fn new() -> Self {
+        Self {
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn with(mut self, tenant: TenantId, value: T) -> Self {
+        self.values.insert(tenant, value);
+        self
+    }
+
+    /// Add or replace the tenant's value, e.g. when a customer signs up or rotates keys
+    pub fn insert(&mut self, tenant: TenantId, value: T) -> Option<T> {
+        self.values.insert(tenant, value)
+    }
+
+    pub fn remove(&mut self, tenant: &TenantId) -> Option<T> {
+        self.values.remove(tenant)
+    }
+
+    /// The tenant's value; fails with `AuthError::NotFound` for an unknown tenant
+    pub fn get(&self, tenant: &TenantId) -> Result<&T, AuthError> {
+        self.values
+            .get(tenant)
+            .ok_or_else(|| AuthError::NotFound(format!("Unknown tenant {}", tenant)))
+    }
+
+    pub fn get_mut(&mut self, tenant: &TenantId) -> Result<&mut T, AuthError> {
+        self.values
+            .get_mut(tenant)
+            .ok_or_else(|| AuthError::NotFound(format!("Unknown tenant {}", tenant)))
+    }
+
+    pub fn tenants(&self) -> impl Iterator<Item = &TenantId> {
+        self.values.keys()
+    }
+}
+
+impl<T> Default for Tenants<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A view of a store shared by every tenant that only sees one tenant's entries
+///
+/// Keys, and for sessions the id hash and account, are prefixed with the tenant id, so codes,
+/// attempts and sessions of one tenant can never be read, guessed or revoked through another.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::rate_limit::{AttemptStore, MemoryAttemptStore};
+/// use lonewolf_auth_toolkit::tenant::{Scoped, TenantId};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let store = MemoryAttemptStore::default();
+///     let acme = TenantId::new("acme")?;
+///     let globex = TenantId::new("globex")?;
+///
+///     let window = Duration::from_secs(60);
+///     Scoped::new(&acme, &store).increment("alice", window).await?;
+///     let (acme_attempts, _) = Scoped::new(&acme, &store).increment("alice", window).await?;
+///     let (globex_attempts, _) = Scoped::new(&globex, &store).increment("alice", window).await?;
+///
+///     assert_eq!(acme_attempts, 2);
+///     assert_eq!(globex_attempts, 1);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Scoped<'a, S> {
+    tenant: &'a TenantId,
+    store: &'a S,
+}
+
+impl<'a, S> Scoped<'a, S> {
+    pub fn new(tenant: &'a TenantId, store: &'a S) -> Self {
+        Self { tenant, store }
+    }
+
+    pub fn tenant(&self) -> &TenantId {
+        self.tenant
+    }
+}
+
+impl<S> Clone for Scoped<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for Scoped<'_, S> {}
+
+impl<S: AttemptStore + Sync> AttemptStore for Scoped<'_, S> {
+    async fn increment(&self, key: &str, window: Duration) -> Result<(u32, Duration), AuthError> {
+        self.store.increment(&self.tenant.scope(key), window).await
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), AuthError> {
+        self.store.reset(&self.tenant.scope(key)).await
+    }
+}
+
+#[cfg(feature = "mfa")]
+impl<S: CodeStore + Sync> CodeStore for Scoped<'_, S> {
+    async fn save(&self, key: &str, code: PendingCode) -> Result<(), AuthError> {
+        self.store.save(&self.tenant.scope(key), code).await
+    }
+
+    async fn load(&self, key: &str) -> Result<Option<PendingCode>, AuthError> {
+        self.store.load(&self.tenant.scope(key)).await
+    }
+
+    async fn increment_attempts(&self, key: &str) -> Result<u32, AuthError> {
+        self.store.increment_attempts(&self.tenant.scope(key)).await
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), AuthError> {
+        self.store.remove(&self.tenant.scope(key)).await
+    }
+
+    async fn consume(&self, key: &str, code_hash: &str) -> Result<bool, AuthError> {
+        self.store.consume(&self.tenant.scope(key), code_hash).await
+    }
+}
+
+#[cfg(feature = "mfa")]
+impl<S: UsedStepStore + Sync> UsedStepStore for Scoped<'_, S> {
+    async fn mark_used(&self, key: &str, step: u64) -> Result<bool, AuthError> {
+        self.store.mark_used(&self.tenant.scope(key), step).await
+    }
+}
+
+#[cfg(feature = "mfa")]
+impl<S: DriftStore + Sync> DriftStore for Scoped<'_, S> {
+    async fn load(&self, key: &str) -> Result<Option<i64>, AuthError> {
+        self.store.load(&self.tenant.scope(key)).await
+    }
+
+    async fn save(&self, key: &str, drift: i64) -> Result<(), AuthError> {
+        self.store.save(&self.tenant.scope(key), drift).await
+    }
+}
+
+#[cfg(feature = "session")]
+impl<S> Scoped<'_, S> {
+    fn scope_record(&self, mut record: SessionRecord) -> SessionRecord {
+        record.id_hash = self.tenant.scope(&record.id_hash);
+        record.account = record.account.map(|account| self.tenant.scope(&account));
+        record
+    }
+
+    /// The record as the tenant saved it, or `None` if it belongs to another tenant
+    fn unscope_record(&self, mut record: SessionRecord) -> Option<SessionRecord> {
+        record.id_hash = self.tenant.unscope(&record.id_hash)?.to_string();
+        record.account = match record.account {
+            Some(account) => Some(self.tenant.unscope(&account)?.to_string()),
+            None => None,
+        };
+        Some(record)
+    }
+}
+
+#[cfg(feature = "session")]
+impl<S: SessionStore + Sync> SessionStore for Scoped<'_, S> {
+    async fn load(&self, id_hash: &str) -> Result<Option<SessionRecord>, AuthError> {
+        Ok(self
+            .store
+            .load(&self.tenant.scope(id_hash))
+            .await?
+            .and_then(|record| self.unscope_record(record)))
+    }
+
+    async fn save(&self, record: SessionRecord) -> Result<(), AuthError> {
+        self.store.save(self.scope_record(record)).await
+    }
+
+    async fn update(&self, record: SessionRecord) -> Result<bool, AuthError> {
+        self.store.update(self.scope_record(record)).await
+    }
+
+    async fn delete(&self, id_hash: &str) -> Result<(), AuthError> {
+        self.store.delete(&self.tenant.scope(id_hash)).await
+    }
+
+    async fn rotate(&self, old_hash: &str, record: SessionRecord) -> Result<bool, AuthError> {
+        self.store
+            .rotate(&self.tenant.scope(old_hash), self.scope_record(record))
+            .await
+    }
+
+    async fn list(&self, account: &str) -> Result<Vec<SessionRecord>, AuthError> {
+        Ok(self
+            .store
+            .list(&self.tenant.scope(account))
+            .await?
+            .into_iter()
+            .filter_map(|record| self.unscope_record(record))
+            .collect())
+    }
+
+    async fn delete_all(&self, account: &str) -> Result<usize, AuthError> {
+        self.store.delete_all(&self.tenant.scope(account)).await
+    }
+}
@@ -0,0 +1,447 @@
+use std::time::Duration;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::{
+    rand::SystemRandom,
+    signature::{
+        EcdsaKeyPair, KeyPair, RsaPublicKeyComponents, UnparsedPublicKey, ECDSA_P256_SHA256_FIXED,
+        ECDSA_P256_SHA256_FIXED_SIGNING, RSA_PKCS1_2048_8192_SHA256,
+    },
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value as Json};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::AuthError;
+
+use super::{
+    generate_token,
+    nonce::{NonceStore, Nonces},
+    now, DEFAULT_LEEWAY,
+};
+
+/// The `typ` header of a DPoP proof
+pub const PROOF_TYPE: &str = "dpop+jwt";
+
+/// How long after its `iat` a proof is accepted
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// The `cnf` claim binding an access token to the client's key (RFC 9449 6)
+///
+/// Put it in the access token's claims as `cnf` when the token request came with a DPoP proof,
+/// using the proof's `jkt`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Confirmation {
+    /// The JWK SHA-256 thumbprint (RFC 7638) of the client's key
+    pub jkt: String,
+}
+
+/// A client's P-256 key for signing DPoP proofs (RFC 9449)
+///
+/// Each request carries a fresh proof in its `DPoP` header, bound to the request's method and URL
+/// and, for requests to resource servers, to the access token. Keep the key for as long as the
+/// tokens issued with it.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::token::dpop::DpopKey;
+/// use ring::{rand::SystemRandom, signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING}};
+///
+/// let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+///     .unwrap();
+/// let key = DpopKey::new(pkcs8.as_ref())?;
+///
+/// let token_request = key.proof("POST", "https://auth.example.com/token", None, None)?;
+/// let api_request = key.proof(
+///     "GET",
+///     "https://api.example.com/reports?page=2",
+///     Some("SomeAccessToken"),
+///     None,
+/// )?;
+/// assert_ne!(token_request, api_request);
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+pub struct DpopKey {
+    pair: EcdsaKeyPair,
+    jwk: Json,
+    thumbprint: String,
+}
+
+impl DpopKey {
+    /// A P-256 private key in PKCS#8 DER, as generated by `ring`
+    pub fn new(pkcs8: &[u8]) -> Result<Self, AuthError> {
+        let pair = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            pkcs8,
+            &SystemRandom::new(),
+        )
+        .map_err(|_| AuthError::InvalidInput("Invalid P-256 PKCS#8 key".to_string()))?;
+
+        // An uncompressed SEC1 point: 0x04, then x and y
+        let point = pair.public_key().as_ref();
+        let jwk = json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+        });
+        let thumbprint = thumbprint(&jwk)?;
+
+        Ok(Self {
+            pair,
+            jwk,
+            thumbprint,
+        })
+    }
+
+    /// The JWK SHA-256 thumbprint tokens issued to this key are bound to
+    pub fn thumbprint(&self) -> &str {
+        &self.thumbprint
+    }
+
+    /// The public key as a JWK
+    pub fn jwk(&self) -> &Json {
+        &self.jwk
+    }
+
+    /// A proof for one request
+    ///
+    /// Pass the access token when calling a resource server, and the `DPoP-Nonce` the server last
+    /// sent if it requires one. The query and fragment of `url` are left out of the proof.
+    pub fn proof(
+        &self,
+        method: &str,
+        url: &str,
+        access_token: Option<&str>,
+        nonce: Option<&str>,
+    ) -> Result<String, AuthError> {
+        let header = json!({ "typ": PROOF_TYPE, "alg": "ES256", "jwk": self.jwk });
+        let claims = ProofClaims {
+            id: generate_token(),
+            method: method.to_string(),
+            url: target(url)
+                .ok_or_else(|| AuthError::InvalidInput("Invalid request URL".to_string()))?,
+            issued_at: now()?,
+            access_token_hash: access_token.map(access_token_hash),
+            nonce: nonce.map(str::to_string),
+        };
+
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?)
+        );
+        let signature = self
+            .pair
+            .sign(&SystemRandom::new(), message.as_bytes())
+            .map_err(|_| AuthError::backend("Failed to sign DPoP proof"))?;
+
+        Ok(format!(
+            "{}.{}",
+            message,
+            URL_SAFE_NO_PAD.encode(signature.as_ref())
+        ))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProofClaims {
+    #[serde(rename = "jti")]
+    id: String,
+    #[serde(rename = "htm")]
+    method: String,
+    #[serde(rename = "htu")]
+    url: String,
+    #[serde(rename = "iat")]
+    issued_at: u64,
+    #[serde(rename = "ath", default, skip_serializing_if = "Option::is_none")]
+    access_token_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+}
+
+/// A DPoP proof that passed verification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DpopProof {
+    /// The thumbprint of the key that signed the proof
+    pub jkt: String,
+    pub jti: String,
+    pub issued_at: u64,
+    /// The server provided nonce the proof carries, for servers that require one
+    pub nonce: Option<String>,
+}
+
+/// Verifies DPoP proofs (RFC 9449 4.3) so access tokens are sender-constrained
+///
+/// A proof must be signed with ES256 or RS256 by the public key in its own header, name the
+/// request's method and URL, be recent, and not have been seen before. At the token endpoint,
+/// `verify` returns the key thumbprint to bind the issued tokens to with a `Confirmation`. At a
+/// resource server, `verify_bound` also checks the proof covers the access token and was signed
+/// by the key in its `cnf.jkt` claim. Unparseable proofs are `AuthError::Malformed`; everything
+/// else is `AuthError::Verification`.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::token::dpop::{DpopKey, DpopVerifier};
+/// use lonewolf_auth_toolkit::token::nonce::MemoryNonceStore;
+/// use ring::{rand::SystemRandom, signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING}};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let pkcs8 =
+///         EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+///             .unwrap();
+///     let key = DpopKey::new(pkcs8.as_ref())?;
+///     let verifier = DpopVerifier::new(MemoryNonceStore::default());
+///
+///     // The token endpoint binds the issued token to the proof's key
+///     let proof = key.proof("POST", "https://auth.example.com/token", None, None)?;
+///     let jkt = verifier.verify(&proof, "POST", "https://auth.example.com/token").await?.jkt;
+///     assert_eq!(jkt, key.thumbprint());
+///
+///     // Proofs are single use
+///     assert!(verifier.verify(&proof, "POST", "https://auth.example.com/token").await.is_err());
+///
+///     // The resource server checks the proof against the token and its cnf.jkt
+///     let url = "https://api.example.com/reports";
+///     let proof = key.proof("GET", url, Some("SomeAccessToken"), None)?;
+///     let wrong_method = verifier.verify_bound(&proof, "DELETE", url, "SomeAccessToken", &jkt);
+///     assert!(wrong_method.await.is_err());
+///     verifier.verify_bound(&proof, "GET", url, "SomeAccessToken", &jkt).await?;
+///
+///     Ok(())
+/// }
+/// ```
+pub struct DpopVerifier<S> {
+    nonces: Nonces<S>,
+    max_age: Duration,
+    leeway: Duration,
+}
+
+impl<S: NonceStore> DpopVerifier<S> {
+    /// Remember the `jti` of accepted proofs in `store` to reject replays
+    pub fn new(store: S) -> Self {
+        Self {
+            nonces: Nonces::new(store).ttl(DEFAULT_MAX_AGE + 2 * DEFAULT_LEEWAY),
+            max_age: DEFAULT_MAX_AGE,
+            leeway: DEFAULT_LEEWAY,
+        }
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self.nonces = self.nonces.ttl(self.max_age + 2 * self.leeway);
+        self
+    }
+
+    /// Clock skew tolerated when checking `iat`
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self.nonces = self.nonces.ttl(self.max_age + 2 * self.leeway);
+        self
+    }
+
+    /// Verify a proof sent without an access token, such as to the token endpoint
+    pub async fn verify(
+        &self,
+        proof: &str,
+        method: &str,
+        url: &str,
+    ) -> Result<DpopProof, AuthError> {
+        let (jkt, claims) = self.check(proof, method, url)?;
+        if claims.access_token_hash.is_some() {
+            return Err(invalid("is bound to an access token"));
+        }
+
+        self.accept(jkt, claims).await
+    }
+
+    /// Verify a proof sent with the DPoP bound `access_token` whose `cnf.jkt` claim is `jkt`
+    pub async fn verify_bound(
+        &self,
+        proof: &str,
+        method: &str,
+        url: &str,
+        access_token: &str,
+        jkt: &str,
+    ) -> Result<DpopProof, AuthError> {
+        let (proof_jkt, claims) = self.check(proof, method, url)?;
+        if claims.access_token_hash.as_deref() != Some(access_token_hash(access_token).as_str()) {
+            return Err(invalid("does not match the access token"));
+        }
+        if proof_jkt != jkt {
+            return Err(invalid(
+                "is signed by a key the access token is not bound to",
+            ));
+        }
+
+        self.accept(proof_jkt, claims).await
+    }
+
+    fn check(
+        &self,
+        proof: &str,
+        method: &str,
+        url: &str,
+    ) -> Result<(String, ProofClaims), AuthError> {
+        let mut parts = proof.split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(malformed("is not a JWT"));
+        };
+
+        let message = &proof[..header.len() + 1 + payload.len()];
+        let header: Json = decode(header)?;
+        if header["typ"] != PROOF_TYPE {
+            return Err(invalid("has the wrong typ"));
+        }
+        let jwk = &header["jwk"];
+        if !jwk.is_object() {
+            return Err(malformed("has no jwk header"));
+        }
+        if jwk.get("d").is_some() {
+            return Err(invalid("header contains a private key"));
+        }
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| malformed("signature is not base64url"))?;
+        verify_signature(&header["alg"], jwk, message.as_bytes(), &signature)?;
+
+        let claims: ProofClaims = decode(payload)?;
+        if claims.method != method {
+            return Err(invalid("is for another HTTP method"));
+        }
+        let url = target(url).ok_or_else(|| AuthError::InvalidInput("Invalid URL".to_string()))?;
+        if target(&claims.url).as_ref() != Some(&url) {
+            return Err(invalid("is for another URL"));
+        }
+
+        let now = now()?;
+        let (max_age, leeway) = (self.max_age.as_secs(), self.leeway.as_secs());
+        if claims.issued_at > now + leeway || claims.issued_at + max_age + leeway < now {
+            return Err(invalid("is expired or issued in the future"));
+        }
+
+        Ok((thumbprint(jwk)?, claims))
+    }
+
+    async fn accept(&self, jkt: String, claims: ProofClaims) -> Result<DpopProof, AuthError> {
+        if !self
+            .nonces
+            .remember(&format!("dpop:{}:{}", jkt, claims.id))
+            .await?
+        {
+            return Err(invalid("was already used"));
+        }
+
+        Ok(DpopProof {
+            jkt,
+            jti: claims.id,
+            issued_at: claims.issued_at,
+            nonce: claims.nonce,
+        })
+    }
+}
+
+fn verify_signature(
+    alg: &Json,
+    jwk: &Json,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), AuthError> {
+    let verified = match (alg.as_str(), jwk["kty"].as_str()) {
+        (Some("ES256"), Some("EC")) => {
+            if jwk["crv"] != "P-256" {
+                return Err(invalid("key uses an unsupported curve"));
+            }
+            let mut point = vec![0x04];
+            point.extend(coordinate(jwk, "x")?);
+            point.extend(coordinate(jwk, "y")?);
+
+            UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point)
+                .verify(message, signature)
+                .is_ok()
+        }
+        (Some("RS256"), Some("RSA")) => RsaPublicKeyComponents {
+            n: coordinate(jwk, "n")?,
+            e: coordinate(jwk, "e")?,
+        }
+        .verify(&RSA_PKCS1_2048_8192_SHA256, message, signature)
+        .is_ok(),
+        _ => return Err(invalid("uses an unsupported algorithm or key type")),
+    };
+
+    if !verified {
+        return Err(invalid("signature is invalid"));
+    }
+
+    Ok(())
+}
+
+/// The JWK SHA-256 thumbprint (RFC 7638) of an EC or RSA public key
+pub fn thumbprint(jwk: &Json) -> Result<String, AuthError> {
+    let member = |name: &str| {
+        jwk[name]
+            .as_str()
+            .ok_or_else(|| malformed("key is missing a member"))
+            .and_then(|value| Ok(serde_json::to_string(value)?))
+    };
+
+    // The required members only, in lexicographic order and without whitespace
+    let canonical = match jwk["kty"].as_str() {
+        Some("EC") => format!(
+            r#"{{"crv":{},"kty":"EC","x":{},"y":{}}}"#,
+            member("crv")?,
+            member("x")?,
+            member("y")?
+        ),
+        Some("RSA") => format!(
+            r#"{{"e":{},"kty":"RSA","n":{}}}"#,
+            member("e")?,
+            member("n")?
+        ),
+        _ => return Err(invalid("uses an unsupported key type")),
+    };
+
+    Ok(URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes())))
+}
+
+fn coordinate(jwk: &Json, name: &str) -> Result<Vec<u8>, AuthError> {
+    jwk[name]
+        .as_str()
+        .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+        .ok_or_else(|| malformed("key is missing a member"))
+}
+
+/// The `ath` claim: the access token's SHA-256 hash, base64url encoded
+fn access_token_hash(access_token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(access_token.as_bytes()))
+}
+
+/// The URL without its query and fragment, as compared against `htu`
+fn target(url: &str) -> Option<String> {
+    let mut url = Url::parse(url).ok()?;
+    url.set_query(None);
+    url.set_fragment(None);
+
+    Some(url.to_string())
+}
+
+fn decode<T: DeserializeOwned>(part: &str) -> Result<T, AuthError> {
+    let json = URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| malformed("is not base64url"))?;
+
+    serde_json::from_slice(&json).map_err(|_| malformed("has invalid JSON"))
+}
+
+fn invalid(problem: &str) -> AuthError {
+    AuthError::Verification(format!("DPoP proof {}", problem))
+}
+
+fn malformed(problem: &str) -> AuthError {
+    AuthError::Malformed(format!("DPoP proof {}", problem))
+}
@@ -0,0 +1,317 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditSink, NoAudit},
+    crypto::ct_eq,
+    AuthError,
+};
+
+use super::{generate_token, hash_token, now};
+
+/// How long both addresses have to confirm an email change
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// An address change waiting for both addresses to confirm it
+///
+/// Only SHA-256 hashes of the three tokens are stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEmailChange {
+    pub id: String,
+    pub account: String,
+    /// Lowercased current address
+    pub old_email: String,
+    /// Lowercased address to switch to
+    pub new_email: String,
+    /// Hash of the confirmation token sent to the current address
+    pub old_hash: String,
+    /// Hash of the confirmation token sent to the new address
+    pub new_hash: String,
+    /// Hash of the cancel token sent to the current address
+    pub cancel_hash: String,
+    pub old_confirmed: bool,
+    pub new_confirmed: bool,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+}
+
+/// The tokens to email when a change starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailChangeTokens {
+    /// Confirms the change, for a link sent to the current address
+    pub old: String,
+    /// Confirms the change, for a link sent to the new address
+    pub new: String,
+    /// Calls the change off, for a second link sent to the current address
+    pub cancel: String,
+}
+
+/// What a confirmation did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmailChangeOutcome {
+    /// The other address has yet to confirm
+    Waiting(PendingEmailChange),
+    /// Both addresses confirmed; switch the account to `new_email`
+    Changed(PendingEmailChange),
+}
+
+/// Persists pending email changes, at most one per account
+pub trait EmailChangeStore {
+    /// Insert the change, replacing any pending change for the same account
+    fn save(
+        &self,
+        change: PendingEmailChange,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    /// Mark the side whose token hash is `hash` confirmed and return the updated change
+    ///
+    /// Must be a single atomic update, e.g. `UPDATE ... RETURNING`, so confirmations from both
+    /// addresses arriving together are both kept. Returns `None` unless `hash` is the
+    /// `old_hash` or `new_hash` of a pending change.
+    fn confirm(
+        &self,
+        hash: &str,
+    ) -> impl Future<Output = Result<Option<PendingEmailChange>, AuthError>> + Send;
+
+    /// The change whose `cancel_hash` is `hash`
+    fn find_cancel(
+        &self,
+        hash: &str,
+    ) -> impl Future<Output = Result<Option<PendingEmailChange>, AuthError>> + Send;
+
+    /// Returns `false` if there was no change with this id
+    fn remove(&self, id: &str) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Changes an account's email address only once both the old and the new address confirm it
+///
+/// A stolen session alone is then not enough to move an account to an attacker's address, and a
+/// mistyped new address cannot lock the user out. `start` returns three tokens: a confirmation
+/// for each address and a cancel token for the current one, so its owner can call off a change
+/// they did not ask for. Starting again replaces the pending change. Switch the account's address
+/// when `confirm` returns `EmailChangeOutcome::Changed`, and consider ending its other sessions.
+/// Starting, completing and cancelling are audited.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::token::email_change::{
+///     EmailChangeOutcome, EmailChanges, MemoryEmailChangeStore,
+/// };
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let changes = EmailChanges::new(MemoryEmailChangeStore::default());
+///
+///     // Email links with the tokens to both addresses
+///     let tokens = changes
+///         .start("SomeAccountName", "old@example.com", "New@Example.com")
+///         .await?;
+///
+///     assert!(matches!(changes.confirm(&tokens.new).await?, EmailChangeOutcome::Waiting(_)));
+///     let EmailChangeOutcome::Changed(change) = changes.confirm(&tokens.old).await? else {
+///         panic!("both addresses confirmed");
+///     };
+///     assert_eq!(change.new_email, "new@example.com");
+///
+///     // The owner of the old address can call off a change they did not ask for
+///     let tokens = changes
+///         .start("SomeAccountName", "new@example.com", "attacker@example.com")
+///         .await?;
+///     changes.cancel(&tokens.cancel).await?;
+///     assert!(changes.confirm(&tokens.new).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct EmailChanges<S, A = NoAudit> {
+    store: S,
+    ttl: Duration,
+    audit: A,
+}
+
+impl<S: EmailChangeStore> EmailChanges<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            ttl: DEFAULT_TTL,
+            audit: NoAudit,
+        }
+    }
+}
+
+impl<S: EmailChangeStore, A: AuditSink> EmailChanges<S, A> {
+    /// Record email changes to an audit sink
+    pub fn audit<B: AuditSink>(self, audit: B) -> EmailChanges<S, B> {
+        EmailChanges {
+            store: self.store,
+            ttl: self.ttl,
+            audit,
+        }
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Start moving `account` from `old_email` to `new_email`
+    pub async fn start(
+        &self,
+        account: &str,
+        old_email: &str,
+        new_email: &str,
+    ) -> Result<EmailChangeTokens, AuthError> {
+        let old_email = old_email.trim().to_lowercase();
+        let new_email = new_email.trim().to_lowercase();
+        if old_email.is_empty() || new_email.is_empty() {
+            return Err(AuthError::InvalidInput(
+                "Email address is empty".to_string(),
+            ));
+        }
+        if old_email == new_email {
+            return Err(AuthError::InvalidInput(
+                "The new email address is the current one".to_string(),
+            ));
+        }
+
+        let tokens = EmailChangeTokens {
+            old: generate_token(),
+            new: generate_token(),
+            cancel: generate_token(),
+        };
+        let detail = format!("{} to {}", old_email, new_email);
+
+        self.store
+            .save(PendingEmailChange {
+                id: hash_token(&generate_token()),
+                account: account.to_string(),
+                old_email,
+                new_email,
+                old_hash: hash_token(&tokens.old),
+                new_hash: hash_token(&tokens.new),
+                cancel_hash: hash_token(&tokens.cancel),
+                old_confirmed: false,
+                new_confirmed: false,
+                expires_at: now()? + self.ttl.as_secs(),
+            })
+            .await?;
+
+        let event = AuditEvent::new(AuditAction::EmailChangeRequested)?
+            .account(account)
+            .detail(detail);
+        self.audit.record(event).await?;
+
+        Ok(tokens)
+    }
+
+    /// Confirm the change from one of its addresses
+    ///
+    /// Fails with `AuthError::Verification` for unknown, cancelled, completed or expired changes.
+    pub async fn confirm(&self, token: &str) -> Result<EmailChangeOutcome, AuthError> {
+        let invalid =
+            || AuthError::Verification("Email change token is invalid or used".to_string());
+
+        let change = self
+            .store
+            .confirm(&hash_token(token))
+            .await?
+            .ok_or_else(invalid)?;
+        if change.expires_at <= now()? {
+            self.store.remove(&change.id).await?;
+            return Err(AuthError::Verification(
+                "Email change has expired".to_string(),
+            ));
+        }
+
+        if !(change.old_confirmed && change.new_confirmed) {
+            return Ok(EmailChangeOutcome::Waiting(change));
+        }
+        if !self.store.remove(&change.id).await? {
+            return Err(invalid());
+        }
+
+        let event = AuditEvent::new(AuditAction::EmailChanged)?
+            .account(&change.account)
+            .detail(format!("{} to {}", change.old_email, change.new_email));
+        self.audit.record(event).await?;
+
+        Ok(EmailChangeOutcome::Changed(change))
+    }
+
+    /// Call off a pending change with the cancel token sent to the current address
+    pub async fn cancel(&self, token: &str) -> Result<PendingEmailChange, AuthError> {
+        let invalid =
+            || AuthError::Verification("Email change token is invalid or used".to_string());
+
+        let change = self
+            .store
+            .find_cancel(&hash_token(token))
+            .await?
+            .ok_or_else(invalid)?;
+        if !self.store.remove(&change.id).await? {
+            return Err(invalid());
+        }
+
+        let event = AuditEvent::new(AuditAction::EmailChangeCancelled)?
+            .account(&change.account)
+            .detail(format!("{} to {}", change.old_email, change.new_email));
+        self.audit.record(event).await?;
+
+        Ok(change)
+    }
+}
+
+/// Keeps pending email changes in process memory, dropping expired ones as new ones are saved
+#[derive(Debug, Default)]
+pub struct MemoryEmailChangeStore {
+    changes: Mutex<HashMap<String, PendingEmailChange>>,
+}
+
+impl MemoryEmailChangeStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, PendingEmailChange>>, AuthError> {
+        self.changes
+            .lock()
+            .map_err(|_| AuthError::backend("Email change store lock poisoned"))
+    }
+}
+
+impl EmailChangeStore for MemoryEmailChangeStore {
+    async fn save(&self, change: PendingEmailChange) -> Result<(), AuthError> {
+        let now = now()?;
+        let mut changes = self.lock()?;
+
+        changes.retain(|_, pending| pending.expires_at > now && pending.account != change.account);
+        changes.insert(change.id.clone(), change);
+
+        Ok(())
+    }
+
+    async fn confirm(&self, hash: &str) -> Result<Option<PendingEmailChange>, AuthError> {
+        let mut changes = self.lock()?;
+        let change = changes
+            .values_mut()
+            .find(|change| ct_eq(&change.old_hash, hash) || ct_eq(&change.new_hash, hash));
+
+        Ok(change.map(|change| {
+            change.old_confirmed |= ct_eq(&change.old_hash, hash);
+            change.new_confirmed |= ct_eq(&change.new_hash, hash);
+            change.clone()
+        }))
+    }
+
+    async fn find_cancel(&self, hash: &str) -> Result<Option<PendingEmailChange>, AuthError> {
+        Ok(self
+            .lock()?
+            .values()
+            .find(|change| ct_eq(&change.cancel_hash, hash))
+            .cloned())
+    }
+
+    async fn remove(&self, id: &str) -> Result<bool, AuthError> {
+        Ok(self.lock()?.remove(id).is_some())
+    }
+}
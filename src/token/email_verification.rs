@@ -0,0 +1,191 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::AuthError;
+
+use super::{generate_token, hash_token, now};
+
+/// How long an email verification link stays valid
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// An address confirmation that has been sent and not yet completed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingVerification {
+    /// SHA-256 of the token; the token itself is never stored
+    pub hash: String,
+    /// The account whose address is being confirmed
+    pub account: String,
+    /// Lowercased address the token was sent to
+    pub email: String,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+}
+
+/// Persists pending email verifications by token hash
+///
+/// `consume` must delete the verification and report whether it existed in a single atomic
+/// operation so a token can only be used once.
+pub trait EmailVerificationStore {
+    fn save(
+        &self,
+        verification: PendingVerification,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn get(
+        &self,
+        hash: &str,
+    ) -> impl Future<Output = Result<Option<PendingVerification>, AuthError>> + Send;
+
+    /// Returns `false` if there was no verification with this hash
+    fn consume(&self, hash: &str) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Issues and checks single use tokens that confirm an account owns an email address
+///
+/// Each token is bound to the address it was sent to, and `verify` returns that address. Only mark
+/// the account verified if the returned address is still the account's address, so a link sent
+/// before an address change cannot confirm the new one.
+///
+/// ### Example
+/// ```rust
+/// use std::{collections::HashMap, sync::Mutex};
+///
+/// use lonewolf_auth_toolkit::token::email_verification::{
+///     EmailVerificationStore, EmailVerifier, PendingVerification,
+/// };
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, PendingVerification>>);
+///
+/// impl EmailVerificationStore for MemoryStore {
+///     async fn save(&self, verification: PendingVerification) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(verification.hash.clone(), verification);
+///         Ok(())
+///     }
+///
+///     async fn get(&self, hash: &str) -> Result<Option<PendingVerification>, AuthError> {
+///         Ok(self.0.lock().unwrap().get(hash).cloned())
+///     }
+///
+///     async fn consume(&self, hash: &str) -> Result<bool, AuthError> {
+///         Ok(self.0.lock().unwrap().remove(hash).is_some())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let verifier = EmailVerifier::new(MemoryStore::default());
+///
+///     // Email https://app.example.com/verify?token=... to the new address
+///     let token = verifier.issue("SomeAccountName", "Someone@Example.com").await?;
+///
+///     let verified = verifier.verify(&token).await?;
+///     assert_eq!(verified.account, "SomeAccountName");
+///     assert_eq!(verified.email, "someone@example.com");
+///
+///     assert!(verifier.verify(&token).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct EmailVerifier<S> {
+    store: S,
+    ttl: Duration,
+}
+
+impl<S: EmailVerificationStore> EmailVerifier<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// A token to send to `email` confirming it belongs to `account`
+    pub async fn issue(&self, account: &str, email: &str) -> Result<String, AuthError> {
+        let email = email.trim().to_lowercase();
+        if email.is_empty() {
+            return Err(AuthError::InvalidInput(
+                "Email address is empty".to_string(),
+            ));
+        }
+
+        let token = generate_token();
+
+        self.store
+            .save(PendingVerification {
+                hash: hash_token(&token),
+                account: account.to_string(),
+                email,
+                expires_at: now()? + self.ttl.as_secs(),
+            })
+            .await?;
+
+        Ok(token)
+    }
+
+    /// Use up a token, returning the account and the address it confirms
+    pub async fn verify(&self, token: &str) -> Result<PendingVerification, AuthError> {
+        let hash = hash_token(token);
+        let invalid =
+            || AuthError::Verification("Email verification token is invalid or used".to_string());
+
+        let verification = self.store.get(&hash).await?.ok_or_else(invalid)?;
+
+        if !self.store.consume(&hash).await? {
+            return Err(invalid());
+        }
+
+        if verification.expires_at <= now()? {
+            return Err(AuthError::Verification(
+                "Email verification token has expired".to_string(),
+            ));
+        }
+
+        Ok(verification)
+    }
+}
+
+/// Keeps pending verifications in process memory, dropping expired ones as new ones are saved
+#[derive(Debug, Default)]
+pub struct MemoryEmailVerificationStore {
+    verifications: Mutex<HashMap<String, PendingVerification>>,
+}
+
+impl MemoryEmailVerificationStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, PendingVerification>>, AuthError> {
+        self.verifications
+            .lock()
+            .map_err(|_| AuthError::backend("Email verification store lock poisoned"))
+    }
+}
+
+impl EmailVerificationStore for MemoryEmailVerificationStore {
+    async fn save(&self, verification: PendingVerification) -> Result<(), AuthError> {
+        let now = now()?;
+        let mut verifications = self.lock()?;
+
+        verifications.retain(|_, verification| verification.expires_at > now);
+        verifications.insert(verification.hash.clone(), verification);
+
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Option<PendingVerification>, AuthError> {
+        Ok(self.lock()?.get(hash).cloned())
+    }
+
+    async fn consume(&self, hash: &str) -> Result<bool, AuthError> {
+        Ok(self.lock()?.remove(hash).is_some())
+    }
+}
@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditSink},
+    AuthError,
+};
+
+use super::{generate_token, keyring::KeyRing, Claims};
+
+/// How long impersonation tokens are valid unless configured otherwise
+pub const DEFAULT_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// The longest lifetime `Impersonation::ttl` accepts
+pub const MAX_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The party acting on the subject's behalf, the `act` claim of RFC 8693
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Actor {
+    #[serde(rename = "sub")]
+    pub subject: String,
+}
+
+/// The claims identifying an impersonation token, alongside the registered ones
+///
+/// `sub` is the impersonated user and `act.sub` the administrator, so code that only looks at
+/// `sub` treats the request as the user's while `act` tells the two apart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImpersonationClaims {
+    #[serde(rename = "act")]
+    pub actor: Actor,
+    /// Why the administrator is acting as the user, e.g. a support ticket
+    pub reason: String,
+}
+
+/// A verified impersonation token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Impersonated {
+    /// The administrator acting as the user
+    pub admin: String,
+    /// The impersonated user
+    pub target: String,
+    pub reason: String,
+    /// The token's `jti`, for revoking it
+    pub id: String,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+}
+
+/// Short lived tokens that let an administrator act as another user, with an audit trail
+///
+/// There is no way to leave out the audit sink: every issued token records an
+/// `ImpersonationStarted` event and every accepted one an `ImpersonationUsed` event, both for the
+/// target account with the administrator in the detail. When the sink fails, so does issuing or
+/// verifying. `verify` only accepts tokens with an `act` claim, so sign impersonation tokens with
+/// a key ring of their own or have other verifiers reject tokens that carry `act`.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::audit::{AuditAction, MemoryAuditSink};
+/// use lonewolf_auth_toolkit::token::impersonation::Impersonation;
+/// use lonewolf_auth_toolkit::token::jwt::{SigningKey, VerifyingKey};
+/// use lonewolf_auth_toolkit::token::keyring::KeyRing;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let secret = b"a secret of at least 32 bytes, for impersonation";
+///     let ring = KeyRing::new(
+///         "2024-05",
+///         SigningKey::hs256(secret)?,
+///         VerifyingKey::hs256(secret)?,
+///     );
+///     let audit = Arc::new(MemoryAuditSink::default());
+///     let impersonation = Impersonation::new(ring, audit.clone());
+///
+///     let token = impersonation.issue("SomeAdminId", "SomeUserId", "Support ticket 4711").await?;
+///     let impersonated = impersonation.verify(&token).await?;
+///     assert_eq!(impersonated.admin, "SomeAdminId");
+///     assert_eq!(impersonated.target, "SomeUserId");
+///
+///     let actions: Vec<_> = audit.events()?.into_iter().map(|event| event.action).collect();
+///     assert_eq!(actions, [AuditAction::ImpersonationStarted, AuditAction::ImpersonationUsed]);
+///
+///     // Administrators cannot impersonate themselves, and every impersonation needs a reason
+///     assert!(impersonation.issue("SomeAdminId", "SomeAdminId", "Testing").await.is_err());
+///     assert!(impersonation.issue("SomeAdminId", "SomeUserId", " ").await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Impersonation<A> {
+    keys: KeyRing,
+    audit: A,
+    ttl: Duration,
+}
+
+impl<A: AuditSink> Impersonation<A> {
+    pub fn new(keys: KeyRing, audit: A) -> Self {
+        Self {
+            keys,
+            audit,
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// How long issued tokens are valid, at most `MAX_TTL`
+    pub fn ttl(mut self, ttl: Duration) -> Result<Self, AuthError> {
+        if ttl.is_zero() || ttl > MAX_TTL {
+            return Err(AuthError::InvalidInput(format!(
+                "Impersonation tokens must expire within {} seconds",
+                MAX_TTL.as_secs()
+            )));
+        }
+
+        self.ttl = ttl;
+        Ok(self)
+    }
+
+    /// Issue a token for `admin` to act as `target`
+    pub async fn issue(
+        &self,
+        admin: &str,
+        target: &str,
+        reason: &str,
+    ) -> Result<String, AuthError> {
+        if admin.is_empty() || target.is_empty() {
+            return Err(AuthError::InvalidInput(
+                "Impersonation needs an administrator and a user".to_string(),
+            ));
+        }
+        if admin == target {
+            return Err(AuthError::InvalidInput(
+                "An administrator cannot impersonate themselves".to_string(),
+            ));
+        }
+        if reason.trim().is_empty() {
+            return Err(AuthError::InvalidInput(
+                "Impersonation needs a reason".to_string(),
+            ));
+        }
+
+        let custom = ImpersonationClaims {
+            actor: Actor {
+                subject: admin.to_string(),
+            },
+            reason: reason.to_string(),
+        };
+        let claims = Claims::new(custom, self.ttl)?
+            .subject(target)
+            .id(generate_token());
+        let token = self.keys.sign(&claims)?;
+
+        let event = AuditEvent::new(AuditAction::ImpersonationStarted)?
+            .account(target)
+            .detail(format!("{}: {}", admin, reason));
+        self.audit.record(event).await?;
+
+        Ok(token)
+    }
+
+    /// Verify an impersonation token, recording its use
+    ///
+    /// Fails with `AuthError::Verification` for ordinary tokens without an `act` claim and for
+    /// tokens valid for longer than `MAX_TTL`.
+    pub async fn verify(&self, token: &str) -> Result<Impersonated, AuthError> {
+        let claims = self
+            .keys
+            .verify::<ImpersonationClaims>(token)
+            .map_err(|_| AuthError::Verification("Not a valid impersonation token".to_string()))?;
+
+        let lifetime = claims
+            .issued_at
+            .map(|issued_at| claims.expires_at.saturating_sub(issued_at));
+        if lifetime.is_none_or(|lifetime| lifetime > MAX_TTL.as_secs()) {
+            return Err(AuthError::Verification(
+                "Impersonation token lifetime is too long".to_string(),
+            ));
+        }
+
+        let (target, id) = claims.subject.zip(claims.id).ok_or_else(|| {
+            AuthError::Verification("Not a valid impersonation token".to_string())
+        })?;
+        let admin = claims.custom.actor.subject;
+
+        let event = AuditEvent::new(AuditAction::ImpersonationUsed)?
+            .account(&target)
+            .detail(&admin);
+        self.audit.record(event).await?;
+
+        Ok(Impersonated {
+            admin,
+            target,
+            reason: claims.custom.reason,
+            id,
+            expires_at: claims.expires_at,
+        })
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use jsonwebtoken::jwk::Jwk;
+use serde::de::DeserializeOwned;
+use serde_json::Value as Json;
+
+use crate::{
+    clock::Clock,
+    http::{HttpClient, HttpRequest},
+    otel, AuthError,
+};
+
+use super::{
+    jwt::{self, JwtVerifier, VerifyingKey},
+    now, Claims,
+};
+
+/// How long fetched keys are trusted when the response has no `Cache-Control: max-age`
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Minimum time between fetches triggered by an unknown `kid`
+pub const DEFAULT_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+type Keys = Vec<(Option<String>, VerifyingKey)>;
+
+struct Cache {
+    keys: Keys,
+    etag: Option<String>,
+    fetched_at: u64,
+    expires_at: u64,
+}
+
+/// Fetches and caches the signing keys an identity provider publishes at its `jwks_uri`
+///
+/// Keys are refetched once they expire, revalidating with `If-None-Match` when the provider sent
+/// an `ETag`. A token with an unknown `kid` triggers an early refetch so key rollover works
+/// without waiting for the cache to expire, at most once per `min_refresh` so forged tokens cannot
+/// hammer the provider. Keys other than RS256, ES256 and EdDSA signing keys are ignored.
+///
+/// ### Example
+/// ```rust
+/// use std::{
+///     sync::atomic::{AtomicUsize, Ordering},
+///     time::Duration,
+/// };
+///
+/// use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::token::jwks::JwksClient;
+/// use lonewolf_auth_toolkit::token::jwt::{JwtSigner, JwtVerifier, SigningKey};
+/// use lonewolf_auth_toolkit::token::{Claims, ValidationPolicy};
+/// use lonewolf_auth_toolkit::AuthError;
+/// use ring::{
+///     rand::SystemRandom,
+///     signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+/// };
+///
+/// struct FakeIdp {
+///     jwks: String,
+///     fetches: AtomicUsize,
+/// }
+///
+/// impl HttpClient for FakeIdp {
+///     async fn send(&self, _request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         self.fetches.fetch_add(1, Ordering::SeqCst);
+///
+///         Ok(HttpResponse {
+///             status: 200,
+///             headers: vec![("Cache-Control".to_string(), "public, max-age=600".to_string())],
+///             body: self.jwks.as_bytes().to_vec(),
+///         })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let rng = SystemRandom::new();
+///     let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+///     let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+///     let point = pair.public_key().as_ref();
+///
+///     let jwks = format!(
+///         r#"{{"keys":[{{"kty":"EC","crv":"P-256","use":"sig","kid":"idp-1","x":"{}","y":"{}"}}]}}"#,
+///         URL_SAFE_NO_PAD.encode(&point[1..33]),
+///         URL_SAFE_NO_PAD.encode(&point[33..]),
+///     );
+///
+///     let idp = FakeIdp { jwks, fetches: AtomicUsize::new(0) };
+///     let jwks = JwksClient::new(idp, "https://idp.example.com/.well-known/jwks.json");
+///     let verifier = JwtVerifier::new().policy(ValidationPolicy::new().issuer("https://idp.example.com"));
+///
+///     let signer = JwtSigner::new(SigningKey::es256_pkcs8(pkcs8.as_ref())).kid("idp-1");
+///     let claims = Claims::new((), Duration::from_secs(900))?.issuer("https://idp.example.com");
+///     let token = signer.sign(&claims)?;
+///
+///     assert_eq!(jwks.verify::<()>(&verifier, &token).await?, claims);
+///     assert_eq!(jwks.verify::<()>(&verifier, &token).await?, claims);
+///
+///     // The second verification was served from the cache
+///     assert_eq!(jwks.client().fetches.load(Ordering::SeqCst), 1);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct JwksClient<C> {
+    client: C,
+    uri: String,
+    ttl: Duration,
+    min_refresh: Duration,
+    cache: Mutex<Option<Cache>>,
+}
+
+impl<C: HttpClient + Sync> JwksClient<C> {
+    pub fn new(client: C, uri: impl Into<String>) -> Self {
+        Self {
+            client,
+            uri: uri.into(),
+            ttl: DEFAULT_TTL,
+            min_refresh: DEFAULT_MIN_REFRESH,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// How long to trust keys when the provider does not say
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn min_refresh(mut self, min_refresh: Duration) -> Self {
+        self.min_refresh = min_refresh;
+        self
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// The cached keys, fetching them first if they are missing or expired
+    pub async fn keys(&self) -> Result<Vec<(Option<String>, VerifyingKey)>, AuthError> {
+        let now = now()?;
+
+        if let Some(cache) = self.lock()?.as_ref().filter(|cache| cache.expires_at > now) {
+            return Ok(cache.keys.clone());
+        }
+
+        self.refresh().await
+    }
+
+    /// Fetch the key set now, revalidating the cached copy if there is one
+    pub async fn refresh(&self) -> Result<Vec<(Option<String>, VerifyingKey)>, AuthError> {
+        let etag = self.lock()?.as_ref().and_then(|cache| cache.etag.clone());
+
+        let mut request = HttpRequest::get(&self.uri).header("Accept", "application/json");
+        if let Some(etag) = &etag {
+            request = request.header("If-None-Match", etag);
+        }
+
+        let response = otel::send(&self.client, request).await?;
+        let now = now()?;
+        let expires_at = now
+            + max_age(response.header("Cache-Control"))
+                .unwrap_or(self.ttl)
+                .as_secs();
+
+        let mut cache = self.lock()?;
+
+        if response.status == 304 {
+            if let Some(cached) = cache.as_mut() {
+                cached.fetched_at = now;
+                cached.expires_at = expires_at;
+
+                return Ok(cached.keys.clone());
+            }
+        }
+
+        if !response.is_success() {
+            return Err(AuthError::backend(format!(
+                "JWKS endpoint returned status {}",
+                response.status
+            )));
+        }
+
+        let keys = parse(&response.body)?;
+
+        *cache = Some(Cache {
+            keys: keys.clone(),
+            etag: response.header("ETag").map(str::to_string),
+            fetched_at: now,
+            expires_at,
+        });
+
+        Ok(keys)
+    }
+
+    /// Verify a token with the verifier's rules against the published keys
+    ///
+    /// Keys registered directly on the verifier are accepted as well.
+    pub async fn verify<T: DeserializeOwned>(
+        &self,
+        verifier: &JwtVerifier<impl Clock>,
+        token: &str,
+    ) -> Result<Claims<T>, AuthError> {
+        let kid = jwt::kid(token)?;
+        let mut keys = self.keys().await?;
+
+        let unknown = kid
+            .as_ref()
+            .is_some_and(|kid| !keys.iter().any(|(known, _)| known.as_ref() == Some(kid)));
+
+        if unknown && self.may_refresh()? {
+            keys = self.refresh().await?;
+        }
+
+        verifier.verify_with(token, &keys)
+    }
+
+    fn may_refresh(&self) -> Result<bool, AuthError> {
+        let now = now()?;
+
+        Ok(self
+            .lock()?
+            .as_ref()
+            .is_none_or(|cache| cache.fetched_at + self.min_refresh.as_secs() <= now))
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Option<Cache>>, AuthError> {
+        self.cache
+            .lock()
+            .map_err(|_| AuthError::backend("JWKS cache lock poisoned"))
+    }
+}
+
+/// Parse a key set, skipping keys that are malformed or use unsupported algorithms
+fn parse(body: &[u8]) -> Result<Keys, AuthError> {
+    let set: Json = serde_json::from_slice(body)?;
+    let keys = set["keys"]
+        .as_array()
+        .ok_or_else(|| AuthError::Malformed("JWKS has no keys array".to_string()))?;
+
+    Ok(keys
+        .iter()
+        .filter_map(|key| serde_json::from_value::<Jwk>(key.clone()).ok())
+        .filter_map(|jwk| {
+            VerifyingKey::from_jwk(&jwk)
+                .ok()
+                .map(|key| (jwk.common.key_id.clone(), key))
+        })
+        .collect())
+}
+
+fn max_age(cache_control: Option<&str>) -> Option<Duration> {
+    cache_control?
+        .split(',')
+        .find_map(|directive| directive.trim().strip_prefix("max-age="))
+        .and_then(|seconds| seconds.parse().ok())
+        .map(Duration::from_secs)
+}
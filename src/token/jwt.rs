@@ -0,0 +1,621 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    errors::{Error as JwtError, ErrorKind},
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters, Jwk,
+        KeyAlgorithm, OctetKeyPairParameters, PublicKeyUse,
+    },
+    Algorithm as JwtAlgorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    crypto::{
+        provider::{KeyProvider, SignatureAlgorithm},
+        MIN_HMAC_KEY_LEN,
+    },
+    metrics, trace, AuthError,
+};
+
+use super::{Claims, ValidationPolicy};
+
+/// The signature algorithms accepted for JWTs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    HS256,
+    RS256,
+    ES256,
+    /// Ed25519
+    EdDSA,
+}
+
+impl Algorithm {
+    fn to_jwt(self) -> JwtAlgorithm {
+        match self {
+            Algorithm::HS256 => JwtAlgorithm::HS256,
+            Algorithm::RS256 => JwtAlgorithm::RS256,
+            Algorithm::ES256 => JwtAlgorithm::ES256,
+            Algorithm::EdDSA => JwtAlgorithm::EdDSA,
+        }
+    }
+
+    fn to_jwk(self) -> KeyAlgorithm {
+        match self {
+            Algorithm::HS256 => KeyAlgorithm::HS256,
+            Algorithm::RS256 => KeyAlgorithm::RS256,
+            Algorithm::ES256 => KeyAlgorithm::ES256,
+            Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+        }
+    }
+}
+
+/// A private key or shared secret used to sign tokens
+pub struct SigningKey {
+    algorithm: Algorithm,
+    key: EncodingKey,
+}
+
+impl SigningKey {
+    pub fn hs256(secret: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self {
+            algorithm: Algorithm::HS256,
+            key: EncodingKey::from_secret(check_secret(secret)?),
+        })
+    }
+
+    /// An RSA private key in PKCS#1 or PKCS#8 PEM
+    pub fn rs256_pem(pem: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self {
+            algorithm: Algorithm::RS256,
+            key: EncodingKey::from_rsa_pem(pem).map_err(key_error)?,
+        })
+    }
+
+    /// A P-256 private key in PKCS#8 PEM
+    pub fn es256_pem(pem: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self {
+            algorithm: Algorithm::ES256,
+            key: EncodingKey::from_ec_pem(pem).map_err(key_error)?,
+        })
+    }
+
+    /// A P-256 private key in PKCS#8 DER, as generated by `ring`
+    pub fn es256_pkcs8(der: &[u8]) -> Self {
+        Self {
+            algorithm: Algorithm::ES256,
+            key: EncodingKey::from_ec_der(der),
+        }
+    }
+
+    /// An Ed25519 private key in PKCS#8 PEM
+    pub fn ed25519_pem(pem: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self {
+            algorithm: Algorithm::EdDSA,
+            key: EncodingKey::from_ed_pem(pem).map_err(key_error)?,
+        })
+    }
+
+    /// An Ed25519 private key in PKCS#8 DER, as generated by `ring`
+    pub fn ed25519_pkcs8(der: &[u8]) -> Self {
+        Self {
+            algorithm: Algorithm::EdDSA,
+            key: EncodingKey::from_ed_der(der),
+        }
+    }
+}
+
+/// A public key or shared secret used to verify tokens
+#[derive(Clone)]
+pub struct VerifyingKey {
+    algorithm: Algorithm,
+    key: DecodingKey,
+    /// The public parameters, when known, for `to_jwk`
+    public: Option<AlgorithmParameters>,
+}
+
+impl VerifyingKey {
+    pub fn hs256(secret: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self {
+            algorithm: Algorithm::HS256,
+            key: DecodingKey::from_secret(check_secret(secret)?),
+            public: None,
+        })
+    }
+
+    /// An RSA public key in PKCS#1 or SPKI PEM
+    pub fn rs256_pem(pem: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self {
+            algorithm: Algorithm::RS256,
+            key: DecodingKey::from_rsa_pem(pem).map_err(key_error)?,
+            public: None,
+        })
+    }
+
+    /// A P-256 public key in SPKI PEM
+    pub fn es256_pem(pem: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self {
+            algorithm: Algorithm::ES256,
+            key: DecodingKey::from_ec_pem(pem).map_err(key_error)?,
+            public: None,
+        })
+    }
+
+    /// An Ed25519 public key in SPKI PEM
+    pub fn ed25519_pem(pem: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self {
+            algorithm: Algorithm::EdDSA,
+            key: DecodingKey::from_ed_pem(pem).map_err(key_error)?,
+            public: None,
+        })
+    }
+
+    /// A raw 32 byte Ed25519 public key, as returned by `ring`
+    pub fn ed25519(public_key: &[u8]) -> Result<Self, AuthError> {
+        if public_key.len() != 32 {
+            return Err(AuthError::InvalidInput(
+                "Ed25519 public keys are 32 bytes".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            algorithm: Algorithm::EdDSA,
+            key: DecodingKey::from_ed_der(public_key),
+            public: Some(AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: Default::default(),
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(public_key),
+            })),
+        })
+    }
+
+    /// A public key published in a JWKS; only RS256, ES256 and EdDSA signing keys are accepted
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, AuthError> {
+        if jwk.common.public_key_use == Some(PublicKeyUse::Encryption) {
+            return Err(AuthError::InvalidInput(
+                "JWK is an encryption key".to_string(),
+            ));
+        }
+
+        let algorithm = match (&jwk.common.key_algorithm, &jwk.algorithm) {
+            (Some(KeyAlgorithm::RS256), AlgorithmParameters::RSA(_))
+            | (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+            (Some(KeyAlgorithm::ES256), AlgorithmParameters::EllipticCurve(params))
+            | (None, AlgorithmParameters::EllipticCurve(params))
+                if params.curve == EllipticCurve::P256 =>
+            {
+                Algorithm::ES256
+            }
+            (Some(KeyAlgorithm::EdDSA), AlgorithmParameters::OctetKeyPair(params))
+            | (None, AlgorithmParameters::OctetKeyPair(params))
+                if params.curve == EllipticCurve::Ed25519 =>
+            {
+                Algorithm::EdDSA
+            }
+            _ => {
+                return Err(AuthError::InvalidInput(
+                    "JWK uses an unsupported algorithm".to_string(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            algorithm,
+            key: DecodingKey::from_jwk(jwk).map_err(key_error)?,
+            public: Some(jwk.algorithm.clone()),
+        })
+    }
+
+    /// A P-256 public key as an uncompressed SEC1 point
+    pub fn es256_point(point: &[u8]) -> Self {
+        // 0x04 followed by the 32 byte x and y coordinates
+        let public = (point.len() == 65 && point[0] == 4).then(|| {
+            AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                key_type: Default::default(),
+                curve: EllipticCurve::P256,
+                x: URL_SAFE_NO_PAD.encode(&point[1..33]),
+                y: URL_SAFE_NO_PAD.encode(&point[33..]),
+            })
+        });
+
+        Self {
+            algorithm: Algorithm::ES256,
+            key: DecodingKey::from_ec_der(point),
+            public,
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The key as a JWK signing key, e.g. to publish in a JWKS
+    ///
+    /// Only keys built from a raw public key, a point or a JWK can be exported; fails with
+    /// `AuthError::InvalidState` for PEM keys and shared secrets.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use lonewolf_auth_toolkit::token::jwt::{Algorithm, VerifyingKey};
+    /// use ring::{
+    ///     rand::SystemRandom,
+    ///     signature::{Ed25519KeyPair, KeyPair},
+    /// };
+    ///
+    /// let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    /// let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    ///
+    /// let key = VerifyingKey::ed25519(pair.public_key().as_ref())?;
+    /// let jwk = key.to_jwk(Some("2024-06"))?;
+    /// assert_eq!(jwk.common.key_id.as_deref(), Some("2024-06"));
+    ///
+    /// assert_eq!(VerifyingKey::from_jwk(&jwk)?.algorithm(), Algorithm::EdDSA);
+    /// let secret = VerifyingKey::hs256(b"an example secret of at least 32 bytes")?;
+    /// assert!(secret.to_jwk(None).is_err());
+    /// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+    /// ```
+    pub fn to_jwk(&self, kid: Option<&str>) -> Result<Jwk, AuthError> {
+        let algorithm = self.public.clone().ok_or_else(|| {
+            AuthError::InvalidState("Key has no exportable public parameters".to_string())
+        })?;
+
+        Ok(Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(self.algorithm.to_jwk()),
+                key_id: kid.map(str::to_string),
+                ..Default::default()
+            },
+            algorithm,
+        })
+    }
+}
+
+/// Mints signed JWTs, tagging them with a `kid` header when one is set
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::token::jwt::{JwtSigner, JwtVerifier, SigningKey, VerifyingKey};
+/// use lonewolf_auth_toolkit::token::Claims;
+/// use ring::{
+///     rand::SystemRandom,
+///     signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+/// };
+///
+/// let rng = SystemRandom::new();
+/// let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+/// let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+///
+/// let signer = JwtSigner::new(SigningKey::es256_pkcs8(pkcs8.as_ref())).kid("2024-06");
+/// let verifier = JwtVerifier::new()
+///     .key(Some("2024-06"), VerifyingKey::es256_point(pair.public_key().as_ref()));
+///
+/// let claims = Claims::new((), Duration::from_secs(900)).unwrap().subject("SomeAccountName");
+/// let token = signer.sign(&claims).unwrap();
+///
+/// assert_eq!(verifier.verify::<()>(&token).unwrap(), claims);
+/// ```
+pub struct JwtSigner {
+    key: SigningKey,
+    kid: Option<String>,
+}
+
+impl JwtSigner {
+    pub fn new(key: SigningKey) -> Self {
+        Self { key, kid: None }
+    }
+
+    /// Identify the key in the token header so verifiers can pick it during rotation
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &Claims<T>) -> Result<String, AuthError> {
+        let mut header = Header::new(self.key.algorithm.to_jwt());
+        header.kid = self.kid.clone();
+
+        let token = encode(&header, claims, &self.key.key)
+            .map_err(|error| AuthError::InvalidInput(format!("Failed to sign token: {}", error)))?;
+        metrics::token_issued("jwt");
+
+        Ok(token)
+    }
+}
+
+/// Signs JWTs with a key held by a `KeyProvider`, such as AWS KMS or HashiCorp Vault
+///
+/// Tokens are identical to those `JwtSigner` produces, so `JwtVerifier` checks them with the
+/// public key exported from the key management service. HS256 is not supported, as its secret
+/// would have to be shared with every verifier anyway.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::crypto::provider::MemoryKeyProvider;
+/// use lonewolf_auth_toolkit::token::jwt::{Algorithm, JwtVerifier, RemoteJwtSigner, VerifyingKey};
+/// use lonewolf_auth_toolkit::token::Claims;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let keys = MemoryKeyProvider::default();
+///     let public_key = keys.generate_es256("jwt-signing")?;
+///
+///     let signer = RemoteJwtSigner::new(keys, "jwt-signing", Algorithm::ES256)?.kid("2024-06");
+///     let verifier =
+///         JwtVerifier::new().key(Some("2024-06"), VerifyingKey::es256_point(&public_key));
+///
+///     let claims = Claims::new((), Duration::from_secs(900))?.subject("SomeAccountName");
+///     let token = signer.sign(&claims).await?;
+///
+///     assert_eq!(verifier.verify::<()>(&token)?, claims);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RemoteJwtSigner<P> {
+    provider: P,
+    key_id: String,
+    algorithm: Algorithm,
+    kid: Option<String>,
+}
+
+impl<P: KeyProvider> RemoteJwtSigner<P> {
+    /// Sign with the provider's key `key_id`, which must be a key for `algorithm`
+    pub fn new(provider: P, key_id: &str, algorithm: Algorithm) -> Result<Self, AuthError> {
+        if algorithm == Algorithm::HS256 {
+            return Err(AuthError::InvalidInput(
+                "Remote signing keys must be asymmetric".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            provider,
+            key_id: key_id.to_string(),
+            algorithm,
+            kid: None,
+        })
+    }
+
+    /// Identify the key in the token header so verifiers can pick it during rotation
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    pub async fn sign<T: Serialize>(&self, claims: &Claims<T>) -> Result<String, AuthError> {
+        let mut header = Header::new(self.algorithm.to_jwt());
+        header.kid = self.kid.clone();
+
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?)
+        );
+        let algorithm = match self.algorithm {
+            Algorithm::RS256 => SignatureAlgorithm::Rs256,
+            Algorithm::ES256 => SignatureAlgorithm::Es256,
+            _ => SignatureAlgorithm::EdDsa,
+        };
+        let signature = self
+            .provider
+            .sign(&self.key_id, algorithm, signing_input.as_bytes())
+            .await?;
+        metrics::token_issued("jwt");
+
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature)
+        ))
+    }
+}
+
+/// Validates JWT signatures and claims
+///
+/// A token is checked against the keys registered for its `alg`, narrowed to its `kid` when it has
+/// one, so an attacker cannot switch to a weaker algorithm. Its claims are then checked against
+/// the `ValidationPolicy`.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::token::jwt::{JwtSigner, JwtVerifier, SigningKey, VerifyingKey};
+/// use lonewolf_auth_toolkit::token::{Claims, ValidationPolicy};
+/// use lonewolf_auth_toolkit::AuthError;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct Session {
+///     mfa: bool,
+/// }
+///
+/// let secret = b"an example secret of at least 32 bytes";
+/// let signer = JwtSigner::new(SigningKey::hs256(secret).unwrap()).kid("1");
+/// let verifier = JwtVerifier::new()
+///     .key(Some("1"), VerifyingKey::hs256(secret).unwrap())
+///     .policy(
+///         ValidationPolicy::new()
+///             .issuer("https://auth.example.com")
+///             .audience("https://api.example.com"),
+///     );
+///
+/// let claims = Claims::new(Session { mfa: true }, Duration::from_secs(900))
+///     .unwrap()
+///     .issuer("https://auth.example.com")
+///     .subject("SomeAccountName")
+///     .audience("https://api.example.com");
+///
+/// let verified = verifier.verify::<Session>(&signer.sign(&claims).unwrap()).unwrap();
+/// assert!(verified.custom.mfa);
+///
+/// let elsewhere = Claims::new(Session { mfa: true }, Duration::from_secs(900))
+///     .unwrap()
+///     .issuer("https://auth.example.com")
+///     .audience("https://other.example.com");
+///
+/// assert!(matches!(
+///     verifier.verify::<Session>(&signer.sign(&elsewhere).unwrap()),
+///     Err(AuthError::Verification(_))
+/// ));
+/// ```
+#[derive(Default)]
+pub struct JwtVerifier<C = SystemClock> {
+    keys: Vec<(Option<String>, VerifyingKey)>,
+    policy: ValidationPolicy,
+    clock: C,
+}
+
+impl JwtVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<C: Clock> JwtVerifier<C> {
+    /// Check `exp`, `nbf` and `iat` against `clock` instead of the system time
+    ///
+    /// ### Example
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// use lonewolf_auth_toolkit::clock::{Clock, MockClock, SystemClock};
+    /// use lonewolf_auth_toolkit::token::jwt::{JwtSigner, JwtVerifier, SigningKey, VerifyingKey};
+    /// use lonewolf_auth_toolkit::token::Claims;
+    ///
+    /// let secret = b"an example secret of at least 32 bytes";
+    /// let token = JwtSigner::new(SigningKey::hs256(secret)?)
+    ///     .sign(&Claims::new((), Duration::from_secs(900))?)?;
+    ///
+    /// let clock = MockClock::at(SystemClock.now()?);
+    /// let verifier = JwtVerifier::new()
+    ///     .key(None, VerifyingKey::hs256(secret)?)
+    ///     .clock(clock.clone());
+    /// assert!(verifier.verify::<()>(&token).is_ok());
+    ///
+    /// // Past `exp` plus the default minute of leeway
+    /// clock.advance(Duration::from_secs(16 * 60));
+    /// assert!(verifier.verify::<()>(&token).is_err());
+    /// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+    /// ```
+    pub fn clock<D: Clock>(self, clock: D) -> JwtVerifier<D> {
+        JwtVerifier {
+            keys: self.keys,
+            policy: self.policy,
+            clock,
+        }
+    }
+
+    /// Accept tokens signed by this key; `kid` must match the token's `kid` header if it has one
+    pub fn key(mut self, kid: Option<&str>, key: VerifyingKey) -> Self {
+        self.keys.push((kid.map(str::to_string), key));
+        self
+    }
+
+    pub fn policy(mut self, policy: ValidationPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<Claims<T>, AuthError> {
+        self.verify_with(token, &[])
+    }
+
+    /// Verify against the registered keys plus `extra`, such as keys fetched from a JWKS
+    pub(crate) fn verify_with<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        extra: &[(Option<String>, VerifyingKey)],
+    ) -> Result<Claims<T>, AuthError> {
+        trace::instrument_sync("token.jwt.verify", &[], || self.check(token, extra))
+    }
+
+    fn check<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        extra: &[(Option<String>, VerifyingKey)],
+    ) -> Result<Claims<T>, AuthError> {
+        let header = decode_header(token).map_err(token_error)?;
+        let validation = signature_only(header.alg);
+
+        let mut candidates = self
+            .keys
+            .iter()
+            .chain(extra)
+            .filter(|(kid, key)| {
+                key.algorithm.to_jwt() == header.alg
+                    && header
+                        .kid
+                        .as_ref()
+                        .is_none_or(|wanted| kid.as_ref() == Some(wanted))
+            })
+            .peekable();
+
+        if candidates.peek().is_none() {
+            return Err(AuthError::Verification(
+                "No key matches the token's algorithm and key id".to_string(),
+            ));
+        }
+
+        for (_, key) in candidates {
+            match decode::<Claims<T>>(token, &key.key, &validation) {
+                Ok(data) => {
+                    self.policy.validate_at(&data.claims, self.clock.now()?)?;
+
+                    return Ok(data.claims);
+                }
+                Err(error) if *error.kind() == ErrorKind::InvalidSignature => continue,
+                Err(error) => return Err(token_error(error)),
+            }
+        }
+
+        Err(AuthError::Verification(
+            "Token signature is invalid".to_string(),
+        ))
+    }
+}
+
+/// Signature checks only; claims are left to the `ValidationPolicy`
+fn signature_only(algorithm: JwtAlgorithm) -> Validation {
+    let mut validation = Validation::new(algorithm);
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+    validation.required_spec_claims.clear();
+
+    validation
+}
+
+/// The `kid` header of a token, read without verifying it
+pub(crate) fn kid(token: &str) -> Result<Option<String>, AuthError> {
+    Ok(decode_header(token).map_err(token_error)?.kid)
+}
+
+fn check_secret(secret: &[u8]) -> Result<&[u8], AuthError> {
+    if secret.len() < MIN_HMAC_KEY_LEN {
+        return Err(AuthError::InvalidInput(format!(
+            "HS256 secrets must be at least {} bytes",
+            MIN_HMAC_KEY_LEN
+        )));
+    }
+
+    Ok(secret)
+}
+
+fn key_error(error: JwtError) -> AuthError {
+    AuthError::InvalidInput(format!("Invalid key: {}", error))
+}
+
+fn token_error(error: JwtError) -> AuthError {
+    match error.kind() {
+        ErrorKind::InvalidToken
+        | ErrorKind::Base64(_)
+        | ErrorKind::Json(_)
+        | ErrorKind::Utf8(_) => AuthError::Malformed(format!("Malformed token: {}", error)),
+        _ => AuthError::Verification(format!("Token rejected: {}", error)),
+    }
+}
@@ -0,0 +1,182 @@
+use jsonwebtoken::jwk::JwkSet;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::AuthError;
+
+use super::{
+    jwt::{JwtSigner, JwtVerifier, SigningKey, VerifyingKey},
+    Claims, ValidationPolicy,
+};
+
+/// A current JWT signing key plus the verification keys for every key still in use
+///
+/// New tokens are signed with the current key and tagged with its `kid`. After `rotate`,
+/// tokens signed with earlier keys keep verifying until those keys are retired, which should
+/// happen once the longest lived token signed with them has expired.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::token::jwt::{SigningKey, VerifyingKey};
+/// use lonewolf_auth_toolkit::token::keyring::KeyRing;
+/// use lonewolf_auth_toolkit::token::Claims;
+///
+/// let old_secret = b"the first secret, at least 32 bytes long";
+/// let new_secret = b"the second secret, at least 32 bytes long";
+///
+/// let ring = KeyRing::new("2024-05", SigningKey::hs256(old_secret).unwrap(), VerifyingKey::hs256(old_secret).unwrap());
+/// let claims = Claims::new((), Duration::from_secs(900)).unwrap().subject("SomeAccountName");
+/// let old_token = ring.sign(&claims).unwrap();
+///
+/// let ring = ring
+///     .rotate("2024-06", SigningKey::hs256(new_secret).unwrap(), VerifyingKey::hs256(new_secret).unwrap())
+///     .unwrap();
+/// let new_token = ring.sign(&claims).unwrap();
+///
+/// assert_eq!(ring.current_kid(), "2024-06");
+/// assert!(ring.verify::<()>(&old_token).is_ok());
+/// assert!(ring.verify::<()>(&new_token).is_ok());
+///
+/// let ring = ring.retire("2024-05").unwrap();
+/// assert!(ring.verify::<()>(&old_token).is_err());
+/// ```
+pub struct KeyRing {
+    current: String,
+    signer: JwtSigner,
+    keys: Vec<(Option<String>, VerifyingKey)>,
+    verifier: JwtVerifier,
+}
+
+impl KeyRing {
+    /// Create a ring whose current key pair has the given `kid`
+    pub fn new(kid: impl Into<String>, signing: SigningKey, verifying: VerifyingKey) -> Self {
+        let kid = kid.into();
+
+        Self {
+            signer: JwtSigner::new(signing).kid(kid.clone()),
+            keys: vec![(Some(kid.clone()), verifying)],
+            current: kid,
+            verifier: JwtVerifier::new(),
+        }
+    }
+
+    /// The claim checks applied by `verify`
+    pub fn policy(mut self, policy: ValidationPolicy) -> Self {
+        self.verifier = JwtVerifier::new().policy(policy);
+        self
+    }
+
+    /// Add an older key that is only used to verify existing tokens
+    pub fn with_previous(
+        mut self,
+        kid: impl Into<String>,
+        verifying: VerifyingKey,
+    ) -> Result<Self, AuthError> {
+        let kid = kid.into();
+        self.check_unused(&kid)?;
+        self.keys.push((Some(kid), verifying));
+
+        Ok(self)
+    }
+
+    /// Make a new key pair current, keeping every existing verification key
+    pub fn rotate(
+        mut self,
+        kid: impl Into<String>,
+        signing: SigningKey,
+        verifying: VerifyingKey,
+    ) -> Result<Self, AuthError> {
+        let kid = kid.into();
+        self.check_unused(&kid)?;
+
+        self.signer = JwtSigner::new(signing).kid(kid.clone());
+        self.keys.push((Some(kid.clone()), verifying));
+        self.current = kid;
+
+        Ok(self)
+    }
+
+    /// Stop accepting tokens signed with an old key
+    pub fn retire(mut self, kid: &str) -> Result<Self, AuthError> {
+        if kid == self.current {
+            return Err(AuthError::InvalidInput(
+                "The current key cannot be retired".to_string(),
+            ));
+        }
+
+        self.keys.retain(|(known, _)| known.as_deref() != Some(kid));
+
+        Ok(self)
+    }
+
+    /// `kid` of the key new tokens are signed with
+    pub fn current_kid(&self) -> &str {
+        &self.current
+    }
+
+    /// The `kid` of every key tokens are accepted from, current key included
+    pub fn kids(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().filter_map(|(kid, _)| kid.as_deref())
+    }
+
+    /// The public keys to serve at a `jwks_uri`, each tagged with its `kid`
+    ///
+    /// Shared secrets and keys without exportable public parameters, such as PEM keys, are left
+    /// out.
+    ///
+    /// ### Example
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// use lonewolf_auth_toolkit::token::jwt::{SigningKey, VerifyingKey};
+    /// use lonewolf_auth_toolkit::token::keyring::KeyRing;
+    /// use lonewolf_auth_toolkit::token::Claims;
+    /// use ring::{
+    ///     rand::SystemRandom,
+    ///     signature::{Ed25519KeyPair, KeyPair},
+    /// };
+    ///
+    /// let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    /// let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    ///
+    /// let ring = KeyRing::new(
+    ///     "2024-06",
+    ///     SigningKey::ed25519_pkcs8(pkcs8.as_ref()),
+    ///     VerifyingKey::ed25519(pair.public_key().as_ref())?,
+    /// );
+    /// let claims = Claims::new((), Duration::from_secs(900))?.subject("SomeAccountName");
+    /// assert!(ring.verify::<()>(&ring.sign(&claims)?).is_ok());
+    ///
+    /// let jwks = ring.jwks();
+    /// assert!(jwks.find("2024-06").is_some());
+    /// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+    /// ```
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .keys
+                .iter()
+                .filter_map(|(kid, key)| key.to_jwk(kid.as_deref()).ok())
+                .collect(),
+        }
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &Claims<T>) -> Result<String, AuthError> {
+        self.signer.sign(claims)
+    }
+
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<Claims<T>, AuthError> {
+        self.verifier.verify_with(token, &self.keys)
+    }
+
+    fn check_unused(&self, kid: &str) -> Result<(), AuthError> {
+        if self.kids().any(|known| known == kid) {
+            return Err(AuthError::InvalidInput(
+                "Key id is already in use".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use crate::{
+    mfa::{
+        code::CodeStore,
+        otp::{Otp, OtpChannel},
+    },
+    otel, AuthError,
+};
+
+/// How long a login code can be used for
+pub const DEFAULT_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Passwordless sign in by sending a short numeric code the user types in
+///
+/// The alternative to `magic_link::MagicLinks` for mail clients and phones that break or open
+/// links in the wrong browser. Codes go out over any `OtpChannel`, e.g. `email::Mailer` or
+/// `mfa::sms::SmsChannel`, and are kept apart from MFA codes sent over the same channel. Only a
+/// hash of each code is stored; a code is single use, expires after the TTL and is discarded after
+/// too many wrong guesses, and sending again within the cooldown fails with
+/// `AuthError::RateLimited`.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::mfa::code::MemoryCodeStore;
+/// use lonewolf_auth_toolkit::mfa::otp::OtpChannel;
+/// use lonewolf_auth_toolkit::token::login_code::LoginCodes;
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct Outbox(Mutex<Vec<String>>);
+///
+/// impl OtpChannel for Outbox {
+///     const NAME: &'static str = "email";
+///
+///     async fn deliver(&self, _email: &str, code: &str) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().push(code.to_string());
+///         Ok(())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let codes = LoginCodes::new(Outbox::default(), MemoryCodeStore::default());
+///
+///     codes.send("Someone@Example.com").await?;
+///     assert!(matches!(
+///         codes.send("someone@example.com").await,
+///         Err(AuthError::RateLimited(_))
+///     ));
+///
+///     // The user types the code from the email
+///     let code = codes.channel().0.lock().unwrap()[0].clone();
+///     assert_eq!(codes.consume("someone@example.com", &code).await?, "someone@example.com");
+///
+///     // Codes only work once
+///     assert!(codes.consume("someone@example.com", &code).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct LoginCodes<C, S> {
+    otp: Otp<C, S>,
+}
+
+impl<C: OtpChannel, S: CodeStore> LoginCodes<C, S> {
+    pub fn new(channel: C, store: S) -> Self {
+        Self {
+            otp: Otp::new(channel, store).ttl(DEFAULT_TTL),
+        }
+    }
+
+    /// Length of the generated codes
+    pub fn digits(mut self, digits: usize) -> Self {
+        self.otp = self.otp.digits(digits);
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.otp = self.otp.ttl(ttl);
+        self
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.otp = self.otp.max_attempts(max_attempts);
+        self
+    }
+
+    /// Minimum time between two codes for the same address, `otp::DEFAULT_COOLDOWN` by default
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.otp = self.otp.cooldown(cooldown);
+        self
+    }
+
+    pub fn channel(&self) -> &C {
+        self.otp.channel()
+    }
+
+    /// Generate a code for the address or phone number and deliver it
+    pub async fn send(&self, recipient: &str) -> Result<(), AuthError> {
+        let key = self.key(recipient);
+        let send = self.otp.dispatch(&key, recipient.trim());
+
+        otel::span("login_code.send", Some(&key), send).await
+    }
+
+    /// Check a code the user typed in and use it up, returning the lowercased recipient to sign
+    /// in
+    ///
+    /// Fails with `AuthError::Verification` for wrong, used and expired codes.
+    pub async fn consume(&self, recipient: &str, code: &str) -> Result<String, AuthError> {
+        let key = self.key(recipient);
+        let consume = async {
+            if !self.otp.check(&key, code).await? {
+                return Err(AuthError::Verification(
+                    "Login code is invalid or expired".to_string(),
+                ));
+            }
+
+            Ok(normalize(recipient))
+        };
+
+        otel::span("login_code.consume", Some(&key), consume).await
+    }
+
+    fn key(&self, recipient: &str) -> String {
+        self.otp.key(&format!("login:{}", normalize(recipient)))
+    }
+}
+
+fn normalize(recipient: &str) -> String {
+    recipient.trim().to_lowercase()
+}
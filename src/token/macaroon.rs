@@ -0,0 +1,471 @@
+use std::collections::HashSet;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, RngCore};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    crypto::{ct_eq, MIN_HMAC_KEY_LEN},
+    AuthError,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+type Predicate = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// How deeply discharges may themselves require discharges
+const MAX_DEPTH: usize = 8;
+
+/// A condition a macaroon is only valid under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Caveat {
+    /// A predicate the target service checks itself, e.g. `time < 1700000000`
+    FirstParty(String),
+    /// A condition another service vouches for by issuing a discharge macaroon
+    ThirdParty {
+        /// Where the client should ask for the discharge
+        location: String,
+        /// Tells the third party what to check and with which key to sign the discharge
+        id: String,
+        /// The caveat key, encrypted under the signature it was added at
+        verification_id: Vec<u8>,
+    },
+}
+
+/// A bearer token that anyone holding it can restrict further before passing it on
+///
+/// The issuer mints it from a root key. Each caveat chains the HMAC signature, so caveats can be
+/// added without the key but never removed: a client can narrow a token to one scope or a few
+/// minutes before delegating it, and the service still verifies it with only its root key.
+/// Third party caveats require a discharge macaroon from another service, which the client binds
+/// to this token with `bind` so it cannot be reused with another one.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::token::macaroon::{Macaroon, MacaroonVerifier};
+/// use lonewolf_auth_toolkit::clock::{Clock, SystemClock};
+///
+/// let now = SystemClock.now()?;
+/// let root_key = b"a root key of at least 32 bytes!";
+/// let macaroon = Macaroon::new(root_key, "SomeAccountName", Some("https://api.example.com"))?
+///     .scopes(&["files:read", "files:write"])
+///     .expires_at(now + 3600);
+///
+/// // The client narrows its token before handing it to a helper
+/// let delegated = Macaroon::parse(&macaroon.serialize())?.scopes(&["files:read"]);
+///
+/// let reading = MacaroonVerifier::new().scope("files:read");
+/// reading.verify(root_key, &delegated, &[])?;
+///
+/// let writing = MacaroonVerifier::new().scope("files:write");
+/// assert!(writing.verify(root_key, &delegated, &[]).is_err());
+/// writing.verify(root_key, &macaroon, &[])?;
+///
+/// // A third party caveat needs a discharge from the service that holds its key
+/// let caveat_key = b"a caveat key, at least 32 bytes!";
+/// let guarded = macaroon.third_party("https://mfa.example.com", caveat_key, "mfa-check-1")?;
+/// assert!(reading.verify(root_key, &guarded, &[]).is_err());
+///
+/// let discharge = Macaroon::new(caveat_key, "mfa-check-1", None)?.expires_at(now + 300);
+/// reading.verify(root_key, &guarded, &[guarded.bind(&discharge)])?;
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Macaroon {
+    location: Option<String>,
+    identifier: String,
+    caveats: Vec<Caveat>,
+    signature: [u8; 32],
+}
+
+impl Macaroon {
+    /// Mint a macaroon; `identifier` lets the issuer find `root_key` again at verification
+    pub fn new(
+        root_key: &[u8],
+        identifier: &str,
+        location: Option<&str>,
+    ) -> Result<Self, AuthError> {
+        if root_key.len() < MIN_HMAC_KEY_LEN {
+            return Err(AuthError::InvalidInput(format!(
+                "Macaroon keys must be at least {} bytes",
+                MIN_HMAC_KEY_LEN
+            )));
+        }
+
+        Ok(Self {
+            location: location.map(str::to_string),
+            identifier: identifier.to_string(),
+            caveats: Vec::new(),
+            signature: hmac(&derive_key(root_key), identifier.as_bytes()),
+        })
+    }
+
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    pub fn caveats(&self) -> &[Caveat] {
+        &self.caveats
+    }
+
+    /// Add a first party caveat
+    pub fn caveat(mut self, predicate: &str) -> Self {
+        self.signature = hmac(&self.signature, predicate.as_bytes());
+        self.caveats.push(Caveat::FirstParty(predicate.to_string()));
+        self
+    }
+
+    /// Only valid before the Unix timestamp `expires_at`
+    pub fn expires_at(self, expires_at: u64) -> Self {
+        self.caveat(&format!("time < {}", expires_at))
+    }
+
+    /// Only valid for requests needing no scope outside `scopes`
+    ///
+    /// Adding this again narrows the token to the scopes both lists share.
+    pub fn scopes(self, scopes: &[&str]) -> Self {
+        self.caveat(&format!("scope = {}", scopes.join(" ")))
+    }
+
+    /// Add a caveat only a discharge macaroon minted with `caveat_key` satisfies
+    ///
+    /// Share `caveat_key` with the third party, typically inside `id` encrypted to it, and ask
+    /// it to check whatever `id` describes before minting `Macaroon::new(caveat_key, id, ..)`.
+    pub fn third_party(
+        mut self,
+        location: &str,
+        caveat_key: &[u8],
+        id: &str,
+    ) -> Result<Self, AuthError> {
+        if caveat_key.len() < MIN_HMAC_KEY_LEN {
+            return Err(AuthError::InvalidInput(format!(
+                "Macaroon keys must be at least {} bytes",
+                MIN_HMAC_KEY_LEN
+            )));
+        }
+
+        let verification_id = encrypt(&self.signature, &derive_key(caveat_key))?;
+        self.signature = hmac(&self.signature, &third_party_input(&verification_id, id));
+        self.caveats.push(Caveat::ThirdParty {
+            location: location.to_string(),
+            id: id.to_string(),
+            verification_id,
+        });
+
+        Ok(self)
+    }
+
+    /// Tie a discharge to this macaroon, so it is only accepted alongside it
+    pub fn bind(&self, discharge: &Macaroon) -> Macaroon {
+        let mut bound = discharge.clone();
+        bound.signature = bind(&self.signature, &discharge.signature);
+
+        bound
+    }
+
+    /// URL safe Base64 of the macaroon's JSON form
+    pub fn serialize(&self) -> String {
+        let wire = Wire {
+            location: self.location.clone(),
+            identifier: self.identifier.clone(),
+            caveats: self
+                .caveats
+                .iter()
+                .map(|caveat| match caveat {
+                    Caveat::FirstParty(predicate) => WireCaveat {
+                        id: predicate.clone(),
+                        location: None,
+                        verification_id: None,
+                    },
+                    Caveat::ThirdParty {
+                        location,
+                        id,
+                        verification_id,
+                    } => WireCaveat {
+                        id: id.clone(),
+                        location: Some(location.clone()),
+                        verification_id: Some(URL_SAFE_NO_PAD.encode(verification_id)),
+                    },
+                })
+                .collect(),
+            signature: URL_SAFE_NO_PAD.encode(self.signature),
+        };
+
+        // Serializing plain strings cannot fail
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&wire).unwrap_or_default())
+    }
+
+    /// Read a macaroon produced by `serialize`; fails with `AuthError::Malformed`
+    pub fn parse(serialized: &str) -> Result<Self, AuthError> {
+        let malformed = || AuthError::Malformed("Macaroon is malformed".to_string());
+
+        let json = URL_SAFE_NO_PAD
+            .decode(serialized.trim())
+            .map_err(|_| malformed())?;
+        let wire: Wire = serde_json::from_slice(&json).map_err(|_| malformed())?;
+
+        let caveats = wire
+            .caveats
+            .into_iter()
+            .map(|caveat| match (caveat.location, caveat.verification_id) {
+                (None, None) => Ok(Caveat::FirstParty(caveat.id)),
+                (Some(location), Some(verification_id)) => Ok(Caveat::ThirdParty {
+                    location,
+                    id: caveat.id,
+                    verification_id: URL_SAFE_NO_PAD
+                        .decode(verification_id)
+                        .map_err(|_| malformed())?,
+                }),
+                _ => Err(malformed()),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            location: wire.location,
+            identifier: wire.identifier,
+            caveats,
+            signature: URL_SAFE_NO_PAD
+                .decode(wire.signature)
+                .ok()
+                .and_then(|signature| signature.try_into().ok())
+                .ok_or_else(malformed)?,
+        })
+    }
+}
+
+/// Checks a macaroon's signature chain and caveats on behalf of one request
+///
+/// `time < ...` caveats are checked against the clock and `scope = ...` caveats against the
+/// scopes the request needs. Any other first party caveat must be satisfied explicitly with
+/// `satisfy` or `satisfy_with`, so caveats the service does not understand are never ignored.
+#[derive(Default)]
+pub struct MacaroonVerifier<C = SystemClock> {
+    scopes: Vec<String>,
+    exact: HashSet<String>,
+    general: Vec<Predicate>,
+    clock: C,
+}
+
+impl MacaroonVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<C: Clock> MacaroonVerifier<C> {
+    /// Check `time < ...` caveats against `clock` instead of the system time
+    pub fn clock<D: Clock>(self, clock: D) -> MacaroonVerifier<D> {
+        MacaroonVerifier {
+            scopes: self.scopes,
+            exact: self.exact,
+            general: self.general,
+            clock,
+        }
+    }
+
+    /// A scope the request needs; every `scope = ...` caveat must grant it
+    pub fn scope(mut self, scope: &str) -> Self {
+        self.scopes.push(scope.to_string());
+        self
+    }
+
+    /// Accept caveats equal to `predicate`, e.g. `account = SomeAccountName`
+    pub fn satisfy(mut self, predicate: &str) -> Self {
+        self.exact.insert(predicate.to_string());
+        self
+    }
+
+    /// Accept caveats `check` returns true for
+    pub fn satisfy_with(mut self, check: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.general.push(Box::new(check));
+        self
+    }
+
+    /// Fails with `AuthError::Verification` unless the macaroon was minted with `root_key`, every
+    /// caveat holds and every third party caveat has a bound discharge in `discharges`
+    pub fn verify(
+        &self,
+        root_key: &[u8],
+        macaroon: &Macaroon,
+        discharges: &[Macaroon],
+    ) -> Result<(), AuthError> {
+        let now = self.clock.now()?;
+        let signature = self.chain(
+            &derive_key(root_key),
+            macaroon,
+            macaroon,
+            discharges,
+            0,
+            now,
+        )?;
+
+        if !ct_eq(signature, macaroon.signature) {
+            return Err(AuthError::Verification(
+                "Macaroon signature is invalid".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Recompute `macaroon`'s signature from `key`, checking its caveats on the way
+    fn chain(
+        &self,
+        key: &[u8],
+        root: &Macaroon,
+        macaroon: &Macaroon,
+        discharges: &[Macaroon],
+        depth: usize,
+        now: u64,
+    ) -> Result<[u8; 32], AuthError> {
+        let rejected = |reason: String| Err(AuthError::Verification(reason));
+        let mut signature = hmac(key, macaroon.identifier.as_bytes());
+
+        for caveat in &macaroon.caveats {
+            match caveat {
+                Caveat::FirstParty(predicate) => {
+                    if !self.holds(predicate, now) {
+                        return rejected(format!("Macaroon caveat {} is not met", predicate));
+                    }
+
+                    signature = hmac(&signature, predicate.as_bytes());
+                }
+                Caveat::ThirdParty {
+                    id,
+                    verification_id,
+                    ..
+                } => {
+                    if depth >= MAX_DEPTH {
+                        return rejected("Macaroon discharges are nested too deeply".to_string());
+                    }
+                    let Some(caveat_key) = decrypt(&signature, verification_id) else {
+                        return rejected("Macaroon caveat is corrupt".to_string());
+                    };
+
+                    let satisfied = discharges
+                        .iter()
+                        .filter(|discharge| discharge.identifier == *id)
+                        .any(|discharge| {
+                            self.chain(&caveat_key, root, discharge, discharges, depth + 1, now)
+                                .is_ok_and(|discharged| {
+                                    ct_eq(bind(&root.signature, &discharged), discharge.signature)
+                                })
+                        });
+                    if !satisfied {
+                        return rejected(format!("Macaroon caveat {} is not discharged", id));
+                    }
+
+                    signature = hmac(&signature, &third_party_input(verification_id, id));
+                }
+            }
+        }
+
+        Ok(signature)
+    }
+
+    fn holds(&self, predicate: &str, now: u64) -> bool {
+        if let Some(expires_at) = predicate.strip_prefix("time < ") {
+            return expires_at
+                .parse::<u64>()
+                .is_ok_and(|expires_at| now < expires_at);
+        }
+
+        if let Some(granted) = predicate.strip_prefix("scope = ") {
+            let granted: HashSet<&str> = granted.split_whitespace().collect();
+            return self
+                .scopes
+                .iter()
+                .all(|scope| granted.contains(scope.as_str()));
+        }
+
+        self.exact.contains(predicate) || self.general.iter().any(|check| check(predicate))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Wire {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    identifier: String,
+    caveats: Vec<WireCaveat>,
+    signature: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireCaveat {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verification_id: Option<String>,
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+
+    mac.finalize().into_bytes().into()
+}
+
+/// Keys of any length become a fixed size HMAC key, as libmacaroons does
+fn derive_key(key: &[u8]) -> [u8; 32] {
+    hmac(b"macaroons-key-generator", key)
+}
+
+fn bind(root_signature: &[u8; 32], discharge_signature: &[u8; 32]) -> [u8; 32] {
+    hmac(root_signature, discharge_signature)
+}
+
+fn third_party_input(verification_id: &[u8], id: &str) -> Vec<u8> {
+    let mut input = verification_id.to_vec();
+    input.extend_from_slice(id.as_bytes());
+
+    input
+}
+
+fn aead_key(signature: &[u8; 32]) -> LessSafeKey {
+    // A 32 byte key is always accepted
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, signature).expect("32 byte key"))
+}
+
+fn encrypt(signature: &[u8; 32], caveat_key: &[u8; 32]) -> Result<Vec<u8>, AuthError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    thread_rng().fill_bytes(&mut nonce);
+
+    let mut in_out = caveat_key.to_vec();
+    aead_key(signature)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| AuthError::InvalidInput("Failed to encrypt caveat key".to_string()))?;
+
+    let mut verification_id = nonce.to_vec();
+    verification_id.extend_from_slice(&in_out);
+
+    Ok(verification_id)
+}
+
+fn decrypt(signature: &[u8; 32], verification_id: &[u8]) -> Option<Vec<u8>> {
+    if verification_id.len() < NONCE_LEN {
+        return None;
+    }
+
+    let (nonce, ciphertext) = verification_id.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let mut in_out = ciphertext.to_vec();
+
+    aead_key(signature)
+        .open_in_place(nonce, Aad::empty(), &mut in_out)
+        .ok()
+        .map(|key| key.to_vec())
+}
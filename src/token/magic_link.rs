@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use url::Url;
+use zeroize::Zeroizing;
+
+use crate::{crypto::MIN_HMAC_KEY_LEN, otel, AuthError};
+
+use super::{generate_token, hash_token, now};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long a magic link can be used for
+pub const DEFAULT_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// A magic link that has been sent and not yet used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingMagicLink {
+    /// SHA-256 of the link's id; the id itself is never stored
+    pub hash: String,
+    /// Lowercased address the link was sent to
+    pub email: String,
+    /// Where to send the user after signing in, a path on this site
+    pub redirect: Option<String>,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+}
+
+/// Delivers magic links, e.g. through an email provider's API
+pub trait MagicLinkSender {
+    fn send(&self, email: &str, link: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Persists magic links between sending and use
+///
+/// `consume` must delete the link and report whether it existed in a single atomic operation so
+/// a link can only be used once.
+pub trait MagicLinkStore {
+    fn save(&self, link: PendingMagicLink) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn get(
+        &self,
+        hash: &str,
+    ) -> impl Future<Output = Result<Option<PendingMagicLink>, AuthError>> + Send;
+
+    /// Returns `false` if there was no link with this hash
+    fn consume(&self, hash: &str) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Passwordless sign in by emailing a single use link
+///
+/// The link carries a token `<id>.<expires>.<signature>` whose HMAC binds it to the address it was
+/// sent to, and only a hash of the id is stored. `consume` accepts each link once, before it
+/// expires, and returns the address to sign in. Redirects must be paths on this site so links
+/// cannot be used as open redirects.
+///
+/// ### Example
+/// ```rust
+/// use std::{collections::HashMap, sync::Mutex};
+///
+/// use lonewolf_auth_toolkit::token::magic_link::{
+///     MagicLinkSender, MagicLinkStore, MagicLinks, PendingMagicLink,
+/// };
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct Outbox(Mutex<Vec<(String, String)>>);
+///
+/// impl MagicLinkSender for Outbox {
+///     async fn send(&self, email: &str, link: &str) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().push((email.to_string(), link.to_string()));
+///         Ok(())
+///     }
+/// }
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, PendingMagicLink>>);
+///
+/// impl MagicLinkStore for MemoryStore {
+///     async fn save(&self, link: PendingMagicLink) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(link.hash.clone(), link);
+///         Ok(())
+///     }
+///
+///     async fn get(&self, hash: &str) -> Result<Option<PendingMagicLink>, AuthError> {
+///         Ok(self.0.lock().unwrap().get(hash).cloned())
+///     }
+///
+///     async fn consume(&self, hash: &str) -> Result<bool, AuthError> {
+///         Ok(self.0.lock().unwrap().remove(hash).is_some())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let links = MagicLinks::new(
+///         Outbox::default(),
+///         MemoryStore::default(),
+///         &[7u8; 32],
+///         "https://app.example.com/auth/magic",
+///     )?;
+///
+///     links.send("Someone@Example.com", Some("/settings")).await?;
+///
+///     // The user clicks https://app.example.com/auth/magic?token=...
+///     let (_, link) = links.sender().0.lock().unwrap().pop().unwrap();
+///     let token = link.split("token=").nth(1).unwrap().to_string();
+///
+///     let signed_in = links.consume(&token).await?;
+///     assert_eq!(signed_in.email, "someone@example.com");
+///     assert_eq!(signed_in.redirect.as_deref(), Some("/settings"));
+///
+///     // Links only work once
+///     assert!(links.consume(&token).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct MagicLinks<D, S> {
+    sender: D,
+    store: S,
+    signing_key: Zeroizing<Vec<u8>>,
+    base_url: Url,
+    ttl: Duration,
+}
+
+impl<D: MagicLinkSender, S: MagicLinkStore> MagicLinks<D, S> {
+    /// `base_url` is the page that handles clicked links; the token is added as `?token=`
+    pub fn new(sender: D, store: S, signing_key: &[u8], base_url: &str) -> Result<Self, AuthError> {
+        if signing_key.len() < MIN_HMAC_KEY_LEN {
+            return Err(AuthError::InvalidInput(format!(
+                "Magic link signing keys must be at least {} bytes",
+                MIN_HMAC_KEY_LEN
+            )));
+        }
+
+        Ok(Self {
+            sender,
+            store,
+            signing_key: Zeroizing::new(signing_key.to_vec()),
+            base_url: Url::parse(base_url)?,
+            ttl: DEFAULT_TTL,
+        })
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn sender(&self) -> &D {
+        &self.sender
+    }
+
+    /// Create a link for the address and deliver it with the sender
+    pub async fn send(&self, email: &str, redirect: Option<&str>) -> Result<(), AuthError> {
+        let link = self.issue(email, redirect).await?;
+
+        self.sender.send(email.trim(), &link).await
+    }
+
+    /// Create a link for the address without sending it, for callers that deliver it themselves
+    pub async fn issue(&self, email: &str, redirect: Option<&str>) -> Result<String, AuthError> {
+        let id = generate_token();
+        let issue = async {
+            if let Some(redirect) = redirect {
+                if !redirect.starts_with('/')
+                    || redirect.starts_with("//")
+                    || redirect.contains('\\')
+                {
+                    return Err(AuthError::InvalidInput(
+                        "Magic link redirects must be a path on this site".to_string(),
+                    ));
+                }
+            }
+
+            let email = normalize(email);
+            let expires_at = now()? + self.ttl.as_secs();
+
+            self.store
+                .save(PendingMagicLink {
+                    hash: hash_token(&id),
+                    email: email.clone(),
+                    redirect: redirect.map(str::to_string),
+                    expires_at,
+                })
+                .await?;
+
+            let tag = self.mac(&id, &email, expires_at).finalize().into_bytes();
+            let token = format!("{}.{}.{}", id, expires_at, URL_SAFE_NO_PAD.encode(tag));
+
+            let mut link = self.base_url.clone();
+            link.query_pairs_mut().append_pair("token", &token);
+
+            Ok(link.into())
+        };
+
+        otel::span("magic_link.issue", Some(&id), issue).await
+    }
+
+    /// Check a clicked link's token and use it up, returning who to sign in
+    pub async fn consume(&self, token: &str) -> Result<PendingMagicLink, AuthError> {
+        let flow = token.split('.').next();
+        otel::span("magic_link.consume", flow, self.check(token)).await
+    }
+
+    async fn check(&self, token: &str) -> Result<PendingMagicLink, AuthError> {
+        let mut parts = token.split('.');
+        let (id, expires_at, tag) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(expires_at), Some(tag), None) => {
+                (id, expires_at.parse::<u64>()?, URL_SAFE_NO_PAD.decode(tag)?)
+            }
+            _ => return Err(AuthError::Malformed("Malformed magic link".to_string())),
+        };
+
+        if expires_at <= now()? {
+            return Err(AuthError::Verification(
+                "Magic link has expired".to_string(),
+            ));
+        }
+
+        let hash = hash_token(id);
+        let invalid = || AuthError::Verification("Magic link is invalid or used".to_string());
+
+        let link = self.store.get(&hash).await?.ok_or_else(invalid)?;
+
+        self.mac(id, &link.email, expires_at)
+            .verify_slice(&tag)
+            .map_err(|_| invalid())?;
+
+        if !self.store.consume(&hash).await? {
+            return Err(invalid());
+        }
+
+        Ok(link)
+    }
+
+    fn mac(&self, id: &str, email: &str, expires_at: u64) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.signing_key).expect("HMAC accepts any key length");
+        mac.update(format!("{}.{}.{}", id, email, expires_at).as_bytes());
+        mac
+    }
+}
+
+fn normalize(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Keeps pending magic links in process memory, dropping expired ones as new ones are saved
+#[derive(Debug, Default)]
+pub struct MemoryMagicLinkStore {
+    links: Mutex<HashMap<String, PendingMagicLink>>,
+}
+
+impl MemoryMagicLinkStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, PendingMagicLink>>, AuthError> {
+        self.links
+            .lock()
+            .map_err(|_| AuthError::backend("Magic link store lock poisoned"))
+    }
+}
+
+impl MagicLinkStore for MemoryMagicLinkStore {
+    async fn save(&self, link: PendingMagicLink) -> Result<(), AuthError> {
+        let now = now()?;
+        let mut links = self.lock()?;
+
+        links.retain(|_, link| link.expires_at > now);
+        links.insert(link.hash.clone(), link);
+
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Option<PendingMagicLink>, AuthError> {
+        Ok(self.lock()?.get(hash).cloned())
+    }
+
+    async fn consume(&self, hash: &str) -> Result<bool, AuthError> {
+        Ok(self.lock()?.remove(hash).is_some())
+    }
+}
@@ -0,0 +1,180 @@
+pub mod dpop;
+pub mod email_change;
+pub mod email_verification;
+#[cfg(feature = "token")]
+pub mod impersonation;
+#[cfg(feature = "token")]
+pub mod jwks;
+#[cfg(feature = "token")]
+pub mod jwt;
+#[cfg(feature = "token")]
+pub mod keyring;
+#[cfg(feature = "mfa")]
+pub mod login_code;
+pub mod macaroon;
+pub mod magic_link;
+pub mod nonce;
+pub mod opaque;
+#[cfg(feature = "token")]
+pub mod paseto;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod random;
+pub mod refresh;
+pub mod remember;
+pub mod revocation;
+pub mod signed_url;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+mod validation;
+
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use crate::{clock, AuthError};
+
+use random::RandomToken;
+
+pub use validation::{ValidationPolicy, DEFAULT_LEEWAY};
+
+/// The registered claims of a token plus an application defined `custom` set
+///
+/// Timestamps are Unix seconds. `custom` is flattened into the same JSON object, so its field names
+/// must not clash with the registered ones.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::token::Claims;
+///
+/// let claims = Claims::new((), Duration::from_secs(900))
+///     .unwrap()
+///     .issuer("https://auth.example.com")
+///     .subject("SomeAccountName")
+///     .audience("https://api.example.com");
+///
+/// assert_eq!(claims.expires_at - claims.issued_at.unwrap(), 900);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claims<T> {
+    #[serde(rename = "iss", default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    #[serde(rename = "sub", default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// Serialized as a single string when there is exactly one audience
+    #[serde(
+        rename = "aud",
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "serialize_audience",
+        deserialize_with = "deserialize_audience"
+    )]
+    pub audience: Vec<String>,
+    #[serde(rename = "exp")]
+    pub expires_at: u64,
+    #[serde(rename = "nbf", default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<u64>,
+    #[serde(rename = "iat", default, skip_serializing_if = "Option::is_none")]
+    pub issued_at: Option<u64>,
+    #[serde(rename = "jti", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub custom: T,
+}
+
+impl<T> Claims<T> {
+    /// Claims issued now that expire after `ttl`
+    pub fn new(custom: T, ttl: Duration) -> Result<Self, AuthError> {
+        let now = now()?;
+
+        Ok(Self {
+            issuer: None,
+            subject: None,
+            audience: Vec::new(),
+            expires_at: now + ttl.as_secs(),
+            not_before: None,
+            issued_at: Some(now),
+            id: None,
+            custom,
+        })
+    }
+
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    pub fn subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = Some(subject.into());
+        self
+    }
+
+    /// Add an intended audience; may be called more than once
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience.push(audience.into());
+        self
+    }
+
+    /// Reject the token before this Unix timestamp
+    pub fn not_before(mut self, not_before: u64) -> Self {
+        self.not_before = Some(not_before);
+        self
+    }
+
+    /// A unique token id, useful for revocation lists
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+}
+
+/// Hash an opaque or refresh token for storage and lookup
+///
+/// Tokens carry 256 bits of entropy, so a fast unsalted hash is enough to make a leaked table
+/// useless.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 32 random bytes, base64url encoded
+pub(crate) fn generate_token() -> String {
+    RandomToken::new().generate()
+}
+
+/// The current Unix time in seconds
+pub(crate) fn now() -> Result<u64, AuthError> {
+    Ok(clock::since_epoch()?.as_secs())
+}
+
+fn serialize_audience<S: Serializer>(
+    audience: &[String],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match audience {
+        [single] => serializer.serialize_str(single),
+        many => many.serialize(serializer),
+    }
+}
+
+pub(crate) fn deserialize_audience<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Audience {
+        Single(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Audience::deserialize(deserializer)? {
+        Audience::Single(single) => vec![single],
+        Audience::Many(many) => many,
+    })
+}
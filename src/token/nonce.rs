@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::AuthError;
+
+use super::{generate_token, hash_token, now};
+
+/// How long an issued nonce stays usable
+pub const DEFAULT_TTL: Duration = Duration::from_secs(600);
+
+/// Persists nonce hashes until they are used or expire
+///
+/// Both methods must be single atomic operations, e.g. `SET NX` and `DEL` in Redis or an
+/// `INSERT ... ON CONFLICT DO NOTHING` and a `DELETE ... RETURNING` in SQL, so a nonce cannot be
+/// accepted twice by racing requests. Entries past their expiry count as absent.
+pub trait NonceStore {
+    /// Insert the hash unless an unexpired entry already exists, returning whether it was inserted
+    fn insert(
+        &self,
+        hash: &str,
+        expires_at: u64,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Delete the hash, returning whether an unexpired entry existed
+    fn take(&self, hash: &str) -> impl Future<Output = Result<bool, AuthError>> + Send;
+}
+
+/// Single use nonces with a TTL
+///
+/// There are two ways to use it:
+///
+/// - Issued nonces, for OIDC `nonce` parameters or magic link ids: `issue` returns a random nonce
+///   and `consume` accepts it once before it expires.
+/// - Seen nonces, for webhook replay protection or deduplicating requests: `remember` records a
+///   nonce chosen by someone else, such as a webhook delivery id, and returns `false` if it was
+///   already seen within the TTL. Reject messages older than the TTL by their timestamp, since
+///   their nonces may have been forgotten.
+///
+/// Only a SHA-256 hash of each nonce is stored.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::token::nonce::{MemoryNonceStore, Nonces};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let nonces = Nonces::new(MemoryNonceStore::default());
+///
+///     // Sent as the OIDC nonce and checked against the ID token's nonce claim
+///     let nonce = nonces.issue().await?;
+///     assert!(nonces.consume(&nonce).await?);
+///     assert!(!nonces.consume(&nonce).await?);
+///
+///     // A webhook delivered twice
+///     assert!(nonces.remember("evt_1NqVx2").await?);
+///     assert!(!nonces.remember("evt_1NqVx2").await?);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Nonces<S> {
+    store: S,
+    ttl: Duration,
+}
+
+impl<S: NonceStore> Nonces<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// A new random nonce, usable once with `consume`
+    pub async fn issue(&self) -> Result<String, AuthError> {
+        let nonce = generate_token();
+        let hash = hash_token(&scoped("issued", &nonce));
+
+        if !self.store.insert(&hash, self.expires_at()?).await? {
+            return Err(AuthError::backend("Generated nonce already exists"));
+        }
+
+        Ok(nonce)
+    }
+
+    /// Use up an issued nonce; `false` if it is unknown, expired or already used
+    pub async fn consume(&self, nonce: &str) -> Result<bool, AuthError> {
+        self.store.take(&hash_token(&scoped("issued", nonce))).await
+    }
+
+    /// Record a nonce chosen elsewhere; `false` if it was already seen within the TTL
+    pub async fn remember(&self, nonce: &str) -> Result<bool, AuthError> {
+        if nonce.is_empty() {
+            return Err(AuthError::InvalidInput("Nonce is empty".to_string()));
+        }
+
+        self.store
+            .insert(&hash_token(&scoped("seen", nonce)), self.expires_at()?)
+            .await
+    }
+
+    fn expires_at(&self) -> Result<u64, AuthError> {
+        Ok(now()? + self.ttl.as_secs())
+    }
+}
+
+/// Keeps issued and seen nonces apart, so remembering a nonce can never make it consumable
+fn scoped(kind: &str, nonce: &str) -> String {
+    format!("{}:{}", kind, nonce)
+}
+
+/// Keeps nonce hashes in process memory, dropping expired ones as new ones are inserted
+#[derive(Debug, Default)]
+pub struct MemoryNonceStore {
+    nonces: Mutex<HashMap<String, u64>>,
+}
+
+impl MemoryNonceStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, u64>>, AuthError> {
+        self.nonces
+            .lock()
+            .map_err(|_| AuthError::backend("Nonce store lock poisoned"))
+    }
+}
+
+impl NonceStore for MemoryNonceStore {
+    async fn insert(&self, hash: &str, expires_at: u64) -> Result<bool, AuthError> {
+        let now = now()?;
+        let mut nonces = self.lock()?;
+
+        nonces.retain(|_, expires_at| *expires_at > now);
+        if nonces.contains_key(hash) {
+            return Ok(false);
+        }
+        nonces.insert(hash.to_string(), expires_at);
+
+        Ok(true)
+    }
+
+    async fn take(&self, hash: &str) -> Result<bool, AuthError> {
+        let now = now()?;
+
+        Ok(self
+            .lock()?
+            .remove(hash)
+            .is_some_and(|expires_at| expires_at > now))
+    }
+}
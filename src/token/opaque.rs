@@ -0,0 +1,251 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{metrics, trace, AuthError};
+
+use super::{generate_token, hash_token, now};
+
+/// How long an opaque token stays valid unless configured otherwise
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A stored opaque token; the token itself is only kept as a SHA-256 hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpaqueToken {
+    pub hash: String,
+    /// What the token is for, e.g. "session", "api-key" or "password-reset"
+    pub purpose: String,
+    /// Usually the account the token was issued to
+    pub subject: String,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+    /// Unix timestamp in seconds, or `None` for tokens that never expire
+    pub expires_at: Option<u64>,
+}
+
+impl OpaqueToken {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Persists opaque tokens by hash
+///
+/// `revoke` must delete the token and report whether it existed in a single atomic operation
+/// (e.g. `DELETE ... RETURNING`) so single use tokens can only be consumed once.
+pub trait OpaqueTokenStore {
+    fn insert(&self, token: OpaqueToken) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn get(
+        &self,
+        hash: &str,
+    ) -> impl Future<Output = Result<Option<OpaqueToken>, AuthError>> + Send;
+
+    /// Returns `false` if there was no token with this hash
+    fn revoke(&self, hash: &str) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Delete every token with this purpose issued to the subject
+    fn revoke_all(
+        &self,
+        purpose: &str,
+        subject: &str,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// Issues and checks high entropy bearer tokens for one purpose
+///
+/// Tokens are 256 random bits, optionally behind a prefix such as `lw_live_` so leaked keys are
+/// easy to spot with secret scanners. Only their hash is stored, and a token issued for one
+/// purpose is never accepted for another.
+///
+/// ### Example
+/// ```rust
+/// use std::{collections::HashMap, sync::Mutex, time::Duration};
+///
+/// use lonewolf_auth_toolkit::token::opaque::{OpaqueToken, OpaqueTokenStore, OpaqueTokens};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, OpaqueToken>>);
+///
+/// impl OpaqueTokenStore for MemoryStore {
+///     async fn insert(&self, token: OpaqueToken) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(token.hash.clone(), token);
+///         Ok(())
+///     }
+///
+///     async fn get(&self, hash: &str) -> Result<Option<OpaqueToken>, AuthError> {
+///         Ok(self.0.lock().unwrap().get(hash).cloned())
+///     }
+///
+///     async fn revoke(&self, hash: &str) -> Result<bool, AuthError> {
+///         Ok(self.0.lock().unwrap().remove(hash).is_some())
+///     }
+///
+///     async fn revoke_all(&self, purpose: &str, subject: &str) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().retain(|_, token| !(token.purpose == purpose && token.subject == subject));
+///         Ok(())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let resets = OpaqueTokens::new(MemoryStore::default(), "password-reset").ttl(Duration::from_secs(900));
+///     let token = resets.issue("SomeAccountName").await?;
+///
+///     assert_eq!(resets.lookup(&token).await?.unwrap().subject, "SomeAccountName");
+///
+///     // Reset tokens are single use
+///     assert!(resets.consume(&token).await?.is_some());
+///     assert!(resets.consume(&token).await?.is_none());
+///
+///     let api_keys = OpaqueTokens::new(MemoryStore::default(), "api-key").prefix("lw_live_").no_expiry();
+///     let key = api_keys.issue("SomeAccountName").await?;
+///
+///     assert!(key.starts_with("lw_live_"));
+///     assert!(api_keys.revoke(&key).await?);
+///     assert!(api_keys.lookup(&key).await?.is_none());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct OpaqueTokens<S> {
+    store: S,
+    purpose: String,
+    prefix: String,
+    ttl: Option<Duration>,
+}
+
+impl<S: OpaqueTokenStore> OpaqueTokens<S> {
+    pub fn new(store: S, purpose: impl Into<String>) -> Self {
+        Self {
+            store,
+            purpose: purpose.into(),
+            prefix: String::new(),
+            ttl: Some(DEFAULT_TTL),
+        }
+    }
+
+    /// Start every token with this string
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Issue tokens that stay valid until revoked, such as API keys
+    pub fn no_expiry(mut self) -> Self {
+        self.ttl = None;
+        self
+    }
+
+    pub async fn issue(&self, subject: &str) -> Result<String, AuthError> {
+        let token = format!("{}{}", self.prefix, generate_token());
+        let now = now()?;
+
+        self.store
+            .insert(OpaqueToken {
+                hash: hash_token(&token),
+                purpose: self.purpose.clone(),
+                subject: subject.to_string(),
+                created_at: now,
+                expires_at: self.ttl.map(|ttl| now + ttl.as_secs()),
+            })
+            .await?;
+        metrics::token_issued("opaque");
+
+        Ok(token)
+    }
+
+    /// The stored token, or `None` if it is unknown, expired or for another purpose
+    pub async fn lookup(&self, token: &str) -> Result<Option<OpaqueToken>, AuthError> {
+        let lookup = async {
+            let now = now()?;
+
+            Ok(self
+                .store
+                .get(&hash_token(token))
+                .await?
+                .filter(|stored| stored.purpose == self.purpose && !stored.is_expired(now)))
+        };
+
+        trace::instrument("token.opaque.lookup", &[("purpose", &self.purpose)], lookup).await
+    }
+
+    /// Look up and revoke a single use token, such as a password reset link
+    pub async fn consume(&self, token: &str) -> Result<Option<OpaqueToken>, AuthError> {
+        let stored = match self.lookup(token).await? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+
+        if !self.store.revoke(&stored.hash).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(stored))
+    }
+
+    /// Returns `false` if the token is unknown or for another purpose
+    pub async fn revoke(&self, token: &str) -> Result<bool, AuthError> {
+        let hash = hash_token(token);
+
+        match self.store.get(&hash).await? {
+            Some(stored) if stored.purpose == self.purpose => self.store.revoke(&hash).await,
+            _ => Ok(false),
+        }
+    }
+
+    /// Revoke every token for this purpose issued to the subject, e.g. after a password change
+    pub async fn revoke_all(&self, subject: &str) -> Result<(), AuthError> {
+        self.store.revoke_all(&self.purpose, subject).await
+    }
+}
+
+/// Keeps opaque tokens in process memory, dropping expired ones as new ones are inserted
+#[derive(Debug, Default)]
+pub struct MemoryOpaqueTokenStore {
+    tokens: Mutex<HashMap<String, OpaqueToken>>,
+}
+
+impl MemoryOpaqueTokenStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, OpaqueToken>>, AuthError> {
+        self.tokens
+            .lock()
+            .map_err(|_| AuthError::backend("Token store lock poisoned"))
+    }
+}
+
+impl OpaqueTokenStore for MemoryOpaqueTokenStore {
+    async fn insert(&self, token: OpaqueToken) -> Result<(), AuthError> {
+        let now = now()?;
+        let mut tokens = self.lock()?;
+
+        tokens.retain(|_, token| !token.is_expired(now));
+        tokens.insert(token.hash.clone(), token);
+
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Option<OpaqueToken>, AuthError> {
+        Ok(self.lock()?.get(hash).cloned())
+    }
+
+    async fn revoke(&self, hash: &str) -> Result<bool, AuthError> {
+        Ok(self.lock()?.remove(hash).is_some())
+    }
+
+    async fn revoke_all(&self, purpose: &str, subject: &str) -> Result<(), AuthError> {
+        self.lock()?
+            .retain(|_, token| !(token.purpose == purpose && token.subject == subject));
+
+        Ok(())
+    }
+}
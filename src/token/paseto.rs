@@ -0,0 +1,523 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat};
+use rand::{thread_rng, RngCore};
+use ring::signature::{Ed25519KeyPair, UnparsedPublicKey, ED25519};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value as Json};
+use zeroize::Zeroizing;
+
+use crate::{
+    crypto::{
+        blake2b::blake2b_keyed,
+        ct_eq,
+        provider::{KeyProvider, SignatureAlgorithm},
+        xchacha20::xchacha20,
+    },
+    metrics, AuthError,
+};
+
+use super::{Claims, ValidationPolicy};
+
+/// Length in bytes of a `v4.local` key
+pub const KEY_LEN: usize = 32;
+
+const LOCAL_HEADER: &str = "v4.local.";
+const PUBLIC_HEADER: &str = "v4.public.";
+const NONCE_LEN: usize = 32;
+const MAC_LEN: usize = 32;
+const SIGNATURE_LEN: usize = 64;
+const TIME_CLAIMS: [&str; 3] = ["exp", "nbf", "iat"];
+
+/// Generate a random `v4.local` key
+pub fn generate_key() -> [u8; KEY_LEN] {
+    let mut key = [0u8; KEY_LEN];
+    thread_rng().fill_bytes(&mut key);
+
+    key
+}
+
+/// Read the `kid` from a token's footer so the right key can be chosen before verifying
+///
+/// The footer is authenticated when the token is verified, but not by this function.
+pub fn kid(token: &str) -> Result<Option<String>, AuthError> {
+    let (_, footer) = split(token)?;
+
+    if footer.is_empty() {
+        return Ok(None);
+    }
+
+    let footer: Json = serde_json::from_slice(&footer)?;
+
+    Ok(footer["kid"].as_str().map(str::to_string))
+}
+
+macro_rules! verify_builder {
+    () => {
+        pub fn policy(mut self, policy: ValidationPolicy) -> Self {
+            self.policy = policy;
+            self
+        }
+
+        /// Bind tokens to data that is authenticated but not stored, such as an account id
+        pub fn implicit(mut self, implicit: impl Into<Vec<u8>>) -> Self {
+            self.implicit = implicit.into();
+            self
+        }
+    };
+}
+
+/// Symmetric `v4.local` tokens: encrypted with XChaCha20 and authenticated with keyed BLAKE2b
+///
+/// Use these when the issuer and the consumer are the same service. The claims are hidden from the
+/// client and there is no algorithm header to tamper with.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::token::paseto::{self, PasetoLocal};
+/// use lonewolf_auth_toolkit::token::{Claims, ValidationPolicy};
+///
+/// let local = PasetoLocal::new(&paseto::generate_key())
+///     .unwrap()
+///     .kid("2024-06")
+///     .policy(ValidationPolicy::new().issuer("https://auth.example.com"));
+///
+/// let claims = Claims::new((), Duration::from_secs(900))
+///     .unwrap()
+///     .issuer("https://auth.example.com")
+///     .subject("SomeAccountName");
+///
+/// let token = local.encrypt(&claims).unwrap();
+///
+/// assert!(token.starts_with("v4.local."));
+/// assert_eq!(paseto::kid(&token).unwrap().as_deref(), Some("2024-06"));
+/// assert_eq!(local.decrypt::<()>(&token).unwrap(), claims);
+///
+/// let other = PasetoLocal::new(&paseto::generate_key()).unwrap();
+/// assert!(other.decrypt::<()>(&token).is_err());
+/// ```
+pub struct PasetoLocal {
+    key: Zeroizing<[u8; KEY_LEN]>,
+    kid: Option<String>,
+    policy: ValidationPolicy,
+    implicit: Vec<u8>,
+}
+
+impl PasetoLocal {
+    pub fn new(key: &[u8]) -> Result<Self, AuthError> {
+        let key = Zeroizing::new(key.try_into().map_err(|_| {
+            AuthError::InvalidInput(format!("PASETO keys must be {} bytes", KEY_LEN))
+        })?);
+
+        Ok(Self {
+            key,
+            kid: None,
+            policy: ValidationPolicy::default(),
+            implicit: Vec::new(),
+        })
+    }
+
+    /// Put `{"kid": ...}` in the footer of new tokens
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    verify_builder!();
+
+    pub fn encrypt<T: Serialize>(&self, claims: &Claims<T>) -> Result<String, AuthError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        thread_rng().fill_bytes(&mut nonce);
+
+        let token = self.encrypt_with_nonce(claims, nonce)?;
+        metrics::token_issued("paseto_local");
+
+        Ok(token)
+    }
+
+    pub fn decrypt<T: DeserializeOwned>(&self, token: &str) -> Result<Claims<T>, AuthError> {
+        let payload = token
+            .strip_prefix(LOCAL_HEADER)
+            .ok_or_else(|| AuthError::Malformed("Token is not v4.local".to_string()))?;
+        let (body, footer) = split(payload)?;
+
+        if body.len() < NONCE_LEN + MAC_LEN {
+            return Err(AuthError::Malformed("Token is too short".to_string()));
+        }
+
+        let (nonce, rest) = body.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - MAC_LEN);
+        let (encryption_key, stream_nonce, auth_key) = self.derive(nonce);
+
+        let expected = blake2b_keyed(
+            MAC_LEN,
+            &auth_key,
+            &pae(&[
+                LOCAL_HEADER.as_bytes(),
+                nonce,
+                ciphertext,
+                &footer,
+                &self.implicit,
+            ]),
+        );
+
+        if !ct_eq(&expected, tag) {
+            return Err(AuthError::Verification(
+                "Token authentication failed".to_string(),
+            ));
+        }
+
+        let mut message = ciphertext.to_vec();
+        xchacha20(&encryption_key, &stream_nonce, &mut message);
+
+        let claims = decode_claims(&message)?;
+        self.policy.validate(&claims)?;
+
+        Ok(claims)
+    }
+
+    fn encrypt_with_nonce<T: Serialize>(
+        &self,
+        claims: &Claims<T>,
+        nonce: [u8; NONCE_LEN],
+    ) -> Result<String, AuthError> {
+        let footer = footer(&self.kid);
+        let (encryption_key, stream_nonce, auth_key) = self.derive(&nonce);
+
+        let mut ciphertext = encode_claims(claims)?;
+        xchacha20(&encryption_key, &stream_nonce, &mut ciphertext);
+
+        let tag = blake2b_keyed(
+            MAC_LEN,
+            &auth_key,
+            &pae(&[
+                LOCAL_HEADER.as_bytes(),
+                &nonce,
+                &ciphertext,
+                &footer,
+                &self.implicit,
+            ]),
+        );
+
+        let mut body = nonce.to_vec();
+        body.extend_from_slice(&ciphertext);
+        body.extend_from_slice(&tag);
+
+        Ok(join(LOCAL_HEADER, &body, &footer))
+    }
+
+    fn derive(&self, nonce: &[u8]) -> ([u8; 32], [u8; 24], Vec<u8>) {
+        let prefixed = |label: &[u8]| [label, nonce].concat();
+
+        let derived = Zeroizing::new(blake2b_keyed(
+            56,
+            &*self.key,
+            &prefixed(b"paseto-encryption-key"),
+        ));
+        let auth_key = blake2b_keyed(32, &*self.key, &prefixed(b"paseto-auth-key-for-aead"));
+
+        (
+            derived[..32].try_into().unwrap(),
+            derived[32..].try_into().unwrap(),
+            auth_key,
+        )
+    }
+}
+
+/// Signs `v4.public` tokens with Ed25519
+///
+/// The claims of public tokens are readable by anyone; use them when other services verify tokens
+/// with only the public key.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::token::paseto::{PasetoSigner, PasetoVerifier};
+/// use lonewolf_auth_toolkit::token::{Claims, ValidationPolicy};
+/// use lonewolf_auth_toolkit::AuthError;
+/// use ring::{
+///     rand::SystemRandom,
+///     signature::{Ed25519KeyPair, KeyPair},
+/// };
+///
+/// let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+/// let public_key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap().public_key().as_ref().to_vec();
+///
+/// let signer = PasetoSigner::new(pkcs8.as_ref()).unwrap().implicit("SomeAccountName");
+/// let verifier = PasetoVerifier::new(&public_key)
+///     .unwrap()
+///     .policy(ValidationPolicy::new().audience("https://api.example.com"))
+///     .implicit("SomeAccountName");
+///
+/// let claims = Claims::new((), Duration::from_secs(900))
+///     .unwrap()
+///     .audience("https://api.example.com");
+/// let token = signer.sign(&claims).unwrap();
+///
+/// assert_eq!(verifier.verify::<()>(&token).unwrap(), claims);
+///
+/// let wrong_account = PasetoVerifier::new(&public_key).unwrap().implicit("OtherAccountName");
+/// assert!(matches!(wrong_account.verify::<()>(&token), Err(AuthError::Verification(_))));
+/// ```
+pub struct PasetoSigner {
+    key: Ed25519KeyPair,
+    kid: Option<String>,
+    implicit: Vec<u8>,
+}
+
+impl PasetoSigner {
+    /// An Ed25519 private key in PKCS#8 DER
+    pub fn new(pkcs8: &[u8]) -> Result<Self, AuthError> {
+        let key = Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8)
+            .map_err(|error| AuthError::InvalidInput(format!("Invalid Ed25519 key: {}", error)))?;
+
+        Ok(Self {
+            key,
+            kid: None,
+            implicit: Vec::new(),
+        })
+    }
+
+    /// Put `{"kid": ...}` in the footer of new tokens
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    /// Bind tokens to data that is authenticated but not stored, such as an account id
+    pub fn implicit(mut self, implicit: impl Into<Vec<u8>>) -> Self {
+        self.implicit = implicit.into();
+        self
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &Claims<T>) -> Result<String, AuthError> {
+        let footer = footer(&self.kid);
+        let message = encode_claims(claims)?;
+        let signature = self.key.sign(&pae(&[
+            PUBLIC_HEADER.as_bytes(),
+            &message,
+            &footer,
+            &self.implicit,
+        ]));
+
+        let mut body = message;
+        body.extend_from_slice(signature.as_ref());
+        metrics::token_issued("paseto_public");
+
+        Ok(join(PUBLIC_HEADER, &body, &footer))
+    }
+}
+
+/// Signs `v4.public` tokens with an Ed25519 key held by a `KeyProvider`, such as HashiCorp Vault
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::crypto::provider::MemoryKeyProvider;
+/// use lonewolf_auth_toolkit::token::paseto::{PasetoVerifier, RemotePasetoSigner};
+/// use lonewolf_auth_toolkit::token::Claims;
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let keys = MemoryKeyProvider::default();
+///     let public_key = keys.generate_ed25519("paseto-signing")?;
+///
+///     let signer = RemotePasetoSigner::new(keys, "paseto-signing").kid("2024-06");
+///     let claims = Claims::new((), Duration::from_secs(900))?.subject("SomeAccountName");
+///     let token = signer.sign(&claims).await?;
+///
+///     assert_eq!(PasetoVerifier::new(&public_key)?.verify::<()>(&token)?, claims);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RemotePasetoSigner<P> {
+    provider: P,
+    key_id: String,
+    kid: Option<String>,
+    implicit: Vec<u8>,
+}
+
+impl<P: KeyProvider> RemotePasetoSigner<P> {
+    /// Sign with the provider's Ed25519 key `key_id`
+    pub fn new(provider: P, key_id: &str) -> Self {
+        Self {
+            provider,
+            key_id: key_id.to_string(),
+            kid: None,
+            implicit: Vec::new(),
+        }
+    }
+
+    /// Put `{"kid": ...}` in the footer of new tokens
+    pub fn kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    /// Bind tokens to data that is authenticated but not stored, such as an account id
+    pub fn implicit(mut self, implicit: impl Into<Vec<u8>>) -> Self {
+        self.implicit = implicit.into();
+        self
+    }
+
+    pub async fn sign<T: Serialize>(&self, claims: &Claims<T>) -> Result<String, AuthError> {
+        let footer = footer(&self.kid);
+        let message = encode_claims(claims)?;
+        let signed = pae(&[PUBLIC_HEADER.as_bytes(), &message, &footer, &self.implicit]);
+        let signature = self
+            .provider
+            .sign(&self.key_id, SignatureAlgorithm::EdDsa, &signed)
+            .await?;
+
+        let mut body = message;
+        body.extend_from_slice(&signature);
+        metrics::token_issued("paseto_public");
+
+        Ok(join(PUBLIC_HEADER, &body, &footer))
+    }
+}
+
+/// Verifies `v4.public` tokens against an Ed25519 public key
+pub struct PasetoVerifier {
+    public_key: Vec<u8>,
+    policy: ValidationPolicy,
+    implicit: Vec<u8>,
+}
+
+impl PasetoVerifier {
+    /// A raw 32 byte Ed25519 public key
+    pub fn new(public_key: &[u8]) -> Result<Self, AuthError> {
+        if public_key.len() != 32 {
+            return Err(AuthError::InvalidInput(
+                "Ed25519 public keys must be 32 bytes".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            public_key: public_key.to_vec(),
+            policy: ValidationPolicy::default(),
+            implicit: Vec::new(),
+        })
+    }
+
+    verify_builder!();
+
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<Claims<T>, AuthError> {
+        let payload = token
+            .strip_prefix(PUBLIC_HEADER)
+            .ok_or_else(|| AuthError::Malformed("Token is not v4.public".to_string()))?;
+        let (body, footer) = split(payload)?;
+
+        if body.len() < SIGNATURE_LEN {
+            return Err(AuthError::Malformed("Token is too short".to_string()));
+        }
+
+        let (message, signature) = body.split_at(body.len() - SIGNATURE_LEN);
+
+        UnparsedPublicKey::new(&ED25519, &self.public_key)
+            .verify(
+                &pae(&[PUBLIC_HEADER.as_bytes(), message, &footer, &self.implicit]),
+                signature,
+            )
+            .map_err(|_| AuthError::Verification("Token signature is invalid".to_string()))?;
+
+        let claims = decode_claims(message)?;
+        self.policy.validate(&claims)?;
+
+        Ok(claims)
+    }
+}
+
+/// Pre-authentication encoding, which prefixes each piece with its length
+fn pae(pieces: &[&[u8]]) -> Vec<u8> {
+    let le64 = |n: usize| ((n as u64) & (u64::MAX >> 1)).to_le_bytes();
+
+    let mut output = le64(pieces.len()).to_vec();
+    for piece in pieces {
+        output.extend_from_slice(&le64(piece.len()));
+        output.extend_from_slice(piece);
+    }
+
+    output
+}
+
+fn footer(kid: &Option<String>) -> Vec<u8> {
+    kid.as_ref()
+        .map(|kid| json!({ "kid": kid }).to_string().into_bytes())
+        .unwrap_or_default()
+}
+
+fn join(header: &str, body: &[u8], footer: &[u8]) -> String {
+    let mut token = format!("{}{}", header, URL_SAFE_NO_PAD.encode(body));
+
+    if !footer.is_empty() {
+        token.push('.');
+        token.push_str(&URL_SAFE_NO_PAD.encode(footer));
+    }
+
+    token
+}
+
+/// Decode the body and footer following a header, or of a whole token
+fn split(payload: &str) -> Result<(Vec<u8>, Vec<u8>), AuthError> {
+    let payload = payload
+        .strip_prefix(LOCAL_HEADER)
+        .or_else(|| payload.strip_prefix(PUBLIC_HEADER))
+        .unwrap_or(payload);
+
+    match payload.split('.').collect::<Vec<_>>()[..] {
+        [body] => Ok((URL_SAFE_NO_PAD.decode(body)?, Vec::new())),
+        [body, footer] => Ok((
+            URL_SAFE_NO_PAD.decode(body)?,
+            URL_SAFE_NO_PAD.decode(footer)?,
+        )),
+        _ => Err(AuthError::Malformed(
+            "Token has too many segments".to_string(),
+        )),
+    }
+}
+
+/// Serialize claims with the RFC 3339 timestamps PASETO requires
+fn encode_claims<T: Serialize>(claims: &Claims<T>) -> Result<Vec<u8>, AuthError> {
+    let mut object = match serde_json::to_value(claims)? {
+        Json::Object(object) => object,
+        _ => {
+            return Err(AuthError::InvalidInput(
+                "Claims must serialize to a JSON object".to_string(),
+            ))
+        }
+    };
+
+    for name in TIME_CLAIMS {
+        if let Some(seconds) = object.get(name).and_then(Json::as_i64) {
+            let time = DateTime::from_timestamp(seconds, 0).ok_or_else(|| {
+                AuthError::InvalidInput(format!("Claim {} is out of range", name))
+            })?;
+            object.insert(
+                name.to_string(),
+                Json::String(time.to_rfc3339_opts(SecondsFormat::Secs, false)),
+            );
+        }
+    }
+
+    Ok(serde_json::to_vec(&object)?)
+}
+
+fn decode_claims<T: DeserializeOwned>(message: &[u8]) -> Result<Claims<T>, AuthError> {
+    let mut object: Map<String, Json> = serde_json::from_slice(message)?;
+
+    for name in TIME_CLAIMS {
+        if let Some(time) = object.get(name).and_then(Json::as_str) {
+            let seconds = DateTime::parse_from_rfc3339(time)
+                .map_err(|_| AuthError::Malformed(format!("Claim {} is not RFC 3339", name)))?
+                .timestamp();
+            object.insert(name.to_string(), json!(seconds));
+        }
+    }
+
+    Ok(serde_json::from_value(Json::Object(object))?)
+}
@@ -0,0 +1,290 @@
+use std::sync::Arc;
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+
+use crate::{
+    postgres::{PostgresClient, Row},
+    AuthError,
+};
+
+use super::{
+    now,
+    opaque::{OpaqueToken, OpaqueTokenStore},
+    refresh::{RefreshRecord, RefreshTokenStore},
+};
+
+/// An `OpaqueTokenStore` keeping tokens in the `auth_opaque_tokens` table created by
+/// `PostgresClient::migrate`
+///
+/// `revoke` is a single `DELETE`, so a single use token can only be consumed once however many
+/// instances race for it. Call `delete_expired` periodically to remove tokens nobody used.
+///
+/// ### Example
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::postgres::{PostgresClient, PostgresConfig};
+/// use lonewolf_auth_toolkit::token::{opaque::OpaqueTokens, postgres::PostgresTokenStore};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = PostgresConfig::new("postgres", "myapp").password("SomePassword");
+///     let postgres = Arc::new(PostgresClient::connect("127.0.0.1:5432", &config).await?);
+///     postgres.migrate().await?;
+///
+///     let resets = OpaqueTokens::new(PostgresTokenStore::new(postgres), "password-reset");
+///
+///     let token = resets.issue("SomeAccountName").await?;
+///     assert!(resets.consume(&token).await?.is_some());
+///     assert!(resets.consume(&token).await?.is_none());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct PostgresTokenStore<S = TcpStream> {
+    client: Arc<PostgresClient<S>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> PostgresTokenStore<S> {
+    pub fn new(client: Arc<PostgresClient<S>>) -> Self {
+        Self { client }
+    }
+
+    pub fn client(&self) -> &PostgresClient<S> {
+        &self.client
+    }
+
+    /// Delete every expired token, returning how many were deleted
+    pub async fn delete_expired(&self) -> Result<u64, AuthError> {
+        self.client
+            .execute(
+                "DELETE FROM auth_opaque_tokens WHERE expires_at <= $1::bigint",
+                &[Some(&now()?.to_string())],
+            )
+            .await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> OpaqueTokenStore for PostgresTokenStore<S> {
+    async fn insert(&self, token: OpaqueToken) -> Result<(), AuthError> {
+        let created_at = token.created_at.to_string();
+        let expires_at = token.expires_at.map(|expires_at| expires_at.to_string());
+
+        self.client
+            .execute(
+                "INSERT INTO auth_opaque_tokens (hash, purpose, subject, created_at, expires_at) \
+                 VALUES ($1, $2, $3, $4::bigint, $5::bigint)",
+                &[
+                    Some(&token.hash),
+                    Some(&token.purpose),
+                    Some(&token.subject),
+                    Some(&created_at),
+                    expires_at.as_deref(),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Option<OpaqueToken>, AuthError> {
+        self.client
+            .query(
+                "SELECT hash, purpose, subject, created_at, expires_at FROM auth_opaque_tokens \
+                 WHERE hash = $1",
+                &[Some(hash)],
+            )
+            .await?
+            .first()
+            .map(token)
+            .transpose()
+    }
+
+    async fn revoke(&self, hash: &str) -> Result<bool, AuthError> {
+        let deleted = self
+            .client
+            .execute(
+                "DELETE FROM auth_opaque_tokens WHERE hash = $1",
+                &[Some(hash)],
+            )
+            .await?;
+
+        Ok(deleted == 1)
+    }
+
+    async fn revoke_all(&self, purpose: &str, subject: &str) -> Result<(), AuthError> {
+        self.client
+            .execute(
+                "DELETE FROM auth_opaque_tokens WHERE purpose = $1 AND subject = $2",
+                &[Some(purpose), Some(subject)],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn token(row: &Row) -> Result<OpaqueToken, AuthError> {
+    let expires_at = match row.get(4) {
+        Some(_) => Some(u64::try_from(row.int(4)?)?),
+        None => None,
+    };
+
+    Ok(OpaqueToken {
+        hash: row.text(0)?.to_string(),
+        purpose: row.text(1)?.to_string(),
+        subject: row.text(2)?.to_string(),
+        created_at: u64::try_from(row.int(3)?)?,
+        expires_at,
+    })
+}
+
+/// A `RefreshTokenStore` keeping tokens in the `auth_refresh_tokens` table created by
+/// `PostgresClient::migrate`
+///
+/// `rotate` marks the old token and inserts its successor in a single statement. `revoke_family`
+/// first marks the whole family rotated, which waits for any refresh holding a lock on one of its
+/// tokens, and only then deletes it, so a successor committed by that refresh is deleted too. Call
+/// `delete_expired` periodically to remove families nobody refreshed.
+///
+/// ### Example
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::postgres::{PostgresClient, PostgresConfig};
+/// use lonewolf_auth_toolkit::token::{
+///     postgres::PostgresRefreshTokenStore,
+///     refresh::{Refresh, RefreshTokens},
+/// };
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let config = PostgresConfig::new("postgres", "myapp").password("SomePassword");
+///     let postgres = Arc::new(PostgresClient::connect("127.0.0.1:5432", &config).await?);
+///     postgres.migrate().await?;
+///
+///     let tokens = RefreshTokens::new(PostgresRefreshTokenStore::new(postgres));
+///
+///     let first = tokens.issue("SomeAccountName").await?;
+///     assert!(matches!(tokens.rotate(&first).await?, Refresh::Rotated { .. }));
+///     assert!(matches!(tokens.rotate(&first).await?, Refresh::Reused { .. }));
+///
+///     Ok(())
+/// }
+/// ```
+pub struct PostgresRefreshTokenStore<S = TcpStream> {
+    client: Arc<PostgresClient<S>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> PostgresRefreshTokenStore<S> {
+    pub fn new(client: Arc<PostgresClient<S>>) -> Self {
+        Self { client }
+    }
+
+    pub fn client(&self) -> &PostgresClient<S> {
+        &self.client
+    }
+
+    /// Delete every expired token, returning how many were deleted
+    pub async fn delete_expired(&self) -> Result<u64, AuthError> {
+        self.client
+            .execute(
+                "DELETE FROM auth_refresh_tokens WHERE expires_at <= $1::bigint",
+                &[Some(&now()?.to_string())],
+            )
+            .await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> RefreshTokenStore for PostgresRefreshTokenStore<S> {
+    async fn insert(&self, record: RefreshRecord) -> Result<(), AuthError> {
+        let expires_at = record.expires_at.to_string();
+
+        self.client
+            .execute(
+                "INSERT INTO auth_refresh_tokens (hash, family, parent, account, expires_at, \
+                 rotated) VALUES ($1, $2, $3, $4, $5::bigint, $6::boolean)",
+                &[
+                    Some(&record.hash),
+                    Some(&record.family),
+                    record.parent.as_deref(),
+                    Some(&record.account),
+                    Some(&expires_at),
+                    Some(if record.rotated { "true" } else { "false" }),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Option<RefreshRecord>, AuthError> {
+        self.client
+            .query(
+                "SELECT hash, family, parent, account, expires_at, rotated \
+                 FROM auth_refresh_tokens WHERE hash = $1",
+                &[Some(hash)],
+            )
+            .await?
+            .first()
+            .map(refresh_record)
+            .transpose()
+    }
+
+    async fn rotate(&self, old_hash: &str, record: RefreshRecord) -> Result<bool, AuthError> {
+        let expires_at = record.expires_at.to_string();
+
+        let inserted = self
+            .client
+            .execute(
+                "WITH old AS (UPDATE auth_refresh_tokens SET rotated = TRUE \
+                 WHERE hash = $1 AND NOT rotated RETURNING hash) \
+                 INSERT INTO auth_refresh_tokens (hash, family, parent, account, expires_at) \
+                 SELECT $2, $3, $4, $5, $6::bigint FROM old",
+                &[
+                    Some(old_hash),
+                    Some(&record.hash),
+                    Some(&record.family),
+                    record.parent.as_deref(),
+                    Some(&record.account),
+                    Some(&expires_at),
+                ],
+            )
+            .await?;
+
+        Ok(inserted == 1)
+    }
+
+    async fn revoke_family(&self, family: &str) -> Result<(), AuthError> {
+        // The UPDATE blocks on a refresh in flight, so the DELETE's later snapshot sees its
+        // successor; a single DELETE would miss a successor committed while it waited
+        self.client
+            .execute(
+                "UPDATE auth_refresh_tokens SET rotated = TRUE WHERE family = $1",
+                &[Some(family)],
+            )
+            .await?;
+        self.client
+            .execute(
+                "DELETE FROM auth_refresh_tokens WHERE family = $1",
+                &[Some(family)],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn refresh_record(row: &Row) -> Result<RefreshRecord, AuthError> {
+    Ok(RefreshRecord {
+        hash: row.text(0)?.to_string(),
+        family: row.text(1)?.to_string(),
+        parent: row.get(2).map(str::to_string),
+        account: row.text(3)?.to_string(),
+        expires_at: u64::try_from(row.int(4)?)?,
+        rotated: row.bool(5)?,
+    })
+}
@@ -0,0 +1,147 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{thread_rng, RngCore};
+
+/// Random bytes in a token unless configured otherwise, 256 bits
+pub const DEFAULT_BYTES: usize = 32;
+
+const BASE32: base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+/// The Bitcoin alphabet, without `0`, `O`, `I` and `l`
+const BASE58: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+const DIGITS: &[u8] = b"0123456789";
+
+/// How a token's random bytes are written out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Alphabet {
+    /// Lower case hexadecimal
+    Hex,
+    /// Upper case RFC 4648 Base32 without padding, as authenticator apps expect
+    Base32,
+    /// Bitcoin Base58, easy to read aloud and select with a double click
+    Base58,
+    /// URL safe Base64 without padding, the shortest
+    #[default]
+    Base64Url,
+    /// Decimal digits, for codes typed on a keypad
+    Numeric,
+}
+
+/// Generates random tokens of a chosen shape from the operating system's CSPRNG
+///
+/// Every token from one generator has the same length: the byte length sets the entropy and the
+/// alphabet only changes how it is written, so `bytes(16)` is 128 bits whichever alphabet is
+/// used. Keep at least 16 bytes for anything used as a bearer credential.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::token::random::{Alphabet, RandomToken};
+///
+/// let api_key = RandomToken::new().alphabet(Alphabet::Base58).prefix("lw_live_");
+/// let token = api_key.generate();
+/// assert!(token.starts_with("lw_live_"));
+/// assert_eq!(token.len(), api_key.len());
+///
+/// let invite = RandomToken::new().bytes(5).alphabet(Alphabet::Numeric).generate();
+/// assert_eq!(invite.len(), 13);
+/// assert!(invite.chars().all(|c| c.is_ascii_digit()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RandomToken {
+    bytes: usize,
+    alphabet: Alphabet,
+    prefix: String,
+}
+
+impl Default for RandomToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RandomToken {
+    pub fn new() -> Self {
+        Self {
+            bytes: DEFAULT_BYTES,
+            alphabet: Alphabet::default(),
+            prefix: String::new(),
+        }
+    }
+
+    /// How many random bytes each token carries
+    pub fn bytes(mut self, bytes: usize) -> Self {
+        self.bytes = bytes;
+        self
+    }
+
+    pub fn alphabet(mut self, alphabet: Alphabet) -> Self {
+        self.alphabet = alphabet;
+        self
+    }
+
+    /// Text put in front of every token, e.g. `lw_live_` so leaked tokens are easy to spot
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// The length of every generated token, prefix included
+    pub fn len(&self) -> usize {
+        let bits = self.bytes * 8;
+        let encoded = match self.alphabet {
+            Alphabet::Hex => self.bytes * 2,
+            Alphabet::Base32 => bits.div_ceil(5),
+            Alphabet::Base64Url => bits.div_ceil(6),
+            Alphabet::Base58 => width(self.bytes, BASE58.len()),
+            Alphabet::Numeric => width(self.bytes, DIGITS.len()),
+        };
+
+        self.prefix.len() + encoded
+    }
+
+    /// Whether tokens would be empty, i.e. zero bytes and no prefix
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn generate(&self) -> String {
+        let mut bytes = vec![0u8; self.bytes];
+        thread_rng().fill_bytes(&mut bytes);
+
+        let encoded = match self.alphabet {
+            Alphabet::Hex => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            Alphabet::Base32 => base32::encode(BASE32, &bytes),
+            Alphabet::Base64Url => URL_SAFE_NO_PAD.encode(&bytes),
+            Alphabet::Base58 => encode_digits(bytes, BASE58),
+            Alphabet::Numeric => encode_digits(bytes, DIGITS),
+        };
+
+        format!("{}{}", self.prefix, encoded)
+    }
+}
+
+/// Digits needed to write any `bytes` long number in the base
+fn width(bytes: usize, base: usize) -> usize {
+    ((bytes * 8) as f64 / (base as f64).log2()).ceil() as usize
+}
+
+/// Write the big endian number as fixed width digits, keeping leading zeros so every token of
+/// the same byte length has the same length
+fn encode_digits(mut number: Vec<u8>, digits: &[u8]) -> String {
+    let base = digits.len() as u32;
+    let width = width(number.len(), digits.len());
+    let mut encoded = Vec::with_capacity(width);
+
+    for _ in 0..width {
+        let mut remainder = 0u32;
+        for byte in number.iter_mut() {
+            let value = (remainder << 8) | u32::from(*byte);
+            *byte = (value / base) as u8;
+            remainder = value % base;
+        }
+
+        encoded.push(char::from(digits[remainder as usize]));
+    }
+
+    encoded.iter().rev().collect()
+}
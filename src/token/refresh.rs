@@ -0,0 +1,384 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditSink, NoAudit},
+    metrics, AuthError,
+};
+
+use super::{generate_token, hash_token, now};
+
+/// How long a refresh token stays usable if it is not rotated
+pub const DEFAULT_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A stored refresh token; the token itself is only kept as a SHA-256 hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefreshRecord {
+    pub hash: String,
+    /// Shared by every token descended from the same sign in
+    pub family: String,
+    /// Hash of the token this one replaced; `None` for the first token of a family
+    pub parent: Option<String>,
+    pub account: String,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+    /// Set once the token has been exchanged for its successor
+    pub rotated: bool,
+}
+
+/// Persists refresh tokens
+///
+/// `rotate` must mark the old token rotated and insert its successor in a single atomic operation
+/// (e.g. one transaction that inserts only if `UPDATE ... WHERE rotated = false` changed a row) so
+/// two concurrent refreshes cannot both succeed, and a `revoke_family` racing with a refresh
+/// cannot leave the new token behind.
+pub trait RefreshTokenStore {
+    fn insert(&self, record: RefreshRecord) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn get(
+        &self,
+        hash: &str,
+    ) -> impl Future<Output = Result<Option<RefreshRecord>, AuthError>> + Send;
+
+    /// Mark `old_hash` rotated and insert `record`, its successor
+    ///
+    /// Returns `false` without inserting anything if the old token was already rotated or no
+    /// longer exists, e.g. because its family was revoked.
+    fn rotate(
+        &self,
+        old_hash: &str,
+        record: RefreshRecord,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Delete every token in the family
+    fn revoke_family(&self, family: &str) -> impl Future<Output = Result<(), AuthError>> + Send;
+}
+
+/// The outcome of `RefreshTokens::rotate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Refresh {
+    /// Store the new token in place of the old one
+    Rotated { account: String, token: String },
+    /// An already rotated token was presented, so it has leaked; the whole family is revoked
+    Reused { account: String },
+    /// Unknown, revoked or expired
+    Rejected,
+}
+
+/// Issues opaque refresh tokens that are replaced on every use
+///
+/// Presenting a token that was already exchanged means two parties hold it, so every token from
+/// the same sign in is revoked, `AuditAction::RefreshTokenReused` is recorded to the audit sink
+/// and the legitimate user has to sign in again. A client that retries a refresh after losing the
+/// response is treated the same way. Each token records its parent, so `lineage` can show how a
+/// family grew when investigating a reuse.
+///
+/// ### Example
+/// ```rust
+/// use std::{collections::HashMap, sync::Arc, sync::Mutex};
+///
+/// use lonewolf_auth_toolkit::audit::{AuditAction, MemoryAuditSink};
+/// use lonewolf_auth_toolkit::token::refresh::{Refresh, RefreshRecord, RefreshTokenStore, RefreshTokens};
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, RefreshRecord>>);
+///
+/// impl RefreshTokenStore for MemoryStore {
+///     async fn insert(&self, record: RefreshRecord) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(record.hash.clone(), record);
+///         Ok(())
+///     }
+///
+///     async fn get(&self, hash: &str) -> Result<Option<RefreshRecord>, AuthError> {
+///         Ok(self.0.lock().unwrap().get(hash).cloned())
+///     }
+///
+///     async fn rotate(&self, old_hash: &str, record: RefreshRecord) -> Result<bool, AuthError> {
+///         let mut records = self.0.lock().unwrap();
+///         match records.get_mut(old_hash) {
+///             Some(old) if !old.rotated => old.rotated = true,
+///             _ => return Ok(false),
+///         }
+///         records.insert(record.hash.clone(), record);
+///         Ok(true)
+///     }
+///
+///     async fn revoke_family(&self, family: &str) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().retain(|_, record| record.family != family);
+///         Ok(())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let audit = Arc::new(MemoryAuditSink::default());
+///     let tokens = RefreshTokens::new(MemoryStore::default()).audit(audit.clone());
+///     let first = tokens.issue("SomeAccountName").await?;
+///
+///     let second = match tokens.rotate(&first).await? {
+///         Refresh::Rotated { token, .. } => token,
+///         other => panic!("expected a rotation, got {:?}", other),
+///     };
+///
+///     // The first token was stolen and replayed
+///     assert_eq!(tokens.rotate(&first).await?, Refresh::Reused { account: "SomeAccountName".to_string() });
+///
+///     // Which revoked the legitimate user's token too
+///     assert_eq!(tokens.rotate(&second).await?, Refresh::Rejected);
+///     assert_eq!(audit.events()?[0].action, AuditAction::RefreshTokenReused);
+///
+///     let third = tokens.issue("SomeAccountName").await?;
+///     let fourth = match tokens.rotate(&third).await? {
+///         Refresh::Rotated { token, .. } => token,
+///         other => panic!("expected a rotation, got {:?}", other),
+///     };
+///     let lineage = tokens.lineage(&fourth).await?;
+///     assert_eq!(lineage.len(), 2);
+///     assert_eq!(lineage[0].parent.as_ref(), Some(&lineage[1].hash));
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RefreshTokens<S, A = NoAudit> {
+    store: S,
+    ttl: Duration,
+    audit: A,
+}
+
+impl<S: RefreshTokenStore> RefreshTokens<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            ttl: DEFAULT_TTL,
+            audit: NoAudit,
+        }
+    }
+}
+
+impl<S: RefreshTokenStore, A: AuditSink> RefreshTokens<S, A> {
+    /// Record refresh token reuse to `audit`
+    pub fn audit<B: AuditSink>(self, audit: B) -> RefreshTokens<S, B> {
+        RefreshTokens {
+            store: self.store,
+            ttl: self.ttl,
+            audit,
+        }
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Start a new token family, e.g. after the user signs in
+    pub async fn issue(&self, account: &str) -> Result<String, AuthError> {
+        self.issue_in(account, &generate_token(), None).await
+    }
+
+    /// Exchange a token for its successor
+    pub async fn rotate(&self, token: &str) -> Result<Refresh, AuthError> {
+        let hash = hash_token(token);
+
+        let record = match self.store.get(&hash).await? {
+            Some(record) => record,
+            None => return Ok(Refresh::Rejected),
+        };
+
+        if record.expires_at <= now()? {
+            return Ok(Refresh::Rejected);
+        }
+
+        let successor = generate_token();
+        let child = self.record(
+            &successor,
+            &record.account,
+            &record.family,
+            Some(hash.clone()),
+        )?;
+
+        if record.rotated || !self.store.rotate(&hash, child).await? {
+            self.store.revoke_family(&record.family).await?;
+
+            let event = AuditEvent::new(AuditAction::RefreshTokenReused)?
+                .account(record.account.as_str())
+                .detail(format!("family {} revoked", record.family));
+            self.audit.record(event).await?;
+
+            return Ok(Refresh::Reused {
+                account: record.account,
+            });
+        }
+
+        metrics::token_issued("refresh");
+
+        Ok(Refresh::Rotated {
+            account: record.account,
+            token: successor,
+        })
+    }
+
+    /// Revoke the token's whole family, e.g. on sign out; unknown tokens are ignored
+    pub async fn revoke(&self, token: &str) -> Result<(), AuthError> {
+        if let Some(record) = self.store.get(&hash_token(token)).await? {
+            self.store.revoke_family(&record.family).await?;
+        }
+
+        Ok(())
+    }
+
+    /// The token's record followed by those of the tokens it descends from, newest first
+    ///
+    /// Stops at the first token of the family or at an ancestor the store no longer has; empty
+    /// for an unknown token.
+    pub async fn lineage(&self, token: &str) -> Result<Vec<RefreshRecord>, AuthError> {
+        let mut lineage: Vec<RefreshRecord> = Vec::new();
+
+        let mut next = Some(hash_token(token));
+        while let Some(hash) = next {
+            let record = match self.store.get(&hash).await? {
+                Some(record) => record,
+                None => break,
+            };
+
+            // A parent is always an older token of the same family, so anything else is corrupt
+            if lineage
+                .last()
+                .is_some_and(|child| child.family != record.family)
+                || lineage.iter().any(|seen| seen.hash == record.hash)
+            {
+                return Err(AuthError::backend("Refresh token lineage is inconsistent"));
+            }
+
+            next = record.parent.clone();
+            lineage.push(record);
+        }
+
+        Ok(lineage)
+    }
+
+    async fn issue_in(
+        &self,
+        account: &str,
+        family: &str,
+        parent: Option<String>,
+    ) -> Result<String, AuthError> {
+        let token = generate_token();
+
+        self.store
+            .insert(self.record(&token, account, family, parent)?)
+            .await?;
+        metrics::token_issued("refresh");
+
+        Ok(token)
+    }
+
+    fn record(
+        &self,
+        token: &str,
+        account: &str,
+        family: &str,
+        parent: Option<String>,
+    ) -> Result<RefreshRecord, AuthError> {
+        Ok(RefreshRecord {
+            hash: hash_token(token),
+            family: family.to_string(),
+            parent,
+            account: account.to_string(),
+            expires_at: now()? + self.ttl.as_secs(),
+            rotated: false,
+        })
+    }
+}
+
+/// Keeps refresh tokens in process memory, dropping expired ones as new ones are inserted
+///
+/// `rotate` checks and updates the old token under the same lock that `revoke_family` takes, so a
+/// refresh racing with a revocation either finishes first and is revoked with the family, or
+/// finds the family gone and inserts nothing.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::token::refresh::{
+///     MemoryRefreshTokenStore, RefreshRecord, RefreshTokenStore,
+/// };
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let store = MemoryRefreshTokenStore::default();
+///     let parent = RefreshRecord {
+///         hash: "parent".to_string(),
+///         family: "family".to_string(),
+///         parent: None,
+///         account: "SomeAccountName".to_string(),
+///         expires_at: u64::MAX,
+///         rotated: false,
+///     };
+///     let child = RefreshRecord {
+///         hash: "child".to_string(),
+///         parent: Some("parent".to_string()),
+///         ..parent.clone()
+///     };
+///     store.insert(parent).await?;
+///
+///     // A reuse detected elsewhere revokes the family while this refresh is in flight
+///     store.revoke_family("family").await?;
+///     assert!(!store.rotate("parent", child).await?);
+///     assert!(store.get("child").await?.is_none());
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryRefreshTokenStore {
+    records: Mutex<HashMap<String, RefreshRecord>>,
+}
+
+impl MemoryRefreshTokenStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, RefreshRecord>>, AuthError> {
+        self.records
+            .lock()
+            .map_err(|_| AuthError::backend("Refresh token store lock poisoned"))
+    }
+}
+
+impl RefreshTokenStore for MemoryRefreshTokenStore {
+    async fn insert(&self, record: RefreshRecord) -> Result<(), AuthError> {
+        let now = now()?;
+        let mut records = self.lock()?;
+
+        records.retain(|_, record| record.expires_at > now);
+        records.insert(record.hash.clone(), record);
+
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Option<RefreshRecord>, AuthError> {
+        Ok(self.lock()?.get(hash).cloned())
+    }
+
+    async fn rotate(&self, old_hash: &str, record: RefreshRecord) -> Result<bool, AuthError> {
+        let now = now()?;
+        let mut records = self.lock()?;
+
+        match records.get_mut(old_hash) {
+            Some(old) if !old.rotated => old.rotated = true,
+            _ => return Ok(false),
+        }
+
+        records.retain(|_, record| record.expires_at > now);
+        records.insert(record.hash.clone(), record);
+
+        Ok(true)
+    }
+
+    async fn revoke_family(&self, family: &str) -> Result<(), AuthError> {
+        self.lock()?.retain(|_, record| record.family != family);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditSink, NoAudit},
+    crypto::ct_eq,
+    metrics, AuthError,
+};
+
+use super::{generate_token, hash_token, now};
+
+/// How long a remembered sign in lasts without being used
+pub const DEFAULT_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// One remembered sign in; both halves of the cookie are only kept as SHA-256 hashes
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PersistentLogin {
+    /// Hash of the series, which stays the same for the life of the remembered sign in
+    pub series_hash: String,
+    /// Hash of the current token, which changes every time the cookie is used
+    pub token_hash: String,
+    pub account: String,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+    /// Unix timestamp in seconds
+    pub last_used_at: u64,
+    /// Unix timestamp in seconds
+    pub expires_at: u64,
+}
+
+/// Persists remembered sign ins by series
+///
+/// `replace_token` must swap the token only if it is still `old_hash`, in a single atomic
+/// operation (e.g. `UPDATE ... WHERE token_hash = $2`), so two requests carrying the same cookie
+/// cannot both rotate it.
+pub trait PersistentLoginStore {
+    fn insert(&self, login: PersistentLogin) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn get(
+        &self,
+        series_hash: &str,
+    ) -> impl Future<Output = Result<Option<PersistentLogin>, AuthError>> + Send;
+
+    /// Returns `false` if the series is gone or its token is no longer `old_hash`
+    fn replace_token(
+        &self,
+        series_hash: &str,
+        old_hash: &str,
+        login: PersistentLogin,
+    ) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Returns `false` if there was no such series
+    fn delete(&self, series_hash: &str) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Delete every series of the account, returning how many were deleted
+    fn delete_all(&self, account: &str) -> impl Future<Output = Result<usize, AuthError>> + Send;
+}
+
+/// The outcome of `RememberMe::authenticate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Remembered {
+    /// Sign the account in and set the new cookie value in place of the old one
+    SignedIn { account: String, cookie: String },
+    /// The series is known but the token is stale, so the cookie was copied and used elsewhere;
+    /// every remembered sign in of the account is revoked
+    Stolen { account: String },
+    /// Unknown, revoked, expired or malformed
+    Rejected,
+}
+
+/// "Remember me" cookies using the series and token scheme
+///
+/// `issue` returns a cookie value `<series>.<token>`. Each time it is presented, `authenticate`
+/// keeps the series and replaces the token, so the browser always holds the only valid token. A
+/// stale token for a known series means the cookie was stolen and replayed, or the victim's copy
+/// is now stale: every remembered sign in of the account is revoked,
+/// `AuditAction::PersistentLoginStolen` is recorded and the user has to sign in again. Two
+/// requests racing with the same cookie are not treated as theft; the one that loses is rejected.
+///
+/// A remembered sign in proves less than a password, so ask for the password again, or MFA,
+/// before sensitive actions.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::audit::{AuditAction, MemoryAuditSink};
+/// use lonewolf_auth_toolkit::token::remember::{
+///     MemoryPersistentLoginStore, RememberMe, Remembered,
+/// };
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let audit = Arc::new(MemoryAuditSink::default());
+///     let remember = RememberMe::new(MemoryPersistentLoginStore::default()).audit(audit.clone());
+///
+///     // The user ticked "remember me" when signing in
+///     let first = remember.issue("SomeAccountName").await?;
+///
+///     // Their session expired; the cookie signs them back in and is replaced
+///     let second = match remember.authenticate(&first).await? {
+///         Remembered::SignedIn { account, cookie } => {
+///             assert_eq!(account, "SomeAccountName");
+///             cookie
+///         }
+///         other => panic!("expected a sign in, got {:?}", other),
+///     };
+///
+///     // A copy of the first cookie is replayed by whoever stole it
+///     let stolen = remember.authenticate(&first).await?;
+///     assert_eq!(stolen, Remembered::Stolen { account: "SomeAccountName".to_string() });
+///
+///     // Which revoked the legitimate cookie too
+///     assert_eq!(remember.authenticate(&second).await?, Remembered::Rejected);
+///     assert_eq!(audit.events()?[0].action, AuditAction::PersistentLoginStolen);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RememberMe<S, A = NoAudit> {
+    store: S,
+    ttl: Duration,
+    audit: A,
+}
+
+impl<S: PersistentLoginStore> RememberMe<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            ttl: DEFAULT_TTL,
+            audit: NoAudit,
+        }
+    }
+}
+
+impl<S: PersistentLoginStore, A: AuditSink> RememberMe<S, A> {
+    /// Record detected cookie theft to `audit`
+    pub fn audit<B: AuditSink>(self, audit: B) -> RememberMe<S, B> {
+        RememberMe {
+            store: self.store,
+            ttl: self.ttl,
+            audit,
+        }
+    }
+
+    /// How long a remembered sign in lasts after it was last used; also the cookie's `Max-Age`
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Remember a sign in that just succeeded, returning the cookie value
+    pub async fn issue(&self, account: &str) -> Result<String, AuthError> {
+        let series = generate_token();
+        let token = generate_token();
+        let now = now()?;
+
+        self.store
+            .insert(PersistentLogin {
+                series_hash: hash_token(&series),
+                token_hash: hash_token(&token),
+                account: account.to_string(),
+                created_at: now,
+                last_used_at: now,
+                expires_at: now + self.ttl.as_secs(),
+            })
+            .await?;
+        metrics::token_issued("remember_me");
+
+        Ok(format!("{}.{}", series, token))
+    }
+
+    /// Sign in with a cookie value from `issue`, rotating its token
+    pub async fn authenticate(&self, cookie: &str) -> Result<Remembered, AuthError> {
+        let (series, token) = match cookie.trim().split_once('.') {
+            Some((series, token)) if !series.is_empty() && !token.is_empty() => (series, token),
+            _ => return Ok(Remembered::Rejected),
+        };
+        let series_hash = hash_token(series);
+
+        let login = match self.store.get(&series_hash).await? {
+            Some(login) => login,
+            None => return Ok(Remembered::Rejected),
+        };
+
+        let now = now()?;
+        if login.expires_at <= now {
+            self.store.delete(&series_hash).await?;
+
+            return Ok(Remembered::Rejected);
+        }
+
+        if !ct_eq(hash_token(token).as_bytes(), login.token_hash.as_bytes()) {
+            let revoked = self.store.delete_all(&login.account).await?;
+
+            let event = AuditEvent::new(AuditAction::PersistentLoginStolen)?
+                .account(login.account.as_str())
+                .detail(format!("{} remembered sign ins revoked", revoked));
+            self.audit.record(event).await?;
+
+            return Ok(Remembered::Stolen {
+                account: login.account,
+            });
+        }
+
+        let next = generate_token();
+        let old_hash = login.token_hash.clone();
+        let rotated = PersistentLogin {
+            token_hash: hash_token(&next),
+            last_used_at: now,
+            expires_at: now + self.ttl.as_secs(),
+            ..login
+        };
+        let account = rotated.account.clone();
+        if !self
+            .store
+            .replace_token(&series_hash, &old_hash, rotated)
+            .await?
+        {
+            return Ok(Remembered::Rejected);
+        }
+        metrics::token_issued("remember_me");
+
+        Ok(Remembered::SignedIn {
+            account,
+            cookie: format!("{}.{}", series, next),
+        })
+    }
+
+    /// Forget the cookie's sign in, e.g. on sign out; unknown cookies are ignored
+    pub async fn revoke(&self, cookie: &str) -> Result<(), AuthError> {
+        if let Some((series, _)) = cookie.trim().split_once('.') {
+            self.store.delete(&hash_token(series)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Forget every remembered sign in of the account, e.g. after a password change
+    pub async fn revoke_all(&self, account: &str) -> Result<usize, AuthError> {
+        self.store.delete_all(account).await
+    }
+}
+
+/// Keeps remembered sign ins in process memory, dropping expired ones as new ones are inserted
+#[derive(Debug, Default)]
+pub struct MemoryPersistentLoginStore {
+    logins: Mutex<HashMap<String, PersistentLogin>>,
+}
+
+impl MemoryPersistentLoginStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, PersistentLogin>>, AuthError> {
+        self.logins
+            .lock()
+            .map_err(|_| AuthError::backend("Persistent login store lock poisoned"))
+    }
+}
+
+impl PersistentLoginStore for MemoryPersistentLoginStore {
+    async fn insert(&self, login: PersistentLogin) -> Result<(), AuthError> {
+        let now = now()?;
+        let mut logins = self.lock()?;
+
+        logins.retain(|_, login| login.expires_at > now);
+        logins.insert(login.series_hash.clone(), login);
+
+        Ok(())
+    }
+
+    async fn get(&self, series_hash: &str) -> Result<Option<PersistentLogin>, AuthError> {
+        Ok(self.lock()?.get(series_hash).cloned())
+    }
+
+    async fn replace_token(
+        &self,
+        series_hash: &str,
+        old_hash: &str,
+        login: PersistentLogin,
+    ) -> Result<bool, AuthError> {
+        match self.lock()?.get_mut(series_hash) {
+            Some(current) if ct_eq(&current.token_hash, old_hash) => {
+                *current = login;
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete(&self, series_hash: &str) -> Result<bool, AuthError> {
+        Ok(self.lock()?.remove(series_hash).is_some())
+    }
+
+    async fn delete_all(&self, account: &str) -> Result<usize, AuthError> {
+        let mut logins = self.lock()?;
+        let before = logins.len();
+        logins.retain(|_, login| login.account != account);
+
+        Ok(before - logins.len())
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::AuthError;
+
+use super::{hash_token, now, Claims, DEFAULT_LEEWAY};
+
+/// Persists revoked token ids and hashes until their tokens would have expired anyway
+///
+/// Backends with native expiry (e.g. Redis `SET ... EXAT`) can drop entries themselves and make
+/// `purge` a no-op.
+pub trait RevocationStore {
+    /// Add an entry that may be forgotten after the Unix timestamp `expires_at`
+    fn insert(
+        &self,
+        key: &str,
+        expires_at: u64,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn contains(&self, key: &str) -> impl Future<Output = Result<bool, AuthError>> + Send;
+
+    /// Remove entries that expired before `now`, returning how many were removed
+    fn purge(&self, now: u64) -> impl Future<Output = Result<usize, AuthError>> + Send;
+}
+
+/// A denylist for rejecting tokens before their natural expiry, e.g. after sign out
+///
+/// Tokens with a `jti` are revoked by id; any other token can be revoked by its hash. Entries are
+/// kept until the token's `exp` plus the verifier's leeway, after which the token is rejected
+/// anyway and the entry can be purged.
+///
+/// ### Example
+/// ```rust
+/// use std::{collections::HashMap, sync::Mutex, time::Duration};
+///
+/// use lonewolf_auth_toolkit::token::revocation::{RevocationList, RevocationStore};
+/// use lonewolf_auth_toolkit::token::Claims;
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, u64>>);
+///
+/// impl RevocationStore for MemoryStore {
+///     async fn insert(&self, key: &str, expires_at: u64) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(key.to_string(), expires_at);
+///         Ok(())
+///     }
+///
+///     async fn contains(&self, key: &str) -> Result<bool, AuthError> {
+///         Ok(self.0.lock().unwrap().contains_key(key))
+///     }
+///
+///     async fn purge(&self, now: u64) -> Result<usize, AuthError> {
+///         let mut entries = self.0.lock().unwrap();
+///         let before = entries.len();
+///         entries.retain(|_, expires_at| *expires_at >= now);
+///         Ok(before - entries.len())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let denylist = RevocationList::new(MemoryStore::default());
+///     let claims = Claims::new((), Duration::from_secs(900))?.id("token-1");
+///
+///     assert!(!denylist.is_revoked(&claims).await?);
+///     denylist.revoke(&claims).await?;
+///     assert!(denylist.is_revoked(&claims).await?);
+///
+///     // Nothing has expired yet
+///     assert_eq!(denylist.purge().await?, 0);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct RevocationList<S> {
+    store: S,
+    leeway: Duration,
+}
+
+impl<S: RevocationStore> RevocationList<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            leeway: DEFAULT_LEEWAY,
+        }
+    }
+
+    /// Keep entries this long past the token's expiry; match the verifier's leeway
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Revoke by `jti`; errors if the claims have none
+    pub async fn revoke<T>(&self, claims: &Claims<T>) -> Result<(), AuthError> {
+        let key = id_key(claims)?;
+
+        self.store
+            .insert(&key, claims.expires_at + self.leeway.as_secs())
+            .await
+    }
+
+    /// Whether the token's `jti` has been revoked; tokens without a `jti` are never revoked by id
+    pub async fn is_revoked<T>(&self, claims: &Claims<T>) -> Result<bool, AuthError> {
+        match id_key(claims) {
+            Ok(key) => self.store.contains(&key).await,
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Revoke a token without a `jti` by its hash, keeping the entry until `expires_at`
+    pub async fn revoke_token(&self, token: &str, expires_at: u64) -> Result<(), AuthError> {
+        self.store
+            .insert(&hash_key(token), expires_at + self.leeway.as_secs())
+            .await
+    }
+
+    pub async fn is_token_revoked(&self, token: &str) -> Result<bool, AuthError> {
+        self.store.contains(&hash_key(token)).await
+    }
+
+    /// Drop entries for tokens that have expired, returning how many were removed
+    pub async fn purge(&self) -> Result<usize, AuthError> {
+        self.store.purge(now()?).await
+    }
+}
+
+fn id_key<T>(claims: &Claims<T>) -> Result<String, AuthError> {
+    claims
+        .id
+        .as_ref()
+        .map(|id| format!("jti:{}", id))
+        .ok_or_else(|| AuthError::InvalidInput("Claims have no jti to revoke".to_string()))
+}
+
+fn hash_key(token: &str) -> String {
+    format!("sha256:{}", hash_token(token))
+}
+
+/// Keeps revoked token ids and hashes in process memory until `purge` removes them
+#[derive(Debug, Default)]
+pub struct MemoryRevocationStore {
+    entries: Mutex<HashMap<String, u64>>,
+}
+
+impl MemoryRevocationStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, u64>>, AuthError> {
+        self.entries
+            .lock()
+            .map_err(|_| AuthError::backend("Revocation store lock poisoned"))
+    }
+}
+
+impl RevocationStore for MemoryRevocationStore {
+    async fn insert(&self, key: &str, expires_at: u64) -> Result<(), AuthError> {
+        self.lock()?.insert(key.to_string(), expires_at);
+
+        Ok(())
+    }
+
+    async fn contains(&self, key: &str) -> Result<bool, AuthError> {
+        Ok(self.lock()?.contains_key(key))
+    }
+
+    async fn purge(&self, now: u64) -> Result<usize, AuthError> {
+        let mut entries = self.lock()?;
+        let before = entries.len();
+        entries.retain(|_, expires_at| *expires_at >= now);
+
+        Ok(before - entries.len())
+    }
+}
@@ -0,0 +1,269 @@
+use std::sync::Arc;
+
+use crate::{
+    sqlite::{Row, SqliteDatabase},
+    AuthError,
+};
+
+use super::{
+    now,
+    opaque::{OpaqueToken, OpaqueTokenStore},
+    refresh::{RefreshRecord, RefreshTokenStore},
+};
+
+/// An `OpaqueTokenStore` keeping tokens in the `auth_opaque_tokens` table created by
+/// `SqliteDatabase::migrate`
+///
+/// `revoke` is a single `DELETE`, so a single use token can only be consumed once however many
+/// instances race for it. Call `delete_expired` periodically to remove tokens nobody used.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::sqlite::SqliteDatabase;
+/// use lonewolf_auth_toolkit::token::{opaque::OpaqueTokens, sqlite::SqliteTokenStore};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let database = Arc::new(SqliteDatabase::open_in_memory()?);
+///     database.migrate()?;
+///
+///     let resets = OpaqueTokens::new(SqliteTokenStore::new(database), "password-reset");
+///
+///     let token = resets.issue("SomeAccountName").await?;
+///     assert!(resets.consume(&token).await?.is_some());
+///     assert!(resets.consume(&token).await?.is_none());
+///
+///     Ok(())
+/// }
+/// ```
+pub struct SqliteTokenStore {
+    database: Arc<SqliteDatabase>,
+}
+
+impl SqliteTokenStore {
+    pub fn new(database: Arc<SqliteDatabase>) -> Self {
+        Self { database }
+    }
+
+    pub fn database(&self) -> &SqliteDatabase {
+        &self.database
+    }
+
+    /// Delete every expired token, returning how many were deleted
+    pub async fn delete_expired(&self) -> Result<u64, AuthError> {
+        self.database.execute(
+            "DELETE FROM auth_opaque_tokens WHERE expires_at <= ?1",
+            &[Some(&now()?.to_string())],
+        )
+    }
+}
+
+impl OpaqueTokenStore for SqliteTokenStore {
+    async fn insert(&self, token: OpaqueToken) -> Result<(), AuthError> {
+        let created_at = token.created_at.to_string();
+        let expires_at = token.expires_at.map(|expires_at| expires_at.to_string());
+
+        self.database.execute(
+            "INSERT INTO auth_opaque_tokens (hash, purpose, subject, created_at, expires_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            &[
+                Some(&token.hash),
+                Some(&token.purpose),
+                Some(&token.subject),
+                Some(&created_at),
+                expires_at.as_deref(),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Option<OpaqueToken>, AuthError> {
+        self.database
+            .query(
+                "SELECT hash, purpose, subject, created_at, expires_at FROM auth_opaque_tokens \
+                 WHERE hash = ?1",
+                &[Some(hash)],
+            )?
+            .first()
+            .map(token)
+            .transpose()
+    }
+
+    async fn revoke(&self, hash: &str) -> Result<bool, AuthError> {
+        let deleted = self.database.execute(
+            "DELETE FROM auth_opaque_tokens WHERE hash = ?1",
+            &[Some(hash)],
+        )?;
+
+        Ok(deleted == 1)
+    }
+
+    async fn revoke_all(&self, purpose: &str, subject: &str) -> Result<(), AuthError> {
+        self.database.execute(
+            "DELETE FROM auth_opaque_tokens WHERE purpose = ?1 AND subject = ?2",
+            &[Some(purpose), Some(subject)],
+        )?;
+
+        Ok(())
+    }
+}
+
+fn token(row: &Row) -> Result<OpaqueToken, AuthError> {
+    let expires_at = match row.get(4) {
+        Some(_) => Some(u64::try_from(row.int(4)?)?),
+        None => None,
+    };
+
+    Ok(OpaqueToken {
+        hash: row.text(0)?.to_string(),
+        purpose: row.text(1)?.to_string(),
+        subject: row.text(2)?.to_string(),
+        created_at: u64::try_from(row.int(3)?)?,
+        expires_at,
+    })
+}
+
+/// A `RefreshTokenStore` keeping tokens in the `auth_refresh_tokens` table created by
+/// `SqliteDatabase::migrate`
+///
+/// `rotate` runs in an immediate transaction, so a refresh racing with `revoke_family` either
+/// commits its successor before the family is deleted or finds the old token gone and inserts
+/// nothing. Call `delete_expired` periodically to remove families nobody refreshed.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use lonewolf_auth_toolkit::sqlite::SqliteDatabase;
+/// use lonewolf_auth_toolkit::token::{
+///     refresh::{Refresh, RefreshTokens},
+///     sqlite::SqliteRefreshTokenStore,
+/// };
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let database = Arc::new(SqliteDatabase::open_in_memory()?);
+///     database.migrate()?;
+///
+///     let tokens = RefreshTokens::new(SqliteRefreshTokenStore::new(database));
+///
+///     let first = tokens.issue("SomeAccountName").await?;
+///     let second = match tokens.rotate(&first).await? {
+///         Refresh::Rotated { token, .. } => token,
+///         other => panic!("expected a rotation, got {:?}", other),
+///     };
+///
+///     // Replaying the first token revokes the family, the legitimate successor included
+///     assert!(matches!(tokens.rotate(&first).await?, Refresh::Reused { .. }));
+///     assert_eq!(tokens.rotate(&second).await?, Refresh::Rejected);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct SqliteRefreshTokenStore {
+    database: Arc<SqliteDatabase>,
+}
+
+impl SqliteRefreshTokenStore {
+    pub fn new(database: Arc<SqliteDatabase>) -> Self {
+        Self { database }
+    }
+
+    pub fn database(&self) -> &SqliteDatabase {
+        &self.database
+    }
+
+    /// Delete every expired token, returning how many were deleted
+    pub async fn delete_expired(&self) -> Result<u64, AuthError> {
+        self.database.execute(
+            "DELETE FROM auth_refresh_tokens WHERE expires_at <= ?1",
+            &[Some(&now()?.to_string())],
+        )
+    }
+}
+
+const INSERT_REFRESH: &str = "INSERT INTO auth_refresh_tokens \
+     (hash, family, parent, account, expires_at, rotated) VALUES (?1, ?2, ?3, ?4, ?5, ?6)";
+
+impl RefreshTokenStore for SqliteRefreshTokenStore {
+    async fn insert(&self, record: RefreshRecord) -> Result<(), AuthError> {
+        let expires_at = record.expires_at.to_string();
+
+        self.database.execute(
+            INSERT_REFRESH,
+            &[
+                Some(&record.hash),
+                Some(&record.family),
+                record.parent.as_deref(),
+                Some(&record.account),
+                Some(&expires_at),
+                Some(if record.rotated { "1" } else { "0" }),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Option<RefreshRecord>, AuthError> {
+        self.database
+            .query(
+                "SELECT hash, family, parent, account, expires_at, rotated \
+                 FROM auth_refresh_tokens WHERE hash = ?1",
+                &[Some(hash)],
+            )?
+            .first()
+            .map(refresh_record)
+            .transpose()
+    }
+
+    async fn rotate(&self, old_hash: &str, record: RefreshRecord) -> Result<bool, AuthError> {
+        let expires_at = record.expires_at.to_string();
+
+        self.database.transaction(|connection| {
+            let marked = connection.execute(
+                "UPDATE auth_refresh_tokens SET rotated = 1 WHERE hash = ?1 AND rotated = 0",
+                &[Some(old_hash)],
+            )?;
+            if marked == 0 {
+                return Ok(false);
+            }
+
+            connection.execute(
+                INSERT_REFRESH,
+                &[
+                    Some(&record.hash),
+                    Some(&record.family),
+                    record.parent.as_deref(),
+                    Some(&record.account),
+                    Some(&expires_at),
+                    Some("0"),
+                ],
+            )?;
+
+            Ok(true)
+        })
+    }
+
+    async fn revoke_family(&self, family: &str) -> Result<(), AuthError> {
+        self.database.execute(
+            "DELETE FROM auth_refresh_tokens WHERE family = ?1",
+            &[Some(family)],
+        )?;
+
+        Ok(())
+    }
+}
+
+fn refresh_record(row: &Row) -> Result<RefreshRecord, AuthError> {
+    Ok(RefreshRecord {
+        hash: row.text(0)?.to_string(),
+        family: row.text(1)?.to_string(),
+        parent: row.get(2).map(str::to_string),
+        account: row.text(3)?.to_string(),
+        expires_at: u64::try_from(row.int(4)?)?,
+        rotated: row.bool(5)?,
+    })
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use crate::AuthError;
+
+use super::{now, Claims};
+
+/// Clock skew tolerated when checking `exp`, `nbf` and `iat`
+pub const DEFAULT_LEEWAY: Duration = Duration::from_secs(60);
+
+/// The claim checks applied to every verified token, shared by JWT and PASETO verifiers
+///
+/// `exp` is always checked and `nbf` is checked when present. Issuer, audience and maximum age
+/// checks only apply once configured; with `max_age` set the token must carry an `iat`.
+///
+/// ### Example
+/// ```rust
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::token::{Claims, ValidationPolicy};
+///
+/// let policy = ValidationPolicy::new()
+///     .issuer("https://auth.example.com")
+///     .issuer("https://login.example.com")
+///     .audience("https://api.example.com")
+///     .max_age(Duration::from_secs(3600));
+///
+/// let claims = Claims::new((), Duration::from_secs(900))
+///     .unwrap()
+///     .issuer("https://login.example.com")
+///     .audience("https://api.example.com");
+///
+/// assert!(policy.validate(&claims).is_ok());
+/// assert!(policy.validate(&claims.clone().issuer("https://evil.example.com")).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationPolicy {
+    issuers: Vec<String>,
+    audiences: Vec<String>,
+    leeway: Duration,
+    max_age: Option<Duration>,
+}
+
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        Self {
+            issuers: Vec::new(),
+            audiences: Vec::new(),
+            leeway: DEFAULT_LEEWAY,
+            max_age: None,
+        }
+    }
+}
+
+impl ValidationPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept tokens from this issuer; may be called more than once
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuers.push(issuer.into());
+        self
+    }
+
+    /// Require the token's `aud` to name this audience, or any one of them if called more than once
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        self.audiences.push(audience.into());
+        self
+    }
+
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Reject tokens issued longer ago than this, whatever their `exp`
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn validate<T>(&self, claims: &Claims<T>) -> Result<(), AuthError> {
+        self.validate_at(claims, now()?)
+    }
+
+    /// Like `validate`, as of the Unix timestamp `now` in seconds, e.g. from a `Clock`
+    pub fn validate_at<T>(&self, claims: &Claims<T>, now: u64) -> Result<(), AuthError> {
+        let leeway = self.leeway.as_secs();
+
+        if claims.expires_at.saturating_add(leeway) <= now {
+            return Err(AuthError::Verification("Token has expired".to_string()));
+        }
+
+        if claims
+            .not_before
+            .is_some_and(|nbf| nbf > now.saturating_add(leeway))
+        {
+            return Err(AuthError::Verification(
+                "Token is not valid yet".to_string(),
+            ));
+        }
+
+        if let Some(max_age) = self.max_age {
+            let issued_at = claims.issued_at.ok_or_else(|| {
+                AuthError::Verification("Token has no issued at time".to_string())
+            })?;
+
+            if issued_at > now.saturating_add(leeway) {
+                return Err(AuthError::Verification(
+                    "Token was issued in the future".to_string(),
+                ));
+            }
+
+            if now.saturating_sub(issued_at) > max_age.as_secs() + leeway {
+                return Err(AuthError::Verification("Token is too old".to_string()));
+            }
+        }
+
+        if !self.issuers.is_empty()
+            && claims
+                .issuer
+                .as_ref()
+                .is_none_or(|issuer| !self.issuers.contains(issuer))
+        {
+            return Err(AuthError::Verification(
+                "Token has the wrong issuer".to_string(),
+            ));
+        }
+
+        if !self.audiences.is_empty()
+            && !claims
+                .audience
+                .iter()
+                .any(|audience| self.audiences.contains(audience))
+        {
+            return Err(AuthError::Verification(
+                "Token has the wrong audience".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+// Timing and outcome hooks for the verification flows, reported to the trace subscriber and the
+// metrics recorder; without the `tracing` and `metrics` features every hook compiles to a plain
+// call
+//
+// Despite its name the `tracing` feature does not depend on the `tracing` crate yet, which could
+// not be added when these hooks were written: a `tracing` subscriber sees nothing until the
+// application forwards events to it from `set_subscriber`. Emitting real `tracing` spans and
+// events is still to be done.
+
+use std::future::Future;
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+use std::time::Instant;
+#[cfg(feature = "tracing")]
+use std::{sync::OnceLock, time::Duration};
+
+use crate::AuthError;
+
+#[cfg(feature = "tracing")]
+type Subscriber = Box<dyn Fn(&TraceEvent<'_>) + Send + Sync>;
+
+#[cfg(feature = "tracing")]
+static SUBSCRIBER: OnceLock<Subscriber> = OnceLock::new();
+
+/// How a traced operation ended
+#[cfg(feature = "tracing")]
+#[derive(Debug)]
+pub enum Outcome<'a> {
+    Success,
+    /// It ran but said no, e.g. a wrong code or an unknown token
+    Rejected,
+    Failed(&'a AuthError),
+}
+
+/// One finished operation
+#[cfg(feature = "tracing")]
+#[derive(Debug)]
+pub struct TraceEvent<'a> {
+    /// Dotted operation name, e.g. `mfa.verify` or `token.jwt.verify`
+    pub name: &'static str,
+    /// Non secret context, e.g. `("account", "SomeAccountName")`
+    pub fields: &'a [(&'static str, &'a str)],
+    pub elapsed: Duration,
+    pub outcome: Outcome<'a>,
+}
+
+/// Send every traced operation to `subscriber`, e.g. to record it as a `tracing` event or a
+/// latency histogram in the application's observability stack
+///
+/// This crate does not emit `tracing` spans or events itself yet; forwarding them from here is
+/// the only way for a `tracing` subscriber to see these operations.
+///
+/// Events only carry the fields listed in `TraceEvent::fields`, such as account ids and token
+/// purposes; codes, passwords, tokens and secrets are never included. Fails with
+/// `AuthError::InvalidState` if a subscriber is already set.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::{mfa::verify, trace::{set_subscriber, Outcome}};
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     set_subscriber(|event| {
+///         if let Outcome::Failed(error) = &event.outcome {
+///             eprintln!("{} failed after {:?}: {}", event.name, event.elapsed, error);
+///         }
+///     })?;
+///
+///     // Logs "mfa.verify failed after ...: Malformed secret: ..."
+///     assert!(verify("123456".to_string(), "2SHORT".to_string()).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "tracing")]
+pub fn set_subscriber(
+    subscriber: impl Fn(&TraceEvent<'_>) + Send + Sync + 'static,
+) -> Result<(), AuthError> {
+    SUBSCRIBER
+        .set(Box::new(subscriber))
+        .map_err(|_| AuthError::InvalidState("A trace subscriber is already set".to_string()))
+}
+
+/// Results whose `Ok` value can still mean no, reported as `Outcome::Rejected`
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+pub(crate) trait Traced {
+    fn rejected(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+impl Traced for bool {
+    fn rejected(&self) -> bool {
+        !self
+    }
+}
+
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+impl<T> Traced for Option<T> {
+    fn rejected(&self) -> bool {
+        self.is_none()
+    }
+}
+
+#[cfg(all(
+    feature = "mfa",
+    feature = "password",
+    any(feature = "metrics", feature = "tracing")
+))]
+impl Traced for crate::account::SignIn {}
+
+#[cfg(all(feature = "mfa", any(feature = "metrics", feature = "tracing")))]
+impl Traced for crate::mfa::VerifyOutcome {
+    fn rejected(&self) -> bool {
+        !self.is_match()
+    }
+}
+
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+impl Traced for crate::apikey::ApiKeyRecord {}
+
+#[cfg(all(feature = "token", any(feature = "metrics", feature = "tracing")))]
+impl<T> Traced for crate::token::Claims<T> {}
+
+/// Run `future`, reporting its duration and outcome as `name`
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+pub(crate) async fn instrument<T: Traced>(
+    name: &'static str,
+    fields: &[(&'static str, &str)],
+    future: impl Future<Output = Result<T, AuthError>>,
+) -> Result<T, AuthError> {
+    let started = Instant::now();
+    let result = future.await;
+    emit(name, fields, started, &result);
+
+    result
+}
+
+#[cfg(not(any(feature = "metrics", feature = "tracing")))]
+pub(crate) async fn instrument<T>(
+    _name: &'static str,
+    _fields: &[(&'static str, &str)],
+    future: impl Future<Output = Result<T, AuthError>>,
+) -> Result<T, AuthError> {
+    future.await
+}
+
+/// Like `instrument`, for synchronous operations
+#[cfg(all(
+    any(feature = "mfa", feature = "token"),
+    any(feature = "metrics", feature = "tracing")
+))]
+pub(crate) fn instrument_sync<T: Traced>(
+    name: &'static str,
+    fields: &[(&'static str, &str)],
+    f: impl FnOnce() -> Result<T, AuthError>,
+) -> Result<T, AuthError> {
+    let started = Instant::now();
+    let result = f();
+    emit(name, fields, started, &result);
+
+    result
+}
+
+#[cfg(all(
+    any(feature = "mfa", feature = "token"),
+    not(any(feature = "metrics", feature = "tracing"))
+))]
+pub(crate) fn instrument_sync<T>(
+    _name: &'static str,
+    _fields: &[(&'static str, &str)],
+    f: impl FnOnce() -> Result<T, AuthError>,
+) -> Result<T, AuthError> {
+    f()
+}
+
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+fn emit<T: Traced>(
+    name: &'static str,
+    fields: &[(&'static str, &str)],
+    started: Instant,
+    result: &Result<T, AuthError>,
+) {
+    let rejected = matches!(result, Ok(value) if value.rejected());
+    #[cfg(not(feature = "tracing"))]
+    let _ = fields;
+
+    #[cfg(feature = "metrics")]
+    crate::metrics::verification(
+        name,
+        match result {
+            Ok(_) if rejected => "rejected",
+            Ok(_) => "success",
+            Err(_) => "error",
+        },
+        started.elapsed(),
+    );
+
+    #[cfg(feature = "tracing")]
+    if let Some(subscriber) = SUBSCRIBER.get() {
+        let outcome = match result {
+            Ok(_) if rejected => Outcome::Rejected,
+            Ok(_) => Outcome::Success,
+            Err(error) => Outcome::Failed(error),
+        };
+
+        subscriber(&TraceEvent {
+            name,
+            fields,
+            elapsed: started.elapsed(),
+            outcome,
+        });
+    }
+}
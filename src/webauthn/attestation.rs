@@ -0,0 +1,833 @@
+use ring::signature::{self, VerificationAlgorithm};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::{
+    mtls::certificate::{self, Certificate},
+    token::now,
+    AuthError,
+};
+
+use super::{
+    cbor::Value,
+    cose::{self, CoseKey},
+    metadata::MetadataBlob,
+    Attestation, AuthenticatorData,
+};
+
+/// COSE algorithm identifiers attestation signatures may use besides the credential ones
+const ES384: i128 = -35;
+const RS384: i128 = -258;
+const RS512: i128 = -259;
+
+/// `id-fido-gen-ce-aaguid`, 1.3.6.1.4.1.45724.1.1.4
+const FIDO_AAGUID: &[u8] = &[
+    0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xe5, 0x1c, 0x01, 0x01, 0x04,
+];
+/// The Android key attestation extension, 1.3.6.1.4.1.11129.2.1.17
+const ANDROID_KEY_DESCRIPTION: &[u8] =
+    &[0x2b, 0x06, 0x01, 0x04, 0x01, 0xd6, 0x79, 0x02, 0x01, 0x11];
+/// `tcg-kp-AIKCertificate`, 2.23.133.8.3
+const TCG_AIK_CERTIFICATE: &[u8] = &[0x67, 0x81, 0x05, 0x08, 0x03];
+/// `organizationalUnitName`, 2.5.4.11
+const ORGANIZATIONAL_UNIT: &[u8] = &[0x55, 0x04, 0x0b];
+
+const TPM_GENERATED_VALUE: u32 = 0xff54_4347;
+const TPM_ST_ATTEST_CERTIFY: u16 = 0x8017;
+const TPM_ALG_RSA: u16 = 0x0001;
+const TPM_ALG_SHA1: u16 = 0x0004;
+const TPM_ALG_SHA256: u16 = 0x000b;
+const TPM_ALG_SHA384: u16 = 0x000c;
+const TPM_ALG_SHA512: u16 = 0x000d;
+const TPM_ALG_NULL: u16 = 0x0010;
+const TPM_ALG_ECC: u16 = 0x0023;
+const TPM_ECC_NIST_P256: u16 = 0x0003;
+
+/// Android `Tag::PURPOSE`, `Tag::ALL_APPLICATIONS` and `Tag::ORIGIN` tag numbers
+const ANDROID_PURPOSE: u32 = 1;
+const ANDROID_ALL_APPLICATIONS: u32 = 600;
+const ANDROID_ORIGIN: u32 = 702;
+const KM_PURPOSE_SIGN: u64 = 2;
+const KM_ORIGIN_GENERATED: u64 = 0;
+
+/// How the authenticator vouched for its credential
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttestationType {
+    /// `none`: nothing is known about the authenticator
+    None,
+    /// Signed with the credential's own key, which proves possession but not the model
+    SelfAttestation,
+    /// Signed with a key certified by the vendor, shared by a batch of authenticators
+    Basic,
+    /// Signed with a TPM attestation identity key certified by a privacy CA
+    AttestationCa,
+}
+
+/// An attestation statement whose signature checked out
+///
+/// `trusted` says whether `trust_path` chains to a root configured on the policy or listed for
+/// the authenticator in the metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedAttestation {
+    pub format: String,
+    pub kind: AttestationType,
+    /// Identifies the authenticator model, for allowlists and metadata lookups
+    pub aaguid: [u8; 16],
+    /// The attestation certificate first, then any intermediates
+    pub trust_path: Vec<Certificate>,
+    pub trusted: bool,
+}
+
+/// Which authenticators may register, judged by their attestation statements
+///
+/// The default policy verifies whatever statement the authenticator sent, `none` included,
+/// without requiring it to chain anywhere. Regulated deployments add the vendors' attestation
+/// roots or a FIDO Metadata Service BLOB, allow the AAGUIDs of approved models and call
+/// `require_trusted`. Models the metadata reports as compromised, e.g. with
+/// `ATTESTATION_KEY_COMPROMISE`, are always rejected. Supports the `packed`, `tpm`,
+/// `android-key` and `none` formats; others fail with `AuthError::Malformed`.
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::mtls::certificate::Certificate;
+/// use lonewolf_auth_toolkit::webauthn::attestation::{AttestationPolicy, AttestationType};
+/// use lonewolf_auth_toolkit::webauthn::cbor::{encode, Value};
+/// use lonewolf_auth_toolkit::webauthn::Attestation;
+/// use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+/// use sha2::{Digest, Sha256};
+///
+/// let rng = ring::rand::SystemRandom::new();
+/// let aaguid = [
+///     0x6d, 0x44, 0xba, 0x9b, 0xf6, 0xec, 0x2e, 0x49,
+///     0xb9, 0x30, 0x0c, 0x8f, 0xe9, 0x20, 0xcb, 0x73,
+/// ];
+///
+/// // The credential key, created by the authenticator
+/// let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+/// let credential =
+///     EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+/// let point = credential.public_key().as_ref();
+/// let cose_key = encode(&Value::Map(vec![
+///     (Value::Integer(1), Value::Integer(2)),
+///     (Value::Integer(3), Value::Integer(-7)),
+///     (Value::Integer(-1), Value::Integer(1)),
+///     (Value::Integer(-2), Value::Bytes(point[1..33].to_vec())),
+///     (Value::Integer(-3), Value::Bytes(point[33..].to_vec())),
+/// ]));
+///
+/// let mut authenticator_data = Sha256::digest(b"example.com").to_vec();
+/// authenticator_data.extend([0x41, 0, 0, 0, 0]);
+/// authenticator_data.extend(aaguid);
+/// authenticator_data.extend([0, 4]);
+/// authenticator_data.extend(b"cred");
+/// authenticator_data.extend(&cose_key);
+/// let client_data_hash = Sha256::digest(b"{}").to_vec();
+///
+/// // The vendor's batch attestation key, certified by its root
+/// let attestation_key = EcdsaKeyPair::from_pkcs8(
+///     &ECDSA_P256_SHA256_ASN1_SIGNING,
+///     include_bytes!("testdata/attestation.pk8"),
+///     &rng,
+/// )
+/// .unwrap();
+/// let certificate = &Certificate::parse_pem(include_str!("testdata/attestation.pem"))?[0];
+/// let signature = attestation_key
+///     .sign(&rng, &[authenticator_data.as_slice(), &client_data_hash].concat())
+///     .unwrap();
+///
+/// let attestation = Attestation {
+///     format: "packed".to_string(),
+///     statement: Value::Map(vec![
+///         (Value::Text("alg".to_string()), Value::Integer(-7)),
+///         (Value::Text("sig".to_string()), Value::Bytes(signature.as_ref().to_vec())),
+///         (
+///             Value::Text("x5c".to_string()),
+///             Value::Array(vec![Value::Bytes(certificate.der().to_vec())]),
+///         ),
+///     ]),
+///     authenticator_data,
+///     client_data_hash,
+/// };
+///
+/// let policy = AttestationPolicy::new()
+///     .roots(Certificate::parse_pem(include_str!("testdata/root.pem"))?)
+///     .allow_aaguid(aaguid)
+///     .require_trusted(true);
+/// let verified = policy.check(&attestation)?;
+/// assert_eq!(verified.kind, AttestationType::Basic);
+/// assert!(verified.trusted);
+///
+/// // Other models are turned away
+/// let policy = AttestationPolicy::new().allow_aaguid([0; 16]);
+/// assert!(policy.check(&attestation).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AttestationPolicy {
+    roots: Vec<Certificate>,
+    metadata: Option<MetadataBlob>,
+    aaguids: Vec<[u8; 16]>,
+    require_trusted: bool,
+}
+
+impl AttestationPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust attestation certificates issued by these roots, e.g. a vendor's published CA
+    pub fn roots(mut self, roots: impl IntoIterator<Item = Certificate>) -> Self {
+        self.roots.extend(roots);
+        self
+    }
+
+    /// Look authenticators up in a verified FIDO Metadata Service BLOB
+    ///
+    /// Each entry's attestation roots are trusted for its AAGUID, and models with a compromised
+    /// status are rejected.
+    pub fn metadata(mut self, metadata: MetadataBlob) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Only accept authenticators with this AAGUID; call once per approved model
+    pub fn allow_aaguid(mut self, aaguid: [u8; 16]) -> Self {
+        self.aaguids.push(aaguid);
+        self
+    }
+
+    /// Reject attestations that do not chain to a trusted root, including `none` and self
+    /// attestation
+    pub fn require_trusted(mut self, required: bool) -> Self {
+        self.require_trusted = required;
+        self
+    }
+
+    /// Verify the statement and check it against the policy
+    ///
+    /// Fails with `AuthError::Verification` for bad signatures and authenticators the policy
+    /// does not allow.
+    pub fn check(&self, attestation: &Attestation) -> Result<VerifiedAttestation, AuthError> {
+        self.check_at(attestation, now()?)
+    }
+
+    /// Like `check`, validating certificates at the Unix timestamp `now`
+    pub fn check_at(
+        &self,
+        attestation: &Attestation,
+        now: u64,
+    ) -> Result<VerifiedAttestation, AuthError> {
+        let mut verified = verify_statement(attestation)?;
+
+        if !self.aaguids.is_empty() && !self.aaguids.contains(&verified.aaguid) {
+            return Err(AuthError::Verification(
+                "Authenticator model is not allowed".to_string(),
+            ));
+        }
+
+        let entry = self
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.entry(&verified.aaguid));
+        if entry.is_some_and(|entry| !entry.is_trusted()) {
+            return Err(AuthError::Verification(
+                "Authenticator model has a known security issue".to_string(),
+            ));
+        }
+
+        let roots: Vec<&Certificate> = self
+            .roots
+            .iter()
+            .chain(entry.into_iter().flat_map(|entry| &entry.attestation_roots))
+            .collect();
+        verified.trusted = chains_to(&verified.trust_path, &roots, now);
+
+        if self.require_trusted && !verified.trusted {
+            return Err(AuthError::Verification(
+                "Attestation is not from a trusted authenticator".to_string(),
+            ));
+        }
+
+        Ok(verified)
+    }
+}
+
+/// Check an attestation statement's signature and format specific requirements
+///
+/// This proves the statement is genuine, not that its certificates are trusted; use an
+/// `AttestationPolicy` for that.
+pub fn verify_statement(attestation: &Attestation) -> Result<VerifiedAttestation, AuthError> {
+    let data = AuthenticatorData::parse(&attestation.authenticator_data)?;
+    let credential = data.attested.ok_or_else(|| {
+        AuthError::Malformed("Authenticator data does not include a credential".to_string())
+    })?;
+    let key = CoseKey::from_cbor(&credential.public_key)?;
+
+    let statement = &attestation.statement;
+    let signed = [
+        attestation.authenticator_data.as_slice(),
+        &attestation.client_data_hash,
+    ]
+    .concat();
+
+    let (kind, trust_path) = match attestation.format.as_str() {
+        "none" => match statement {
+            Value::Map(entries) if entries.is_empty() => (AttestationType::None, Vec::new()),
+            _ => {
+                return Err(AuthError::Malformed(
+                    "A none attestation must have an empty statement".to_string(),
+                ))
+            }
+        },
+        "packed" => packed(statement, &signed, &key, &credential.aaguid)?,
+        "tpm" => tpm(statement, &signed, &key, &credential.aaguid)?,
+        "android-key" => android_key(statement, &signed, &key, &attestation.client_data_hash)?,
+        other => {
+            return Err(AuthError::Malformed(format!(
+                "Unsupported attestation format {}",
+                other
+            )))
+        }
+    };
+
+    Ok(VerifiedAttestation {
+        format: attestation.format.clone(),
+        kind,
+        aaguid: credential.aaguid,
+        trust_path,
+        trusted: false,
+    })
+}
+
+/// Whether the first certificate of `path` chains through the rest of it to one of `roots`
+pub(crate) fn chains_to(path: &[Certificate], roots: &[&Certificate], now: u64) -> bool {
+    let Some(mut current) = path.first() else {
+        return false;
+    };
+    if path.iter().any(|certificate| !certificate.is_valid_at(now)) {
+        return false;
+    }
+
+    for _ in 0..=path.len() {
+        if roots
+            .iter()
+            .any(|root| root.is_valid_at(now) && (*root == current || current.is_signed_by(root)))
+        {
+            return true;
+        }
+
+        match path
+            .iter()
+            .find(|issuer| *issuer != current && issuer.is_ca() && current.is_signed_by(issuer))
+        {
+            Some(issuer) => current = issuer,
+            None => return false,
+        }
+    }
+
+    false
+}
+
+type Verified = (AttestationType, Vec<Certificate>);
+
+fn packed(
+    statement: &Value,
+    signed: &[u8],
+    key: &CoseKey,
+    aaguid: &[u8; 16],
+) -> Result<Verified, AuthError> {
+    let algorithm = algorithm(statement)?;
+    let signature = bytes(statement, "sig")?;
+
+    let Some(chain) = x5c(statement)? else {
+        if algorithm != key.algorithm() {
+            return Err(AuthError::Verification(
+                "Self attestation must use the credential's algorithm".to_string(),
+            ));
+        }
+        key.verify(signed, signature)
+            .map_err(|_| invalid_signature())?;
+
+        return Ok((AttestationType::SelfAttestation, Vec::new()));
+    };
+
+    let leaf = &chain[0];
+    if !leaf.verifies(certificate_algorithm(algorithm)?, signed, signature) {
+        return Err(invalid_signature());
+    }
+    if leaf.is_ca()
+        || leaf.subject_attribute(ORGANIZATIONAL_UNIT).as_deref()
+            != Some("Authenticator Attestation")
+    {
+        return Err(AuthError::Verification(
+            "Packed attestation certificate does not meet the requirements".to_string(),
+        ));
+    }
+    check_aaguid(leaf, aaguid)?;
+
+    Ok((AttestationType::Basic, chain))
+}
+
+fn tpm(
+    statement: &Value,
+    signed: &[u8],
+    key: &CoseKey,
+    aaguid: &[u8; 16],
+) -> Result<Verified, AuthError> {
+    if statement.get_text("ver").and_then(Value::as_text) != Some("2.0") {
+        return Err(AuthError::Malformed(
+            "Only TPM 2.0 attestation is supported".to_string(),
+        ));
+    }
+    let algorithm = algorithm(statement)?;
+    let signature = bytes(statement, "sig")?;
+    let cert_info = bytes(statement, "certInfo")?;
+    let pub_area = bytes(statement, "pubArea")?;
+    let chain = x5c(statement)?.ok_or_else(|| {
+        AuthError::Malformed("TPM attestation is missing its certificates".to_string())
+    })?;
+
+    let public = PublicArea::parse(pub_area)?;
+    if !public.matches(key) {
+        return Err(AuthError::Verification(
+            "TPM public area does not match the credential key".to_string(),
+        ));
+    }
+
+    let info = CertInfo::parse(cert_info)?;
+    let mut name = public.name_alg.to_be_bytes().to_vec();
+    name.extend(tpm_digest(public.name_alg, pub_area)?);
+    if info.magic != TPM_GENERATED_VALUE
+        || info.kind != TPM_ST_ATTEST_CERTIFY
+        || info.extra_data != hash(algorithm, signed)?
+        || info.attested_name != name
+    {
+        return Err(AuthError::Verification(
+            "TPM certification does not cover this credential".to_string(),
+        ));
+    }
+
+    let aik = &chain[0];
+    if !aik.verifies(certificate_algorithm(algorithm)?, cert_info, signature) {
+        return Err(invalid_signature());
+    }
+    let aik_usage = aik
+        .extended_key_usages()
+        .is_some_and(|usages| usages.iter().any(|usage| usage == TCG_AIK_CERTIFICATE));
+    if aik.is_ca() || !aik.has_empty_subject() || !aik_usage {
+        return Err(AuthError::Verification(
+            "TPM attestation certificate does not meet the requirements".to_string(),
+        ));
+    }
+    check_aaguid(aik, aaguid)?;
+
+    Ok((AttestationType::AttestationCa, chain))
+}
+
+fn android_key(
+    statement: &Value,
+    signed: &[u8],
+    key: &CoseKey,
+    client_data_hash: &[u8],
+) -> Result<Verified, AuthError> {
+    let algorithm = algorithm(statement)?;
+    let signature = bytes(statement, "sig")?;
+    let chain = x5c(statement)?.ok_or_else(|| {
+        AuthError::Malformed("Android key attestation is missing its certificates".to_string())
+    })?;
+
+    let leaf = &chain[0];
+    if !leaf.verifies(certificate_algorithm(algorithm)?, signed, signature) {
+        return Err(invalid_signature());
+    }
+    if !same_key(leaf, key)? {
+        return Err(AuthError::Verification(
+            "Android attestation certificate is not for the credential key".to_string(),
+        ));
+    }
+
+    let description = leaf.extension(ANDROID_KEY_DESCRIPTION).ok_or_else(|| {
+        AuthError::Verification("Android attestation has no key description".to_string())
+    })?;
+    let description = KeyDescription::parse(description)?;
+    if description.challenge != client_data_hash
+        || description.all_applications
+        || description.origin != Some(KM_ORIGIN_GENERATED)
+        || !description.purposes.contains(&KM_PURPOSE_SIGN)
+    {
+        return Err(AuthError::Verification(
+            "Android key description does not meet the requirements".to_string(),
+        ));
+    }
+
+    Ok((AttestationType::Basic, chain))
+}
+
+fn algorithm(statement: &Value) -> Result<i128, AuthError> {
+    statement
+        .get_text("alg")
+        .and_then(Value::as_integer)
+        .ok_or_else(|| AuthError::Malformed("Attestation statement is missing alg".to_string()))
+}
+
+fn bytes<'a>(statement: &'a Value, name: &str) -> Result<&'a [u8], AuthError> {
+    statement
+        .get_text(name)
+        .and_then(Value::as_bytes)
+        .ok_or_else(|| AuthError::Malformed(format!("Attestation statement is missing {}", name)))
+}
+
+/// The statement's certificate chain, attestation certificate first
+fn x5c(statement: &Value) -> Result<Option<Vec<Certificate>>, AuthError> {
+    let Some(chain) = statement.get_text("x5c") else {
+        return Ok(None);
+    };
+
+    let chain = chain
+        .as_array()
+        .filter(|chain| !chain.is_empty())
+        .ok_or_else(|| AuthError::Malformed("Attestation x5c must not be empty".to_string()))?
+        .iter()
+        .map(|der| {
+            der.as_bytes()
+                .ok_or_else(|| AuthError::Malformed("Attestation x5c must hold DER".to_string()))
+                .and_then(Certificate::from_der)
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Some(chain))
+}
+
+/// A certificate key verifier for the COSE algorithm an attestation statement names
+fn certificate_algorithm(algorithm: i128) -> Result<&'static dyn VerificationAlgorithm, AuthError> {
+    Ok(match algorithm {
+        cose::ES256 => &signature::ECDSA_P256_SHA256_ASN1,
+        ES384 => &signature::ECDSA_P384_SHA384_ASN1,
+        cose::EDDSA => &signature::ED25519,
+        cose::RS256 => &signature::RSA_PKCS1_2048_8192_SHA256,
+        RS384 => &signature::RSA_PKCS1_2048_8192_SHA384,
+        RS512 => &signature::RSA_PKCS1_2048_8192_SHA512,
+        _ => {
+            return Err(AuthError::Malformed(format!(
+                "Unsupported attestation algorithm {}",
+                algorithm
+            )))
+        }
+    })
+}
+
+/// The digest a TPM puts in `extraData` for the COSE algorithm
+fn hash(algorithm: i128, message: &[u8]) -> Result<Vec<u8>, AuthError> {
+    Ok(match algorithm {
+        cose::ES256 | cose::RS256 => Sha256::digest(message).to_vec(),
+        ES384 | RS384 => Sha384::digest(message).to_vec(),
+        RS512 => Sha512::digest(message).to_vec(),
+        _ => {
+            return Err(AuthError::Malformed(format!(
+                "Unsupported attestation algorithm {}",
+                algorithm
+            )))
+        }
+    })
+}
+
+fn tpm_digest(algorithm: u16, message: &[u8]) -> Result<Vec<u8>, AuthError> {
+    Ok(match algorithm {
+        TPM_ALG_SHA1 => Sha1::digest(message).to_vec(),
+        TPM_ALG_SHA256 => Sha256::digest(message).to_vec(),
+        TPM_ALG_SHA384 => Sha384::digest(message).to_vec(),
+        TPM_ALG_SHA512 => Sha512::digest(message).to_vec(),
+        _ => {
+            return Err(AuthError::Malformed(format!(
+                "Unsupported TPM name algorithm {:#06x}",
+                algorithm
+            )))
+        }
+    })
+}
+
+/// Fail if the certificate names a different authenticator model than the credential
+fn check_aaguid(certificate: &Certificate, aaguid: &[u8; 16]) -> Result<(), AuthError> {
+    if let Some(value) = certificate.extension(FIDO_AAGUID) {
+        let (certified, _) = certificate::read(value, 0x04)?;
+        if certified != aaguid {
+            return Err(AuthError::Verification(
+                "Attestation certificate is for another authenticator model".to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the certificate's public key is the credential key
+fn same_key(certificate: &Certificate, key: &CoseKey) -> Result<bool, AuthError> {
+    Ok(match key {
+        CoseKey::Es256 { point } => certificate.public_key() == point.as_slice(),
+        CoseKey::EdDsa { public_key } => certificate.public_key() == public_key.as_slice(),
+        CoseKey::Rs256 { n, e } => {
+            let (rsa, _) = certificate::read(certificate.public_key(), 0x30)?;
+            let (modulus, rest) = certificate::read(rsa, 0x02)?;
+            let (exponent, _) = certificate::read(rest, 0x02)?;
+
+            unsigned(modulus) == unsigned(n) && unsigned(exponent) == unsigned(e)
+        }
+    })
+}
+
+/// A big endian unsigned integer without its leading zeros
+fn unsigned(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|byte| *byte != 0)
+        .unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+fn invalid_signature() -> AuthError {
+    AuthError::Verification("Attestation signature is invalid".to_string())
+}
+
+/// Reads the big endian structures TPMs produce
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], AuthError> {
+        if self.0.len() < count {
+            return Err(AuthError::Malformed(
+                "TPM structure is truncated".to_string(),
+            ));
+        }
+        let (taken, rest) = self.0.split_at(count);
+        self.0 = rest;
+
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, AuthError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, AuthError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    /// A `TPM2B` value: a two byte size, then that many bytes
+    fn sized(&mut self) -> Result<&'a [u8], AuthError> {
+        let size = self.u16()?;
+        self.take(usize::from(size))
+    }
+}
+
+/// The parts of a `TPMT_PUBLIC` needed to compare it with the credential key
+struct PublicArea<'a> {
+    name_alg: u16,
+    key: TpmKey<'a>,
+}
+
+enum TpmKey<'a> {
+    Rsa {
+        modulus: &'a [u8],
+        exponent: u32,
+    },
+    Ecc {
+        curve: u16,
+        x: &'a [u8],
+        y: &'a [u8],
+    },
+}
+
+impl<'a> PublicArea<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self, AuthError> {
+        let mut cursor = Cursor(bytes);
+        let kind = cursor.u16()?;
+        let name_alg = cursor.u16()?;
+        cursor.u32()?; // objectAttributes
+        cursor.sized()?; // authPolicy
+
+        if cursor.u16()? != TPM_ALG_NULL {
+            return Err(AuthError::Malformed(
+                "TPM signing keys must not have a symmetric algorithm".to_string(),
+            ));
+        }
+        if cursor.u16()? != TPM_ALG_NULL {
+            cursor.u16()?; // The scheme's hash algorithm
+        }
+
+        let key = match kind {
+            TPM_ALG_RSA => {
+                cursor.u16()?; // keyBits
+                let exponent = cursor.u32()?;
+                TpmKey::Rsa {
+                    exponent,
+                    modulus: cursor.sized()?,
+                }
+            }
+            TPM_ALG_ECC => {
+                let curve = cursor.u16()?;
+                if cursor.u16()? != TPM_ALG_NULL {
+                    cursor.u16()?; // The KDF's hash algorithm
+                }
+                TpmKey::Ecc {
+                    curve,
+                    x: cursor.sized()?,
+                    y: cursor.sized()?,
+                }
+            }
+            _ => return Err(AuthError::Malformed("Unsupported TPM key type".to_string())),
+        };
+
+        Ok(Self { name_alg, key })
+    }
+
+    fn matches(&self, key: &CoseKey) -> bool {
+        match (&self.key, key) {
+            (TpmKey::Rsa { modulus, exponent }, CoseKey::Rs256 { n, e }) => {
+                // An exponent of zero means the default, 65537
+                let exponent = if *exponent == 0 { 65537 } else { *exponent };
+                unsigned(modulus) == unsigned(n) && unsigned(&exponent.to_be_bytes()) == unsigned(e)
+            }
+            (TpmKey::Ecc { curve, x, y }, CoseKey::Es256 { point }) => {
+                *curve == TPM_ECC_NIST_P256 && point[1..] == [*x, *y].concat()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The parts of a `TPMS_ATTEST` that bind it to the credential
+struct CertInfo<'a> {
+    magic: u32,
+    kind: u16,
+    extra_data: &'a [u8],
+    attested_name: &'a [u8],
+}
+
+impl<'a> CertInfo<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self, AuthError> {
+        let mut cursor = Cursor(bytes);
+        let magic = cursor.u32()?;
+        let kind = cursor.u16()?;
+        cursor.sized()?; // qualifiedSigner
+        let extra_data = cursor.sized()?;
+        cursor.take(17)?; // clockInfo
+        cursor.take(8)?; // firmwareVersion
+        let attested_name = cursor.sized()?;
+
+        Ok(Self {
+            magic,
+            kind,
+            extra_data,
+            attested_name,
+        })
+    }
+}
+
+/// The parts of an Android `KeyDescription` the WebAuthn checks look at
+struct KeyDescription<'a> {
+    challenge: &'a [u8],
+    purposes: Vec<u64>,
+    origin: Option<u64>,
+    all_applications: bool,
+}
+
+impl<'a> KeyDescription<'a> {
+    fn parse(extension: &'a [u8]) -> Result<Self, AuthError> {
+        let (mut rest, _) = certificate::read(extension, 0x30)?;
+        // attestationVersion, attestationSecurityLevel, keymasterVersion, keymasterSecurityLevel
+        for _ in 0..4 {
+            rest = certificate::next(rest)?.2;
+        }
+        let (challenge, rest) = certificate::read(rest, 0x04)?;
+        let (_, _, rest) = certificate::next(rest)?; // uniqueId
+        let (software, rest) = certificate::read(rest, 0x30)?;
+        let (tee, _) = certificate::read(rest, 0x30)?;
+
+        let mut description = Self {
+            challenge,
+            purposes: Vec::new(),
+            origin: None,
+            all_applications: false,
+        };
+        for mut list in [software, tee] {
+            while !list.is_empty() {
+                let (tag, contents, rest) = tagged(list)?;
+                list = rest;
+
+                match tag {
+                    ANDROID_PURPOSE => {
+                        let (mut set, _) = certificate::read(contents, 0x31)?;
+                        while !set.is_empty() {
+                            let (purpose, rest) = certificate::read(set, 0x02)?;
+                            description.purposes.push(integer(purpose)?);
+                            set = rest;
+                        }
+                    }
+                    ANDROID_ORIGIN => {
+                        description.origin = Some(integer(certificate::read(contents, 0x02)?.0)?);
+                    }
+                    ANDROID_ALL_APPLICATIONS => description.all_applications = true,
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(description)
+    }
+}
+
+/// The next context specific element's tag number, contents and whatever follows it
+///
+/// Android authorization lists use tag numbers above 30, which take several identifier octets.
+fn tagged(input: &[u8]) -> Result<(u32, &[u8], &[u8]), AuthError> {
+    let malformed = || AuthError::Malformed("Android key description is not valid DER".to_string());
+
+    let (&first, mut rest) = input.split_first().ok_or_else(malformed)?;
+    let mut number = u32::from(first & 0x1f);
+    if number == 0x1f {
+        number = 0;
+        loop {
+            let (&byte, after) = rest.split_first().ok_or_else(malformed)?;
+            rest = after;
+            number = number
+                .checked_mul(128)
+                .map(|number| number | u32::from(byte & 0x7f))
+                .ok_or_else(malformed)?;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+    }
+
+    let (&length, rest) = rest.split_first().ok_or_else(malformed)?;
+    let (length, rest) = if length < 0x80 {
+        (usize::from(length), rest)
+    } else {
+        let count = usize::from(length & 0x7f);
+        if count == 0 || count > 4 || rest.len() < count {
+            return Err(malformed());
+        }
+        let length = rest[..count]
+            .iter()
+            .fold(0usize, |length, &byte| (length << 8) | usize::from(byte));
+        (length, &rest[count..])
+    };
+    if rest.len() < length {
+        return Err(malformed());
+    }
+    let (contents, rest) = rest.split_at(length);
+
+    Ok((number, contents, rest))
+}
+
+fn integer(bytes: &[u8]) -> Result<u64, AuthError> {
+    if bytes.len() > 8 {
+        return Err(AuthError::Malformed(
+            "Android key description integer is too large".to_string(),
+        ));
+    }
+
+    Ok(bytes
+        .iter()
+        .fold(0u64, |value, byte| (value << 8) | u64::from(*byte)))
+}
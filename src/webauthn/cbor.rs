@@ -0,0 +1,247 @@
+use crate::AuthError;
+
+/// A decoded CBOR data item
+///
+/// Only the subset used by WebAuthn and CTAP2 is supported: definite lengths, integers, byte and
+/// text strings, arrays, maps, booleans and null. Tags are decoded as their inner value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Integer(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    /// Look up a map entry by key
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(entry_key, _)| entry_key == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Look up a map entry by integer key, as used in COSE keys
+    pub fn get_int(&self, key: i128) -> Option<&Value> {
+        self.get(&Value::Integer(key))
+    }
+
+    /// Look up a map entry by text key, as used in attestation objects
+    pub fn get_text(&self, key: &str) -> Option<&Value> {
+        self.get(&Value::Text(key.to_string()))
+    }
+
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Decode a single CBOR item from the start of `input`
+///
+/// Returns the item and the number of bytes it occupied, so callers can continue reading data
+/// that follows it (authenticator data places extensions after the credential public key).
+///
+/// ### Example
+/// ```rust
+/// use lonewolf_auth_toolkit::webauthn::cbor::{decode, encode, Value};
+///
+/// let value = Value::Map(vec![(Value::Integer(1), Value::Bytes(vec![1, 2, 3]))]);
+/// let bytes = encode(&value);
+///
+/// assert_eq!(decode(&bytes).unwrap(), (value, bytes.len()));
+/// ```
+pub fn decode(input: &[u8]) -> Result<(Value, usize), AuthError> {
+    let mut reader = Reader { input, position: 0 };
+    let value = reader.value(0)?;
+
+    Ok((value, reader.position))
+}
+
+/// Encode a value using definite lengths and the shortest argument encoding
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut output = Vec::new();
+    write(value, &mut output);
+
+    output
+}
+
+/// Nesting beyond this depth is rejected to bound recursion on hostile input
+const MAX_DEPTH: usize = 16;
+
+struct Reader<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, length: usize) -> Result<&[u8], AuthError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.input.len())
+            .ok_or_else(|| AuthError::Malformed("CBOR input ended unexpectedly".to_string()))?;
+        let bytes = &self.input[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    fn argument(&mut self, additional: u8) -> Result<u64, AuthError> {
+        Ok(match additional {
+            0..=23 => additional as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into()?) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into()?) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into()?),
+            _ => {
+                return Err(AuthError::Malformed(
+                    "Indefinite length CBOR items are not supported".to_string(),
+                ))
+            }
+        })
+    }
+
+    fn length(&mut self, additional: u8) -> Result<usize, AuthError> {
+        let length = self.argument(additional)?;
+
+        if length > (self.input.len() - self.position) as u64 {
+            return Err(AuthError::Malformed(
+                "CBOR length exceeds the remaining input".to_string(),
+            ));
+        }
+
+        Ok(length as usize)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, AuthError> {
+        if depth > MAX_DEPTH {
+            return Err(AuthError::Malformed(
+                "CBOR input is nested too deeply".to_string(),
+            ));
+        }
+
+        let initial = self.take(1)?[0];
+        let (major, additional) = (initial >> 5, initial & 0x1f);
+
+        Ok(match major {
+            0 => Value::Integer(self.argument(additional)? as i128),
+            1 => Value::Integer(-1 - self.argument(additional)? as i128),
+            2 => {
+                let length = self.length(additional)?;
+                Value::Bytes(self.take(length)?.to_vec())
+            }
+            3 => {
+                let length = self.length(additional)?;
+                Value::Text(String::from_utf8(self.take(length)?.to_vec())?)
+            }
+            4 => {
+                let length = self.length(additional)?;
+                let items = (0..length)
+                    .map(|_| self.value(depth + 1))
+                    .collect::<Result<_, _>>()?;
+                Value::Array(items)
+            }
+            5 => {
+                let length = self.length(additional)?;
+                let entries = (0..length)
+                    .map(|_| Ok((self.value(depth + 1)?, self.value(depth + 1)?)))
+                    .collect::<Result<_, AuthError>>()?;
+                Value::Map(entries)
+            }
+            6 => {
+                self.argument(additional)?;
+                self.value(depth + 1)?
+            }
+            _ => match additional {
+                20 => Value::Bool(false),
+                21 => Value::Bool(true),
+                22 | 23 => Value::Null,
+                _ => {
+                    return Err(AuthError::Malformed(
+                        "Unsupported CBOR simple value".to_string(),
+                    ))
+                }
+            },
+        })
+    }
+}
+
+fn write_head(major: u8, argument: u64, output: &mut Vec<u8>) {
+    let major = major << 5;
+
+    match argument {
+        0..=23 => output.push(major | argument as u8),
+        24..=0xff => output.extend_from_slice(&[major | 24, argument as u8]),
+        0x100..=0xffff => {
+            output.push(major | 25);
+            output.extend_from_slice(&(argument as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            output.push(major | 26);
+            output.extend_from_slice(&(argument as u32).to_be_bytes());
+        }
+        _ => {
+            output.push(major | 27);
+            output.extend_from_slice(&argument.to_be_bytes());
+        }
+    }
+}
+
+fn write(value: &Value, output: &mut Vec<u8>) {
+    match value {
+        Value::Integer(value) if *value >= 0 => write_head(0, *value as u64, output),
+        Value::Integer(value) => write_head(1, (-1 - *value) as u64, output),
+        Value::Bytes(bytes) => {
+            write_head(2, bytes.len() as u64, output);
+            output.extend_from_slice(bytes);
+        }
+        Value::Text(text) => {
+            write_head(3, text.len() as u64, output);
+            output.extend_from_slice(text.as_bytes());
+        }
+        Value::Array(items) => {
+            write_head(4, items.len() as u64, output);
+            items.iter().for_each(|item| write(item, output));
+        }
+        Value::Map(entries) => {
+            write_head(5, entries.len() as u64, output);
+            for (key, value) in entries {
+                write(key, output);
+                write(value, output);
+            }
+        }
+        Value::Bool(false) => output.push(0xf4),
+        Value::Bool(true) => output.push(0xf5),
+        Value::Null => output.push(0xf6),
+    }
+}
@@ -0,0 +1,110 @@
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+
+use crate::AuthError;
+
+use super::cbor::{self, Value};
+
+/// COSE algorithm identifiers this crate can verify
+pub const ES256: i128 = -7;
+pub const EDDSA: i128 = -8;
+pub const RS256: i128 = -257;
+
+/// A credential public key decoded from its COSE_Key encoding (RFC 8152)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoseKey {
+    /// P-256 point as the uncompressed SEC1 encoding
+    Es256 {
+        point: Vec<u8>,
+    },
+    EdDsa {
+        public_key: Vec<u8>,
+    },
+    Rs256 {
+        n: Vec<u8>,
+        e: Vec<u8>,
+    },
+}
+
+impl CoseKey {
+    /// Decode a COSE_Key from CBOR bytes
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, AuthError> {
+        let (key, _) = cbor::decode(bytes)?;
+
+        Self::from_value(&key)
+    }
+
+    pub(crate) fn from_value(key: &Value) -> Result<Self, AuthError> {
+        let int = |label: i128| key.get_int(label).and_then(Value::as_integer);
+        let bytes = |label: i128| {
+            key.get_int(label)
+                .and_then(Value::as_bytes)
+                .map(|bytes| bytes.to_vec())
+                .ok_or_else(|| {
+                    AuthError::Malformed(format!("COSE key is missing parameter {}", label))
+                })
+        };
+
+        match (int(1), int(3)) {
+            (Some(2), Some(ES256)) => {
+                if int(-1) != Some(1) {
+                    return Err(AuthError::Malformed(
+                        "ES256 keys must use the P-256 curve".to_string(),
+                    ));
+                }
+
+                let mut point = vec![0x04];
+                point.extend(bytes(-2)?);
+                point.extend(bytes(-3)?);
+
+                Ok(CoseKey::Es256 { point })
+            }
+            (Some(1), Some(EDDSA)) => {
+                if int(-1) != Some(6) {
+                    return Err(AuthError::Malformed(
+                        "EdDSA keys must use the Ed25519 curve".to_string(),
+                    ));
+                }
+
+                Ok(CoseKey::EdDsa {
+                    public_key: bytes(-2)?,
+                })
+            }
+            (Some(3), Some(RS256)) => Ok(CoseKey::Rs256 {
+                n: bytes(-1)?,
+                e: bytes(-2)?,
+            }),
+            _ => Err(AuthError::Malformed(
+                "Unsupported COSE key type or algorithm".to_string(),
+            )),
+        }
+    }
+
+    /// The COSE algorithm identifier for this key
+    pub fn algorithm(&self) -> i128 {
+        match self {
+            CoseKey::Es256 { .. } => ES256,
+            CoseKey::EdDsa { .. } => EDDSA,
+            CoseKey::Rs256 { .. } => RS256,
+        }
+    }
+
+    /// Verify a WebAuthn assertion or attestation signature over `message`
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), AuthError> {
+        let result = match self {
+            CoseKey::Es256 { point } => {
+                UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, point)
+                    .verify(message, signature)
+            }
+            CoseKey::EdDsa { public_key } => {
+                UnparsedPublicKey::new(&signature::ED25519, public_key).verify(message, signature)
+            }
+            CoseKey::Rs256 { n, e } => RsaPublicKeyComponents { n, e }.verify(
+                &signature::RSA_PKCS1_2048_8192_SHA256,
+                message,
+                signature,
+            ),
+        };
+
+        result.map_err(|_| AuthError::Verification("Signature verification failed".to_string()))
+    }
+}
@@ -0,0 +1,307 @@
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use ring::signature::{self, VerificationAlgorithm};
+use serde::Deserialize;
+
+use crate::{
+    http::{HttpClient, HttpRequest},
+    mtls::certificate::Certificate,
+    otel,
+    token::now,
+    AuthError,
+};
+
+use super::attestation::chains_to;
+
+/// Where the FIDO Alliance publishes the Metadata Service BLOB
+pub const MDS_URL: &str = "https://mds3.fidoalliance.org/";
+
+/// Statuses after which an authenticator model must no longer be trusted
+pub const COMPROMISED_STATUSES: &[&str] = &[
+    "REVOKED",
+    "USER_VERIFICATION_BYPASS",
+    "ATTESTATION_KEY_COMPROMISE",
+    "USER_KEY_REMOTE_COMPROMISE",
+    "USER_KEY_PHYSICAL_COMPROMISE",
+];
+
+/// A change in an authenticator model's certification or security status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusReport {
+    /// E.g. `FIDO_CERTIFIED_L1` or `ATTESTATION_KEY_COMPROMISE`
+    pub status: String,
+    /// ISO 8601 date
+    pub effective_date: Option<String>,
+}
+
+/// What the Metadata Service knows about one FIDO2 authenticator model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataEntry {
+    pub aaguid: [u8; 16],
+    pub description: Option<String>,
+    /// Roots the model's attestation certificates chain to
+    pub attestation_roots: Vec<Certificate>,
+    pub status_reports: Vec<StatusReport>,
+}
+
+impl MetadataEntry {
+    /// The most recent status
+    pub fn status(&self) -> Option<&str> {
+        self.status_reports
+            .iter()
+            .enumerate()
+            .max_by_key(|(index, report)| (report.effective_date.as_deref(), *index))
+            .map(|(_, report)| report.status.as_str())
+    }
+
+    /// Whether the most recent status is not one of `COMPROMISED_STATUSES`
+    pub fn is_trusted(&self) -> bool {
+        self.status()
+            .is_none_or(|status| !COMPROMISED_STATUSES.contains(&status))
+    }
+}
+
+/// The verified contents of a FIDO Metadata Service (MDS3) BLOB
+///
+/// The BLOB is a JWT signed with a certificate chain in its `x5c` header, which must lead to one
+/// of the given roots; for the FIDO Alliance's service that is the GlobalSign Root CA - R3 from
+/// its download page. The BLOB is republished about monthly, so fetch it on a schedule and hand
+/// the result to `AttestationPolicy::metadata`. Only FIDO2 entries, those with an AAGUID, are
+/// kept.
+///
+/// ### Example
+/// ```rust
+/// use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+/// use base64::Engine;
+/// use lonewolf_auth_toolkit::mtls::certificate::Certificate;
+/// use lonewolf_auth_toolkit::webauthn::metadata::MetadataBlob;
+/// use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+///
+/// let root = Certificate::parse_pem(include_str!("testdata/root.pem"))?;
+/// let signer = &Certificate::parse_pem(include_str!("testdata/metadata-signer.pem"))?[0];
+///
+/// // A BLOB as the Metadata Service would publish it
+/// let header = serde_json::json!({
+///     "alg": "ES256",
+///     "typ": "JWT",
+///     "x5c": [STANDARD.encode(signer.der())],
+/// });
+/// let payload = serde_json::json!({
+///     "no": 42,
+///     "nextUpdate": "2030-01-01",
+///     "entries": [{
+///         "aaguid": "6d44ba9b-f6ec-2e49-b930-0c8fe920cb73",
+///         "metadataStatement": {
+///             "description": "Example Key",
+///             "attestationRootCertificates": [STANDARD.encode(root[0].der())],
+///         },
+///         "statusReports": [
+///             { "status": "FIDO_CERTIFIED_L1", "effectiveDate": "2024-01-15" },
+///             { "status": "ATTESTATION_KEY_COMPROMISE", "effectiveDate": "2024-03-01" },
+///         ],
+///     }],
+/// });
+/// let signing_input = format!(
+///     "{}.{}",
+///     URL_SAFE_NO_PAD.encode(header.to_string()),
+///     URL_SAFE_NO_PAD.encode(payload.to_string())
+/// );
+/// let rng = ring::rand::SystemRandom::new();
+/// let key = EcdsaKeyPair::from_pkcs8(
+///     &ECDSA_P256_SHA256_FIXED_SIGNING,
+///     include_bytes!("testdata/metadata-signer.pk8"),
+///     &rng,
+/// )
+/// .unwrap();
+/// let signature = key.sign(&rng, signing_input.as_bytes()).unwrap();
+/// let blob = format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature));
+///
+/// let metadata = MetadataBlob::verify(&blob, &root)?;
+/// let aaguid = [
+///     0x6d, 0x44, 0xba, 0x9b, 0xf6, 0xec, 0x2e, 0x49,
+///     0xb9, 0x30, 0x0c, 0x8f, 0xe9, 0x20, 0xcb, 0x73,
+/// ];
+/// let entry = metadata.entry(&aaguid).unwrap();
+///
+/// assert_eq!(metadata.number, 42);
+/// assert_eq!(entry.status(), Some("ATTESTATION_KEY_COMPROMISE"));
+/// assert!(!entry.is_trusted());
+///
+/// // A BLOB that does not chain to the given roots is rejected
+/// let other = Certificate::parse_pem(include_str!("testdata/attestation.pem"))?;
+/// assert!(MetadataBlob::verify(&blob, &other).is_err());
+/// # Ok::<(), lonewolf_auth_toolkit::AuthError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataBlob {
+    /// Serial number, increasing with every publication
+    pub number: u64,
+    /// ISO 8601 date by which a new BLOB will be published
+    pub next_update: String,
+    pub entries: Vec<MetadataEntry>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    x5c: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Payload {
+    no: u64,
+    next_update: String,
+    #[serde(default)]
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    aaguid: Option<String>,
+    metadata_statement: Option<Statement>,
+    #[serde(default)]
+    status_reports: Vec<Report>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Statement {
+    description: Option<String>,
+    #[serde(default)]
+    attestation_root_certificates: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    status: String,
+    effective_date: Option<String>,
+}
+
+impl MetadataBlob {
+    /// Check the BLOB's signature and certificate chain and decode its entries
+    ///
+    /// Fails with `AuthError::Verification` if it is not signed by a chain to one of `roots`.
+    pub fn verify(blob: &str, roots: &[Certificate]) -> Result<Self, AuthError> {
+        Self::verify_at(blob, roots, now()?)
+    }
+
+    /// Like `verify`, validating certificates at the Unix timestamp `now`
+    pub fn verify_at(blob: &str, roots: &[Certificate], now: u64) -> Result<Self, AuthError> {
+        let malformed = || AuthError::Malformed("Metadata BLOB is not a JWT".to_string());
+
+        let blob = blob.trim();
+        let (signing_input, signature) = blob.rsplit_once('.').ok_or_else(malformed)?;
+        let (header, payload) = signing_input.split_once('.').ok_or_else(malformed)?;
+        let header: Header = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header)?)?;
+
+        let chain = header
+            .x5c
+            .iter()
+            .map(|der| Certificate::from_der(&STANDARD.decode(der)?))
+            .collect::<Result<Vec<_>, _>>()?;
+        let roots: Vec<&Certificate> = roots.iter().collect();
+        if !chains_to(&chain, &roots, now) {
+            return Err(AuthError::Verification(
+                "Metadata BLOB is not signed by a trusted root".to_string(),
+            ));
+        }
+
+        let algorithm: &'static dyn VerificationAlgorithm = match header.alg.as_str() {
+            "ES256" => &signature::ECDSA_P256_SHA256_FIXED,
+            "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+            other => {
+                return Err(AuthError::Malformed(format!(
+                    "Unsupported metadata BLOB algorithm {}",
+                    other
+                )))
+            }
+        };
+        if !chain[0].verifies(
+            algorithm,
+            signing_input.as_bytes(),
+            &URL_SAFE_NO_PAD.decode(signature)?,
+        ) {
+            return Err(AuthError::Verification(
+                "Metadata BLOB signature is invalid".to_string(),
+            ));
+        }
+
+        let payload: Payload = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload)?)?;
+        let mut entries = Vec::new();
+        for entry in payload.entries {
+            let Some(aaguid) = entry.aaguid.as_deref() else {
+                continue;
+            };
+            let statement = entry.metadata_statement;
+
+            entries.push(MetadataEntry {
+                aaguid: parse_aaguid(aaguid)?,
+                description: statement
+                    .as_ref()
+                    .and_then(|statement| statement.description.clone()),
+                attestation_roots: statement
+                    .iter()
+                    .flat_map(|statement| &statement.attestation_root_certificates)
+                    .map(|der| Certificate::from_der(&STANDARD.decode(der)?))
+                    .collect::<Result<_, _>>()?,
+                status_reports: entry
+                    .status_reports
+                    .into_iter()
+                    .map(|report| StatusReport {
+                        status: report.status,
+                        effective_date: report.effective_date,
+                    })
+                    .collect(),
+            });
+        }
+
+        Ok(Self {
+            number: payload.no,
+            next_update: payload.next_update,
+            entries,
+        })
+    }
+
+    /// Download and verify the BLOB, e.g. from `MDS_URL`
+    pub async fn fetch<C: HttpClient>(
+        client: &C,
+        url: &str,
+        roots: &[Certificate],
+    ) -> Result<Self, AuthError> {
+        let response = otel::send(client, HttpRequest::get(url)).await?;
+        if !response.is_success() {
+            return Err(AuthError::backend(format!(
+                "Metadata Service returned status {}",
+                response.status
+            )));
+        }
+
+        Self::verify(&String::from_utf8(response.body)?, roots)
+    }
+
+    pub fn entry(&self, aaguid: &[u8; 16]) -> Option<&MetadataEntry> {
+        self.entries.iter().find(|entry| &entry.aaguid == aaguid)
+    }
+}
+
+/// An AAGUID in its hyphenated hex form
+fn parse_aaguid(text: &str) -> Result<[u8; 16], AuthError> {
+    let hex: String = text.chars().filter(|c| *c != '-').collect();
+    let invalid = || AuthError::Malformed(format!("Invalid AAGUID {}", text));
+    if hex.len() != 32 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut aaguid = [0u8; 16];
+    for (index, byte) in aaguid.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+
+    Ok(aaguid)
+}
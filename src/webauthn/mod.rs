@@ -0,0 +1,662 @@
+pub mod attestation;
+pub mod cbor;
+pub mod cose;
+pub mod metadata;
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{thread_rng, RngCore};
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value as Json};
+use sha2::{Digest, Sha256};
+
+use crate::{crypto::ct_eq, AuthError};
+
+use attestation::AttestationPolicy;
+use cose::CoseKey;
+
+/// How long a registration or authentication challenge stays valid
+pub const DEFAULT_CHALLENGE_TTL: Duration = Duration::from_secs(300);
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// Holds outstanding challenges between the start and finish of a ceremony
+///
+/// `take` must return and delete the challenge in one operation so each challenge is single use.
+/// Stores should drop challenges once `ttl` has passed.
+pub trait ChallengeStore {
+    fn save(
+        &self,
+        key: &str,
+        challenge: Vec<u8>,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn take(&self, key: &str) -> impl Future<Output = Result<Option<Vec<u8>>, AuthError>> + Send;
+}
+
+/// The relying party: your site, identified by its domain and the origin browsers report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+    pub origin: String,
+}
+
+/// The account a credential is being registered for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserEntity {
+    /// Opaque handle, must not contain personal information
+    pub id: Vec<u8>,
+    pub name: String,
+    pub display_name: String,
+}
+
+/// A registered passkey, to be stored against the account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credential {
+    pub id: Vec<u8>,
+    pub user_id: Vec<u8>,
+    /// COSE_Key encoded public key
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+    pub aaguid: [u8; 16],
+}
+
+impl Credential {
+    /// Serialize the credential as JSON with base64url encoded binary fields
+    pub fn to_json(&self) -> String {
+        self.to_value().to_string()
+    }
+
+    fn to_value(&self) -> Json {
+        json!({
+            "id": URL_SAFE_NO_PAD.encode(&self.id),
+            "userId": URL_SAFE_NO_PAD.encode(&self.user_id),
+            "publicKey": URL_SAFE_NO_PAD.encode(&self.public_key),
+            "signCount": self.sign_count,
+            "aaguid": URL_SAFE_NO_PAD.encode(self.aaguid),
+        })
+    }
+
+    /// Restore a credential serialized with `to_json`
+    pub fn from_json(text: &str) -> Result<Self, AuthError> {
+        Self::from_value(&serde_json::from_str(text)?)
+    }
+
+    fn from_value(value: &Json) -> Result<Self, AuthError> {
+        let field = |name: &str| -> Result<Vec<u8>, AuthError> {
+            let text = value[name]
+                .as_str()
+                .ok_or_else(|| AuthError::Malformed(format!("Credential is missing {}", name)))?;
+            Ok(URL_SAFE_NO_PAD.decode(text)?)
+        };
+        let sign_count = value["signCount"]
+            .as_u64()
+            .ok_or_else(|| AuthError::Malformed("Credential is missing signCount".to_string()))?;
+
+        Ok(Self {
+            id: field("id")?,
+            user_id: field("userId")?,
+            public_key: field("publicKey")?,
+            sign_count: u32::try_from(sign_count)?,
+            aaguid: field("aaguid")?.try_into().map_err(|_| {
+                AuthError::Malformed("Credential aaguid must be 16 bytes".to_string())
+            })?,
+        })
+    }
+}
+
+/// Uses the same JSON shape as `Credential::to_json`
+#[cfg(feature = "serde")]
+impl Serialize for Credential {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_value().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Credential {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::from_value(&Json::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+/// The fields of a `navigator.credentials.create()` result, decoded from base64url
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistrationResponse {
+    pub client_data_json: Vec<u8>,
+    pub attestation_object: Vec<u8>,
+}
+
+/// The fields of a `navigator.credentials.get()` result, decoded from base64url
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticationResponse {
+    pub credential_id: Vec<u8>,
+    pub client_data_json: Vec<u8>,
+    pub authenticator_data: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// Data from an attestation object, for checking the attestation statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+    pub format: String,
+    pub statement: cbor::Value,
+    pub authenticator_data: Vec<u8>,
+    pub client_data_hash: Vec<u8>,
+}
+
+/// Parsed authenticator data (WebAuthn section 6.1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AuthenticatorData {
+    pub rp_id_hash: Vec<u8>,
+    pub flags: u8,
+    pub sign_count: u32,
+    pub attested: Option<AttestedCredential>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AttestedCredential {
+    pub aaguid: [u8; 16],
+    pub id: Vec<u8>,
+    pub public_key: Vec<u8>,
+}
+
+impl AuthenticatorData {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, AuthError> {
+        if data.len() < 37 {
+            return Err(AuthError::Malformed(
+                "Authenticator data is too short".to_string(),
+            ));
+        }
+
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes(data[33..37].try_into()?);
+        let attested = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+            let rest = &data[37..];
+
+            if rest.len() < 18 {
+                return Err(AuthError::Malformed(
+                    "Attested credential data is too short".to_string(),
+                ));
+            }
+
+            let id_length = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+            let id = rest.get(18..18 + id_length).ok_or_else(|| {
+                AuthError::Malformed("Credential id exceeds authenticator data".to_string())
+            })?;
+            let key_start = 18 + id_length;
+            let (_, key_length) = cbor::decode(&rest[key_start..])?;
+
+            Some(AttestedCredential {
+                aaguid: rest[..16].try_into()?,
+                id: id.to_vec(),
+                public_key: rest[key_start..key_start + key_length].to_vec(),
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            rp_id_hash: data[..32].to_vec(),
+            flags,
+            sign_count,
+            attested,
+        })
+    }
+}
+
+/// Runs WebAuthn registration and authentication ceremonies for one relying party
+///
+/// Attestation statements are not verified unless an `AttestationPolicy` is set with
+/// `attestation`, in which case registration options ask for `direct` attestation and
+/// registrations the policy rejects fail. Ceremonies are keyed by a caller chosen string, typically
+/// the session id, so the challenge issued by `start_*` is found again by `finish_*`.
+///
+/// ### Example
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::sync::Mutex;
+/// use std::time::Duration;
+///
+/// use lonewolf_auth_toolkit::webauthn::cbor::{encode, Value};
+/// use lonewolf_auth_toolkit::webauthn::{
+///     AuthenticationResponse, ChallengeStore, RegistrationResponse, RelyingParty, UserEntity, Webauthn,
+/// };
+/// use lonewolf_auth_toolkit::AuthError;
+/// use ring::signature::{Ed25519KeyPair, KeyPair};
+/// use sha2::{Digest, Sha256};
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, Vec<u8>>>);
+///
+/// impl ChallengeStore for MemoryStore {
+///     async fn save(&self, key: &str, challenge: Vec<u8>, _ttl: Duration) -> Result<(), AuthError> {
+///         self.0.lock().unwrap().insert(key.to_string(), challenge);
+///         Ok(())
+///     }
+///
+///     async fn take(&self, key: &str) -> Result<Option<Vec<u8>>, AuthError> {
+///         Ok(self.0.lock().unwrap().remove(key))
+///     }
+/// }
+///
+/// fn client_data(kind: &str, options: &serde_json::Value) -> Vec<u8> {
+///     serde_json::json!({
+///         "type": kind,
+///         "challenge": options["challenge"],
+///         "origin": "https://example.com",
+///     })
+///     .to_string()
+///     .into_bytes()
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let store = MemoryStore::default();
+///     let webauthn = Webauthn::new(RelyingParty {
+///         id: "example.com".to_string(),
+///         name: "Example".to_string(),
+///         origin: "https://example.com".to_string(),
+///     });
+///     let user = UserEntity {
+///         id: b"user-1".to_vec(),
+///         name: "someone@example.com".to_string(),
+///         display_name: "Someone".to_string(),
+///     };
+///
+///     // A software authenticator holding an Ed25519 key
+///     let rng = ring::rand::SystemRandom::new();
+///     let key_pair = Ed25519KeyPair::from_pkcs8(Ed25519KeyPair::generate_pkcs8(&rng).unwrap().as_ref()).unwrap();
+///     let rp_id_hash = Sha256::digest(b"example.com");
+///     let cose_key = encode(&Value::Map(vec![
+///         (Value::Integer(1), Value::Integer(1)),
+///         (Value::Integer(3), Value::Integer(-8)),
+///         (Value::Integer(-1), Value::Integer(6)),
+///         (Value::Integer(-2), Value::Bytes(key_pair.public_key().as_ref().to_vec())),
+///     ]));
+///
+///     let options = webauthn.start_registration(&store, "session-1", &user, &[]).await?;
+///     let mut auth_data = rp_id_hash.to_vec();
+///     auth_data.extend([0x41, 0, 0, 0, 0]);
+///     auth_data.extend([0u8; 16]);
+///     auth_data.extend([0, 4]);
+///     auth_data.extend(b"cred");
+///     auth_data.extend(&cose_key);
+///     let attestation_object = encode(&Value::Map(vec![
+///         (Value::Text("fmt".to_string()), Value::Text("none".to_string())),
+///         (Value::Text("attStmt".to_string()), Value::Map(vec![])),
+///         (Value::Text("authData".to_string()), Value::Bytes(auth_data)),
+///     ]));
+///     let credential = webauthn
+///         .finish_registration(&store, "session-1", &user, RegistrationResponse {
+///             client_data_json: client_data("webauthn.create", &options),
+///             attestation_object,
+///         })
+///         .await?;
+///
+///     let options = webauthn.start_authentication(&store, "session-1", &[credential.clone()]).await?;
+///     let client_data_json = client_data("webauthn.get", &options);
+///     let mut authenticator_data = rp_id_hash.to_vec();
+///     authenticator_data.extend([0x01, 0, 0, 0, 1]);
+///     let mut signed = authenticator_data.clone();
+///     signed.extend(Sha256::digest(&client_data_json));
+///     let credential = webauthn
+///         .finish_authentication(&store, "session-1", &credential, AuthenticationResponse {
+///             credential_id: b"cred".to_vec(),
+///             client_data_json,
+///             authenticator_data,
+///             signature: key_pair.sign(&signed).as_ref().to_vec(),
+///         })
+///         .await?;
+///
+///     assert_eq!(credential.sign_count, 1);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Webauthn {
+    rp: RelyingParty,
+    challenge_ttl: Duration,
+    require_user_verification: bool,
+    attestation: Option<AttestationPolicy>,
+}
+
+impl Webauthn {
+    pub fn new(rp: RelyingParty) -> Self {
+        Self {
+            rp,
+            challenge_ttl: DEFAULT_CHALLENGE_TTL,
+            require_user_verification: false,
+            attestation: None,
+        }
+    }
+
+    /// Require the authenticator to verify the user (PIN or biometric), not just their presence
+    pub fn require_user_verification(mut self, required: bool) -> Self {
+        self.require_user_verification = required;
+        self
+    }
+
+    /// Request attestation and only register authenticators the policy accepts
+    pub fn attestation(mut self, policy: AttestationPolicy) -> Self {
+        self.attestation = Some(policy);
+        self
+    }
+
+    /// How long a started ceremony may take to finish
+    pub fn challenge_ttl(mut self, ttl: Duration) -> Self {
+        self.challenge_ttl = ttl;
+        self
+    }
+
+    /// Begin registering a new passkey
+    ///
+    /// Returns `PublicKeyCredentialCreationOptions` as JSON with base64url binary fields, ready for
+    /// `PublicKeyCredential.parseCreationOptionsFromJSON` in the browser. Pass the account's existing
+    /// credentials as `exclude` so the same authenticator is not registered twice.
+    pub async fn start_registration<S: ChallengeStore>(
+        &self,
+        store: &S,
+        key: &str,
+        user: &UserEntity,
+        exclude: &[Credential],
+    ) -> Result<Json, AuthError> {
+        let challenge = self.issue_challenge(store, key).await?;
+
+        Ok(json!({
+            "rp": { "id": self.rp.id, "name": self.rp.name },
+            "user": {
+                "id": URL_SAFE_NO_PAD.encode(&user.id),
+                "name": user.name,
+                "displayName": user.display_name,
+            },
+            "challenge": URL_SAFE_NO_PAD.encode(challenge),
+            "pubKeyCredParams": [
+                { "type": "public-key", "alg": cose::ES256 as i64 },
+                { "type": "public-key", "alg": cose::EDDSA as i64 },
+                { "type": "public-key", "alg": cose::RS256 as i64 },
+            ],
+            "timeout": self.challenge_ttl.as_millis() as u64,
+            "excludeCredentials": descriptors(exclude),
+            "authenticatorSelection": {
+                "residentKey": "preferred",
+                "userVerification": self.user_verification(),
+            },
+            "attestation": if self.attestation.is_some() { "direct" } else { "none" },
+        }))
+    }
+
+    /// Complete a registration, returning the credential to store for the user
+    pub async fn finish_registration<S: ChallengeStore>(
+        &self,
+        store: &S,
+        key: &str,
+        user: &UserEntity,
+        response: RegistrationResponse,
+    ) -> Result<Credential, AuthError> {
+        let (credential, _) = self
+            .finish_registration_with_attestation(store, key, user, response)
+            .await?;
+
+        Ok(credential)
+    }
+
+    /// Complete a registration, also returning the attestation for callers that verify it
+    pub async fn finish_registration_with_attestation<S: ChallengeStore>(
+        &self,
+        store: &S,
+        key: &str,
+        user: &UserEntity,
+        response: RegistrationResponse,
+    ) -> Result<(Credential, Attestation), AuthError> {
+        self.verify_client_data(store, key, "webauthn.create", &response.client_data_json)
+            .await?;
+
+        let (attestation_object, _) = cbor::decode(&response.attestation_object)?;
+        let auth_data_bytes = attestation_object
+            .get_text("authData")
+            .and_then(cbor::Value::as_bytes)
+            .ok_or_else(|| {
+                AuthError::Malformed("Attestation object is missing authData".to_string())
+            })?;
+        let auth_data = self.verify_authenticator_data(auth_data_bytes)?;
+        let attested = auth_data.attested.ok_or_else(|| {
+            AuthError::Malformed("Registration did not include a credential".to_string())
+        })?;
+
+        CoseKey::from_cbor(&attested.public_key)?;
+
+        let attestation = Attestation {
+            format: attestation_object
+                .get_text("fmt")
+                .and_then(cbor::Value::as_text)
+                .unwrap_or("none")
+                .to_string(),
+            statement: attestation_object
+                .get_text("attStmt")
+                .cloned()
+                .unwrap_or(cbor::Value::Map(vec![])),
+            authenticator_data: auth_data_bytes.to_vec(),
+            client_data_hash: Sha256::digest(&response.client_data_json).to_vec(),
+        };
+        if let Some(policy) = &self.attestation {
+            policy.check(&attestation)?;
+        }
+
+        let credential = Credential {
+            id: attested.id,
+            user_id: user.id.clone(),
+            public_key: attested.public_key,
+            sign_count: auth_data.sign_count,
+            aaguid: attested.aaguid,
+        };
+
+        Ok((credential, attestation))
+    }
+
+    /// Begin authenticating with a passkey
+    ///
+    /// Returns `PublicKeyCredentialRequestOptions` as JSON. Pass no credentials to let the
+    /// browser offer any discoverable passkey for this site.
+    pub async fn start_authentication<S: ChallengeStore>(
+        &self,
+        store: &S,
+        key: &str,
+        allow: &[Credential],
+    ) -> Result<Json, AuthError> {
+        let challenge = self.issue_challenge(store, key).await?;
+
+        Ok(json!({
+            "challenge": URL_SAFE_NO_PAD.encode(challenge),
+            "rpId": self.rp.id,
+            "timeout": self.challenge_ttl.as_millis() as u64,
+            "allowCredentials": descriptors(allow),
+            "userVerification": self.user_verification(),
+        }))
+    }
+
+    /// Complete an authentication, returning the credential with its updated sign count
+    ///
+    /// The caller looks up `credential` by `response.credential_id` and must persist the
+    /// returned copy. A sign count that fails to increase suggests a cloned authenticator
+    /// and is rejected.
+    pub async fn finish_authentication<S: ChallengeStore>(
+        &self,
+        store: &S,
+        key: &str,
+        credential: &Credential,
+        response: AuthenticationResponse,
+    ) -> Result<Credential, AuthError> {
+        if !ct_eq(&response.credential_id, &credential.id) {
+            return Err(AuthError::Verification(
+                "Response is for a different credential".to_string(),
+            ));
+        }
+
+        self.verify_client_data(store, key, "webauthn.get", &response.client_data_json)
+            .await?;
+
+        let auth_data = self.verify_authenticator_data(&response.authenticator_data)?;
+        let mut signed = response.authenticator_data.clone();
+        signed.extend_from_slice(&Sha256::digest(&response.client_data_json));
+
+        CoseKey::from_cbor(&credential.public_key)?.verify(&signed, &response.signature)?;
+
+        let counters_in_use = auth_data.sign_count != 0 || credential.sign_count != 0;
+
+        if counters_in_use && auth_data.sign_count <= credential.sign_count {
+            return Err(AuthError::Verification(
+                "Sign count did not increase, the authenticator may be cloned".to_string(),
+            ));
+        }
+
+        Ok(Credential {
+            sign_count: auth_data.sign_count,
+            ..credential.clone()
+        })
+    }
+
+    async fn issue_challenge<S: ChallengeStore>(
+        &self,
+        store: &S,
+        key: &str,
+    ) -> Result<Vec<u8>, AuthError> {
+        let mut challenge = vec![0u8; 32];
+        thread_rng().fill_bytes(&mut challenge);
+
+        store
+            .save(key, challenge.clone(), self.challenge_ttl)
+            .await?;
+
+        Ok(challenge)
+    }
+
+    async fn verify_client_data<S: ChallengeStore>(
+        &self,
+        store: &S,
+        key: &str,
+        kind: &str,
+        client_data_json: &[u8],
+    ) -> Result<(), AuthError> {
+        let expected = store.take(key).await?.ok_or_else(|| {
+            AuthError::NotFound("No challenge is pending or it has expired".to_string())
+        })?;
+        let client_data: Json = serde_json::from_slice(client_data_json)?;
+
+        if client_data["type"] != kind {
+            return Err(AuthError::Verification(format!(
+                "Client data type must be {}",
+                kind
+            )));
+        }
+
+        let challenge =
+            URL_SAFE_NO_PAD.decode(client_data["challenge"].as_str().ok_or_else(|| {
+                AuthError::Malformed("Client data is missing the challenge".to_string())
+            })?)?;
+
+        if !ct_eq(&challenge, &expected) {
+            return Err(AuthError::Verification(
+                "Challenge does not match".to_string(),
+            ));
+        }
+
+        if client_data["origin"] != self.rp.origin.as_str() {
+            return Err(AuthError::Verification(
+                "Origin does not match the relying party".to_string(),
+            ));
+        }
+
+        if client_data["crossOrigin"] == true {
+            return Err(AuthError::Verification(
+                "Cross origin ceremonies are not allowed".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn verify_authenticator_data(&self, data: &[u8]) -> Result<AuthenticatorData, AuthError> {
+        let auth_data = AuthenticatorData::parse(data)?;
+
+        if !ct_eq(&auth_data.rp_id_hash, Sha256::digest(self.rp.id.as_bytes())) {
+            return Err(AuthError::Verification(
+                "Authenticator data is for a different relying party".to_string(),
+            ));
+        }
+
+        if auth_data.flags & FLAG_USER_PRESENT == 0 {
+            return Err(AuthError::Verification(
+                "User presence was not confirmed".to_string(),
+            ));
+        }
+
+        if self.require_user_verification && auth_data.flags & FLAG_USER_VERIFIED == 0 {
+            return Err(AuthError::Verification(
+                "User verification is required".to_string(),
+            ));
+        }
+
+        Ok(auth_data)
+    }
+
+    fn user_verification(&self) -> &'static str {
+        if self.require_user_verification {
+            "required"
+        } else {
+            "preferred"
+        }
+    }
+}
+
+fn descriptors(credentials: &[Credential]) -> Vec<Json> {
+    credentials
+        .iter()
+        .map(|credential| json!({ "type": "public-key", "id": URL_SAFE_NO_PAD.encode(&credential.id) }))
+        .collect()
+}
+
+/// Keeps outstanding challenges in process memory, dropping each once its ttl has passed
+#[derive(Debug, Default)]
+pub struct MemoryChallengeStore {
+    challenges: Mutex<HashMap<String, (Vec<u8>, Instant)>>,
+}
+
+impl ChallengeStore for MemoryChallengeStore {
+    async fn save(&self, key: &str, challenge: Vec<u8>, ttl: Duration) -> Result<(), AuthError> {
+        let now = Instant::now();
+        let mut challenges = self
+            .challenges
+            .lock()
+            .map_err(|_| AuthError::backend("Challenge store lock poisoned"))?;
+
+        challenges.retain(|_, (_, expires_at)| *expires_at > now);
+        challenges.insert(key.to_string(), (challenge, now + ttl));
+
+        Ok(())
+    }
+
+    async fn take(&self, key: &str) -> Result<Option<Vec<u8>>, AuthError> {
+        let now = Instant::now();
+
+        Ok(self
+            .challenges
+            .lock()
+            .map_err(|_| AuthError::backend("Challenge store lock poisoned"))?
+            .remove(key)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(challenge, _)| challenge))
+    }
+}
@@ -0,0 +1,448 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::{
+    audit::{AuditAction, AuditEvent, AuditSink},
+    crypto::{ct_eq, MIN_HMAC_KEY_LEN},
+    http::{HttpClient, HttpRequest},
+    otel,
+    token::{generate_token, now},
+    AuthError,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Attempts made before a delivery is marked failed
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry, doubled after each further failure
+pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Header carrying `t=<timestamp>,v1=<signature>`
+pub const SIGNATURE_HEADER: &str = "Webhook-Signature";
+
+/// Header carrying the delivery id, which stays the same across retries
+pub const DELIVERY_HEADER: &str = "Webhook-Id";
+
+/// A URL that receives audit events
+pub struct WebhookEndpoint {
+    pub id: String,
+    pub url: String,
+    secret: Zeroizing<Vec<u8>>,
+    /// The actions sent to this endpoint; every action if empty
+    pub actions: Vec<AuditAction>,
+}
+
+impl WebhookEndpoint {
+    pub fn new(
+        id: impl Into<String>,
+        url: impl Into<String>,
+        secret: &[u8],
+    ) -> Result<Self, AuthError> {
+        if secret.len() < MIN_HMAC_KEY_LEN {
+            return Err(AuthError::InvalidInput(format!(
+                "Webhook secrets must be at least {} bytes",
+                MIN_HMAC_KEY_LEN
+            )));
+        }
+
+        Ok(Self {
+            id: id.into(),
+            url: url.into(),
+            secret: Zeroizing::new(secret.to_vec()),
+            actions: Vec::new(),
+        })
+    }
+
+    /// Only send these actions, e.g. `LoginSucceeded`, `MfaDisabled` and `AccountLocked`
+    pub fn actions(mut self, actions: impl IntoIterator<Item = AuditAction>) -> Self {
+        self.actions = actions.into_iter().collect();
+        self
+    }
+
+    fn wants(&self, action: AuditAction) -> bool {
+        self.actions.is_empty() || self.actions.contains(&action)
+    }
+}
+
+/// Where a delivery is in its lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Not yet accepted; retried at `next_attempt_at`
+    Pending,
+    /// The endpoint answered with a 2xx status
+    Delivered,
+    /// Every attempt failed; no more are made
+    Failed,
+}
+
+/// One event on its way to one endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: String,
+    pub endpoint_id: String,
+    pub event: AuditEvent,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// The HTTP status of the last attempt, if the endpoint answered
+    pub last_status: Option<u16>,
+    /// Why the last attempt failed
+    pub last_error: Option<String>,
+    /// Unix timestamp in seconds of the next retry while pending
+    pub next_attempt_at: Option<u64>,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+}
+
+/// Persists deliveries so their status can be shown and failed ones retried
+pub trait DeliveryStore {
+    /// Insert or replace the delivery
+    fn save(&self, delivery: Delivery) -> impl Future<Output = Result<(), AuthError>> + Send;
+
+    fn get(&self, id: &str) -> impl Future<Output = Result<Option<Delivery>, AuthError>> + Send;
+
+    /// Pending deliveries whose `next_attempt_at` is at or before the Unix timestamp `now`
+    fn due(&self, now: u64) -> impl Future<Output = Result<Vec<Delivery>, AuthError>> + Send;
+
+    /// The endpoint's deliveries, for a delivery log
+    fn list(
+        &self,
+        endpoint_id: &str,
+    ) -> impl Future<Output = Result<Vec<Delivery>, AuthError>> + Send;
+}
+
+/// Delivers audit events to webhook endpoints
+///
+/// `WebhookEmitter` is an `AuditSink`: each event is posted as JSON to every endpoint that wants
+/// it, signed with the endpoint's secret in the `Webhook-Signature` header as
+/// `t=<timestamp>,v1=<hex HMAC-SHA256 of "<timestamp>.<body>">`; receivers check it with
+/// `verify_signature`. A delivery that fails is not an error for the flow that emitted the event.
+/// It stays pending, and `retry_due`, called periodically, tries it again with exponential backoff
+/// until it succeeds or `max_attempts` is reached.
+///
+/// ### Example
+/// ```rust
+/// use std::sync::Mutex;
+///
+/// use lonewolf_auth_toolkit::audit::{AuditAction, AuditEvent, AuditSink};
+/// use lonewolf_auth_toolkit::http::{HttpClient, HttpRequest, HttpResponse};
+/// use lonewolf_auth_toolkit::webhook::{
+///     verify_signature, DeliveryStatus, MemoryDeliveryStore, WebhookEmitter, WebhookEndpoint,
+///     SIGNATURE_HEADER,
+/// };
+/// use lonewolf_auth_toolkit::AuthError;
+///
+/// #[derive(Default)]
+/// struct Receiver(Mutex<Vec<HttpRequest>>);
+///
+/// impl HttpClient for Receiver {
+///     async fn send(&self, request: HttpRequest) -> Result<HttpResponse, AuthError> {
+///         self.0.lock().unwrap().push(request);
+///         Ok(HttpResponse { status: 204, headers: Vec::new(), body: Vec::new() })
+///     }
+/// }
+///
+/// #[tokio::main]
+/// pub async fn main() -> Result<(), anyhow::Error> {
+///     let secret = [9u8; 32];
+///     let endpoint = WebhookEndpoint::new("siem", "https://siem.example.com/hooks", &secret)?
+///         .actions([AuditAction::LoginFailed, AuditAction::AccountLocked]);
+///     let webhooks = WebhookEmitter::new(Receiver::default(), MemoryDeliveryStore::default())
+///         .endpoint(endpoint);
+///
+///     webhooks.record(AuditEvent::new(AuditAction::LoginSucceeded)?).await?;
+///     webhooks
+///         .record(AuditEvent::new(AuditAction::AccountLocked)?.account("SomeAccountName"))
+///         .await?;
+///
+///     let deliveries = webhooks.deliveries("siem").await?;
+///     assert_eq!(deliveries.len(), 1);
+///     assert_eq!(deliveries[0].status, DeliveryStatus::Delivered);
+///
+///     // On the receiving end
+///     let request = webhooks.client().0.lock().unwrap().remove(0);
+///     let headers = &request.headers;
+///     let (_, signature) = headers.iter().find(|(name, _)| name == SIGNATURE_HEADER).unwrap();
+///     assert!(verify_signature(&secret, signature, &request.body, 300)?);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct WebhookEmitter<C, S> {
+    client: C,
+    store: S,
+    endpoints: Vec<WebhookEndpoint>,
+    max_attempts: u32,
+    backoff: Duration,
+}
+
+impl<C: HttpClient, S: DeliveryStore> WebhookEmitter<C, S> {
+    pub fn new(client: C, store: S) -> Self {
+        Self {
+            client,
+            store,
+            endpoints: Vec::new(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            backoff: DEFAULT_BACKOFF,
+        }
+    }
+
+    pub fn endpoint(mut self, endpoint: WebhookEndpoint) -> Self {
+        self.endpoints.push(endpoint);
+        self
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Look up a delivery, e.g. to show its status
+    pub async fn delivery(&self, id: &str) -> Result<Option<Delivery>, AuthError> {
+        self.store.get(id).await
+    }
+
+    /// The endpoint's deliveries
+    pub async fn deliveries(&self, endpoint_id: &str) -> Result<Vec<Delivery>, AuthError> {
+        self.store.list(endpoint_id).await
+    }
+
+    /// Send `event` to every endpoint that wants it, returning the deliveries
+    pub async fn emit(&self, event: &AuditEvent) -> Result<Vec<Delivery>, AuthError> {
+        let now = now()?;
+        let mut deliveries = Vec::new();
+
+        for endpoint in self
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.wants(event.action))
+        {
+            let delivery = Delivery {
+                id: generate_token(),
+                endpoint_id: endpoint.id.clone(),
+                event: event.clone(),
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                last_status: None,
+                last_error: None,
+                next_attempt_at: Some(now),
+                created_at: now,
+            };
+
+            deliveries.push(self.attempt(endpoint, delivery).await?);
+        }
+
+        Ok(deliveries)
+    }
+
+    /// Retry the pending deliveries that are due, returning how many were delivered
+    ///
+    /// Call it from a periodic job. Deliveries to endpoints no longer configured are marked
+    /// failed.
+    pub async fn retry_due(&self) -> Result<usize, AuthError> {
+        let mut delivered = 0;
+
+        for mut delivery in self.store.due(now()?).await? {
+            let endpoint = self
+                .endpoints
+                .iter()
+                .find(|endpoint| endpoint.id == delivery.endpoint_id);
+
+            match endpoint {
+                Some(endpoint) => {
+                    let delivery = self.attempt(endpoint, delivery).await?;
+                    if delivery.status == DeliveryStatus::Delivered {
+                        delivered += 1;
+                    }
+                }
+                None => {
+                    delivery.status = DeliveryStatus::Failed;
+                    delivery.next_attempt_at = None;
+                    delivery.last_error = Some("Endpoint is no longer configured".to_string());
+                    self.store.save(delivery).await?;
+                }
+            }
+        }
+
+        Ok(delivered)
+    }
+
+    async fn attempt(
+        &self,
+        endpoint: &WebhookEndpoint,
+        mut delivery: Delivery,
+    ) -> Result<Delivery, AuthError> {
+        let body = serde_json::to_vec(&json!({
+            "id": delivery.id,
+            "type": delivery.event.action,
+            "event": delivery.event,
+        }))?;
+        let timestamp = now()?;
+        let request = HttpRequest::post_json(&endpoint.url, body.clone())
+            .header(DELIVERY_HEADER, &delivery.id)
+            .header(
+                SIGNATURE_HEADER,
+                format!(
+                    "t={},v1={}",
+                    timestamp,
+                    sign(&endpoint.secret, timestamp, &body)
+                ),
+            );
+
+        delivery.attempts += 1;
+        match otel::send(&self.client, request).await {
+            Ok(response) if response.is_success() => {
+                delivery.status = DeliveryStatus::Delivered;
+                delivery.last_status = Some(response.status);
+                delivery.last_error = None;
+            }
+            Ok(response) => {
+                delivery.last_status = Some(response.status);
+                delivery.last_error = Some(format!("HTTP {}", response.status));
+            }
+            Err(error) => {
+                delivery.last_status = None;
+                delivery.last_error = Some(error.to_string());
+            }
+        }
+
+        delivery.next_attempt_at = None;
+        if delivery.status == DeliveryStatus::Pending {
+            if delivery.attempts >= self.max_attempts {
+                delivery.status = DeliveryStatus::Failed;
+            } else {
+                let doublings = (delivery.attempts - 1).min(31);
+                let wait = self.backoff.saturating_mul(1 << doublings);
+                delivery.next_attempt_at = Some(now()? + wait.as_secs());
+            }
+        }
+
+        self.store.save(delivery.clone()).await?;
+
+        Ok(delivery)
+    }
+}
+
+impl<C: HttpClient + Sync, S: DeliveryStore + Sync> AuditSink for WebhookEmitter<C, S> {
+    async fn record(&self, event: AuditEvent) -> Result<(), AuthError> {
+        self.emit(&event).await.map(|_| ())
+    }
+}
+
+/// Check a `Webhook-Signature` header on a received webhook
+///
+/// Returns `false` if the signature does not match `body` or its timestamp is more than
+/// `tolerance_secs` from now, which stops old deliveries from being replayed.
+pub fn verify_signature(
+    secret: &[u8],
+    header: &str,
+    body: &[u8],
+    tolerance_secs: u64,
+) -> Result<bool, AuthError> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<u64>().ok(),
+            Some(("v1", value)) => signatures.push(value),
+            _ => {}
+        }
+    }
+
+    let timestamp = match timestamp {
+        Some(timestamp) => timestamp,
+        None => return Ok(false),
+    };
+    if now()?.abs_diff(timestamp) > tolerance_secs {
+        return Ok(false);
+    }
+
+    let expected = sign(secret, timestamp, body);
+
+    Ok(signatures
+        .iter()
+        .any(|signature| ct_eq(signature, &expected)))
+}
+
+fn sign(secret: &[u8], timestamp: u64, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Keeps deliveries in process memory
+#[derive(Debug, Default)]
+pub struct MemoryDeliveryStore {
+    deliveries: Mutex<HashMap<String, Delivery>>,
+}
+
+impl MemoryDeliveryStore {
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, Delivery>>, AuthError> {
+        self.deliveries
+            .lock()
+            .map_err(|_| AuthError::backend("Delivery store lock poisoned"))
+    }
+
+    fn matching(&self, filter: impl Fn(&Delivery) -> bool) -> Result<Vec<Delivery>, AuthError> {
+        let mut deliveries: Vec<_> = self
+            .lock()?
+            .values()
+            .filter(|delivery| filter(delivery))
+            .cloned()
+            .collect();
+        deliveries.sort_by_key(|delivery| delivery.created_at);
+
+        Ok(deliveries)
+    }
+}
+
+impl DeliveryStore for MemoryDeliveryStore {
+    async fn save(&self, delivery: Delivery) -> Result<(), AuthError> {
+        self.lock()?.insert(delivery.id.clone(), delivery);
+
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Delivery>, AuthError> {
+        Ok(self.lock()?.get(id).cloned())
+    }
+
+    async fn due(&self, now: u64) -> Result<Vec<Delivery>, AuthError> {
+        self.matching(|delivery| {
+            delivery.status == DeliveryStatus::Pending
+                && delivery.next_attempt_at.is_some_and(|at| at <= now)
+        })
+    }
+
+    async fn list(&self, endpoint_id: &str) -> Result<Vec<Delivery>, AuthError> {
+        self.matching(|delivery| delivery.endpoint_id == endpoint_id)
+    }
+}